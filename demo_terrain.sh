@@ -15,16 +15,16 @@ echo "===================================="
 echo "PNG Image Information:"
 echo "===================================="
 
-if [ -f "terrain_map_99999.png" ]; then
-    file terrain_map_99999.png
-    ls -lh terrain_map_99999.png
+if [ -f "terrain_s99999_l40_r50_c50.png" ]; then
+    file terrain_s99999_l40_r50_c50.png
+    ls -lh terrain_s99999_l40_r50_c50.png
     echo ""
     echo "The high-resolution PNG has been saved!"
     echo "You can open it with any image viewer."
     echo ""
-    echo "Example: xdg-open terrain_map_99999.png  # Linux"
-    echo "         open terrain_map_99999.png      # macOS"
-    echo "         start terrain_map_99999.png     # Windows"
+    echo "Example: xdg-open terrain_s99999_l40_r50_c50.png  # Linux"
+    echo "         open terrain_s99999_l40_r50_c50.png      # macOS"
+    echo "         start terrain_s99999_l40_r50_c50.png     # Windows"
 else
     echo "PNG generation failed or file not found."
 fi
\ No newline at end of file
@@ -0,0 +1,267 @@
+//! Climate raster export.
+//!
+//! Moisture, temperature and a precipitation estimate derived from them,
+//! one value per tile, as PNG images for looking at and as raw float grids
+//! for analysis tools and game engines. Biomes are only a summary of these
+//! fields; the rasters carry the full values. The day-by-day weather that
+//! follows from them is exported as a CSV table for named places.
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use image::{Rgb, RgbImage};
+
+use crate::error::MapperError;
+use crate::terrain_generator::TerrainMap;
+
+/// Yearly precipitation, in mm, of a saturated tile at the hottest
+/// temperature
+pub const MAX_PRECIPITATION_MM: f32 = 3000.0;
+
+/// A per-tile climate value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClimateField {
+    /// 0 (arid) to 1 (saturated)
+    Moisture,
+    /// 0 (coldest) to 1 (hottest)
+    Temperature,
+    /// Estimated mm per year: moisture, scaled down in cold air, which
+    /// holds less water
+    Precipitation,
+}
+
+impl ClimateField {
+    pub const ALL: [ClimateField; 3] = [
+        ClimateField::Moisture,
+        ClimateField::Temperature,
+        ClimateField::Precipitation,
+    ];
+
+    /// Lower-case name, used in file names.
+    pub fn name(self) -> &'static str {
+        match self {
+            ClimateField::Moisture => "moisture",
+            ClimateField::Temperature => "temperature",
+            ClimateField::Precipitation => "precipitation",
+        }
+    }
+
+    /// The field's value on every tile of `map`, row-major.
+    pub fn values(self, map: &TerrainMap) -> Vec<f32> {
+        map.terrain
+            .iter()
+            .flatten()
+            .map(|point| {
+                let (moisture, temperature) = (point.moisture as f32, point.temperature as f32);
+                match self {
+                    ClimateField::Moisture => moisture,
+                    ClimateField::Temperature => temperature,
+                    ClimateField::Precipitation => {
+                        MAX_PRECIPITATION_MM * moisture * (0.3 + 0.7 * temperature)
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// The range of values, mapped to black..white or along the ramp.
+    fn range(self) -> f32 {
+        match self {
+            ClimateField::Precipitation => MAX_PRECIPITATION_MM,
+            _ => 1.0,
+        }
+    }
+
+    /// Colors from the low to the high end of the field, evenly spaced.
+    fn ramp(self) -> &'static [[f32; 3]] {
+        match self {
+            // Parched brown through green to deep blue
+            ClimateField::Moisture => &[[150.0, 110.0, 60.0], [110.0, 170.0, 80.0], [30.0, 80.0, 170.0]],
+            // Cold blue through white to hot red
+            ClimateField::Temperature => &[[40.0, 70.0, 190.0], [240.0, 240.0, 240.0], [200.0, 40.0, 30.0]],
+            // Dry white through blue to purple
+            ClimateField::Precipitation => &[[250.0, 250.0, 240.0], [80.0, 150.0, 220.0], [90.0, 30.0, 140.0]],
+        }
+    }
+}
+
+/// How a field is colored in an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RasterStyle {
+    /// Black at the low end to white at the high end
+    Grayscale,
+    /// A color ramp chosen for the field
+    Ramp,
+}
+
+impl RasterStyle {
+    /// `gray` (or `grayscale`) or `ramp`.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "gray" | "grayscale" => Some(RasterStyle::Grayscale),
+            "ramp" => Some(RasterStyle::Ramp),
+            _ => None,
+        }
+    }
+}
+
+/// `field` as an image with one pixel per tile.
+pub fn field_image(map: &TerrainMap, field: ClimateField, style: RasterStyle) -> RgbImage {
+    let values = field.values(map);
+    let mut img = RgbImage::new(map.width as u32, map.height as u32);
+    for (pixel, value) in img.pixels_mut().zip(values) {
+        let t = (value / field.range()).clamp(0.0, 1.0);
+        let color = match style {
+            RasterStyle::Grayscale => [t * 255.0; 3],
+            RasterStyle::Ramp => {
+                let stops = field.ramp();
+                let along = t * (stops.len() - 1) as f32;
+                let i = (along as usize).min(stops.len() - 2);
+                let f = along - i as f32;
+                let (a, b) = (stops[i], stops[i + 1]);
+                [0, 1, 2].map(|c| a[c] + (b[c] - a[c]) * f)
+            }
+        };
+        *pixel = Rgb(color.map(|c| c.round() as u8));
+    }
+    img
+}
+
+/// `values` as a raw grid: little-endian f32, row-major, no header.
+pub fn raw_grid(values: &[f32]) -> Vec<u8> {
+    values.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+/// Write every climate field next to `stem`: `<stem>_<field>.png` in
+/// `style` and `<stem>_<field>.f32` as a raw grid of the map's size.
+/// Returns the files written.
+pub fn save_climate_rasters(
+    map: &TerrainMap,
+    stem: &Path,
+    style: RasterStyle,
+) -> Result<Vec<PathBuf>, MapperError> {
+    let base = stem.file_stem().unwrap_or_default().to_string_lossy();
+    let mut written = Vec::new();
+    for field in ClimateField::ALL {
+        let png = stem.with_file_name(format!("{}_{}.png", base, field.name()));
+        field_image(map, field, style).save(&png)?;
+        let raw = png.with_extension("f32");
+        std::fs::write(&raw, raw_grid(&field.values(map)))?;
+        written.extend([png, raw]);
+    }
+    Ok(written)
+}
+
+/// The weather of each city and named region for `days` days from the
+/// start of the year, as CSV: one row per place and day, cities first.
+/// Regions take the weather at their label.
+pub fn weather_csv(map: &TerrainMap, days: u32) -> String {
+    let quote = |text: &str| format!("\"{}\"", text.replace('"', "\"\""));
+    let tile = |v: f32, size: usize| (v.max(0.0) as usize).min(size.saturating_sub(1));
+    let places = map
+        .cities
+        .iter()
+        .map(|c| (&c.name, "city", c.x, c.y))
+        .chain(map.labels.iter().map(|l| (&l.name, "region", tile(l.x, map.width), tile(l.y, map.height))));
+
+    let mut out = String::from("place,kind,x,y,day,temperature_c,precipitation_mm,cloud_cover,conditions\n");
+    for (name, kind, x, y) in places {
+        for day in 0..days {
+            let Some(weather) = map.weather_at(x, y, day) else {
+                continue;
+            };
+            let _ = writeln!(
+                out,
+                "{},{},{},{},{},{:.1},{:.1},{:.2},{}",
+                quote(name),
+                kind,
+                x,
+                y,
+                day,
+                weather.temperature_c,
+                weather.precipitation_mm,
+                weather.cloud_cover,
+                weather.conditions()
+            );
+        }
+    }
+    out
+}
+
+/// Write `weather_csv` for `days` days to `path`.
+pub fn save_weather_csv(map: &TerrainMap, path: &Path, days: u32) -> Result<(), MapperError> {
+    std::fs::write(path, weather_csv(map, days))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain_generator::test_map;
+
+    fn map() -> &'static TerrainMap {
+        test_map(3, 40, 30)
+    }
+
+    /// A tile away from the edges to check values at
+    const TILE: (usize, usize) = (17, 9);
+
+    #[test]
+    fn every_field_has_a_value_in_range_for_each_tile() {
+        for field in ClimateField::ALL {
+            let values = field.values(map());
+            assert_eq!(values.len(), 40 * 30);
+            assert!(values.iter().all(|&v| (0.0..=field.range()).contains(&v)), "{:?}", field);
+        }
+    }
+
+    #[test]
+    fn raw_grids_hold_little_endian_floats() {
+        let (x, y) = TILE;
+        for field in ClimateField::ALL {
+            let values = field.values(map());
+            let raw = raw_grid(&values);
+            assert_eq!(raw.len(), values.len() * 4);
+            let at = (y * 40 + x) * 4;
+            let value = f32::from_le_bytes(raw[at..at + 4].try_into().unwrap());
+            assert_eq!(value, values[y * 40 + x]);
+        }
+    }
+
+    #[test]
+    fn grayscale_images_scale_each_value_to_its_range() {
+        let (x, y) = TILE;
+        for field in ClimateField::ALL {
+            let value = field.values(map())[y * 40 + x];
+            let gray = field_image(map(), field, RasterStyle::Grayscale);
+            assert_eq!(gray.dimensions(), (40, 30));
+            let level = gray.get_pixel(x as u32, y as u32)[0];
+            assert_eq!(level, (value / field.range() * 255.0).round() as u8);
+        }
+    }
+
+    #[test]
+    fn temperature_is_read_from_the_terrain() {
+        let (x, y) = TILE;
+        let temperature = map().terrain[y][x].temperature as f32;
+        assert_eq!(ClimateField::Temperature.values(map())[y * 40 + x], temperature);
+    }
+
+    #[test]
+    fn raster_styles_are_found_by_name() {
+        assert_eq!(RasterStyle::parse("Gray"), Some(RasterStyle::Grayscale));
+        assert!(RasterStyle::parse("sepia").is_none());
+    }
+
+    #[test]
+    fn weather_csv_has_a_row_a_day_for_every_place() {
+        let map = map();
+        let csv = weather_csv(map, 10);
+        assert_eq!(csv.lines().count(), 1 + 10 * (map.cities.len() + map.labels.len()));
+        let city = &map.cities[0];
+        let first = map.weather_at(city.x, city.y, 0).unwrap();
+        let row = csv.lines().nth(1).unwrap();
+        assert!(row.starts_with(&format!("\"{}\",city,{},{},0,{:.1},", city.name, city.x, city.y, first.temperature_c)));
+        assert!(row.ends_with(first.conditions()));
+    }
+}
@@ -0,0 +1,307 @@
+//! User configuration file.
+//!
+//! Defaults for every invocation live in `~/.config/mapper/config.toml`
+//! (or `$XDG_CONFIG_HOME/mapper/config.toml`), and `--config <file>` points
+//! the CLI at a different one. Every key is optional; anything missing falls
+//! back to the built-in defaults. Command-line flags override the file.
+//!
+//! ```toml
+//! output_dir = "maps"
+//! name_template = "{seed}_{land}"
+//! style = "satellite"
+//! names = "dwarf_names.toml"
+//!
+//! [palette]
+//! slope_tint = [90, 70, 50]
+//! coastline = [20, 20, 40]
+//!
+//! [settings]
+//! river_density = 0.7
+//! city_density = 0.3
+//! land_percentage = 0.55
+//! temperature = 0.4
+//!
+//! # What makes a good city site (see `SettlementWeights`)
+//! [settings.settlement_weights]
+//! coast = 0.9
+//! fresh_water = 0.3
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::terrain_generator::GenerationSettings;
+use crate::terrain_renderer::{RenderOptions, RenderStyle};
+
+/// Default file stem: seed plus the main settings as whole percentages,
+/// e.g. `terrain_s42_l40_r50_c50`.
+pub const DEFAULT_NAME_TEMPLATE: &str = "terrain_s{seed}_l{land}_r{rivers}_c{cities}{variant}";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Default generation settings (CLI flags and GUI sliders start here)
+    pub settings: GenerationSettings,
+    /// Directory generated files are written to (default: current directory)
+    pub output_dir: Option<PathBuf>,
+    /// File stem template for generated files (see [`expand_name_template`])
+    pub name_template: Option<String>,
+    /// User-defined presets (see [`crate::presets`])
+    pub presets: BTreeMap<String, GenerationSettings>,
+    /// Terrain coloring, one of `RenderStyle::NAMES` (default: atlas)
+    pub style: Option<String>,
+    /// Colors rendered maps start from
+    pub palette: Palette,
+    /// Name lists file to name places in, for maps in one culture's style
+    /// (see [`crate::name_lists`])
+    pub names: Option<PathBuf>,
+}
+
+/// Map colors from the config file; each missing one keeps the renderer's.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Palette {
+    /// What steep slopes are tinted toward, as `[r, g, b]`
+    pub slope_tint: Option<[u8; 3]>,
+    /// The line drawn along coasts, as `[r, g, b]`
+    pub coastline: Option<[u8; 3]>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "cannot read {}: {}", path.display(), e),
+            ConfigError::Parse(path, e) => write!(f, "invalid config {}: {}", path.display(), e),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// Location of the per-user config file, if a home directory is known.
+    pub fn default_path() -> Option<PathBuf> {
+        let base = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| {
+                std::env::var_os("HOME")
+                    .or_else(|| std::env::var_os("USERPROFILE"))
+                    .map(|home| PathBuf::from(home).join(".config"))
+            })?;
+        Some(base.join("mapper").join("config.toml"))
+    }
+
+    /// Load the per-user config file. A missing file is not an error - it
+    /// just means "use the defaults".
+    pub fn load_default() -> Result<Config, ConfigError> {
+        match Self::default_path() {
+            Some(path) if path.exists() => Self::load(&path),
+            _ => Ok(Config::default()),
+        }
+    }
+
+    /// Load an explicitly requested config file (must exist).
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        let text = fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+        Self::parse(&text).map_err(|e| ConfigError::Parse(path.to_path_buf(), e))
+    }
+
+    pub fn parse(text: &str) -> Result<Config, toml::de::Error> {
+        let mut config: Config = toml::from_str(text)?;
+        config.settings = config.settings.clamped();
+        if let Some(style) = config.style.as_deref().filter(|s| RenderStyle::parse(s).is_none()) {
+            return Err(serde::de::Error::custom(format!(
+                "unknown style '{}' (available: {})",
+                style,
+                RenderStyle::NAMES.join(", ")
+            )));
+        }
+        Ok(config)
+    }
+
+    /// The configured terrain coloring, if any.
+    pub fn render_style(&self) -> Option<RenderStyle> {
+        self.style.as_deref().and_then(RenderStyle::parse)
+    }
+
+    /// Default render options with the configured style and palette.
+    pub fn render_options(&self) -> RenderOptions {
+        let defaults = RenderOptions::default();
+        RenderOptions {
+            style: self.render_style().unwrap_or(defaults.style),
+            slope_tint: self.palette.slope_tint.unwrap_or(defaults.slope_tint),
+            coastline: self.palette.coastline.or(defaults.coastline),
+            ..defaults
+        }
+    }
+
+    /// Where a generated file called `filename` should be written.
+    pub fn output_path(&self, filename: &str) -> PathBuf {
+        match &self.output_dir {
+            Some(dir) => dir.join(filename),
+            None => PathBuf::from(filename),
+        }
+    }
+
+    /// Deterministic output path for a map: the same seed and settings always
+    /// produce the same name, so re-running a command overwrites its own
+    /// output and different settings never collide.
+    pub fn output_file(&self, seed: u32, settings: &GenerationSettings, extension: &str) -> PathBuf {
+        let template = self.name_template.as_deref().unwrap_or(DEFAULT_NAME_TEMPLATE);
+        let stem = expand_name_template(template, seed, settings);
+        self.output_path(&format!("{}.{}", stem, extension))
+    }
+}
+
+/// Expand a file stem template. Placeholders:
+///
+/// - `{seed}`: the map seed
+/// - `{land}`, `{rivers}`, `{cities}`, `{temp}`: settings as whole percentages
+/// - `{formation}`: the pinned formation, or `random`
+/// - `{hash}`: 8 hex digits identifying the exact settings
+/// - `{variant}`: empty when the map differs from the defaults only in
+///   land, rivers and cities, each a whole percentage, otherwise `_`
+///   followed by `{hash}`, so names stay short but never collide
+///
+/// Anything else is copied through unchanged.
+pub fn expand_name_template(template: &str, seed: u32, settings: &GenerationSettings) -> String {
+    let percent = |v: f32| format!("{:.0}", v * 100.0);
+    // 0.401 would print as 40 too, and share a name with 0.4
+    let whole_percent = |v: f32| (v * 100.0).round() / 100.0 == v;
+    let hash = format!("{:08x}", settings_hash(settings));
+    let named = GenerationSettings {
+        land_percentage: settings.land_percentage,
+        river_density: settings.river_density,
+        city_density: settings.city_density,
+        ..Default::default()
+    };
+    let variant = if *settings == named
+        && whole_percent(settings.land_percentage)
+        && whole_percent(settings.river_density)
+        && whole_percent(settings.city_density)
+    {
+        String::new()
+    } else {
+        format!("_{}", hash)
+    };
+    let formation = match settings.formation {
+        Some(f) => serde_json::to_value(f)
+            .ok()
+            .and_then(|v| v.as_str().map(str::to_string))
+            .unwrap_or_default(),
+        None => "random".to_string(),
+    };
+
+    template
+        .replace("{seed}", &seed.to_string())
+        .replace("{land}", &percent(settings.land_percentage))
+        .replace("{rivers}", &percent(settings.river_density))
+        .replace("{cities}", &percent(settings.city_density))
+        .replace("{temp}", &percent(settings.temperature))
+        .replace("{formation}", &formation)
+        .replace("{hash}", &hash)
+        .replace("{variant}", &variant)
+}
+
+/// FNV-1a over the serialized settings: stable across runs and platforms.
+fn settings_hash(settings: &GenerationSettings) -> u32 {
+    let bytes = serde_json::to_vec(settings).unwrap_or_default();
+    bytes.iter().fold(0x811c_9dc5u32, |h, &b| {
+        (h ^ b as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partial_config_keeps_defaults() {
+        let config = Config::parse("[settings]\nriver_density = 0.8\n").unwrap();
+        let defaults = GenerationSettings::default();
+        assert_eq!(config.settings.river_density, 0.8);
+        assert_eq!(config.settings.city_density, defaults.city_density);
+        assert_eq!(config.settings.land_percentage, defaults.land_percentage);
+        assert!(config.output_dir.is_none());
+
+        let config = Config::parse("[settings.settlement_weights]\ncoast = 0.9\n").unwrap();
+        let weights = config.settings.settlement_weights;
+        assert_eq!(weights.coast, 0.9);
+        assert_eq!(weights.fresh_water, defaults.settlement_weights.fresh_water);
+    }
+
+    #[test]
+    fn default_names_are_deterministic_and_distinct() {
+        let config = Config::default();
+        let settings = GenerationSettings::default();
+        assert_eq!(
+            config.output_file(42, &settings, "png"),
+            PathBuf::from("terrain_s42_l40_r50_c50.png")
+        );
+        let colder = GenerationSettings {
+            temperature: 0.2,
+            ..settings
+        };
+        let name = config.output_file(42, &colder, "png");
+        assert_ne!(name, PathBuf::from("terrain_s42_l40_r50_c50.png"));
+        assert_eq!(name, config.output_file(42, &colder, "png"));
+    }
+
+    #[test]
+    fn fractional_percentages_get_a_variant() {
+        let config = Config::default();
+        let settings = GenerationSettings {
+            land_percentage: 0.55,
+            ..Default::default()
+        };
+        assert_eq!(
+            config.output_file(42, &settings, "png"),
+            PathBuf::from("terrain_s42_l55_r50_c50.png")
+        );
+        let nearly = GenerationSettings {
+            land_percentage: 0.401,
+            ..Default::default()
+        };
+        let name = config.output_file(42, &nearly, "png");
+        assert!(name.to_str().unwrap().starts_with("terrain_s42_l40_r50_c50_"));
+    }
+
+    #[test]
+    fn render_defaults_are_read() {
+        let config = Config::parse(
+            "style = \"line-art\"\nnames = \"dwarves.toml\"\n[palette]\ncoastline = [20, 20, 40]\n",
+        )
+        .unwrap();
+        assert_eq!(config.render_style(), Some(RenderStyle::LineArt));
+        assert_eq!(config.names, Some(PathBuf::from("dwarves.toml")));
+        assert_eq!(config.palette.coastline, Some([20, 20, 40]));
+        assert_eq!(config.palette.slope_tint, None);
+        let options = config.render_options();
+        assert_eq!(options.style, RenderStyle::LineArt);
+        assert_eq!(options.coastline, Some([20, 20, 40]));
+        assert_eq!(options.slope_tint, RenderOptions::default().slope_tint);
+    }
+
+    #[test]
+    fn unknown_style_is_an_error() {
+        assert!(Config::parse("style = \"oil-paint\"\n").is_err());
+    }
+
+    #[test]
+    fn out_of_range_settings_are_clamped() {
+        let config = Config::parse("[settings]\nland_percentage = 4.0\n").unwrap();
+        assert_eq!(config.settings.land_percentage, 1.0);
+    }
+}
@@ -0,0 +1,342 @@
+//! Assets for fantasy-map editors (Wonderdraft, Inkarnate).
+//!
+//! The editors draw their own coasts, rivers and symbols, so rather than
+//! a finished picture they want the map's shapes: a land mask to build the
+//! landmass from (white land on black water, what both import), a river
+//! mask to trace, and where to put the symbols (cities, mountains, trees
+//! and labels). Everything is at one of the editor's canvas sizes, the
+//! map scaled to fit and centred on open water.
+
+use std::path::{Path, PathBuf};
+
+use image::{GrayImage, Luma};
+use serde::Serialize;
+
+use crate::error::MapperError;
+use crate::terrain_generator::{Biome, TerrainMap};
+
+/// Mountain symbols closer together than this, in tiles, count once
+const PEAK_SPACING: usize = 4;
+/// Tiles between tree symbols in a forest
+const TREE_SPACING: usize = 3;
+
+/// A map editor to export for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Editor {
+    Wonderdraft,
+    Inkarnate,
+}
+
+impl Editor {
+    pub const NAMES: [&'static str; 2] = ["wonderdraft", "inkarnate"];
+
+    /// An editor by name, as in `NAMES`.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "wonderdraft" => Some(Editor::Wonderdraft),
+            "inkarnate" => Some(Editor::Inkarnate),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Editor::Wonderdraft => "wonderdraft",
+            Editor::Inkarnate => "inkarnate",
+        }
+    }
+
+    /// The canvas a new map gets: Wonderdraft's HD preset, Inkarnate's
+    /// 2K one.
+    pub fn default_size(self) -> (u32, u32) {
+        match self {
+            Editor::Wonderdraft => (1920, 1080),
+            Editor::Inkarnate => (2048, 1536),
+        }
+    }
+}
+
+/// Where the map lies on a canvas: pixels per tile and the top-left offset.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CanvasFit {
+    pub width: u32,
+    pub height: u32,
+    pub scale: f32,
+    pub offset: (f32, f32),
+}
+
+impl CanvasFit {
+    /// `map` scaled to fit a `width`×`height` canvas, centred.
+    pub fn new(map: &TerrainMap, width: u32, height: u32) -> CanvasFit {
+        let scale = (width as f32 / map.width.max(1) as f32).min(height as f32 / map.height.max(1) as f32);
+        let offset = (
+            (width as f32 - map.width as f32 * scale) / 2.0,
+            (height as f32 - map.height as f32 * scale) / 2.0,
+        );
+        CanvasFit { width, height, scale, offset }
+    }
+
+    /// Canvas pixel of the centre of tile (`x`, `y`).
+    pub fn tile_center(&self, x: f32, y: f32) -> [f32; 2] {
+        [self.offset.0 + (x + 0.5) * self.scale, self.offset.1 + (y + 0.5) * self.scale]
+    }
+
+    /// The tile under canvas pixel (`px`, `py`), as fractional tile
+    /// coordinates of tile centres.
+    fn tile_at(&self, px: u32, py: u32) -> (f32, f32) {
+        (
+            (px as f32 + 0.5 - self.offset.0) / self.scale - 0.5,
+            (py as f32 + 0.5 - self.offset.1) / self.scale - 0.5,
+        )
+    }
+}
+
+/// Land in white on black water and lakes. Elevation is interpolated
+/// between tiles, so coasts are smooth at any scale.
+pub fn land_mask(map: &TerrainMap, fit: &CanvasFit) -> GrayImage {
+    let (width, height) = (map.width, map.height);
+    let mut mask = GrayImage::new(fit.width, fit.height);
+    if width == 0 || height == 0 {
+        return mask;
+    }
+    let elevation = |x: usize, y: usize| map.terrain[y.min(height - 1)][x.min(width - 1)].elevation;
+    for (px, py, pixel) in mask.enumerate_pixels_mut() {
+        let (tx, ty) = fit.tile_at(px, py);
+        if tx < -0.5 || ty < -0.5 || tx > width as f32 - 0.5 || ty > height as f32 - 0.5 {
+            continue;
+        }
+        let (x0, y0) = (tx.max(0.0).floor() as usize, ty.max(0.0).floor() as usize);
+        let (fx, fy) = ((tx - x0 as f32).clamp(0.0, 1.0) as f64, (ty - y0 as f32).clamp(0.0, 1.0) as f64);
+        let top = elevation(x0, y0) * (1.0 - fx) + elevation(x0 + 1, y0) * fx;
+        let bottom = elevation(x0, y0 + 1) * (1.0 - fx) + elevation(x0 + 1, y0 + 1) * fx;
+        let nearest = (tx.round().max(0.0) as usize).min(width - 1);
+        let lake = map.terrain[(ty.round().max(0.0) as usize).min(height - 1)][nearest].biome == Biome::Lake;
+        if top * (1.0 - fy) + bottom * fy >= 0.0 && !lake {
+            *pixel = Luma([255]);
+        }
+    }
+    mask
+}
+
+/// Rivers in white on black, wider downstream.
+pub fn river_mask(map: &TerrainMap, fit: &CanvasFit) -> GrayImage {
+    let mut mask = GrayImage::new(fit.width, fit.height);
+    for river in river_paths(map, fit) {
+        let radius = (fit.scale * 0.25 * river.order as f32).max(1.0);
+        for segment in river.points.windows(2) {
+            let ([ax, ay], [bx, by]) = (segment[0], segment[1]);
+            let steps = (bx - ax).abs().max((by - ay).abs()).ceil().max(1.0) as usize;
+            for step in 0..=steps {
+                let t = step as f32 / steps as f32;
+                let (cx, cy) = (ax + (bx - ax) * t, ay + (by - ay) * t);
+                let r = radius.ceil() as i64;
+                for dy in -r..=r {
+                    for dx in -r..=r {
+                        let (x, y) = (cx as i64 + dx, cy as i64 + dy);
+                        let inside = (0..fit.width as i64).contains(&x) && (0..fit.height as i64).contains(&y);
+                        if inside && ((dx * dx + dy * dy) as f32) <= radius * radius {
+                            mask.put_pixel(x as u32, y as u32, Luma([255]));
+                        }
+                    }
+                }
+            }
+        }
+    }
+    mask
+}
+
+/// A river as a polyline from source to mouth, in canvas pixels.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RiverPath {
+    /// Strahler order at the mouth: 1 for a stream, higher downstream of
+    /// confluences
+    pub order: u32,
+    pub points: Vec<[f32; 2]>,
+}
+
+fn river_paths(map: &TerrainMap, fit: &CanvasFit) -> Vec<RiverPath> {
+    map.river_flows()
+        .into_iter()
+        .map(|flow| RiverPath {
+            order: flow.order,
+            points: map.rivers[flow.river]
+                .iter()
+                .map(|&(x, y)| fit.tile_center(x as f32, y as f32))
+                .collect(),
+        })
+        .collect()
+}
+
+/// A symbol to place, at its centre in canvas pixels.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Symbol {
+    /// "city", "town", "village", "mountain", "tree" or "label"
+    pub kind: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub x: f32,
+    pub y: f32,
+}
+
+/// Where the symbols go: a symbol for each city (by size), mountain
+/// peaks, trees through the forests, and the region labels.
+pub fn symbols(map: &TerrainMap, fit: &CanvasFit) -> Vec<Symbol> {
+    let mut symbols = Vec::new();
+    let mut place = |kind, name: Option<String>, x: f32, y: f32| {
+        let [x, y] = fit.tile_center(x, y);
+        symbols.push(Symbol { kind, name, x, y });
+    };
+
+    for city in &map.cities {
+        let kind = match city.population {
+            100_000.. => "city",
+            20_000.. => "town",
+            _ => "village",
+        };
+        place(kind, Some(city.name.clone()), city.x as f32, city.y as f32);
+    }
+
+    // Peaks: the highest mountain tiles, kept apart
+    let mut mountains: Vec<(usize, usize)> = (0..map.height)
+        .flat_map(|y| (0..map.width).map(move |x| (x, y)))
+        .filter(|&(x, y)| matches!(map.terrain[y][x].biome, Biome::Mountains | Biome::SnowPeaks))
+        .collect();
+    mountains.sort_by(|a, b| map.terrain[b.1][b.0].elevation.total_cmp(&map.terrain[a.1][a.0].elevation));
+    let mut peaks: Vec<(usize, usize)> = Vec::new();
+    for (x, y) in mountains {
+        if peaks.iter().all(|&(px, py)| px.abs_diff(x).max(py.abs_diff(y)) >= PEAK_SPACING) {
+            peaks.push((x, y));
+        }
+    }
+    for (x, y) in peaks {
+        place("mountain", None, x as f32, y as f32);
+    }
+
+    // Trees on a staggered grid through the forests
+    for y in (0..map.height).step_by(TREE_SPACING) {
+        let stagger = (y / TREE_SPACING) % 2 * (TREE_SPACING / 2);
+        for x in (stagger..map.width).step_by(TREE_SPACING) {
+            if map.terrain[y][x].biome == Biome::Forest {
+                place("tree", None, x as f32, y as f32);
+            }
+        }
+    }
+
+    for label in &map.labels {
+        place("label", Some(label.name.clone()), label.x - 0.5, label.y - 0.5);
+    }
+    symbols
+}
+
+/// What the JSON file holds: the canvas, rivers and symbols.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EditorAssets {
+    pub editor: &'static str,
+    pub width: u32,
+    pub height: u32,
+    /// Canvas pixels per map tile
+    pub scale: f32,
+    pub rivers: Vec<RiverPath>,
+    pub symbols: Vec<Symbol>,
+}
+
+impl EditorAssets {
+    pub fn new(map: &TerrainMap, editor: Editor, fit: &CanvasFit) -> EditorAssets {
+        EditorAssets {
+            editor: editor.name(),
+            width: fit.width,
+            height: fit.height,
+            scale: fit.scale,
+            rivers: river_paths(map, fit),
+            symbols: symbols(map, fit),
+        }
+    }
+}
+
+/// Write the assets for `editor` at `size` next to `stem`:
+/// `<stem>_<editor>_land.png`, `<stem>_<editor>_rivers.png` and
+/// `<stem>_<editor>.json`. Returns the files written.
+pub fn save_editor_assets(
+    map: &TerrainMap,
+    stem: &Path,
+    editor: Editor,
+    (width, height): (u32, u32),
+) -> Result<Vec<PathBuf>, MapperError> {
+    let fit = CanvasFit::new(map, width, height);
+    let base = format!("{}_{}", stem.file_stem().unwrap_or_default().to_string_lossy(), editor.name());
+    let land = stem.with_file_name(format!("{}_land.png", base));
+    land_mask(map, &fit).save(&land)?;
+    let rivers = stem.with_file_name(format!("{}_rivers.png", base));
+    river_mask(map, &fit).save(&rivers)?;
+    let json = stem.with_file_name(format!("{}.json", base));
+    std::fs::write(&json, serde_json::to_string_pretty(&EditorAssets::new(map, editor, &fit))?)?;
+    Ok(vec![land, rivers, json])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain_generator::test_map;
+
+    fn map() -> &'static TerrainMap {
+        test_map(9, 80, 60)
+    }
+
+    /// The map on a 400x200 canvas
+    fn fit() -> CanvasFit {
+        CanvasFit::new(map(), 400, 200)
+    }
+
+    #[test]
+    fn height_limits_the_scale_and_the_map_is_centred_across() {
+        let fit = fit();
+        assert_eq!(fit.scale, 200.0 / 60.0);
+        assert!((fit.offset.0 - (400.0 - 80.0 * fit.scale) / 2.0).abs() < 1e-3 && fit.offset.1 == 0.0);
+    }
+
+    #[test]
+    fn land_masks_agree_with_the_map_away_from_the_coasts() {
+        let (map, fit) = (map(), fit());
+        let land = land_mask(map, &fit);
+        assert_eq!(land.dimensions(), (400, 200));
+        // Margins are water
+        assert_eq!(land.get_pixel(0, 100)[0], 0);
+        let is_land = |x: usize, y: usize| {
+            let point = &map.terrain[y][x];
+            point.elevation >= 0.0 && point.biome != Biome::Lake
+        };
+        let mut checked = 0;
+        for y in 1..59 {
+            for x in 1..79 {
+                let around = (y - 1..=y + 1).flat_map(|ny| (x - 1..=x + 1).map(move |nx| (nx, ny)));
+                if around.clone().all(|(nx, ny)| is_land(nx, ny) == is_land(x, y)) {
+                    let [px, py] = fit.tile_center(x as f32, y as f32);
+                    assert_eq!(land.get_pixel(px as u32, py as u32)[0] == 255, is_land(x, y), "tile {},{}", x, y);
+                    checked += 1;
+                }
+            }
+        }
+        assert!(checked > 1000);
+    }
+
+    #[test]
+    fn river_masks_cover_every_river_tile() {
+        let (map, fit) = (map(), fit());
+        let rivers = river_mask(map, &fit);
+        for &(x, y) in map.rivers.iter().flatten() {
+            let [px, py] = fit.tile_center(x as f32, y as f32);
+            assert_eq!(rivers.get_pixel(px as u32, py as u32)[0], 255);
+        }
+    }
+
+    #[test]
+    fn symbols_mark_every_city_on_the_canvas() {
+        let map = map();
+        let assets = EditorAssets::new(map, Editor::Inkarnate, &fit());
+        assert_eq!(assets.rivers.len(), map.rivers.len());
+        let cities = assets.symbols.iter().filter(|s| matches!(s.kind, "city" | "town" | "village"));
+        assert_eq!(cities.count(), map.cities.len());
+        assert!(assets.symbols.iter().all(|s| (0.0..400.0).contains(&s.x) && (0.0..200.0).contains(&s.y)));
+        assert_eq!(Editor::parse("Wonderdraft"), Some(Editor::Wonderdraft));
+    }
+}
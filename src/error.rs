@@ -0,0 +1,91 @@
+//! The crate-wide error type.
+//!
+//! Fallible library calls return [`MapperError`] (or a narrower error that
+//! converts into it with `?`), so the front-ends can report any failure
+//! with one message instead of panicking.
+
+use std::fmt;
+use std::io;
+
+use crate::config::ConfigError;
+use crate::terrain_generator::GenerationError;
+
+#[derive(Debug)]
+pub enum MapperError {
+    Io(io::Error),
+    /// A map file that isn't valid map JSON
+    Json(serde_json::Error),
+    /// Encoding or writing an image
+    Image(image::ImageError),
+    /// Font data that couldn't be parsed
+    Font(&'static str),
+    /// A shape mask that can't be used, and why
+    Mask(String),
+    /// A saved fog of war that can't be used, and why
+    Fog(String),
+    Config(ConfigError),
+    Generation(GenerationError),
+}
+
+impl fmt::Display for MapperError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapperError::Io(e) => write!(f, "{}", e),
+            MapperError::Json(e) => write!(f, "invalid map data: {}", e),
+            MapperError::Image(e) => write!(f, "{}", e),
+            MapperError::Font(name) => write!(f, "cannot load font {}", name),
+            MapperError::Mask(e) => write!(f, "invalid shape mask: {}", e),
+            MapperError::Fog(e) => write!(f, "invalid fog of war: {}", e),
+            MapperError::Config(e) => write!(f, "{}", e),
+            MapperError::Generation(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for MapperError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MapperError::Io(e) => Some(e),
+            MapperError::Json(e) => Some(e),
+            MapperError::Image(e) => Some(e),
+            MapperError::Font(_) | MapperError::Mask(_) | MapperError::Fog(_) => None,
+            MapperError::Config(e) => Some(e),
+            MapperError::Generation(e) => Some(e),
+        }
+    }
+}
+
+impl From<io::Error> for MapperError {
+    fn from(e: io::Error) -> Self {
+        MapperError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for MapperError {
+    fn from(e: serde_json::Error) -> Self {
+        // Failures of the underlying reader or writer stay I/O errors
+        if e.is_io() {
+            MapperError::Io(e.into())
+        } else {
+            MapperError::Json(e)
+        }
+    }
+}
+
+impl From<image::ImageError> for MapperError {
+    fn from(e: image::ImageError) -> Self {
+        MapperError::Image(e)
+    }
+}
+
+impl From<ConfigError> for MapperError {
+    fn from(e: ConfigError) -> Self {
+        MapperError::Config(e)
+    }
+}
+
+impl From<GenerationError> for MapperError {
+    fn from(e: GenerationError) -> Self {
+        MapperError::Generation(e)
+    }
+}
@@ -0,0 +1,260 @@
+//! Seed gallery.
+//!
+//! A small local collection of favorite worlds: each entry is a seed with
+//! the settings that go with it, free-form tags and a note, and a terrain
+//! thumbnail to recognise it by. The collection is a JSON file next to
+//! the config file (`~/.config/mapper/gallery.json`), the thumbnails PNGs
+//! in a `gallery` directory beside it, tagged with their seed and settings
+//! like any map the CLI saves.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, Cursor};
+use std::path::{Path, PathBuf};
+
+use image::{ImageOutputFormat, RgbImage};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::error::MapperError;
+use crate::png_metadata::MapMetadata;
+use crate::terrain_generator::{GenerationSettings, TerrainGenerator};
+use crate::terrain_renderer::{Layers, RenderOptions, TerrainRenderer};
+
+/// Largest thumbnail, in pixels; maps are shrunk to fit
+pub const THUMBNAIL_SIZE: (u32, u32) = (320, 240);
+
+/// One saved world.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GalleryEntry {
+    /// Number the entry is referred to by
+    pub id: u32,
+    pub seed: u32,
+    pub settings: GenerationSettings,
+    pub tags: BTreeSet<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+impl GalleryEntry {
+    pub fn metadata(&self) -> MapMetadata {
+        MapMetadata {
+            seed: self.seed,
+            settings: self.settings,
+        }
+    }
+
+    /// Whether this entry is `seed` with exactly `settings`.
+    fn is(&self, seed: u32, settings: &GenerationSettings) -> bool {
+        self.seed == seed
+            && serde_json::to_value(self.settings).ok() == serde_json::to_value(settings).ok()
+    }
+}
+
+/// The gallery file and its entries, in the order they were added.
+#[derive(Debug, Clone)]
+pub struct Gallery {
+    path: PathBuf,
+    pub entries: Vec<GalleryEntry>,
+}
+
+impl Gallery {
+    /// Location of the per-user gallery, next to the config file.
+    pub fn default_path() -> Option<PathBuf> {
+        Some(Config::default_path()?.with_file_name("gallery.json"))
+    }
+
+    /// Load the gallery at `path`; a missing file is an empty gallery.
+    pub fn load(path: &Path) -> Result<Gallery, MapperError> {
+        let entries = match fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Gallery {
+            path: path.to_path_buf(),
+            entries,
+        })
+    }
+
+    pub fn save(&self) -> Result<(), MapperError> {
+        if let Some(dir) = self.path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, serde_json::to_string_pretty(&self.entries)?)?;
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Add `seed` with `settings`, or add `tags` to the entry that already
+    /// has them. Returns the entry's id.
+    pub fn add(&mut self, seed: u32, settings: GenerationSettings, tags: &[String]) -> u32 {
+        let settings = settings.clamped();
+        if let Some(entry) = self.entries.iter_mut().find(|e| e.is(seed, &settings)) {
+            entry.tags.extend(tags.iter().cloned());
+            return entry.id;
+        }
+        let id = self.entries.iter().map(|e| e.id + 1).max().unwrap_or(1);
+        self.entries.push(GalleryEntry {
+            id,
+            seed,
+            settings,
+            tags: tags.iter().cloned().collect(),
+            note: None,
+        });
+        id
+    }
+
+    pub fn get(&self, id: u32) -> Option<&GalleryEntry> {
+        self.entries.iter().find(|e| e.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: u32) -> Option<&mut GalleryEntry> {
+        self.entries.iter_mut().find(|e| e.id == id)
+    }
+
+    /// Take entry `id` out of the gallery, deleting its thumbnail.
+    pub fn remove(&mut self, id: u32) -> Option<GalleryEntry> {
+        let index = self.entries.iter().position(|e| e.id == id)?;
+        let entry = self.entries.remove(index);
+        fs::remove_file(self.thumbnail_path(&entry)).ok();
+        Some(entry)
+    }
+
+    /// Entries with `tag`, or all of them for `None`.
+    pub fn tagged<'a>(&'a self, tag: Option<&'a str>) -> impl Iterator<Item = &'a GalleryEntry> + 'a {
+        self.entries
+            .iter()
+            .filter(move |e| tag.is_none_or(|tag| e.tags.contains(tag)))
+    }
+
+    /// Where the thumbnail of `entry` is kept.
+    pub fn thumbnail_path(&self, entry: &GalleryEntry) -> PathBuf {
+        self.path.with_file_name("gallery").join(format!("{}.png", entry.id))
+    }
+
+    /// Render the thumbnail of `entry` and save it. Returns its path.
+    pub fn save_thumbnail(&self, entry: &GalleryEntry) -> Result<PathBuf, MapperError> {
+        let path = self.thumbnail_path(entry);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut png = Vec::new();
+        render_thumbnail(entry.seed, entry.settings)
+            .write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)?;
+        fs::write(&path, entry.metadata().embed(&png)?)?;
+        Ok(path)
+    }
+}
+
+/// The terrain and rivers of `seed`, shrunk to fit `THUMBNAIL_SIZE`: as
+/// far as a whole factor goes by `TerrainMap::downsample` before
+/// rendering, the rest by scaling the image. Only the terrain phases run.
+pub fn render_thumbnail(seed: u32, settings: GenerationSettings) -> RgbImage {
+    let map = TerrainGenerator::new_with_settings(seed, settings)
+        .generate_terrain(settings.map_width, settings.map_height);
+    let (max_width, max_height) = THUMBNAIL_SIZE;
+    let factor = (map.width / max_width as usize).max(map.height / max_height as usize);
+    let layers = Layers {
+        terrain: true,
+        relief: true,
+        rivers: true,
+        ..Layers::none()
+    };
+    let img = TerrainRenderer::render_layers_to_image(&map.downsample(factor), 1, layers, &RenderOptions::default());
+    let img = image::DynamicImage::ImageRgba8(img).to_rgb8();
+    if img.width() <= max_width && img.height() <= max_height {
+        return img;
+    }
+    let shrink = (max_width as f32 / img.width() as f32).min(max_height as f32 / img.height() as f32);
+    let width = ((img.width() as f32 * shrink) as u32).max(1);
+    let height = ((img.height() as f32 * shrink) as u32).max(1);
+    image::imageops::thumbnail(&img, width, height)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn small() -> GenerationSettings {
+        GenerationSettings {
+            map_width: 60,
+            map_height: 40,
+            ..GenerationSettings::default()
+        }
+    }
+
+    /// An empty gallery in a directory of its own for the test `name`
+    fn empty_gallery(name: &str) -> (PathBuf, Gallery) {
+        let dir = std::env::temp_dir().join(format!("mapper-gallery-{}-{}", name, std::process::id()));
+        let gallery = Gallery::load(&dir.join("gallery.json")).unwrap();
+        assert!(gallery.entries.is_empty());
+        (dir, gallery)
+    }
+
+    #[test]
+    fn the_same_world_added_again_only_gains_tags() {
+        let (_, mut gallery) = empty_gallery("again");
+        let first = gallery.add(7, small(), &["islands".to_string()]);
+        let second = gallery.add(8, small(), &[]);
+        assert_ne!(first, second);
+        assert_eq!(gallery.add(7, small(), &["favorite".to_string()]), first);
+        assert_eq!(gallery.entries.len(), 2);
+    }
+
+    #[test]
+    fn entries_persist_with_their_tags_and_notes() {
+        let (dir, mut gallery) = empty_gallery("persist");
+        let first = gallery.add(7, small(), &["islands".to_string()]);
+        gallery.add(7, small(), &["favorite".to_string()]);
+        let second = gallery.add(8, small(), &[]);
+        gallery.get_mut(second).unwrap().note = Some("frozen & empty".to_string());
+        gallery.save().unwrap();
+
+        let loaded = Gallery::load(&dir.join("gallery.json")).unwrap();
+        fs::remove_dir_all(&dir).ok();
+        let tags: Vec<&str> = loaded.get(first).unwrap().tags.iter().map(String::as_str).collect();
+        assert_eq!(tags, ["favorite", "islands"]);
+        assert_eq!(loaded.get(second).unwrap().note.as_deref(), Some("frozen & empty"));
+        assert_eq!(loaded.tagged(Some("islands")).count(), 1);
+        assert_eq!(loaded.tagged(None).count(), 2);
+    }
+
+    #[test]
+    fn thumbnails_are_the_map_with_its_seed() {
+        let (dir, mut gallery) = empty_gallery("thumbnail");
+        let id = gallery.add(7, small(), &[]);
+        let thumbnail = gallery.save_thumbnail(gallery.get(id).unwrap()).unwrap();
+        let image = image::open(&thumbnail).unwrap();
+        let metadata = MapMetadata::read(&thumbnail).unwrap().unwrap();
+        fs::remove_dir_all(&dir).ok();
+        assert_eq!((image.width(), image.height()), (60, 40));
+        assert_eq!(metadata.seed, 7);
+    }
+
+    #[test]
+    fn removed_entries_take_their_thumbnail_and_keep_their_id() {
+        let (dir, mut gallery) = empty_gallery("remove");
+        let first = gallery.add(7, small(), &[]);
+        let second = gallery.add(8, small(), &[]);
+        let thumbnail = gallery.save_thumbnail(gallery.get(first).unwrap()).unwrap();
+        gallery.remove(first).unwrap();
+        assert!(!thumbnail.exists());
+        assert!(gallery.add(9, small(), &[]) > second);
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn large_maps_are_shrunk_to_thumbnails() {
+        let large = GenerationSettings {
+            map_width: 400,
+            map_height: 100,
+            ..small()
+        };
+        let shrunk = render_thumbnail(1, large);
+        assert_eq!((shrunk.width(), shrunk.height()), (320, 80));
+    }
+}
@@ -0,0 +1,241 @@
+//! Globe views of a world map.
+//!
+//! The map is taken as a whole world in equirectangular form: its width
+//! spans 360 degrees of longitude, from 180 W at the left edge, and its
+//! height 180 degrees of latitude, the north pole along the top. A view
+//! shows the hemisphere around its centre as a disc, either as the globe
+//! would look from far away (orthographic) or as a polar map that keeps
+//! distances from the centre true (azimuthal equidistant). Each pixel of
+//! the disc is looked up in the flat render, so the terrain, coastlines,
+//! rivers and roads are reprojected with it; labels are placed at their
+//! projected anchors with `GlobeView::place` and drawn upright. Maps made
+//! with `wrap_x` join at the back of the globe without a seam.
+
+use std::f64::consts::FRAC_PI_2;
+
+use image::{Rgba, RgbaImage};
+
+/// How the sphere is flattened onto the disc.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GlobeProjection {
+    /// The globe seen from far off: the hemisphere facing the viewer,
+    /// foreshortened towards the rim
+    #[default]
+    Orthographic,
+    /// Azimuthal equidistant, centred on the north pole unless another
+    /// centre is given: distances and directions from the centre are true
+    Polar,
+}
+
+impl GlobeProjection {
+    pub const NAMES: [&'static str; 2] = ["orthographic", "polar"];
+
+    /// A projection by name, as in `NAMES`.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "orthographic" | "globe" => Some(GlobeProjection::Orthographic),
+            "polar" | "azimuthal" => Some(GlobeProjection::Polar),
+            _ => None,
+        }
+    }
+
+    /// Where the view looks by default, as (longitude, latitude) in
+    /// degrees: the middle of the map, or the north pole for `Polar`.
+    pub fn default_center(self) -> (f32, f32) {
+        match self {
+            GlobeProjection::Orthographic => (0.0, 0.0),
+            GlobeProjection::Polar => (0.0, 90.0),
+        }
+    }
+}
+
+/// A globe view: its projection, where it is centred and its size.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GlobeView {
+    pub projection: GlobeProjection,
+    /// Longitude and latitude at the centre of the disc, in degrees
+    pub center: (f32, f32),
+    /// Width and height of the square image, in pixels; `None` matches
+    /// the height of the flat render, so the centre keeps its detail
+    pub size: Option<u32>,
+}
+
+impl GlobeView {
+    /// A view in `projection` from its default centre.
+    pub fn new(projection: GlobeProjection) -> GlobeView {
+        GlobeView {
+            projection,
+            center: projection.default_center(),
+            size: None,
+        }
+    }
+
+    /// Size of the image for a flat render `flat_height` pixels high.
+    pub fn image_size(&self, flat_height: u32) -> u32 {
+        self.size.unwrap_or(flat_height).max(8)
+    }
+
+    /// Where (`lon`, `lat`), in degrees, falls on the disc: unit disc
+    /// coordinates with north up, or `None` on the hemisphere out of view.
+    pub fn project(&self, lon: f64, lat: f64) -> Option<(f64, f64)> {
+        let (lon0, lat0) = self.center_radians();
+        let (lon, lat) = (lon.to_radians(), lat.to_radians());
+        let d_lon = lon - lon0;
+        let cos_c = lat0.sin() * lat.sin() + lat0.cos() * lat.cos() * d_lon.cos();
+        if cos_c < 0.0 {
+            return None;
+        }
+        let x = lat.cos() * d_lon.sin();
+        let y = lat0.cos() * lat.sin() - lat0.sin() * lat.cos() * d_lon.cos();
+        let k = match self.projection {
+            GlobeProjection::Orthographic => 1.0,
+            GlobeProjection::Polar => {
+                // Scaled so the angular distance c lies c / 90 degrees out
+                let c = cos_c.clamp(-1.0, 1.0).acos();
+                if c < 1e-9 { 1.0 } else { c / c.sin() / FRAC_PI_2 }
+            }
+        };
+        Some((x * k, y * k))
+    }
+
+    /// The longitude and latitude, in degrees, at unit disc coordinates
+    /// (`x`, `y`), or `None` off the disc.
+    pub fn unproject(&self, x: f64, y: f64) -> Option<(f64, f64)> {
+        let rho = x.hypot(y);
+        if rho > 1.0 {
+            return None;
+        }
+        let (lon0, lat0) = self.center_radians();
+        if rho < 1e-12 {
+            return Some((lon0.to_degrees(), lat0.to_degrees()));
+        }
+        let c = match self.projection {
+            GlobeProjection::Orthographic => rho.asin(),
+            GlobeProjection::Polar => rho * FRAC_PI_2,
+        };
+        let lat = (c.cos() * lat0.sin() + y * c.sin() * lat0.cos() / rho).clamp(-1.0, 1.0).asin();
+        let lon = lon0 + (x * c.sin()).atan2(rho * lat0.cos() * c.cos() - y * lat0.sin() * c.sin());
+        Some((wrap_longitude(lon.to_degrees()), lat.to_degrees()))
+    }
+
+    /// Where the pixel (`x`, `y`) of a flat render `flat` pixels across
+    /// and down lands in this view's image, or `None` if it is out of view.
+    pub fn place(&self, flat: (u32, u32), x: f32, y: f32) -> Option<(f32, f32)> {
+        let (width, height) = (flat.0.max(1) as f64, flat.1.max(1) as f64);
+        let lon = x as f64 / width * 360.0 - 180.0;
+        let lat = 90.0 - y as f64 / height * 180.0;
+        let (dx, dy) = self.project(lon, lat)?;
+        let radius = self.radius(self.image_size(flat.1));
+        Some(((radius.0 + dx * radius.1) as f32, (radius.0 - dy * radius.1) as f32))
+    }
+
+    /// `flat`, a render of a whole map, reprojected onto the disc. Pixels
+    /// are interpolated from the four nearest of the flat render, across
+    /// its east-west edges; outside the disc is transparent.
+    pub fn reproject(&self, flat: &RgbaImage) -> RgbaImage {
+        let size = self.image_size(flat.height());
+        let (center, radius) = self.radius(size);
+        let (width, height) = flat.dimensions();
+        let mut globe = RgbaImage::new(size, size);
+        if width == 0 || height == 0 {
+            return globe;
+        }
+        for (px, py, pixel) in globe.enumerate_pixels_mut() {
+            let x = (px as f64 + 0.5 - center) / radius;
+            let y = (center - py as f64 - 0.5) / radius;
+            let Some((lon, lat)) = self.unproject(x, y) else {
+                continue;
+            };
+            let fx = (lon + 180.0) / 360.0 * width as f64 - 0.5;
+            let fy = ((90.0 - lat) / 180.0 * height as f64 - 0.5).clamp(0.0, height as f64 - 1.0);
+            *pixel = bilinear(flat, fx, fy);
+        }
+        globe
+    }
+
+    /// Centre and radius of the disc in an image `size` pixels across.
+    fn radius(&self, size: u32) -> (f64, f64) {
+        let center = size as f64 / 2.0;
+        (center, center - 1.0)
+    }
+
+    fn center_radians(&self) -> (f64, f64) {
+        let lat = (self.center.1 as f64).clamp(-90.0, 90.0);
+        (wrap_longitude(self.center.0 as f64).to_radians(), lat.to_radians())
+    }
+}
+
+/// `lon` brought into -180..180 degrees.
+fn wrap_longitude(lon: f64) -> f64 {
+    (lon + 180.0).rem_euclid(360.0) - 180.0
+}
+
+/// The color of `img` at (`x`, `y`), between pixel centres, wrapping
+/// east-west.
+fn bilinear(img: &RgbaImage, x: f64, y: f64) -> Rgba<u8> {
+    let (width, height) = (img.width() as i64, img.height() as i64);
+    let (x0, y0) = (x.floor(), y.floor());
+    let (tx, ty) = (x - x0, y - y0);
+    let column = |dx: i64| (x0 as i64 + dx).rem_euclid(width) as u32;
+    let row = |dy: i64| (y0 as i64 + dy).clamp(0, height - 1) as u32;
+    let mut out = [0u8; 4];
+    for (c, channel) in out.iter_mut().enumerate() {
+        let at = |dx, dy| img.get_pixel(column(dx), row(dy))[c] as f64;
+        let top = at(0, 0) * (1.0 - tx) + at(1, 0) * tx;
+        let bottom = at(0, 1) * (1.0 - tx) + at(1, 1) * tx;
+        *channel = (top * (1.0 - ty) + bottom * ty).round() as u8;
+    }
+    Rgba(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn views_project_and_unproject_the_same_points() {
+        for projection in [GlobeProjection::Orthographic, GlobeProjection::Polar] {
+            let view = GlobeView { center: (30.0, 20.0), ..GlobeView::new(projection) };
+            for &(lon, lat) in &[(30.0, 20.0), (70.0, 40.0), (-10.0, -30.0), (100.0, 10.0)] {
+                let (x, y) = view.project(lon, lat).unwrap();
+                let (back_lon, back_lat) = view.unproject(x, y).unwrap();
+                assert!((back_lon - lon).abs() < 1e-6 && (back_lat - lat).abs() < 1e-6, "{:?}", projection);
+            }
+        }
+    }
+
+    #[test]
+    fn the_far_side_is_hidden_and_off_the_disc_is_nowhere() {
+        for projection in [GlobeProjection::Orthographic, GlobeProjection::Polar] {
+            let view = GlobeView { center: (30.0, 20.0), ..GlobeView::new(projection) };
+            assert_eq!(view.project(-150.0, -20.0), None);
+            assert_eq!(view.unproject(0.8, 0.8), None);
+        }
+    }
+
+    #[test]
+    fn the_north_pole_is_the_middle_of_a_polar_view_and_the_equator_its_rim() {
+        let polar = GlobeView::new(GlobeProjection::Polar);
+        assert_eq!(polar.project(45.0, 90.0).map(|(x, y)| x.hypot(y) < 1e-9), Some(true));
+        let (x, y) = polar.project(45.0, 0.0).unwrap();
+        assert!((x.hypot(y) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn a_flat_renders_middle_lands_mid_disc_with_its_color() {
+        let mut flat = RgbaImage::from_pixel(360, 180, Rgba([0, 0, 255, 255]));
+        for y in 85..95 {
+            for x in 175..185 {
+                flat.put_pixel(x, y, Rgba([255, 0, 0, 255]));
+            }
+        }
+        let view = GlobeView::new(GlobeProjection::Orthographic);
+        let globe = view.reproject(&flat);
+        assert_eq!(globe.dimensions(), (180, 180));
+        assert_eq!(view.place((360, 180), 180.0, 90.0), Some((90.0, 90.0)));
+        assert_eq!(globe.get_pixel(90, 90), &Rgba([255, 0, 0, 255]));
+        assert_eq!(globe.get_pixel(90, 20), &Rgba([0, 0, 255, 255]));
+        // Corners are outside the disc
+        assert_eq!(globe.get_pixel(0, 0)[3], 0);
+    }
+}
@@ -0,0 +1,406 @@
+//! Hex grid overlay and hex-crawl content sheets.
+//!
+//! Hex-crawl campaigns key their maps by hex: a number on each hex and a
+//! sheet listing what is in it. The grid is flat-topped, in offset
+//! columns with the odd ones shifted down half a hex (numbered `CCRR`
+//! from `0101` in the top-left), and `size` tiles across from flat side to
+//! flat side. Each hex's entry gives its most common terrain, what it
+//! holds (cities, rivers, crossings, roads, lakes and coast) and a short
+//! description to read out at the table, as Markdown or CSV.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as _;
+
+use image::{Rgba, RgbaImage};
+use serde::Serialize;
+
+use crate::terrain_generator::{Biome, TerrainMap};
+
+/// Flat-topped hexes over a map, `size` tiles from flat side to flat side.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HexGrid {
+    pub size: f32,
+}
+
+impl HexGrid {
+    pub fn new(size: f32) -> HexGrid {
+        HexGrid { size: size.max(1.0) }
+    }
+
+    /// Distance from a hex's centre to its corners, in tiles.
+    fn radius(&self) -> f32 {
+        self.size / 3f32.sqrt()
+    }
+
+    /// Centre of hex (`col`, `row`), in tiles. Hex (0, 0) is centred on
+    /// the map's top-left corner.
+    pub fn center(&self, col: usize, row: usize) -> (f32, f32) {
+        let x = 1.5 * self.radius() * col as f32;
+        let y = self.size * (row as f32 + if col % 2 == 1 { 0.5 } else { 0.0 });
+        (x, y)
+    }
+
+    /// The hex holding the point (`x`, `y`), in tiles; points left of or
+    /// above the map give `None`.
+    pub fn hex_at(&self, x: f32, y: f32) -> Option<(usize, usize)> {
+        if x < 0.0 || y < 0.0 {
+            return None;
+        }
+        // Axial coordinates, rounded in cube space
+        let r = self.radius();
+        let q = 2.0 / 3.0 * x / r;
+        let s = (-x / 3.0 + 3f32.sqrt() / 3.0 * y) / r;
+        let cube = [q, s, -q - s];
+        let mut rounded = cube.map(f32::round);
+        let diff = [0, 1, 2].map(|k| (rounded[k] - cube[k]).abs());
+        if diff[0] > diff[1] && diff[0] > diff[2] {
+            rounded[0] = -rounded[1] - rounded[2];
+        } else if diff[1] > diff[2] {
+            rounded[1] = -rounded[0] - rounded[2];
+        }
+        let (q, s) = (rounded[0] as i64, rounded[1] as i64);
+        let row = s + (q - (q & 1)) / 2;
+        Some((usize::try_from(q).ok()?, usize::try_from(row).ok()?))
+    }
+
+    /// The hex each tile of `map` belongs to, by its centre.
+    pub fn hex_of_tile(&self, x: usize, y: usize) -> (usize, usize) {
+        self.hex_at(x as f32 + 0.5, y as f32 + 0.5).unwrap_or((0, 0))
+    }
+
+    /// The six corners of hex (`col`, `row`), in tiles, clockwise from the
+    /// right.
+    pub fn corners(&self, col: usize, row: usize) -> [(f32, f32); 6] {
+        let (cx, cy) = self.center(col, row);
+        let r = self.radius();
+        [0, 1, 2, 3, 4, 5].map(|k| {
+            let angle = std::f32::consts::PI / 3.0 * k as f32;
+            (cx + r * angle.cos(), cy + r * angle.sin())
+        })
+    }
+
+    /// Every hex with a tile of `map`, by column then row.
+    pub fn hexes(&self, map: &TerrainMap) -> BTreeMap<(usize, usize), Vec<(usize, usize)>> {
+        let mut hexes: BTreeMap<_, Vec<_>> = BTreeMap::new();
+        for y in 0..map.height {
+            for x in 0..map.width {
+                hexes.entry(self.hex_of_tile(x, y)).or_default().push((x, y));
+            }
+        }
+        hexes
+    }
+}
+
+/// The `CCRR` number of hex (`col`, `row`), counting from 01.
+pub fn hex_number(col: usize, row: usize) -> String {
+    format!("{:02}{:02}", col + 1, row + 1)
+}
+
+/// Draw the hex outlines over `img`, a render of `map` at `scale` pixels
+/// per tile. Hex numbers are text and left to the caller.
+pub fn draw_hex_grid(img: &mut RgbaImage, map: &TerrainMap, grid: &HexGrid, scale: u32, color: Rgba<u8>) {
+    let s = scale as f32;
+    let (width, height) = (img.width() as f32, img.height() as f32);
+    for &(col, row) in grid.hexes(map).keys() {
+        let corners = grid.corners(col, row);
+        for k in 0..6 {
+            let (a, b) = (corners[k], corners[(k + 1) % 6]);
+            let steps = ((b.0 - a.0).abs().max((b.1 - a.1).abs()) * s).ceil().max(1.0) as usize;
+            for step in 0..=steps {
+                let t = step as f32 / steps as f32;
+                let (x, y) = ((a.0 + (b.0 - a.0) * t) * s, (a.1 + (b.1 - a.1) * t) * s);
+                if (0.0..width).contains(&x) && (0.0..height).contains(&y) {
+                    img.put_pixel(x as u32, y as u32, color);
+                }
+            }
+        }
+    }
+}
+
+/// Something in a hex worth keying.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum HexFeature {
+    City { name: String, population: u32 },
+    /// A bridge where a road crosses a river
+    Crossing { name: String },
+    /// A point of interest other than a city or bridge, e.g. ruins
+    Poi { poi_kind: String, name: String },
+    Road { name: String },
+    River,
+    Lake,
+    Coast,
+}
+
+impl HexFeature {
+    fn short(&self) -> String {
+        match self {
+            HexFeature::City { name, population } => format!("{} (pop. {})", name, population),
+            HexFeature::Crossing { name } => format!("{} (crossing)", name),
+            HexFeature::Poi { poi_kind, name } => format!("{} ({})", name, poi_kind),
+            HexFeature::Road { name } => name.clone(),
+            HexFeature::River => "river".to_string(),
+            HexFeature::Lake => "lake".to_string(),
+            HexFeature::Coast => "coast".to_string(),
+        }
+    }
+}
+
+/// One hex of the sheet.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HexEntry {
+    /// `CCRR`
+    pub number: String,
+    pub col: usize,
+    pub row: usize,
+    /// Most common biome of its tiles on the map
+    pub terrain: Biome,
+    pub features: Vec<HexFeature>,
+    pub description: String,
+}
+
+/// The hex-crawl sheet of `map` on `grid`, one entry per hex.
+pub fn hex_sheet(map: &TerrainMap, grid: &HexGrid) -> Vec<HexEntry> {
+    grid.hexes(map)
+        .into_iter()
+        .map(|((col, row), tiles)| {
+            let tile_set: BTreeSet<(usize, usize)> = tiles.iter().copied().collect();
+            let mut counts: BTreeMap<u8, (usize, Biome)> = BTreeMap::new();
+            for &(x, y) in &tiles {
+                let biome = map.terrain[y][x].biome;
+                counts.entry(biome as u8).or_insert((0, biome)).0 += 1;
+            }
+            let mut ranked: Vec<(usize, Biome)> = counts.into_values().collect();
+            ranked.sort_by_key(|&(count, _)| std::cmp::Reverse(count));
+            let terrain = ranked[0].1;
+            let second = ranked
+                .get(1)
+                .filter(|&&(count, _)| count * 4 >= tiles.len())
+                .map(|&(_, biome)| biome);
+
+            let mut features = BTreeSet::new();
+            for city in map.cities.iter().filter(|c| tile_set.contains(&(c.x, c.y))) {
+                features.insert(HexFeature::City { name: city.name.clone(), population: city.population });
+            }
+            for bridge in map.bridges.iter().filter(|b| tile_set.contains(&(b.x, b.y))) {
+                features.insert(HexFeature::Crossing { name: bridge.name.clone() });
+            }
+            for poi in map.pois.iter().filter(|p| tile_set.contains(&p.position)) {
+                features.insert(HexFeature::Poi { poi_kind: poi.kind.clone(), name: poi.name.clone() });
+            }
+            for road in map.roads.iter().filter(|r| r.path.iter().any(|p| tile_set.contains(p))) {
+                features.insert(HexFeature::Road { name: road.name.clone() });
+            }
+            let has = |biome: Biome| tiles.iter().any(|&(x, y)| map.terrain[y][x].biome == biome);
+            if has(Biome::River) || map.rivers.iter().flatten().any(|p| tile_set.contains(p)) {
+                features.insert(HexFeature::River);
+            }
+            if has(Biome::Lake) {
+                features.insert(HexFeature::Lake);
+            }
+            let sea = tiles
+                .iter()
+                .any(|&(x, y)| matches!(map.terrain[y][x].biome, Biome::Shore | Biome::Ocean | Biome::DeepOcean));
+            if sea && tiles.iter().any(|&(x, y)| !map.terrain[y][x].biome.is_water()) {
+                features.insert(HexFeature::Coast);
+            }
+            let features: Vec<HexFeature> = features.into_iter().collect();
+
+            // The region named around the hex's centre, if it has one
+            let (cx, cy) = grid.center(col, row);
+            let centre = tiles
+                .iter()
+                .min_by(|a, b| {
+                    let d = |&(x, y): &(usize, usize)| (x as f32 + 0.5 - cx).powi(2) + (y as f32 + 0.5 - cy).powi(2);
+                    d(a).total_cmp(&d(b))
+                })
+                .copied()
+                .unwrap_or((0, 0));
+            let region = map.region_of(centre.0, centre.1).map(|label| label.name.as_str());
+            let description = describe(terrain, second, &features, region, col * 31 + row * 17);
+
+            HexEntry {
+                number: hex_number(col, row),
+                col,
+                row,
+                terrain,
+                features,
+                description,
+            }
+        })
+        .collect()
+}
+
+/// A phrase for land or sea of `biome`, one of a few picked by `variant`.
+fn terrain_phrase(biome: Biome, variant: usize) -> &'static str {
+    let phrases: &[&str] = match biome {
+        Biome::DeepOcean => &["Deep, dark open sea", "Fathomless grey water", "Empty ocean to every horizon"],
+        Biome::Ocean => &["Open sea", "Rolling grey-green swells", "Choppy open water"],
+        Biome::Shore => &["Shallow coastal water", "Clear shallows over sand", "Shoals and sandbars"],
+        Biome::Beach => &["Sandy shoreline", "Dunes and driftwood", "A long strand of sand"],
+        Biome::Plains => &["Open grassland", "Rolling meadows", "Wide, windswept plains"],
+        Biome::Forest => &["Dense woodland", "Old forest under a thick canopy", "Tangled woods"],
+        Biome::Hills => &["Rolling hills", "Broken hill country", "Steep, grassy hills"],
+        Biome::Mountains => &["Rugged mountains", "Bare rock and scree", "High, craggy peaks"],
+        Biome::SnowPeaks => &["Snowbound summits", "Ice and wind-scoured snow", "Glaciers among the peaks"],
+        Biome::River => &["A broad river valley", "Reeds along a winding river", "A river plain"],
+        Biome::Lake => &["A still lake", "Dark lake water", "A wide mere"],
+        Biome::Swamp => &["Sodden marsh", "Fog over black water and reeds", "A mire of sedge and sinkholes"],
+        Biome::Desert => &["Sun-baked desert", "Dunes of blowing sand", "Cracked, stony waste"],
+    };
+    phrases[variant % phrases.len()]
+}
+
+/// A short description of a hex to read out.
+fn describe(terrain: Biome, second: Option<Biome>, features: &[HexFeature], region: Option<&str>, variant: usize) -> String {
+    let mut text = terrain_phrase(terrain, variant).to_string();
+    if let Some(region) = region {
+        let _ = write!(text, " in the {}", region.strip_prefix("The ").unwrap_or(region));
+    }
+    if let Some(second) = second {
+        let _ = write!(text, ", giving way to {}", terrain_phrase(second, variant + 1).to_lowercase());
+    }
+    text.push('.');
+
+    for feature in features {
+        let sentence = match feature {
+            HexFeature::City { name, population } if *population >= 100_000 => {
+                format!(" The city of {} ({} souls) dominates the hex.", name, population)
+            }
+            HexFeature::City { name, population } => format!(" The town of {} ({} souls) lies here.", name, population),
+            HexFeature::Crossing { name } => format!(" {} spans the river.", name),
+            HexFeature::Poi { poi_kind, name } => format!(" {} ({}) is here.", name, poi_kind),
+            HexFeature::Road { name } => format!(" {} passes through.", name),
+            HexFeature::River if !features.iter().any(|f| matches!(f, HexFeature::Crossing { .. })) => {
+                " A river runs through, with no bridge.".to_string()
+            }
+            HexFeature::Lake if terrain != Biome::Lake => " A lake lies in a hollow.".to_string(),
+            HexFeature::Coast if !second.is_some_and(|b| b.is_water()) => " The land meets the sea here.".to_string(),
+            _ => continue,
+        };
+        text.push_str(&sentence);
+    }
+    text
+}
+
+/// The sheet as a Markdown document, one table row per hex.
+pub fn sheet_markdown(title: &str, grid: &HexGrid, entries: &[HexEntry]) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "# {}\n", title);
+    let _ = writeln!(out, "Hexes {} tiles across, numbered column then row.\n", grid.size);
+    let _ = writeln!(out, "| Hex | Terrain | Features | Description |");
+    let _ = writeln!(out, "|-----|---------|----------|-------------|");
+    let cell = |text: &str| text.replace('|', "\\|");
+    for entry in entries {
+        let features: Vec<String> = entry.features.iter().map(HexFeature::short).collect();
+        let _ = writeln!(
+            out,
+            "| {} | {:?} | {} | {} |",
+            entry.number,
+            entry.terrain,
+            cell(&features.join(", ")),
+            cell(&entry.description)
+        );
+    }
+    out
+}
+
+/// The sheet as CSV with a header row: hex, col, row, terrain, features
+/// (separated by `; `) and description.
+pub fn sheet_csv(entries: &[HexEntry]) -> String {
+    let quote = |text: &str| format!("\"{}\"", text.replace('"', "\"\""));
+    let mut out = String::from("hex,col,row,terrain,features,description\n");
+    for entry in entries {
+        let features: Vec<String> = entry.features.iter().map(HexFeature::short).collect();
+        let _ = writeln!(
+            out,
+            "{},{},{},{:?},{},{}",
+            quote(&entry.number),
+            entry.col + 1,
+            entry.row + 1,
+            entry.terrain,
+            quote(&features.join("; ")),
+            quote(&entry.description)
+        );
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain_generator::test_map;
+
+    fn grid() -> HexGrid {
+        HexGrid::new(6.0)
+    }
+
+    fn map() -> &'static TerrainMap {
+        test_map(12, 60, 45)
+    }
+
+    #[test]
+    fn points_are_in_the_hex_round_them() {
+        let grid = grid();
+        for (col, row) in [(0, 0), (1, 0), (3, 2), (4, 7)] {
+            let (x, y) = grid.center(col, row);
+            assert_eq!(grid.hex_at(x, y), Some((col, row)));
+            // Just inside each corner is still this hex
+            for (cx, cy) in grid.corners(col, row) {
+                let inside = (x + (cx - x) * 0.9, y + (cy - y) * 0.9);
+                if inside.0 >= 0.0 && inside.1 >= 0.0 {
+                    assert_eq!(grid.hex_at(inside.0, inside.1), Some((col, row)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn hexes_are_numbered_from_one_by_column_then_row() {
+        assert_eq!(hex_number(0, 11), "0112");
+    }
+
+    #[test]
+    fn every_tile_is_in_one_keyed_hex() {
+        let (map, grid) = (map(), grid());
+        let tiles: usize = grid.hexes(map).values().map(Vec::len).sum();
+        assert_eq!(tiles, 60 * 45);
+        assert_eq!(hex_sheet(map, &grid).len(), grid.hexes(map).len());
+    }
+
+    #[test]
+    fn cities_and_rivers_are_keyed_in_their_hex() {
+        let (map, grid) = (map(), grid());
+        let entries = hex_sheet(map, &grid);
+        for city in &map.cities {
+            let (col, row) = grid.hex_of_tile(city.x, city.y);
+            let entry = entries.iter().find(|e| (e.col, e.row) == (col, row)).unwrap();
+            assert!(entry.features.contains(&HexFeature::City { name: city.name.clone(), population: city.population }));
+            assert!(entry.description.contains(&city.name));
+        }
+        for &(x, y) in map.rivers.iter().flatten() {
+            let hex = grid.hex_of_tile(x, y);
+            let entry = entries.iter().find(|e| (e.col, e.row) == hex).unwrap();
+            assert!(entry.features.contains(&HexFeature::River));
+        }
+    }
+
+    #[test]
+    fn sheets_have_a_row_for_every_hex() {
+        let grid = grid();
+        let entries = hex_sheet(map(), &grid);
+        let markdown = sheet_markdown("Seed 12", &grid, &entries);
+        let rows = markdown.lines().filter(|l| l.starts_with("| ") && !l.starts_with("| Hex"));
+        assert_eq!(rows.count(), entries.len());
+        let csv = sheet_csv(&entries);
+        assert_eq!(csv.lines().count(), entries.len() + 1);
+        assert!(csv.starts_with("hex,col,row,terrain,features,description\n\"0101\",1,1,"));
+    }
+
+    #[test]
+    fn the_grid_is_drawn_over_the_map() {
+        let mut img = RgbaImage::new(60 * 2, 45 * 2);
+        draw_hex_grid(&mut img, map(), &grid(), 2, Rgba([0, 0, 0, 255]));
+        assert!(img.pixels().any(|p| p[3] == 255));
+    }
+}
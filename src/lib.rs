@@ -1,2 +1,21 @@
+pub mod climate_export;
+pub mod config;
+pub mod editor_export;
+pub mod error;
+pub mod gallery;
+pub mod globe_export;
+pub mod hexcrawl;
+pub mod measure;
+pub mod name_lists;
+pub mod png_metadata;
+pub mod presets;
+pub mod profile_chart;
+pub mod project;
+pub mod roll20_export;
+pub mod stl_export;
+pub mod svg_export;
 pub mod terrain_generator;
 pub mod terrain_renderer;
+pub mod text;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
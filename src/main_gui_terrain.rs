@@ -1,216 +1,838 @@
-use mapper::terrain_generator::{GenerationSettings, TerrainGenerator, TerrainMap};
-use mapper::terrain_renderer::TerrainRenderer;
-use slint::{Image, Rgba8Pixel, SharedPixelBuffer};
-use std::time::SystemTime;
-use rusttype::{Font, Scale};
-use imageproc::drawing::draw_text_mut;
-use image::{ImageBuffer, Rgb};
+use mapper::config::Config;
+use mapper::measure::{Measurement, DEFAULT_KM_PER_TILE};
+use mapper::project::{Project, ProjectView, PROJECT_EXTENSION};
+use mapper::svg_export::render_svg;
+use mapper::terrain_generator::{
+    Biome, Brush, BrushEffect, CancellationToken, FeatureIndex, Formation, GenerationPhase, GenerationSettings,
+    LabelBox, MapFeature, MapStats, NoiseKind, PhaseCache, ProgressCallback, Rederive, Region, RegionReroll,
+    TerrainGenerator, TerrainMap,
+};
+use mapper::terrain_renderer::{LabelOptions, LabelRenderer, Layers, RenderOptions, RenderStyle, TerrainRenderer, Viewport};
+use mapper::text::FontChain;
+use slint::{Color, Image, ModelRc, Rgba8Pixel, SharedPixelBuffer, Timer, TimerMode, VecModel};
+use std::cell::Cell;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use std::thread;
 
 slint::include_modules!();
 
-fn generate_terrain_image(map: &TerrainMap) -> Image {
-    let width = map.width;
-    let height = map.height;
-    let scale = 2; // Tiny tiles - each tile is only 2x2 pixels for maximum map visibility
-    
-    // Use the shared terrain renderer
-    let pixels = TerrainRenderer::render_to_pixels(map, width, height, scale);
-    
-    let img_width = width * scale;
-    let img_height = height * scale;
-    
-    // Convert to Slint's pixel buffer format
-    let mut pixel_buffer = SharedPixelBuffer::<Rgba8Pixel>::new(img_width as u32, img_height as u32);
-    let dest_pixels = pixel_buffer.make_mut_bytes();
-    
-    // Copy pixels from renderer output to Slint buffer
-    for i in 0..pixels.len() {
-        dest_pixels[i] = pixels[i];
+/// Largest render scale in pixels per tile; zooming in further magnifies
+/// the image instead
+const MAX_SCALE: usize = 12;
+
+/// Map sizes in tiles, in the order of the settings panel's size list
+const MAP_SIZES: [(usize, usize); 4] = [(800, 500), (1200, 750), (1600, 1000), (2400, 1500)];
+
+/// Biomes the stamp brush offers, in the order of its list
+const STAMP_BIOMES: [Biome; 7] = [
+    Biome::Ocean,
+    Biome::Forest,
+    Biome::Plains,
+    Biome::Hills,
+    Biome::Mountains,
+    Biome::Desert,
+    Biome::Swamp,
+];
+
+/// Size of the label text on screen, as a share of its size on a map
+/// exported at a scale of 10
+const SCREEN_TEXT_SIZE: f32 = 0.45;
+
+/// Elevation a raise or lower dab adds at full strength
+const BRUSH_STEP: f64 = 0.1;
+
+/// Population of a city added by hand, a small town
+const NEW_CITY_POPULATION: u32 = 10000;
+
+/// Road types the road tool offers, in the order of its list
+const ROAD_TYPES: [&str; 4] = ["highway", "road", "trail", "motorway"];
+
+/// The map being shown, shared between the UI and the render threads.
+#[derive(Default)]
+struct ViewState {
+    map: Option<Arc<TerrainMap>>,
+    /// What `map` was generated from, for naming exported files
+    seed: u32,
+    settings: GenerationSettings,
+    /// Bumped for every render request. A finished render is only shown
+    /// if no newer request was made while it ran
+    render_ticket: usize,
+    /// The layers of the last render shown, for toggling layers without
+    /// rendering again
+    cache: Option<LayerCache>,
+    /// Stops the generation in progress, if any
+    cancel: Option<CancellationToken>,
+    /// Project file `map` was last saved to or opened from
+    project_path: Option<PathBuf>,
+    /// Compare mode's second map, and what it was generated from
+    compare: Option<Arc<TerrainMap>>,
+    compare_seed: u32,
+    compare_settings: GenerationSettings,
+    /// Features of `map` and its labels as shown, for hover tooltips
+    features: FeatureIndex,
+    /// The measure tool's path, in tiles
+    measure: Measurement,
+    /// Phase outputs of the last generation, so regenerating after a
+    /// settings change reruns only the phases that read it
+    phase_cache: PhaseCache,
+    /// Fonts for the names of `map`, with the map they were built for
+    fonts: Option<(Arc<TerrainMap>, MapFonts)>,
+    /// The same for `compare`
+    compare_fonts: Option<(Arc<TerrainMap>, MapFonts)>,
+}
+
+/// Fonts for a map's names, or why there are none. Finding system fonts
+/// for the names is slow, so they are built once per map and kept with it.
+type MapFonts = Result<Arc<FontChain>, String>;
+
+fn map_fonts(map: &TerrainMap) -> MapFonts {
+    FontChain::for_map(map).map(Arc::new).map_err(|e| e.to_string())
+}
+
+/// The fonts in `kept` if they were built for `map`.
+fn fonts_kept_for(kept: &Option<(Arc<TerrainMap>, MapFonts)>, map: &Arc<TerrainMap>) -> Option<MapFonts> {
+    kept.as_ref().filter(|(of, _)| Arc::ptr_eq(of, map)).map(|(_, fonts)| fonts.clone())
+}
+
+/// Rough share of the generation time spent in each phase, for one
+/// overall progress bar
+const PHASE_WEIGHTS: [f32; GenerationPhase::ALL.len()] = [0.2, 0.05, 0.1, 0.02, 0.5, 0.13];
+
+/// Forwards the generator's progress to the progress bar, at most once per
+/// percent. Stops once `cancel` is cancelled, leaving "Cancelling…" up.
+fn gui_progress_callback(ui_handle: slint::Weak<MapperWindow>, cancel: CancellationToken) -> ProgressCallback {
+    let mut last = None;
+    Box::new(move |phase, fraction| {
+        if cancel.is_cancelled() {
+            return;
+        }
+        let index = GenerationPhase::ALL.iter().position(|&p| p == phase).unwrap_or(0);
+        let done = PHASE_WEIGHTS[..index].iter().sum::<f32>() + PHASE_WEIGHTS[index] * fraction;
+        let percent = (done * 100.0) as i32;
+        if last == Some((phase, percent)) {
+            return;
+        }
+        last = Some((phase, percent));
+        let _ = ui_handle.upgrade_in_event_loop(move |ui| {
+            ui.set_progress(done);
+            ui.set_progress_text(format!("{} {}%", phase.name(), percent).into());
+        });
+    })
+}
+
+/// City and region labels for the part of the map inside `view` (pixels
+/// at `scale` per tile), each on its own transparent RGBA layer, laid out
+/// as the CLI lays out an exported map's. Text is `text_size` times its
+/// size on a map exported at a scale of 10. Also returns where each label
+/// went, in tiles, for hover tooltips.
+fn render_labels(
+    map: &TerrainMap,
+    fonts: &MapFonts,
+    view: Viewport,
+    scale: usize,
+    layers: Layers,
+    labels: LabelOptions,
+    text_size: f32,
+) -> Result<(Vec<u8>, Vec<u8>, Vec<LabelBox>), String> {
+    let fonts = fonts.as_deref().map_err(|e| e.clone())?;
+    let mut city_labels = image::RgbaImage::new(view.width as u32, view.height as u32);
+    let mut region_labels = city_labels.clone();
+    let label_boxes = LabelRenderer::new(map, scale as u32, layers, labels)
+        .with_fonts(fonts)
+        .with_view(view)
+        .with_text_size(text_size)
+        .draw_layers(&mut city_labels, &mut region_labels)
+        .map_err(|e| e.to_string())?;
+    Ok((city_labels.into_raw(), region_labels.into_raw(), label_boxes))
+}
+
+/// The render scale and the part of the map the window currently shows,
+/// from the view properties. `None` if nothing of the map is visible.
+fn visible_viewport(ui: &MapperWindow, map: &TerrainMap) -> Option<(usize, Viewport)> {
+    let zoom = ui.get_zoom();
+    if zoom <= 0.0 {
+        return None;
     }
-    
-    // Draw text labels using image crate, then convert back
-    let mut img: image::RgbaImage = ImageBuffer::from_raw(
-        img_width as u32, 
-        img_height as u32, 
-        pixels.clone()
-    ).unwrap();
-    
-    // Load font for text rendering
-    let font_data = include_bytes!("../assets/fonts/DejaVuSans.ttf");
-    {
-        if let Some(font) = Font::try_from_bytes(font_data as &[u8]) {
-            // Convert to RGB for text rendering
-            let mut rgb_img = image::DynamicImage::ImageRgba8(img.clone()).to_rgb8();
-            
-            // Track occupied regions to avoid overlap
-            let mut occupied_regions: Vec<(i32, i32, i32, i32)> = Vec::new();
-            
-            // Helper function to check overlap
-            let check_overlap = |x: i32, y: i32, w: i32, h: i32, regions: &Vec<(i32, i32, i32, i32)>| -> bool {
-                for &(rx, ry, rw, rh) in regions {
-                    if x < rx + rw && x + w > rx && y < ry + rh && y + h > ry {
-                        return true;
-                    }
-                }
-                false
+    // Whole pixels per tile, at least the screen's physical resolution
+    let scale = ((zoom * ui.window().scale_factor()).ceil() as usize).clamp(1, MAX_SCALE);
+    let s = scale as f32;
+    let left = ui.get_view_x().max(0.0);
+    let top = ui.get_view_y().max(0.0);
+    let right = (ui.get_view_x() + ui.get_map_view_width() / zoom).min(map.width as f32);
+    let bottom = (ui.get_view_y() + ui.get_map_view_height() / zoom).min(map.height as f32);
+    if right <= left || bottom <= top {
+        return None;
+    }
+    let x = (left * s).floor() as usize;
+    let y = (top * s).floor() as usize;
+    let viewport = Viewport {
+        x,
+        y,
+        width: ((right * s).ceil() as usize).saturating_sub(x).max(1),
+        height: ((bottom * s).ceil() as usize).saturating_sub(y).max(1),
+    };
+    Some((scale, viewport))
+}
+
+/// The layers to show, from the layer checkboxes.
+#[derive(Clone, Copy)]
+struct ViewLayers {
+    layers: Layers,
+    grid: bool,
+}
+
+fn ui_view_layers(ui: &MapperWindow) -> ViewLayers {
+    ViewLayers {
+        layers: Layers {
+            terrain: true,
+            relief: ui.get_show_relief(),
+            rivers: ui.get_show_rivers(),
+            roads: ui.get_show_roads(),
+            cities: ui.get_show_cities(),
+            labels: ui.get_show_labels(),
+            territories: ui.get_show_territories(),
+            lights: ui.get_show_lights(),
+            clouds: ui.get_show_clouds(),
+        },
+        grid: ui.get_show_grid(),
+    }
+}
+
+/// Each layer of one view rendered on its own, as RGBA pixels of the
+/// viewport's size, so layers can be switched on and off by compositing
+/// them again.
+struct LayerCache {
+    scale: usize,
+    viewport: Viewport,
+    /// Whether `terrain` includes hillshading
+    relief: bool,
+    terrain: Vec<u8>,
+    territories: Vec<u8>,
+    rivers: Vec<u8>,
+    roads: Vec<u8>,
+    lights: Vec<u8>,
+    cities: Vec<u8>,
+    clouds: Vec<u8>,
+    city_labels: Vec<u8>,
+    region_labels: Vec<u8>,
+    label_boxes: Vec<LabelBox>,
+    /// Why the labels couldn't be drawn, if they couldn't; the view is
+    /// then shown unlabelled
+    label_error: Option<String>,
+}
+
+impl LayerCache {
+    fn render(map: &TerrainMap, fonts: &MapFonts, scale: usize, viewport: Viewport, relief: bool) -> Self {
+        let layer = |layers: Layers| TerrainRenderer::render_viewport_to_pixels(map, scale, viewport, layers, &RenderOptions::default());
+        let terrain = layer(Layers { terrain: true, relief, ..Layers::none() });
+        let territories = layer(Layers { territories: true, ..Layers::none() });
+        let rivers = layer(Layers { rivers: true, ..Layers::none() });
+        let roads = layer(Layers { roads: true, ..Layers::none() });
+        let lights = layer(Layers { lights: true, ..Layers::none() });
+        let cities = layer(Layers { cities: true, ..Layers::none() });
+        let clouds = layer(Layers { clouds: true, ..Layers::none() });
+        let labels = render_labels(map, fonts, viewport, scale, Layers::all(), LabelOptions::default(), SCREEN_TEXT_SIZE);
+        let (city_labels, region_labels, label_boxes, label_error) = match labels {
+            Ok((city_labels, region_labels, label_boxes)) => (city_labels, region_labels, label_boxes, None),
+            // Empty layers composite as nothing
+            Err(e) => (Vec::new(), Vec::new(), Vec::new(), Some(e)),
+        };
+        LayerCache {
+            scale,
+            viewport,
+            relief,
+            terrain,
+            territories,
+            rivers,
+            roads,
+            lights,
+            cities,
+            clouds,
+            city_labels,
+            region_labels,
+            label_boxes,
+            label_error,
+        }
+    }
+
+    /// The boxes of the labels `shown` draws.
+    fn shown_label_boxes(&self, shown: ViewLayers) -> Vec<LabelBox> {
+        if !shown.layers.labels {
+            return Vec::new();
+        }
+        self.label_boxes
+            .iter()
+            .filter(|label| shown.layers.cities || !matches!(label.feature, MapFeature::City(_) | MapFeature::Poi(_)))
+            .copied()
+            .collect()
+    }
+
+    /// The shown layers stacked bottom to top, ready for display.
+    fn composite(&self, shown: ViewLayers) -> SharedPixelBuffer<Rgba8Pixel> {
+        let mut pixels = self.terrain.clone();
+        let layers = shown.layers;
+        if layers.territories {
+            TerrainRenderer::composite_over(&mut pixels, &self.territories);
+        }
+        if layers.rivers {
+            TerrainRenderer::composite_over(&mut pixels, &self.rivers);
+        }
+        if layers.roads {
+            TerrainRenderer::composite_over(&mut pixels, &self.roads);
+        }
+        if layers.lights {
+            TerrainRenderer::composite_over(&mut pixels, &self.lights);
+        }
+        if layers.cities {
+            TerrainRenderer::composite_over(&mut pixels, &self.cities);
+        }
+        if layers.clouds {
+            TerrainRenderer::composite_over(&mut pixels, &self.clouds);
+        }
+        // City names go with their dots
+        if layers.labels && layers.cities {
+            TerrainRenderer::composite_over(&mut pixels, &self.city_labels);
+        }
+        if layers.labels {
+            TerrainRenderer::composite_over(&mut pixels, &self.region_labels);
+        }
+        if shown.grid {
+            draw_grid(&mut pixels, self.viewport, self.scale);
+        }
+        SharedPixelBuffer::<Rgba8Pixel>::clone_from_slice(
+            &pixels,
+            self.viewport.width as u32,
+            self.viewport.height as u32,
+        )
+    }
+}
+
+/// Tile grid lines over the view: every 1, 5, 10, 50 or 100 tiles,
+/// whichever is the finest that keeps lines at least 24 pixels apart.
+fn draw_grid(pixels: &mut [u8], view: Viewport, scale: usize) {
+    let step = [1, 5, 10, 50, 100]
+        .into_iter()
+        .find(|step| step * scale >= 24)
+        .unwrap_or(100)
+        * scale;
+    let line = [0u8, 0, 0, 70];
+    let mut grid = vec![0u8; pixels.len()];
+    for py in 0..view.height {
+        let on_row = (view.y + py).is_multiple_of(step);
+        for px in 0..view.width {
+            if on_row || (view.x + px).is_multiple_of(step) {
+                let i = (py * view.width + px) * 4;
+                grid[i..i + 4].copy_from_slice(&line);
+            }
+        }
+    }
+    TerrainRenderer::composite_over(pixels, &grid);
+}
+
+/// Re-render the visible part of the map in the background. Until it is
+/// done the previous image stays up, stretched to the new view.
+fn render_visible(ui: &MapperWindow, view_state: &Arc<Mutex<ViewState>>) {
+    let (map, fonts, compare, ticket, map_changed) = {
+        let mut state = view_state.lock().unwrap();
+        let Some(map) = state.map.clone() else { return };
+        state.render_ticket += 1;
+        let fonts = fonts_kept_for(&state.fonts, &map);
+        let compare = state
+            .compare
+            .clone()
+            .filter(|_| ui.get_compare_mode())
+            .map(|compare| (fonts_kept_for(&state.compare_fonts, &compare), compare));
+        // Every change to the map drops the cache
+        (map, fonts, compare, state.render_ticket, state.cache.is_none())
+    };
+    let Some((scale, viewport)) = visible_viewport(ui, &map) else { return };
+    let compare = compare.and_then(|(fonts, map)| Some((visible_viewport(ui, &map)?, fonts, map)));
+    let shown = ui_view_layers(ui);
+    let ui_handle = ui.as_weak();
+    let view_state = view_state.clone();
+    thread::spawn(move || {
+        let new_fonts = fonts.is_none();
+        let fonts = fonts.unwrap_or_else(|| map_fonts(&map));
+        let cache = LayerCache::render(&map, &fonts, scale, viewport, shown.layers.relief);
+        let buffer = cache.composite(shown);
+        let stats = map_changed.then(|| (map.stats(), FeatureIndex::new(&map)));
+        let compare = compare.map(|((scale, viewport), fonts, map)| {
+            let fonts = fonts.unwrap_or_else(|| map_fonts(&map));
+            let buffer = LayerCache::render(&map, &fonts, scale, viewport, shown.layers.relief).composite(shown);
+            (scale, viewport, buffer, map, fonts)
+        });
+        let _ = slint::invoke_from_event_loop(move || {
+            let mut state = view_state.lock().unwrap();
+            // Fonts are kept even from a render that is no longer wanted,
+            // as long as their map is still shown
+            let shown_now = |shown: &Option<Arc<TerrainMap>>, map: &Arc<TerrainMap>| {
+                shown.as_ref().is_some_and(|shown| Arc::ptr_eq(shown, map))
             };
-            
-            // Sort cities by population (draw larger cities first)
-            let mut sorted_cities: Vec<_> = map.cities.iter().collect();
-            sorted_cities.sort_by(|a, b| b.population.cmp(&a.population));
-            
-            // Draw city labels with smart positioning
-            for city in sorted_cities {
-                let city_x = city.x * scale + scale / 2;
-                let city_y = city.y * scale + scale / 2;
-                
-                // Text scale for GUI's 2x scale
-                let text_scale = if city.population > 250000 {
-                    Scale::uniform(11.0)
-                } else if city.population > 100000 {
-                    Scale::uniform(10.0)
-                } else {
-                    Scale::uniform(9.0)
-                };
-                
-                // Estimate text dimensions
-                let text_width = (city.name.len() as i32 * text_scale.x as i32 * 3) / 5;
-                let text_height = text_scale.y as i32;
-                
-                // Try different positions to avoid overlap
-                let offsets = [
-                    (scale as i32, -(scale as i32) / 2),  // Right of city
-                    (-(text_width + scale as i32), -(scale as i32) / 2),  // Left
-                    (scale as i32 / 2 - text_width / 2, -(scale as i32 + text_height)),  // Above
-                    (scale as i32 / 2 - text_width / 2, scale as i32),  // Below
-                ];
-                
-                let mut best_pos = None;
-                for &(dx, dy) in offsets.iter() {
-                    let test_x = city_x as i32 + dx;
-                    let test_y = city_y as i32 + dy;
-                    
-                    if !check_overlap(test_x, test_y, text_width, text_height, &occupied_regions) {
-                        best_pos = Some((test_x, test_y));
-                        break;
-                    }
-                }
-                
-                let (label_x, label_y) = best_pos.unwrap_or((city_x as i32 + scale as i32, city_y as i32));
-                
-                // Draw outline for better visibility
-                for dy in -1i32..=1 {
-                    for dx in -1i32..=1 {
-                        if dx != 0 || dy != 0 {
-                            draw_text_mut(
-                                &mut rgb_img,
-                                Rgb([0, 0, 0]),
-                                label_x + dx,
-                                label_y + dy,
-                                text_scale,
-                                &font,
-                                &city.name
-                            );
-                        }
-                    }
-                }
-                
-                // Draw city name
-                draw_text_mut(
-                    &mut rgb_img,
-                    Rgb([255, 255, 255]),
-                    label_x,
-                    label_y,
-                    text_scale,
-                    &font,
-                    &city.name
-                );
-                
-                occupied_regions.push((label_x, label_y, text_width, text_height));
-                
-                // Draw population for large cities
-                if city.population > 100000 {
-                    let pop_text = format!("({}k)", city.population / 1000);
-                    let pop_scale = Scale::uniform(8.0);
-                    let pop_y = label_y + text_height + 2;
-                    
-                    draw_text_mut(
-                        &mut rgb_img,
-                        Rgb([200, 200, 200]),
-                        label_x,
-                        pop_y,
-                        pop_scale,
-                        &font,
-                        &pop_text
-                    );
+            if shown_now(&state.map, &map) {
+                state.fonts = Some((map, fonts));
+            }
+            let compare = compare.map(|(scale, viewport, buffer, map, fonts)| {
+                if shown_now(&state.compare, &map) {
+                    state.compare_fonts = Some((map, fonts));
                 }
+                (scale, viewport, buffer)
+            });
+            if state.render_ticket != ticket {
+                return;
             }
-            
-            // Draw geographic labels
-            for label in &map.labels {
-                let x = (label.x * scale as f32) as i32;
-                let y = (label.y * scale as f32) as i32;
-                
-                let text_color = match label.feature_type.as_str() {
-                    "ocean" => Rgb([150, 200, 255]),
-                    "mountains" => Rgb([150, 150, 150]),
-                    "forest" => Rgb([100, 200, 100]),
-                    "swamp" => Rgb([150, 180, 150]),
-                    "river" => Rgb([100, 150, 255]),
-                    _ => Rgb([200, 200, 200]),
-                };
-                
-                let label_scale = match label.feature_type.as_str() {
-                    "ocean" => Scale::uniform(14.0),
-                    "mountains" => Scale::uniform(12.0),
-                    "forest" => Scale::uniform(11.0),
-                    "swamp" => Scale::uniform(11.0),
-                    "river" => Scale::uniform(10.0),
-                    _ => Scale::uniform(11.0),
-                };
-                
-                // Draw outline
-                for dy in -1i32..=1 {
-                    for dx in -1i32..=1 {
-                        if dx != 0 || dy != 0 {
-                            draw_text_mut(
-                                &mut rgb_img,
-                                Rgb([0, 0, 0]),
-                                x + dx,
-                                y + dy,
-                                label_scale,
-                                &font,
-                                &label.name
-                            );
-                        }
-                    }
+            let stats = stats.map(|(stats, features)| {
+                state.features = features;
+                stats
+            });
+            let label_error = cache.label_error.clone();
+            state.features.set_labels(cache.shown_label_boxes(shown));
+            state.cache = Some(cache);
+            let Some(ui) = ui_handle.upgrade() else { return };
+            // Reported once per map, when its fonts are built, under what
+            // it was generated from
+            if let (true, Some(error)) = (new_fonts, label_error) {
+                ui.set_map_status(format!("{}\nLabels not drawn: {}", ui.get_map_status(), error).into());
+            }
+            // Where the image sits, in tiles
+            let s = scale as f32;
+            ui.set_image_x(viewport.x as f32 / s);
+            ui.set_image_y(viewport.y as f32 / s);
+            ui.set_image_width(viewport.width as f32 / s);
+            ui.set_image_height(viewport.height as f32 / s);
+            ui.set_map_image(Image::from_rgba8(buffer));
+            if let Some(stats) = stats {
+                show_map_stats(&ui, &stats);
+            }
+            if let Some((scale, viewport, buffer)) = compare {
+                let s = scale as f32;
+                ui.set_compare_image_x(viewport.x as f32 / s);
+                ui.set_compare_image_y(viewport.y as f32 / s);
+                ui.set_compare_image_width(viewport.width as f32 / s);
+                ui.set_compare_image_height(viewport.height as f32 / s);
+                ui.set_compare_image(Image::from_rgba8(buffer));
+            }
+        });
+    });
+}
+
+/// File formats the export dialog offers, in the order of its format list.
+#[derive(Clone, Copy)]
+enum ExportFormat {
+    Png,
+    Svg,
+    Json,
+    /// 16-bit grayscale elevation, one pixel per tile
+    Heightmap,
+}
+
+impl ExportFormat {
+    fn from_index(index: i32) -> Self {
+        match index {
+            1 => ExportFormat::Svg,
+            2 => ExportFormat::Json,
+            3 => ExportFormat::Heightmap,
+            _ => ExportFormat::Png,
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Png | ExportFormat::Heightmap => "png",
+            ExportFormat::Svg => "svg",
+            ExportFormat::Json => "json",
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            ExportFormat::Png => "PNG image",
+            ExportFormat::Svg => "SVG drawing",
+            ExportFormat::Json => "Map data (JSON)",
+            ExportFormat::Heightmap => "Heightmap (16-bit PNG)",
+        }
+    }
+}
+
+/// The files "Export pack" writes, as the end of the file name after the
+/// map's own, format and style: everything usually exported at the end of
+/// a session. Images use the export dialog's scale and layers.
+const EXPORT_PACK: [(&str, ExportFormat, RenderStyle); 6] = [
+    ("atlas.png", ExportFormat::Png, RenderStyle::Atlas),
+    ("satellite.png", ExportFormat::Png, RenderStyle::Satellite),
+    ("print.png", ExportFormat::Png, RenderStyle::LineArt),
+    ("map.svg", ExportFormat::Svg, RenderStyle::Atlas),
+    ("map.json", ExportFormat::Json, RenderStyle::Atlas),
+    ("heightmap.png", ExportFormat::Heightmap, RenderStyle::Atlas),
+];
+
+/// The layers picked in the export dialog. Style 1 is flat color, without
+/// hillshading.
+fn ui_export_layers(ui: &MapperWindow) -> Layers {
+    Layers {
+        terrain: ui.get_export_terrain(),
+        relief: ui.get_export_style() == 0,
+        rivers: ui.get_export_rivers(),
+        roads: ui.get_export_roads(),
+        cities: ui.get_export_cities(),
+        labels: ui.get_export_labels(),
+        territories: ui.get_export_territories(),
+        lights: ui.get_export_lights(),
+        clouds: ui.get_export_clouds(),
+    }
+}
+
+/// Write the whole map to `path`. PNGs are labelled as the CLI labels
+/// them; JSON is the reloadable map data.
+fn export_map(
+    map: &TerrainMap,
+    fonts: &MapFonts,
+    path: &Path,
+    format: ExportFormat,
+    scale: usize,
+    layers: Layers,
+    options: &RenderOptions,
+) -> Result<(), String> {
+    match format {
+        ExportFormat::Png => {
+            let viewport = Viewport { x: 0, y: 0, width: map.width * scale, height: map.height * scale };
+            let mut pixels = TerrainRenderer::render_viewport_to_pixels(map, scale, viewport, layers, options);
+            if layers.labels {
+                let text_size = LabelRenderer::export_text_size(scale as u32);
+                let (city_labels, region_labels, _) = render_labels(map, fonts, viewport, scale, layers, options.labels, text_size)
+                    .map_err(|e| format!("cannot draw labels: {}", e))?;
+                if layers.cities {
+                    TerrainRenderer::composite_over(&mut pixels, &city_labels);
                 }
-                
-                draw_text_mut(
-                    &mut rgb_img,
-                    text_color,
-                    x,
-                    y,
-                    label_scale,
-                    &font,
-                    &label.name
-                );
+                TerrainRenderer::composite_over(&mut pixels, &region_labels);
             }
-            
-            // Convert back to RGBA
-            img = image::DynamicImage::ImageRgb8(rgb_img).to_rgba8();
+            let image = image::RgbaImage::from_raw(viewport.width as u32, viewport.height as u32, pixels)
+                .ok_or_else(|| "map too large to export at this scale".to_string())?;
+            image.save(path).map_err(|e| e.to_string())
         }
+        ExportFormat::Svg => {
+            let svg = render_svg(map, scale as u32, layers, options).map_err(|e| e.to_string())?;
+            std::fs::write(path, svg).map_err(|e| e.to_string())
+        }
+        ExportFormat::Json => map.save_json(path).map_err(|e| e.to_string()),
+        ExportFormat::Heightmap => TerrainRenderer::heightmap_image(map).save(path).map_err(|e| e.to_string()),
     }
-    
-    // Copy the final image back to the pixel buffer
-    let final_pixels = img.as_raw();
-    let dest_pixels = pixel_buffer.make_mut_bytes();
-    for i in 0..final_pixels.len() {
-        dest_pixels[i] = final_pixels[i];
+}
+
+/// Zoom so the whole map fits the window, centred.
+fn fit_view(ui: &MapperWindow, map: &TerrainMap) {
+    let (area_width, area_height) = (ui.get_map_view_width(), ui.get_map_view_height());
+    let zoom = (area_width / map.width as f32).min(area_height / map.height as f32);
+    if zoom <= 0.0 {
+        return;
+    }
+    ui.set_min_zoom(zoom);
+    ui.set_zoom(zoom);
+    ui.set_view_x((map.width as f32 - area_width / zoom) / 2.0);
+    ui.set_view_y((map.height as f32 - area_height / zoom) / 2.0);
+}
+
+thread_local! {
+    /// The settings `set_ui_settings` was last given (the config file's,
+    /// or an opened project's), for the fields the panel has no controls for
+    static PANEL_SETTINGS: Cell<GenerationSettings> = Cell::new(GenerationSettings::default());
+}
+
+/// The generation settings from the settings panel.
+fn ui_settings(ui: &MapperWindow) -> GenerationSettings {
+    let formation_index = ui.get_formation_index();
+    let (map_width, map_height) = MAP_SIZES[ui.get_map_size_index().clamp(0, MAP_SIZES.len() as i32 - 1) as usize];
+    GenerationSettings {
+        river_density: ui.get_river_density(),
+        city_density: ui.get_city_density(),
+        land_percentage: ui.get_land_percentage(),
+        formation: usize::try_from(formation_index - 1).ok().and_then(|i| Formation::ALL.get(i).copied()),
+        temperature: ui.get_temperature(),
+        mountain_density: ui.get_mountain_density(),
+        forest_density: ui.get_forest_density(),
+        moisture: ui.get_moisture(),
+        map_width,
+        map_height,
+        elevation_noise: NoiseKind::ALL.get(ui.get_elevation_noise_index() as usize).copied().unwrap_or_default(),
+        moisture_noise: NoiseKind::ALL.get(ui.get_moisture_noise_index() as usize).copied().unwrap_or_default(),
+        ..PANEL_SETTINGS.get()
+    }
+}
+
+/// Show `settings` in the settings panel.
+fn set_ui_settings(ui: &MapperWindow, settings: &GenerationSettings) {
+    ui.set_river_density(settings.river_density);
+    ui.set_city_density(settings.city_density);
+    ui.set_land_percentage(settings.land_percentage);
+    let formation_index = settings
+        .formation
+        .and_then(|f| Formation::ALL.iter().position(|&g| g == f))
+        .map_or(0, |i| i as i32 + 1);
+    ui.set_formation_index(formation_index);
+    ui.set_temperature(settings.temperature);
+    ui.set_mountain_density(settings.mountain_density);
+    ui.set_forest_density(settings.forest_density);
+    ui.set_moisture(settings.moisture);
+    if let Some(index) = MAP_SIZES.iter().position(|&size| size == (settings.map_width, settings.map_height)) {
+        ui.set_map_size_index(index as i32);
     }
+    let noise_index = |kind: NoiseKind| NoiseKind::ALL.iter().position(|&k| k == kind).unwrap_or(0) as i32;
+    ui.set_elevation_noise_index(noise_index(settings.elevation_noise));
+    ui.set_moisture_noise_index(noise_index(settings.moisture_noise));
+    PANEL_SETTINGS.set(*settings);
+}
+
+/// Generate a map in the background and show it when done. With
+/// `keep_view` the zoom and position stay as they are if the map size is
+/// unchanged, so a regenerated world can be compared in place.
+/// What a generated map is for.
+#[derive(Clone, Copy, PartialEq)]
+enum GenerationTarget {
+    /// A new world, shown fitted to the window
+    New,
+    /// The shown world again; the view stays if the size is unchanged
+    Regenerate,
+    /// Compare mode's second map
+    Comparison,
+}
+
+fn start_generation(ui: &MapperWindow, seed: u32, settings: GenerationSettings, target: GenerationTarget, view_state: Arc<Mutex<ViewState>>) {
+    if target == GenerationTarget::Comparison {
+        ui.set_compare_seed_text(seed.to_string().into());
+    } else {
+        ui.set_seed_text(seed.to_string().into());
+        ui.set_seed_error("".into());
+    }
+    ui.set_progress(0.0);
+    ui.set_progress_text("".into());
+    ui.set_is_generating(true);
+    let (width, height) = (settings.map_width, settings.map_height);
+    let cancel = CancellationToken::new();
+    // The comparison map leaves the shown map's cache alone
+    let phase_cache = {
+        let mut state = view_state.lock().unwrap();
+        state.cancel = Some(cancel.clone());
+        (target != GenerationTarget::Comparison).then(|| std::mem::take(&mut state.phase_cache))
+    };
     
-    Image::from_rgba8(pixel_buffer)
+    // Clone the weak handle for use in the thread
+    let ui_handle_thread = ui.as_weak();
+    
+    // Generate map in a separate thread to keep UI responsive
+    thread::spawn(move || {
+        let mut generator = TerrainGenerator::new_with_settings(seed, settings);
+        generator.set_progress_callback(gui_progress_callback(ui_handle_thread.clone(), cancel.clone()));
+        if let Some(phase_cache) = phase_cache {
+            generator.set_phase_cache(phase_cache);
+        }
+        
+        // The only error is being cancelled
+        let map = generator.generate_cancellable(width, height, &cancel).ok().map(Arc::new);
+        let info = map.as_deref().map(generate_map_info);
+        let phase_cache = generator.take_phase_cache();
+        
+        // Update UI from main thread
+        let _ = slint::invoke_from_event_loop(move || {
+            let ui = ui_handle_thread.unwrap();
+            let mut state = view_state.lock().unwrap();
+            state.cancel = None;
+            if let Some(phase_cache) = phase_cache {
+                state.phase_cache = phase_cache;
+            }
+            ui.set_is_generating(false);
+            let (Some(map), Some(info)) = (map, info) else {
+                // Cancelled: keep showing the previous map, if any
+                if state.map.is_none() {
+                    ui.set_map_status("Generation cancelled".into());
+                }
+                return;
+            };
+            if target == GenerationTarget::Comparison {
+                ui.set_compare_status(comparison_status(seed, &map).into());
+                ui.set_has_compare(true);
+                state.compare = Some(map);
+                state.compare_seed = seed;
+                state.compare_settings = settings;
+                drop(state);
+                render_visible(&ui, &view_state);
+                return;
+            }
+            let same_size = state
+                .map
+                .as_ref()
+                .is_some_and(|old| old.width == map.width && old.height == map.height);
+            ui.set_map_status(format!("Map generated (Seed: {})\n{}", seed, info).into());
+            ui.set_map_tiles_width(map.width as f32);
+            ui.set_map_tiles_height(map.height as f32);
+            if !(target == GenerationTarget::Regenerate && same_size) {
+                fit_view(&ui, &map);
+            }
+            ui.set_show_inspector(false);
+            ui.set_has_map(true);
+            state.map = Some(map);
+            state.seed = seed;
+            state.settings = settings;
+            state.cache = None;
+            state.project_path = None;
+            drop(state);
+            ui.set_file_status("".into());
+            render_visible(&ui, &view_state);
+        });
+    });
+}
+
+/// Rebuild the `what` layers of the shown map in a background thread,
+/// with progress, then show the result.
+fn rederive_in_background(ui: &MapperWindow, view_state: &Arc<Mutex<ViewState>>, what: Rederive) {
+    let (map, seed, settings) = {
+        let state = view_state.lock().unwrap();
+        let Some(map) = state.map.clone() else { return };
+        (map, state.seed, state.settings)
+    };
+    ui.set_progress(0.0);
+    ui.set_progress_text("".into());
+    ui.set_is_generating(true);
+    let ui_handle = ui.as_weak();
+    let view_state = view_state.clone();
+    thread::spawn(move || {
+        let mut generator = TerrainGenerator::new_with_settings(seed, settings);
+        generator.set_progress_callback(gui_progress_callback(ui_handle.clone(), CancellationToken::new()));
+        let mut map = (*map).clone();
+        generator.rederive(&mut map, what);
+        let info = generate_map_info(&map);
+        let _ = slint::invoke_from_event_loop(move || {
+            let Some(ui) = ui_handle.upgrade() else { return };
+            ui.set_is_generating(false);
+            ui.set_map_status(format!("Map edited (Seed: {})\n{}", seed, info).into());
+            let mut state = view_state.lock().unwrap();
+            state.map = Some(Arc::new(map));
+            state.cache = None;
+            drop(state);
+            render_visible(&ui, &view_state);
+        });
+    });
+}
+
+/// Show city `index` in the city editor.
+fn open_city_editor(ui: &MapperWindow, map: &TerrainMap, index: usize) {
+    let Some(city) = map.cities.get(index) else { return };
+    ui.set_city_index(index as i32);
+    ui.set_city_name(city.name.clone().into());
+    ui.set_city_population(city.population.min(i32::MAX as u32) as i32);
+    ui.set_tool_status("".into());
+    ui.set_show_city_editor(true);
+}
+
+/// After a city was renamed or removed: reroute the roads if asked to,
+/// otherwise just redraw.
+fn city_edited(ui: &MapperWindow, view_state: &Arc<Mutex<ViewState>>) {
+    if ui.get_city_reroute() {
+        rederive_in_background(ui, view_state, Rederive { rivers: false, roads: true });
+    } else {
+        render_visible(ui, view_state);
+    }
+}
+
+/// Draw the measured path and show its length and area in the tool bar.
+fn show_measurement(ui: &MapperWindow, measure: &Measurement) {
+    let mut path: Vec<String> = measure
+        .points
+        .iter()
+        .enumerate()
+        .map(|(i, &(x, y))| format!("{} {:.3} {:.3}", if i == 0 { "M" } else { "L" }, x, y))
+        .collect();
+    if measure.closed {
+        path.push("Z".to_string());
+    }
+    ui.set_measure_path(path.join(" ").into());
+    ui.set_measure_points(measure.points.len() as i32);
+    ui.set_measure_closed(measure.closed);
+    if let Some(&(x, y)) = measure.points.first() {
+        ui.set_measure_start_x(x as f32);
+        ui.set_measure_start_y(y as f32);
+    }
+    let status = if measure.points.len() > 1 {
+        measure.summary(ui.get_km_per_tile().max(1) as f64)
+    } else {
+        String::new()
+    };
+    ui.set_tool_status(status.into());
+}
+
+/// One line about the comparison map, for the compare bar.
+fn comparison_status(seed: u32, map: &TerrainMap) -> String {
+    let land = map.terrain.iter().flatten().filter(|p| !p.biome.is_water()).count();
+    format!(
+        "Right: seed {}, {:.0}% land, {} cities",
+        seed,
+        100.0 * land as f64 / (map.width * map.height) as f64,
+        map.cities.len()
+    )
+}
+
+/// Fill in the statistics panel's charts.
+fn show_map_stats(ui: &MapperWindow, stats: &MapStats) {
+    // Pie slices clockwise from twelve o'clock
+    let mut angle = 0.0_f64;
+    let point = |angle: f64| (angle.sin(), -angle.cos());
+    let slices: Vec<BiomeSlice> = stats
+        .biomes
+        .iter()
+        .map(|&(biome, count)| {
+            let share = count as f64 / stats.tiles as f64;
+            let sweep = share * std::f64::consts::TAU;
+            let (x0, y0) = point(angle);
+            let (x1, y1) = point(angle + sweep);
+            let path = if share >= 1.0 {
+                // An arc can't end where it starts, so a whole circle is two
+                "M 0 -1 A 1 1 0 1 1 0 1 A 1 1 0 1 1 0 -1 Z".to_string()
+            } else {
+                format!("M 0 0 L {x0:.4} {y0:.4} A 1 1 0 {} 1 {x1:.4} {y1:.4} Z", (sweep > std::f64::consts::PI) as u8)
+            };
+            angle += sweep;
+            let [r, g, b, _] = biome.color();
+            BiomeSlice {
+                name: format!("{:?}", biome).into(),
+                share: format!("{:.1}%", share * 100.0).into(),
+                color: Color::from_rgb_u8(r, g, b),
+                path: path.into(),
+            }
+        })
+        .collect();
+    ui.set_stats_biomes(ModelRc::new(VecModel::from(slices)));
+
+    let tallest = stats.elevation_histogram.iter().copied().max().unwrap_or(0).max(1);
+    let elevation: Vec<f32> = stats.elevation_histogram.iter().map(|&count| count as f32 / tallest as f32).collect();
+    ui.set_stats_elevation(ModelRc::new(VecModel::from(elevation)));
+
+    let largest = stats.populations.first().copied().unwrap_or(0).max(1);
+    let populations: Vec<f32> = stats.populations.iter().map(|&p| p as f32 / largest as f32).collect();
+    ui.set_stats_populations(ModelRc::new(VecModel::from(populations)));
+    // Zipf's law: the city of rank r has 1/r of the largest's population
+    let zipf_path: Vec<String> = (1..=stats.populations.len())
+        .map(|rank| format!("{} {:.4} {:.4}", if rank == 1 { "M" } else { "L" }, rank as f64 - 0.5, 1.0 - 1.0 / rank as f64))
+        .collect();
+    ui.set_stats_zipf_path(zipf_path.join(" ").into());
+    let cities = match stats.zipf_exponent() {
+        Some(exponent) => format!(
+            "{} cities, largest {}. Fitted Zipf exponent {:.2}; the line is Zipf's law (1.00).",
+            stats.populations.len(), largest, exponent
+        ),
+        None => format!("{} cities", stats.populations.len()),
+    };
+    ui.set_stats_cities(cities.into());
+
+    let mut roads = format!(
+        "{} roads, {:.0} tiles in total",
+        stats.roads.iter().map(|t| t.count).sum::<usize>(),
+        stats.road_length()
+    );
+    for total in &stats.roads {
+        roads.push_str(&format!("\n  {}: {} roads, {:.0} tiles", total.road_type, total.count, total.length));
+    }
+    roads.push_str(&format!("\n{} bridges", stats.bridges));
+    ui.set_stats_roads(roads.into());
 }
 
 fn generate_map_info(map: &TerrainMap) -> String {
@@ -249,47 +871,697 @@ fn generate_map_info(map: &TerrainMap) -> String {
 
 fn main() -> Result<(), slint::PlatformError> {
     let ui = MapperWindow::new()?;
+
+    // Start the settings panel from the user's config file
+    let config = Config::load_default().unwrap_or_else(|e| {
+        eprintln!("Ignoring config: {}", e);
+        Config::default()
+    });
+    set_ui_settings(&ui, &config.settings);
+    ui.set_km_per_tile(DEFAULT_KM_PER_TILE as i32);
+    let config = Arc::new(config);
+    let view_state = Arc::new(Mutex::new(ViewState::default()));
     
+    // File → Generate and "Random Seed": a new world
     let ui_handle = ui.as_weak();
+    let view_state_start = view_state.clone();
     ui.on_menu_start(move || {
         let ui = ui_handle.unwrap();
-        
-        // Get settings from UI before spawning thread
-        let settings = GenerationSettings {
-            river_density: ui.get_river_density(),
-            city_density: ui.get_city_density(),
-            land_percentage: ui.get_land_percentage(),
+        let settings = ui_settings(&ui);
+        start_generation(&ui, rand::random(), settings, GenerationTarget::New, view_state_start.clone());
+    });
+    
+    // "Regenerate": the seed in the seed field with the current sliders
+    let ui_handle = ui.as_weak();
+    let view_state_regenerate = view_state.clone();
+    ui.on_regenerate(move || {
+        let ui = ui_handle.unwrap();
+        let text = ui.get_seed_text();
+        let seed = if text.trim().is_empty() {
+            rand::random()
+        } else {
+            match text.trim().parse::<u32>() {
+                Ok(seed) => seed,
+                Err(_) => {
+                    ui.set_seed_error(format!("Seeds are whole numbers from 0 to {}", u32::MAX).into());
+                    return;
+                }
+            }
+        };
+        let settings = ui_settings(&ui);
+        start_generation(&ui, seed, settings, GenerationTarget::Regenerate, view_state_regenerate.clone());
+    });
+    
+    let ui_handle = ui.as_weak();
+    let view_state_cancel = view_state.clone();
+    ui.on_cancel_generation(move || {
+        if let Some(cancel) = &view_state_cancel.lock().unwrap().cancel {
+            cancel.cancel();
+            // Stops at the end of the running phase
+            ui_handle.unwrap().set_progress_text("Cancelling…".into());
+        }
+    });
+    
+    // Re-render once the view has stopped changing for a moment, so
+    // dragging and wheel zooming stay smooth
+    let render_timer = Timer::default();
+    let ui_handle = ui.as_weak();
+    let view_state_changed = view_state.clone();
+    ui.on_view_changed(move || {
+        let ui_handle = ui_handle.clone();
+        let view_state = view_state_changed.clone();
+        render_timer.start(TimerMode::SingleShot, Duration::from_millis(100), move || {
+            if let Some(ui) = ui_handle.upgrade() {
+                render_visible(&ui, &view_state);
+            }
+        });
+    });
+    
+    // Toggling a layer only needs the cached layers composited again,
+    // unless hillshading changed
+    let ui_handle = ui.as_weak();
+    let view_state_layers = view_state.clone();
+    ui.on_layers_changed(move || {
+        let ui = ui_handle.unwrap();
+        let shown = ui_view_layers(&ui);
+        let mut state = view_state_layers.lock().unwrap();
+        // The comparison map has no cache, so it is always rendered again
+        let comparing = ui.get_compare_mode() && state.compare.is_some();
+        let buffer = match &state.cache {
+            Some(cache) if cache.relief == shown.layers.relief && !comparing => Some(cache.composite(shown)),
+            _ => None,
+        };
+        if let Some(labels) = state.cache.as_ref().map(|cache| cache.shown_label_boxes(shown)) {
+            state.features.set_labels(labels);
+        }
+        drop(state);
+        match buffer {
+            Some(buffer) => ui.set_map_image(Image::from_rgba8(buffer)),
+            None => render_visible(&ui, &view_state_layers),
+        }
+    });
+    
+    let ui_handle = ui.as_weak();
+    let view_state_inspect = view_state.clone();
+    ui.on_tile_clicked(move |tile_x, tile_y| {
+        let ui = ui_handle.unwrap();
+        let state = view_state_inspect.lock().unwrap();
+        let Some(map) = &state.map else { return };
+        let info = if tile_x >= 0.0 && tile_y >= 0.0 {
+            map.inspect(tile_x as usize, tile_y as usize)
+        } else {
+            None
+        };
+        match info {
+            Some(info) => {
+                ui.set_inspector_text(info.to_string().into());
+                ui.set_selected_tile_x(info.x as i32);
+                ui.set_selected_tile_y(info.y as i32);
+                ui.set_show_inspector(true);
+            }
+            None => ui.set_show_inspector(false),
+        }
+    });
+    
+    // Tooltips: the feature under the pointer, among the layers shown
+    let ui_handle = ui.as_weak();
+    let view_state_hover = view_state.clone();
+    ui.on_feature_at(move |tile_x, tile_y| {
+        let ui = ui_handle.unwrap();
+        let state = view_state_hover.lock().unwrap();
+        let Some(map) = &state.map else { return "".into() };
+        let layers = ui_view_layers(&ui).layers;
+        let shown = |feature| match feature {
+            MapFeature::City(_) | MapFeature::Poi(_) => layers.cities,
+            MapFeature::Road(_) | MapFeature::Bridge(_) => layers.roads,
+            MapFeature::Label(_) => layers.labels,
+        };
+        // A few pixels of slack, however far out the view is zoomed
+        let radius = (6.0 / ui.get_zoom()).max(0.75);
+        state
+            .features
+            .feature_at(tile_x, tile_y, radius, shown)
+            .and_then(|feature| map.describe_feature(feature))
+            .unwrap_or_default()
+            .into()
+    });
+    
+    // Terrain brushes edit the map in place; the debounced re-render
+    // shows the result
+    let ui_handle = ui.as_weak();
+    let view_state_brush = view_state.clone();
+    ui.on_brush_stroke(move |tile_x, tile_y| {
+        let ui = ui_handle.unwrap();
+        let step = BRUSH_STEP * ui.get_brush_strength() as f64;
+        let effect = match ui.get_tool() {
+            MapTool::Raise => BrushEffect::Elevation(step),
+            MapTool::Lower => BrushEffect::Elevation(-step),
+            MapTool::Stamp => {
+                let index = (ui.get_stamp_biome_index().max(0) as usize).min(STAMP_BIOMES.len() - 1);
+                BrushEffect::Stamp(STAMP_BIOMES[index])
+            }
+            MapTool::Inspect | MapTool::City | MapTool::Road | MapTool::Region | MapTool::Measure => return,
+        };
+        let brush = Brush { radius: ui.get_brush_radius() as f64, effect };
+        let mut state = view_state_brush.lock().unwrap();
+        let settings = state.settings;
+        let Some(map) = state.map.as_mut() else { return };
+        // Copies the map only if a render thread still holds it
+        if Arc::make_mut(map).apply_brush(tile_x as f64, tile_y as f64, &brush, &settings) {
+            state.cache = None;
+            drop(state);
+            ui.invoke_view_changed();
+        }
+    });
+    
+    // Rebuild rivers and roads for the edited terrain in the background
+    let ui_handle = ui.as_weak();
+    let view_state_rederive = view_state.clone();
+    ui.on_rederive_map(move || {
+        let ui = ui_handle.unwrap();
+        let what = Rederive { rivers: ui.get_rederive_rivers(), roads: ui.get_rederive_roads() };
+        rederive_in_background(&ui, &view_state_rederive, what);
+    });
+    
+    // City tool: pick, add, move, edit and delete cities
+    let ui_handle = ui.as_weak();
+    let view_state_pick = view_state.clone();
+    ui.on_pick_city(move |tile_x, tile_y| {
+        let ui = ui_handle.unwrap();
+        let state = view_state_pick.lock().unwrap();
+        let Some(map) = &state.map else { return -1 };
+        // A few pixels of slack, however far out the view is zoomed
+        let radius = (10.0 / ui.get_zoom() as f64).max(1.0);
+        map.city_near(tile_x as f64, tile_y as f64, radius).map_or(-1, |i| i as i32)
+    });
+    
+    let ui_handle = ui.as_weak();
+    let view_state_add = view_state.clone();
+    ui.on_add_city(move |tile_x, tile_y| {
+        let ui = ui_handle.unwrap();
+        if tile_x < 0.0 || tile_y < 0.0 {
+            return;
+        }
+        let mut state = view_state_add.lock().unwrap();
+        let settings = state.settings;
+        let Some(map) = state.map.as_mut() else { return };
+        let name = TerrainGenerator::new_with_settings(rand::random(), settings).name_city(map);
+        let map = Arc::make_mut(map);
+        match map.add_city(tile_x as usize, tile_y as usize, &name, NEW_CITY_POPULATION) {
+            Some(index) => {
+                open_city_editor(&ui, map, index);
+                state.cache = None;
+                drop(state);
+                ui.invoke_view_changed();
+            }
+            None => ui.set_tool_status("Cities need dry land that no other city stands on".into()),
+        }
+    });
+    
+    let ui_handle = ui.as_weak();
+    let view_state_drag = view_state.clone();
+    ui.on_drag_city(move |index, tile_x, tile_y| {
+        let ui = ui_handle.unwrap();
+        if index < 0 || tile_x < 0.0 || tile_y < 0.0 {
+            return;
+        }
+        let mut state = view_state_drag.lock().unwrap();
+        let Some(map) = state.map.as_mut() else { return };
+        let (x, y) = (tile_x as usize, tile_y as usize);
+        let unchanged = map.cities.get(index as usize).is_none_or(|city| (city.x, city.y) == (x, y));
+        if !unchanged && Arc::make_mut(map).move_city(index as usize, x, y) {
+            state.cache = None;
+            drop(state);
+            ui.invoke_view_changed();
+        }
+    });
+    
+    let ui_handle = ui.as_weak();
+    let view_state_moved = view_state.clone();
+    ui.on_city_moved(move |_| {
+        let ui = ui_handle.unwrap();
+        if ui.get_city_reroute() {
+            rederive_in_background(&ui, &view_state_moved, Rederive { rivers: false, roads: true });
+        }
+    });
+    
+    let ui_handle = ui.as_weak();
+    let view_state_edit = view_state.clone();
+    ui.on_edit_city(move |index| {
+        let ui = ui_handle.unwrap();
+        let state = view_state_edit.lock().unwrap();
+        if let Some(map) = &state.map {
+            open_city_editor(&ui, map, index.max(0) as usize);
+        }
+    });
+    
+    let ui_handle = ui.as_weak();
+    let view_state_apply = view_state.clone();
+    ui.on_apply_city_edit(move || {
+        let ui = ui_handle.unwrap();
+        let name = ui.get_city_name().trim().to_string();
+        if name.is_empty() {
+            return;
+        }
+        let index = ui.get_city_index().max(0) as usize;
+        let mut state = view_state_apply.lock().unwrap();
+        let Some(map) = state.map.as_mut() else { return };
+        if index >= map.cities.len() {
+            return;
+        }
+        let city = &mut Arc::make_mut(map).cities[index];
+        city.name = name;
+        city.population = ui.get_city_population().max(0) as u32;
+        state.cache = None;
+        drop(state);
+        ui.set_show_city_editor(false);
+        city_edited(&ui, &view_state_apply);
+    });
+    
+    let ui_handle = ui.as_weak();
+    let view_state_delete = view_state.clone();
+    ui.on_delete_city(move || {
+        let ui = ui_handle.unwrap();
+        let index = ui.get_city_index().max(0) as usize;
+        let mut state = view_state_delete.lock().unwrap();
+        let Some(map) = state.map.as_mut() else { return };
+        if index >= map.cities.len() {
+            return;
+        }
+        Arc::make_mut(map).remove_city(index);
+        state.cache = None;
+        drop(state);
+        ui.set_show_city_editor(false);
+        city_edited(&ui, &view_state_delete);
+    });
+    
+    // Road tool: pathfinding across a large map takes a moment, so the
+    // road is built on a copy in the background
+    let ui_handle = ui.as_weak();
+    let view_state_road = view_state.clone();
+    ui.on_build_road(move |start_x, start_y, end_x, end_y| {
+        let ui = ui_handle.unwrap();
+        if start_x.min(start_y).min(end_x).min(end_y) < 0.0 {
+            ui.set_tool_status("Roads need both ends on the map".into());
+            return;
+        }
+        let (map, settings) = {
+            let state = view_state_road.lock().unwrap();
+            let Some(map) = state.map.clone() else { return };
+            (map, state.settings)
+        };
+        let from = (start_x as usize, start_y as usize);
+        let to = (end_x as usize, end_y as usize);
+        let index = (ui.get_road_type_index().max(0) as usize).min(ROAD_TYPES.len() - 1);
+        let road_type = ROAD_TYPES[index];
+        ui.set_progress(0.0);
+        ui.set_progress_text("Routing road…".into());
+        ui.set_is_generating(true);
+        let ui_handle = ui.as_weak();
+        let view_state = view_state_road.clone();
+        thread::spawn(move || {
+            let mut generator = TerrainGenerator::new_with_settings(rand::random(), settings);
+            let mut map = (*map).clone();
+            let built = generator.build_road(&mut map, from, to, road_type).map(|i| map.roads[i].name.clone());
+            let _ = slint::invoke_from_event_loop(move || {
+                let Some(ui) = ui_handle.upgrade() else { return };
+                ui.set_is_generating(false);
+                let Some(name) = built else {
+                    ui.set_tool_status("No land route joins those points".into());
+                    return;
+                };
+                ui.set_tool_status(format!("Built {}", name).into());
+                let mut state = view_state.lock().unwrap();
+                state.map = Some(Arc::new(map));
+                state.cache = None;
+                drop(state);
+                render_visible(&ui, &view_state);
+            });
+        });
+    });
+    
+    let ui_handle = ui.as_weak();
+    let view_state_delete_road = view_state.clone();
+    ui.on_delete_road(move |tile_x, tile_y| {
+        let ui = ui_handle.unwrap();
+        let mut state = view_state_delete_road.lock().unwrap();
+        let Some(map) = state.map.as_mut() else { return };
+        let radius = (10.0 / ui.get_zoom() as f64).max(1.0);
+        let Some(index) = map.road_near(tile_x as f64, tile_y as f64, radius) else {
+            ui.set_tool_status("No road there".into());
+            return;
+        };
+        if let Some(road) = Arc::make_mut(map).remove_road(index) {
+            ui.set_tool_status(format!("Removed {}", road.name).into());
+        }
+        state.cache = None;
+        drop(state);
+        render_visible(&ui, &view_state_delete_road);
+    });
+    
+    // Measure tool: clicking the first point again closes the loop, and a
+    // click after that starts a new measurement
+    let ui_handle = ui.as_weak();
+    let view_state_measure = view_state.clone();
+    ui.on_measure_point(move |tile_x, tile_y| {
+        let ui = ui_handle.unwrap();
+        let mut state = view_state_measure.lock().unwrap();
+        let measure = &mut state.measure;
+        if measure.closed {
+            *measure = Measurement::default();
+        }
+        let (x, y) = (tile_x as f64, tile_y as f64);
+        // A few pixels of slack, however far out the view is zoomed
+        let radius = (8.0 / ui.get_zoom() as f64).max(0.5);
+        match measure.points.first() {
+            Some(&(first_x, first_y)) if measure.points.len() >= 3 && (x - first_x).hypot(y - first_y) <= radius => {
+                measure.closed = true;
+            }
+            _ => measure.points.push((x, y)),
+        }
+        show_measurement(&ui, measure);
+    });
+    
+    let ui_handle = ui.as_weak();
+    let view_state_measure = view_state.clone();
+    ui.on_clear_measure(move || {
+        let mut state = view_state_measure.lock().unwrap();
+        state.measure = Measurement::default();
+        show_measurement(&ui_handle.unwrap(), &state.measure);
+    });
+    
+    let ui_handle = ui.as_weak();
+    let view_state_measure = view_state.clone();
+    ui.on_refresh_measure(move || {
+        show_measurement(&ui_handle.unwrap(), &view_state_measure.lock().unwrap().measure);
+    });
+    
+    // Compare mode: the second map uses the current sliders, and the shown
+    // map's seed unless another is given
+    let ui_handle = ui.as_weak();
+    let view_state_compare = view_state.clone();
+    ui.on_generate_comparison(move || {
+        let ui = ui_handle.unwrap();
+        let text = ui.get_compare_seed_text();
+        let seed = if text.trim().is_empty() {
+            view_state_compare.lock().unwrap().seed
+        } else {
+            match text.trim().parse::<u32>() {
+                Ok(seed) => seed,
+                Err(_) => {
+                    ui.set_compare_status(format!("Seeds are whole numbers from 0 to {}", u32::MAX).into());
+                    return;
+                }
+            }
+        };
+        let settings = ui_settings(&ui);
+        start_generation(&ui, seed, settings, GenerationTarget::Comparison, view_state_compare.clone());
+    });
+    
+    // Swap the two maps, so the comparison can be edited, saved or exported
+    let ui_handle = ui.as_weak();
+    let view_state_swap = view_state.clone();
+    ui.on_keep_comparison(move || {
+        let ui = ui_handle.unwrap();
+        let mut guard = view_state_swap.lock().unwrap();
+        let state = &mut *guard;
+        let Some(compare) = state.compare.take() else { return };
+        state.compare = state.map.replace(compare.clone());
+        std::mem::swap(&mut state.seed, &mut state.compare_seed);
+        std::mem::swap(&mut state.settings, &mut state.compare_settings);
+        state.cache = None;
+        state.project_path = None;
+        ui.set_seed_text(state.seed.to_string().into());
+        ui.set_compare_seed_text(state.compare_seed.to_string().into());
+        ui.set_map_status(format!("Map generated (Seed: {})\n{}", state.seed, generate_map_info(&compare)).into());
+        if let Some(map) = &state.compare {
+            ui.set_compare_status(comparison_status(state.compare_seed, map).into());
+        }
+        ui.set_map_tiles_width(compare.width as f32);
+        ui.set_map_tiles_height(compare.height as f32);
+        ui.set_show_inspector(false);
+        ui.set_file_status("".into());
+        drop(guard);
+        render_visible(&ui, &view_state_swap);
+    });
+    
+    // Region tool: reroll the selection with a fresh seed and the current
+    // sliders, in the background
+    let ui_handle = ui.as_weak();
+    let view_state_region = view_state.clone();
+    ui.on_reroll_region(move |x, y, width, height, terrain| {
+        let ui = ui_handle.unwrap();
+        let (x0, y0) = (x.max(0.0), y.max(0.0));
+        let region = Region {
+            x: x0 as usize,
+            y: y0 as usize,
+            width: (x + width - x0).max(0.0) as usize,
+            height: (y + height - y0).max(0.0) as usize,
+        };
+        let what = if terrain { RegionReroll::Terrain } else { RegionReroll::Settlements };
+        let Some(map) = view_state_region.lock().unwrap().map.clone() else { return };
+        let settings = ui_settings(&ui);
+        ui.set_progress(0.0);
+        ui.set_progress_text("".into());
+        ui.set_is_generating(true);
+        let ui_handle = ui.as_weak();
+        let view_state = view_state_region.clone();
+        thread::spawn(move || {
+            let mut generator = TerrainGenerator::new_with_settings(rand::random(), settings);
+            generator.set_progress_callback(gui_progress_callback(ui_handle.clone(), CancellationToken::new()));
+            let mut map = (*map).clone();
+            let rerolled = generator.regenerate_region(&mut map, region, what);
+            let _ = slint::invoke_from_event_loop(move || {
+                let Some(ui) = ui_handle.upgrade() else { return };
+                ui.set_is_generating(false);
+                if !rerolled {
+                    ui.set_tool_status("The selection is outside the map".into());
+                    return;
+                }
+                ui.set_tool_status(format!("Rerolled {}×{} tiles", region.width, region.height).into());
+                let mut state = view_state.lock().unwrap();
+                state.map = Some(Arc::new(map));
+                state.cache = None;
+                drop(state);
+                render_visible(&ui, &view_state);
+            });
+        });
+    });
+    
+    // Export dialog: ask where to save, then write the file in the
+    // background
+    let ui_handle = ui.as_weak();
+    let view_state_export = view_state.clone();
+    let config_export = config.clone();
+    ui.on_export_map(move || {
+        let ui = ui_handle.unwrap();
+        let (map, fonts, seed, settings) = {
+            let state = view_state_export.lock().unwrap();
+            let Some(map) = state.map.clone() else { return };
+            (map.clone(), fonts_kept_for(&state.fonts, &map), state.seed, state.settings)
         };
+        let format = ExportFormat::from_index(ui.get_export_format());
+        let scale = ui.get_export_scale().max(1) as usize;
+        let layers = ui_export_layers(&ui);
         
-        // Clone the weak handle for use in the thread
-        let ui_handle_thread = ui_handle.clone();
+        let suggested = config_export.output_file(seed, &settings, format.extension());
+        let mut dialog = rfd::FileDialog::new()
+            .set_title("Export Map")
+            .add_filter(format.description(), &[format.extension()]);
+        if let Some(name) = suggested.file_name() {
+            dialog = dialog.set_file_name(name.to_string_lossy());
+        }
+        if let Some(dir) = suggested.parent().filter(|d| !d.as_os_str().is_empty()) {
+            dialog = dialog.set_directory(dir);
+        }
+        let Some(path) = dialog.save_file() else { return };
         
-        // Generate map in a separate thread to keep UI responsive
+        ui.set_is_exporting(true);
+        ui.set_export_status(format!("Exporting {}…", path.display()).into());
+        let ui_handle = ui.as_weak();
+        let render = config_export.render_options();
         thread::spawn(move || {
-            // Generate terrain with current timestamp as seed
-            let seed = SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap()
-                .as_secs() as u32;
-            
-            let mut generator = TerrainGenerator::new_with_settings(seed, settings);
-            
-            // Generate a huge map - 1600x1000 tiles
-            let map = generator.generate(1600, 1000);
-            let info = generate_map_info(&map);
-            
-            // Update UI from main thread
+            let fonts = fonts.unwrap_or_else(|| map_fonts(&map));
+            let status = match export_map(&map, &fonts, &path, format, scale, layers, &render) {
+                Ok(()) => format!("Saved {}", path.display()),
+                Err(e) => format!("Export failed: {}", e),
+            };
+            let _ = ui_handle.upgrade_in_event_loop(move |ui| {
+                ui.set_is_exporting(false);
+                ui.set_export_status(status.into());
+            });
+        });
+    });
+
+    // Export pack: every file of `EXPORT_PACK` into a chosen folder, with
+    // progress in the export dialog
+    let ui_handle = ui.as_weak();
+    let view_state_pack = view_state.clone();
+    let config_pack = config.clone();
+    ui.on_export_pack(move || {
+        let ui = ui_handle.unwrap();
+        let (map, fonts, seed, settings) = {
+            let state = view_state_pack.lock().unwrap();
+            let Some(map) = state.map.clone() else { return };
+            (map.clone(), fonts_kept_for(&state.fonts, &map), state.seed, state.settings)
+        };
+        let scale = ui.get_export_scale().max(1) as usize;
+        let layers = ui_export_layers(&ui);
+
+        let suggested = config_pack.output_file(seed, &settings, "png");
+        let stem = suggested.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+        let mut dialog = rfd::FileDialog::new().set_title("Export Pack");
+        if let Some(dir) = suggested.parent().filter(|d| !d.as_os_str().is_empty()) {
+            dialog = dialog.set_directory(dir);
+        }
+        let Some(dir) = dialog.pick_folder() else { return };
+
+        ui.set_is_exporting(true);
+        ui.set_is_exporting_pack(true);
+        ui.set_export_progress(0.0);
+        let ui_handle = ui.as_weak();
+        let render = config_pack.render_options();
+        thread::spawn(move || {
+            let report = |progress: f32, status: String| {
+                let _ = ui_handle.upgrade_in_event_loop(move |ui| {
+                    ui.set_export_progress(progress);
+                    ui.set_export_status(status.into());
+                });
+            };
+            let fonts = fonts.unwrap_or_else(|| map_fonts(&map));
+            let mut status = format!("Saved {} files to {}", EXPORT_PACK.len(), dir.display());
+            for (index, (suffix, format, style)) in EXPORT_PACK.into_iter().enumerate() {
+                let path = dir.join(format!("{}_{}", stem, suffix));
+                report(
+                    index as f32 / EXPORT_PACK.len() as f32,
+                    format!("Exporting {} ({} of {})…", path.display(), index + 1, EXPORT_PACK.len()),
+                );
+                let options = RenderOptions { style, ..render };
+                if let Err(e) = export_map(&map, &fonts, &path, format, scale, layers, &options) {
+                    status = format!("Export failed on {}: {}", path.display(), e);
+                    break;
+                }
+            }
+            let _ = ui_handle.upgrade_in_event_loop(move |ui| {
+                ui.set_is_exporting(false);
+                ui.set_is_exporting_pack(false);
+                ui.set_export_status(status.into());
+            });
+        });
+    });
+    
+    // Project files keep the map with its edits, settings and view
+    let ui_handle = ui.as_weak();
+    let view_state_save = view_state.clone();
+    ui.on_save_project(move |choose_file| {
+        let ui = ui_handle.unwrap();
+        let (map, seed, settings, path) = {
+            let state = view_state_save.lock().unwrap();
+            let Some(map) = state.map.clone() else { return };
+            (map, state.seed, state.settings, state.project_path.clone())
+        };
+        let path = match path.clone().filter(|_| !choose_file) {
+            Some(path) => path,
+            None => {
+                let suggested = path.unwrap_or_else(|| config.output_file(seed, &settings, PROJECT_EXTENSION));
+                let mut dialog = rfd::FileDialog::new()
+                    .set_title("Save Project")
+                    .add_filter("Mapper project", &[PROJECT_EXTENSION]);
+                if let Some(name) = suggested.file_name() {
+                    dialog = dialog.set_file_name(name.to_string_lossy());
+                }
+                if let Some(dir) = suggested.parent().filter(|d| !d.as_os_str().is_empty()) {
+                    dialog = dialog.set_directory(dir);
+                }
+                let Some(path) = dialog.save_file() else { return };
+                path
+            }
+        };
+        let view = ProjectView { zoom: ui.get_zoom(), x: ui.get_view_x(), y: ui.get_view_y() };
+        ui.set_file_status(format!("Saving {}…", path.display()).into());
+        let ui_handle = ui.as_weak();
+        let view_state = view_state_save.clone();
+        thread::spawn(move || {
+            let project = Project { map: (*map).clone(), seed, settings, view };
+            let result = project.save(&path);
             let _ = slint::invoke_from_event_loop(move || {
-                let ui = ui_handle_thread.unwrap();
-                let image = generate_terrain_image(&map);
-                ui.set_map_image(image);
-                ui.set_map_status(format!("Map generated (Seed: {})\n{}", seed, info).into());
+                let Some(ui) = ui_handle.upgrade() else { return };
+                match result {
+                    Ok(()) => {
+                        ui.set_file_status(format!("Saved {}", path.display()).into());
+                        view_state.lock().unwrap().project_path = Some(path);
+                    }
+                    Err(e) => ui.set_file_status(format!("Could not save {}: {}", path.display(), e).into()),
+                }
+            });
+        });
+    });
+    
+    let ui_handle = ui.as_weak();
+    let view_state_open = view_state.clone();
+    ui.on_open_project(move || {
+        let ui = ui_handle.unwrap();
+        let Some(path) = rfd::FileDialog::new()
+            .set_title("Open Project")
+            .add_filter("Mapper project", &[PROJECT_EXTENSION])
+            .pick_file()
+        else {
+            return;
+        };
+        ui.set_file_status(format!("Opening {}…", path.display()).into());
+        let ui_handle = ui.as_weak();
+        let view_state = view_state_open.clone();
+        thread::spawn(move || {
+            let project = Project::load(&path).map(|project| {
+                let info = generate_map_info(&project.map);
+                (project, info)
+            });
+            let _ = slint::invoke_from_event_loop(move || {
+                let Some(ui) = ui_handle.upgrade() else { return };
+                let (project, info) = match project {
+                    Ok(loaded) => loaded,
+                    Err(e) => {
+                        ui.set_file_status(format!("Could not open {}: {}", path.display(), e).into());
+                        return;
+                    }
+                };
+                let Project { map, seed, settings, view } = project;
+                set_ui_settings(&ui, &settings);
+                ui.set_seed_text(seed.to_string().into());
+                ui.set_seed_error("".into());
+                ui.set_map_status(format!("Opened {} (Seed: {})\n{}", path.display(), seed, info).into());
+                ui.set_file_status(format!("Opened {}", path.display()).into());
+                ui.set_map_tiles_width(map.width as f32);
+                ui.set_map_tiles_height(map.height as f32);
+                // Fitting first sets the zoom limits for this map's size
+                fit_view(&ui, &map);
+                ui.set_zoom(view.zoom.clamp(ui.get_min_zoom(), ui.get_max_zoom()));
+                ui.set_view_x(view.x);
+                ui.set_view_y(view.y);
+                ui.set_show_inspector(false);
                 ui.set_has_map(true);
-                ui.set_is_generating(false);
+                let mut state = view_state.lock().unwrap();
+                state.map = Some(Arc::new(map));
+                state.seed = seed;
+                state.settings = settings;
+                state.cache = None;
+                state.project_path = Some(path);
+                drop(state);
+                render_visible(&ui, &view_state);
             });
         });
     });
     
+    let ui_handle = ui.as_weak();
+    ui.on_fit_view(move || {
+        let ui = ui_handle.unwrap();
+        let map = view_state.lock().unwrap().map.clone();
+        if let Some(map) = map {
+            fit_view(&ui, &map);
+            render_visible(&ui, &view_state);
+        }
+    });
+    
     ui.on_menu_exit(move || {
         std::process::exit(0);
     });
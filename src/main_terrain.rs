@@ -1,11 +1,34 @@
-use mapper::terrain_generator::{Biome, GenerationSettings, TerrainGenerator, TerrainMap};
-use mapper::terrain_renderer::TerrainRenderer;
-use std::io::{self, Write};
-use std::time::SystemTime;
+use mapper::climate_export::{save_climate_rasters, save_weather_csv, RasterStyle};
+use mapper::config::{expand_name_template, Config};
+use mapper::editor_export::{save_editor_assets, Editor};
+use mapper::error::MapperError;
+use mapper::gallery::Gallery;
+use mapper::globe_export::{GlobeProjection, GlobeView};
+use mapper::hexcrawl::{draw_hex_grid, hex_number, hex_sheet, sheet_csv, sheet_markdown, HexGrid};
+use mapper::measure::DEFAULT_KM_PER_TILE;
+use mapper::name_lists::NameLists;
+use mapper::png_metadata::MapMetadata;
+use mapper::presets::{find_preset, preset_names};
+use mapper::profile_chart::{path_distances, straight_path, ProfileChart};
+use mapper::roll20_export::{fit_to_grid, Roll20Options, Roll20Page};
+use mapper::stl_export::{save_stl, StlOptions};
+use mapper::svg_export::render_svg;
+use mapper::terrain_generator::{
+    Biome, FogOfWar, GenerationObserver, GenerationPhase, GenerationSettings, MapScore,
+    NodeKind, Region, ShapeMask, Symmetry, TerrainGenerator, TerrainMap, TravelProfile, TravelSpeeds, Viewpoint, MAX_EDGE_ROADS,
+    ROUTE_REVEAL,
+};
+use mapper::terrain_renderer::{
+    FogStyle, LabelOptions, LabelRenderer, Layers, NamePlate, RenderOptions, RenderStyle, TerrainRenderer, MAX_SUPERSAMPLE,
+};
+use mapper::text::{draw_text, FontChain};
+use std::io::{self, Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
 use std::env;
-use image::Rgb;
-use rusttype::{Font, Scale};
-use imageproc::drawing::draw_text_mut;
+use image::{ImageOutputFormat, Rgb, Rgba, RgbaImage};
+use indicatif::{ProgressBar, ProgressStyle};
+use rusttype::Scale;
 
 fn print_terrain_ascii(map: &TerrainMap) {
     // ASCII representation with sampling for large maps
@@ -109,497 +132,613 @@ fn print_terrain_info(map: &TerrainMap) {
     println!("  \x1b[94m~\x1b[0m Rivers");
 }
 
-fn save_terrain_png(map: &TerrainMap, filename: &str, base_scale: u32) -> Result<(), image::ImageError> {
+/// A seed from the clock, for runs that don't name one.
+fn time_seed() -> u32 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as u32
+}
+
+/// Read one line from stdin, or `None` once it is closed or unreadable.
+fn read_input_line() -> Option<String> {
+    let _ = io::stdout().flush();
+    let mut input = String::new();
+    match io::stdin().read_line(&mut input) {
+        Ok(n) if n > 0 => Some(input),
+        _ => None,
+    }
+}
+
+/// Save `map` as a PNG (or any format the extension of `filename` names).
+/// PNGs get `metadata` embedded when there is some.
+fn save_terrain_png(map: &TerrainMap, filename: &Path, base_scale: u32, layers: Layers, options: &RenderOptions, metadata: Option<&MapMetadata>, progress: &ProgressBar) -> Result<(), MapperError> {
     // Use the shared terrain renderer
     let scale = base_scale; // Direct scale, no multiplication
-    let mut img = TerrainRenderer::render_to_image(map, scale);
-    
-    // Load font for text rendering
-    let font_data = include_bytes!("../assets/fonts/DejaVuSans.ttf");
-    let font = Font::try_from_bytes(font_data as &[u8]).unwrap();
+    let mut img = TerrainRenderer::render_layers_to_image(map, scale, layers, options);
+    progress.set_position(60);
     
-    // Track occupied label regions to avoid overlaps
-    let mut occupied_regions: Vec<(i32, i32, i32, i32)> = Vec::new();
+    draw_labels(&mut img, map, scale, layers, &options.labels)?;
     
-    // Helper function to check if a region overlaps with occupied regions
-    let check_overlap = |x: i32, y: i32, w: i32, h: i32, occupied: &Vec<(i32, i32, i32, i32)>| -> bool {
-        // Add extra margin to prevent labels from being too close
-        let margin = 5;
-        for &(ox, oy, ow, oh) in occupied {
-            if x - margin < ox + ow && x + w + margin > ox && 
-               y - margin < oy + oh && y + h + margin > oy {
-                return true;
-            }
+    progress.set_position(80);
+    if let Some(dir) = filename.parent().filter(|d| !d.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)?;
+    }
+    let img = if layers.terrain {
+        // Opaque: keep the smaller RGB encoding
+        image::DynamicImage::ImageRgb8(image::DynamicImage::ImageRgba8(img).to_rgb8())
+    } else {
+        image::DynamicImage::ImageRgba8(img)
+    };
+    let is_png = filename
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("png"));
+    match metadata {
+        Some(metadata) if is_png => {
+            let mut png = Vec::new();
+            img.write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)?;
+            std::fs::write(filename, metadata.embed(&png)?)?;
         }
-        false
+        _ => img.save(filename)?,
+    }
+    progress.set_position(100);
+    Ok(())
+}
+
+/// Draw the text for `layers` onto `img`, a map rendered at `scale`:
+/// region names, plus city, road and bridge names for the layers drawn,
+/// as many of them as `labels` asks for.
+fn draw_labels(img: &mut RgbaImage, map: &TerrainMap, scale: u32, layers: Layers, labels: &LabelOptions) -> Result<(), MapperError> {
+    draw_labels_on(img, map, scale, layers, labels, None)
+}
+
+/// Draw the text for `layers` as `draw_labels` does, onto `img`: the
+/// flat map, or its reprojection in `globe`, where each name goes to its
+/// place's projected point and those out of view are left out.
+fn draw_labels_on(img: &mut RgbaImage, map: &TerrainMap, scale: u32, layers: Layers, labels: &LabelOptions, globe: Option<&GlobeView>) -> Result<(), MapperError> {
+    let flat = (map.width as u32 * scale, map.height as u32 * scale);
+    let onto_globe = |x: f32, y: f32| globe.and_then(|view| view.place(flat, x, y));
+    let names = LabelRenderer::new(map, scale, layers, *labels);
+    match globe {
+        Some(_) => names.with_projection(&onto_globe).draw(img)?,
+        None => names.draw(img)?,
     };
-    
-    // Helper function to draw a leader line with arrow
-    let draw_leader = |img: &mut image::RgbImage, from_x: i32, from_y: i32, to_x: i32, to_y: i32| {
-        use imageproc::drawing::{draw_line_segment_mut, draw_filled_circle_mut};
-        
-        // Draw white outline first for contrast against any background
-        let white_color = Rgb([255, 255, 255]);
-        let black_color = Rgb([0, 0, 0]);
-        
-        // Draw thick white outline (5 pixels)
-        for offset in -2..=2 {
-            draw_line_segment_mut(
-                img,
-                (from_x as f32 + offset as f32, from_y as f32),
-                (to_x as f32 + offset as f32, to_y as f32),
-                white_color
-            );
-            draw_line_segment_mut(
-                img,
-                (from_x as f32, from_y as f32 + offset as f32),
-                (to_x as f32, to_y as f32 + offset as f32),
-                white_color
-            );
+    Ok(())
+}
+
+fn save_terrain_svg(map: &TerrainMap, filename: &Path, scale: u32, layers: Layers, options: &RenderOptions) -> Result<(), MapperError> {
+    let svg = render_svg(map, scale, layers, options)?;
+    if let Some(dir) = filename.parent().filter(|d| !d.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(filename, svg)?;
+    Ok(())
+}
+
+/// How much the CLI prints: `--quiet` keeps only errors, `--verbose` adds
+/// per-phase timings and a summary of the generated map.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+}
+
+/// A 0-100 progress bar for one phase. Bars go to stderr and are hidden
+/// automatically when it isn't a terminal.
+fn phase_bar(name: &str, verbosity: Verbosity) -> ProgressBar {
+    if verbosity == Verbosity::Quiet {
+        return ProgressBar::hidden();
+    }
+    let bar = ProgressBar::new(100);
+    bar.set_style(
+        ProgressStyle::with_template("{prefix:>10} [{bar:40.cyan/blue}] {percent:>3}% {msg}")
+            .unwrap()
+            .progress_chars("=> "),
+    );
+    bar.set_prefix(name.to_string());
+    bar
+}
+
+fn finish_bar(bar: &ProgressBar, verbosity: Verbosity) {
+    if verbosity < Verbosity::Verbose {
+        bar.finish();
+        return;
+    }
+    let timing = format!("{:.2?}", bar.elapsed());
+    if bar.is_hidden() {
+        eprintln!("{:>10} done in {}", bar.prefix(), timing);
+    }
+    bar.finish_with_message(timing);
+}
+
+/// Shows the generator's progress as one bar per phase.
+struct PhaseBars {
+    verbosity: Verbosity,
+    running: Option<ProgressBar>,
+}
+
+impl GenerationObserver for PhaseBars {
+    fn on_phase_start(&mut self, phase: GenerationPhase) {
+        self.running = Some(phase_bar(phase.name(), self.verbosity));
+    }
+
+    fn on_progress(&mut self, _phase: GenerationPhase, fraction: f32) {
+        if let Some(bar) = &self.running {
+            bar.set_position((fraction * 100.0) as u64);
         }
-        
-        // Draw black center line (3 pixels)
-        for offset in -1..=1 {
-            draw_line_segment_mut(
-                img,
-                (from_x as f32 + offset as f32, from_y as f32),
-                (to_x as f32 + offset as f32, to_y as f32),
-                black_color
-            );
-            draw_line_segment_mut(
-                img,
-                (from_x as f32, from_y as f32 + offset as f32),
-                (to_x as f32, to_y as f32 + offset as f32),
-                black_color
-            );
+    }
+
+    fn on_phase_end(&mut self, _phase: GenerationPhase, _elapsed: Duration) {
+        if let Some(bar) = self.running.take() {
+            finish_bar(&bar, self.verbosity);
         }
-        
-        // Draw large arrow head pointing to the city
-        let dx = to_x - from_x;
-        let dy = to_y - from_y;
-        let len = ((dx * dx + dy * dy) as f32).sqrt();
-        if len > 10.0 {
-            // Normalize direction vector
-            let ndx = dx as f32 / len;
-            let ndy = dy as f32 / len;
-            
-            // Larger arrow head
-            let arrow_len = 15.0;
-            let arrow_width = 8.0;
-            
-            // Calculate arrow head points - point directly at city
-            let arrow_x = to_x as f32;
-            let arrow_y = to_y as f32;
-            
-            // Draw thick arrow head lines
-            for offset in -1..=1 {
-                draw_line_segment_mut(
-                    img,
-                    (arrow_x, arrow_y),
-                    (arrow_x - ndx * arrow_len - ndy * arrow_width + offset as f32, 
-                     arrow_y - ndy * arrow_len + ndx * arrow_width),
-                    black_color
-                );
-                draw_line_segment_mut(
-                    img,
-                    (arrow_x, arrow_y),
-                    (arrow_x - ndx * arrow_len + ndy * arrow_width + offset as f32, 
-                     arrow_y - ndy * arrow_len - ndx * arrow_width),
-                    black_color
-                );
-            }
+    }
+}
+
+fn cli_generator(seed: u32, settings: GenerationSettings, names: &NameLists, mask: Option<&ShapeMask>, verbosity: Verbosity) -> TerrainGenerator {
+    let mut generator = TerrainGenerator::new_with_settings(seed, settings);
+    generator.set_name_lists(names.clone());
+    generator.set_shape_mask(mask.cloned());
+    if verbosity > Verbosity::Quiet {
+        generator.set_observer(Box::new(PhaseBars { verbosity, running: None }));
+    }
+    generator
+}
+
+/// Generate a map at the CLI's output resolution, reporting progress.
+/// With `gpu` the noise fields are computed on the GPU if there is one;
+/// with `mask` land is only raised within it.
+fn generate_map(seed: u32, settings: GenerationSettings, names: &NameLists, mask: Option<&ShapeMask>, verbosity: Verbosity, gpu: bool) -> TerrainMap {
+    let mut generator = cli_generator(seed, settings, names, mask, verbosity);
+    if gpu && !generator.set_gpu(true) && verbosity > Verbosity::Quiet {
+        eprintln!("No usable GPU (or built without the gpu feature); generating on the CPU");
+    }
+    let started = Instant::now();
+    let map = generator.generate(settings.map_width, settings.map_height);
+    if verbosity == Verbosity::Verbose {
+        eprintln!("Generated {}x{} tiles in {:.2?}: {} rivers, {} cities, {} roads, {} bridges, {} labels",
+                  map.width, map.height, started.elapsed(),
+                  map.rivers.len(), map.cities.len(), map.roads.len(), map.bridges.len(), map.labels.len());
+    }
+    map
+}
+
+/// Generate the terrain of `count` consecutive seeds from `first`, score
+/// each, and finish the best one. Returns its seed with the map.
+fn generate_best_map(first: u32, count: u32, settings: GenerationSettings, names: &NameLists, mask: Option<&ShapeMask>, verbosity: Verbosity, gpu: bool) -> (u32, TerrainMap) {
+    let bar = phase_bar("candidates", verbosity);
+    bar.set_length(count as u64);
+    let mut best: Option<(u32, TerrainMap, MapScore)> = None;
+    for n in 0..count {
+        let seed = first.wrapping_add(n);
+        bar.set_message(format!("seed {}", seed));
+        let mut generator = TerrainGenerator::new_with_settings(seed, settings);
+        generator.set_shape_mask(mask.cloned());
+        generator.set_gpu(gpu);
+        let map = generator.generate_terrain(settings.map_width, settings.map_height);
+        let score = map.score();
+        if verbosity == Verbosity::Verbose {
+            bar.suspend(|| eprintln!("Seed {}: {}", seed, score));
         }
-        
-        // Draw a larger circle at the arrow point for clarity
-        if to_x >= 3 && to_y >= 3 && to_x < img.width() as i32 - 3 && to_y < img.height() as i32 - 3 {
-            // White circle with black outline for contrast
-            draw_filled_circle_mut(img, (to_x, to_y), 5, Rgb([0, 0, 0]));
-            draw_filled_circle_mut(img, (to_x, to_y), 3, Rgb([255, 255, 255]));
+        if best.as_ref().is_none_or(|(_, _, top)| score.total() > top.total()) {
+            best = Some((seed, map, score));
+        }
+        bar.inc(1);
+    }
+    bar.set_message(String::new());
+    finish_bar(&bar, verbosity);
+
+    let (seed, mut map, score) = best.expect("at least one candidate");
+    if verbosity > Verbosity::Quiet {
+        println!("Best of {}: seed {} scored {:.0}/100", count, seed, score.total());
+    }
+    // The settlement phases don't depend on how the terrain was made
+    cli_generator(seed, settings, names, mask, verbosity).regenerate(&mut map, GenerationPhase::Cities);
+    (seed, map)
+}
+
+/// Load a saved map and re-run `from` and the phases after it with `seed`,
+/// keeping the landmass, climate and rivers.
+fn reroll_map(path: &Path, from: GenerationPhase, seed: u32, settings: GenerationSettings, names: &NameLists, mask: Option<&ShapeMask>, verbosity: Verbosity) -> TerrainMap {
+    let mut map = TerrainMap::load_json(path).unwrap_or_else(|e| {
+        eprintln!("Error: cannot load map {}: {}", path.display(), e);
+        std::process::exit(1);
+    });
+    cli_generator(seed, settings, names, mask, verbosity).regenerate(&mut map, from);
+    if verbosity == Verbosity::Verbose {
+        eprintln!("Rerolled from {}: {} cities, {} roads, {} labels",
+                  from.name(), map.cities.len(), map.roads.len(), map.labels.len());
+    }
+    map
+}
+
+/// Save each selected layer on its own as an RGBA PNG with a transparent
+/// background, `<stem>_<layer>.png` next to `filename`, for compositing
+/// elsewhere. Returns the files written.
+fn save_layer_pngs(map: &TerrainMap, filename: &Path, scale: u32, layers: Layers, options: &RenderOptions, verbosity: Verbosity) -> Result<Vec<PathBuf>, MapperError> {
+    let bar = phase_bar("layers", verbosity);
+    let split = layers.split();
+    let stem = filename.file_stem().unwrap_or_default().to_string_lossy();
+    let mut written = Vec::new();
+    for (done, (name, layer)) in split.iter().enumerate() {
+        let mut img = TerrainRenderer::render_layers_to_image(map, scale, *layer, options);
+        if layer.labels {
+            // The text of the other selected layers, without their drawing
+            draw_labels(&mut img, map, scale, layers, &options.labels)?;
         }
+        let file = filename.with_file_name(format!("{}_{}.png", stem, name));
+        if let Some(dir) = file.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        img.save(&file)?;
+        written.push(file);
+        bar.set_position(((done + 1) * 100 / split.len()) as u64);
+    }
+    finish_bar(&bar, verbosity);
+    Ok(written)
+}
+
+/// Render and save a map at `scale` pixels per tile with a progress bar
+/// for the render phase. Files ending in `.svg` are written as SVG,
+/// anything else as PNG, with the seed and settings in `metadata` embedded.
+fn save_map(map: &TerrainMap, filename: &Path, scale: u32, layers: Layers, options: &RenderOptions, metadata: Option<&MapMetadata>, verbosity: Verbosity) -> Result<(), MapperError> {
+    let bar = phase_bar("render", verbosity);
+    let is_svg = filename
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("svg"));
+    let result = if is_svg {
+        save_terrain_svg(map, filename, scale, layers, options)
+    } else {
+        save_terrain_png(map, filename, scale, layers, options, metadata, &bar)
     };
-    
-    // Sort cities by population (draw larger cities first to give them priority)
-    let mut sorted_cities: Vec<_> = map.cities.iter().enumerate().collect();
-    sorted_cities.sort_by(|a, b| b.1.population.cmp(&a.1.population));
-    
-    // Draw city labels with smart positioning
-    for (_, city) in sorted_cities {
-        let city_x = (city.x as u32) * scale + scale / 2;
-        let city_y = (city.y as u32) * scale + scale / 2;
-        
-        // Text scale based on city size - adjusted for readability at any scale
-        let text_size_factor = (scale as f32).max(10.0) / 10.0; // Normalize to reasonable text size
-        let text_scale = if city.population > 250000 {
-            Scale::uniform(28.0 * text_size_factor)  // Large cities
-        } else if city.population > 100000 {
-            Scale::uniform(24.0 * text_size_factor)  // Medium cities
-        } else {
-            Scale::uniform(20.0 * text_size_factor)  // Small cities
-        };
-        
-        // Estimate text dimensions with padding for better collision detection
-        let char_width = (text_scale.x * 0.6) as i32; // More accurate character width
-        let text_width = city.name.len() as i32 * char_width + 10; // Add padding
-        let text_height = text_scale.y as i32 + 10; // Add padding
-        
-        // Try different positions to avoid overlap - more positions for better placement
-        let close_offsets = [
-            (scale as i32 + 5, -5),  // Right
-            (-(text_width + scale as i32 + 5), -5),  // Left
-            (-text_width / 2, -(scale as i32 + text_height + 5)),  // Above
-            (-text_width / 2, scale as i32 + 5),  // Below
-            (scale as i32 + 5, -(scale as i32 + text_height)),  // Right-up
-            (-(text_width + scale as i32 + 5), -(scale as i32 + text_height)),  // Left-up
-            (scale as i32 + 5, scale as i32),  // Right-down
-            (-(text_width + scale as i32 + 5), scale as i32),  // Left-down
-        ];
-        
-        let far_offsets = [
-            (scale as i32 * 3, -(scale as i32 * 2)),  // Far right-up
-            (-(text_width + scale as i32 * 3), -(scale as i32 * 2)),  // Far left-up
-            (scale as i32 * 3, scale as i32 * 2),  // Far right-down
-            (-(text_width + scale as i32 * 3), scale as i32 * 2),  // Far left-down
-            (scale as i32 * 4, -(scale as i32)),  // Very far right
-            (-(text_width + scale as i32 * 4), -(scale as i32)),  // Very far left
-            (-text_width / 2, -(scale as i32 * 3 + text_height)),  // Very far above
-            (-text_width / 2, scale as i32 * 3),  // Very far below
-        ];
-        
-        let mut best_pos = None;
+    match result {
+        Ok(_) => finish_bar(&bar, verbosity),
+        Err(_) => bar.abandon(),
+    }
+    result
+}
 
-        // First try close positions without leader lines
-        for &(dx, dy) in close_offsets.iter() {
-            let test_x = city_x as i32 + dx;
-            let test_y = city_y as i32 + dy;
-            
-            if test_x > 0 && test_y > 0 && 
-               test_x + text_width < img.width() as i32 && 
-               test_y + text_height < img.height() as i32 &&
-               !check_overlap(test_x, test_y, text_width, text_height, &occupied_regions) {
-                best_pos = Some((test_x, test_y));
-                break;
+/// Save `<stem>_roll20.png`, the map scaled to the Roll20 grid, and
+/// `<stem>_roll20.json`, its page size and city tokens. Returns the files
+/// written.
+fn save_roll20(map: &TerrainMap, filename: &Path, layers: Layers, render: &RenderOptions, options: &Roll20Options) -> Result<Vec<PathBuf>, MapperError> {
+    let scale = options.render_scale();
+    let mut img = TerrainRenderer::render_layers_to_image(map, scale, layers, render);
+    draw_labels(&mut img, map, scale, layers, &render.labels)?;
+    let stem = filename.file_stem().unwrap_or_default().to_string_lossy();
+    let png = filename.with_file_name(format!("{}_roll20.png", stem));
+    fit_to_grid(&img, map, options).save(&png)?;
+    let json = png.with_extension("json");
+    let image = png.file_name().unwrap_or_default().to_string_lossy();
+    Roll20Page::new(map, &stem, &image, options).save_json(&json)?;
+    Ok(vec![png, json])
+}
+
+/// Save `<stem>_globe.png`, the whole map reprojected onto a disc as
+/// `view` says, with its labels at their projected places. Returns the
+/// file written.
+fn save_globe(map: &TerrainMap, filename: &Path, scale: u32, layers: Layers, render: &RenderOptions, view: &GlobeView) -> Result<PathBuf, MapperError> {
+    let flat = TerrainRenderer::render_layers_to_image(map, scale, layers, render);
+    let mut img = view.reproject(&flat);
+    draw_labels_on(&mut img, map, scale, layers, &render.labels, Some(view))?;
+    let stem = filename.file_stem().unwrap_or_default().to_string_lossy();
+    let png = filename.with_file_name(format!("{}_globe.png", stem));
+    img.save(&png)?;
+    Ok(png)
+}
+
+/// A part of the fog of war `--reveal` or `--reveal-route` uncovers.
+enum Reveal {
+    /// The tiles within a radius of a tile
+    Circle((usize, usize), f32),
+    /// The tiles along the way on foot between two places, each a city
+    /// name or X,Y
+    Route(String, String),
+}
+
+/// Tiles round a tile `--reveal` uncovers when no radius is given
+const DEFAULT_REVEAL_RADIUS: f32 = 5.0;
+
+/// What the player map shows of the world, from `--fog`, `--reveal`,
+/// `--reveal-route` and `--fog-style`.
+#[derive(Default)]
+struct PlayerMap {
+    /// Fog of war saved before, to reveal more of
+    fog: Option<PathBuf>,
+    reveals: Vec<Reveal>,
+    style: FogStyle,
+}
+
+impl PlayerMap {
+    /// Whether any player map was asked for.
+    fn wanted(&self) -> bool {
+        self.fog.is_some() || !self.reveals.is_empty()
+    }
+}
+
+/// Save `<stem>_player.png`, the map with its labels under the fog of war,
+/// only what the player's reveals uncover (and what a saved fog already
+/// had) showing, and `<stem>_fog.json`, to reveal more of next time.
+/// Returns the files written.
+fn save_player_map(
+    map: &TerrainMap,
+    filename: &Path,
+    scale: u32,
+    layers: Layers,
+    render: &RenderOptions,
+    player: &PlayerMap,
+) -> Result<Vec<PathBuf>, MapperError> {
+    let mut fog = match &player.fog {
+        Some(path) => FogOfWar::load_json(path)?,
+        None => FogOfWar::new(map),
+    };
+    if (fog.width, fog.height) != (map.width, map.height) {
+        return Err(MapperError::Fog(format!(
+            "of {}x{} tiles for a {}x{} map",
+            fog.width, fog.height, map.width, map.height
+        )));
+    }
+    let place = |text: &str| {
+        map.find_city(text)
+            .map(|city| (city.x, city.y))
+            .or_else(|| parse_point(text))
+            .ok_or_else(|| MapperError::Fog(format!("reveal: no city or tile '{}'", text)))
+    };
+    for reveal in &player.reveals {
+        match reveal {
+            Reveal::Circle(center, radius) => fog.reveal_circle(*center, *radius),
+            Reveal::Route(from, to) => {
+                let route = map
+                    .route(place(from)?, place(to)?, TravelProfile::OnFoot)
+                    .ok_or_else(|| MapperError::Fog(format!("reveal: no way on foot from {} to {}", from, to)))?;
+                fog.reveal_route(&route.path, ROUTE_REVEAL);
             }
         }
-        
-        // If no close position works, try farther positions with leader lines
-        if best_pos.is_none() {
-            for &(dx, dy) in far_offsets.iter() {
-                let test_x = city_x as i32 + dx;
-                let test_y = city_y as i32 + dy;
-                
-                if test_x > 0 && test_y > 0 && 
-                   test_x + text_width < img.width() as i32 && 
-                   test_y + text_height < img.height() as i32 &&
-                   !check_overlap(test_x, test_y, text_width, text_height, &occupied_regions) {
-                    best_pos = Some((test_x, test_y));
-                    break;
+    }
+
+    let mut img = TerrainRenderer::render_layers_to_image(map, scale, layers, render);
+    draw_labels(&mut img, map, scale, layers, &render.labels)?;
+    TerrainRenderer::draw_fog(&mut img, &fog, scale, player.style);
+    let stem = filename.file_stem().unwrap_or_default().to_string_lossy();
+    let png = filename.with_file_name(format!("{}_player.png", stem));
+    img.save(&png)?;
+    let json = filename.with_file_name(format!("{}_fog.json", stem));
+    fog.save_json(&json)?;
+    Ok(vec![png, json])
+}
+
+/// Save the hex-crawl set for `grid`: `<stem>_hexes.png`, the map with the
+/// hex grid and numbers over it, and the hex sheet as `<stem>_hexes.md`
+/// and `.csv`. Returns the files written.
+fn save_hex_crawl(map: &TerrainMap, filename: &Path, scale: u32, layers: Layers, render: &RenderOptions, grid: &HexGrid) -> Result<Vec<PathBuf>, MapperError> {
+    let mut img = TerrainRenderer::render_layers_to_image(map, scale, layers, render);
+    draw_labels(&mut img, map, scale, layers, &render.labels)?;
+    draw_hex_grid(&mut img, map, grid, scale, Rgba([40, 30, 20, 255]));
+    // Numbers along the top of each hex
+    let fonts = FontChain::bundled()?;
+    let text = Scale::uniform((grid.size * scale as f32 / 5.0).max(8.0));
+    for &(col, row) in grid.hexes(map).keys() {
+        let (x, y) = grid.center(col, row);
+        let number = hex_number(col, row);
+        let left = (x * scale as f32 - fonts.text_width(&number, text) / 2.0) as i32;
+        let top = ((y - grid.size * 0.45) * scale as f32) as i32;
+        draw_text(&mut img, Rgba([40, 30, 20, 255]), left, top, text, &fonts, &number);
+    }
+    let stem = filename.file_stem().unwrap_or_default().to_string_lossy();
+    let png = filename.with_file_name(format!("{}_hexes.png", stem));
+    img.save(&png)?;
+
+    let entries = hex_sheet(map, grid);
+    let markdown = png.with_extension("md");
+    std::fs::write(&markdown, sheet_markdown(&format!("{} hex crawl", stem), grid, &entries))?;
+    let csv = png.with_extension("csv");
+    std::fs::write(&csv, sheet_csv(&entries))?;
+    Ok(vec![png, markdown, csv])
+}
+
+/// Save a generated map and everything the flags ask for alongside it
+/// (map data, climate rasters, split layers), reporting each file.
+fn save_outputs(map: &TerrainMap, filename: &Path, cli: &CliArgs, seed: u32, metadata: Option<&MapMetadata>) {
+    let verbosity = cli.verbosity;
+    let render = cli.render_options(seed);
+    match save_map(map, filename, cli.scale, cli.layers, &render, metadata, verbosity) {
+        Ok(_) if verbosity > Verbosity::Quiet => println!("Map saved as: {}", filename.display()),
+        Ok(_) => {}
+        Err(e) => eprintln!("Error saving map: {}", e),
+    }
+    if cli.save_json {
+        let json_file = filename.with_extension("json");
+        match map.save_json(&json_file) {
+            Ok(_) if verbosity > Verbosity::Quiet => println!("Map data saved as: {}", json_file.display()),
+            Ok(_) => {}
+            Err(e) => eprintln!("Error saving map data: {}", e),
+        }
+    }
+    if let Some(style) = cli.climate {
+        match save_climate_rasters(map, filename, style) {
+            Ok(files) if verbosity > Verbosity::Quiet => {
+                println!("Climate rasters ({}x{} tiles) saved as:", map.width, map.height);
+                for file in files {
+                    println!("  {}", file.display());
                 }
             }
+            Ok(_) => {}
+            Err(e) => eprintln!("Error saving climate rasters: {}", e),
         }
-        
-        // If still no position found, skip this label to avoid overlap
-        let (label_x, label_y) = if let Some(pos) = best_pos {
-            pos
-        } else {
-            // For important cities (large population), try harder to find a spot
-            if city.population > 100000 {
-                // Try to find a position with minimal overlap
-                let search_radius = scale as i32 * 6;
-                let mut best_angle_pos = None;
-                let mut min_overlap_count = i32::MAX;
-            
-                for angle in (0..360).step_by(45) {
-                    let rad = (angle as f32) * std::f32::consts::PI / 180.0;
-                    let test_x = city_x as i32 + (search_radius as f32 * rad.cos()) as i32 - text_width / 2;
-                    let test_y = city_y as i32 + (search_radius as f32 * rad.sin()) as i32 - text_height / 2;
-                    
-                    if test_x > 0 && test_y > 0 && 
-                       test_x + text_width < img.width() as i32 && 
-                       test_y + text_height < img.height() as i32 {
-                        // Count overlapping labels
-                        let mut overlap_count = 0;
-                        for &(rx, ry, rw, rh) in occupied_regions.iter() {
-                            if test_x < rx + rw && test_x + text_width > rx && 
-                               test_y < ry + rh && test_y + text_height > ry {
-                                overlap_count += 1;
-                            }
-                        }
-                        
-                        if overlap_count < min_overlap_count {
-                            min_overlap_count = overlap_count;
-                            best_angle_pos = Some((test_x, test_y));
-                        }
-                        
-                        // If we found a spot with no overlaps, use it
-                        if overlap_count == 0 {
-                            break;
-                        }
-                    }
-                }
-                
-                // Use the best position found, even if it has some overlap
-                // Important cities should always have labels
-                best_angle_pos.unwrap_or((city_x as i32 + search_radius, city_y as i32))
-            } else {
-                // For smaller cities, try progressively farther distances
-                let mut found_pos = None;
-                for radius_mult in [8, 10, 12, 15].iter() {
-                    let search_radius = scale as i32 * radius_mult;
-                    for angle in (0..360).step_by(60) {
-                        let rad = (angle as f32) * std::f32::consts::PI / 180.0;
-                        let test_x = city_x as i32 + (search_radius as f32 * rad.cos()) as i32 - text_width / 2;
-                        let test_y = city_y as i32 + (search_radius as f32 * rad.sin()) as i32 - text_height / 2;
-                        
-                        if test_x > 0 && test_y > 0 && 
-                           test_x + text_width < img.width() as i32 && 
-                           test_y + text_height < img.height() as i32 &&
-                           !check_overlap(test_x, test_y, text_width, text_height, &occupied_regions) {
-                            found_pos = Some((test_x, test_y));
-                            break;
-                        }
-                    }
-                    if found_pos.is_some() {
-                        break;
-                    }
-                }
-                // Always place the label somewhere, even if far away
-                found_pos.unwrap_or((city_x as i32 + scale as i32 * 10, city_y as i32 - scale as i32 * 5))
+    }
+    if let Some(days) = cli.weather_days {
+        let stem = filename.file_stem().unwrap_or_default().to_string_lossy();
+        let weather_file = filename.with_file_name(format!("{}_weather.csv", stem));
+        match save_weather_csv(map, &weather_file, days) {
+            Ok(_) if verbosity > Verbosity::Quiet => {
+                println!("Weather ({} days) saved as: {}", days, weather_file.display())
             }
-        };
-        
-        // Draw the label with outline FIRST
-        for dy in -2i32..=2 {
-            for dx in -2i32..=2 {
-                if dx != 0 || dy != 0 {
-                    draw_text_mut(
-                        &mut img,
-                        Rgb([0, 0, 0]),
-                        label_x + dx,
-                        label_y + dy,
-                        text_scale,
-                        &font,
-                        &city.name
-                    );
+            Ok(_) => {}
+            Err(e) => eprintln!("Error saving weather: {}", e),
+        }
+    }
+    if let Some(options) = &cli.stl {
+        let stl_file = filename.with_extension("stl");
+        match save_stl(map, &stl_file, options) {
+            Ok(_) if verbosity > Verbosity::Quiet => println!("3D model saved as: {}", stl_file.display()),
+            Ok(_) => {}
+            Err(e) => eprintln!("Error saving 3D model: {}", e),
+        }
+    }
+    if let Some(grid) = &cli.hex {
+        match save_hex_crawl(map, filename, cli.scale, cli.layers, &render, grid) {
+            Ok(files) if verbosity > Verbosity::Quiet => {
+                println!("Hex crawl ({} tiles per hex) saved as:", grid.size);
+                for file in files {
+                    println!("  {}", file.display());
                 }
             }
+            Ok(_) => {}
+            Err(e) => eprintln!("Error saving hex crawl: {}", e),
         }
-        
-        draw_text_mut(
-            &mut img,
-            Rgb([255, 255, 255]),
-            label_x,
-            label_y,
-            text_scale,
-            &font,
-            &city.name
-        );
-        
-        // ALWAYS draw leader line for every city (draw AFTER text so it's visible)
-        // Calculate the closest edge of the text box to the city
-        let text_center_x = label_x + text_width / 2;
-        let text_center_y = label_y + text_height / 2;
-        
-        // Find the edge point of the text box closest to the city
-        let dx = city_x as i32 - text_center_x;
-        let dy = city_y as i32 - text_center_y;
-        
-        let from_x = if dx.abs() > dy.abs() {
-            // Connect from left or right edge
-            if dx > 0 {
-                label_x + text_width  // Right edge
-            } else {
-                label_x  // Left edge
-            }
-        } else {
-            text_center_x  // Center horizontally
-        };
-        
-        let from_y = if dy.abs() > dx.abs() {
-            // Connect from top or bottom edge
-            if dy > 0 {
-                label_y + text_height  // Bottom edge
-            } else {
-                label_y  // Top edge
+    }
+    if let Some((editor, size)) = cli.editor {
+        match save_editor_assets(map, filename, editor, size) {
+            Ok(files) if verbosity > Verbosity::Quiet => {
+                println!("{} assets ({}x{}) saved as:", editor.name(), size.0, size.1);
+                for file in files {
+                    println!("  {}", file.display());
+                }
             }
-        } else {
-            text_center_y  // Center vertically
-        };
-        
-        // Always draw the leader line
-        draw_leader(&mut img, from_x, from_y, city_x as i32, city_y as i32);
-        
-        // Mark this region as occupied
-        occupied_regions.push((label_x, label_y, text_width, text_height));
-        
-        // Draw population if large enough
-        if city.population > 100000 {
-            let pop_text = format!("({}k)", city.population / 1000);
-            let pop_scale = Scale::uniform(16.0 * text_size_factor);
-            
-            // Position population text below the city name
-            let pop_y = label_y + text_height + 5;
-            
-            draw_text_mut(
-                &mut img,
-                Rgb([200, 200, 200]),
-                label_x,
-                pop_y,
-                pop_scale,
-                &font,
-                &pop_text
-            );
-            
-            // Mark population label as occupied too
-            let pop_width = (pop_text.len() as i32 * pop_scale.x as i32 * 3) / 5;
-            occupied_regions.push((label_x, pop_y, pop_width, pop_scale.y as i32));
+            Ok(_) => {}
+            Err(e) => eprintln!("Error saving {} assets: {}", editor.name(), e),
         }
     }
-    
-    // Draw road labels (only for major roads to avoid clutter)
-    for road in &map.roads {
-        if road.road_type == "highway" && road.path.len() > 10 {
-            // Draw label at midpoint of road
-            let mid_idx = road.path.len() / 2;
-            let (rx, ry) = road.path[mid_idx];
-            let x = (rx as u32) * scale;
-            let y = (ry as u32) * scale;
-            
-            let text_size_factor = (scale as f32).max(10.0) / 10.0;
-            let road_scale = Scale::uniform(16.0 * text_size_factor);
-            
-            // Draw with outline for visibility
-            for dy in -1i32..=1 {
-                for dx in -1i32..=1 {
-                    if dx != 0 || dy != 0 {
-                        draw_text_mut(
-                            &mut img,
-                            Rgb([255, 255, 255]),
-                            x as i32 + dx,
-                            y as i32 + dy,
-                            road_scale,
-                            &font,
-                            &road.name
-                        );
-                    }
+    if let Some(options) = &cli.roll20 {
+        match save_roll20(map, filename, cli.layers, &render, options) {
+            Ok(files) if verbosity > Verbosity::Quiet => {
+                let (width, height) = options.page_cells(map);
+                println!("Roll20 page ({} x {} cells of {} px) saved as:", width, height, options.cell_px);
+                for file in files {
+                    println!("  {}", file.display());
                 }
             }
-            
-            draw_text_mut(
-                &mut img,
-                Rgb([60, 60, 60]),
-                x as i32,
-                y as i32,
-                road_scale,
-                &font,
-                &road.name
-            );
+            Ok(_) => {}
+            Err(e) => eprintln!("Error saving Roll20 page: {}", e),
         }
     }
-    
-    // Draw bridge labels
-    for bridge in &map.bridges {
-        let x = (bridge.x as u32) * scale;
-        let y = (bridge.y as u32) * scale - scale / 2;
-        
-        let text_size_factor = (scale as f32).max(10.0) / 10.0;
-        let bridge_scale = Scale::uniform(14.0 * text_size_factor);
-        
-        // Draw with white outline
-        for dy in -1i32..=1 {
-            for dx in -1i32..=1 {
-                if dx != 0 || dy != 0 {
-                    draw_text_mut(
-                        &mut img,
-                        Rgb([255, 255, 255]),
-                        x as i32 + dx,
-                        y as i32 + dy,
-                        bridge_scale,
-                        &font,
-                        &bridge.name
-                    );
+    if let Some(view) = &cli.globe {
+        match save_globe(map, filename, cli.scale, cli.layers, &render, view) {
+            Ok(file) if verbosity > Verbosity::Quiet => println!("Globe view saved as: {}", file.display()),
+            Ok(_) => {}
+            Err(e) => eprintln!("Error saving globe view: {}", e),
+        }
+    }
+    if cli.player.wanted() {
+        match save_player_map(map, filename, cli.scale, cli.layers, &render, &cli.player) {
+            Ok(files) if verbosity > Verbosity::Quiet => {
+                println!("Player map saved as:");
+                for file in files {
+                    println!("  {}", file.display());
                 }
             }
+            Ok(_) => {}
+            Err(e) => eprintln!("Error saving player map: {}", e),
         }
-        
-        draw_text_mut(
-            &mut img,
-            Rgb([80, 60, 40]),
-            x as i32,
-            y as i32,
-            bridge_scale,
-            &font,
-            &bridge.name
-        );
     }
-    
-    // Draw geographic feature labels
-    for label in &map.labels {
-        let x = (label.x * scale as f32) as u32;
-        let y = (label.y * scale as f32) as u32;
-        
-        // Choose color based on feature type
-        let text_color = match label.feature_type.as_str() {
-            "ocean" => Rgb([150, 200, 255]),
-            "mountains" => Rgb([150, 150, 150]),
-            "forest" => Rgb([100, 200, 100]),
-            "swamp" => Rgb([150, 180, 150]),
-            "river" => Rgb([100, 150, 255]),
-            _ => Rgb([200, 200, 200]),
-        };
-        
-        // Much larger font sizes for geographic features
-        let text_size_factor = (scale as f32).max(10.0) / 10.0;
-        let label_scale = match label.feature_type.as_str() {
-            "ocean" => Scale::uniform(32.0 * text_size_factor),     // Oceans - large
-            "mountains" => Scale::uniform(26.0 * text_size_factor), // Mountains - medium-large
-            "forest" => Scale::uniform(22.0 * text_size_factor),    // Forests - medium
-            "swamp" => Scale::uniform(22.0 * text_size_factor),     // Swamps - medium
-            "river" => Scale::uniform(18.0 * text_size_factor),     // Rivers - small-medium
-            _ => Scale::uniform(20.0 * text_size_factor),           // Default
-        };
-        
-        // Draw black outline for better visibility
-        for dy in -1i32..=1 {
-            for dx in -1i32..=1 {
-                if dx != 0 || dy != 0 {
-                    draw_text_mut(
-                        &mut img,
-                        Rgb([0, 0, 0]),
-                        x as i32 + dx,
-                        y as i32 + dy,
-                        label_scale,
-                        &font,
-                        &label.name
-                    );
+    if cli.split_layers {
+        match save_layer_pngs(map, filename, cli.scale, cli.layers, &render, verbosity) {
+            Ok(files) if verbosity > Verbosity::Quiet => {
+                println!("Layers saved as:");
+                for file in files {
+                    println!("  {}", file.display());
                 }
             }
+            Ok(_) => {}
+            Err(e) => eprintln!("Error saving layers: {}", e),
         }
-        
-        // Draw colored text
-        draw_text_mut(
-            &mut img,
-            text_color,
-            x as i32,
-            y as i32,
-            label_scale,
-            &font,
-            &label.name
-        );
     }
-    
-    img.save(filename)?;
-    Ok(())
 }
 
-fn parse_args() -> CliArgs {
-    let args: Vec<String> = env::args().collect();
+/// Load the config file: the one named by `--config`, else the per-user
+/// default. Exits with an error message if the file can't be used.
+fn load_config(args: &[String]) -> Config {
+    let explicit = args
+        .iter()
+        .position(|a| a == "--config")
+        .and_then(|i| args.get(i + 1));
+    let result = match explicit {
+        Some(path) => Config::load(Path::new(path)),
+        None => Config::load_default(),
+    };
+    result.unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    })
+}
+
+/// Parse the generation options. `args[0]` is the program (or subcommand)
+/// name and is skipped.
+fn parse_args(args: &[String]) -> CliArgs {
+    let config = load_config(args);
     let mut cli = CliArgs {
-        settings: GenerationSettings::default(),
+        settings: config.settings,
         seed: None,
         output: None,
         quick: false,
+        save_json: false,
+        climate: None,
+        weather_days: None,
+        names: NameLists::default(),
+        mask: None,
+        layers: Layers::all(),
+        scale: DEFAULT_SCALE,
+        render: config.render_options(),
+        clouds: false,
+        player: PlayerMap::default(),
+        svg: false,
+        split_layers: false,
+        stl: None,
+        roll20: None,
+        globe: None,
+        hex: None,
+        editor: None,
+        best_of: 1,
+        keep_terrain: None,
+        reroll_from: None,
+        crop: None,
+        verbosity: Verbosity::Normal,
+        gpu: false,
+        config,
     };
 
+    // Names from the config file, unless --names below replaces them
+    if let Some(path) = &cli.config.names {
+        cli.names = NameLists::load(path).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        });
+    }
+
+    // A preset replaces the configured settings; individual flags below
+    // still override it regardless of their position on the command line
+    if let Some(name) = args
+        .iter()
+        .position(|a| a == "--preset")
+        .and_then(|i| args.get(i + 1))
+    {
+        match find_preset(name, &cli.config) {
+            Some(settings) => {
+                cli.settings = settings;
+                cli.quick = true;
+            }
+            None => {
+                eprintln!(
+                    "Error: unknown preset '{}' (available: {})",
+                    name,
+                    preset_names(&cli.config).join(", ")
+                );
+                std::process::exit(1);
+            }
+        }
+    }
+
     let mut i = 1;
     while i < args.len() {
         match args[i].as_str() {
@@ -630,6 +769,46 @@ fn parse_args() -> CliArgs {
                     }
                 }
             }
+            "--edge-roads" => {
+                if let Some(count) = args.get(i + 1).and_then(|arg| arg.parse::<usize>().ok()) {
+                    cli.settings.edge_roads = count.min(MAX_EDGE_ROADS);
+                    cli.quick = true;
+                    i += 1;
+                }
+            }
+            "--modern" => {
+                cli.settings.modern = true;
+                cli.quick = true;
+            }
+            "--wrap" => {
+                cli.settings.wrap_x = true;
+                cli.quick = true;
+            }
+            "--symmetry" => {
+                if i + 1 < args.len() {
+                    cli.settings.symmetry = Symmetry::parse(&args[i + 1]).unwrap_or_else(|| {
+                        eprintln!("Error: unknown symmetry '{}' (available: {})", args[i + 1], Symmetry::NAMES.join(", "));
+                        std::process::exit(1);
+                    });
+                    cli.quick = true;
+                    i += 1;
+                }
+            }
+            "--symmetric-cities" => {
+                cli.settings.symmetric_cities = true;
+                cli.quick = true;
+            }
+            "--size" => {
+                let size = args.get(i + 1)
+                    .and_then(|arg| arg.split_once('x'))
+                    .and_then(|(w, h)| Some((w.parse::<usize>().ok()?, h.parse::<usize>().ok()?)));
+                if let Some((width, height)) = size.filter(|&(w, h)| w > 0 && h > 0) {
+                    cli.settings.map_width = width;
+                    cli.settings.map_height = height;
+                    cli.quick = true;
+                    i += 1;
+                }
+            }
             "--seed" => {
                 if i + 1 < args.len() {
                     if let Ok(value) = args[i + 1].parse::<u32>() {
@@ -641,78 +820,1606 @@ fn parse_args() -> CliArgs {
             }
             "--output" => {
                 if i + 1 < args.len() {
-                    cli.output = Some(args[i + 1].clone());
+                    cli.output = Some(PathBuf::from(&args[i + 1]));
                     cli.quick = true;
                     i += 1;
                 }
             }
-            "--help" => {
-                println!("Terrain Generator CLI");
-                println!("\nUsage: mapper-terrain-cli [OPTIONS]");
-                println!("\nOptions:");
-                println!("  --rivers <0.0-1.0>  Set river density (default: 0.5)");
-                println!("  --cities <0.0-1.0>  Set city density (default: 0.5)");
-                println!("  --land <0.0-1.0>    Set land percentage (default: 0.4)");
-                println!("  --seed <u32>        Seed for reproducible maps (default: current time)");
-                println!("  --output <file>     Output PNG filename (default: terrain_map_<seed>.png)");
-                println!("  --help              Show this help message");
-                println!("\nAny option switches to non-interactive quick mode.");
-                println!("\nExample:");
-                println!("  mapper-terrain-cli --rivers 0.8 --cities 0.3 --land 0.6 --seed 42 --output map.png");
-                std::process::exit(0);
+            "--name-template" => {
+                if i + 1 < args.len() {
+                    cli.config.name_template = Some(args[i + 1].clone());
+                    cli.quick = true;
+                    i += 1;
+                }
             }
-            _ => {}
-        }
-        i += 1;
-    }
-
-    cli
-}
-
-struct CliArgs {
-    settings: GenerationSettings,
-    seed: Option<u32>,
-    output: Option<String>,
-    quick: bool,
-}
-
-fn main() {
-    let cli = parse_args();
-    let settings = cli.settings;
-
-    if cli.quick {
-        // Quick mode: generate immediately and exit
-        println!("Generating terrain map with settings: Rivers={:.0}%, Cities={:.0}%, Land={:.0}%",
-                 settings.river_density * 100.0,
-                 settings.city_density * 100.0,
-                 settings.land_percentage * 100.0);
-
-        let seed = cli.seed.unwrap_or_else(|| {
-            SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap()
-                .as_secs() as u32
-        });
-        println!("Seed: {}", seed);
-        let mut generator = TerrainGenerator::new_with_settings(seed, settings);
-        let map = generator.generate(320, 240);  // Ultra-high resolution: 320x240 tiles
-
-        let filename = cli
-            .output
-            .unwrap_or_else(|| format!("terrain_map_{}.png", seed));
-
-        match save_terrain_png(&map, &filename, 5) {
-            Ok(_) => println!("Map saved as: {}", filename),
-            Err(e) => eprintln!("Error saving map: {}", e),
-        }
-        return;
-    }
-    
-    loop {
-        println!("\n\x1b[1mMenu:\x1b[0m");
-        println!("1. Generate new terrain map");
-        println!("2. Generate with custom seed");
-        println!("3. About");
+            "--best-of" => {
+                if let Some(count) = args.get(i + 1).and_then(|arg| arg.parse::<u32>().ok()) {
+                    cli.best_of = count.clamp(1, MAX_BEST_OF);
+                    cli.quick = true;
+                    i += 1;
+                }
+            }
+            "--scale" => {
+                if let Some(scale) = args.get(i + 1).and_then(|arg| arg.parse::<u32>().ok()) {
+                    cli.scale = scale.clamp(1, MAX_SCALE);
+                    cli.quick = true;
+                    i += 1;
+                }
+            }
+            "--layers" => {
+                if i + 1 < args.len() {
+                    cli.layers = Layers::parse(&args[i + 1]).unwrap_or_else(|e| {
+                        eprintln!("Error: {}", e);
+                        std::process::exit(1);
+                    });
+                    cli.quick = true;
+                    i += 1;
+                }
+            }
+            "--relief" | "--relief-detail" | "--slope-tint-strength" | "--coast-ring-width" => {
+                if i + 1 < args.len() {
+                    if let Ok(value) = args[i + 1].parse::<f32>() {
+                        match args[i].as_str() {
+                            "--relief" => cli.render.relief_strength = value.clamp(0.0, 5.0),
+                            "--relief-detail" => cli.render.relief_detail = value.clamp(0.1, 4.0),
+                            "--coast-ring-width" => cli.render.coast_ring_width = value.clamp(0.1, 5.0),
+                            _ => cli.render.slope_tint_strength = value.clamp(0.0, 1.0),
+                        }
+                        cli.quick = true;
+                        i += 1;
+                    }
+                }
+            }
+            "--style" => {
+                if i + 1 < args.len() {
+                    cli.render.style = RenderStyle::parse(&args[i + 1]).unwrap_or_else(|| {
+                        eprintln!("Error: unknown style '{}' (available: {})", args[i + 1], RenderStyle::NAMES.join(", "));
+                        std::process::exit(1);
+                    });
+                    cli.quick = true;
+                    i += 1;
+                }
+            }
+            "--clouds" => {
+                cli.clouds = true;
+                cli.quick = true;
+            }
+            "--snow" => {
+                let season = args.get(i + 1).and_then(|arg| arg.parse::<f32>().ok()).unwrap_or_else(|| {
+                    eprintln!("Error: --snow expects a season from 0 (summer) to 1 (winter)");
+                    std::process::exit(1);
+                });
+                cli.render.snow = Some(season.clamp(0.0, 1.0));
+                cli.quick = true;
+                i += 1;
+            }
+            "--dither" => {
+                cli.render.dither = true;
+                cli.quick = true;
+            }
+            "--supersample" => {
+                let samples = args.get(i + 1).and_then(|arg| arg.parse::<u32>().ok()).filter(|n| (1..=MAX_SUPERSAMPLE).contains(n));
+                cli.render.supersample = samples.unwrap_or_else(|| {
+                    eprintln!("Error: --supersample expects 1 to {} samples across each pixel", MAX_SUPERSAMPLE);
+                    std::process::exit(1);
+                });
+                cli.quick = true;
+                i += 1;
+            }
+            "--flow-arrows" => {
+                cli.render.flow_arrows = true;
+                cli.quick = true;
+            }
+            "--viewshed" => {
+                let viewpoint = args.get(i + 1).and_then(|arg| {
+                    let parts: Vec<&str> = arg.split(',').map(str::trim).collect();
+                    let height = match parts.get(2) {
+                        Some(text) => text.parse::<f64>().ok().filter(|h| *h >= 0.0)?,
+                        None => Viewpoint::TOWER,
+                    };
+                    match parts[..] {
+                        [x, y] | [x, y, _] => Some(Viewpoint { x: x.parse().ok()?, y: y.parse().ok()?, height }),
+                        _ => None,
+                    }
+                });
+                match viewpoint {
+                    Some(viewpoint) => {
+                        cli.render.viewshed = Some(viewpoint);
+                        cli.quick = true;
+                        i += 1;
+                    }
+                    None => {
+                        eprintln!("Error: --viewshed needs X,Y or X,Y,HEIGHT");
+                        std::process::exit(1);
+                    }
+                }
+            }
+            "--reveal" => {
+                let reveal = args.get(i + 1).and_then(|arg| {
+                    let parts: Vec<&str> = arg.split(',').map(str::trim).collect();
+                    let radius = match parts.get(2) {
+                        Some(text) => text.parse::<f32>().ok().filter(|r| *r >= 0.0)?,
+                        None => DEFAULT_REVEAL_RADIUS,
+                    };
+                    match parts[..] {
+                        [x, y] | [x, y, _] => Some(Reveal::Circle((x.parse().ok()?, y.parse().ok()?), radius)),
+                        _ => None,
+                    }
+                });
+                match reveal {
+                    Some(reveal) => {
+                        cli.player.reveals.push(reveal);
+                        cli.quick = true;
+                        i += 1;
+                    }
+                    None => {
+                        eprintln!("Error: --reveal needs X,Y or X,Y,RADIUS");
+                        std::process::exit(1);
+                    }
+                }
+            }
+            "--reveal-route" => {
+                let (Some(from), Some(to)) = (args.get(i + 1), args.get(i + 2)) else {
+                    eprintln!("Error: --reveal-route needs a start and a destination");
+                    std::process::exit(1);
+                };
+                cli.player.reveals.push(Reveal::Route(from.clone(), to.clone()));
+                cli.quick = true;
+                i += 2;
+            }
+            "--fog" => {
+                if let Some(path) = args.get(i + 1) {
+                    cli.player.fog = Some(PathBuf::from(path));
+                    cli.quick = true;
+                    i += 1;
+                }
+            }
+            "--fog-style" => {
+                if i + 1 < args.len() {
+                    cli.player.style = FogStyle::parse(&args[i + 1]).unwrap_or_else(|| {
+                        eprintln!("Error: unknown fog style '{}' (available: {})", args[i + 1], FogStyle::NAMES.join(", "));
+                        std::process::exit(1);
+                    });
+                    cli.quick = true;
+                    i += 1;
+                }
+            }
+            "--labels" => {
+                if i + 1 < args.len() {
+                    cli.render.labels = LabelOptions::parse(&args[i + 1]).unwrap_or_else(|| {
+                        eprintln!("Error: unknown label set '{}' (available: {})", args[i + 1], LabelOptions::NAMES.join(", "));
+                        std::process::exit(1);
+                    });
+                    cli.quick = true;
+                    i += 1;
+                }
+            }
+            "--max-city-labels" => {
+                if let Some(count) = args.get(i + 1).and_then(|arg| arg.parse::<usize>().ok()) {
+                    cli.render.labels.max_city_labels = Some(count);
+                    cli.quick = true;
+                    i += 1;
+                }
+            }
+            "--min-label-population" => {
+                if let Some(population) = args.get(i + 1).and_then(|arg| arg.parse::<u32>().ok()) {
+                    cli.render.labels.min_city_population_for_label = population;
+                    cli.quick = true;
+                    i += 1;
+                }
+            }
+            "--leader-population" => {
+                if let Some(population) = args.get(i + 1).and_then(|arg| arg.parse::<u32>().ok()) {
+                    cli.render.labels.leader_min_population = population;
+                    cli.quick = true;
+                    i += 1;
+                }
+            }
+            "--no-leader-lines" => {
+                cli.render.labels.leader_min_population = u32::MAX;
+                cli.quick = true;
+            }
+            "--leader-width" | "--leader-curve" => {
+                if let Some(value) = args.get(i + 1).and_then(|arg| arg.parse::<f32>().ok()) {
+                    if args[i] == "--leader-width" {
+                        cli.render.labels.leader_width = value.clamp(0.5, 6.0);
+                    } else {
+                        cli.render.labels.leader_curve = value.clamp(-1.0, 1.0);
+                    }
+                    cli.quick = true;
+                    i += 1;
+                }
+            }
+            "--name-plates" => {
+                if i + 1 < args.len() {
+                    cli.render.labels.plates = NamePlate::parse(&args[i + 1]).unwrap_or_else(|| {
+                        eprintln!("Error: unknown name plate '{}' (available: {})", args[i + 1], NamePlate::NAMES.join(", "));
+                        std::process::exit(1);
+                    });
+                    cli.quick = true;
+                    i += 1;
+                }
+            }
+            "--no-road-labels" => {
+                cli.render.labels.show_roads = false;
+                cli.quick = true;
+            }
+            "--no-bridge-labels" => {
+                cli.render.labels.show_bridges = false;
+                cli.quick = true;
+            }
+            "--label-density" => {
+                if let Some(density) = args.get(i + 1).and_then(|arg| arg.parse::<f32>().ok()) {
+                    cli.render.labels.geographic_label_density = density.clamp(0.0, 1.0);
+                    cli.quick = true;
+                    i += 1;
+                }
+            }
+            "--coast-rings" => {
+                if i + 1 < args.len() {
+                    if let Ok(value) = args[i + 1].parse::<u32>() {
+                        cli.render.coast_rings = value.min(12);
+                        cli.quick = true;
+                        i += 1;
+                    }
+                }
+            }
+            "--slope-tint" => {
+                if i + 1 < args.len() {
+                    let channels: Vec<u8> = args[i + 1].split(',').filter_map(|c| c.trim().parse().ok()).collect();
+                    let Ok(color) = <[u8; 3]>::try_from(channels) else {
+                        eprintln!("Error: --slope-tint expects R,G,B (0-255 each), not '{}'", args[i + 1]);
+                        std::process::exit(1);
+                    };
+                    cli.render.slope_tint = color;
+                    cli.quick = true;
+                    i += 1;
+                }
+            }
+            "--coastline" => {
+                if i + 1 < args.len() {
+                    let channels: Vec<u8> = args[i + 1].split(',').filter_map(|c| c.trim().parse().ok()).collect();
+                    let Ok(color) = <[u8; 3]>::try_from(channels) else {
+                        eprintln!("Error: --coastline expects R,G,B (0-255 each), not '{}'", args[i + 1]);
+                        std::process::exit(1);
+                    };
+                    cli.render.coastline = Some(color);
+                    cli.quick = true;
+                    i += 1;
+                }
+            }
+            "--svg" => {
+                cli.svg = true;
+                cli.quick = true;
+            }
+            "--split-layers" => {
+                cli.split_layers = true;
+                cli.quick = true;
+            }
+            "--stl" => {
+                cli.stl.get_or_insert_with(StlOptions::default);
+                cli.quick = true;
+            }
+            "--hex" => {
+                if i + 1 < args.len() {
+                    if let Ok(size) = args[i + 1].parse::<f32>() {
+                        cli.hex = Some(HexGrid::new(size.clamp(2.0, 200.0)));
+                        cli.quick = true;
+                        i += 1;
+                    }
+                }
+            }
+            "--editor" => {
+                if i + 1 < args.len() {
+                    let editor = Editor::parse(&args[i + 1]).unwrap_or_else(|| {
+                        eprintln!("Error: unknown editor '{}' (available: {})", args[i + 1], Editor::NAMES.join(", "));
+                        std::process::exit(1);
+                    });
+                    let size = cli.editor.map_or(editor.default_size(), |(_, size)| size);
+                    cli.editor = Some((editor, size));
+                    cli.quick = true;
+                    i += 1;
+                }
+            }
+            "--editor-size" => {
+                if i + 1 < args.len() {
+                    let size = args[i + 1]
+                        .split_once(['x', 'X'])
+                        .and_then(|(w, h)| Some((w.trim().parse::<u32>().ok()?, h.trim().parse::<u32>().ok()?)))
+                        .filter(|&(w, h)| (16..=16384).contains(&w) && (16..=16384).contains(&h))
+                        .unwrap_or_else(|| {
+                            eprintln!("Error: --editor-size expects WIDTHxHEIGHT in pixels (16-16384), not '{}'", args[i + 1]);
+                            std::process::exit(1);
+                        });
+                    let editor = cli.editor.map_or(Editor::Wonderdraft, |(editor, _)| editor);
+                    cli.editor = Some((editor, size));
+                    cli.quick = true;
+                    i += 1;
+                }
+            }
+            "--roll20" => {
+                cli.roll20.get_or_insert_with(Roll20Options::default);
+                cli.quick = true;
+            }
+            "--globe" => {
+                if i + 1 < args.len() {
+                    let projection = GlobeProjection::parse(&args[i + 1]).unwrap_or_else(|| {
+                        eprintln!("Error: unknown projection '{}' (available: {})", args[i + 1], GlobeProjection::NAMES.join(", "));
+                        std::process::exit(1);
+                    });
+                    let view = cli.globe.get_or_insert_with(|| GlobeView::new(projection));
+                    if view.projection != projection {
+                        *view = GlobeView { projection, center: projection.default_center(), ..*view };
+                    }
+                    cli.quick = true;
+                    i += 1;
+                }
+            }
+            "--globe-center" => {
+                let center = args.get(i + 1)
+                    .and_then(|arg| arg.split_once(','))
+                    .and_then(|(lon, lat)| Some((lon.trim().parse::<f32>().ok()?, lat.trim().parse::<f32>().ok()?)));
+                if let Some((lon, lat)) = center {
+                    cli.globe.get_or_insert_with(|| GlobeView::new(GlobeProjection::Orthographic)).center = (lon, lat.clamp(-90.0, 90.0));
+                    cli.quick = true;
+                    i += 1;
+                }
+            }
+            "--globe-size" => {
+                if let Some(size) = args.get(i + 1).and_then(|arg| arg.parse::<u32>().ok()) {
+                    cli.globe.get_or_insert_with(|| GlobeView::new(GlobeProjection::Orthographic)).size = Some(size.clamp(16, 16384));
+                    cli.quick = true;
+                    i += 1;
+                }
+            }
+            "--cell-px" | "--tiles-per-cell" => {
+                if i + 1 < args.len() {
+                    if let Ok(value) = args[i + 1].parse::<u32>() {
+                        let options = cli.roll20.get_or_insert_with(Roll20Options::default);
+                        match args[i].as_str() {
+                            "--cell-px" => options.cell_px = value.clamp(10, 500),
+                            _ => options.tiles_per_cell = value.clamp(1, 100),
+                        }
+                        cli.quick = true;
+                        i += 1;
+                    }
+                }
+            }
+            "--exaggeration" => {
+                if i + 1 < args.len() {
+                    if let Ok(value) = args[i + 1].parse::<f32>() {
+                        cli.stl.get_or_insert_with(StlOptions::default).exaggeration = value.clamp(0.1, 20.0);
+                        cli.quick = true;
+                        i += 1;
+                    }
+                }
+            }
+            "--json" => {
+                cli.save_json = true;
+                cli.quick = true;
+            }
+            "--climate" => {
+                if i + 1 < args.len() {
+                    let style = RasterStyle::parse(&args[i + 1]).unwrap_or_else(|| {
+                        eprintln!("Error: --climate expects gray or ramp, not '{}'", args[i + 1]);
+                        std::process::exit(1);
+                    });
+                    cli.climate = Some(style);
+                    cli.quick = true;
+                    i += 1;
+                }
+            }
+            "--names" => {
+                if i + 1 < args.len() {
+                    cli.names = NameLists::load(Path::new(&args[i + 1])).unwrap_or_else(|e| {
+                        eprintln!("Error: {}", e);
+                        std::process::exit(1);
+                    });
+                    cli.quick = true;
+                    i += 1;
+                }
+            }
+            "--mask" => {
+                if i + 1 < args.len() {
+                    cli.mask = Some(ShapeMask::load(Path::new(&args[i + 1])).unwrap_or_else(|e| {
+                        eprintln!("Error: {}", e);
+                        std::process::exit(1);
+                    }));
+                    cli.quick = true;
+                    i += 1;
+                }
+            }
+            "--crop" => {
+                let rect = args.get(i + 1).and_then(|arg| {
+                    let (corner, size) = arg.rsplit_once(',')?;
+                    let (x, y) = parse_point(corner)?;
+                    let (width, height) = size.split_once('x')?;
+                    Some(Region { x, y, width: width.trim().parse().ok()?, height: height.trim().parse().ok()? })
+                });
+                match rect {
+                    Some(rect) if rect.width > 0 && rect.height > 0 => {
+                        cli.crop = Some(rect);
+                        cli.quick = true;
+                        i += 1;
+                    }
+                    _ => {
+                        eprintln!("Error: --crop needs X,Y,WxH, e.g. 40,30,120x90");
+                        std::process::exit(1);
+                    }
+                }
+            }
+            "--weather" => {
+                if i + 1 < args.len() {
+                    if let Ok(days) = args[i + 1].parse::<u32>() {
+                        cli.weather_days = Some(days.clamp(1, MAX_WEATHER_DAYS));
+                        cli.quick = true;
+                        i += 1;
+                    }
+                }
+            }
+            "--keep-terrain" => {
+                if i + 1 < args.len() {
+                    cli.keep_terrain = Some(PathBuf::from(&args[i + 1]));
+                    cli.quick = true;
+                    i += 1;
+                }
+            }
+            "--reroll-cities" | "--reroll-roads" | "--reroll-labels" => {
+                let phase = match args[i].as_str() {
+                    "--reroll-cities" => GenerationPhase::Cities,
+                    "--reroll-roads" => GenerationPhase::Roads,
+                    _ => GenerationPhase::Labels,
+                };
+                // Rerolling a phase reruns everything after it anyway
+                cli.reroll_from = Some(cli.reroll_from.map_or(phase, |p| p.min(phase)));
+                cli.quick = true;
+            }
+            "--gpu" => {
+                cli.gpu = true;
+            }
+            "--verbose" | "-v" => {
+                cli.verbosity = Verbosity::Verbose;
+            }
+            "--quiet" | "-q" => {
+                cli.verbosity = Verbosity::Quiet;
+            }
+            "--config" | "--preset" | "--count" | "--columns" | "--gallery" | "--tag" | "--note" => {
+                // Applied above or read by the explore and gallery
+                // subcommands; just skip the value
+                i += 1;
+            }
+            "--help" => {
+                println!("Terrain Generator CLI");
+                println!("\nUsage: mapper-terrain-cli [OPTIONS]");
+                println!("       mapper-terrain-cli query <map.json> <question>  (see query --help)");
+                println!("       mapper-terrain-cli stats <map.json>             (see stats --help)");
+                println!("       mapper-terrain-cli validate <map.json>          (see validate --help)");
+                println!("       mapper-terrain-cli travel-times <map.json>      (see travel-times --help)");
+                println!("       mapper-terrain-cli economy <map.json>           (see economy --help)");
+                println!("       mapper-terrain-cli stitch <piece.json@X,Y>...   (see stitch --help)");
+                println!("       mapper-terrain-cli refine <map.json>            (see refine --help)");
+                println!("       mapper-terrain-cli explore [OPTIONS]            (see explore --help)");
+                println!("       mapper-terrain-cli reproduce <map.png> [OPTIONS] (see reproduce --help)");
+                println!("       mapper-terrain-cli gallery <command>            (see gallery --help)");
+                println!("\nOptions:");
+                println!("  --rivers <0.0-1.0>  Set river density (default: 0.5)");
+                println!("  --cities <0.0-1.0>  Set city density (default: 0.5)");
+                println!("  --land <0.0-1.0>    Set land percentage (default: 0.4)");
+                println!("  --edge-roads <n>    Highways leaving the map from border cities (0-{}, default: 0)", MAX_EDGE_ROADS);
+                println!("  --modern            Motorways, airports, container terminals and power lines");
+                println!("  --wrap              Join the map's east and west edges, as on a globe");
+                println!("  --symmetry <kind>   Terrain alike on every side: mirror (east and west),");
+                println!("                      rotate (a half turn) or quarters (default: none)");
+                println!("  --symmetric-cities  With --symmetry: cities and metro areas repeat too");
+                println!("  --size <WxH>        Map size in tiles (default: 320x240)");
+                println!("  --seed <u32>        Seed for reproducible maps (default: current time)");
+                println!("  --best-of <n>       Score the terrain of n seeds from --seed on (coastline, biomes,");
+                println!("                      harbors, rivers, landmasses) and finish the best one");
+                println!("  --output <file>     Output PNG filename (default: from --name-template)");
+                println!("  --name-template <t> Output file stem; placeholders {{seed}} {{land}} {{rivers}}");
+                println!("                      {{cities}} {{temp}} {{formation}} {{hash}} {{variant}}");
+                println!("                      (default: terrain_s{{seed}}_l{{land}}_r{{rivers}}_c{{cities}}{{variant}})");
+                println!("  --preset <name>     Start from a named world preset");
+                println!("                      ({})", preset_names(&cli.config).join(", "));
+                println!("  --config <file>     Config file (default: ~/.config/mapper/config.toml)");
+                println!("  --scale <n>         Pixels per tile, 1-{} (default: {})", MAX_SCALE, DEFAULT_SCALE);
+                println!("  --layers <list>     Layers to draw: terrain,rivers,roads,cities,labels (default: all);");
+                println!("                      territories adds each city's catchment area, tinted;");
+                println!("                      lights adds city lights, as seen at night, and clouds");
+                println!("                      clouds shaped by the seed;");
+                println!("                      without terrain the background is transparent");
+                println!("  --style <name>      Terrain coloring: atlas (default), satellite, pixel-art,");
+                println!("                      line-art (black and white, for printing) or night");
+                println!("  --clouds            With --style satellite: clouds shaped by the seed");
+                println!("  --snow <season>     Snow and ice by temperature, from 0 (summer) to 1 (winter):");
+                println!("                      snow down the mountains, lakes and rivers frozen over");
+                println!("  --dither            With --style pixel-art: dither between palette colors");
+                println!("  --supersample <n>   Render n x n samples per pixel, 2-{}, and average them:", MAX_SUPERSAMPLE);
+                println!("                      smooth coastlines, rivers and roads, n*n times slower");
+                println!("  --flow-arrows       Arrows along rivers pointing downstream, with their sources");
+                println!("                      and mouths marked");
+                println!("  --viewshed <X,Y[,H]> Shade what cannot be seen from tile X,Y with eyes H above");
+                println!("                      the ground (elevation units, default 0.005, a tower)");
+                println!("  --reveal <X,Y[,R]>  Player map: uncover the fog of war within R tiles of X,Y");
+                println!("                      (default: {}); saves <stem>_player.png and <stem>_fog.json", DEFAULT_REVEAL_RADIUS);
+                println!("  --reveal-route <FROM> <TO>  Player map: uncover the way on foot between two");
+                println!("                      cities or X,Y tiles");
+                println!("  --fog <fog.json>    Player map: start from a fog saved before");
+                println!("  --fog-style <name>  Player map: hidden (default) or desaturated");
+                println!("  --labels <set>      Which names to write: reference (all, default) or poster");
+                println!("                      (the largest cities and half the regions); the flags below");
+                println!("                      adjust it when they come after");
+                println!("  --max-city-labels <n>  Name at most n cities, the most populous");
+                println!("  --min-label-population <n>  Leave cities smaller than n unnamed");
+                println!("  --leader-population <n>  Cities of n people or more (default 20000) may be");
+                println!("                      named a little way off, with a leader line, when crowded;");
+                println!("                      smaller ones go unnamed instead");
+                println!("  --no-leader-lines   Name cities only beside them, with no leader lines");
+                println!("  --leader-width <px>, --leader-curve <share>  Leader line width (default 1.2)");
+                println!("                      and bow (default 0.15; 0 is straight)");
+                println!("  --name-plates <name>  Behind city names: none (default), rounded (a translucent");
+                println!("                      dark box) or banner (a parchment banner)");
+                println!("  --no-road-labels    Leave out highway names");
+                println!("  --no-bridge-labels  Leave out bridge names");
+                println!("  --label-density <0.0-1.0>  Share of ocean, mountain, forest, swamp and river");
+                println!("                      names written, the biggest first (default: 1)");
+                println!("  --relief <0.0-5.0>  Hillshade strength (default: 1; 0 is flat)");
+                println!("  --relief-detail <n> Fineness of the relief, 0.1-4 (default: 1); lower is");
+                println!("                      smoother, for small scales");
+                println!("  --slope-tint <rgb>  Rock color on steep slopes as R,G,B (default: 139,90,43)");
+                println!("  --slope-tint-strength <0.0-1.0>  Share of that color on the steepest slopes");
+                println!("                      (default: 0.1)");
+                println!("  --coast-rings <n>   Bands of paler water along the coasts, 0-12 (default: 0)");
+                println!("  --coast-ring-width <tiles>  Width of each band (default: 0.6)");
+                println!("  --coastline <rgb>   Draw every coast and lake shore as a line of this color");
+                println!("  --svg               Save as SVG instead of PNG (also chosen by an .svg --output)");
+                println!("  --split-layers      Also save each selected layer as <stem>_<layer>.png with a");
+                println!("                      transparent background, for compositing elsewhere");
+                println!("  --json              Also save the map data as JSON next to the PNG");
+                println!("  --hex <tiles>       Also save <stem>_hexes.png with a numbered hex grid of that");
+                println!("                      size and the hex-crawl sheet as <stem>_hexes.md and .csv");
+                println!("  --editor <name>     Also save a land mask, a river mask and symbol placements");
+                println!("                      for wonderdraft or inkarnate, at its default canvas size");
+                println!("  --editor-size <WxH> Canvas size for --editor in pixels (e.g. 3840x2160)");
+                println!("  --roll20            Also save <stem>_roll20.png sized to the Roll20 grid and");
+                println!("                      <stem>_roll20.json with the page size and city tokens");
+                println!("  --cell-px <n>       Pixels per Roll20 grid cell (default: 70; implies --roll20)");
+                println!("  --tiles-per-cell <n>  Map tiles across each grid cell (default: 8; implies --roll20)");
+                println!("  --globe <projection>  Also save <stem>_globe.png, the map as a world seen as a");
+                println!("                      globe: orthographic or polar (azimuthal, from the north pole)");
+                println!("  --globe-center <lon,lat>  Centre of the globe view in degrees (implies --globe)");
+                println!("  --globe-size <px>   Width of the globe view (default: the map's height; implies --globe)");
+                println!("  --stl               Also save the relief as a closed STL solid for 3D printing");
+                println!("                      (half a millimetre per tile on a 2 mm base)");
+                println!("  --exaggeration <n>  Vertical exaggeration of the STL relief, 0.1-20 (default: 1;");
+                println!("                      implies --stl)");
+                println!("  --climate <style>   Also save moisture, temperature and precipitation as PNGs");
+                println!("                      (gray or ramp) and raw little-endian f32 grids");
+                println!("  --names <file>      Name cities, rivers, mountains, oceans, forests and swamps");
+                println!("                      from the word lists in a TOML file");
+                println!("  --mask <file>       Keep land within a shape: an image (light or opaque pixels");
+                println!("                      are land) or a JSON polygon of [x, y] corners from 0 to 1");
+                println!("  --weather <days>    Also save daily weather for each city and region as");
+                println!("                      <stem>_weather.csv, from the first day of the year");
+                println!("  --keep-terrain <f>  Load a map saved with --json and keep its terrain,");
+                println!("                      climate and rivers; --seed then seeds the rerolled phases");
+                println!("  --reroll-cities     With --keep-terrain: new cities, roads and labels (default)");
+                println!("  --reroll-roads      With --keep-terrain: keep cities, new roads and labels");
+                println!("  --reroll-labels     With --keep-terrain: only new labels");
+                println!("  --crop <X,Y,WxH>    Keep only the W by H tiles from X,Y on: terrain, the cities");
+                println!("                      in it, clipped rivers and roads, and labels moved inside");
+                println!("  --gpu               Compute the noise fields on the GPU (gpu feature builds;");
+                println!("                      falls back to the CPU without a usable GPU)");
+                println!("  -v, --verbose       Show per-phase timings and a map summary");
+                println!("  -q, --quiet         Only print errors (no progress bars)");
+                println!("  --help              Show this help message");
+                println!("\nAny option except --config, --gpu, --verbose and --quiet switches to");
+                println!("non-interactive quick mode.");
+                println!("Flags override values from the config file.");
+                println!("\nExample:");
+                println!("  mapper-terrain-cli --rivers 0.8 --cities 0.3 --land 0.6 --seed 42 --output map.png");
+                println!("  mapper-terrain-cli --preset archipelago --cities 0.8");
+                println!("  mapper-terrain-cli --seed 42 --layers rivers,roads --svg");
+                println!("  mapper-terrain-cli --seed 42 --json");
+                println!("  mapper-terrain-cli --keep-terrain terrain_s42_l40_r50_c50.json --reroll-cities --seed 7");
+                std::process::exit(0);
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    if cli.reroll_from.is_some() && cli.keep_terrain.is_none() {
+        eprintln!("Error: --reroll-cities/--reroll-roads/--reroll-labels need --keep-terrain <map.json>");
+        std::process::exit(1);
+    }
+
+    cli
+}
+
+/// Pixels per tile of saved maps, unless `--scale` says otherwise
+const DEFAULT_SCALE: u32 = 5;
+const MAX_SCALE: u32 = 40;
+/// Most candidate seeds `--best-of` tries
+const MAX_BEST_OF: u32 = 1000;
+/// Most days of weather `--weather` writes: ten years
+const MAX_WEATHER_DAYS: u32 = 3650;
+
+struct CliArgs {
+    settings: GenerationSettings,
+    seed: Option<u32>,
+    output: Option<PathBuf>,
+    quick: bool,
+    save_json: bool,
+    /// Also save the climate rasters, in this style
+    climate: Option<RasterStyle>,
+    /// Also save this many days of weather as CSV
+    weather_days: Option<u32>,
+    /// Words to name places from; empty for the built-in names
+    names: NameLists,
+    /// The shape land is kept within, if any
+    mask: Option<ShapeMask>,
+    layers: Layers,
+    /// Pixels per tile
+    scale: u32,
+    /// Relief shading and slope tint
+    render: RenderOptions,
+    /// Clouds over a satellite render, seeded by the map's seed
+    clouds: bool,
+    /// Also save a player map under the fog of war
+    player: PlayerMap,
+    svg: bool,
+    /// Also save each layer as its own transparent PNG
+    split_layers: bool,
+    /// Also save the relief as an STL model
+    stl: Option<StlOptions>,
+    /// Also save a Roll20 page image and its tokens
+    roll20: Option<Roll20Options>,
+    /// Also save the map reprojected as a globe view
+    globe: Option<GlobeView>,
+    /// Also save a hex-crawl map and sheet on this grid
+    hex: Option<HexGrid>,
+    /// Also save masks and placements for a map editor, at this canvas size
+    editor: Option<(Editor, (u32, u32))>,
+    /// Candidate seeds to score, keeping the best (1 generates `seed` as is)
+    best_of: u32,
+    keep_terrain: Option<PathBuf>,
+    reroll_from: Option<GenerationPhase>,
+    /// Keep only this rectangle of the map
+    crop: Option<Region>,
+    verbosity: Verbosity,
+    gpu: bool,
+    config: Config,
+}
+
+impl CliArgs {
+    /// The render options for the map generated from `seed`.
+    fn render_options(&self, seed: u32) -> RenderOptions {
+        RenderOptions {
+            clouds: self.clouds.then_some(seed),
+            ..self.render
+        }
+    }
+
+    /// Whether a map generated with these options is the one its seed and
+    /// settings alone make again: not named from word lists, shaped by a
+    /// mask or computed on the GPU, whose noise only matches the CPU's to
+    /// within rounding.
+    fn reproducible(&self) -> bool {
+        self.names.is_empty() && self.mask.is_none() && !self.gpu
+    }
+}
+
+const QUERY_USAGE: &str = "Usage: mapper-terrain-cli query <map.json> <question>
+
+Questions:
+  nearest-city X,Y       Closest city to a tile
+  biome-at X,Y           Terrain at a tile
+  inspect X,Y            Everything on a tile: terrain, nearest city, river, roads, bridge
+  route FROM TO [BY]     Quickest route; FROM and TO are city names or X,Y,
+                         BY is foot (default), horse, cart (roads only) or
+                         boat (water only, from and to a port)
+  road-route FROM TO     Shortest way by road between two named cities
+  viewshed X,Y [HEIGHT]  How much of the map can be seen from a tile, with eyes
+                         HEIGHT above the ground (default 0.005, a tower)
+  profile WHAT OUT.png   Elevation cross-section chart; WHAT is road NAME,
+                         river N (counted from 0) or X,Y X,Y ... joined by
+                         straight lines
+
+Maps are saved with --json. Coordinates are tile positions.";
+
+fn parse_point(text: &str) -> Option<(usize, usize)> {
+    let (x, y) = text.split_once(',')?;
+    Some((x.trim().parse().ok()?, y.trim().parse().ok()?))
+}
+
+/// Answer a question about a saved map (`query` subcommand).
+fn run_query(args: &[String]) -> Result<(), String> {
+    if args.iter().any(|a| a == "--help") {
+        println!("{}", QUERY_USAGE);
+        return Ok(());
+    }
+    if args.len() < 2 {
+        return Err(format!("missing arguments\n\n{}", QUERY_USAGE));
+    }
+    let path = Path::new(&args[0]);
+    let map = TerrainMap::load_json(path)
+        .map_err(|e| format!("cannot load map {}: {}", path.display(), e))?;
+    let point_arg = |i: usize| -> Result<(usize, usize), String> {
+        let text = args.get(i).ok_or("missing X,Y coordinates")?;
+        let point = parse_point(text).ok_or_else(|| format!("expected X,Y but got '{}'", text))?;
+        if map.point_at(point.0, point.1).is_none() {
+            return Err(format!("{},{} is outside the {}x{} map", point.0, point.1, map.width, map.height));
+        }
+        Ok(point)
+    };
+    // Route endpoints: a city name, or a tile
+    let place_arg = |i: usize| -> Result<((usize, usize), String), String> {
+        let text = args.get(i).ok_or("route needs a start and a destination")?;
+        if let Some(city) = map.find_city(text) {
+            return Ok(((city.x, city.y), city.name.clone()));
+        }
+        if parse_point(text).is_some() {
+            return point_arg(i).map(|p| (p, text.clone()));
+        }
+        Err(format!("no city called '{}'", text))
+    };
+
+    match args[1].as_str() {
+        "nearest-city" => {
+            let (x, y) = point_arg(2)?;
+            let (city, distance) = map.nearest_city(x, y).ok_or("the map has no cities")?;
+            println!("{} (population {}) at {},{} - {:.1} tiles away",
+                     city.name, city.population, city.x, city.y, distance);
+        }
+        "biome-at" => {
+            let (x, y) = point_arg(2)?;
+            if let Some(point) = map.point_at(x, y) {
+                println!("{:?} at {},{} (elevation {:.2}, moisture {:.2}, temperature {:.2})",
+                         point.biome, x, y, point.elevation, point.moisture, point.temperature);
+            }
+        }
+        "inspect" => {
+            let (x, y) = point_arg(2)?;
+            if let Some(info) = map.inspect(x, y) {
+                println!("{}", info);
+            }
+        }
+        "route" => {
+            let (from, from_name) = place_arg(2)?;
+            let (to, to_name) = place_arg(3)?;
+            let profile = match args.get(4) {
+                None => TravelProfile::OnFoot,
+                Some(text) => TravelProfile::from_name(text).ok_or_else(|| {
+                    let names: Vec<&str> = TravelProfile::ALL.iter().map(|p| p.name()).collect();
+                    format!("unknown way to travel '{}' (expected one of {})", text, names.join(", "))
+                })?,
+            };
+            let route = map
+                .route(from, to, profile)
+                .ok_or_else(|| format!("no route by {} from {} to {}", profile.name(), from_name, to_name))?;
+            let road_share = if route.distance > 0.0 { route.road_distance / route.distance * 100.0 } else { 0.0 };
+            println!("{} -> {} by {}: {:.1} tiles, {:.1} on roads ({:.0}%), {} river crossing{}, about {:.1} hours",
+                     from_name, to_name, profile.name(), route.distance, route.road_distance, road_share,
+                     route.river_crossings, if route.river_crossings == 1 { "" } else { "s" }, route.hours);
+        }
+        "road-route" => {
+            let city_arg = |i: usize| -> Result<usize, String> {
+                let text = args.get(i).ok_or("road-route needs two city names")?;
+                map.cities.iter().position(|c| c.name.eq_ignore_ascii_case(text.trim()))
+                    .ok_or_else(|| format!("no city called '{}'", text))
+            };
+            let (from, to) = (city_arg(2)?, city_arg(3)?);
+            let network = map.road_network();
+            let (from_name, to_name) = (&map.cities[from].name, &map.cities[to].name);
+            let route = network
+                .shortest_route(from, to)
+                .ok_or_else(|| format!("no road joins {} and {}", from_name, to_name))?;
+            let mut roads: Vec<&str> = Vec::new();
+            for &edge in &route.edges {
+                let name = map.roads[network.edges[edge].road].name.as_str();
+                if roads.last() != Some(&name) {
+                    roads.push(name);
+                }
+            }
+            let bridges = route.nodes.iter()
+                .filter(|&&node| matches!(network.nodes[node].kind, NodeKind::Bridge(_)))
+                .count();
+            println!("{} -> {}: {:.1} tiles by road, {} bridge{}",
+                     from_name, to_name, route.length, bridges, if bridges == 1 { "" } else { "s" });
+            if !roads.is_empty() {
+                println!("Via {}", roads.join(", "));
+            }
+        }
+        "viewshed" => {
+            let (x, y) = point_arg(2)?;
+            let height = match args.get(3) {
+                None => Viewpoint::TOWER,
+                Some(text) => text.parse::<f64>().ok().filter(|h| *h >= 0.0)
+                    .ok_or_else(|| format!("expected an eye height but got '{}'", text))?,
+            };
+            let visible = map.viewshed(x, y, height);
+            let land = visible.iter().filter(|&&(vx, vy)| !map.is_water(vx, vy)).count();
+            let cities: Vec<&str> = map.cities.iter()
+                .filter(|c| (c.x, c.y) != (x, y) && visible.contains(&(c.x, c.y)))
+                .map(|c| c.name.as_str())
+                .collect();
+            println!("{} tiles visible from {},{} ({:.1}% of the map), {} of them land",
+                     visible.len(), x, y, visible.len() as f64 / (map.width * map.height) as f64 * 100.0, land);
+            if !cities.is_empty() {
+                println!("Cities in sight: {}", cities.join(", "));
+            }
+        }
+        "profile" => {
+            let out = args.last().filter(|_| args.len() > 3).ok_or("profile needs a path and an output file")?;
+            let what = &args[2..args.len() - 1];
+            let (path, name) = match what.first().map(String::as_str) {
+                Some("road") => {
+                    let name = what[1..].join(" ");
+                    let road = map.roads.iter().find(|r| r.name.eq_ignore_ascii_case(name.trim()))
+                        .ok_or_else(|| format!("no road called '{}'", name))?;
+                    (road.path.clone(), road.name.clone())
+                }
+                Some("river") => {
+                    let text = what.get(1).ok_or("river needs a number")?;
+                    let river = text.parse::<usize>().ok().and_then(|i| map.rivers.get(i))
+                        .ok_or_else(|| format!("no river {} (the map has {})", text, map.rivers.len()))?;
+                    (river.clone(), format!("River {}", text))
+                }
+                _ => {
+                    let points = (2..args.len() - 1).map(point_arg).collect::<Result<Vec<_>, _>>()?;
+                    if points.len() < 2 {
+                        return Err("a profile needs at least two X,Y points".to_string());
+                    }
+                    (straight_path(&points), what.join(" to "))
+                }
+            };
+            let profile = map.elevation_profile(&path);
+            let length = path_distances(&path, map.width, map.wrap_x).last().copied().unwrap_or(0.0);
+            let climb: f64 = profile.windows(2).map(|w| (w[1] - w[0]).max(0.0)).sum();
+            let descent: f64 = profile.windows(2).map(|w| (w[0] - w[1]).max(0.0)).sum();
+            let highest = profile.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+            let fonts = FontChain::bundled().map_err(|e| e.to_string())?;
+            ProfileChart::default()
+                .render(&map, &path, &fonts)
+                .save(out)
+                .map_err(|e| format!("cannot write {}: {}", out, e))?;
+            println!("{}: {:.1} tiles, climbing {:.2} and descending {:.2}, highest point {:.2}",
+                     name, length, climb, descent, highest);
+            println!("Saved chart to {}", out);
+        }
+        other => return Err(format!("unknown question '{}'\n\n{}", other, QUERY_USAGE)),
+    }
+    Ok(())
+}
+
+const STATS_USAGE: &str = "Usage: mapper-terrain-cli stats <map.json>
+
+Prints the biome shares, an elevation histogram, city populations by rank
+with the Zipf exponent they fit, road lengths by type, and the map's score
+(as used by --best-of).
+
+Maps are saved with --json.";
+
+/// Print summary statistics of a saved map (`stats` subcommand).
+fn run_stats(args: &[String]) -> Result<(), String> {
+    if args.iter().any(|a| a == "--help") {
+        println!("{}", STATS_USAGE);
+        return Ok(());
+    }
+    let path = Path::new(args.first().ok_or_else(|| format!("missing map file\n\n{}", STATS_USAGE))?);
+    let map = TerrainMap::load_json(path)
+        .map_err(|e| format!("cannot load map {}: {}", path.display(), e))?;
+    println!("{}", map.stats());
+    println!("\nScore: {}", map.score());
+    Ok(())
+}
+
+const VALIDATE_USAGE: &str = "Usage: mapper-terrain-cli validate <map.json> [--json]
+
+Checks a saved map for problems: roads through deep ocean, rivers running
+uphill, bridges off the rivers, labels off the map, cities sharing a tile
+(errors), and cities no road reaches from another city (warnings, common on
+islands). Exits with status 1 if there are errors.
+
+Options:
+  --json   Print the report as JSON";
+
+/// Check a saved map for consistency problems (`validate` subcommand).
+fn run_validate(args: &[String]) -> Result<(), String> {
+    if args.iter().any(|a| a == "--help") {
+        println!("{}", VALIDATE_USAGE);
+        return Ok(());
+    }
+    let path = Path::new(args.first().ok_or_else(|| format!("missing map file\n\n{}", VALIDATE_USAGE))?);
+    let map = TerrainMap::load_json(path)
+        .map_err(|e| format!("cannot load map {}: {}", path.display(), e))?;
+    let report = map.validate();
+    if args.iter().any(|a| a == "--json") {
+        println!("{}", serde_json::to_string_pretty(&report).map_err(|e| e.to_string())?);
+    } else {
+        print!("{}", report.display(&map));
+    }
+    if !report.is_valid() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+const TRAVEL_USAGE: &str = "Usage: mapper-terrain-cli travel-times <map.json> [OPTIONS]
+
+Prints the travel time in hours between every pair of cities as CSV. Routes
+follow roads where they help, cross open country at speeds set by the
+terrain, and take ferries or ships over water.
+
+Options:
+  --json              Print JSON instead of CSV (unreachable pairs are null)
+  --km-per-tile <n>   Map scale (default: 5)
+  --output <file>     Write to a file instead of standard output
+
+Maps are saved with --json.";
+
+/// Export the city-to-city travel-time matrix of a saved map
+/// (`travel-times` subcommand).
+fn run_travel_times(args: &[String]) -> Result<(), String> {
+    if args.iter().any(|a| a == "--help") {
+        println!("{}", TRAVEL_USAGE);
+        return Ok(());
+    }
+    let path = Path::new(args.first().ok_or_else(|| format!("missing map file\n\n{}", TRAVEL_USAGE))?);
+    let value_of = |flag: &str| args.iter().position(|a| a == flag).map(|i| args.get(i + 1));
+    let km_per_tile = match value_of("--km-per-tile") {
+        None => DEFAULT_KM_PER_TILE,
+        Some(Some(text)) => match text.parse::<f64>() {
+            Ok(km) if km > 0.0 && km.is_finite() => km,
+            _ => return Err(format!("--km-per-tile expects a positive number but got '{}'", text)),
+        },
+        Some(None) => return Err("--km-per-tile needs a value".to_string()),
+    };
+    let output = match value_of("--output") {
+        None => None,
+        Some(Some(file)) => Some(PathBuf::from(file)),
+        Some(None) => return Err("--output needs a filename".to_string()),
+    };
+
+    let map = TerrainMap::load_json(path)
+        .map_err(|e| format!("cannot load map {}: {}", path.display(), e))?;
+    let times = map.travel_times(&TravelSpeeds::default(), km_per_tile);
+    let text = if args.iter().any(|a| a == "--json") {
+        serde_json::to_string_pretty(&times).map_err(|e| e.to_string())?
+    } else {
+        times.to_csv()
+    };
+    match output {
+        Some(file) => std::fs::write(&file, text)
+            .map_err(|e| format!("cannot write {}: {}", file.display(), e))?,
+        None => print!("{}", text),
+    }
+    Ok(())
+}
+
+const ECONOMY_USAGE: &str = "Usage: mapper-terrain-cli economy <map.json> [OPTIONS]
+
+Prints what each city produces and needs in a year as CSV: grain, livestock,
+fish, timber, stone, ore and salt, from the land around it and the water its
+boats reach. Needs follow population, and each surplus is traded by road to
+the nearest cities short of it.
+
+Options:
+  --flows             Print the trade flows (good, from, to, amount, road
+                      distance) instead of the cities
+  --json              Print cities and flows together as JSON
+  --output <file>     Write to a file instead of standard output
+
+Maps are saved with --json.";
+
+/// Export the per-city economy and trade flows of a saved map
+/// (`economy` subcommand).
+fn run_economy(args: &[String]) -> Result<(), String> {
+    if args.iter().any(|a| a == "--help") {
+        println!("{}", ECONOMY_USAGE);
+        return Ok(());
+    }
+    let path = Path::new(args.first().ok_or_else(|| format!("missing map file\n\n{}", ECONOMY_USAGE))?);
+    let output = match args.iter().position(|a| a == "--output").map(|i| args.get(i + 1)) {
+        None => None,
+        Some(Some(file)) => Some(PathBuf::from(file)),
+        Some(None) => return Err("--output needs a filename".to_string()),
+    };
+
+    let map = TerrainMap::load_json(path)
+        .map_err(|e| format!("cannot load map {}: {}", path.display(), e))?;
+    let economy = map.economy();
+    let text = if args.iter().any(|a| a == "--json") {
+        serde_json::to_string_pretty(&economy).map_err(|e| e.to_string())?
+    } else if args.iter().any(|a| a == "--flows") {
+        economy.flows_csv()
+    } else {
+        economy.cities_csv()
+    };
+    match output {
+        Some(file) => std::fs::write(&file, text)
+            .map_err(|e| format!("cannot write {}: {}", file.display(), e))?,
+        None => print!("{}", text),
+    }
+    Ok(())
+}
+
+const STITCH_USAGE: &str = "Usage: mapper-terrain-cli stitch <piece.json@X,Y>... --output <world.json> [OPTIONS]
+
+Assembles saved maps into one world, each piece with its top-left corner at
+tile X,Y; later pieces cover earlier ones where they overlap and open sea
+fills the gaps. Coastlines and climate are blended across the seams, rivers
+that ran off a piece carry on downhill, and roads that ran off it are joined
+to the nearest road leaving the piece next door.
+
+Options:
+  --output <file>   World map to save as JSON (required)
+  --png <file>      Also render the world to a PNG or SVG
+  --scale <n>       Pixels per tile of the render (default: 5)
+
+Example:
+  mapper-terrain-cli stitch west.json@0,0 east.json@200,0 --output world.json --png world.png";
+
+/// Assemble saved maps into one world (`stitch` subcommand).
+fn run_stitch(args: &[String]) -> Result<(), String> {
+    if args.iter().any(|a| a == "--help") {
+        println!("{}", STITCH_USAGE);
+        return Ok(());
+    }
+    let value_of = |flag: &str| args.iter().position(|a| a == flag).map(|i| args.get(i + 1));
+    let output = match value_of("--output") {
+        Some(Some(file)) => PathBuf::from(file),
+        Some(None) => return Err("--output needs a filename".to_string()),
+        None => return Err(format!("missing --output\n\n{}", STITCH_USAGE)),
+    };
+    let png = match value_of("--png") {
+        None => None,
+        Some(Some(file)) => Some(PathBuf::from(file)),
+        Some(None) => return Err("--png needs a filename".to_string()),
+    };
+    let scale = match value_of("--scale") {
+        None => 5,
+        Some(Some(text)) => match text.parse::<u32>() {
+            Ok(scale) if scale > 0 => scale,
+            _ => return Err(format!("--scale expects a positive whole number but got '{}'", text)),
+        },
+        Some(None) => return Err("--scale needs a value".to_string()),
+    };
+
+    let mut pieces = Vec::new();
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        if arg.starts_with("--") {
+            rest.next();
+            continue;
+        }
+        let (file, offset) = arg
+            .rsplit_once('@')
+            .and_then(|(file, at)| Some((file, parse_point(at)?)))
+            .ok_or_else(|| format!("expected <piece.json>@X,Y but got '{}'", arg))?;
+        let map = TerrainMap::load_json(Path::new(file)).map_err(|e| format!("cannot load map {}: {}", file, e))?;
+        pieces.push((offset, map));
+    }
+    if pieces.is_empty() {
+        return Err(format!("no maps to stitch\n\n{}", STITCH_USAGE));
+    }
+
+    let world = TerrainMap::stitch(&pieces);
+    world
+        .save_json(&output)
+        .map_err(|e| format!("cannot write {}: {}", output.display(), e))?;
+    println!("Stitched {} maps into a {}x{} world: {}", pieces.len(), world.width, world.height, output.display());
+    if let Some(png) = png {
+        save_map(&world, &png, scale, Layers::all(), &RenderOptions::default(), None, Verbosity::Normal)
+            .map_err(|e| format!("cannot write {}: {}", png.display(), e))?;
+        println!("Saved {}", png.display());
+    }
+    Ok(())
+}
+
+const REFINE_USAGE: &str = "Usage: mapper-terrain-cli refine <map.json> --output <fine.json> [OPTIONS]
+
+Splits every tile of a saved map into FACTOR by FACTOR tiles, adding detail
+from the map's seed to the elevation and coastlines while cities, roads,
+rivers and labels stay where they were. Preview cheaply at a low resolution,
+then finalize the map you like at a high one.
+
+Options:
+  --factor <n>      Tiles each tile becomes along each side (default: 4)
+  --output <file>   Refined map to save as JSON (required)
+  --png <file>      Also render the refined map to a PNG or SVG
+  --scale <n>       Pixels per tile of the render (default: 2)
+
+Example:
+  mapper-terrain-cli --seed 42 --size 160x120 --json
+  mapper-terrain-cli refine terrain_s42_l40_r50_c50.json --factor 4 --output fine.json --png fine.png";
+
+/// Refine a saved map to a higher resolution (`refine` subcommand).
+fn run_refine(args: &[String]) -> Result<(), String> {
+    if args.iter().any(|a| a == "--help") {
+        println!("{}", REFINE_USAGE);
+        return Ok(());
+    }
+    let path = Path::new(args.first().filter(|a| !a.starts_with("--")).ok_or_else(|| format!("missing map file\n\n{}", REFINE_USAGE))?);
+    let value_of = |flag: &str| args.iter().position(|a| a == flag).map(|i| args.get(i + 1));
+    let whole_number = |flag: &str, default: usize| match value_of(flag) {
+        None => Ok(default),
+        Some(Some(text)) => match text.parse::<usize>() {
+            Ok(n) if n > 0 => Ok(n),
+            _ => Err(format!("{} expects a positive whole number but got '{}'", flag, text)),
+        },
+        Some(None) => Err(format!("{} needs a value", flag)),
+    };
+    let factor = whole_number("--factor", 4)?;
+    let scale = whole_number("--scale", 2)? as u32;
+    let output = match value_of("--output") {
+        Some(Some(file)) => PathBuf::from(file),
+        Some(None) => return Err("--output needs a filename".to_string()),
+        None => return Err(format!("missing --output\n\n{}", REFINE_USAGE)),
+    };
+    let png = match value_of("--png") {
+        None => None,
+        Some(Some(file)) => Some(PathBuf::from(file)),
+        Some(None) => return Err("--png needs a filename".to_string()),
+    };
+
+    let map = TerrainMap::load_json(path)
+        .map_err(|e| format!("cannot load map {}: {}", path.display(), e))?;
+    let fine = TerrainGenerator::new(map.seed).refine(&map, factor);
+    fine.save_json(&output)
+        .map_err(|e| format!("cannot write {}: {}", output.display(), e))?;
+    println!("Refined {}x{} to {}x{} tiles: {}", map.width, map.height, fine.width, fine.height, output.display());
+    if let Some(png) = png {
+        save_map(&fine, &png, scale, Layers::all(), &RenderOptions::default(), None, Verbosity::Normal)
+            .map_err(|e| format!("cannot write {}: {}", png.display(), e))?;
+        println!("Saved {}", png.display());
+    }
+    Ok(())
+}
+
+const EXPLORE_USAGE: &str = "Usage: mapper-terrain-cli explore [OPTIONS]
+
+Renders the terrain of consecutive seeds as thumbnails on one contact sheet,
+each labelled with its seed. Generate the one you like at full size with
+--seed.
+
+Options:
+  --count <n>         Number of seeds to show (default: 36)
+  --columns <n>       Thumbnails per row (default: square-ish grid)
+  --seed <u32>        First seed (default: current time)
+  --output <file>     Contact sheet filename
+                      (default: explore_s<first>-<last>_l<land>_r<rivers>_c<cities>.png)
+
+The --rivers, --cities, --land, --preset, --config and --quiet options work
+as for a normal run.";
+
+/// Tiles per thumbnail; the same size as a full map, so a thumbnail shows
+/// exactly the terrain its seed generates.
+const THUMB_WIDTH: usize = 320;
+const THUMB_HEIGHT: usize = 240;
+/// Height of the seed caption under each thumbnail, in pixels
+const CAPTION_HEIGHT: u32 = 26;
+
+/// Render a contact sheet of thumbnails for consecutive seeds (`explore`
+/// subcommand).
+fn run_explore(args: &[String]) -> Result<(), String> {
+    if args.iter().any(|a| a == "--help") {
+        println!("{}", EXPLORE_USAGE);
+        return Ok(());
+    }
+    let number_arg = |flag: &str| -> Result<Option<u32>, String> {
+        match args.iter().position(|a| a == flag).map(|i| args.get(i + 1)) {
+            None => Ok(None),
+            Some(Some(text)) => match text.parse::<u32>() {
+                Ok(n) if n > 0 => Ok(Some(n)),
+                _ => Err(format!("{} expects a positive number but got '{}'", flag, text)),
+            },
+            Some(None) => Err(format!("{} needs a value", flag)),
+        }
+    };
+    let count = number_arg("--count")?.unwrap_or(36);
+    let columns = number_arg("--columns")?
+        .unwrap_or_else(|| (count as f64).sqrt().ceil() as u32)
+        .min(count);
+    let rows = count.div_ceil(columns);
+
+    let cli = parse_args(args);
+    let settings = cli.settings;
+    let verbosity = cli.verbosity;
+    let first = cli.seed.unwrap_or_else(time_seed);
+    let last = first.wrapping_add(count - 1);
+    let filename = cli.output.clone().unwrap_or_else(|| {
+        let template = format!("explore_s{{seed}}-{}_l{{land}}_r{{rivers}}_c{{cities}}{{variant}}", last);
+        cli.config.output_path(&format!("{}.png", expand_name_template(&template, first, &settings)))
+    });
+
+    let (cell_width, cell_height) = (THUMB_WIDTH as u32, THUMB_HEIGHT as u32 + CAPTION_HEIGHT);
+    let mut sheet = image::RgbImage::from_pixel(columns * cell_width, rows * cell_height, Rgb([24, 24, 28]));
+    let fonts = FontChain::bundled().map_err(|e| e.to_string())?;
+    let thumbnail_layers = Layers { terrain: true, relief: true, rivers: true, ..Layers::none() };
+
+    let bar = phase_bar("explore", verbosity);
+    bar.set_length(count as u64);
+    for n in 0..count {
+        let seed = first.wrapping_add(n);
+        bar.set_message(format!("seed {}", seed));
+        // Terrain only: settlements would not show at thumbnail size and
+        // are by far the slowest part of generation
+        let map = TerrainGenerator::new_with_settings(seed, settings).generate_terrain(THUMB_WIDTH, THUMB_HEIGHT);
+        let thumbnail = image::DynamicImage::ImageRgba8(TerrainRenderer::render_layers_to_image(&map, 1, thumbnail_layers, &cli.render)).to_rgb8();
+
+        let x = (n % columns) * cell_width;
+        let y = (n / columns) * cell_height;
+        image::imageops::replace(&mut sheet, &thumbnail, x as i64, y as i64);
+        draw_text(&mut sheet, Rgb([230, 230, 230]), x as i32 + 6, (y + cell_height - CAPTION_HEIGHT) as i32 + 4,
+              Scale::uniform(18.0), &fonts, &format!("seed {}", seed));
+        bar.inc(1);
+    }
+    bar.set_message(String::new());
+    finish_bar(&bar, verbosity);
+
+    if let Some(dir) = filename.parent().filter(|d| !d.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir).map_err(|e| format!("cannot create {}: {}", dir.display(), e))?;
+    }
+    sheet.save(&filename).map_err(|e| format!("cannot save {}: {}", filename.display(), e))?;
+    if verbosity > Verbosity::Quiet {
+        println!("Contact sheet for seeds {}-{} saved as: {}", first, last, filename.display());
+        println!("Render one at full size with: mapper-terrain-cli --seed <seed>");
+    }
+    Ok(())
+}
+
+const REPRODUCE_USAGE: &str = "Usage: mapper-terrain-cli reproduce <map.png> [OPTIONS]
+
+Generate again the world a PNG map was made from, using the seed and
+settings the CLI embedded in it, and render it with new options.
+
+Options:
+  --scale <n>       Pixels per tile (default: 5)
+  --output <file>   Output file (default: <map>_reproduced.png)
+  --style, --layers, --relief, --svg, --json, ...
+                    Any rendering or output option of a new map (see --help);
+                    generation options are taken from the PNG
+
+Example:
+  mapper-terrain-cli reproduce terrain_s42_l40_r50_c50.png --scale 10 --style line-art";
+
+/// Regenerate the map embedded in a PNG and render it again (`reproduce`
+/// subcommand).
+fn run_reproduce(args: &[String]) -> Result<(), String> {
+    if args.iter().any(|a| a == "--help") {
+        println!("{}", REPRODUCE_USAGE);
+        return Ok(());
+    }
+    let source = args
+        .get(1)
+        .filter(|a| !a.starts_with("--"))
+        .map(Path::new)
+        .ok_or_else(|| format!("missing map file\n\n{}", REPRODUCE_USAGE))?;
+    let metadata = MapMetadata::read(source)
+        .map_err(|e| format!("cannot read {}: {}", source.display(), e))?
+        .ok_or_else(|| format!("{} has no seed or settings in it (only maps saved by mapper-terrain-cli do)", source.display()))?;
+
+    let cli = parse_args(args);
+    let MapMetadata { seed, settings } = metadata;
+    if cli.verbosity > Verbosity::Quiet {
+        println!("Reproducing seed {} at {}x{} tiles from {}", seed, settings.map_width, settings.map_height, source.display());
+    }
+    let map = generate_map(seed, settings, &cli.names, cli.mask.as_ref(), cli.verbosity, cli.gpu);
+    let mut filename = cli.output.clone().unwrap_or_else(|| {
+        let stem = source.file_stem().unwrap_or_default().to_string_lossy();
+        source.with_file_name(format!("{}_reproduced.png", stem))
+    });
+    if cli.svg {
+        filename.set_extension("svg");
+    }
+    // Word lists, a mask or the GPU make a different world from the one
+    // the PNG describes
+    save_outputs(&map, &filename, &cli, seed, cli.reproducible().then_some(&metadata));
+    Ok(())
+}
+
+const GALLERY_USAGE: &str = "Usage: mapper-terrain-cli gallery <command> [OPTIONS]
+
+Keep a collection of favorite worlds with their settings, tags and a
+terrain thumbnail each.
+
+Commands:
+  add --seed <n> [OPTIONS]  Save a seed with the settings the options give
+                            (--land, --preset, ...; see --help)
+  add <map.png>             Save the world a PNG saved by the CLI was made from
+  list                      Show the saved worlds
+  tag <id> <tag>...         Tag a world; with --remove, take the tags off
+  render <id> [OPTIONS]     Generate a saved world and save the map, with any
+                            rendering or output option of a new map
+  remove <id>               Take a world out of the gallery
+
+Options:
+  --tag <tag>       With add: tag the world (repeatable);
+                    with list: only worlds with this tag
+  --note <text>     With add: a note to remember the world by
+  --gallery <file>  Gallery file (default: ~/.config/mapper/gallery.json)
+
+Example:
+  mapper-terrain-cli gallery add --seed 42 --preset archipelago --tag islands
+  mapper-terrain-cli gallery list --tag islands
+  mapper-terrain-cli gallery render 1 --scale 8";
+
+/// Manage the seed gallery (`gallery` subcommand). `args[0]` is the
+/// gallery command.
+fn run_gallery(args: &[String]) -> Result<(), String> {
+    if args.is_empty() || args.iter().any(|a| a == "--help") {
+        println!("{}", GALLERY_USAGE);
+        return Ok(());
+    }
+    let value_of = |flag: &str| args.iter().position(|a| a == flag).and_then(|i| args.get(i + 1));
+    let path = match value_of("--gallery") {
+        Some(path) => PathBuf::from(path),
+        None => Gallery::default_path().ok_or("no home directory for the gallery; use --gallery <file>")?,
+    };
+    let mut gallery = Gallery::load(&path).map_err(|e| format!("cannot load gallery {}: {}", path.display(), e))?;
+    let tags: Vec<String> = args.windows(2).filter(|w| w[0] == "--tag").map(|w| w[1].clone()).collect();
+    let entry_id = || -> Result<u32, String> {
+        let text = args.get(1).ok_or_else(|| format!("missing world number\n\n{}", GALLERY_USAGE))?;
+        text.parse::<u32>()
+            .ok()
+            .filter(|&id| gallery.get(id).is_some())
+            .ok_or_else(|| format!("no world {} in the gallery (see gallery list)", text))
+    };
+
+    match args[0].as_str() {
+        "add" => {
+            let source = args.get(1).filter(|a| !a.starts_with("--"));
+            let (seed, settings) = match source {
+                Some(source) => {
+                    let source = Path::new(source);
+                    let metadata = MapMetadata::read(source)
+                        .map_err(|e| format!("cannot read {}: {}", source.display(), e))?
+                        .ok_or_else(|| format!("{} has no seed or settings in it (only maps saved by mapper-terrain-cli do)", source.display()))?;
+                    (metadata.seed, metadata.settings)
+                }
+                None => {
+                    let cli = parse_args(args);
+                    let seed = cli.seed.ok_or_else(|| format!("add needs --seed <n> or a map PNG\n\n{}", GALLERY_USAGE))?;
+                    (seed, cli.settings)
+                }
+            };
+            let id = gallery.add(seed, settings, &tags);
+            let entry = gallery.get_mut(id).expect("just added");
+            if let Some(note) = value_of("--note") {
+                entry.note = Some(note.clone());
+            }
+            let entry = entry.clone();
+            let thumbnail = gallery.save_thumbnail(&entry).map_err(|e| format!("cannot save thumbnail: {}", e))?;
+            gallery.save().map_err(|e| format!("cannot save gallery {}: {}", path.display(), e))?;
+            println!("Saved seed {} as world {} (thumbnail: {})", seed, id, thumbnail.display());
+        }
+        "list" => {
+            let tag = value_of("--tag").map(String::as_str);
+            let mut shown = 0;
+            for entry in gallery.tagged(tag) {
+                let settings = &entry.settings;
+                let tags = entry.tags.iter().cloned().collect::<Vec<_>>().join(", ");
+                println!("{:>4}  seed {:<10} {}x{}  land {:.0}%  rivers {:.0}%  cities {:.0}%  {}",
+                         entry.id, entry.seed, settings.map_width, settings.map_height,
+                         settings.land_percentage * 100.0, settings.river_density * 100.0,
+                         settings.city_density * 100.0, tags);
+                if let Some(note) = &entry.note {
+                    println!("      {}", note);
+                }
+                shown += 1;
+            }
+            if shown == 0 {
+                println!("No worlds saved{}; add one with: mapper-terrain-cli gallery add --seed <n>",
+                         tag.map(|t| format!(" with tag '{}'", t)).unwrap_or_default());
+            } else {
+                println!("Thumbnails: {}", gallery.path().with_file_name("gallery").display());
+            }
+        }
+        "tag" => {
+            let id = entry_id()?;
+            let mut names = Vec::new();
+            let mut rest = args[2..].iter();
+            while let Some(arg) = rest.next() {
+                match arg.as_str() {
+                    "--gallery" => {
+                        rest.next();
+                    }
+                    "--remove" => {}
+                    _ => names.push(arg),
+                }
+            }
+            if names.is_empty() {
+                return Err(format!("missing tags\n\n{}", GALLERY_USAGE));
+            }
+            let entry = gallery.get_mut(id).expect("checked above");
+            for name in names {
+                if args.iter().any(|a| a == "--remove") {
+                    entry.tags.remove(name);
+                } else {
+                    entry.tags.insert(name.clone());
+                }
+            }
+            let tags = entry.tags.iter().cloned().collect::<Vec<_>>().join(", ");
+            gallery.save().map_err(|e| format!("cannot save gallery {}: {}", path.display(), e))?;
+            println!("World {} tags: {}", id, tags);
+        }
+        "render" => {
+            let entry = gallery.get(entry_id()?).expect("checked above").clone();
+            let cli = parse_args(args);
+            let metadata = entry.metadata();
+            if cli.verbosity > Verbosity::Quiet {
+                println!("Generating world {} (seed {})", entry.id, entry.seed);
+            }
+            let map = generate_map(entry.seed, entry.settings, &cli.names, cli.mask.as_ref(), cli.verbosity, cli.gpu);
+            let mut filename = cli.output.clone().unwrap_or_else(|| cli.config.output_file(entry.seed, &entry.settings, "png"));
+            if cli.svg {
+                filename.set_extension("svg");
+            }
+            save_outputs(&map, &filename, &cli, entry.seed, cli.reproducible().then_some(&metadata));
+        }
+        "remove" => {
+            let id = entry_id()?;
+            gallery.remove(id);
+            gallery.save().map_err(|e| format!("cannot save gallery {}: {}", path.display(), e))?;
+            println!("Removed world {}", id);
+        }
+        other => return Err(format!("unknown gallery command '{}'\n\n{}", other, GALLERY_USAGE)),
+    }
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(String::as_str) == Some("query") {
+        if let Err(e) = run_query(&args[2..]) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if args.get(1).map(String::as_str) == Some("stats") {
+        if let Err(e) = run_stats(&args[2..]) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if args.get(1).map(String::as_str) == Some("validate") {
+        if let Err(e) = run_validate(&args[2..]) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if args.get(1).map(String::as_str) == Some("travel-times") {
+        if let Err(e) = run_travel_times(&args[2..]) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if args.get(1).map(String::as_str) == Some("economy") {
+        if let Err(e) = run_economy(&args[2..]) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if args.get(1).map(String::as_str) == Some("stitch") {
+        if let Err(e) = run_stitch(&args[2..]) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if args.get(1).map(String::as_str) == Some("refine") {
+        if let Err(e) = run_refine(&args[2..]) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if args.get(1).map(String::as_str) == Some("explore") {
+        if let Err(e) = run_explore(&args[1..]) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if args.get(1).map(String::as_str) == Some("gallery") {
+        if let Err(e) = run_gallery(&args[2..]) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if args.get(1).map(String::as_str) == Some("reproduce") {
+        if let Err(e) = run_reproduce(&args[1..]) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let cli = parse_args(&args);
+    let settings = cli.settings;
+    let verbosity = cli.verbosity;
+
+    if cli.quick {
+        // Quick mode: generate immediately and exit
+        if verbosity > Verbosity::Quiet {
+            println!("Generating terrain map with settings: Rivers={:.0}%, Cities={:.0}%, Land={:.0}%",
+                     settings.river_density * 100.0,
+                     settings.city_density * 100.0,
+                     settings.land_percentage * 100.0);
+        }
+
+        let mut seed = cli.seed.unwrap_or_else(time_seed);
+        let (map, default_filename) = match &cli.keep_terrain {
+            Some(path) => {
+                let from = cli.reroll_from.unwrap_or(GenerationPhase::Cities);
+                if verbosity > Verbosity::Quiet {
+                    println!("Keeping terrain from {}, rerolling {} with seed {}", path.display(), from.name(), seed);
+                }
+                let map = reroll_map(path, from, seed, settings, &cli.names, cli.mask.as_ref(), verbosity);
+                let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+                (map, cli.config.output_path(&format!("{}_reroll{}.png", stem, seed)))
+            }
+            None if cli.best_of > 1 => {
+                if verbosity > Verbosity::Quiet {
+                    println!("Seeds: {} to {}", seed, seed.wrapping_add(cli.best_of - 1));
+                }
+                let map;
+                (seed, map) = generate_best_map(seed, cli.best_of, settings, &cli.names, cli.mask.as_ref(), verbosity, cli.gpu);
+                (map, cli.config.output_file(seed, &settings, "png"))
+            }
+            None => {
+                if verbosity > Verbosity::Quiet {
+                    println!("Seed: {}", seed);
+                }
+                (generate_map(seed, settings, &cli.names, cli.mask.as_ref(), verbosity, cli.gpu), cli.config.output_file(seed, &settings, "png"))
+            }
+        };
+
+        let map = match cli.crop {
+            Some(rect) => {
+                let cropped = map.crop(rect);
+                if cropped.terrain.is_empty() {
+                    eprintln!("Error: --crop {},{},{}x{} is outside the {}x{} map", rect.x, rect.y, rect.width, rect.height, map.width, map.height);
+                    std::process::exit(1);
+                }
+                cropped
+            }
+            None => map,
+        };
+
+        // A rerolled or cropped map, or one the options change, can't be
+        // made again from its seed alone
+        let metadata = (cli.keep_terrain.is_none() && cli.crop.is_none() && cli.reproducible()).then_some(MapMetadata { seed, settings });
+        let mut filename = cli.output.clone().unwrap_or(default_filename);
+        if cli.svg {
+            filename.set_extension("svg");
+        }
+        save_outputs(&map, &filename, &cli, seed, metadata.as_ref());
+        return;
+    }
+    
+    loop {
+        println!("\n\x1b[1mMenu:\x1b[0m");
+        println!("1. Generate new terrain map");
+        println!("2. Generate with custom seed");
+        println!("3. About");
         println!("4. Exit");
         println!("\nCurrent settings: Rivers={:.0}%, Cities={:.0}%, Land={:.0}%",
                  settings.river_density * 100.0,
@@ -720,56 +2427,45 @@ fn main() {
                  settings.land_percentage * 100.0);
         
         print!("\nSelect option (1-4): ");
-        io::stdout().flush().unwrap();
-        
-        let mut input = String::new();
-        io::stdin().read_line(&mut input).unwrap();
+        let Some(input) = read_input_line() else {
+            break;
+        };
         let choice = input.trim();
         
         match choice {
             "1" => {
-                let seed = SystemTime::now()
-                    .duration_since(SystemTime::UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs() as u32;
-                let mut generator = TerrainGenerator::new_with_settings(seed, settings);
-                let map = generator.generate(320, 240);  // Ultra-high resolution
+                let seed = time_seed();
+                let map = generate_map(seed, settings, &cli.names, cli.mask.as_ref(), verbosity, cli.gpu);
                 
                 println!("\n\x1b[1mGenerated Terrain Map:\x1b[0m\n");
                 print_terrain_ascii(&map);
                 print_terrain_info(&map);
                 
-                let timestamp = SystemTime::now()
-                    .duration_since(SystemTime::UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs();
-                let filename = format!("terrain_map_{}.png", timestamp);
+                let filename = cli.config.output_file(seed, &settings, "png");
                 
-                match save_terrain_png(&map, &filename, 5) {
-                    Ok(_) => println!("\n\x1b[1mHigh-resolution map saved as: \x1b[92m{}\x1b[0m", filename),
+                let metadata = MapMetadata { seed, settings };
+                match save_map(&map, &filename, cli.scale, cli.layers, &cli.render_options(seed), Some(&metadata), verbosity) {
+                    Ok(_) => println!("\n\x1b[1mHigh-resolution map saved as: \x1b[92m{}\x1b[0m", filename.display()),
                     Err(e) => eprintln!("\x1b[91mError saving map: {}\x1b[0m", e),
                 }
             },
             "2" => {
                 print!("Enter seed value: ");
-                io::stdout().flush().unwrap();
-                
-                let mut seed_input = String::new();
-                io::stdin().read_line(&mut seed_input).unwrap();
+                let seed_input = read_input_line().unwrap_or_default();
                 
                 match seed_input.trim().parse::<u32>() {
                     Ok(seed) => {
-                        let mut generator = TerrainGenerator::new_with_settings(seed, settings);
-                        let map = generator.generate(320, 240);  // Ultra-high resolution
+                        let map = generate_map(seed, settings, &cli.names, cli.mask.as_ref(), verbosity, cli.gpu);
                         
                         println!("\n\x1b[1mGenerated Terrain Map (Seed: {}):\x1b[0m\n", seed);
                         print_terrain_ascii(&map);
                         print_terrain_info(&map);
                         
-                        let filename = format!("terrain_map_seed_{}.png", seed);
+                        let filename = cli.config.output_file(seed, &settings, "png");
                         
-                        match save_terrain_png(&map, &filename, 5) {
-                            Ok(_) => println!("\n\x1b[1mHigh-resolution map saved as: \x1b[92m{}\x1b[0m", filename),
+                        let metadata = MapMetadata { seed, settings };
+                        match save_map(&map, &filename, cli.scale, cli.layers, &cli.render_options(seed), Some(&metadata), verbosity) {
+                            Ok(_) => println!("\n\x1b[1mHigh-resolution map saved as: \x1b[92m{}\x1b[0m", filename.display()),
                             Err(e) => eprintln!("\x1b[91mError saving map: {}\x1b[0m", e),
                         }
                     },
@@ -0,0 +1,97 @@
+//! Distances and areas on a map, for the GUI's measure tool.
+//!
+//! Maps have no built-in scale, so lengths are measured in tiles and
+//! converted with a kilometres-per-tile figure the user picks.
+
+/// Scale the measure tool starts with: a large map is then 8000 km wide
+pub const DEFAULT_KM_PER_TILE: f64 = 5.0;
+
+/// A day's travel on foot, for rough journey times
+const WALKING_KM_PER_DAY: f64 = 30.0;
+
+/// A path of points in tile coordinates, optionally closed into a loop.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Measurement {
+    pub points: Vec<(f64, f64)>,
+    /// The last point joins back to the first
+    pub closed: bool,
+}
+
+impl Measurement {
+    /// Length of the path in tiles, including the closing edge of a loop.
+    pub fn length(&self) -> f64 {
+        let edge = |(x0, y0): (f64, f64), (x1, y1): (f64, f64)| (x1 - x0).hypot(y1 - y0);
+        let open: f64 = self.points.windows(2).map(|w| edge(w[0], w[1])).sum();
+        match (self.closed, self.points.first(), self.points.last()) {
+            (true, Some(&first), Some(&last)) => open + edge(last, first),
+            _ => open,
+        }
+    }
+
+    /// Enclosed area in square tiles, for a closed loop of at least three
+    /// points.
+    pub fn area(&self) -> Option<f64> {
+        if !self.closed || self.points.len() < 3 {
+            return None;
+        }
+        // Shoelace formula
+        let n = self.points.len();
+        let twice: f64 = (0..n)
+            .map(|i| {
+                let (x0, y0) = self.points[i];
+                let (x1, y1) = self.points[(i + 1) % n];
+                x0 * y1 - x1 * y0
+            })
+            .sum();
+        Some(twice.abs() / 2.0)
+    }
+
+    /// One line for the tool bar, e.g. "84.2 tiles, 421 km (about 14 days
+    /// on foot)", with the area of a closed loop.
+    pub fn summary(&self, km_per_tile: f64) -> String {
+        let tiles = self.length();
+        let km = tiles * km_per_tile;
+        let mut text = format!(
+            "{:.1} tiles, {:.0} km (about {:.0} days on foot)",
+            tiles,
+            km,
+            (km / WALKING_KM_PER_DAY).ceil()
+        );
+        if let Some(area) = self.area() {
+            text.push_str(&format!(
+                "; area {:.0} square tiles, {:.0} km²",
+                area,
+                area * km_per_tile * km_per_tile
+            ));
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lengths_and_areas_of_paths_and_loops() {
+        let mut square = Measurement {
+            points: vec![(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)],
+            closed: false,
+        };
+        assert_eq!(square.length(), 30.0);
+        assert_eq!(square.area(), None);
+
+        square.closed = true;
+        assert_eq!(square.length(), 40.0);
+        assert_eq!(square.area(), Some(100.0));
+        // Either winding gives the same area
+        square.points.reverse();
+        assert_eq!(square.area(), Some(100.0));
+        assert_eq!(
+            square.summary(2.0),
+            "40.0 tiles, 80 km (about 3 days on foot); area 100 square tiles, 400 km²"
+        );
+
+        assert_eq!(Measurement::default().length(), 0.0);
+    }
+}
@@ -0,0 +1,234 @@
+//! Place names from user word lists.
+//!
+//! `--names <file>` replaces the built-in English fantasy names for some
+//! kinds of place with the user's own: whole names to pick from, and
+//! prefixes and suffixes joined into more. Kinds left out of the file, or
+//! given no words, keep the built-in names. When a list runs out on a large
+//! map, taken names are told apart the usual way ("North Karak",
+//! "Karak II").
+//!
+//! ```toml
+//! [cities]
+//! names = ["Karak Azul", "Zhufbar"]
+//! prefixes = ["Kar", "Zhu", "Bar"]
+//! suffixes = ["ak", "ndor", "agund"]
+//!
+//! [rivers]
+//! names = ["The Reik", "The Talabec", "The Stir"]
+//!
+//! # A space at the end of a prefix makes two words: "Sea of Claws"
+//! [oceans]
+//! prefixes = ["Sea of ", "Gulf of "]
+//! suffixes = ["Claws", "Chaos", "Dreams"]
+//! ```
+//!
+//! The other kinds are `mountains`, `forests` and `swamps`.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use rand::Rng;
+use serde::Deserialize;
+
+/// The words for one kind of place.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NamePool {
+    /// Whole names, used as they are
+    pub names: Vec<String>,
+    /// Joined to each of `suffixes` as they are, with no space between
+    pub prefixes: Vec<String>,
+    pub suffixes: Vec<String>,
+}
+
+impl NamePool {
+    /// Number of names the pool can give: the whole names and every
+    /// prefix-suffix pairing.
+    pub fn size(&self) -> usize {
+        self.names.len() + self.prefixes.len() * self.suffixes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size() == 0
+    }
+
+    /// A name drawn with `rng`, every one the pool can give equally
+    /// likely; `None` if it is empty.
+    pub fn draw(&self, rng: &mut impl Rng) -> Option<String> {
+        if self.is_empty() {
+            return None;
+        }
+        let pick = rng.gen_range(0..self.size());
+        Some(match self.names.get(pick) {
+            Some(name) => name.clone(),
+            None => {
+                let pairing = pick - self.names.len();
+                let suffixes = self.suffixes.len();
+                format!("{}{}", self.prefixes[pairing / suffixes], self.suffixes[pairing % suffixes])
+            }
+        })
+    }
+
+    /// Why the `kind` pool can't be used: a blank word, or prefixes
+    /// without suffixes to join to (or the reverse).
+    fn check(&self, kind: &str) -> Result<(), String> {
+        for (list, words) in [("names", &self.names), ("prefixes", &self.prefixes), ("suffixes", &self.suffixes)] {
+            if words.iter().any(|word| word.trim().is_empty()) {
+                return Err(format!("[{}] {} has a blank entry", kind, list));
+            }
+        }
+        match (self.prefixes.is_empty(), self.suffixes.is_empty()) {
+            (false, true) => Err(format!("[{}] has prefixes but no suffixes to join them to", kind)),
+            (true, false) => Err(format!("[{}] has suffixes but no prefixes to join them to", kind)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The user's words for each kind of place. An empty pool keeps the
+/// built-in names for its kind.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NameLists {
+    pub cities: NamePool,
+    pub rivers: NamePool,
+    /// Mountain ranges and peaks
+    pub mountains: NamePool,
+    /// Seas, oceans and bays
+    pub oceans: NamePool,
+    pub forests: NamePool,
+    pub swamps: NamePool,
+}
+
+#[derive(Debug)]
+pub enum NameListError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Invalid(PathBuf, String),
+}
+
+impl fmt::Display for NameListError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NameListError::Io(path, e) => write!(f, "cannot read {}: {}", path.display(), e),
+            NameListError::Parse(path, e) => write!(f, "invalid name lists {}: {}", path.display(), e),
+            NameListError::Invalid(path, e) => write!(f, "invalid name lists {}: {}", path.display(), e),
+        }
+    }
+}
+
+impl std::error::Error for NameListError {}
+
+impl NameLists {
+    /// Load and check a name list file.
+    pub fn load(path: &Path) -> Result<NameLists, NameListError> {
+        let text = fs::read_to_string(path).map_err(|e| NameListError::Io(path.to_path_buf(), e))?;
+        let lists = Self::parse(&text).map_err(|e| NameListError::Parse(path.to_path_buf(), e))?;
+        lists.check().map_err(|e| NameListError::Invalid(path.to_path_buf(), e))?;
+        Ok(lists)
+    }
+
+    /// Parse name lists. Unknown kinds and keys are errors, so a typo
+    /// doesn't silently keep the built-in names.
+    pub fn parse(text: &str) -> Result<NameLists, toml::de::Error> {
+        toml::from_str(text)
+    }
+
+    /// Whether every pool is empty, so all names are the built-in ones.
+    pub fn is_empty(&self) -> bool {
+        self.pools().iter().all(|(_, pool)| pool.is_empty())
+    }
+
+    /// The first problem with any pool, naming its kind.
+    pub fn check(&self) -> Result<(), String> {
+        self.pools().iter().try_for_each(|(kind, pool)| pool.check(kind))
+    }
+
+    fn pools(&self) -> [(&'static str, &NamePool); 6] {
+        [
+            ("cities", &self.cities),
+            ("rivers", &self.rivers),
+            ("mountains", &self.mountains),
+            ("oceans", &self.oceans),
+            ("forests", &self.forests),
+            ("swamps", &self.swamps),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::OnceLock;
+
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+    use crate::terrain_generator::{test_map, TerrainGenerator, TerrainMap};
+
+    const CITY_NAMES: [&str; 5] = ["Zhufbar", "Karak", "Kardor", "Morak", "Mordor"];
+
+    fn lists() -> NameLists {
+        let text = "[cities]\nnames = [\"Zhufbar\"]\nprefixes = [\"Kar\", \"Mor\"]\nsuffixes = [\"ak\", \"dor\"]\n\n\
+                    [rivers]\nnames = [\"The Reik\"]\n";
+        NameLists::parse(text).unwrap()
+    }
+
+    /// Seed 7 at 120x90, named from `lists()`
+    fn named_map() -> &'static TerrainMap {
+        static MAP: OnceLock<TerrainMap> = OnceLock::new();
+        MAP.get_or_init(|| {
+            let mut generator = TerrainGenerator::new(7);
+            generator.set_name_lists(lists());
+            generator.generate(120, 90)
+        })
+    }
+
+    #[test]
+    fn user_lists_draw_their_names_and_every_pairing() {
+        let lists = lists();
+        assert_eq!(lists.check(), Ok(()));
+        assert_eq!(lists.cities.size(), 5);
+        assert!(lists.mountains.is_empty() && !lists.is_empty());
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let drawn: Vec<String> = (0..50).map(|_| lists.cities.draw(&mut rng).unwrap()).collect();
+        for name in CITY_NAMES {
+            assert!(drawn.iter().any(|n| n == name), "{} never drawn", name);
+        }
+    }
+
+    #[test]
+    fn user_words_name_their_kinds() {
+        let map = named_map();
+        assert!(map.cities.len() > CITY_NAMES.len());
+        for city in &map.cities {
+            // Names run out and are told apart
+            assert!(CITY_NAMES.iter().any(|name| city.name.contains(name)), "{}", city.name);
+        }
+        let rivers: Vec<_> = map.labels.iter().filter(|l| l.feature_type == "river").collect();
+        assert!(rivers.iter().all(|l| l.name.contains("The") && l.name.contains("Reik")));
+    }
+
+    #[test]
+    fn the_rest_stay_built_in_and_the_places_are_the_same() {
+        let (map, builtin) = (named_map(), test_map(7, 120, 90));
+        let regions = |m: &TerrainMap| -> Vec<(String, f32, f32)> {
+            let regions = m.labels.iter().filter(|l| l.feature_type != "river");
+            regions.map(|l| (l.feature_type.clone(), l.x, l.y)).collect()
+        };
+        assert_eq!(regions(map), regions(builtin));
+        let sites = |m: &TerrainMap| -> Vec<(usize, usize)> { m.cities.iter().map(|c| (c.x, c.y)).collect() };
+        assert_eq!(sites(map), sites(builtin));
+    }
+
+    #[test]
+    fn unknown_kinds_lopsided_and_blank_lists_are_refused() {
+        assert!(NameLists::parse("[citys]\nnames = [\"X\"]\n").is_err());
+        let lopsided = NameLists::parse("[oceans]\nprefixes = [\"Sea of \"]\n").unwrap();
+        assert!(lopsided.check().unwrap_err().contains("[oceans]"));
+        let blank = NameLists::parse("[forests]\nnames = [\" \"]\n").unwrap();
+        assert!(blank.check().is_err());
+    }
+}
@@ -0,0 +1,198 @@
+//! Generation parameters embedded in PNG maps.
+//!
+//! The CLI writes the seed and `GenerationSettings` of each map it
+//! generates into `tEXt` chunks of the PNG, so the world can be made
+//! again from the image alone, at another scale or in another style (the
+//! `reproduce` subcommand). Other programs ignore the chunks; image
+//! editors that rewrite the file may drop them.
+
+use std::io;
+use std::path::Path;
+
+use flate2::Crc;
+
+use crate::error::MapperError;
+use crate::terrain_generator::GenerationSettings;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// Keyword of the chunk holding the seed
+pub const SEED_KEYWORD: &str = "mapper:seed";
+/// Keyword of the chunk holding the settings, as JSON
+pub const SETTINGS_KEYWORD: &str = "mapper:settings";
+
+/// What a map was generated from.
+#[derive(Debug, Clone, Copy)]
+pub struct MapMetadata {
+    pub seed: u32,
+    pub settings: GenerationSettings,
+}
+
+impl MapMetadata {
+    /// `png` with this metadata added after its header.
+    pub fn embed(&self, png: &[u8]) -> Result<Vec<u8>, MapperError> {
+        let settings = serde_json::to_string(&self.settings)?;
+        let seed = self.seed.to_string();
+        with_text_chunks(
+            png,
+            &[
+                ("Software", "mapper"),
+                (SEED_KEYWORD, &seed),
+                (SETTINGS_KEYWORD, &settings),
+            ],
+        )
+    }
+
+    /// The metadata in `png`, or `None` for a PNG without it.
+    pub fn extract(png: &[u8]) -> Result<Option<MapMetadata>, MapperError> {
+        let chunks = text_chunks(png)?;
+        let find = |keyword: &str| chunks.iter().find(|(k, _)| k == keyword).map(|(_, text)| text);
+        let (Some(seed), Some(settings)) = (find(SEED_KEYWORD), find(SETTINGS_KEYWORD)) else {
+            return Ok(None);
+        };
+        let seed = seed
+            .parse()
+            .map_err(|_| invalid(format!("invalid seed '{}' in map metadata", seed)))?;
+        let settings: GenerationSettings = serde_json::from_str(settings)?;
+        Ok(Some(MapMetadata { seed, settings: settings.clamped() }))
+    }
+
+    /// Read the metadata of the PNG file at `path`.
+    pub fn read(path: &Path) -> Result<Option<MapMetadata>, MapperError> {
+        MapMetadata::extract(&std::fs::read(path)?)
+    }
+}
+
+/// Every `tEXt` chunk in `png` as (keyword, text), in file order.
+pub fn text_chunks(png: &[u8]) -> Result<Vec<(String, String)>, MapperError> {
+    let mut found = Vec::new();
+    for (kind, data) in chunks(png)? {
+        if kind == *b"tEXt" {
+            if let Some(split) = data.iter().position(|&b| b == 0) {
+                // Latin-1, which maps byte for byte onto the first code points
+                let latin1 = |bytes: &[u8]| bytes.iter().map(|&b| b as char).collect::<String>();
+                found.push((latin1(&data[..split]), latin1(&data[split + 1..])));
+            }
+        }
+    }
+    Ok(found)
+}
+
+/// `png` with a `tEXt` chunk for each (keyword, text) inserted after the
+/// header. Characters outside Latin-1 are replaced by `?`.
+pub fn with_text_chunks(png: &[u8], entries: &[(&str, &str)]) -> Result<Vec<u8>, MapperError> {
+    let header_end = match chunks(png)?.first() {
+        Some((kind, data)) if *kind == *b"IHDR" => PNG_SIGNATURE.len() + 12 + data.len(),
+        _ => return Err(invalid("PNG does not start with a header chunk".to_string())),
+    };
+    let mut out = png[..header_end].to_vec();
+    for (keyword, text) in entries {
+        let latin1 = |s: &str| s.chars().map(|c| u8::try_from(c).unwrap_or(b'?')).collect::<Vec<u8>>();
+        let mut data = latin1(keyword);
+        data.push(0);
+        data.extend(latin1(text));
+        write_chunk(&mut out, b"tEXt", &data);
+    }
+    out.extend_from_slice(&png[header_end..]);
+    Ok(out)
+}
+
+/// A PNG chunk's type and data.
+type Chunk<'a> = ([u8; 4], &'a [u8]);
+
+/// The chunks of `png` up to and including `IEND`.
+fn chunks(png: &[u8]) -> Result<Vec<Chunk<'_>>, MapperError> {
+    if !png.starts_with(&PNG_SIGNATURE) {
+        return Err(invalid("not a PNG file".to_string()));
+    }
+    let mut found = Vec::new();
+    let mut at = PNG_SIGNATURE.len();
+    loop {
+        if at + 12 > png.len() {
+            return Err(invalid("truncated PNG file".to_string()));
+        }
+        let length = u32::from_be_bytes([png[at], png[at + 1], png[at + 2], png[at + 3]]) as usize;
+        let kind = [png[at + 4], png[at + 5], png[at + 6], png[at + 7]];
+        let data = png
+            .get(at + 8..at + 8 + length)
+            .ok_or_else(|| invalid("truncated PNG file".to_string()))?;
+        found.push((kind, data));
+        at += 12 + length;
+        if kind == *b"IEND" {
+            return Ok(found);
+        }
+    }
+}
+
+fn write_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let mut crc = Crc::new();
+    crc.update(kind);
+    crc.update(data);
+    out.extend_from_slice(&crc.sum().to_be_bytes());
+}
+
+fn invalid(message: String) -> MapperError {
+    MapperError::Io(io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{ImageOutputFormat, Rgb, RgbImage};
+
+    use super::*;
+
+    /// A 3x2 PNG of one color
+    fn plain_png() -> Vec<u8> {
+        let mut png = Vec::new();
+        RgbImage::from_pixel(3, 2, Rgb([10, 20, 30]))
+            .write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)
+            .unwrap();
+        png
+    }
+
+    fn settings() -> GenerationSettings {
+        GenerationSettings {
+            land_percentage: 0.7,
+            map_width: 200,
+            ..GenerationSettings::default()
+        }
+    }
+
+    /// The plain PNG tagged with seed 4242 and `settings()`
+    fn tagged_png() -> Vec<u8> {
+        MapMetadata { seed: 4242, settings: settings() }.embed(&plain_png()).unwrap()
+    }
+
+    #[test]
+    fn plain_pngs_have_no_metadata() {
+        assert!(MapMetadata::extract(&plain_png()).unwrap().is_none());
+    }
+
+    #[test]
+    fn tagged_pngs_still_decode() {
+        let decoded = image::load_from_memory(&tagged_png()).unwrap().to_rgb8();
+        assert_eq!(decoded.get_pixel(2, 1), &Rgb([10, 20, 30]));
+    }
+
+    #[test]
+    fn seed_and_settings_survive_in_the_png() {
+        let metadata = MapMetadata::extract(&tagged_png()).unwrap().unwrap();
+        assert_eq!(metadata.seed, 4242);
+        assert_eq!(
+            serde_json::to_value(metadata.settings).unwrap(),
+            serde_json::to_value(settings()).unwrap()
+        );
+        assert!(text_chunks(&tagged_png()).unwrap().contains(&("Software".to_string(), "mapper".to_string())));
+    }
+
+    #[test]
+    fn files_that_are_not_whole_pngs_are_errors() {
+        assert!(MapMetadata::extract(b"GIF89a").is_err());
+        assert!(MapMetadata::extract(&tagged_png()[..40]).is_err());
+    }
+}
@@ -0,0 +1,149 @@
+//! Named world presets.
+//!
+//! A preset bundles land percentage, formation, climate, density and
+//! settlement settings under a memorable name (`--preset archipelago`).
+//! Users can define their own in the config file; a user preset with a
+//! built-in name replaces it.
+//!
+//! ```toml
+//! [presets.drowned-lands]
+//! land_percentage = 0.25
+//! formation = "archipelago"
+//! temperature = 0.6
+//! ```
+
+use crate::config::Config;
+use crate::terrain_generator::{Formation, GenerationSettings, SettlementWeights};
+
+pub struct Preset {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub settings: GenerationSettings,
+}
+
+/// The presets that ship with mapper.
+pub fn builtin_presets() -> Vec<Preset> {
+    vec![
+        Preset {
+            name: "archipelago",
+            description: "Scattered island clusters in a warm sea",
+            settings: GenerationSettings {
+                river_density: 0.3,
+                city_density: 0.4,
+                land_percentage: 0.3,
+                formation: Some(Formation::Archipelago),
+                temperature: 0.6,
+                mountain_density: 0.5,
+                forest_density: 0.5,
+                ..GenerationSettings::default()
+            },
+        },
+        Preset {
+            name: "pangea",
+            description: "One vast supercontinent",
+            settings: GenerationSettings {
+                river_density: 0.6,
+                city_density: 0.6,
+                land_percentage: 0.65,
+                formation: Some(Formation::Plates),
+                temperature: 0.5,
+                mountain_density: 0.5,
+                forest_density: 0.5,
+                ..GenerationSettings::default()
+            },
+        },
+        Preset {
+            name: "inland-sea",
+            description: "Land ringed around a central sea",
+            settings: GenerationSettings {
+                river_density: 0.5,
+                city_density: 0.5,
+                land_percentage: 0.65,
+                formation: Some(Formation::InlandSea),
+                temperature: 0.55,
+                mountain_density: 0.5,
+                forest_density: 0.5,
+                ..GenerationSettings::default()
+            },
+        },
+        Preset {
+            name: "frozen-north",
+            description: "Cold, sparsely settled lands with ice sheets",
+            settings: GenerationSettings {
+                river_density: 0.4,
+                city_density: 0.2,
+                land_percentage: 0.45,
+                formation: None,
+                temperature: 0.15,
+                mountain_density: 0.5,
+                forest_density: 0.5,
+                ..GenerationSettings::default()
+            },
+        },
+        Preset {
+            name: "trade-coasts",
+            description: "Port cities strung along long coastlines",
+            settings: GenerationSettings {
+                city_density: 0.6,
+                land_percentage: 0.45,
+                settlement_weights: SettlementWeights::COASTAL_TRADE,
+                ..GenerationSettings::default()
+            },
+        },
+        Preset {
+            name: "river-valleys",
+            description: "Inland civilizations along well-watered rivers",
+            settings: GenerationSettings {
+                river_density: 0.8,
+                land_percentage: 0.6,
+                moisture: 0.4,
+                settlement_weights: SettlementWeights::RIVER_VALLEY,
+                ..GenerationSettings::default()
+            },
+        },
+    ]
+}
+
+/// Settings for the preset called `name`: user presets from the config
+/// file first, then the built-in ones.
+pub fn find_preset(name: &str, config: &Config) -> Option<GenerationSettings> {
+    if let Some(settings) = config.presets.get(name) {
+        return Some(settings.clamped());
+    }
+    builtin_presets()
+        .into_iter()
+        .find(|p| p.name == name)
+        .map(|p| p.settings)
+}
+
+/// Every preset name available with this config, sorted.
+pub fn preset_names(config: &Config) -> Vec<String> {
+    let mut names: Vec<String> = builtin_presets()
+        .iter()
+        .map(|p| p.name.to_string())
+        .chain(config.presets.keys().cloned())
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn user_presets_override_builtins() {
+        let config = Config::parse(
+            "[presets.pangea]\nland_percentage = 0.9\n\n[presets.mine]\nformation = \"spine\"\n",
+        )
+        .unwrap();
+        let pangea = find_preset("pangea", &config).unwrap();
+        assert_eq!(pangea.land_percentage, 0.9);
+        let mine = find_preset("mine", &config).unwrap();
+        assert_eq!(mine.formation, Some(Formation::Spine));
+        assert!(find_preset("frozen-north", &config).is_some());
+        assert!(find_preset("nowhere", &config).is_none());
+        assert!(preset_names(&config).contains(&"mine".to_string()));
+    }
+}
@@ -0,0 +1,212 @@
+//! Elevation profiles: the cross-section of the terrain along a path.
+//!
+//! A [`ProfileChart`] plots [`TerrainMap::elevation_profile`] against the
+//! distance travelled along a road, a river or any path of tiles, with the
+//! sea level marked and the sea filled in where the path dips below it.
+//! Distances are in tiles, diagonal steps counting as sqrt 2 and steps
+//! across the seam of a wrapping map as one.
+
+use image::{Rgba, RgbaImage};
+use imageproc::drawing::{draw_filled_rect_mut, draw_line_segment_mut};
+use imageproc::rect::Rect;
+use rusttype::Scale;
+
+use crate::terrain_generator::TerrainMap;
+use crate::text::{draw_text, FontChain};
+
+const BACKGROUND: Rgba<u8> = Rgba([255, 255, 255, 255]);
+const GROUND: Rgba<u8> = Rgba([196, 170, 128, 255]);
+const SEABED: Rgba<u8> = Rgba([150, 140, 120, 255]);
+const WATER: Rgba<u8> = Rgba([150, 190, 230, 255]);
+const SEA_LEVEL: Rgba<u8> = Rgba([40, 90, 170, 255]);
+const OUTLINE: Rgba<u8> = Rgba([70, 50, 30, 255]);
+const AXIS: Rgba<u8> = Rgba([90, 90, 90, 255]);
+
+/// Space around the plot for the axis labels: left, right, top, bottom
+const MARGIN: (u32, u32, u32, u32) = (44, 12, 10, 22);
+
+/// Size of the axis labels, in pixels
+const LABEL_SIZE: f32 = 12.0;
+
+/// A chart of elevation against distance along a path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProfileChart {
+    /// Size of the image, in pixels
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Default for ProfileChart {
+    fn default() -> Self {
+        ProfileChart { width: 640, height: 200 }
+    }
+}
+
+impl ProfileChart {
+    /// The chart of `path` across `map`. Tiles outside the map are left
+    /// out; an empty path draws the bare axes.
+    pub fn render(&self, map: &TerrainMap, path: &[(usize, usize)], fonts: &FontChain) -> RgbaImage {
+        let tiles: Vec<(usize, usize)> = path.iter().copied().filter(|&(x, y)| map.point_at(x, y).is_some()).collect();
+        let elevations = map.elevation_profile(&tiles);
+        let distances = path_distances(&tiles, map.width, map.wrap_x);
+        let length = distances.last().copied().unwrap_or(0.0);
+
+        let (width, height) = (self.width.max(MARGIN.0 + MARGIN.1 + 16), self.height.max(MARGIN.2 + MARGIN.3 + 16));
+        let mut img = RgbaImage::from_pixel(width, height, BACKGROUND);
+        let (left, top) = (MARGIN.0 as f32, MARGIN.2 as f32);
+        let (plot_width, plot_height) = ((width - MARGIN.0 - MARGIN.1) as f32, (height - MARGIN.2 - MARGIN.3) as f32);
+        let bottom = top + plot_height;
+
+        // The sea level is always in view, with a little room above and
+        // below the path
+        let lowest = elevations.iter().copied().fold(0.0, f64::min);
+        let highest = elevations.iter().copied().fold(0.0, f64::max);
+        let pad = ((highest - lowest) * 0.05).max(0.02);
+        let (lo, hi) = (if lowest < 0.0 { lowest - pad } else { 0.0 }, highest + pad);
+        let to_y = |elevation: f64| top + ((hi - elevation) / (hi - lo)) as f32 * plot_height;
+        let sea_y = to_y(0.0);
+
+        if elevations.len() > 1 && length > 0.0 {
+            for column in 0..plot_width as u32 {
+                let distance = (column as f64 + 0.5) / plot_width as f64 * length;
+                let elevation = elevation_at(&distances, &elevations, distance);
+                let x = left as i32 + column as i32;
+                let ground_y = to_y(elevation);
+                let fill = if elevation < 0.0 { SEABED } else { GROUND };
+                fill_column(&mut img, x, ground_y, bottom, fill);
+                if elevation < 0.0 {
+                    fill_column(&mut img, x, sea_y, ground_y, WATER);
+                }
+            }
+            let to_x = |distance: f64| left + (distance / length) as f32 * plot_width;
+            for i in 1..elevations.len() {
+                let start = (to_x(distances[i - 1]), to_y(elevations[i - 1]));
+                let end = (to_x(distances[i]), to_y(elevations[i]));
+                draw_line_segment_mut(&mut img, start, end, OUTLINE);
+            }
+        }
+
+        draw_line_segment_mut(&mut img, (left, sea_y), (left + plot_width, sea_y), SEA_LEVEL);
+        draw_line_segment_mut(&mut img, (left, top), (left, bottom), AXIS);
+        draw_line_segment_mut(&mut img, (left, bottom), (left + plot_width, bottom), AXIS);
+
+        let scale = Scale::uniform(LABEL_SIZE);
+        let half = LABEL_SIZE / 2.0;
+        let label = |img: &mut RgbaImage, text: &str, x: f32, y: f32| {
+            draw_text(img, AXIS, x as i32, y as i32, scale, fonts, text);
+        };
+        let right_aligned = |text: &str| left - 4.0 - fonts.text_width(text, scale);
+        let text = format!("{:.2}", highest);
+        label(&mut img, &text, right_aligned(&text), to_y(highest) - half);
+        if (sea_y - to_y(highest)).abs() > LABEL_SIZE {
+            label(&mut img, "0", right_aligned("0"), sea_y - half);
+        }
+        if lowest < 0.0 && (to_y(lowest) - sea_y).abs() > LABEL_SIZE {
+            let text = format!("{:.2}", lowest);
+            label(&mut img, &text, right_aligned(&text), to_y(lowest) - half);
+        }
+        label(&mut img, "0", left, bottom + 4.0);
+        let text = format!("{:.0} tiles", length);
+        label(&mut img, &text, left + plot_width - fonts.text_width(&text, scale), bottom + 4.0);
+        img
+    }
+}
+
+/// The tiles on straight lines from each of `points` to the next, as a
+/// path for [`ProfileChart::render`].
+pub fn straight_path(points: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    let mut path: Vec<(usize, usize)> = points.first().copied().into_iter().collect();
+    for pair in points.windows(2) {
+        let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+        let (dx, dy) = (x1 as f64 - x0 as f64, y1 as f64 - y0 as f64);
+        let steps = dx.abs().max(dy.abs()) as usize;
+        for step in 1..=steps {
+            let t = step as f64 / steps as f64;
+            let tile = ((x0 as f64 + dx * t).round() as usize, (y0 as f64 + dy * t).round() as usize);
+            if path.last() != Some(&tile) {
+                path.push(tile);
+            }
+        }
+    }
+    path
+}
+
+/// Distance along `path` to each of its tiles, in tiles, on a map
+/// `width` across that may wrap east-west.
+pub fn path_distances(path: &[(usize, usize)], width: usize, wrap_x: bool) -> Vec<f64> {
+    let mut distance = 0.0;
+    let mut distances = Vec::with_capacity(path.len());
+    for (i, &(x, y)) in path.iter().enumerate() {
+        if i > 0 {
+            let (px, py) = path[i - 1];
+            let mut dx = x.abs_diff(px);
+            if wrap_x {
+                dx = dx.min(width - dx);
+            }
+            distance += (dx as f64).hypot(y.abs_diff(py) as f64);
+        }
+        distances.push(distance);
+    }
+    distances
+}
+
+/// The elevation `distance` along the path, between the tiles either side.
+fn elevation_at(distances: &[f64], elevations: &[f64], distance: f64) -> f64 {
+    let next = distances.partition_point(|&d| d < distance).clamp(1, distances.len() - 1);
+    let (d0, d1) = (distances[next - 1], distances[next]);
+    let t = if d1 > d0 { ((distance - d0) / (d1 - d0)).clamp(0.0, 1.0) } else { 1.0 };
+    elevations[next - 1] * (1.0 - t) + elevations[next] * t
+}
+
+/// Fill column `x` of `img` between `from` and `to`, in either order.
+fn fill_column(img: &mut RgbaImage, x: i32, from: f32, to: f32, color: Rgba<u8>) {
+    let (start, end) = (from.min(to).round() as i32, from.max(to).round() as i32);
+    if end > start {
+        draw_filled_rect_mut(img, Rect::at(x, start).of_size(1, (end - start) as u32), color);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain_generator::test_map;
+
+    #[test]
+    fn paths_are_drawn_straight_between_their_points() {
+        assert_eq!(straight_path(&[(0, 0), (3, 1)]), vec![(0, 0), (1, 0), (2, 1), (3, 1)]);
+        assert_eq!(straight_path(&[(2, 2)]), vec![(2, 2)]);
+    }
+
+    #[test]
+    fn distances_go_the_short_way_round_and_elevations_are_interpolated() {
+        let distances = path_distances(&[(9, 0), (0, 0), (1, 1)], 10, true);
+        assert_eq!(distances[1], 1.0);
+        assert!((distances[2] - (1.0 + std::f64::consts::SQRT_2)).abs() < 1e-9);
+        assert_eq!(elevation_at(&[0.0, 1.0, 2.0], &[0.0, 1.0, -1.0], 1.5), 0.0);
+    }
+
+    #[test]
+    fn a_line_from_the_sea_to_the_highest_peak_rises_out_of_the_water() {
+        let map = test_map(5, 120, 90);
+        let mut peak = (0, 0);
+        let mut sea = (0, 0);
+        for y in 0..map.height {
+            for x in 0..map.width {
+                let elevation = map.terrain[y][x].elevation;
+                if elevation > map.terrain[peak.1][peak.0].elevation {
+                    peak = (x, y);
+                }
+                if elevation < map.terrain[sea.1][sea.0].elevation {
+                    sea = (x, y);
+                }
+            }
+        }
+        let chart = ProfileChart::default();
+        let img = chart.render(map, &straight_path(&[sea, peak]), &FontChain::bundled().unwrap());
+        assert_eq!(img.dimensions(), (chart.width, chart.height));
+        let colors: Vec<Rgba<u8>> = img.pixels().copied().collect();
+        for color in [GROUND, WATER, SEABED, OUTLINE] {
+            assert!(colors.contains(&color), "{:?} missing", color);
+        }
+    }
+}
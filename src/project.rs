@@ -0,0 +1,129 @@
+//! Project files.
+//!
+//! A project is a map, hand edits included, together with the seed and
+//! settings it was generated from and where the GUI's view was, so a
+//! worldbuilding session can be picked up later. On disk it is a short
+//! header followed by the project in bincode, deflate-compressed: the
+//! terrain grid is millions of values, and unlike JSON bincode keeps every
+//! one of them exact.
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+
+use crate::terrain_generator::{GenerationSettings, TerrainMap};
+
+/// File extension for project files
+pub const PROJECT_EXTENSION: &str = "mapper";
+
+const MAGIC: &[u8; 8] = b"MAPPROJ\0";
+/// Bumped whenever the saved types change shape
+const FORMAT_VERSION: u32 = 5;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Project {
+    pub map: TerrainMap,
+    pub seed: u32,
+    pub settings: GenerationSettings,
+    pub view: ProjectView,
+}
+
+/// Where the view was: `zoom` screen pixels per tile, with tile (x, y) at
+/// the top-left corner.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ProjectView {
+    pub zoom: f32,
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Project {
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        let mut encoder = DeflateEncoder::new(writer, Compression::fast());
+        bincode::serialize_into(&mut encoder, self).map_err(invalid_data)?;
+        encoder.finish()?.flush()
+    }
+
+    /// Read a project written by [`Project::save`]. Files that are not
+    /// projects, or come from a newer version, are reported as
+    /// `InvalidData`.
+    pub fn load(path: &Path) -> io::Result<Project> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut magic = [0; 8];
+        let mut version = [0; 4];
+        reader.read_exact(&mut magic)?;
+        reader.read_exact(&mut version)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a mapper project file"));
+        }
+        let version = u32::from_le_bytes(version);
+        if version != FORMAT_VERSION {
+            return Err(invalid_data(format!(
+                "unsupported project format version {}",
+                version
+            )));
+        }
+        bincode::deserialize_from(DeflateDecoder::new(reader)).map_err(invalid_data)
+    }
+}
+
+fn invalid_data<E: Into<Box<dyn std::error::Error + Send + Sync>>>(error: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain_generator::test_map;
+
+    #[test]
+    fn projects_round_trip_exactly() {
+        // Placed points of interest carry empty metadata
+        let mut map = test_map(21, 200, 150).clone();
+        assert!(map.pois.iter().any(|p| p.metadata.is_empty()));
+        map.cities[0].name = "Edited".to_string();
+        map.terrain[10][10].elevation = 0.123456789;
+        let project = Project {
+            map,
+            seed: 21,
+            settings: GenerationSettings::default(),
+            view: ProjectView {
+                zoom: 2.5,
+                x: 12.0,
+                y: -3.5,
+            },
+        };
+
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("mapper-project-{}.mapper", std::process::id()));
+        project.save(&path).unwrap();
+        let loaded = Project::load(&path).unwrap();
+        fs::remove_file(&path).ok();
+        assert_eq!(loaded.seed, 21);
+        assert_eq!(loaded.view, project.view);
+        assert_eq!(
+            serde_json::to_string(&loaded.map).unwrap(),
+            serde_json::to_string(&project.map).unwrap()
+        );
+    }
+
+    #[test]
+    fn a_file_that_is_not_a_project_is_invalid_data() {
+        let path = std::env::temp_dir().join(format!("mapper-not-a-project-{}.mapper", std::process::id()));
+        fs::write(&path, b"{\"width\": 1}").unwrap();
+        let error = Project::load(&path).unwrap_err();
+        fs::remove_file(&path).ok();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
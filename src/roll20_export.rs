@@ -0,0 +1,188 @@
+//! Roll20 page export.
+//!
+//! Roll20 pages are measured in grid cells of 70 pixels. The map image is
+//! scaled so a whole number of tiles fills each cell and the grid lines
+//! up with the map, and a JSON file gives the page size and a token for
+//! each city, with the properties Roll20's API uses for graphics (`left`
+//! and `top` are the token's centre, in page pixels), for a GM to place
+//! by hand or with an import script.
+
+use std::path::Path;
+
+use image::imageops::{self, FilterType};
+use image::RgbaImage;
+use serde::Serialize;
+
+use crate::error::MapperError;
+use crate::terrain_generator::TerrainMap;
+
+/// Cities with at least this many people get a token two cells across
+pub const LARGE_CITY_POPULATION: u32 = 100_000;
+
+/// How the map lies on the Roll20 grid.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Roll20Options {
+    /// Pixels per grid cell; Roll20's own is 70
+    pub cell_px: u32,
+    /// Map tiles across each grid cell
+    pub tiles_per_cell: u32,
+}
+
+impl Default for Roll20Options {
+    fn default() -> Self {
+        Roll20Options {
+            cell_px: 70,
+            tiles_per_cell: 8,
+        }
+    }
+}
+
+impl Roll20Options {
+    /// Pixels per map tile on the page.
+    pub fn tile_px(&self) -> f32 {
+        self.cell_px.max(1) as f32 / self.tiles_per_cell.max(1) as f32
+    }
+
+    /// The smallest whole render scale at least as fine as the page, to
+    /// render at before `fit_to_grid` scales down.
+    pub fn render_scale(&self) -> u32 {
+        (self.tile_px().ceil() as u32).max(1)
+    }
+
+    /// Page size in grid cells; a part cell at the edge counts.
+    pub fn page_cells(&self, map: &TerrainMap) -> (f32, f32) {
+        let tiles = self.tiles_per_cell.max(1) as f32;
+        (map.width as f32 / tiles, map.height as f32 / tiles)
+    }
+
+    /// Page size in pixels.
+    pub fn page_px(&self, map: &TerrainMap) -> (u32, u32) {
+        let px = |tiles: usize| ((tiles as f32 * self.tile_px()).round() as u32).max(1);
+        (px(map.width), px(map.height))
+    }
+}
+
+/// One city token.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Roll20Token {
+    pub name: String,
+    pub population: u32,
+    /// Centre of the token, in page pixels from the left
+    pub left: f32,
+    /// Centre of the token, in page pixels from the top
+    pub top: f32,
+    /// Size in page pixels
+    pub width: u32,
+    pub height: u32,
+    /// Roll20 layer: "objects" for tokens players see
+    pub layer: &'static str,
+}
+
+/// A page: its size, its background image and the city tokens.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Roll20Page {
+    pub name: String,
+    /// Size in grid cells
+    pub width: f32,
+    pub height: f32,
+    /// Pixels per grid cell
+    pub grid_px: u32,
+    /// File name of the page's map image
+    pub image: String,
+    pub tokens: Vec<Roll20Token>,
+}
+
+impl Roll20Page {
+    /// The page for `map`, whose image is saved as `image`.
+    pub fn new(map: &TerrainMap, name: &str, image: &str, options: &Roll20Options) -> Roll20Page {
+        let (width, height) = options.page_cells(map);
+        let tile_px = options.tile_px();
+        let tokens = map
+            .cities
+            .iter()
+            .map(|city| {
+                let size = if city.population >= LARGE_CITY_POPULATION { 2 } else { 1 } * options.cell_px;
+                Roll20Token {
+                    name: city.name.clone(),
+                    population: city.population,
+                    left: (city.x as f32 + 0.5) * tile_px,
+                    top: (city.y as f32 + 0.5) * tile_px,
+                    width: size,
+                    height: size,
+                    layer: "objects",
+                }
+            })
+            .collect();
+        Roll20Page {
+            name: name.to_string(),
+            width,
+            height,
+            grid_px: options.cell_px,
+            image: image.to_string(),
+            tokens,
+        }
+    }
+
+    pub fn save_json(&self, path: &Path) -> Result<(), MapperError> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// `img`, the whole of `map` rendered at `options.render_scale()`, scaled
+/// to the page so each grid cell covers `tiles_per_cell` tiles exactly.
+pub fn fit_to_grid(img: &RgbaImage, map: &TerrainMap, options: &Roll20Options) -> RgbaImage {
+    let (width, height) = options.page_px(map);
+    if img.dimensions() == (width, height) {
+        return img.clone();
+    }
+    imageops::resize(img, width, height, FilterType::Triangle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain_generator::test_map;
+    use crate::terrain_renderer::{Layers, RenderOptions, TerrainRenderer};
+
+    fn map() -> &'static TerrainMap {
+        test_map(6, 80, 60)
+    }
+
+    #[test]
+    fn default_pages_are_ten_by_seven_and_a_half_cells() {
+        let (map, options) = (map(), Roll20Options::default());
+        assert_eq!(options.render_scale(), 9);
+        assert_eq!(options.page_cells(map), (10.0, 7.5));
+        assert_eq!(options.page_px(map), (700, 525));
+        let img = TerrainRenderer::render_layers_to_image(map, options.render_scale(), Layers::all(), &RenderOptions::default());
+        assert_eq!(fit_to_grid(&img, map, &options).dimensions(), (700, 525));
+    }
+
+    #[test]
+    fn tokens_sit_on_their_cities() {
+        let map = map();
+        let page = Roll20Page::new(map, "Seed 6", "seed6.png", &Roll20Options::default());
+        assert_eq!(page.tokens.len(), map.cities.len());
+        let city = &map.cities[0];
+        let token = &page.tokens[0];
+        // The token sits on its city's tile, a cell holding eight tiles
+        assert_eq!((token.left / 70.0 * 8.0) as usize, city.x);
+        assert_eq!((token.top / 70.0 * 8.0) as usize, city.y);
+        assert!(page.tokens.iter().all(|t| t.left < 700.0 && t.top < 525.0));
+    }
+
+    #[test]
+    fn pages_carry_the_grid_and_token_layer() {
+        let page = Roll20Page::new(map(), "Seed 6", "seed6.png", &Roll20Options::default());
+        let json = serde_json::to_value(&page).unwrap();
+        assert_eq!(json["grid_px"], 70);
+        assert_eq!(json["tokens"][0]["layer"], "objects");
+    }
+
+    #[test]
+    fn one_tile_per_cell_needs_no_scaling() {
+        let exact = Roll20Options { cell_px: 10, tiles_per_cell: 1 };
+        assert_eq!((exact.render_scale(), exact.page_px(map())), (10, (800, 600)));
+    }
+}
@@ -0,0 +1,199 @@
+//! STL export for 3D printing.
+//!
+//! The elevation grid becomes a closed solid: a relief surface with one
+//! vertex per tile, walls down from its edges and a flat base, so slicers
+//! accept it without repair. The sea is printed flat at sea level. Units
+//! are millimetres, north is +Y and up is +Z.
+
+use std::path::Path;
+
+use crate::error::MapperError;
+use crate::terrain_generator::TerrainMap;
+
+/// Height of elevation 1.0 above sea level, in tiles, before
+/// exaggeration: the highest peaks of a 200-tile map rise a twentieth of
+/// its width
+pub const RELIEF_TILES: f32 = 10.0;
+
+/// A triangle's three corners, counter-clockwise seen from outside.
+pub type Triangle = [[f32; 3]; 3];
+
+/// Size and relief of the printed model.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StlOptions {
+    /// Width of a tile, in mm
+    pub tile_size: f32,
+    /// Multiplies the height of the relief; 1 is `RELIEF_TILES`
+    pub exaggeration: f32,
+    /// Thickness of the solid under sea level, in mm
+    pub base: f32,
+}
+
+impl Default for StlOptions {
+    fn default() -> Self {
+        StlOptions {
+            tile_size: 0.5,
+            exaggeration: 1.0,
+            base: 2.0,
+        }
+    }
+}
+
+/// The closed surface of `map` as a relief solid. Maps less than two
+/// tiles wide or high have no surface and give no triangles.
+pub fn terrain_mesh(map: &TerrainMap, options: &StlOptions) -> Vec<Triangle> {
+    let (width, height) = (map.width, map.height);
+    if width < 2 || height < 2 || map.terrain.len() < height {
+        return Vec::new();
+    }
+    let relief = RELIEF_TILES * options.exaggeration.max(0.0) * options.tile_size;
+    // Grid corner (i, j) counts j up from the southern edge
+    let top = |i: usize, j: usize| {
+        let elevation = map.terrain[height - 1 - j].get(i).map_or(0.0, |p| p.elevation.max(0.0));
+        [
+            i as f32 * options.tile_size,
+            j as f32 * options.tile_size,
+            options.base + elevation as f32 * relief,
+        ]
+    };
+
+    let mut triangles = Vec::with_capacity(4 * width * height);
+    for j in 0..height - 1 {
+        for i in 0..width - 1 {
+            let (a, b, c, d) = (top(i, j), top(i + 1, j), top(i + 1, j + 1), top(i, j + 1));
+            triangles.push([a, b, c]);
+            triangles.push([a, c, d]);
+        }
+    }
+
+    // The edge corners counter-clockwise from the south-west, each once
+    let mut rim = Vec::with_capacity(2 * (width + height));
+    rim.extend((0..width - 1).map(|i| (i, 0)));
+    rim.extend((0..height - 1).map(|j| (width - 1, j)));
+    rim.extend((1..width).rev().map(|i| (i, height - 1)));
+    rim.extend((1..height).rev().map(|j| (0, j)));
+
+    let floor = |[x, y, _]: [f32; 3]| [x, y, 0.0];
+    let centre = [
+        (width - 1) as f32 * options.tile_size / 2.0,
+        (height - 1) as f32 * options.tile_size / 2.0,
+        0.0,
+    ];
+    for (k, &(i, j)) in rim.iter().enumerate() {
+        let (next_i, next_j) = rim[(k + 1) % rim.len()];
+        let (p, q) = (top(i, j), top(next_i, next_j));
+        // Wall, facing out
+        triangles.push([floor(p), floor(q), q]);
+        triangles.push([floor(p), q, p]);
+        // Base, a fan from the centre, facing down
+        triangles.push([centre, floor(q), floor(p)]);
+    }
+    triangles
+}
+
+/// `triangles` as a binary STL file.
+pub fn write_stl(triangles: &[Triangle]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(84 + 50 * triangles.len());
+    let mut header = [0u8; 80];
+    let name = b"mapper terrain";
+    header[..name.len()].copy_from_slice(name);
+    out.extend_from_slice(&header);
+    out.extend_from_slice(&(triangles.len() as u32).to_le_bytes());
+    for triangle in triangles {
+        for value in normal(triangle).iter().chain(triangle.iter().flatten()) {
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+        out.extend_from_slice(&0u16.to_le_bytes());
+    }
+    out
+}
+
+/// Write `map` as a binary STL file at `path`.
+pub fn save_stl(map: &TerrainMap, path: &Path, options: &StlOptions) -> Result<(), MapperError> {
+    std::fs::write(path, write_stl(&terrain_mesh(map, options)))?;
+    Ok(())
+}
+
+/// Unit normal of `triangle` by the right-hand rule, or zero for a
+/// degenerate one.
+fn normal([a, b, c]: &Triangle) -> [f32; 3] {
+    let (u, v) = ([0, 1, 2].map(|k| b[k] - a[k]), [0, 1, 2].map(|k| c[k] - a[k]));
+    let n = [u[1] * v[2] - u[2] * v[1], u[2] * v[0] - u[0] * v[2], u[0] * v[1] - u[1] * v[0]];
+    let length = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+    if length > 0.0 {
+        n.map(|c| c / length)
+    } else {
+        [0.0; 3]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::terrain_generator::test_map;
+
+    fn options() -> StlOptions {
+        StlOptions { exaggeration: 2.0, ..StlOptions::default() }
+    }
+
+    /// The mesh of a 30x20 map
+    fn mesh() -> Vec<Triangle> {
+        terrain_mesh(test_map(4, 30, 20), &options())
+    }
+
+    #[test]
+    fn the_mesh_has_a_surface_walls_and_a_base() {
+        let rim = 2 * (29 + 19);
+        assert_eq!(mesh().len(), 2 * 29 * 19 + 3 * rim);
+    }
+
+    #[test]
+    fn the_mesh_is_watertight() {
+        // Every edge is used once in each direction
+        let key = |p: [f32; 3]| p.map(f32::to_bits);
+        let mut edges: HashMap<_, i32> = HashMap::new();
+        for triangle in &mesh() {
+            for k in 0..3 {
+                let (from, to) = (key(triangle[k]), key(triangle[(k + 1) % 3]));
+                *edges.entry((from, to)).or_default() += 1;
+                *edges.entry((to, from)).or_default() -= 1;
+            }
+        }
+        assert!(edges.values().all(|&count| count == 0));
+    }
+
+    #[test]
+    fn the_mesh_faces_out_and_rises_from_its_base() {
+        let (triangles, options) = (mesh(), options());
+        // The signed volume is positive and at least the base
+        let volume: f32 = triangles
+            .iter()
+            .map(|[a, b, c]| {
+                (a[0] * (b[1] * c[2] - b[2] * c[1]) - a[1] * (b[0] * c[2] - b[2] * c[0])
+                    + a[2] * (b[0] * c[1] - b[1] * c[0]))
+                    / 6.0
+            })
+            .sum();
+        let base = 29.0 * 0.5 * 19.0 * 0.5 * options.base;
+        assert!(volume > base, "{} <= {}", volume, base);
+        let top = triangles.iter().flatten().map(|p| p[2]).fold(0.0, f32::max);
+        assert!(top > options.base && top <= options.base + 2.0 * RELIEF_TILES * 0.5);
+    }
+
+    #[test]
+    fn stl_files_hold_every_triangle() {
+        let triangles = mesh();
+        let stl = write_stl(&triangles);
+        assert_eq!(stl.len(), 84 + 50 * triangles.len());
+        assert_eq!(u32::from_le_bytes(stl[80..84].try_into().unwrap()) as usize, triangles.len());
+        let first_corner = f32::from_le_bytes(stl[84 + 12..84 + 16].try_into().unwrap());
+        assert_eq!(first_corner, triangles[0][0][0]);
+    }
+
+    #[test]
+    fn a_map_one_tile_wide_has_no_mesh() {
+        assert!(terrain_mesh(&TerrainMap::empty(1, 5), &options()).is_empty());
+    }
+}
@@ -0,0 +1,523 @@
+//! SVG export.
+//!
+//! Rivers, roads, cities and labels are written as vector elements, one
+//! `<g>` group per layer so they can be edited separately in a drawing
+//! program; rivers and roads follow their smoothed curves
+//! (`TerrainMap::smoothed`). The terrain has no natural vector form and is
+//! embedded as a PNG image, with the map's coastlines drawn over it as
+//! lines. Disabled layers are left out entirely. On a map that wraps
+//! east-west, each vector group is drawn again a map's width to either side
+//! with `<use>`, so what crosses the seam shows at both edges.
+
+use std::fmt::Write as _;
+use std::io::Cursor;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use image::{ImageError, ImageOutputFormat};
+
+use crate::terrain_generator::{RiverOutlet, TerrainMap};
+use crate::terrain_renderer::{
+    flow_arrows, is_frozen, Layers, RenderOptions, TerrainRenderer, COAST_INK, ICE_COLOR, PARK_INK,
+};
+
+/// Render `map` as an SVG document, `scale` pixels per tile, the terrain
+/// shaded as `options` say.
+pub fn render_svg(
+    map: &TerrainMap,
+    scale: u32,
+    layers: Layers,
+    options: &RenderOptions,
+) -> Result<String, ImageError> {
+    let s = scale as f32;
+    let width = map.width as f32 * s;
+    let height = map.height as f32 * s;
+    // Tile centers, matching the raster renderer
+    let at = |x: usize, y: usize| (x as f32 * s + s / 2.0, y as f32 * s + s / 2.0);
+    // Rivers and roads along their smoothed curves
+    let placed = |curve: &[(f32, f32)]| -> Vec<(f32, f32)> {
+        curve.iter().map(|&(x, y)| (x * s + s / 2.0, y * s + s / 2.0)).collect()
+    };
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+        w = width,
+        h = height
+    );
+
+    if layers.terrain {
+        let terrain_only = Layers {
+            terrain: true,
+            relief: layers.relief,
+            ..Layers::none()
+        };
+        let img = TerrainRenderer::render_layers_to_image(map, scale, terrain_only, options);
+        let mut png = Vec::new();
+        image::DynamicImage::ImageRgba8(img)
+            .to_rgb8()
+            .write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)?;
+        let _ = writeln!(
+            svg,
+            r#"<g id="terrain"><image width="{}" height="{}" href="data:image/png;base64,{}"/></g>"#,
+            width,
+            height,
+            STANDARD.encode(&png)
+        );
+
+        // The coasts over it as vectors, on the terrain's own grid, lake
+        // shores in a lighter line
+        let _ = writeln!(
+            svg,
+            r#"<g id="coastline" fill="none" stroke="{}" stroke-width="{:.1}" stroke-linejoin="round">"#,
+            svg_color(options.coastline.unwrap_or(COAST_INK)),
+            (s * 0.2).max(0.5)
+        );
+        for coast in &map.coastlines {
+            let class = if coast.lake { r#" class="lake" stroke-opacity="0.6""# } else { "" };
+            for run in coast.runs(map.width, map.height) {
+                let run: Vec<(f32, f32)> = run.iter().map(|&(x, y)| (x * s, y * s)).collect();
+                let _ = writeln!(svg, r#"  <polyline{} points="{}"/>"#, class, points_attr(&run));
+            }
+        }
+        svg.push_str("</g>\n");
+    }
+
+    if layers.territories {
+        let tint = Layers {
+            territories: true,
+            ..Layers::none()
+        };
+        embed_layer(&mut svg, "territories", map, scale, tint, options)?;
+    }
+
+    if layers.rivers {
+        // Tapered like the raster renderer: each river is split into a few
+        // runs that get wider towards the mouth
+        svg.push_str(
+            r#"<g id="rivers" fill="none" stroke="rgb(30,100,220)" stroke-linecap="round" stroke-linejoin="round">"#,
+        );
+        svg.push('\n');
+        for index in (0..map.rivers.len()).filter(|&i| map.rivers[i].len() >= 2) {
+            let curve = map.river_curve(index);
+            let path = placed(&curve);
+            let runs = 4.min(path.len() - 1);
+            for run in 0..runs {
+                let start = run * (path.len() - 1) / runs;
+                let end = (run + 1) * (path.len() - 1) / runs;
+                let t = (start + end) as f32 / 2.0 / path.len() as f32;
+                let stroke = 2.0 * (s * (0.15 + 0.4 * t)).max(0.7);
+                // Runs that start where the snow lies are frozen over
+                let (x, y) = curve[start];
+                let (x, y) = ((x.round() as isize).rem_euclid(map.width.max(1) as isize) as usize, y.round().max(0.0) as usize);
+                let tile = map.terrain.get(y).and_then(|row| row.get(x));
+                let ice = match (options.snow, tile) {
+                    (Some(season), Some(tile)) if is_frozen(tile, season) => {
+                        let [r, g, b] = ICE_COLOR;
+                        format!(r#" stroke="rgb({},{},{})""#, r, g, b)
+                    }
+                    _ => String::new(),
+                };
+                let _ = writeln!(
+                    svg,
+                    r#"  <polyline stroke-width="{:.1}"{} points="{}"/>"#,
+                    stroke,
+                    ice,
+                    points_attr(&path[start..=end])
+                );
+            }
+        }
+        svg.push_str("</g>\n");
+        seam_copies(&mut svg, map, "rivers", s);
+
+        if options.flow_arrows {
+            let size = (s * 1.2).max(3.0);
+            let mark = (s * 0.6).max(2.0);
+            let _ = writeln!(
+                svg,
+                r#"<g id="river-flow" fill="none" stroke="rgb(10,45,130)" stroke-width="{:.1}" stroke-linecap="round" stroke-linejoin="round">"#,
+                (s * 0.2).max(1.0)
+            );
+            for flow in map.river_flows() {
+                for (at, [dx, dy]) in flow_arrows(map, &map.rivers[flow.river]) {
+                    let tip = ((at[0] + dx * 0.5) * s, (at[1] + dy * 0.5) * s);
+                    let (cos, sin) = (35f32.to_radians().cos(), 35f32.to_radians().sin());
+                    let wing = |side: f32| {
+                        let (wx, wy) = (dx * cos - side * dy * sin, side * dx * sin + dy * cos);
+                        (tip.0 - wx * size, tip.1 - wy * size)
+                    };
+                    let _ = writeln!(svg, r#"  <polyline points="{}"/>"#, points_attr(&[wing(-1.0), tip, wing(1.0)]));
+                }
+                let (x, y) = at(flow.source.0, flow.source.1);
+                let _ = writeln!(svg, r#"  <circle cx="{}" cy="{}" r="{:.1}"/>"#, x, y, mark);
+                if !matches!(flow.outlet, RiverOutlet::River(_)) {
+                    let (x, y) = at(flow.mouth.0, flow.mouth.1);
+                    let half = mark + 1.0;
+                    let diamond = [(x, y - half), (x + half, y), (x, y + half), (x - half, y)];
+                    let _ = writeln!(
+                        svg,
+                        r#"  <polygon fill="rgb(10,45,130)" points="{}"/>"#,
+                        points_attr(&diamond)
+                    );
+                }
+            }
+            svg.push_str("</g>\n");
+            seam_copies(&mut svg, map, "river-flow", s);
+        }
+    }
+
+    if layers.roads {
+        svg.push_str(
+            r#"<g id="roads" fill="none" stroke-linecap="round" stroke-linejoin="round">"#,
+        );
+        svg.push('\n');
+        for (index, road) in map.roads.iter().enumerate().filter(|(_, r)| r.path.len() >= 2) {
+            let (color, opacity, stroke) = match road.road_type.as_str() {
+                "motorway" => ("rgb(200,80,40)", 0.95, 3.0),
+                "highway" => ("rgb(40,40,45)", 0.9, 2.0),
+                "road" => ("rgb(60,55,50)", 0.86, 1.0),
+                _ => ("rgb(80,70,60)", 0.78, 1.0),
+            };
+            let points = placed(&map.road_curve(index));
+            let _ = writeln!(
+                svg,
+                r#"  <polyline stroke="{}" stroke-opacity="{}" stroke-width="{}" points="{}"><title>{}</title></polyline>"#,
+                color,
+                opacity,
+                stroke,
+                points_attr(&points),
+                escape(&road.name)
+            );
+        }
+        svg.push_str("</g>\n");
+        seam_copies(&mut svg, map, "roads", s);
+
+        if !map.power_lines.is_empty() {
+            let pylons = (s * 0.25).max(1.5);
+            let _ = writeln!(
+                svg,
+                r#"<g id="power-lines" stroke="rgb(95,95,110)" stroke-width="{}" fill="rgb(95,95,110)">"#,
+                (s * 0.08).max(0.6)
+            );
+            for line in &map.power_lines {
+                let ends = placed(&map.unwrapped_path(&[line.from, line.to]));
+                let _ = writeln!(svg, r#"  <line x1="{}" y1="{}" x2="{}" y2="{}"/>"#, ends[0].0, ends[0].1, ends[1].0, ends[1].1);
+                let length = (ends[1].0 - ends[0].0).hypot(ends[1].1 - ends[0].1);
+                let count = (length / (s * 1.2).max(8.0)).floor() as usize;
+                for pylon in 1..count {
+                    let t = pylon as f32 / count as f32;
+                    let (x, y) = (ends[0].0 + (ends[1].0 - ends[0].0) * t, ends[0].1 + (ends[1].1 - ends[0].1) * t);
+                    let _ = writeln!(svg, r#"  <circle cx="{}" cy="{}" r="{}" stroke="none"/>"#, x, y, pylons);
+                }
+            }
+            svg.push_str("</g>\n");
+            seam_copies(&mut svg, map, "power-lines", s);
+        }
+    }
+
+    // Lights glow, so like the territories they are embedded as an image
+    if layers.lights {
+        let lights = Layers {
+            lights: true,
+            ..Layers::none()
+        };
+        embed_layer(&mut svg, "lights", map, scale, lights, options)?;
+    }
+
+    if layers.cities {
+        let size_factor = (s / 10.0).max(0.5);
+        svg.push_str("<g id=\"cities\">\n");
+        for city in &map.cities {
+            let (cx, cy) = at(city.x, city.y);
+            let (radius, color) = if city.population > 250000 {
+                (12.0, "rgb(220,20,20)")
+            } else if city.population > 100000 {
+                (9.0, "rgb(180,40,40)")
+            } else {
+                (6.0, "rgb(20,20,20)")
+            };
+            let radius = (radius * size_factor).floor();
+            if city.population > 100000 {
+                let _ = writeln!(
+                    svg,
+                    r#"  <circle cx="{}" cy="{}" r="{}" fill="none" stroke="rgb(20,20,20)" stroke-width="2"/>"#,
+                    cx,
+                    cy,
+                    radius + 3.0
+                );
+            }
+            let _ = writeln!(
+                svg,
+                r#"  <circle cx="{}" cy="{}" r="{}" fill="{}"><title>{}</title></circle>"#,
+                cx,
+                cy,
+                radius,
+                color,
+                escape(&city.name)
+            );
+        }
+        for poi in &map.pois {
+            let style = map.poi_style(&poi.kind);
+            let (cx, cy) = at(poi.position.0, poi.position.1);
+            let radius = style.icon_radius * size_factor;
+            let [r, g, b] = style.icon_color;
+            let (element, geometry) = match style.icon.outline(cx, cy, radius).as_slice() {
+                [] => ("circle", format!(r#"cx="{}" cy="{}" r="{}""#, cx, cy, radius)),
+                corners => ("polygon", format!(r#"points="{}""#, points_attr(corners))),
+            };
+            let _ = writeln!(
+                svg,
+                r#"  <{} {} fill="rgb({},{},{})" stroke="rgb(20,20,20)" class="poi-{}"><title>{}</title></{}>"#,
+                element,
+                geometry,
+                r,
+                g,
+                b,
+                escape(&poi.kind),
+                escape(&poi.name),
+                element
+            );
+        }
+        svg.push_str("</g>\n");
+        seam_copies(&mut svg, map, "cities", s);
+    }
+
+    if layers.clouds {
+        let clouds = Layers {
+            clouds: true,
+            ..Layers::none()
+        };
+        embed_layer(&mut svg, "clouds", map, scale, clouds, options)?;
+    }
+
+    if layers.labels {
+        // The parks' boundaries dotted, under their names
+        if !map.parks.is_empty() {
+            let _ = writeln!(
+                svg,
+                r#"<g id="parks" fill="none" stroke="{}" stroke-width="{:.1}" stroke-linecap="round" stroke-dasharray="0 {:.1}">"#,
+                svg_color(PARK_INK),
+                (s * 0.3).max(2.6),
+                (s * 0.9).max(6.0)
+            );
+            for run in map.park_boundaries() {
+                let run: Vec<(f32, f32)> = run.iter().map(|&(x, y)| (x * s, y * s)).collect();
+                let _ = writeln!(svg, r#"  <polyline points="{}"/>"#, points_attr(&run));
+            }
+            svg.push_str("</g>\n");
+        }
+
+        let text_size_factor = s.max(10.0) / 10.0;
+        svg.push_str(
+            r#"<g id="labels" font-family="DejaVu Sans, sans-serif" dominant-baseline="hanging" paint-order="stroke" stroke-linejoin="round">"#,
+        );
+        svg.push('\n');
+
+        for label in options.labels.region_labels(map) {
+            let (color, size) = match label.feature_type.as_str() {
+                _ if map.is_park(&label.name) => ("rgb(200,235,140)", 24.0),
+                "ocean" => ("rgb(150,200,255)", 32.0),
+                "mountains" => ("rgb(150,150,150)", 26.0),
+                "forest" => ("rgb(100,200,100)", 22.0),
+                "swamp" => ("rgb(150,180,150)", 22.0),
+                "river" => ("rgb(100,150,255)", 18.0),
+                _ => ("rgb(200,200,200)", 20.0),
+            };
+            text(
+                &mut svg,
+                (label.x * s, label.y * s),
+                size * text_size_factor,
+                color,
+                "black",
+                &label.name,
+            );
+        }
+
+        if layers.roads && options.labels.show_roads {
+            for road in &map.roads {
+                if matches!(road.road_type.as_str(), "highway" | "motorway") && road.path.len() > 10 {
+                    let (rx, ry) = road.path[road.path.len() / 2];
+                    text(
+                        &mut svg,
+                        (rx as f32 * s, ry as f32 * s),
+                        16.0 * text_size_factor,
+                        "rgb(60,60,60)",
+                        "white",
+                        &road.name,
+                    );
+                }
+            }
+        }
+        if layers.roads && options.labels.show_bridges {
+            for bridge in &map.bridges {
+                text(
+                    &mut svg,
+                    (bridge.x as f32 * s, bridge.y as f32 * s - s / 2.0),
+                    14.0 * text_size_factor,
+                    "rgb(80,60,40)",
+                    "white",
+                    &bridge.name,
+                );
+            }
+        }
+
+        // City names sit to the right of the dot; unlike the PNG export
+        // there is no collision avoidance, labels can be moved by hand
+        if layers.cities {
+            // Suburbs keep their own names at any scale, as they can be
+            // hidden by hand
+            for (index, _) in options.labels.city_labels(map, f32::INFINITY) {
+                let city = &map.cities[index];
+                let (cx, cy) = at(city.x, city.y);
+                let base_size = if city.population > 250000 {
+                    28.0
+                } else if city.population > 100000 {
+                    24.0
+                } else {
+                    20.0
+                };
+                let size = base_size * text_size_factor;
+                let x = cx + s + 5.0;
+                let y = cy - size / 2.0;
+                text(&mut svg, (x, y), size, "white", "black", &city.name);
+                if city.population > 100000 {
+                    let population = format!("({}k)", city.population / 1000);
+                    text(
+                        &mut svg,
+                        (x, y + size + 5.0),
+                        16.0 * text_size_factor,
+                        "rgb(200,200,200)",
+                        "black",
+                        &population,
+                    );
+                }
+            }
+            for poi in &map.pois {
+                let style = map.poi_style(&poi.kind);
+                if style.label_size <= 0.0 {
+                    continue;
+                }
+                let (cx, cy) = at(poi.position.0, poi.position.1);
+                let size = style.label_size * text_size_factor;
+                let [r, g, b] = style.label_color;
+                let x = cx + style.icon_radius * (s / 10.0).max(0.5) + 4.0;
+                text(&mut svg, (x, cy - size / 2.0), size, &format!("rgb({},{},{})", r, g, b), "black", &poi.name);
+            }
+        }
+        svg.push_str("</g>\n");
+        seam_copies(&mut svg, map, "labels", s);
+    }
+
+    svg.push_str("</svg>\n");
+    Ok(svg)
+}
+
+/// The raster `layers` of `map` as the group `id`, one embedded image.
+fn embed_layer(
+    svg: &mut String,
+    id: &str,
+    map: &TerrainMap,
+    scale: u32,
+    layers: Layers,
+    options: &RenderOptions,
+) -> Result<(), ImageError> {
+    let img = TerrainRenderer::render_layers_to_image(map, scale, layers, options);
+    let (width, height) = (img.width(), img.height());
+    let mut png = Vec::new();
+    image::DynamicImage::ImageRgba8(img).write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)?;
+    let _ = writeln!(
+        svg,
+        r#"<g id="{}"><image width="{}" height="{}" href="data:image/png;base64,{}"/></g>"#,
+        id,
+        width,
+        height,
+        STANDARD.encode(&png)
+    );
+    Ok(())
+}
+
+/// On a map that wraps east-west, the group `id` again a map's width to
+/// the west and to the east; `s` pixels per tile.
+fn seam_copies(svg: &mut String, map: &TerrainMap, id: &str, s: f32) {
+    for shift in map.wrap_shifts().into_iter().filter(|&shift| shift != 0.0) {
+        let _ = writeln!(svg, r##"<use href="#{}" x="{}"/>"##, id, shift * s);
+    }
+}
+
+fn svg_color([r, g, b]: [u8; 3]) -> String {
+    format!("rgb({},{},{})", r, g, b)
+}
+
+fn points_attr(points: &[(f32, f32)]) -> String {
+    points
+        .iter()
+        .map(|(x, y)| format!("{},{}", x, y))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// A haloed text label with its top-left corner at `pos`.
+fn text(svg: &mut String, pos: (f32, f32), size: f32, fill: &str, halo: &str, content: &str) {
+    let _ = writeln!(
+        svg,
+        r#"  <text x="{:.1}" y="{:.1}" font-size="{:.1}" fill="{}" stroke="{}" stroke-width="{:.1}">{}</text>"#,
+        pos.0,
+        pos.1,
+        size,
+        fill,
+        halo,
+        (size / 8.0).max(1.0),
+        escape(content)
+    );
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain_generator::test_map;
+
+    /// Rivers and roads without the rest
+    fn network() -> Layers {
+        Layers {
+            rivers: true,
+            roads: true,
+            ..Layers::none()
+        }
+    }
+
+    #[test]
+    fn disabled_layers_are_left_out() {
+        let map = test_map(4, 80, 60);
+        let svg = render_svg(map, 4, network(), &RenderOptions::default()).unwrap();
+        assert!(svg.contains(r#"<g id="rivers""#));
+        assert!(svg.contains(r#"<g id="roads""#));
+        assert!(!svg.contains("<image"));
+        assert!(!svg.contains("<text"));
+        assert!(!svg.contains(r#"<g id="cities""#));
+        assert!(!svg.contains("coastline"));
+    }
+
+    #[test]
+    fn every_layer_embeds_the_raster_and_traces_the_coast() {
+        let full = render_svg(test_map(4, 80, 60), 4, Layers::all(), &RenderOptions::default()).unwrap();
+        assert!(full.contains("data:image/png;base64,"));
+        assert!(full.contains(r#"<g id="coastline""#));
+        assert!(!full.contains("river-flow"));
+    }
+
+    #[test]
+    fn flow_arrows_mark_every_river_source() {
+        let map = test_map(4, 80, 60);
+        let arrows = RenderOptions { flow_arrows: true, ..RenderOptions::default() };
+        let svg = render_svg(map, 4, network(), &arrows).unwrap();
+        assert_eq!(svg.matches("<circle").count(), map.rivers.len());
+    }
+}
@@ -1,7 +1,11 @@
 use serde::{Deserialize, Serialize};
 
+use super::types::GenerationSettings;
 use super::TerrainGenerator;
 
+/// Land colder than this is an ice sheet on maps set colder than temperate
+pub(crate) const ICE_SHEET_TEMPERATURE: f64 = 0.02;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Biome {
     DeepOcean,
@@ -20,6 +24,14 @@ pub enum Biome {
 }
 
 impl Biome {
+    /// Whether land at `temperature` lies under an ice sheet. Only maps
+    /// whose `temperature` setting is colder than temperate, such as the
+    /// `frozen-north` preset, have them; elsewhere the cold ground keeps
+    /// the biome of its height.
+    pub(crate) fn is_ice_sheet(temperature: f64, settings: &GenerationSettings) -> bool {
+        settings.temperature < 0.5 && temperature < ICE_SHEET_TEMPERATURE
+    }
+
     pub fn is_water(&self) -> bool {
         matches!(
             self,
@@ -89,10 +101,23 @@ impl Biome {
 }
 
 impl TerrainGenerator {
+    pub(super) fn determine_biome(&self, elevation: f64, moisture: f64, temperature: f64) -> Biome {
+        Biome::classify(elevation, moisture, temperature, &self.settings)
+    }
+}
+
+impl Biome {
     /// Classify a tile. Elevation is histogram-equalized (its value is the
     /// area quantile), so each threshold below directly controls the share of
     /// water/land that biome covers.
-    pub(super) fn determine_biome(&self, elevation: f64, moisture: f64, temperature: f64) -> Biome {
+    pub(super) fn classify(
+        elevation: f64,
+        moisture: f64,
+        temperature: f64,
+        settings: &GenerationSettings,
+    ) -> Biome {
+        // Forest density moves the moisture forests need (0.5 leaves it)
+        let forest_shift = (settings.forest_density as f64 - 0.5) * 0.4;
         if elevation < -0.45 {
             // Deepest 45% of water
             Biome::DeepOcean
@@ -101,6 +126,9 @@ impl TerrainGenerator {
         } else if elevation < 0.0 {
             // Shallow water - the 5% of water nearest sea level
             Biome::Shore
+        } else if Self::is_ice_sheet(temperature, settings) {
+            // Frozen land: ice sheets where a cold map is bitterly cold
+            Biome::SnowPeaks
         } else if elevation < 0.04 {
             // Beaches - lowest 4% of land
             Biome::Beach
@@ -109,7 +137,7 @@ impl TerrainGenerator {
             if moisture > 0.85 {
                 // Swamps only in very wet areas (rare)
                 Biome::Swamp
-            } else if moisture > 0.55 {
+            } else if moisture > 0.55 - forest_shift {
                 // Coastal forests common
                 Biome::Forest
             } else if moisture < 0.25 && temperature > 0.7 {
@@ -123,7 +151,7 @@ impl TerrainGenerator {
             if moisture > 0.8 && temperature < 0.5 {
                 // Inland swamps (rare)
                 Biome::Swamp
-            } else if moisture > 0.5 {
+            } else if moisture > 0.5 - forest_shift {
                 Biome::Forest
             } else if moisture < 0.3 && temperature > 0.6 {
                 Biome::Desert
@@ -0,0 +1,299 @@
+//! Reusing phase outputs between runs whose settings differ only in what
+//! later phases read.
+//!
+//! Each phase reads a few settings: the elevation field the land share,
+//! formation, ruggedness and elevation noise; the climate the temperature,
+//! moisture, forests and moisture noise; rivers their density; and the
+//! settlement phases the city density and settlement weights (and the roads
+//! the number of edge roads). The shape mask goes with the elevation, and
+//! the name lists and point of interest kinds with the cities. A
+//! [`PhaseCache`] keeps the output of the last run after each stage, so a
+//! run that only changes, say, the city density starts again at the cities.
+
+use std::sync::Arc;
+
+use super::mask::ShapeMask;
+use super::poi::PoiKind;
+use super::progress::GenerationPhase;
+use super::random::PhaseRngs;
+use super::types::{GenerationSettings, TerrainMap, TerrainPoint};
+use super::TerrainGenerator;
+use crate::name_lists::NameLists;
+
+/// Everything a run's results depend on.
+#[derive(Debug, Clone)]
+pub(super) struct RunKey {
+    seed: u32,
+    width: usize,
+    height: usize,
+    /// The random streams as the run started
+    rngs: PhaseRngs,
+    settings: GenerationSettings,
+    shape_mask: Option<ShapeMask>,
+    name_lists: NameLists,
+    poi_kinds: Vec<PoiKind>,
+}
+
+impl RunKey {
+    /// Whether runs with these keys agree on every phase up to and
+    /// including `phase`.
+    fn agrees_through(&self, other: &RunKey, phase: GenerationPhase) -> bool {
+        let (a, b) = (&self.settings, &other.settings);
+        let mut same = self.seed == other.seed
+            && (self.width, self.height) == (other.width, other.height)
+            && self.rngs == other.rngs
+            && a.land_percentage == b.land_percentage
+            && a.formation == b.formation
+            && a.mountain_density == b.mountain_density
+            && a.elevation_noise == b.elevation_noise
+            && a.wrap_x == b.wrap_x
+            && a.symmetry == b.symmetry
+            && self.shape_mask == other.shape_mask;
+        if phase >= GenerationPhase::Climate {
+            same &= a.temperature == b.temperature
+                && a.moisture == b.moisture
+                && a.forest_density == b.forest_density
+                && a.moisture_noise == b.moisture_noise;
+        }
+        if phase >= GenerationPhase::Rivers {
+            same &= a.river_density == b.river_density;
+        }
+        if phase >= GenerationPhase::Cities {
+            same &= a.city_density == b.city_density
+                && a.settlement_weights == b.settlement_weights
+                && a.symmetric_cities == b.symmetric_cities
+                && self.name_lists == other.name_lists
+                && same_poi_kinds(&self.poi_kinds, &other.poi_kinds);
+        }
+        if phase >= GenerationPhase::Roads {
+            same &= a.edge_roads == b.edge_roads && a.modern == b.modern;
+        }
+        same
+    }
+}
+
+/// Whether `a` and `b` place the same points. Rules are told apart by
+/// identity, so a kind only matches its own clones (the built-in kinds
+/// share theirs between generators).
+fn same_poi_kinds(a: &[PoiKind], b: &[PoiKind]) -> bool {
+    a.len() == b.len()
+        && a.iter().zip(b).all(|(a, b)| {
+            a.style == b.style
+                && a.names == b.names
+                && a.placement.density == b.placement.density
+                && a.placement.spacing == b.placement.spacing
+                && Arc::ptr_eq(&a.placement.rule, &b.placement.rule)
+        })
+}
+
+/// The output of one stage, with the run it came from and the random
+/// streams as the stage left them.
+#[derive(Debug, Clone)]
+pub(super) struct Stage<T> {
+    key: RunKey,
+    rngs: PhaseRngs,
+    output: T,
+}
+
+/// Outputs of a generator's last run, stage by stage. Hand it to the next
+/// generator with [`TerrainGenerator::set_phase_cache`] and only the phases
+/// whose settings changed are run again. Holds up to three copies of the
+/// terrain grid.
+#[derive(Debug, Clone, Default)]
+pub struct PhaseCache {
+    pub(super) elevation: Option<Stage<Vec<Vec<f64>>>>,
+    /// The grid before rivers erode it
+    pub(super) climate: Option<Stage<Vec<Vec<TerrainPoint>>>>,
+    /// The map after the physical phases
+    pub(super) rivers: Option<Stage<TerrainMap>>,
+    /// The finished map
+    pub(super) map: Option<Stage<TerrainMap>>,
+}
+
+impl TerrainGenerator {
+    /// Reuse the outputs in `cache` where this generator's seed and
+    /// settings would produce them again, and keep this generator's
+    /// outputs in it.
+    pub fn set_phase_cache(&mut self, cache: PhaseCache) {
+        self.cache = Some(cache);
+    }
+
+    /// The cache, with the outputs of the last run.
+    pub fn take_phase_cache(&mut self) -> Option<PhaseCache> {
+        self.cache.take()
+    }
+
+    /// Key of a run starting now, if there is a cache to look in.
+    pub(super) fn run_key(&self, width: usize, height: usize) -> Option<RunKey> {
+        self.cache.as_ref().map(|_| RunKey {
+            seed: self.seed,
+            width,
+            height,
+            rngs: self.rngs.clone(),
+            settings: self.settings,
+            shape_mask: self.shape_mask.clone(),
+            name_lists: self.name_lists.clone(),
+            poi_kinds: self.poi_kinds.clone(),
+        })
+    }
+
+    /// The output `phase` would give in the run of `key`, if `stage` of the
+    /// cache holds it. The random streams are then restored to where the
+    /// phase left them, and it and the phases before it report completion.
+    pub(super) fn cached<T: Clone>(
+        &mut self,
+        key: Option<&RunKey>,
+        phase: GenerationPhase,
+        stage: fn(&PhaseCache) -> &Option<Stage<T>>,
+    ) -> Option<T> {
+        let key = key?;
+        let hit = stage(self.cache.as_ref()?)
+            .as_ref()
+            .filter(|stage| stage.key.agrees_through(key, phase))?;
+        let output = hit.output.clone();
+        self.rngs = hit.rngs.clone();
+        for done in GenerationPhase::ALL.into_iter().filter(|&p| p <= phase) {
+            self.report_progress(done, 1.0);
+        }
+        Some(output)
+    }
+
+    /// Keep `output` as `stage` of the run of `key`.
+    pub(super) fn keep<T: Clone>(
+        &mut self,
+        key: Option<&RunKey>,
+        stage: fn(&mut PhaseCache) -> &mut Option<Stage<T>>,
+        output: &T,
+    ) {
+        if let (Some(key), Some(cache)) = (key, self.cache.as_mut()) {
+            *stage(cache) = Some(Stage {
+                key: key.clone(),
+                rngs: self.rngs.clone(),
+                output: output.clone(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex, OnceLock};
+
+    use super::*;
+    use crate::terrain_generator::{test_map, SettlementWeights};
+
+    /// Generate the 120x90 map of seed 3 with `settings` from `cache`:
+    /// the map, the cache after the run, and the phases that ran. Those
+    /// served from the cache only report being done.
+    fn run(settings: GenerationSettings, cache: PhaseCache) -> (TerrainMap, PhaseCache, Vec<GenerationPhase>) {
+        let started = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&started);
+        let mut generator = TerrainGenerator::new_with_settings(3, settings);
+        generator.set_progress_callback(Box::new(move |phase, fraction| {
+            let mut log = log.lock().unwrap();
+            if fraction == 0.0 && log.last() != Some(&phase) {
+                log.push(phase);
+            }
+        }));
+        generator.set_phase_cache(cache);
+        let map = generator.generate(120, 90);
+        let ran = started.lock().unwrap().clone();
+        (map, generator.take_phase_cache().unwrap(), ran)
+    }
+
+    /// The cache of a run with default settings, made once
+    fn warm_cache() -> &'static PhaseCache {
+        static CACHE: OnceLock<PhaseCache> = OnceLock::new();
+        CACHE.get_or_init(|| run(GenerationSettings::default(), PhaseCache::default()).1)
+    }
+
+    fn json(map: &TerrainMap) -> String {
+        serde_json::to_string(map).unwrap()
+    }
+
+    /// Only the phases reading a changed setting run again, from
+    /// `first_rerun`, and the result is the map a fresh generator makes
+    fn assert_reruns_from(changed: GenerationSettings, first_rerun: GenerationPhase) {
+        let (cached, _, ran) = run(changed, warm_cache().clone());
+        let fresh = TerrainGenerator::new_with_settings(3, changed).generate(120, 90);
+        assert_eq!(json(&cached), json(&fresh));
+        assert_eq!(ran.first(), Some(&first_rerun));
+    }
+
+    #[test]
+    fn an_unchanged_run_is_served_from_the_cache() {
+        assert!(warm_cache().map.is_some());
+        let (again, _, ran) = run(GenerationSettings::default(), warm_cache().clone());
+        assert_eq!(json(&again), json(test_map(3, 120, 90)));
+        assert!(ran.is_empty());
+    }
+
+    #[test]
+    fn an_empty_cache_runs_every_phase() {
+        let (_, _, ran) = run(GenerationSettings::default(), PhaseCache::default());
+        assert_eq!(ran.len(), GenerationPhase::ALL.len());
+    }
+
+    #[test]
+    fn city_density_reruns_from_the_cities() {
+        assert_reruns_from(GenerationSettings::default().with_city_density(0.9), GenerationPhase::Cities);
+    }
+
+    #[test]
+    fn settlement_weights_rerun_from_the_cities() {
+        let changed = GenerationSettings::default().with_settlement_weights(SettlementWeights::RIVER_VALLEY);
+        assert_reruns_from(changed, GenerationPhase::Cities);
+    }
+
+    #[test]
+    fn river_density_reruns_from_the_rivers() {
+        assert_reruns_from(GenerationSettings::default().with_river_density(0.8), GenerationPhase::Rivers);
+    }
+
+    #[test]
+    fn temperature_reruns_from_the_climate() {
+        assert_reruns_from(GenerationSettings::default().with_temperature(0.3), GenerationPhase::Climate);
+    }
+
+    #[test]
+    fn a_mask_set_before_the_cache_is_not_served_the_old_shape() {
+        let mut generator = TerrainGenerator::new(3);
+        generator.set_phase_cache(PhaseCache::default());
+        let plain = generator.generate(60, 45);
+        let cache = generator.take_phase_cache().unwrap();
+
+        let inside: Vec<bool> = (0..16).map(|i| i % 4 < 2).collect();
+        let mask = ShapeMask::from_cells(4, 4, &inside);
+        let mut masked = TerrainGenerator::new(3);
+        masked.set_shape_mask(Some(mask.clone()));
+        masked.set_phase_cache(cache);
+        let map = masked.generate(60, 45);
+
+        let mut fresh = TerrainGenerator::new(3);
+        fresh.set_shape_mask(Some(mask));
+        let json = |map: &TerrainMap| serde_json::to_string(map).unwrap();
+        assert_eq!(json(&map), json(&fresh.generate(60, 45)));
+        assert_ne!(json(&map), json(&plain));
+    }
+
+    #[test]
+    fn generators_with_the_built_in_kinds_share_a_finished_map() {
+        let mut generator = TerrainGenerator::new(3);
+        generator.set_phase_cache(PhaseCache::default());
+        generator.generate(60, 45);
+        let cache = generator.take_phase_cache().unwrap();
+
+        let mut again = TerrainGenerator::new(3);
+        again.set_phase_cache(cache.clone());
+        let finished = again.cached(again.run_key(60, 45).as_ref(), GenerationPhase::Labels, |c| &c.map);
+        assert!(finished.is_some());
+
+        let mut renamed = TerrainGenerator::new(3);
+        let mut lists = NameLists::default();
+        lists.cities.names = vec!["Karak Azul".to_string()];
+        renamed.set_name_lists(lists);
+        renamed.set_phase_cache(cache);
+        let finished = renamed.cached(renamed.run_key(60, 45).as_ref(), GenerationPhase::Labels, |c| &c.map);
+        assert!(finished.is_none());
+    }
+}
@@ -1,7 +1,8 @@
 use std::collections::VecDeque;
 
-use noise::NoiseFn;
+use rayon::prelude::*;
 
+use super::types::{offset_tile, NoiseKind};
 use super::TerrainGenerator;
 
 impl TerrainGenerator {
@@ -25,17 +26,14 @@ impl TerrainGenerator {
         }
         while let Some((x, y)) = queue.pop_front() {
             let d = dist[y][x];
-            for dy in -1i32..=1 {
-                for dx in -1i32..=1 {
+            for dy in -1isize..=1 {
+                for dx in -1isize..=1 {
                     if dx == 0 && dy == 0 {
                         continue;
                     }
-                    let nx = x as i32 + dx;
-                    let ny = y as i32 + dy;
-                    if nx < 0 || ny < 0 || nx >= width as i32 || ny >= height as i32 {
+                    let Some((nx, ny)) = offset_tile(x, y, dx, dy, width, height, self.wrap_x) else {
                         continue;
-                    }
-                    let (nx, ny) = (nx as usize, ny as usize);
+                    };
                     if dist[ny][nx] == u32::MAX {
                         dist[ny][nx] = d + 1;
                         queue.push_back((nx, ny));
@@ -47,19 +45,35 @@ impl TerrainGenerator {
         // Moisture decays inland over roughly this many tiles
         let decay = (width.min(height) as f64 / 12.0).max(4.0);
 
-        let scale = 1.0 / width.min(height) as f64;
-        let mut moisture = vec![vec![0.0; width]; height];
-        for y in 0..height {
-            for x in 0..width {
-                let nx = x as f64 * scale;
-                let ny = y as f64 * scale;
-                let noise01 = self.moisture_noise.get([nx * 3.0, ny * 3.0]) * 0.5 + 0.5;
-                let ocean = (-(dist[y][x] as f64) / decay).exp();
-                moisture[y][x] = (noise01 * 0.55 + ocean * 0.45).clamp(0.0, 1.0);
-            }
-        }
+        // The moisture setting shifts every tile (0.5 leaves them)
+        let bias = (self.settings.moisture as f64 - 0.5) * 0.4;
 
-        moisture
+        let scale = 1.0 / width.min(height) as f64;
+        let gpu_noise = match self.settings.moisture_noise {
+            NoiseKind::Perlin => self.gpu_noise_field(self.seed.wrapping_add(1), 3.0, width, height),
+            _ => None,
+        };
+        (0..height)
+            .into_par_iter()
+            .map(|y| {
+                let noise: Vec<f64> = match &gpu_noise {
+                    Some(field) => field[y * width..(y + 1) * width].iter().map(|&v| v as f64).collect(),
+                    None => {
+                        let mut noise = vec![0.0; width];
+                        let points = Self::noise_points(y, width, scale, 3.0);
+                        self.moisture_noise.sample_row_wrapped(&points, self.noise_period(width, scale, 3.0), &mut noise);
+                        noise
+                    }
+                };
+                (0..width)
+                    .map(|x| {
+                        let noise01 = noise[x] * 0.5 + 0.5;
+                        let ocean = (-(dist[y][x] as f64) / decay).exp();
+                        (noise01 * 0.55 + ocean * 0.45 + bias).clamp(0.0, 1.0)
+                    })
+                    .collect()
+            })
+            .collect()
     }
 
     pub(super) fn generate_temperature(
@@ -73,14 +87,59 @@ impl TerrainGenerator {
         let scale = 1.0 / width.min(height) as f64;
         let nx = x as f64 * scale;
         let ny = y as f64 * scale;
+        let noise = self.temperature_noise.sample_wrapped([nx * 2.0, ny * 2.0], self.noise_period(width, scale, 2.0));
+        self.temperature_from_noise(noise, y, height, elevation)
+    }
+
+    /// The temperature noise of every tile, row by row, when the GPU
+    /// computes it; `temperature_noise_row` samples it on the CPU otherwise.
+    pub(super) fn temperature_noise_field(&self, width: usize, height: usize) -> Option<Vec<f32>> {
+        self.gpu_noise_field(self.seed.wrapping_add(2), 2.0, width, height)
+    }
 
+    /// The temperature noise of row `y`, the value `generate_temperature`
+    /// uses for each of its tiles.
+    pub(super) fn temperature_noise_row(&self, y: usize, width: usize, height: usize) -> Vec<f64> {
+        let scale = 1.0 / width.min(height) as f64;
+        let mut noise = vec![0.0; width];
+        let points = Self::noise_points(y, width, scale, 2.0);
+        self.temperature_noise.sample_row_wrapped(&points, self.noise_period(width, scale, 2.0), &mut noise);
+        noise
+    }
+
+    /// Where noise sampled as `noise_points` does repeats east-west: once
+    /// round a map that wraps, never on one that doesn't.
+    pub(super) fn noise_period(&self, width: usize, scale: f64, frequency: f64) -> Option<f64> {
+        self.wrap_x.then_some(width as f64 * scale * frequency)
+    }
+
+    /// Noise coordinates of row `y`: the tile coordinates times `scale`,
+    /// then times `frequency`, the way the climate fields sample noise.
+    fn noise_points(y: usize, width: usize, scale: f64, frequency: f64) -> Vec<[f64; 2]> {
+        let ny = y as f64 * scale;
+        (0..width)
+            .map(|x| [x as f64 * scale * frequency, ny * frequency])
+            .collect()
+    }
+
+    /// Temperature of a tile in row `y` whose temperature noise is `noise`.
+    pub(super) fn temperature_from_noise(
+        &self,
+        noise: f64,
+        y: usize,
+        height: usize,
+        elevation: f64,
+    ) -> f64 {
         // Temperature decreases with elevation and latitude
-        let base_temp = self.temperature_noise.get([nx * 2.0, ny * 2.0]) * 0.5 + 0.5;
+        let base_temp = noise * 0.5 + 0.5;
         let latitude_factor = (y as f64 / height as f64 - 0.5).abs() * 2.0;
         let elevation_factor = (elevation + 1.0) / 2.0;
 
-        let temperature =
-            base_temp * (1.0 - latitude_factor * 0.3) * (1.0 - elevation_factor * 0.4);
+        // The global temperature setting shifts the whole field (0.5 = none)
+        let climate_shift = (self.settings.temperature as f64 - 0.5) * 0.8;
+
+        let temperature = base_temp * (1.0 - latitude_factor * 0.3) * (1.0 - elevation_factor * 0.4)
+            + climate_shift;
         temperature.clamp(0.0, 1.0)
     }
 }
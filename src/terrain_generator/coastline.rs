@@ -0,0 +1,276 @@
+//! Coastlines as vector outlines.
+//!
+//! Land meets water (the sea and lakes; rivers count as land) halfway
+//! between a land tile and a water tile. Marching squares over the tile
+//! grid traces those crossings into closed rings, which Douglas-Peucker
+//! then simplifies to within `COAST_TOLERANCE`. Tiles touching only at a
+//! corner are joined when both are land, as the named regions join them.
+//! Outside the map counts as water, so land reaching an edge is closed off
+//! along it; [`Coastline::runs`] leaves those stretches out when drawing.
+//!
+//! Each ring keeps land on its left. The shore of a lake is a ring inside
+//! its landmass's and an island in the lake a ring inside that, so a point
+//! is on land when an odd number of rings enclose it, which
+//! [`TerrainMap::on_land`] answers without looking at the tiles.
+
+use std::collections::BTreeMap;
+
+use super::biome::Biome;
+use super::types::{Coastline, TerrainMap};
+
+/// Farthest a simplified coastline strays from the traced one, in tiles;
+/// under the 0.35 a tile is from the nearest crossing, so every tile stays
+/// on its side
+pub const COAST_TOLERANCE: f32 = 0.3;
+
+impl Coastline {
+    /// Whether (`x`, `y`) lies inside the ring, by the crossings of a ray
+    /// to the east.
+    pub fn encloses(&self, x: f32, y: f32) -> bool {
+        let Some(&last) = self.points.last() else {
+            return false;
+        };
+        let mut inside = false;
+        let mut from = last;
+        for &to in &self.points {
+            if (from.1 > y) != (to.1 > y) && x < from.0 + (to.0 - from.0) * (y - from.1) / (to.1 - from.1) {
+                inside = !inside;
+            }
+            from = to;
+        }
+        inside
+    }
+
+    /// The ring as open runs of points, without the stretches along the
+    /// edge of a `width` by `height` map; the whole ring, closed, if it
+    /// never reaches an edge.
+    pub fn runs(&self, width: usize, height: usize) -> Vec<Vec<(f32, f32)>> {
+        let points = &self.points;
+        let n = points.len();
+        let (width, height) = (width as f32, height as f32);
+        let on_edge = |i: usize| {
+            let (a, b) = (points[i], points[(i + 1) % n]);
+            (a.0 == b.0 && (a.0 == 0.0 || a.0 == width)) || (a.1 == b.1 && (a.1 == 0.0 || a.1 == height))
+        };
+        let Some(first) = (0..n).find(|&i| on_edge(i)) else {
+            let mut ring = points.clone();
+            ring.extend(points.first().copied());
+            return vec![ring];
+        };
+        let mut runs = Vec::new();
+        let mut run: Vec<(f32, f32)> = Vec::new();
+        for i in (1..=n).map(|k| (first + k) % n) {
+            if on_edge(i) {
+                if run.len() >= 2 {
+                    runs.push(std::mem::take(&mut run));
+                }
+                run.clear();
+            } else {
+                if run.is_empty() {
+                    run.push(points[i]);
+                }
+                run.push(points[(i + 1) % n]);
+            }
+        }
+        runs
+    }
+}
+
+impl TerrainMap {
+    /// Whether the point (`x`, `y`), in tile coordinates as in
+    /// [`Coastline`], is on land, by the rings in `coastlines`; by the
+    /// nearest tile on a map with none.
+    pub fn on_land(&self, x: f32, y: f32) -> bool {
+        let x = if self.wrap_x { x.rem_euclid(self.width as f32) } else { x };
+        if self.coastlines.is_empty() {
+            let tile = |v: f32, len: usize| (v.round().max(0.0) as usize).min(len.saturating_sub(1));
+            return self
+                .biome_at(tile(x, self.width), tile(y, self.height))
+                .is_some_and(|biome| !biome.is_water());
+        }
+        self.coastlines.iter().filter(|coast| coast.encloses(x, y)).count() % 2 == 1
+    }
+
+    /// Every coastline of the terrain, traced again; see the
+    /// [module docs](self).
+    pub(super) fn traced_coastlines(&self) -> Vec<Coastline> {
+        if self.terrain.is_empty() {
+            return Vec::new();
+        }
+        let (width, height) = (self.width as isize, self.height as isize);
+        let biome = |x: isize, y: isize| {
+            (x >= 0 && y >= 0 && x < width && y < height).then(|| self.terrain[y as usize][x as usize].biome)
+        };
+        let land = |x: isize, y: isize| biome(x, y).is_some_and(|b| !b.is_water());
+
+        // The water either side of a crossing, if it is on the map
+        let water_at = |(hx, hy): (isize, isize)| {
+            let sides = if hx % 2 != 0 {
+                [((hx - 1) / 2, hy / 2), ((hx + 1) / 2, hy / 2)]
+            } else {
+                [(hx / 2, (hy - 1) / 2), (hx / 2, (hy + 1) / 2)]
+            };
+            sides.into_iter().find(|&(x, y)| !land(x, y)).and_then(|(x, y)| biome(x, y))
+        };
+        let mut coastlines = Vec::new();
+        for ring in traced_rings(self.width, self.height, land) {
+            let lakes = ring.iter().filter(|&&at| water_at(at) == Some(Biome::Lake)).count();
+            coastlines.push(Coastline {
+                points: simplify_ring(&ring_points(&ring, self.width, self.height), COAST_TOLERANCE),
+                lake: lakes * 2 > ring.len(),
+            });
+        }
+        coastlines
+    }
+}
+
+/// The boundaries of the tiles of a `width` by `height` grid for which
+/// `inside` holds, as rings of crossings in half tiles with the inside on
+/// their left; see the [module docs](self). Outside the grid is outside.
+pub(super) fn traced_rings(width: usize, height: usize, inside: impl Fn(isize, isize) -> bool) -> Vec<Vec<(isize, isize)>> {
+    let (width, height) = (width as isize, height as isize);
+    let inside = |x: isize, y: isize| x >= 0 && y >= 0 && x < width && y < height && inside(x, y);
+
+    // Each step along a boundary, between crossings in half tiles, with
+    // the inside on its left. A cell's corners go clockwise from the top
+    // left, each followed by the edge to the next; the boundary enters
+    // where an edge runs from outside to inside and leaves by the nearest
+    // edge back anticlockwise that runs from inside to outside, which
+    // keeps tiles touching at a corner joined
+    let mut steps: BTreeMap<(isize, isize), (isize, isize)> = BTreeMap::new();
+    for y in -1..height {
+        for x in -1..width {
+            let corners = [inside(x, y), inside(x + 1, y), inside(x + 1, y + 1), inside(x, y + 1)];
+            let (hx, hy) = (2 * x, 2 * y);
+            let crossings = [(hx + 1, hy), (hx + 2, hy + 1), (hx + 1, hy + 2), (hx, hy + 1)];
+            for k in 0..4 {
+                if !corners[k] && corners[(k + 1) % 4] {
+                    let exit = (1..4)
+                        .map(|back| (k + 4 - back) % 4)
+                        .find(|&j| corners[j] && !corners[(j + 1) % 4])
+                        .expect("a boundary that enters a cell leaves it");
+                    steps.insert(crossings[k], crossings[exit]);
+                }
+            }
+        }
+    }
+
+    let mut rings = Vec::new();
+    while let Some((&start, _)) = steps.first_key_value() {
+        let mut ring = Vec::new();
+        let mut at = start;
+        while let Some(next) = steps.remove(&at) {
+            ring.push(at);
+            at = next;
+        }
+        rings.push(ring);
+    }
+    rings
+}
+
+/// The crossings of `ring` in tiles, those with the outside of a `width`
+/// by `height` grid on its edge.
+pub(super) fn ring_points(ring: &[(isize, isize)], width: usize, height: usize) -> Vec<(f32, f32)> {
+    // Crossings with the outside lie on the edge of the grid
+    let edge = |v: isize, len: usize| {
+        let v = v as f32 / 2.0;
+        if v < 0.0 {
+            0.0
+        } else if v > (len - 1) as f32 {
+            len as f32
+        } else {
+            v
+        }
+    };
+    ring.iter().map(|&(hx, hy)| (edge(hx, width), edge(hy, height))).collect()
+}
+
+/// `ring` with the points Douglas-Peucker finds within `tolerance` of the
+/// line through their neighbours left out, split for it at the first
+/// point and the one farthest from it.
+pub(super) fn simplify_ring(ring: &[(f32, f32)], tolerance: f32) -> Vec<(f32, f32)> {
+    if ring.len() <= 4 {
+        return ring.to_vec();
+    }
+    let from_first = |i: usize| (ring[i].0 - ring[0].0).hypot(ring[i].1 - ring[0].1);
+    let far = (1..ring.len()).max_by(|&a, &b| from_first(a).total_cmp(&from_first(b))).unwrap_or(0);
+    let mut closed = ring.to_vec();
+    closed.push(ring[0]);
+    let mut keep = vec![false; closed.len()];
+    keep[0] = true;
+    keep[far] = true;
+    let mut spans = vec![(0, far), (far, ring.len())];
+    while let Some((first, last)) = spans.pop() {
+        let (a, b) = (closed[first], closed[last]);
+        let farthest = (first + 1..last)
+            .map(|i| (i, distance_to_segment(closed[i], a, b)))
+            .max_by(|x, y| x.1.total_cmp(&y.1));
+        if let Some((i, _)) = farthest.filter(|&(_, distance)| distance > tolerance) {
+            keep[i] = true;
+            spans.push((first, i));
+            spans.push((i, last));
+        }
+    }
+    ring.iter().zip(keep).filter(|(_, keep)| *keep).map(|(&point, _)| point).collect()
+}
+
+fn distance_to_segment(p: (f32, f32), a: (f32, f32), b: (f32, f32)) -> f32 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let length = dx * dx + dy * dy;
+    let t = if length > 0.0 { (((p.0 - a.0) * dx + (p.1 - a.1) * dy) / length).clamp(0.0, 1.0) } else { 0.0 };
+    (p.0 - a.0 - t * dx).hypot(p.1 - a.1 - t * dy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain_generator::{test_map, TerrainPoint};
+
+    fn map() -> &'static TerrainMap {
+        test_map(5, 120, 90)
+    }
+
+    #[test]
+    fn generated_maps_keep_their_traced_coastlines() {
+        let map = map();
+        assert!(!map.coastlines.is_empty());
+        assert_eq!(map.coastlines, map.traced_coastlines());
+    }
+
+    #[test]
+    fn every_tile_is_on_the_side_of_the_coast_its_biome_says() {
+        let map = map();
+        for y in 0..map.height {
+            for x in 0..map.width {
+                assert_eq!(map.on_land(x as f32, y as f32), !map.is_water(x, y), "tile {},{}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn coastlines_are_simplified_and_stay_on_the_map() {
+        let map = map();
+        let points: usize = map.coastlines.iter().map(|c| c.points.len()).sum();
+        assert!(points > 0 && points < map.width * map.height / 4);
+        for coast in &map.coastlines {
+            assert!(coast.points.iter().all(|&(x, y)| (0.0..=120.0).contains(&x) && (0.0..=90.0).contains(&y)));
+        }
+    }
+
+    #[test]
+    fn an_island_with_a_lake_has_a_coast_and_a_shore() {
+        let point = |elevation, biome| TerrainPoint { elevation, moisture: 0.5, temperature: 0.5, biome };
+        let mut small = TerrainMap::empty(9, 9);
+        small.terrain = vec![vec![point(-0.5, Biome::Ocean); 9]; 9];
+        for row in &mut small.terrain[2..7] {
+            row[2..7].fill(point(0.3, Biome::Plains));
+        }
+        small.terrain[4][4] = point(0.2, Biome::Lake);
+        small.coastlines = small.traced_coastlines();
+        assert_eq!(small.coastlines.len(), 2);
+        assert_eq!(small.coastlines.iter().filter(|c| c.lake).count(), 1);
+        assert!(small.on_land(2.0, 2.0) && small.on_land(3.4, 4.0));
+        assert!(!small.on_land(1.4, 4.0) && !small.on_land(4.2, 4.1));
+        assert_eq!(small.coastlines.iter().map(|c| c.runs(9, 9).len()).sum::<usize>(), 2);
+    }
+}
@@ -0,0 +1,271 @@
+//! Cutting a rectangle out of a finished map as a map of its own.
+//!
+//! Everything inside the rectangle comes along, moved so the rectangle's
+//! corner is (0, 0): its terrain, the cities, bridges and points of
+//! interest standing in it, and the stretches of rivers and roads running
+//! through it, each stretch its own river or road. Labels standing inside
+//! keep their place. A region or river whose label stands outside is
+//! labelled again where enough of it lies inside: a region at the most
+//! interior point of its largest part, a river midway along its longest
+//! stretch.
+
+use std::collections::HashSet;
+
+use super::labels::{region_center, region_test};
+use super::region::Region;
+use super::types::{Bridge, City, PlaceLabel, PowerLine, Road, TerrainMap};
+
+/// Fewest tiles of a region inside the crop for its label to be moved in
+const MIN_REGION_TILES: usize = 30;
+
+/// Fewest tiles of a river inside the crop for its label to be moved in
+const MIN_RIVER_TILES: usize = 12;
+
+impl TerrainMap {
+    /// The part of the map inside `rect` as a standalone map, which can be
+    /// saved, rendered and queried like any other. `rect` is cut down to
+    /// the map; a rectangle wholly outside it gives a map with no tiles.
+    /// Only a crop the full width of a map that wraps east-west wraps too.
+    pub fn crop(&self, rect: Region) -> TerrainMap {
+        let Some(rect) = rect.clipped(self.width, self.height) else {
+            return TerrainMap {
+                seed: self.seed,
+                ..TerrainMap::empty(0, 0)
+            };
+        };
+        let inside = |&(x, y): &(usize, usize)| rect.contains(x, y);
+        let moved = |(x, y): (usize, usize)| (x - rect.x, y - rect.y);
+
+        let mut map = TerrainMap::empty(rect.width, rect.height);
+        map.seed = self.seed;
+        map.wrap_x = self.wrap_x && rect.width == self.width;
+        map.terrain = self.terrain[rect.y..rect.y + rect.height]
+            .iter()
+            .map(|row| row[rect.x..rect.x + rect.width].to_vec())
+            .collect();
+        map.rivers = self
+            .rivers
+            .iter()
+            .flat_map(|river| stretches_inside(river, rect))
+            .collect();
+        for road in &self.roads {
+            for path in stretches_inside(&road.path, rect) {
+                let bridges = road
+                    .bridges
+                    .iter()
+                    .filter(|b| inside(&(b.x, b.y)) && path.contains(&moved((b.x, b.y))))
+                    .map(|b| moved_bridge(b, rect))
+                    .collect();
+                let fords = road
+                    .fords
+                    .iter()
+                    .filter(|&at| inside(at) && path.contains(&moved(*at)))
+                    .map(|&at| moved(at))
+                    .collect();
+                map.roads.push(Road {
+                    path,
+                    name: road.name.clone(),
+                    road_type: road.road_type.clone(),
+                    bridges,
+                    fords,
+                });
+            }
+        }
+        map.bridges = self
+            .bridges
+            .iter()
+            .filter(|b| inside(&(b.x, b.y)))
+            .map(|b| moved_bridge(b, rect))
+            .collect();
+        for city in self.cities.iter().filter(|c| inside(&(c.x, c.y))) {
+            let (x, y) = moved((city.x, city.y));
+            map.cities.push(City { x, y, ..city.clone() });
+        }
+        for line in self.power_lines.iter().filter(|l| inside(&l.from) && inside(&l.to)) {
+            map.power_lines.push(PowerLine { from: moved(line.from), to: moved(line.to) });
+        }
+        for metro in self.metro_areas.iter().filter(|m| inside(&m.center)) {
+            let mut metro = metro.clone();
+            metro.center = moved(metro.center);
+            metro.suburbs = metro.suburbs.iter().copied().filter(inside).map(moved).collect();
+            map.metro_areas.push(metro);
+        }
+        for poi in self.pois.iter().filter(|p| inside(&p.position)) {
+            let mut poi = poi.clone();
+            poi.position = moved(poi.position);
+            map.pois.push(poi);
+        }
+        map.poi_styles = self.poi_styles.clone();
+        map.labels = self
+            .labels
+            .iter()
+            .filter_map(|label| self.cropped_label(label, rect, &map))
+            .collect();
+        map.regions = map.traced_regions();
+        map.parks = self.parks.clone();
+        map.prune_parks();
+        map.coastlines = map.traced_coastlines();
+        map.smoothed = map.smoothed_paths();
+        map
+    }
+
+    /// `label` moved into `cropped`, cut from this map at `rect`: where it
+    /// stood if that is inside, or anchored again on the part of its
+    /// region or river inside; `None` if too little of it is.
+    fn cropped_label(&self, label: &PlaceLabel, rect: Region, cropped: &TerrainMap) -> Option<PlaceLabel> {
+        let anchor = (label.x as usize, label.y as usize);
+        let (x, y) = if rect.contains(anchor.0, anchor.1) {
+            (label.x - rect.x as f32, label.y - rect.y as f32)
+        } else if label.feature_type == "river" {
+            let river = self.rivers.iter().find(|river| river.contains(&anchor))?;
+            let longest = stretches_inside(river, rect).into_iter().max_by_key(Vec::len)?;
+            if longest.len() < MIN_RIVER_TILES {
+                return None;
+            }
+            let (x, y) = longest[longest.len() / 2];
+            (x as f32, y as f32)
+        } else {
+            let belongs = region_test(&label.feature_type)?;
+            // The whole stretch the label names, then its largest part
+            // inside the crop
+            let mut stretch = HashSet::from([anchor]);
+            let mut stack = vec![anchor];
+            while let Some((x, y)) = stack.pop() {
+                for (dx, dy) in NEIGHBOURS {
+                    let Some(next) = self.offset(x, y, dx, dy) else {
+                        continue;
+                    };
+                    if self.biome_at(next.0, next.1).is_some_and(|b| belongs(&b)) && stretch.insert(next) {
+                        stack.push(next);
+                    }
+                }
+            }
+            let inside: HashSet<(usize, usize)> = stretch
+                .into_iter()
+                .filter(|&(x, y)| rect.contains(x, y))
+                .map(|(x, y)| (x - rect.x, y - rect.y))
+                .collect();
+            let part = largest_part(&inside, cropped);
+            if part.len() < MIN_REGION_TILES {
+                return None;
+            }
+            let (x, y) = region_center(&part, cropped.width, cropped.wrap_x);
+            (x as f32, y as f32)
+        };
+        Some(PlaceLabel { x, y, ..label.clone() })
+    }
+}
+
+const NEIGHBOURS: [(isize, isize); 8] = [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)];
+
+/// The runs of `path` inside `rect`, moved to the rectangle's corner, that
+/// are at least a step long.
+fn stretches_inside(path: &[(usize, usize)], rect: Region) -> Vec<Vec<(usize, usize)>> {
+    let mut stretches: Vec<Vec<(usize, usize)>> = vec![Vec::new()];
+    for &(x, y) in path {
+        if rect.contains(x, y) {
+            stretches.last_mut().unwrap().push((x - rect.x, y - rect.y));
+        } else if !stretches.last().unwrap().is_empty() {
+            stretches.push(Vec::new());
+        }
+    }
+    stretches.retain(|stretch| stretch.len() >= 2);
+    stretches
+}
+
+fn moved_bridge(bridge: &Bridge, rect: Region) -> Bridge {
+    Bridge {
+        x: bridge.x - rect.x,
+        y: bridge.y - rect.y,
+        name: bridge.name.clone(),
+    }
+}
+
+/// The largest connected part of `tiles` on `map`, in reading order.
+fn largest_part(tiles: &HashSet<(usize, usize)>, map: &TerrainMap) -> Vec<(usize, usize)> {
+    let mut seen: HashSet<(usize, usize)> = HashSet::new();
+    let mut largest = Vec::new();
+    // In reading order, so ties always go the same way
+    let mut starts: Vec<(usize, usize)> = tiles.iter().copied().collect();
+    starts.sort_by_key(|&(x, y)| (y, x));
+    for start in starts {
+        if !seen.insert(start) {
+            continue;
+        }
+        let mut part = vec![start];
+        let mut next = 0;
+        while let Some(&(x, y)) = part.get(next) {
+            next += 1;
+            for (dx, dy) in NEIGHBOURS {
+                if let Some(tile) = map.offset(x, y, dx, dy).filter(|t| tiles.contains(t)) {
+                    if seen.insert(tile) {
+                        part.push(tile);
+                    }
+                }
+            }
+        }
+        if part.len() > largest.len() {
+            part.sort_by_key(|&(x, y)| (y, x));
+            largest = part;
+        }
+    }
+    largest
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::OnceLock;
+
+    use super::*;
+    use crate::terrain_generator::test_map;
+
+    const RECT: Region = Region { x: 30, y: 20, width: 90, height: 70 };
+
+    fn map() -> &'static TerrainMap {
+        test_map(11, 160, 120)
+    }
+
+    fn crop() -> &'static TerrainMap {
+        static CROP: OnceLock<TerrainMap> = OnceLock::new();
+        CROP.get_or_init(|| map().crop(RECT))
+    }
+
+    #[test]
+    fn crops_carry_the_terrain_inside() {
+        let crop = crop();
+        assert_eq!((crop.width, crop.height, crop.terrain.len(), crop.terrain[0].len()), (90, 70, 70, 90));
+        assert_eq!(crop.terrain[5][7].elevation, map().terrain[25][37].elevation);
+    }
+
+    #[test]
+    fn crops_carry_the_cities_rivers_and_roads_inside_and_nothing_else() {
+        let (map, crop) = (map(), crop());
+        let cities = map.cities.iter().filter(|c| RECT.contains(c.x, c.y)).count();
+        assert!(cities > 0);
+        assert_eq!(crop.cities.len(), cities);
+        let in_bounds = |&(x, y): &(usize, usize)| x < crop.width && y < crop.height;
+        assert!(crop.rivers.iter().chain(crop.roads.iter().map(|r| &r.path)).flatten().all(in_bounds));
+        assert!(!crop.roads.is_empty() && !crop.rivers.is_empty());
+        // Road tiles come from roads of the whole map
+        let road_tiles = |m: &TerrainMap| m.roads.iter().flat_map(|r| r.path.iter().copied()).collect::<HashSet<_>>();
+        let all = road_tiles(map);
+        assert!(road_tiles(crop).iter().all(|&(x, y)| all.contains(&(x + RECT.x, y + RECT.y))));
+    }
+
+    #[test]
+    fn labels_stay_on_the_crop_and_on_the_terrain_they_name() {
+        let crop = crop();
+        for label in &crop.labels {
+            assert!(label.x >= 0.0 && (label.x as usize) < crop.width && (label.y as usize) < crop.height);
+            if label.feature_type != "river" {
+                assert_eq!(crop.region_of(label.x as usize, label.y as usize).map(|l| &l.name), Some(&label.name));
+            }
+        }
+    }
+
+    #[test]
+    fn crops_past_the_edge_are_clipped_to_the_map() {
+        assert_eq!(map().crop(Region { x: 150, y: 100, width: 50, height: 50 }).width, 10);
+        assert!(map().crop(Region { x: 200, y: 0, width: 5, height: 5 }).terrain.is_empty());
+    }
+}
@@ -0,0 +1,211 @@
+//! A coarse overview of a finished map, for thumbnails and zoomed-out
+//! views.
+//!
+//! Each block of `factor` by `factor` tiles becomes one tile: the mean of
+//! its elevation, moisture and temperature and the biome most of it has,
+//! so small islands and lakes fade out as they would from afar. Rivers
+//! and roads follow the blocks they ran through, and the cities in a block
+//! become one, named after the largest and as populous as all of them
+//! together. Rendering the overview is much cheaper than rendering the
+//! map and shrinking the image.
+
+use std::collections::HashMap;
+
+use super::biome::Biome;
+use super::types::{Bridge, City, PowerLine, TerrainMap, TerrainPoint};
+
+impl TerrainMap {
+    /// The map with each `factor` by `factor` block of tiles made one; see
+    /// the [module docs](self). Blocks along the right and bottom edges
+    /// may be smaller. A factor of 0 or 1 gives the map as it is.
+    pub fn downsample(&self, factor: usize) -> TerrainMap {
+        if factor <= 1 || self.terrain.is_empty() {
+            return self.clone();
+        }
+        let (width, height) = (self.width.div_ceil(factor), self.height.div_ceil(factor));
+        let block = |(x, y): (usize, usize)| (x / factor, y / factor);
+
+        let terrain = (0..height)
+            .map(|by| (0..width).map(|bx| self.block_summary(bx, by, factor)).collect())
+            .collect();
+        let mut map = TerrainMap {
+            width,
+            height,
+            terrain,
+            ..TerrainMap::empty(width, height)
+        };
+        map.seed = self.seed;
+        map.wrap_x = self.wrap_x;
+        map.rivers = self.rivers.iter().filter_map(|river| blocks_along(river, factor)).collect();
+        for road in &self.roads {
+            let Some(path) = blocks_along(&road.path, factor) else {
+                continue;
+            };
+            let mut road = road.clone();
+            road.path = path;
+            for bridge in &mut road.bridges {
+                (bridge.x, bridge.y) = block((bridge.x, bridge.y));
+            }
+            road.bridges.dedup_by_key(|b| (b.x, b.y));
+            road.fords = road.fords.iter().map(|&at| block(at)).collect();
+            road.fords.dedup();
+            map.roads.push(road);
+        }
+        for bridge in &self.bridges {
+            let at = block((bridge.x, bridge.y));
+            if !map.bridges.iter().any(|b| (b.x, b.y) == at) {
+                map.bridges.push(Bridge { x: at.0, y: at.1, name: bridge.name.clone() });
+            }
+        }
+
+        // One city per block, the largest first so it gives the name
+        let mut cities: Vec<&City> = self.cities.iter().collect();
+        cities.sort_by_key(|c| std::cmp::Reverse(c.population));
+        let mut merged: HashMap<(usize, usize), usize> = HashMap::new();
+        for city in cities {
+            let at = block((city.x, city.y));
+            match merged.get(&at) {
+                Some(&index) => map.cities[index].population += city.population,
+                None => {
+                    merged.insert(at, map.cities.len());
+                    map.cities.push(City { x: at.0, y: at.1, ..city.clone() });
+                }
+            }
+        }
+        for line in &self.power_lines {
+            let line = PowerLine { from: block(line.from), to: block(line.to) };
+            if line.from != line.to && !map.power_lines.contains(&line) {
+                map.power_lines.push(line);
+            }
+        }
+        for metro in &self.metro_areas {
+            let mut metro = metro.clone();
+            metro.center = block(metro.center);
+            let mut suburbs: Vec<(usize, usize)> = Vec::new();
+            for suburb in metro.suburbs.iter().map(|&s| block(s)) {
+                if suburb != metro.center && !suburbs.contains(&suburb) {
+                    suburbs.push(suburb);
+                }
+            }
+            metro.suburbs = suburbs;
+            map.metro_areas.push(metro);
+        }
+        for poi in &self.pois {
+            let mut poi = poi.clone();
+            poi.position = block(poi.position);
+            map.pois.push(poi);
+        }
+        map.poi_styles = self.poi_styles.clone();
+        for label in &self.labels {
+            let mut label = label.clone();
+            label.x /= factor as f32;
+            label.y /= factor as f32;
+            map.labels.push(label);
+        }
+        map.regions = map.traced_regions();
+        map.parks = self.parks.clone();
+        map.prune_parks();
+        map.coastlines = map.traced_coastlines();
+        map.smoothed = map.smoothed_paths();
+        map
+    }
+
+    /// Block (`bx`, `by`) of `factor` by `factor` tiles as one point.
+    fn block_summary(&self, bx: usize, by: usize, factor: usize) -> TerrainPoint {
+        let rows = by * factor..((by + 1) * factor).min(self.height);
+        let columns = bx * factor..((bx + 1) * factor).min(self.width);
+        let mut sums = [0.0; 3];
+        // Biomes in the order they first appear, so ties go to the first
+        let mut counts: Vec<(Biome, usize)> = Vec::new();
+        for row in &self.terrain[rows] {
+            for point in &row[columns.clone()] {
+                sums[0] += point.elevation;
+                sums[1] += point.moisture;
+                sums[2] += point.temperature;
+                match counts.iter_mut().find(|(biome, _)| *biome == point.biome) {
+                    Some((_, count)) => *count += 1,
+                    None => counts.push((point.biome, 1)),
+                }
+            }
+        }
+        let tiles = counts.iter().map(|(_, count)| count).sum::<usize>() as f64;
+        let mut biome = counts[0];
+        for &entry in &counts[1..] {
+            if entry.1 > biome.1 {
+                biome = entry;
+            }
+        }
+        TerrainPoint {
+            elevation: sums[0] / tiles,
+            moisture: sums[1] / tiles,
+            temperature: sums[2] / tiles,
+            biome: biome.0,
+        }
+    }
+}
+
+/// The blocks `path` runs through, each once per visit, or `None` if it
+/// never leaves its first block.
+fn blocks_along(path: &[(usize, usize)], factor: usize) -> Option<Vec<(usize, usize)>> {
+    let mut blocks: Vec<(usize, usize)> = path.iter().map(|&(x, y)| (x / factor, y / factor)).collect();
+    blocks.dedup();
+    (blocks.len() >= 2).then_some(blocks)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::OnceLock;
+
+    use super::*;
+    use crate::terrain_generator::test_map;
+
+    fn map() -> &'static TerrainMap {
+        test_map(6, 130, 90)
+    }
+
+    /// `map()` in blocks of four
+    fn overview() -> &'static TerrainMap {
+        static OVERVIEW: OnceLock<TerrainMap> = OnceLock::new();
+        OVERVIEW.get_or_init(|| map().downsample(4))
+    }
+
+    #[test]
+    fn overviews_summarize_each_block() {
+        let (map, overview) = (map(), overview());
+        assert_eq!((overview.width, overview.height), (33, 23));
+        assert_eq!((overview.terrain.len(), overview.terrain[0].len()), (23, 33));
+
+        let block: Vec<&TerrainPoint> = map.terrain[8..12].iter().flat_map(|row| &row[20..24]).collect();
+        let mean = block.iter().map(|p| p.elevation).sum::<f64>() / 16.0;
+        assert!((overview.terrain[2][5].elevation - mean).abs() < 1e-9);
+        let most = block.iter().filter(|p| p.biome == overview.terrain[2][5].biome).count();
+        assert!(block.iter().all(|p| block.iter().filter(|q| q.biome == p.biome).count() <= most));
+        assert_eq!(map.downsample(1).width, 130);
+    }
+
+    #[test]
+    fn partial_blocks_at_the_edges_average_only_the_tiles_there_are() {
+        let corner: Vec<f64> = map().terrain[88..90].iter().flat_map(|row| &row[128..130]).map(|p| p.elevation).collect();
+        assert!((overview().terrain[22][32].elevation - corner.iter().sum::<f64>() / 4.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn cities_merge_but_nobody_is_lost() {
+        let (map, overview) = (map(), overview());
+        let people = |m: &TerrainMap| m.cities.iter().map(|c| c.population as u64).sum::<u64>();
+        assert_eq!(people(overview), people(map));
+        assert!(overview.cities.len() <= map.cities.len());
+        let coarse = map.downsample(40);
+        assert!(coarse.cities.len() < map.cities.len());
+        let largest = map.cities.iter().max_by_key(|c| c.population).unwrap();
+        assert!(coarse.cities.iter().any(|c| c.name == largest.name));
+    }
+
+    #[test]
+    fn rivers_and_roads_stay_on_the_overview() {
+        let overview = overview();
+        for path in overview.rivers.iter().chain(overview.roads.iter().map(|r| &r.path)) {
+            assert!(path.iter().all(|&(x, y)| x < overview.width && y < overview.height));
+        }
+    }
+}
@@ -0,0 +1,178 @@
+//! How the rivers connect: where each one ends, which rivers feed it and
+//! its stream order. River paths already run from source to mouth; this
+//! adds the links between them, saved with the map as `river_flow`.
+
+use serde::{Deserialize, Serialize};
+
+use super::biome::Biome;
+use super::types::TerrainMap;
+
+/// Where a river ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RiverOutlet {
+    Sea,
+    Lake,
+    /// Joins the river with this index at a confluence
+    River(usize),
+    /// Runs off the edge of the map (the north or south edge of a map
+    /// that wraps east-west)
+    MapEdge,
+    /// Ends on dry land (only on hand-edited maps)
+    Land,
+}
+
+/// One river's place in the drainage network.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RiverFlow {
+    /// Index into `TerrainMap::rivers`
+    pub river: usize,
+    /// First tile of the path, the furthest upstream
+    pub source: (usize, usize),
+    /// Last tile of the path, the furthest downstream
+    pub mouth: (usize, usize),
+    pub outlet: RiverOutlet,
+    /// Rivers that join this one, from upstream to downstream
+    pub tributaries: Vec<usize>,
+    /// Strahler stream order at the mouth: 1 for a river nothing flows
+    /// into, rising by one where two streams of the same order meet
+    pub order: u32,
+}
+
+impl TerrainMap {
+    /// The drainage network of the map's rivers, one `RiverFlow` per
+    /// river in the same order.
+    pub fn river_flows(&self) -> Vec<RiverFlow> {
+        let mut flows: Vec<RiverFlow> = self
+            .rivers
+            .iter()
+            .enumerate()
+            .filter_map(|(index, path)| {
+                let (&source, &mouth) = (path.first()?, path.last()?);
+                Some(RiverFlow {
+                    river: index,
+                    source,
+                    mouth,
+                    outlet: self.outlet_of(index, mouth),
+                    tributaries: Vec::new(),
+                    order: 1,
+                })
+            })
+            .collect();
+
+        // Tributaries in the order they join along the main river
+        let mut joins: Vec<(usize, usize, usize)> = Vec::new();
+        for flow in &flows {
+            if let RiverOutlet::River(main) = flow.outlet {
+                let at = self.rivers[main].iter().position(|&p| p == flow.mouth).unwrap_or(0);
+                joins.push((main, at, flow.river));
+            }
+        }
+        joins.sort_unstable();
+        for &(main, _, tributary) in &joins {
+            if let Some(flow) = flows.iter_mut().find(|f| f.river == main) {
+                flow.tributaries.push(tributary);
+            }
+        }
+
+        let mut orders: Vec<Option<u32>> = vec![None; self.rivers.len()];
+        for index in 0..flows.len() {
+            let river = flows[index].river;
+            flows[index].order = strahler_order(river, &flows, &mut orders, 0);
+        }
+        flows
+    }
+
+    /// How river `index`, ending at `mouth`, ends.
+    fn outlet_of(&self, index: usize, mouth: (usize, usize)) -> RiverOutlet {
+        let joined = self
+            .rivers
+            .iter()
+            .enumerate()
+            .find(|&(other, path)| other != index && path.contains(&mouth));
+        if let Some((other, _)) = joined {
+            return RiverOutlet::River(other);
+        }
+        let (x, y) = mouth;
+        // The mouth tile is drawn as river, so the sea shows in its elevation
+        match self.terrain.get(y).and_then(|row| row.get(x)) {
+            Some(point) if point.biome == Biome::Lake => RiverOutlet::Lake,
+            Some(point) if point.elevation < 0.0 || point.biome.is_water() => RiverOutlet::Sea,
+            _ if y == 0 || y + 1 >= self.height => RiverOutlet::MapEdge,
+            _ if !self.wrap_x && (x == 0 || x + 1 >= self.width) => RiverOutlet::MapEdge,
+            _ => RiverOutlet::Land,
+        }
+    }
+}
+
+/// Strahler order of `river` at its mouth, walking up its tributaries in
+/// the order they join. Rivers form a tree; `depth` only guards against
+/// loops in hand-edited maps.
+fn strahler_order(river: usize, flows: &[RiverFlow], orders: &mut [Option<u32>], depth: usize) -> u32 {
+    if let Some(known) = orders[river] {
+        return known;
+    }
+    let mut order = 1;
+    if depth < flows.len() {
+        if let Some(flow) = flows.iter().find(|f| f.river == river) {
+            for &tributary in &flow.tributaries {
+                let joining = strahler_order(tributary, flows, orders, depth + 1);
+                order = if joining == order { order + 1 } else { order.max(joining) };
+            }
+        }
+    }
+    orders[river] = Some(order);
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain_generator::test_map;
+
+    fn map() -> &'static TerrainMap {
+        test_map(5, 160, 120)
+    }
+
+    #[test]
+    fn every_river_flows_from_its_source_to_its_mouth() {
+        let map = map();
+        let flows = map.river_flows();
+        assert_eq!(flows.len(), map.rivers.len());
+        assert!(flows.iter().any(|f| f.outlet == RiverOutlet::Sea));
+        for flow in &flows {
+            let path = &map.rivers[flow.river];
+            assert_eq!((flow.source, flow.mouth), (path[0], *path.last().unwrap()));
+        }
+    }
+
+    #[test]
+    fn tributaries_flow_into_rivers_of_at_least_their_order() {
+        let map = map();
+        let flows = map.river_flows();
+        for flow in &flows {
+            if let RiverOutlet::River(main) = flow.outlet {
+                assert!(map.rivers[main].contains(&flow.mouth));
+                assert!(flows[main].tributaries.contains(&flow.river));
+                assert!(flows[main].order >= flow.order);
+            }
+            if flow.tributaries.is_empty() {
+                assert_eq!(flow.order, 1);
+            }
+        }
+        assert!(flows.iter().any(|f| f.order >= 2));
+    }
+
+    #[test]
+    fn river_flows_are_saved_with_the_map() {
+        let map = map();
+        let flows = map.river_flows();
+        let path = std::env::temp_dir().join(format!("mapper-drainage-{}.json", std::process::id()));
+        map.save_json(&path).unwrap();
+        let saved: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        let loaded = TerrainMap::load_json(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(saved["river_flow"], serde_json::to_value(&flows).unwrap());
+        assert_eq!(loaded.river_flows(), flows);
+    }
+}
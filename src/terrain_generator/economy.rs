@@ -0,0 +1,341 @@
+//! A simple economy: what each city produces and needs, and the trade
+//! that evens it out over the roads.
+//!
+//! A city produces from the land of its territory (see
+//! [`TerrainMap::territories`]): grain on plains, timber in forests, ore and
+//! stone in the hills and mountains, salt on beaches and in deserts, and
+//! fish from the water around it. Every good is needed in proportion to
+//! population, so the map as a whole consumes what it makes; each surplus
+//! is shipped by road to the nearest cities short of it. Quantities are in
+//! units a year, one unit being a plains tile's grain harvest.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+use serde::Serialize;
+
+use super::biome::Biome;
+use super::travel::csv_field;
+use super::types::TerrainMap;
+
+/// Tiles around a city that its fishing boats reach
+const FISHING_RADIUS: usize = 4;
+
+/// Surpluses and shortfalls smaller than this are not traded
+const MIN_TRADE: f32 = 0.05;
+
+/// A trade good.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Good {
+    Grain,
+    Livestock,
+    Fish,
+    Timber,
+    Stone,
+    Ore,
+    Salt,
+}
+
+impl Good {
+    pub const ALL: [Good; 7] = [
+        Good::Grain,
+        Good::Livestock,
+        Good::Fish,
+        Good::Timber,
+        Good::Stone,
+        Good::Ore,
+        Good::Salt,
+    ];
+
+    /// Lower-case name, as in CSV headers and JSON.
+    pub fn name(self) -> &'static str {
+        match self {
+            Good::Grain => "grain",
+            Good::Livestock => "livestock",
+            Good::Fish => "fish",
+            Good::Timber => "timber",
+            Good::Stone => "stone",
+            Good::Ore => "ore",
+            Good::Salt => "salt",
+        }
+    }
+}
+
+/// What one land tile of `biome` yields in a year.
+fn land_yield(biome: Biome) -> &'static [(Good, f32)] {
+    match biome {
+        Biome::Plains => &[(Good::Grain, 1.0), (Good::Livestock, 0.4)],
+        Biome::Forest => &[(Good::Timber, 1.0), (Good::Livestock, 0.1)],
+        Biome::Hills => &[(Good::Livestock, 0.6), (Good::Stone, 0.5), (Good::Ore, 0.3)],
+        Biome::Mountains => &[(Good::Ore, 0.8), (Good::Stone, 0.6)],
+        Biome::SnowPeaks => &[(Good::Ore, 0.3)],
+        Biome::Swamp => &[(Good::Timber, 0.2)],
+        Biome::Desert => &[(Good::Salt, 0.4), (Good::Livestock, 0.1)],
+        Biome::Beach => &[(Good::Salt, 0.3)],
+        Biome::River => &[(Good::Grain, 0.6)],
+        Biome::DeepOcean | Biome::Ocean | Biome::Shore | Biome::Lake => &[],
+    }
+}
+
+/// Fish from one water tile of `biome` within reach of a city; the sea
+/// is only fished from ports.
+fn fish_yield(biome: Biome, port: bool) -> f32 {
+    match biome {
+        Biome::Shore | Biome::Ocean if port => 0.8,
+        Biome::DeepOcean if port => 0.2,
+        Biome::Lake => 0.6,
+        Biome::River => 0.3,
+        _ => 0.0,
+    }
+}
+
+/// One city's yearly production and needs, by good.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CityEconomy {
+    pub name: String,
+    pub population: u32,
+    pub produces: BTreeMap<Good, f32>,
+    pub demands: BTreeMap<Good, f32>,
+}
+
+impl CityEconomy {
+    /// Production less demand: positive for exports, negative for imports.
+    pub fn surplus(&self, good: Good) -> f32 {
+        self.produces.get(&good).copied().unwrap_or(0.0) - self.demands.get(&good).copied().unwrap_or(0.0)
+    }
+}
+
+/// Goods shipped by road from one city to another each year.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TradeFlow {
+    pub good: Good,
+    /// Indices into the map's cities
+    pub from: usize,
+    pub to: usize,
+    pub amount: f32,
+    /// Length of the road route in tiles
+    pub distance: f64,
+}
+
+/// The economy of a whole map.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Economy {
+    /// In the map's city order
+    pub cities: Vec<CityEconomy>,
+    pub flows: Vec<TradeFlow>,
+}
+
+impl Economy {
+    /// One row per city: name, population, then production and demand of
+    /// each good.
+    pub fn cities_csv(&self) -> String {
+        let mut csv = String::from("city,population");
+        for good in Good::ALL {
+            let _ = write!(csv, ",{0}_produced,{0}_demanded", good.name());
+        }
+        csv.push('\n');
+        for city in &self.cities {
+            let _ = write!(csv, "{},{}", csv_field(&city.name), city.population);
+            for good in Good::ALL {
+                let produced = city.produces.get(&good).copied().unwrap_or(0.0);
+                let demanded = city.demands.get(&good).copied().unwrap_or(0.0);
+                let _ = write!(csv, ",{:.1},{:.1}", produced, demanded);
+            }
+            csv.push('\n');
+        }
+        csv
+    }
+
+    /// One row per trade flow: good, from, to, amount and road distance.
+    pub fn flows_csv(&self) -> String {
+        let mut csv = String::from("good,from,to,amount,distance\n");
+        for flow in &self.flows {
+            let _ = writeln!(
+                csv,
+                "{},{},{},{:.1},{:.1}",
+                flow.good.name(),
+                csv_field(&self.cities[flow.from].name),
+                csv_field(&self.cities[flow.to].name),
+                flow.amount,
+                flow.distance
+            );
+        }
+        csv
+    }
+}
+
+impl TerrainMap {
+    /// Work out what every city produces and needs, and the trade between
+    /// them over the road network.
+    pub fn economy(&self) -> Economy {
+        let territories = self.territories();
+        let mut produces: Vec<BTreeMap<Good, f32>> = vec![BTreeMap::new(); self.cities.len()];
+        for (tile, owner) in territories.owner.iter().enumerate() {
+            let Some(city) = *owner else {
+                continue;
+            };
+            let biome = self.terrain[tile / self.width][tile % self.width].biome;
+            for &(good, amount) in land_yield(biome) {
+                *produces[city].entry(good).or_default() += amount;
+            }
+        }
+        for (city, output) in self.cities.iter().zip(&mut produces) {
+            let around = |reach: usize| {
+                let xs = city.x.saturating_sub(reach)..=(city.x + reach).min(self.width - 1);
+                let ys = city.y.saturating_sub(reach)..=(city.y + reach).min(self.height - 1);
+                ys.flat_map(move |y| xs.clone().map(move |x| (x, y)))
+            };
+            let port = around(1).any(|(x, y)| {
+                matches!(self.terrain[y][x].biome, Biome::Shore | Biome::Ocean | Biome::DeepOcean)
+            });
+            let fish: f32 = around(FISHING_RADIUS)
+                .map(|(x, y)| fish_yield(self.terrain[y][x].biome, port))
+                .sum();
+            if fish > 0.0 {
+                *output.entry(Good::Fish).or_default() += fish;
+            }
+        }
+
+        // The whole map needs what it makes, shared out by population
+        let population: f32 = self.cities.iter().map(|c| c.population as f32).sum();
+        let mut cities: Vec<CityEconomy> = self
+            .cities
+            .iter()
+            .zip(produces)
+            .map(|(city, produces)| CityEconomy {
+                name: city.name.clone(),
+                population: city.population,
+                produces,
+                demands: BTreeMap::new(),
+            })
+            .collect();
+        for good in Good::ALL {
+            let total: f32 = cities.iter().filter_map(|c| c.produces.get(&good)).sum();
+            if total <= 0.0 || population <= 0.0 {
+                continue;
+            }
+            for city in &mut cities {
+                city.demands.insert(good, total * city.population as f32 / population);
+            }
+        }
+
+        let flows = self.trade_flows(&cities);
+        Economy { cities, flows }
+    }
+
+    /// Ship each surplus to the cities short of that good, nearest by
+    /// road first, until one side runs out.
+    fn trade_flows(&self, cities: &[CityEconomy]) -> Vec<TradeFlow> {
+        let network = self.road_network();
+        let mut distances: HashMap<(usize, usize), Option<f64>> = HashMap::new();
+        let mut flows = Vec::new();
+
+        for good in Good::ALL {
+            let mut surplus: Vec<f32> = cities.iter().map(|c| c.surplus(good)).collect();
+            let mut pairs = Vec::new();
+            for from in (0..cities.len()).filter(|&i| surplus[i] > MIN_TRADE) {
+                for to in (0..cities.len()).filter(|&i| surplus[i] < -MIN_TRADE) {
+                    let key = (from.min(to), from.max(to));
+                    let distance = *distances
+                        .entry(key)
+                        .or_insert_with(|| network.shortest_route(key.0, key.1).map(|r| r.length));
+                    if let Some(distance) = distance {
+                        pairs.push((distance, from, to));
+                    }
+                }
+            }
+            pairs.sort_by(|a, b| a.0.total_cmp(&b.0).then((a.1, a.2).cmp(&(b.1, b.2))));
+
+            for (distance, from, to) in pairs {
+                let amount = surplus[from].min(-surplus[to]);
+                if amount < MIN_TRADE {
+                    continue;
+                }
+                surplus[from] -= amount;
+                surplus[to] += amount;
+                flows.push(TradeFlow {
+                    good,
+                    from,
+                    to,
+                    amount,
+                    distance,
+                });
+            }
+        }
+        flows
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::OnceLock;
+
+    use crate::terrain_generator::test_map;
+
+    use super::*;
+
+    fn map() -> &'static TerrainMap {
+        test_map(12, 160, 120)
+    }
+
+    /// The map's economy, worked out once
+    fn economy() -> &'static Economy {
+        static ECONOMY: OnceLock<Economy> = OnceLock::new();
+        let economy = ECONOMY.get_or_init(|| map().economy());
+        assert_eq!(economy.cities.len(), map().cities.len());
+        assert!(!economy.flows.is_empty());
+        economy
+    }
+
+    #[test]
+    fn everything_produced_is_demanded_somewhere() {
+        let economy = economy();
+        for good in Good::ALL {
+            let produced: f32 = economy.cities.iter().filter_map(|c| c.produces.get(&good)).sum();
+            let demanded: f32 = economy.cities.iter().filter_map(|c| c.demands.get(&good)).sum();
+            assert!((produced - demanded).abs() <= produced * 1e-3 + 1e-3, "{:?}", good);
+        }
+    }
+
+    #[test]
+    fn some_city_lands_fish() {
+        let fish = |c: &CityEconomy| c.produces.get(&Good::Fish).copied().unwrap_or(0.0);
+        assert!(economy().cities.iter().any(|c| fish(c) > 0.0));
+    }
+
+    #[test]
+    fn surpluses_flow_by_road_to_shortfalls() {
+        let economy = economy();
+        let network = map().road_network();
+        for flow in &economy.flows {
+            let (from, to) = (&economy.cities[flow.from], &economy.cities[flow.to]);
+            assert!(from.surplus(flow.good) > 0.0 && to.surplus(flow.good) < 0.0);
+            let route = network.shortest_route(flow.from, flow.to).unwrap();
+            assert!((route.length - flow.distance).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn no_city_ships_more_than_its_surplus() {
+        let economy = economy();
+        for (index, city) in economy.cities.iter().enumerate() {
+            for good in Good::ALL {
+                let shipped: f32 = economy
+                    .flows
+                    .iter()
+                    .filter(|f| f.good == good && f.from == index)
+                    .map(|f| f.amount)
+                    .sum();
+                assert!(shipped <= city.surplus(good).max(0.0) + 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn csvs_have_a_row_for_every_city_and_flow() {
+        let economy = economy();
+        assert_eq!(economy.cities_csv().lines().count(), map().cities.len() + 1);
+        assert_eq!(economy.flows_csv().lines().count(), economy.flows.len() + 1);
+    }
+}
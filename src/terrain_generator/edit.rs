@@ -0,0 +1,533 @@
+//! Hand edits to a finished map: brush strokes on the terrain, then
+//! re-deriving the layers that depend on it.
+
+use super::biome::Biome;
+use super::modern::MOTORWAY;
+use super::names::UsedNames;
+use super::progress::GenerationPhase;
+use super::settlements::Crossings;
+use super::types::{Bridge, City, GenerationSettings, Road, TerrainMap, TerrainPoint};
+use super::TerrainGenerator;
+
+/// What a brush does to the tiles it covers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BrushEffect {
+    /// Add up to this much elevation at the centre (negative lowers),
+    /// fading out towards the edge
+    Elevation(f64),
+    /// Turn every tile under the brush into this biome
+    Stamp(Biome),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Brush {
+    /// In tiles
+    pub radius: f64,
+    pub effect: BrushEffect,
+}
+
+/// Which layers [`TerrainGenerator::rederive`] rebuilds. Labels always are.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rederive {
+    pub rivers: bool,
+    pub roads: bool,
+}
+
+impl TerrainMap {
+    /// Apply one dab of `brush` centred on tile coordinates (x, y).
+    /// Raised or lowered tiles are classified again with `settings`;
+    /// stamped tiles also get an elevation and moisture that fit their new
+    /// biome. Rivers, roads and cities are left alone until
+    /// [`TerrainGenerator::rederive`]. Returns whether any tile changed.
+    pub fn apply_brush(
+        &mut self,
+        x: f64,
+        y: f64,
+        brush: &Brush,
+        settings: &GenerationSettings,
+    ) -> bool {
+        let r = brush.radius.max(0.5);
+        let x0 = (x - r).floor().max(0.0) as usize;
+        let y0 = (y - r).floor().max(0.0) as usize;
+        let x1 = ((x + r).ceil().max(0.0) as usize).min(self.width);
+        let y1 = ((y + r).ceil().max(0.0) as usize).min(self.height);
+
+        let mut changed = false;
+        for ty in y0..y1 {
+            for tx in x0..x1 {
+                // Distance from the tile centre, as a share of the radius
+                let dx = tx as f64 + 0.5 - x;
+                let dy = ty as f64 + 0.5 - y;
+                let d = (dx * dx + dy * dy).sqrt() / r;
+                if d > 1.0 {
+                    continue;
+                }
+                let point = &mut self.terrain[ty][tx];
+                match brush.effect {
+                    BrushEffect::Elevation(amount) => {
+                        let falloff = 1.0 - d * d * (3.0 - 2.0 * d); // smoothstep
+                        point.elevation = (point.elevation + amount * falloff).clamp(-1.0, 1.0);
+                        // River and lake marks stay until the rivers are retraced
+                        let hydro = matches!(point.biome, Biome::River | Biome::Lake);
+                        if !(hydro && point.elevation > 0.0) {
+                            point.biome = Biome::classify(
+                                point.elevation,
+                                point.moisture,
+                                point.temperature,
+                                settings,
+                            );
+                        }
+                    }
+                    BrushEffect::Stamp(biome) => stamp(point, biome),
+                }
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Index of the city closest to (x, y) within `radius` tiles.
+    pub fn city_near(&self, x: f64, y: f64, radius: f64) -> Option<usize> {
+        self.cities
+            .iter()
+            .enumerate()
+            .map(|(i, city)| {
+                let dx = city.x as f64 + 0.5 - x;
+                let dy = city.y as f64 + 0.5 - y;
+                (i, (dx * dx + dy * dy).sqrt())
+            })
+            .filter(|&(_, d)| d <= radius)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(i, _)| i)
+    }
+
+    /// Whether a city could stand on tile (x, y): dry land inside the map
+    /// that no other city (apart from `ignore`) already occupies.
+    fn city_site_free(&self, x: usize, y: usize, ignore: Option<usize>) -> bool {
+        self.biome_at(x, y).is_some_and(|biome| !biome.is_water())
+            && self
+                .cities
+                .iter()
+                .enumerate()
+                .all(|(i, city)| Some(i) == ignore || (city.x, city.y) != (x, y))
+    }
+
+    /// Found a city on tile (x, y) and return its index, or `None` if the
+    /// tile is water, off the map or taken. Roads are not connected to it
+    /// until [`TerrainGenerator::rederive`].
+    pub fn add_city(&mut self, x: usize, y: usize, name: &str, population: u32) -> Option<usize> {
+        if !self.city_site_free(x, y, None) {
+            return None;
+        }
+        self.cities.push(City {
+            x,
+            y,
+            name: name.to_string(),
+            population,
+        });
+        Some(self.cities.len() - 1)
+    }
+
+    /// Move city `index` to tile (x, y), under the same rules as
+    /// [`TerrainMap::add_city`]. Returns whether it moved.
+    pub fn move_city(&mut self, index: usize, x: usize, y: usize) -> bool {
+        if index >= self.cities.len() || !self.city_site_free(x, y, Some(index)) {
+            return false;
+        }
+        let city = &mut self.cities[index];
+        let from = (city.x, city.y);
+        city.x = x;
+        city.y = y;
+        for metro in &mut self.metro_areas {
+            for tile in std::iter::once(&mut metro.center).chain(&mut metro.suburbs) {
+                if *tile == from {
+                    *tile = (x, y);
+                }
+            }
+        }
+        for line in &mut self.power_lines {
+            for end in [&mut line.from, &mut line.to] {
+                if *end == from {
+                    *end = (x, y);
+                }
+            }
+        }
+        true
+    }
+
+    /// Remove city `index`, and its metro area if it was the center. Its
+    /// roads stay until they are rerouted.
+    pub fn remove_city(&mut self, index: usize) -> Option<City> {
+        let city = (index < self.cities.len()).then(|| self.cities.remove(index))?;
+        self.prune_metro_areas();
+        self.prune_power_lines();
+        Some(city)
+    }
+
+    /// Index of the road passing closest to (x, y) within `radius` tiles.
+    pub fn road_near(&self, x: f64, y: f64, radius: f64) -> Option<usize> {
+        self.roads
+            .iter()
+            .enumerate()
+            .flat_map(|(i, road)| {
+                road.path.iter().map(move |&(rx, ry)| {
+                    let dx = rx as f64 + 0.5 - x;
+                    let dy = ry as f64 + 0.5 - y;
+                    (i, (dx * dx + dy * dy).sqrt())
+                })
+            })
+            .filter(|&(_, d)| d <= radius)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(i, _)| i)
+    }
+
+    /// Remove road `index` together with the bridges no other road
+    /// crosses by.
+    pub fn remove_road(&mut self, index: usize) -> Option<Road> {
+        if index >= self.roads.len() {
+            return None;
+        }
+        if self.smoothed.roads.len() == self.roads.len() {
+            self.smoothed.roads.remove(index);
+        }
+        let road = self.roads.remove(index);
+        let same = |a: &Bridge, b: &Bridge| (a.x, a.y) == (b.x, b.y) && a.name == b.name;
+        let roads = &self.roads;
+        self.bridges.retain(|bridge| {
+            !road.bridges.iter().any(|b| same(b, bridge))
+                || roads.iter().any(|other| other.bridges.iter().any(|b| same(b, bridge)))
+        });
+        Some(road)
+    }
+}
+
+/// Give a tile `biome`, moving its elevation (and moisture, for the biomes
+/// that depend on it) into the range `Biome::classify` gives that biome, so
+/// later reclassification and river tracing agree with the stamp.
+fn stamp(point: &mut TerrainPoint, biome: Biome) {
+    let e = point.elevation;
+    point.elevation = match biome {
+        Biome::DeepOcean => e.min(-0.5),
+        Biome::Ocean | Biome::Lake => e.clamp(-0.45, -0.06),
+        Biome::Shore => e.clamp(-0.05, -0.01),
+        Biome::Beach => e.clamp(0.01, 0.03),
+        Biome::Hills => e.clamp(0.6, 0.81),
+        Biome::Mountains => e.clamp(0.82, 0.94),
+        Biome::SnowPeaks => e.max(0.95),
+        Biome::Plains | Biome::Forest | Biome::Swamp | Biome::Desert | Biome::River => {
+            e.clamp(0.05, 0.59)
+        }
+    };
+    point.moisture = match biome {
+        Biome::Forest => point.moisture.max(0.7),
+        Biome::Swamp => point.moisture.max(0.9),
+        Biome::Desert => point.moisture.min(0.2),
+        Biome::Plains => point.moisture.clamp(0.3, 0.45),
+        _ => point.moisture,
+    };
+    point.biome = biome;
+}
+
+impl TerrainGenerator {
+    /// A fresh procedural name for a city added by hand.
+    pub fn name_city(&mut self, map: &TerrainMap) -> String {
+        self.used_names = UsedNames::of_map(map);
+        self.generate_city_name(map.cities.len())
+    }
+
+    /// Lay a road of `road_type` ("motorway", "highway", "road" or "trail") from one
+    /// tile to another along the cheapest path the generator would take,
+    /// crossing rivers by the bridges and fords already there where it can
+    /// and bridging (or, for a trail, fording) them elsewhere. Returns the new road's index, or
+    /// `None` if an end is in water or off the map, or no land path joins
+    /// them. Rerouting roads with [`TerrainGenerator::rederive`] replaces
+    /// hand-drawn roads with generated ones.
+    pub fn build_road(
+        &mut self,
+        map: &mut TerrainMap,
+        from: (usize, usize),
+        to: (usize, usize),
+        road_type: &str,
+    ) -> Option<usize> {
+        let on_land = |(x, y): (usize, usize)| map.biome_at(x, y).is_some_and(|b| !b.is_water());
+        if from == to || !on_land(from) || !on_land(to) {
+            return None;
+        }
+        self.wrap_x = map.wrap_x;
+        let mut crossings = Crossings::of_map(map);
+        let path = self.find_path(&map.terrain, &crossings, from.0, from.1, to.0, to.1);
+        if path.len() < 2 {
+            return None;
+        }
+
+        let (bridges, fords) =
+            self.detect_crossings(&path, road_type, &mut crossings, &map.terrain, &mut map.bridges);
+        self.used_names = UsedNames::of_map(map);
+        let kind = match road_type {
+            "highway" => "Highway",
+            "trail" => "Trail",
+            _ => "Road",
+        };
+        let name = if road_type == MOTORWAY {
+            self.generate_motorway_name()
+        } else {
+            self.generate_road_name(map.roads.len(), |d| format!("{} {}", d, kind))
+        };
+        map.roads.push(Road {
+            path,
+            name,
+            road_type: road_type.to_string(),
+            bridges,
+            fords,
+        });
+        if map.smoothed.roads.len() + 1 == map.roads.len() {
+            let curve = map.smoothed_road(map.roads.len() - 1);
+            map.smoothed.roads.push(curve);
+        }
+        Some(map.roads.len() - 1)
+    }
+
+    /// Rebuild the layers that depend on hand-edited terrain. Rivers are
+    /// traced again from scratch (without eroding the terrain a second
+    /// time); cities left standing in water are removed, and roads are
+    /// routed again between the rest. Labels always follow the new map.
+    pub fn rederive(&mut self, map: &mut TerrainMap, what: Rederive) {
+        self.wrap_x = map.wrap_x;
+        if what.rivers {
+            self.report_progress(GenerationPhase::Rivers, 0.0);
+            for point in map.terrain.iter_mut().flatten() {
+                if matches!(point.biome, Biome::River | Biome::Lake) {
+                    point.biome =
+                        self.determine_biome(point.elevation, point.moisture, point.temperature);
+                }
+            }
+            map.rivers = self.generate_hydrology(&mut map.terrain);
+            for &(x, y) in map.rivers.iter().flatten() {
+                let point = &mut map.terrain[y][x];
+                if point.biome != Biome::Lake {
+                    point.biome = Biome::River;
+                }
+            }
+            self.report_progress(GenerationPhase::Rivers, 1.0);
+        }
+
+        map.coastlines = map.traced_coastlines();
+        let terrain = &map.terrain;
+        map.cities
+            .retain(|city| !terrain[city.y][city.x].biome.is_water());
+        map.prune_metro_areas();
+        map.prune_power_lines();
+        let rerun = if what.roads { GenerationPhase::Roads } else { GenerationPhase::Labels };
+        self.used_names = UsedNames::kept_by(map, rerun);
+
+        if what.roads {
+            self.report_progress(GenerationPhase::Roads, 0.0);
+            let (roads, bridges) =
+                self.generate_roads(&map.terrain, &map.cities, &map.metro_areas, &map.rivers);
+            map.roads = roads;
+            map.bridges = bridges;
+            map.power_lines = if self.settings.modern { map.strung_power_lines() } else { Vec::new() };
+            self.report_progress(GenerationPhase::Roads, 1.0);
+        }
+
+        self.report_progress(GenerationPhase::Labels, 0.0);
+        (map.labels, map.regions) = self.generate_labels(&map.terrain, &map.rivers);
+        map.parks.clear();
+        map.designate_parks();
+        self.report_progress(GenerationPhase::Labels, 0.5);
+        self.place_points_of_interest(map);
+        map.smoothed = map.smoothed_paths();
+        self.report_progress(GenerationPhase::Labels, 1.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain_generator::test_map;
+
+    /// A map with ocean stamped over its first city, and that city
+    fn flooded_city() -> (TerrainMap, City) {
+        let mut map = test_map(21, 160, 120).clone();
+        let city = map.cities[0].clone();
+        let flood = Brush {
+            radius: 4.0,
+            effect: BrushEffect::Stamp(Biome::Ocean),
+        };
+        let (cx, cy) = (city.x as f64 + 0.5, city.y as f64 + 0.5);
+        assert!(map.apply_brush(cx, cy, &flood, &GenerationSettings::default()));
+        (map, city)
+    }
+
+    #[test]
+    fn stamped_water_sinks_below_the_sea() {
+        let (map, city) = flooded_city();
+        let point = map.point_at(city.x, city.y).unwrap();
+        assert_eq!(point.biome, Biome::Ocean);
+        assert!(point.elevation < 0.0);
+    }
+
+    #[test]
+    fn raising_brushes_lift_the_ground_on_the_map_only() {
+        let settings = GenerationSettings::default();
+        let mut map = test_map(21, 160, 120).clone();
+        let raise = Brush {
+            radius: 3.0,
+            effect: BrushEffect::Elevation(0.5),
+        };
+        let before = map.point_at(20, 20).unwrap().elevation;
+        map.apply_brush(20.5, 20.5, &raise, &settings);
+        assert!(map.point_at(20, 20).unwrap().elevation > before);
+        assert!(!map.apply_brush(-50.0, -50.0, &raise, &settings));
+    }
+
+    #[test]
+    fn rederived_rivers_and_roads_follow_stamped_water() {
+        let (mut map, city) = flooded_city();
+        TerrainGenerator::new(21).rederive(
+            &mut map,
+            Rederive {
+                rivers: true,
+                roads: true,
+            },
+        );
+        assert!(map.cities.iter().all(|c| c.name != city.name));
+        for &(x, y) in map.rivers.iter().flatten() {
+            assert!(matches!(
+                map.terrain[y][x].biome,
+                Biome::River | Biome::Lake
+            ));
+        }
+        for road in &map.roads {
+            assert!(road.path.iter().all(|&(x, y)| (x, y) != (city.x, city.y)));
+        }
+    }
+
+    /// The first `count` tiles of `map` a city may be put on
+    fn free_sites(map: &TerrainMap, count: usize) -> Vec<(usize, usize)> {
+        (0..map.height)
+            .flat_map(|y| (0..map.width).map(move |x| (x, y)))
+            .filter(|&(x, y)| map.city_site_free(x, y, None))
+            .take(count)
+            .collect()
+    }
+
+    #[test]
+    fn cities_are_only_added_on_free_land() {
+        let mut map = test_map(8, 120, 90).clone();
+        let water = map.terrain.iter().flatten().position(|p| p.biome.is_water()).unwrap();
+        assert_eq!(map.add_city(water % map.width, water / map.width, "Atlantis", 1000), None);
+        let site = free_sites(&map, 1)[0];
+        assert!(map.add_city(site.0, site.1, "Newton", 42_000).is_some());
+        assert_eq!(map.add_city(site.0, site.1, "Twin", 1), None);
+    }
+
+    #[test]
+    fn moved_cities_are_found_where_they_went() {
+        let mut map = test_map(8, 120, 90).clone();
+        let sites = free_sites(&map, 2);
+        let added = map.add_city(sites[0].0, sites[0].1, "Newton", 42_000).unwrap();
+        assert!(map.move_city(added, sites[1].0, sites[1].1));
+        assert_eq!(
+            map.city_near(sites[1].0 as f64, sites[1].1 as f64, 1.0),
+            Some(added)
+        );
+    }
+
+    #[test]
+    fn removed_cities_are_gone() {
+        let mut map = test_map(8, 120, 90).clone();
+        let removed = map.remove_city(1).unwrap();
+        assert!(map.find_city(&removed.name).is_none());
+    }
+
+    #[test]
+    fn rederived_roads_reach_an_added_city() {
+        let mut generator = TerrainGenerator::new(8);
+        let mut map = test_map(8, 120, 90).clone();
+        let site = free_sites(&map, 2)[1];
+        let name = generator.name_city(&map);
+        map.add_city(site.0, site.1, &name, 42_000).unwrap();
+        generator.rederive(
+            &mut map,
+            Rederive {
+                rivers: false,
+                roads: true,
+            },
+        );
+        let city = map.find_city(&name).unwrap();
+        assert!(map
+            .roads
+            .iter()
+            .any(|road| road.path.contains(&(city.x, city.y))));
+    }
+
+    #[test]
+    fn edited_cities_survive_save_and_load() {
+        let mut map = test_map(8, 120, 90).clone();
+        let site = free_sites(&map, 1)[0];
+        map.add_city(site.0, site.1, "Newton", 42_000).unwrap();
+        map.cities[0].name = "Renamed".to_string();
+        map.cities[0].population = 7;
+        map.remove_city(1).unwrap();
+
+        let path = std::env::temp_dir().join(format!("mapper-edit-{}.json", std::process::id()));
+        map.save_json(&path).unwrap();
+        let loaded = TerrainMap::load_json(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(
+            serde_json::to_string(&loaded.cities).unwrap(),
+            serde_json::to_string(&map.cities).unwrap()
+        );
+        assert_eq!(loaded.cities[0].name, "Renamed");
+    }
+
+    /// A map with a highway drawn between its first two cities, the
+    /// road's index and its ends
+    fn drawn_highway() -> (TerrainMap, usize, [(usize, usize); 2]) {
+        let mut map = test_map(13, 120, 90).clone();
+        let (a, b) = (&map.cities[0], &map.cities[1]);
+        let ends = [(a.x, a.y), (b.x, b.y)];
+        let index = TerrainGenerator::new(13).build_road(&mut map, ends[0], ends[1], "highway").unwrap();
+        (map, index, ends)
+    }
+
+    #[test]
+    fn drawn_roads_run_over_land_between_their_ends() {
+        let (map, index, [from, to]) = drawn_highway();
+        let road = &map.roads[index];
+        assert_eq!(road.path.first(), Some(&from));
+        assert_eq!(road.path.last(), Some(&to));
+        assert!(road.name.ends_with("Highway"));
+        assert!(road
+            .path
+            .iter()
+            .all(|&(x, y)| !map.terrain[y][x].biome.is_water()));
+        for bridge in &road.bridges {
+            assert!(map.bridges.iter().any(|b| b.name == bridge.name));
+        }
+    }
+
+    #[test]
+    fn removing_a_road_keeps_the_bridges_others_share() {
+        let (mut map, index, _) = drawn_highway();
+        let road = &map.roads[index];
+        let (mx, my) = road.path[road.path.len() / 2];
+        let near = map
+            .road_near(mx as f64 + 0.5, my as f64 + 0.5, 0.5)
+            .unwrap();
+        let removed = map.remove_road(near).unwrap();
+        assert_eq!(map.roads.len(), test_map(13, 120, 90).roads.len());
+        for bridge in &removed.bridges {
+            let shared = map.roads.iter().any(|road| road.bridges.iter().any(|b| b.name == bridge.name));
+            assert_eq!(map.bridges.iter().any(|b| b.name == bridge.name), shared);
+        }
+    }
+
+    #[test]
+    fn a_road_needs_two_ends() {
+        let mut map = test_map(13, 120, 90).clone();
+        let from = (map.cities[0].x, map.cities[0].y);
+        assert_eq!(TerrainGenerator::new(13).build_road(&mut map, from, from, "road"), None);
+    }
+}
@@ -1,9 +1,11 @@
-use noise::NoiseFn;
 use rand::Rng;
 use rand_chacha::ChaCha8Rng;
+use rayon::prelude::*;
 
-use super::types::GenerationSettings;
-use super::TerrainGenerator;
+use super::mask::{shape_bias, MASK_FILL, MASK_WARP};
+use super::progress::{GenerationError, GenerationPhase};
+use super::types::{Formation, GenerationSettings};
+use super::{TerrainGenerator, ROW_BATCH};
 
 /// A soft elliptical bump of elevation. Every continent formation is built
 /// from a handful of these; the fractal noise on top supplies all coastline
@@ -17,16 +19,54 @@ struct Blob {
     aspect: f64, // >1.0 stretches the blob along `angle`
 }
 
+/// Squared normalized distance past which a blob's contribution is below
+/// 1e-17: too small to change `1.0 - contribution`, so it is skipped
+const FAR_BLOB_D2: f64 = 16.0;
+
+/// A [`Blob`] with the terms that are the same for every tile worked out
+/// once, since it is evaluated for every tile of the map.
+pub(super) struct PlacedBlob {
+    pub(super) x: f64,
+    pub(super) y: f64,
+    pub(super) cos: f64,
+    pub(super) sin: f64,
+    pub(super) aspect: f64,
+    pub(super) radius2: f64,
+    pub(super) weight: f64,
+}
+
 impl Blob {
+    fn placed(&self) -> PlacedBlob {
+        PlacedBlob {
+            x: self.x,
+            y: self.y,
+            cos: self.angle.cos(),
+            sin: self.angle.sin(),
+            aspect: self.aspect,
+            radius2: self.radius * self.radius,
+            weight: self.weight,
+        }
+    }
+}
+
+impl PlacedBlob {
     /// Contribution of this blob at map-normalized (nx, ny): a smooth
     /// Gaussian-like falloff from `weight` at the center to 0 outside.
-    fn contribution(&self, nx: f64, ny: f64) -> f64 {
-        let dx = nx - self.x;
+    /// With `wrap_x` the blob is measured to the short way round the map,
+    /// so one near the seam spills across it.
+    fn contribution(&self, nx: f64, ny: f64, wrap_x: bool) -> f64 {
+        let mut dx = nx - self.x;
+        if wrap_x {
+            dx -= dx.round();
+        }
         let dy = ny - self.y;
         // Rotate into the blob's frame and apply elongation
-        let along = (dx * self.angle.cos() + dy * self.angle.sin()) / self.aspect;
-        let across = -dx * self.angle.sin() + dy * self.angle.cos();
-        let d2 = (along * along + across * across) / (self.radius * self.radius);
+        let along = (dx * self.cos + dy * self.sin) / self.aspect;
+        let across = -dx * self.sin + dy * self.cos;
+        let d2 = (along * along + across * across) / self.radius2;
+        if d2 > FAR_BLOB_D2 {
+            return 0.0;
+        }
         self.weight * (-2.5 * d2).exp()
     }
 }
@@ -34,19 +74,31 @@ impl Blob {
 /// The large-scale layout of landmasses for one map, drawn once per
 /// generation from the seeded RNG.
 pub(super) struct ContinentPlan {
-    blobs: Vec<Blob>,
+    pub(super) blobs: Vec<PlacedBlob>,
     /// Allow land to touch the map edge (no edge falloff).
-    edge_continent: bool,
+    pub(super) edge_continent: bool,
+    /// The map wraps east-west: blobs spill across the seam, and only the
+    /// north and south edges fall off
+    pub(super) wrap_x: bool,
 }
 
 impl ContinentPlan {
-    pub(super) fn new(rng: &mut ChaCha8Rng, settings: &GenerationSettings) -> Self {
+    pub(super) fn new(rng: &mut ChaCha8Rng, settings: &GenerationSettings, wrap_x: bool) -> Self {
         let land = settings.land_percentage as f64;
         let mut blobs = Vec::new();
 
-        let formation = rng.gen_range(0..5);
+        // Always roll, even when the settings pin the formation, so the rest
+        // of the plan draws the same random numbers either way
+        let rolled = match rng.gen_range(0..5) {
+            0 => Formation::IslandChain,
+            1 => Formation::Spine,
+            2 => Formation::IslandArc,
+            3 => Formation::Plates,
+            _ => Formation::Archipelago,
+        };
+        let formation = settings.formation.unwrap_or(rolled);
         match formation {
-            0 => {
+            Formation::IslandChain => {
                 // Volcanic island chain along a curved line (Hawaii, Aleutians)
                 let n = rng.gen_range(5..10);
                 let angle = rng.gen_range(0.0..std::f64::consts::TAU);
@@ -70,7 +122,7 @@ impl ContinentPlan {
                     });
                 }
             }
-            1 => {
+            Formation::Spine => {
                 // Elongated continent with a mountainous spine
                 let angle = rng.gen_range(0.0..std::f64::consts::TAU);
                 let cx = rng.gen_range(0.4..0.6);
@@ -89,7 +141,7 @@ impl ContinentPlan {
                     });
                 }
             }
-            2 => {
+            Formation::IslandArc => {
                 // Crescent / island arc (Japan, Indonesia)
                 let ccx = rng.gen_range(0.35..0.65);
                 let ccy = rng.gen_range(0.35..0.65);
@@ -110,7 +162,7 @@ impl ContinentPlan {
                     });
                 }
             }
-            3 => {
+            Formation::Plates => {
                 // A few large plates forming a complex landmass
                 let n = 2 + (land * 2.0) as usize + rng.gen_range(0..2);
                 for _ in 0..n {
@@ -124,7 +176,27 @@ impl ContinentPlan {
                     });
                 }
             }
-            _ => {
+            Formation::InlandSea => {
+                // A ring of overlapping plates around an open central sea;
+                // the ring's inner gap is what the sea level floods
+                let ccx = rng.gen_range(0.45..0.55);
+                let ccy = rng.gen_range(0.45..0.55);
+                let ring_r = rng.gen_range(0.30..0.36);
+                let n = rng.gen_range(8..12);
+                let start = rng.gen_range(0.0..std::f64::consts::TAU);
+                for i in 0..n {
+                    let theta = start + std::f64::consts::TAU * (i as f64 / n as f64);
+                    blobs.push(Blob {
+                        x: ccx + theta.cos() * ring_r + rng.gen_range(-0.04..0.04),
+                        y: ccy + theta.sin() * ring_r + rng.gen_range(-0.04..0.04),
+                        radius: rng.gen_range(0.14..0.20),
+                        weight: rng.gen_range(0.85..1.0),
+                        angle: theta + std::f64::consts::FRAC_PI_2,
+                        aspect: rng.gen_range(1.2..1.8),
+                    });
+                }
+            }
+            Formation::Archipelago => {
                 // Archipelago: clusters of islands with power-law sizes
                 let clusters = rng.gen_range(2..4);
                 for _ in 0..clusters {
@@ -160,9 +232,12 @@ impl ContinentPlan {
             });
         }
 
+        // An inland sea is enclosed by land, so its ring runs off the map
+        let edge_continent = rng.gen_bool(0.25) || formation == Formation::InlandSea;
         ContinentPlan {
-            blobs,
-            edge_continent: rng.gen_bool(0.25),
+            blobs: blobs.iter().map(Blob::placed).collect(),
+            edge_continent,
+            wrap_x,
         }
     }
 
@@ -173,7 +248,7 @@ impl ContinentPlan {
         // stacking, so plate boundaries don't produce walls.
         let mut sea_prob = 1.0;
         for blob in &self.blobs {
-            sea_prob *= 1.0 - blob.contribution(nx, ny).clamp(0.0, 1.0);
+            sea_prob *= 1.0 - blob.contribution(nx, ny, self.wrap_x).clamp(0.0, 1.0);
         }
         let mask = 1.0 - sea_prob; // [0, 1]
         mask * 1.6 - 0.8
@@ -183,105 +258,199 @@ impl ContinentPlan {
 impl TerrainGenerator {
     /// Generate the full elevation field for the map.
     ///
-    /// Elevation is domain-warped fractal noise biased by the continent plan.
-    /// The sea level is then chosen as the exact (1 - land_percentage)
-    /// quantile of the generated values, so the land/water ratio matches the
-    /// settings for every seed and formation type. Returned values are
-    /// normalized to [-1, 0) for water and (0, 1] for land.
+    /// Elevation is domain-warped fractal noise biased by the continent plan,
+    /// or by the shape mask if there is one. The sea level is then chosen as
+    /// the exact (1 - land_percentage) quantile of the generated values, so
+    /// the land/water ratio matches the settings for every seed and
+    /// formation type (or as much as the shape holds). A symmetric map is
+    /// made symmetric before then. Returned values are normalized to
+    /// [-1, 0) for water and (0, 1] for land.
     pub(super) fn generate_elevation_field(
         &mut self,
         width: usize,
         height: usize,
-    ) -> Vec<Vec<f64>> {
-        let plan = ContinentPlan::new(&mut self.rng, &self.settings);
+    ) -> Result<Vec<Vec<f64>>, GenerationError> {
+        let plan = ContinentPlan::new(&mut self.rngs.terrain, &self.settings, self.wrap_x);
+        let mut raw = match self.gpu_raw_elevation(&plan, width, height) {
+            Some(raw) => raw?,
+            None => self.raw_elevation(&plan, width, height)?,
+        };
+        self.settings.symmetry.apply(&mut raw);
+        let mut land = self.settings.land_percentage as f64;
+        if let Some(mask) = &self.shape_mask {
+            let inside = (0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .filter(|&(x, y)| mask.contains(x as f64 / width as f64, y as f64 / height as f64))
+                .count();
+            land = land.min(MASK_FILL * inside as f64 / (width * height) as f64);
+        }
+        self.equalize_elevation(&mut raw, width, land);
+        Ok(raw)
+    }
 
+    /// The elevation before equalization, sampled on the CPU.
+    fn raw_elevation(
+        &mut self,
+        plan: &ContinentPlan,
+        width: usize,
+        height: usize,
+    ) -> Result<Vec<Vec<f64>>, GenerationError> {
         // Isotropic noise coordinates (same frequency on both axes)
         let iso = 1.0 / width.min(height) as f64;
+        // On a map that wraps, noise at frequency f repeats every
+        // `width * iso * f` along x (see `sample_row_wrapped`)
+        let period = |frequency: f64| plan.wrap_x.then_some(width as f64 * iso * frequency);
 
         let mut raw = vec![vec![0.0f64; width]; height];
-        for (y, row) in raw.iter_mut().enumerate() {
-            for (x, value) in row.iter_mut().enumerate() {
-                let nx = x as f64 / width as f64;
-                let ny = y as f64 / height as f64;
-                let ax = x as f64 * iso;
+        // Noise sampling is most of the work; rows are independent
+        for (batch, rows) in raw.chunks_mut(ROW_BATCH).enumerate() {
+            self.checkpoint()?;
+            rows.par_iter_mut().enumerate().for_each(|(i, row)| {
+                let y = batch * ROW_BATCH + i;
                 let ay = y as f64 * iso;
+                // Noise is sampled a whole row at a time (see `sample_row`)
+                let mut points = vec![[0.0; 2]; width];
+                let mut noise = vec![0.0; width];
 
                 // Domain warp: perturb the sample position with low-frequency
                 // noise so coastlines and ranges meander instead of following
                 // the blob geometry.
                 let warp = 0.35;
-                let wx = self.detail_noise.get([ax * 2.0 + 31.4, ay * 2.0 + 47.2]);
-                let wy = self.detail_noise.get([ax * 2.0 + 73.1, ay * 2.0 + 11.9]);
-                let qx = ax + wx * warp;
-                let qy = ay + wy * warp;
+                for (x, point) in points.iter_mut().enumerate() {
+                    *point = [x as f64 * iso * 2.0 + 31.4, ay * 2.0 + 47.2];
+                }
+                let mut wx = vec![0.0; width];
+                self.detail_noise.sample_row_wrapped(&points, period(2.0), &mut wx);
+                for (x, point) in points.iter_mut().enumerate() {
+                    *point = [x as f64 * iso * 2.0 + 73.1, ay * 2.0 + 11.9];
+                }
+                let mut wy = vec![0.0; width];
+                self.detail_noise.sample_row_wrapped(&points, period(2.0), &mut wy);
+                let q: Vec<[f64; 2]> = (0..width)
+                    .map(|x| [x as f64 * iso + wx[x] * warp, ay + wy[x] * warp])
+                    .collect();
 
                 // 5-octave fBm for terrain detail
                 let mut amp = 1.0;
                 let mut freq = 2.0;
-                let mut sum = 0.0;
+                let mut sum = vec![0.0; width];
                 let mut norm = 0.0;
                 for _ in 0..5 {
-                    sum += self.elevation_noise.get([qx * freq, qy * freq]) * amp;
+                    for (point, &[qx, qy]) in points.iter_mut().zip(&q) {
+                        *point = [qx * freq, qy * freq];
+                    }
+                    self.elevation_noise.sample_row_wrapped(&points, period(freq), &mut noise);
+                    for (sum, &v) in sum.iter_mut().zip(&noise) {
+                        *sum += v * amp;
+                    }
                     norm += amp;
                     amp *= 0.5;
                     freq *= 2.0;
                 }
-                let fbm = sum / norm; // roughly [-1, 1]
 
                 // Ridged noise forms connected mountain ranges instead of
                 // isolated round peaks: ridge lines follow the zero-set of a
                 // low-frequency noise field.
-                let ridge = {
-                    let r = 1.0 - self.elevation_noise.get([qx * 3.0 + 113.5, qy * 3.0 + 57.7]).abs();
-                    r * r
-                };
+                for (point, &[qx, qy]) in points.iter_mut().zip(&q) {
+                    *point = [qx * 3.0 + 113.5, qy * 3.0 + 57.7];
+                }
+                self.elevation_noise.sample_row_wrapped(&points, period(3.0), &mut noise);
 
-                // Ridges are weighted by the continent mask so mountain
-                // ranges form on continent cores, not in open ocean.
-                let bias = plan.bias(nx, ny);
-                let mask01 = (bias + 0.8) / 1.6;
-                let mut v = bias + fbm * 0.45 + ridge * 0.5 * mask01;
+                for (x, value) in row.iter_mut().enumerate() {
+                    let nx = x as f64 / width as f64;
+                    let ny = y as f64 / height as f64;
+                    let fbm = sum[x] / norm; // roughly [-1, 1]
+                    let ridge = {
+                        let r = 1.0 - noise[x].abs();
+                        r * r
+                    };
 
-                // Soft edge falloff keeps continents off the map border
-                // (75% of maps) so coastlines don't get clipped.
-                if !plan.edge_continent {
-                    let edge = (nx.min(1.0 - nx)).min(ny.min(1.0 - ny));
-                    let f = (edge / 0.08).clamp(0.0, 1.0);
-                    let f = f * f * (3.0 - 2.0 * f); // smoothstep
-                    v = v * f - (1.0 - f);
-                }
+                    // Ridges are weighted by the continent mask so mountain
+                    // ranges form on continent cores, not in open ocean. A
+                    // shape is looked up a little off each tile, by the same
+                    // warp, so its coast meanders within the edge.
+                    let shape = self.shape_mask.as_ref().map(|mask| {
+                        let (sx, sy) = (wx[x] * MASK_WARP / (width as f64 * iso), wy[x] * MASK_WARP / (height as f64 * iso));
+                        (mask.distance(nx, ny), mask.distance(nx + sx, ny + sy))
+                    });
+                    let bias = match shape {
+                        Some((_, warped)) => shape_bias(warped),
+                        None => plan.bias(nx, ny),
+                    };
+                    let mask01 = (bias + 0.8) / 1.6;
+                    let mut v = bias + fbm * 0.45 + ridge * 0.5 * mask01;
 
-                *value = v;
-            }
+                    // Soft edge falloff keeps continents off the map border
+                    // (75% of maps) so coastlines don't get clipped. A map
+                    // that wraps has no east or west border.
+                    if let Some((distance, _)) = shape {
+                        // Below anything inside the shape, deepening away
+                        // from it, so the sea level never lets land out
+                        if distance <= 0.0 {
+                            v = -2.0 + distance.max(-1.0);
+                        }
+                    } else if !plan.edge_continent {
+                        let edge = if plan.wrap_x { ny.min(1.0 - ny) } else { nx.min(1.0 - nx).min(ny.min(1.0 - ny)) };
+                        let f = (edge / 0.08).clamp(0.0, 1.0);
+                        let f = f * f * (3.0 - 2.0 * f); // smoothstep
+                        v = v * f - (1.0 - f);
+                    }
+
+                    *value = v;
+                }
+            });
+            // Equalization below is cheap next to the noise sampling
+            let done = batch * ROW_BATCH + rows.len();
+            self.report_progress(GenerationPhase::Elevation, 0.9 * done as f32 / height as f32);
         }
+        Ok(raw)
+    }
 
+    /// Turn `raw` into area quantiles with `land` of the tiles above sea
+    /// level.
+    fn equalize_elevation(&self, raw: &mut [Vec<f64>], width: usize, land: f64) {
         // Histogram-equalize the field: each tile's elevation becomes its
         // area quantile. Sea level sits at exactly (1 - land_percentage), so
         // the land/water ratio matches the settings for every seed, and the
         // biome thresholds in `determine_biome` directly control what share
-        // of the land each biome covers.
-        let mut sorted: Vec<f64> = raw.iter().flatten().copied().collect();
-        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
-        let land = self.settings.land_percentage as f64;
+        // of the land each biome covers. The mountain density then bends the
+        // land quantiles up (rugged) or down (flat).
+        // Sorting the tiles with their positions gives every tile its rank
+        // (the number of lower values) in one pass afterwards
+        let mut sorted: Vec<(f64, usize)> = raw
+            .iter()
+            .flatten()
+            .enumerate()
+            .map(|(i, &v)| (v, i))
+            .collect();
+        sorted.par_sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+        let mut ranks = vec![0usize; sorted.len()];
+        let mut run_start = 0;
+        for (position, &(v, i)) in sorted.iter().enumerate() {
+            if position > 0 && v != sorted[position - 1].0 {
+                run_start = position;
+            }
+            ranks[i] = run_start;
+        }
         let sea_idx = (((1.0 - land) * (sorted.len() - 1) as f64) as usize).min(sorted.len() - 1);
-        let sea_level = sorted[sea_idx];
+        let sea_level = sorted[sea_idx].0;
         let land_count = (sorted.len() - 1 - sea_idx).max(1) as f64;
         let water_count = sea_idx.max(1) as f64;
+        let ruggedness = self.settings.ruggedness();
 
-        for row in raw.iter_mut() {
-            for value in row.iter_mut() {
-                // Rank of this value in the sorted field (binary search)
-                let rank = sorted.partition_point(|v| *v < *value);
+        raw.par_iter_mut().enumerate().for_each(|(y, row)| {
+            for (x, value) in row.iter_mut().enumerate() {
+                let rank = ranks[y * width + x];
                 *value = if *value > sea_level {
                     // Land: quantile within land, in (0, 1]
-                    (((rank - sea_idx) as f64) / land_count).clamp(0.01, 1.0)
+                    (((rank - sea_idx) as f64) / land_count)
+                        .powf(ruggedness)
+                        .clamp(0.01, 1.0)
                 } else {
                     // Water: quantile within water, in [-1, 0)
                     ((rank as f64 / water_count) - 1.0).min(-0.01)
                 };
             }
-        }
-
-        raw
+        });
     }
 }
@@ -0,0 +1,549 @@
+//! Finding the named feature under the pointer, for the GUI's tooltips,
+//! and the features near a point or inside a rectangle.
+
+use std::collections::HashMap;
+
+use super::region::Region;
+use super::stats::path_length;
+use super::types::TerrainMap;
+
+/// A named thing drawn on the map, by its index in the map's lists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MapFeature {
+    City(usize),
+    Road(usize),
+    Bridge(usize),
+    Label(usize),
+    /// A point of interest other than a city or bridge, by its index in
+    /// `TerrainMap::pois`
+    Poi(usize),
+}
+
+/// Where a label's text was drawn, in tiles. City names are boxes of
+/// their [`MapFeature::City`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LabelBox {
+    pub feature: MapFeature,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl LabelBox {
+    fn contains(&self, x: f32, y: f32) -> bool {
+        x >= self.x && y >= self.y && x < self.x + self.width && y < self.y + self.height
+    }
+}
+
+/// Side of the grid cells features are bucketed into, in tiles
+const CELL_SIZE: usize = 16;
+
+/// Cities, bridges, points of interest, road tiles and label anchors
+/// bucketed by position,
+/// plus the boxes of the labels as last drawn, so the features at a point
+/// are found without scanning the whole map.
+#[derive(Debug, Clone, Default)]
+pub struct FeatureIndex {
+    columns: usize,
+    rows: usize,
+    /// Each feature with the tile it covers; a road once per tile
+    cells: Vec<Vec<(MapFeature, usize, usize)>>,
+    labels: Vec<LabelBox>,
+}
+
+impl FeatureIndex {
+    pub fn new(map: &TerrainMap) -> Self {
+        let columns = map.width.div_ceil(CELL_SIZE).max(1);
+        let rows = map.height.div_ceil(CELL_SIZE).max(1);
+        let mut index = FeatureIndex {
+            columns,
+            rows,
+            cells: vec![Vec::new(); columns * rows],
+            labels: Vec::new(),
+        };
+        for (i, city) in map.cities.iter().enumerate() {
+            index.insert(MapFeature::City(i), city.x, city.y);
+        }
+        for (i, bridge) in map.bridges.iter().enumerate() {
+            index.insert(MapFeature::Bridge(i), bridge.x, bridge.y);
+        }
+        for (i, poi) in map.pois.iter().enumerate() {
+            index.insert(MapFeature::Poi(i), poi.position.0, poi.position.1);
+        }
+        for (i, road) in map.roads.iter().enumerate() {
+            for &(x, y) in &road.path {
+                index.insert(MapFeature::Road(i), x, y);
+            }
+        }
+        for (i, label) in map.labels.iter().enumerate() {
+            let (x, y) = (label.x.max(0.0) as usize, label.y.max(0.0) as usize);
+            index.insert(MapFeature::Label(i), x, y);
+        }
+        index
+    }
+
+    fn insert(&mut self, feature: MapFeature, x: usize, y: usize) {
+        let column = (x / CELL_SIZE).min(self.columns - 1);
+        let row = (y / CELL_SIZE).min(self.rows - 1);
+        self.cells[row * self.columns + column].push((feature, x, y));
+    }
+
+    /// The cell holding tile position `v` along an axis of `cells` cells.
+    fn cell(v: f32, cells: usize) -> usize {
+        ((v.max(0.0) as usize) / CELL_SIZE).min(cells - 1)
+    }
+
+    /// Entries of the cells from `(first_column, first_row)` to
+    /// `(last_column, last_row)`, inclusive.
+    fn entries(
+        &self,
+        (first_column, first_row): (usize, usize),
+        (last_column, last_row): (usize, usize),
+    ) -> impl Iterator<Item = &(MapFeature, usize, usize)> {
+        (first_row..=last_row).flat_map(move |row| {
+            (first_column..=last_column)
+                .flat_map(move |column| &self.cells[row * self.columns + column])
+        })
+    }
+
+    /// Replace the label boxes, after the labels were drawn again.
+    pub fn set_labels(&mut self, labels: Vec<LabelBox>) {
+        self.labels = labels;
+    }
+
+    /// The feature at tile position (x, y), considering only those
+    /// `shown` accepts. Bridges, then cities and points of interest, within
+    /// `radius` tiles come first, then labels under the point, then roads within `radius`.
+    pub fn feature_at(
+        &self,
+        x: f32,
+        y: f32,
+        radius: f32,
+        shown: impl Fn(MapFeature) -> bool,
+    ) -> Option<MapFeature> {
+        let first = (
+            Self::cell(x - radius, self.columns),
+            Self::cell(y - radius, self.rows),
+        );
+        let last = (
+            Self::cell(x + radius, self.columns),
+            Self::cell(y + radius, self.rows),
+        );
+
+        // The best match so far, with its rank and squared distance
+        let mut best: Option<(MapFeature, u8, f32)> = None;
+        for &(feature, tx, ty) in self.entries(first, last) {
+            let dx = tx as f32 + 0.5 - x;
+            let dy = ty as f32 + 0.5 - y;
+            let distance = dx * dx + dy * dy;
+            // Labels are hit by their drawn boxes, not their anchors
+            if distance > radius * radius
+                || matches!(feature, MapFeature::Label(_))
+                || !shown(feature)
+            {
+                continue;
+            }
+            let rank = match feature {
+                MapFeature::Bridge(_) => 0,
+                MapFeature::City(_) | MapFeature::Poi(_) => 1,
+                _ => 2,
+            };
+            if best.is_none_or(|(_, r, d)| (rank, distance) < (r, d)) {
+                best = Some((feature, rank, distance));
+            }
+        }
+        match best {
+            Some((feature, rank, _)) if rank < 2 => Some(feature),
+            // Labels drawn later are on top
+            _ => self
+                .labels
+                .iter()
+                .rev()
+                .find(|label| label.contains(x, y) && shown(label.feature))
+                .map(|label| label.feature)
+                .or(best.map(|(feature, _, _)| feature)),
+        }
+    }
+
+    /// The feature closest to tile position (x, y) among those `shown`
+    /// accepts, with its distance in tiles. A road is as close as its
+    /// nearest tile, a label as its anchor.
+    pub fn nearest(
+        &self,
+        x: f32,
+        y: f32,
+        shown: impl Fn(MapFeature) -> bool,
+    ) -> Option<(MapFeature, f32)> {
+        let (column, row) = (Self::cell(x, self.columns), Self::cell(y, self.rows));
+        let mut best: Option<(MapFeature, f32)> = None;
+        // Search rings of cells outwards until nothing further out can be
+        // closer than the best so far
+        for ring in 0..self.columns.max(self.rows) {
+            let rows = row.saturating_sub(ring)..=(row + ring).min(self.rows - 1);
+            for r in rows {
+                let columns = column.saturating_sub(ring)..=(column + ring).min(self.columns - 1);
+                for c in columns.filter(|c| c.abs_diff(column).max(r.abs_diff(row)) == ring) {
+                    for &(feature, tx, ty) in &self.cells[r * self.columns + c] {
+                        if !shown(feature) {
+                            continue;
+                        }
+                        let distance = (tx as f32 + 0.5 - x).hypot(ty as f32 + 0.5 - y);
+                        if best.is_none_or(|(_, d)| distance < d) {
+                            best = Some((feature, distance));
+                        }
+                    }
+                }
+            }
+            if best.is_some_and(|(_, d)| d <= (ring * CELL_SIZE) as f32) {
+                break;
+            }
+        }
+        best
+    }
+
+    /// Every feature within `radius` tiles of tile position (x, y), closest
+    /// first, with its distance.
+    pub fn within_radius(&self, x: f32, y: f32, radius: f32) -> Vec<(MapFeature, f32)> {
+        let first = (
+            Self::cell(x - radius, self.columns),
+            Self::cell(y - radius, self.rows),
+        );
+        let last = (
+            Self::cell(x + radius, self.columns),
+            Self::cell(y + radius, self.rows),
+        );
+        let mut found: HashMap<MapFeature, f32> = HashMap::new();
+        for &(feature, tx, ty) in self.entries(first, last) {
+            let distance = (tx as f32 + 0.5 - x).hypot(ty as f32 + 0.5 - y);
+            if distance <= radius {
+                let closest = found.entry(feature).or_insert(distance);
+                *closest = closest.min(distance);
+            }
+        }
+        let mut found: Vec<(MapFeature, f32)> = found.into_iter().collect();
+        found.sort_by(|a, b| a.1.total_cmp(&b.1));
+        found
+    }
+
+    /// Every feature with a tile inside `rect`: cities, bridges, points of
+    /// interest and label anchors standing in it and roads passing through it.
+    pub fn in_rect(&self, rect: Region) -> Vec<MapFeature> {
+        if rect.width == 0 || rect.height == 0 {
+            return Vec::new();
+        }
+        let first = (
+            (rect.x / CELL_SIZE).min(self.columns - 1),
+            (rect.y / CELL_SIZE).min(self.rows - 1),
+        );
+        let last = (
+            ((rect.x + rect.width - 1) / CELL_SIZE).min(self.columns - 1),
+            ((rect.y + rect.height - 1) / CELL_SIZE).min(self.rows - 1),
+        );
+        let mut found: Vec<MapFeature> = self
+            .entries(first, last)
+            .filter(|&&(_, tx, ty)| rect.contains(tx, ty))
+            .map(|&(feature, _, _)| feature)
+            .collect();
+        found.sort_by_key(|feature| match *feature {
+            MapFeature::City(i) => (0, i),
+            MapFeature::Bridge(i) => (1, i),
+            MapFeature::Poi(i) => (2, i),
+            MapFeature::Road(i) => (3, i),
+            MapFeature::Label(i) => (4, i),
+        });
+        found.dedup();
+        found
+    }
+}
+
+impl TerrainMap {
+    /// A short description of `feature` for a tooltip: its name, then a
+    /// line of key facts. `None` if the map has no such feature.
+    pub fn describe_feature(&self, feature: MapFeature) -> Option<String> {
+        match feature {
+            MapFeature::City(i) => {
+                let city = self.cities.get(i)?;
+                let roads = self
+                    .roads
+                    .iter()
+                    .filter(|road| road.path.contains(&(city.x, city.y)))
+                    .count();
+                let mut text = format!("{}\nPopulation {}", city.name, city.population);
+                if roads > 0 {
+                    text.push_str(&format!(
+                        ", {} road{}",
+                        roads,
+                        if roads == 1 { "" } else { "s" }
+                    ));
+                }
+                Some(text)
+            }
+            MapFeature::Road(i) => {
+                let road = self.roads.get(i)?;
+                let mut text = format!(
+                    "{}\n{}, {:.0} tiles long",
+                    road.name,
+                    capitalized(&road.road_type),
+                    path_length(&road.path)
+                );
+                if !road.bridges.is_empty() {
+                    text.push_str(&format!(
+                        ", {} bridge{}",
+                        road.bridges.len(),
+                        if road.bridges.len() == 1 { "" } else { "s" }
+                    ));
+                }
+                if !road.fords.is_empty() {
+                    text.push_str(&format!(
+                        ", {} ford{}",
+                        road.fords.len(),
+                        if road.fords.len() == 1 { "" } else { "s" }
+                    ));
+                }
+                Some(text)
+            }
+            MapFeature::Bridge(i) => {
+                let bridge = self.bridges.get(i)?;
+                let road = self.roads.iter().find(|road| {
+                    road.bridges
+                        .iter()
+                        .any(|b| (b.x, b.y) == (bridge.x, bridge.y))
+                });
+                let river = self.river_name_at(bridge.x, bridge.y).unwrap_or("a river");
+                Some(match road {
+                    Some(road) => format!("{}\nCarries {} over {}", bridge.name, road.name, river),
+                    None => format!("{}\nCrosses {}", bridge.name, river),
+                })
+            }
+            MapFeature::Label(i) => {
+                let label = self.labels.get(i)?;
+                Some(format!(
+                    "{}\n{}",
+                    label.name,
+                    capitalized(&label.feature_type)
+                ))
+            }
+            MapFeature::Poi(i) => {
+                let poi = self.pois.get(i)?;
+                let mut text = format!("{}\n{}", poi.name, capitalized(&poi.kind));
+                for (key, value) in &poi.metadata {
+                    text.push_str(&format!("\n{}: {}", capitalized(key), value));
+                }
+                Some(text)
+            }
+        }
+    }
+
+    /// Name of a river through tile (x, y), from the labels of the rivers
+    /// that pass it. Only the longest rivers are named.
+    fn river_name_at(&self, x: usize, y: usize) -> Option<&str> {
+        self.rivers
+            .iter()
+            .filter(|river| river.contains(&(x, y)))
+            .find_map(|river| {
+                self.labels.iter().find(|label| {
+                    label.feature_type == "river"
+                        && river.contains(&(label.x as usize, label.y as usize))
+                })
+            })
+            .map(|label| label.name.as_str())
+    }
+}
+
+pub(super) fn capitalized(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain_generator::test_map;
+
+    fn map() -> &'static TerrainMap {
+        test_map(8, 160, 120)
+    }
+
+    fn all(_: MapFeature) -> bool {
+        true
+    }
+
+    #[test]
+    fn cities_are_found_and_described_on_their_tile() {
+        let map = map();
+        let index = FeatureIndex::new(map);
+        let city = &map.cities[0];
+        let (x, y) = (city.x as f32 + 0.5, city.y as f32 + 0.5);
+        assert_eq!(index.feature_at(x, y, 1.0, all), Some(MapFeature::City(0)));
+        assert!(map
+            .describe_feature(MapFeature::City(0))
+            .unwrap()
+            .starts_with(&city.name));
+    }
+
+    #[test]
+    fn hidden_features_are_skipped() {
+        let map = map();
+        let index = FeatureIndex::new(map);
+        let city = &map.cities[0];
+        let (x, y) = (city.x as f32 + 0.5, city.y as f32 + 0.5);
+        assert_ne!(
+            index.feature_at(x, y, 1.0, |f| !matches!(f, MapFeature::City(_))),
+            Some(MapFeature::City(0))
+        );
+    }
+
+    #[test]
+    fn roads_are_found_along_their_path() {
+        let map = map();
+        let index = FeatureIndex::new(map);
+        let road = &map.roads[0];
+        let &(rx, ry) = &road.path[road.path.len() / 2];
+        let found = index.feature_at(rx as f32 + 0.5, ry as f32 + 0.5, 0.5, |f| {
+            matches!(f, MapFeature::Road(_))
+        });
+        let Some(MapFeature::Road(i)) = found else {
+            panic!("no road at {},{}", rx, ry)
+        };
+        assert!(map.roads[i].path.contains(&(rx, ry)));
+        assert_eq!(
+            map.describe_feature(MapFeature::Road(map.roads.len())),
+            None
+        );
+    }
+
+    #[test]
+    fn labels_are_found_inside_their_box() {
+        let mut index = FeatureIndex::new(map());
+        index.set_labels(vec![LabelBox {
+            feature: MapFeature::Label(0),
+            x: 300.0,
+            y: 300.0,
+            width: 10.0,
+            height: 2.0,
+        }]);
+        assert_eq!(
+            index.feature_at(305.0, 301.0, 1.0, all),
+            Some(MapFeature::Label(0))
+        );
+        assert_eq!(index.feature_at(315.0, 301.0, 1.0, all), None);
+    }
+
+    #[test]
+    fn points_of_interest_are_found_and_described_on_their_tile() {
+        let map = map();
+        let index = FeatureIndex::new(map);
+        let poi = &map.pois[0];
+        let (x, y) = (poi.position.0 as f32 + 0.5, poi.position.1 as f32 + 0.5);
+        assert_eq!(index.feature_at(x, y, 0.5, all), Some(MapFeature::Poi(0)));
+        assert!(map.describe_feature(MapFeature::Poi(0)).unwrap().starts_with(&poi.name));
+    }
+
+    /// Every feature of `map` with every tile it covers
+    fn feature_tiles(map: &TerrainMap) -> Vec<(MapFeature, usize, usize)> {
+        let mut tiles: Vec<(MapFeature, usize, usize)> = Vec::new();
+        tiles.extend(
+            map.cities
+                .iter()
+                .enumerate()
+                .map(|(i, c)| (MapFeature::City(i), c.x, c.y)),
+        );
+        tiles.extend(
+            map.bridges
+                .iter()
+                .enumerate()
+                .map(|(i, b)| (MapFeature::Bridge(i), b.x, b.y)),
+        );
+        for (i, road) in map.roads.iter().enumerate() {
+            tiles.extend(road.path.iter().map(|&(x, y)| (MapFeature::Road(i), x, y)));
+        }
+        for (i, label) in map.labels.iter().enumerate() {
+            tiles.push((MapFeature::Label(i), label.x as usize, label.y as usize));
+        }
+        tiles.extend(
+            map.pois
+                .iter()
+                .enumerate()
+                .map(|(i, p)| (MapFeature::Poi(i), p.position.0, p.position.1)),
+        );
+        tiles
+    }
+
+    /// Places to search from, on the map and off it
+    const POINTS: [(f32, f32); 4] = [(3.0, 4.0), (80.5, 60.5), (159.0, 2.0), (250.0, 300.0)];
+
+    fn distance((x, y): (f32, f32), tx: usize, ty: usize) -> f32 {
+        (tx as f32 + 0.5 - x).hypot(ty as f32 + 0.5 - y)
+    }
+
+    #[test]
+    fn nearest_matches_a_full_scan() {
+        let index = FeatureIndex::new(map());
+        let tiles = feature_tiles(map());
+        let is_city = |f: MapFeature| matches!(f, MapFeature::City(_));
+        for point in POINTS {
+            let closest = tiles
+                .iter()
+                .filter(|t| is_city(t.0))
+                .map(|&(_, tx, ty)| distance(point, tx, ty))
+                .fold(f32::MAX, f32::min);
+            let (feature, d) = index.nearest(point.0, point.1, is_city).unwrap();
+            assert!(is_city(feature));
+            assert_eq!(d, closest, "nearest city to {:?}", point);
+        }
+        assert!(index.nearest(0.0, 0.0, |_| false).is_none());
+    }
+
+    #[test]
+    fn within_radius_matches_a_full_scan() {
+        let index = FeatureIndex::new(map());
+        let tiles = feature_tiles(map());
+        for point in POINTS {
+            let mut expected: Vec<MapFeature> = tiles
+                .iter()
+                .filter(|&&(_, tx, ty)| distance(point, tx, ty) <= 20.0)
+                .map(|t| t.0)
+                .collect();
+            let mut found: Vec<MapFeature> = index
+                .within_radius(point.0, point.1, 20.0)
+                .iter()
+                .map(|f| f.0)
+                .collect();
+            for list in [&mut expected, &mut found] {
+                list.sort_by_key(|f| format!("{:?}", f));
+                list.dedup();
+            }
+            assert_eq!(found, expected, "around {:?}", point);
+        }
+    }
+
+    #[test]
+    fn in_rect_matches_a_full_scan() {
+        let index = FeatureIndex::new(map());
+        let tiles = feature_tiles(map());
+        let rect = Region {
+            x: 40,
+            y: 30,
+            width: 60,
+            height: 50,
+        };
+        let inside = index.in_rect(rect);
+        for &(feature, tx, ty) in &tiles {
+            assert_eq!(
+                inside.contains(&feature),
+                tiles
+                    .iter()
+                    .any(|t| t.0 == feature && rect.contains(t.1, t.2)),
+                "{:?} at {},{}",
+                feature,
+                tx,
+                ty
+            );
+        }
+        assert_eq!(index.in_rect(Region { width: 0, ..rect }), Vec::new());
+    }
+}
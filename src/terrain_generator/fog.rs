@@ -0,0 +1,175 @@
+//! Fog of war: which tiles of a map the players have seen.
+//!
+//! A [`FogOfWar`] starts with the whole map hidden. A game master reveals
+//! it bit by bit as the party explores, a circle round a place at a time
+//! (`reveal_circle`) or a strip either side of the way they went
+//! (`reveal_route`), and saves it between sessions as JSON. Renderers
+//! draw the hidden tiles over a finished map, names and all, so a
+//! player's map comes from the same world as the game master's.
+
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::types::{wrap_dx, TerrainMap};
+use crate::error::MapperError;
+
+/// Tiles either side of a route that `reveal_route` uncovers by default:
+/// what a party sees of the country it passes through
+pub const ROUTE_REVEAL: f32 = 3.0;
+
+/// The tiles of one map the players have seen.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FogOfWar {
+    pub width: usize,
+    pub height: usize,
+    /// Whether reveals carry on across the east-west seam
+    pub wrap_x: bool,
+    /// Row-major, true where the tile has been seen
+    revealed: Vec<bool>,
+}
+
+impl FogOfWar {
+    /// Fog over the whole of `map`, nothing revealed yet.
+    pub fn new(map: &TerrainMap) -> Self {
+        FogOfWar {
+            width: map.width,
+            height: map.height,
+            wrap_x: map.wrap_x,
+            revealed: vec![false; map.width * map.height],
+        }
+    }
+
+    /// Whether tile (`x`, `y`) has been seen; false outside the map.
+    pub fn is_revealed(&self, x: usize, y: usize) -> bool {
+        x < self.width && y < self.height && self.revealed[y * self.width + x]
+    }
+
+    /// Share of the map revealed, 0 to 1.
+    pub fn revealed_share(&self) -> f64 {
+        let seen = self.revealed.iter().filter(|&&seen| seen).count();
+        seen as f64 / self.revealed.len().max(1) as f64
+    }
+
+    /// Reveal the tiles whose centres lie within `radius` tiles of the
+    /// centre of `center`; across the seam of a map that wraps.
+    pub fn reveal_circle(&mut self, center: (usize, usize), radius: f32) {
+        let radius = radius.max(0.0);
+        let reach = radius.ceil() as isize;
+        for dy in -reach..=reach {
+            let y = center.1 as isize + dy;
+            if y < 0 || y >= self.height as isize {
+                continue;
+            }
+            for dx in -reach..=reach {
+                let x = center.0 as isize + dx;
+                let x = if self.wrap_x { x.rem_euclid(self.width.max(1) as isize) } else { x };
+                if x < 0 || x >= self.width as isize {
+                    continue;
+                }
+                let from = wrap_dx(x as f64 - center.0 as f64, self.width, self.wrap_x) as f32;
+                if from.hypot(dy as f32) <= radius {
+                    self.revealed[y as usize * self.width + x as usize] = true;
+                }
+            }
+        }
+    }
+
+    /// Reveal the tiles within `radius` of any tile along `path`, e.g. a
+    /// [`Route`](super::Route)'s path or a road's; [`ROUTE_REVEAL`] is what
+    /// a party sees on the way.
+    pub fn reveal_route(&mut self, path: &[(usize, usize)], radius: f32) {
+        for &tile in path {
+            self.reveal_circle(tile, radius);
+        }
+    }
+
+    /// Read fog saved by [`save_json`](Self::save_json).
+    pub fn load_json(path: &Path) -> Result<FogOfWar, MapperError> {
+        let reader = BufReader::new(File::open(path)?);
+        let fog: FogOfWar = serde_json::from_reader(reader)?;
+        if fog.width.checked_mul(fog.height) != Some(fog.revealed.len()) {
+            return Err(MapperError::Fog(format!(
+                "{}: {} tiles for a {}x{} map",
+                path.display(),
+                fog.revealed.len(),
+                fog.width,
+                fog.height
+            )));
+        }
+        Ok(fog)
+    }
+
+    /// Write the fog as JSON, to reveal more of it later.
+    pub fn save_json(&self, path: &Path) -> Result<(), MapperError> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        serde_json::to_writer(BufWriter::new(File::create(path)?), self)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain_generator::{test_map, TravelProfile};
+
+    fn map() -> &'static TerrainMap {
+        test_map(5, 80, 60)
+    }
+
+    #[test]
+    fn reveals_uncover_circles() {
+        let mut fog = FogOfWar::new(map());
+        assert_eq!(fog.revealed_share(), 0.0);
+        fog.reveal_circle((10, 10), 3.0);
+        assert!(fog.is_revealed(10, 10) && fog.is_revealed(13, 10) && fog.is_revealed(12, 12));
+        assert!(!fog.is_revealed(13, 13) && !fog.is_revealed(14, 10));
+        assert!(!fog.is_revealed(80, 10));
+    }
+
+    #[test]
+    fn reveals_uncover_routes() {
+        let map = map();
+        let mut fog = FogOfWar::new(map);
+        let from = &map.cities[0];
+        let route = map.cities[1..]
+            .iter()
+            .rev()
+            .find_map(|to| map.route((from.x, from.y), (to.x, to.y), TravelProfile::OnFoot))
+            .unwrap();
+        fog.reveal_route(&route.path, ROUTE_REVEAL);
+        assert!(route.path.iter().all(|&(x, y)| fog.is_revealed(x, y)));
+        assert!(fog.revealed_share() > 0.0 && fog.revealed_share() < 1.0);
+    }
+
+    #[test]
+    fn reveals_cross_the_seam_of_a_wrapping_map() {
+        let mut round = FogOfWar { wrap_x: true, ..FogOfWar::new(map()) };
+        round.reveal_circle((0, 30), 2.0);
+        assert!(round.is_revealed(78, 30) && round.is_revealed(2, 30) && !round.is_revealed(77, 30));
+    }
+
+    #[test]
+    fn saved_fog_loads_the_same() {
+        let mut fog = FogOfWar::new(map());
+        fog.reveal_circle((10, 10), 3.0);
+        let path = std::env::temp_dir().join(format!("mapper-fog-{}.json", std::process::id()));
+        fog.save_json(&path).unwrap();
+        assert_eq!(FogOfWar::load_json(&path).unwrap(), fog);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn fog_too_large_for_its_tiles_is_an_error() {
+        let path = std::env::temp_dir().join(format!("mapper-fog-huge-{}.json", std::process::id()));
+        let huge = format!(r#"{{"width":{},"height":2,"wrap_x":false,"revealed":[]}}"#, usize::MAX);
+        std::fs::write(&path, huge).unwrap();
+        let err = FogOfWar::load_json(&path).unwrap_err();
+        let _ = std::fs::remove_file(&path);
+        assert!(matches!(err, MapperError::Fog(_)), "{}", err);
+    }
+}
@@ -0,0 +1,494 @@
+//! Computing the noise fields on the GPU.
+//!
+//! With the `gpu` feature, a generator given [`TerrainGenerator::set_gpu`]
+//! samples the raw elevation field and the moisture and temperature noise
+//! in a wgpu compute shader (`gpu_fields.wgsl`), which ports the `noise`
+//! crate's Perlin noise and the elevation formula of `elevation.rs`. It
+//! works in f32, so fields match the CPU's to within rounding rather than
+//! bit for bit. Only Perlin noise is ported: other `NoiseKind`s, machines
+//! without a usable GPU and builds without the feature stay on the CPU.
+
+use super::elevation::ContinentPlan;
+use super::progress::{GenerationError, GenerationPhase};
+use super::types::NoiseKind;
+use super::TerrainGenerator;
+
+use backend::GpuFields;
+
+impl TerrainGenerator {
+    /// Compute the noise fields on the GPU from now on, or stop. Returns
+    /// whether the GPU will be used: false without the `gpu` feature or a
+    /// usable (hardware) adapter, in which case generation stays on the CPU.
+    pub fn set_gpu(&mut self, enabled: bool) -> bool {
+        self.gpu = enabled && GpuFields::shared().is_some();
+        self.gpu
+    }
+
+    /// The GPU, when it's on and the map doesn't wrap: the shader samples
+    /// the plane, not the cylinder a wrapping map is sampled on.
+    fn gpu_fields(&self) -> Option<&'static GpuFields> {
+        if self.gpu && !self.wrap_x {
+            GpuFields::shared()
+        } else {
+            None
+        }
+    }
+
+    /// The elevation before equalization, sampled on the GPU in batches of
+    /// rows with progress and cancellation in between. `None` if it has to
+    /// be computed on the CPU.
+    pub(super) fn gpu_raw_elevation(
+        &mut self,
+        plan: &ContinentPlan,
+        width: usize,
+        height: usize,
+    ) -> Option<Result<Vec<Vec<f64>>, GenerationError>> {
+        // The shader raises land from the continent plan only
+        if self.settings.elevation_noise != NoiseKind::Perlin || self.shape_mask.is_some() {
+            return None;
+        }
+        let gpu = self.gpu_fields()?;
+        let batch = gpu.max_rows(width);
+        if batch == 0 {
+            return None;
+        }
+        let mut raw = Vec::with_capacity(height);
+        for first in (0..height).step_by(batch) {
+            if let Err(e) = self.checkpoint() {
+                return Some(Err(e));
+            }
+            let rows = first..(first + batch).min(height);
+            let values = gpu.elevation_rows(self.seed, plan, width, height, rows.clone())?;
+            raw.extend(
+                values
+                    .chunks_exact(width)
+                    .map(|row| row.iter().map(|&v| v as f64).collect::<Vec<_>>()),
+            );
+            self.report_progress(GenerationPhase::Elevation, 0.9 * rows.end as f32 / height as f32);
+        }
+        Some(Ok(raw))
+    }
+
+    /// Perlin noise of `seed` at `frequency` times the isotropic tile
+    /// coordinates, for every tile row by row. `None` if it has to be
+    /// sampled on the CPU.
+    pub(super) fn gpu_noise_field(
+        &self,
+        seed: u32,
+        frequency: f32,
+        width: usize,
+        height: usize,
+    ) -> Option<Vec<f32>> {
+        let gpu = self.gpu_fields()?;
+        let batch = gpu.max_rows(width);
+        if batch == 0 {
+            return None;
+        }
+        let mut field = Vec::with_capacity(width * height);
+        for first in (0..height).step_by(batch) {
+            let rows = first..(first + batch).min(height);
+            field.extend(gpu.noise_rows(seed, frequency, width, height, rows)?);
+        }
+        Some(field)
+    }
+}
+
+#[cfg(not(feature = "gpu"))]
+mod backend {
+    use std::ops::Range;
+
+    use super::ContinentPlan;
+
+    /// Stands in for the GPU in builds without the `gpu` feature, which
+    /// never have one.
+    pub(super) enum GpuFields {}
+
+    impl GpuFields {
+        pub(super) fn shared() -> Option<&'static GpuFields> {
+            None
+        }
+
+        pub(super) fn max_rows(&self, _width: usize) -> usize {
+            match *self {}
+        }
+
+        pub(super) fn elevation_rows(
+            &self,
+            _seed: u32,
+            _plan: &ContinentPlan,
+            _width: usize,
+            _height: usize,
+            _rows: Range<usize>,
+        ) -> Option<Vec<f32>> {
+            match *self {}
+        }
+
+        pub(super) fn noise_rows(
+            &self,
+            _seed: u32,
+            _frequency: f32,
+            _width: usize,
+            _height: usize,
+            _rows: Range<usize>,
+        ) -> Option<Vec<f32>> {
+            match *self {}
+        }
+    }
+}
+
+#[cfg(feature = "gpu")]
+mod backend {
+    use std::ops::Range;
+    use std::sync::{mpsc, OnceLock};
+
+    use wgpu::util::DeviceExt;
+
+    use super::super::noise_source::permutation_table;
+    use super::ContinentPlan;
+
+    /// Invocations per workgroup, as declared in the shader
+    const WORKGROUP_SIZE: usize = 64;
+
+    static SHARED: OnceLock<Option<GpuFields>> = OnceLock::new();
+
+    /// The device and the two compute pipelines of `gpu_fields.wgsl`.
+    pub(super) struct GpuFields {
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        elevation: wgpu::ComputePipeline,
+        noise: wgpu::ComputePipeline,
+        /// Most tiles one dispatch can cover
+        max_tiles: usize,
+    }
+
+    impl GpuFields {
+        /// The process's GPU, set up on first use. `None` without a
+        /// hardware adapter: a software one would be slower than the CPU
+        /// path.
+        pub(super) fn shared() -> Option<&'static GpuFields> {
+            SHARED.get_or_init(|| pollster::block_on(Self::new())).as_ref()
+        }
+
+        async fn new() -> Option<GpuFields> {
+            let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+            let adapter = instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: wgpu::PowerPreference::HighPerformance,
+                    ..Default::default()
+                })
+                .await?;
+            if adapter.get_info().device_type == wgpu::DeviceType::Cpu {
+                return None;
+            }
+            let (device, queue) = adapter
+                .request_device(
+                    &wgpu::DeviceDescriptor {
+                        label: Some("mapper fields"),
+                        required_limits: adapter.limits(),
+                        ..Default::default()
+                    },
+                    None,
+                )
+                .await
+                .ok()?;
+
+            // Errors are caught in scopes so a broken driver falls back to
+            // the CPU instead of panicking
+            device.push_error_scope(wgpu::ErrorFilter::Validation);
+            let module = device.create_shader_module(wgpu::include_wgsl!("gpu_fields.wgsl"));
+            let pipeline = |entry_point: &str| {
+                device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some(entry_point),
+                    layout: None,
+                    module: &module,
+                    entry_point: Some(entry_point),
+                    compilation_options: Default::default(),
+                    cache: None,
+                })
+            };
+            let elevation = pipeline("elevation");
+            let noise = pipeline("noise");
+            if device.pop_error_scope().await.is_some() {
+                return None;
+            }
+
+            let limits = device.limits();
+            let max_tiles = (limits.max_compute_workgroups_per_dimension as usize * WORKGROUP_SIZE)
+                .min(limits.max_storage_buffer_binding_size as usize / 4)
+                .min(limits.max_buffer_size as usize / 4);
+            Some(GpuFields {
+                device,
+                queue,
+                elevation,
+                noise,
+                max_tiles,
+            })
+        }
+
+        /// Rows of `width` tiles one dispatch can cover.
+        pub(super) fn max_rows(&self, width: usize) -> usize {
+            self.max_tiles / width.max(1)
+        }
+
+        /// The raw elevation of `rows`: the detail noise warps the
+        /// elevation noise, seeded like `TerrainGenerator::new` seeds them.
+        pub(super) fn elevation_rows(
+            &self,
+            seed: u32,
+            plan: &ContinentPlan,
+            width: usize,
+            height: usize,
+            rows: Range<usize>,
+        ) -> Option<Vec<f32>> {
+            let tables: Vec<u32> = [seed.wrapping_add(3), seed]
+                .into_iter()
+                .flat_map(permutation_table)
+                .map(u32::from)
+                .collect();
+            let mut blobs: Vec<f32> = plan
+                .blobs
+                .iter()
+                .flat_map(|b| [b.x, b.y, b.cos, b.sin, b.aspect, b.radius2, b.weight])
+                .map(|v| v as f32)
+                .collect();
+            if blobs.is_empty() {
+                // A zero-weight blob: storage bindings can't be empty
+                blobs.resize(7, 1.0);
+                blobs[6] = 0.0;
+            }
+            let params = Params {
+                width,
+                height,
+                rows,
+                blob_count: plan.blobs.len(),
+                edge_continent: plan.edge_continent,
+                frequency: 0.0,
+            };
+            self.run(&self.elevation, &params, &tables, Some(&blobs))
+        }
+
+        /// Perlin noise of `seed` at `frequency` times the isotropic tile
+        /// coordinates over `rows`.
+        pub(super) fn noise_rows(
+            &self,
+            seed: u32,
+            frequency: f32,
+            width: usize,
+            height: usize,
+            rows: Range<usize>,
+        ) -> Option<Vec<f32>> {
+            let params = Params {
+                width,
+                height,
+                rows,
+                blob_count: 0,
+                edge_continent: false,
+                frequency,
+            };
+            let table = permutation_table(seed).map(u32::from);
+            self.run(&self.noise, &params, &table, None)
+        }
+
+        fn run(
+            &self,
+            pipeline: &wgpu::ComputePipeline,
+            params: &Params,
+            tables: &[u32],
+            blobs: Option<&[f32]>,
+        ) -> Option<Vec<f32>> {
+            let tiles = params.width * params.rows.len();
+            if tiles == 0 || tiles > self.max_tiles {
+                return None;
+            }
+            let size = (tiles * 4) as wgpu::BufferAddress;
+            let device = &self.device;
+            device.push_error_scope(wgpu::ErrorFilter::OutOfMemory);
+            device.push_error_scope(wgpu::ErrorFilter::Validation);
+
+            let init = |label: &str, contents: &[u8], usage: wgpu::BufferUsages| {
+                device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some(label),
+                    contents,
+                    usage,
+                })
+            };
+            let uniform = init("params", &params.to_bytes(), wgpu::BufferUsages::UNIFORM);
+            let tables = init("tables", &words(tables.iter().copied()), wgpu::BufferUsages::STORAGE);
+            let blobs = blobs.map(|blobs| {
+                let bytes = words(blobs.iter().map(|v| v.to_bits()));
+                init("blobs", &bytes, wgpu::BufferUsages::STORAGE)
+            });
+            let output = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("field"),
+                size,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            });
+            let readback = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("readback"),
+                size,
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+
+            let mut entries = vec![
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: tables.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: output.as_entire_binding(),
+                },
+            ];
+            if let Some(blobs) = &blobs {
+                entries.push(wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: blobs.as_entire_binding(),
+                });
+            }
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &pipeline.get_bind_group_layout(0),
+                entries: &entries,
+            });
+
+            let mut encoder = device.create_command_encoder(&Default::default());
+            {
+                let mut pass = encoder.begin_compute_pass(&Default::default());
+                pass.set_pipeline(pipeline);
+                pass.set_bind_group(0, &bind_group, &[]);
+                pass.dispatch_workgroups(tiles.div_ceil(WORKGROUP_SIZE) as u32, 1, 1);
+            }
+            encoder.copy_buffer_to_buffer(&output, 0, &readback, 0, size);
+            self.queue.submit([encoder.finish()]);
+
+            let failed = pollster::block_on(async {
+                let validation = device.pop_error_scope().await;
+                let memory = device.pop_error_scope().await;
+                validation.is_some() || memory.is_some()
+            });
+            if failed {
+                return None;
+            }
+
+            let slice = readback.slice(..);
+            let (sender, receiver) = mpsc::channel();
+            slice.map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+            device.poll(wgpu::Maintain::Wait);
+            receiver.recv().ok()?.ok()?;
+            let values = slice
+                .get_mapped_range()
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect();
+            readback.unmap();
+            Some(values)
+        }
+    }
+
+    /// The shader's `Params` uniform.
+    struct Params {
+        width: usize,
+        height: usize,
+        rows: Range<usize>,
+        blob_count: usize,
+        edge_continent: bool,
+        frequency: f32,
+    }
+
+    impl Params {
+        fn to_bytes(&self) -> Vec<u8> {
+            let (width, height) = (self.width as f32, self.height as f32);
+            words([
+                self.width as u32,
+                self.height as u32,
+                self.rows.start as u32,
+                self.rows.len() as u32,
+                (1.0 / width.min(height)).to_bits(),
+                self.blob_count as u32,
+                self.edge_continent as u32,
+                self.frequency.to_bits(),
+            ])
+        }
+    }
+
+    fn words(words: impl IntoIterator<Item = u32>) -> Vec<u8> {
+        words.into_iter().flat_map(u32::to_le_bytes).collect()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use noise::{NoiseFn, Perlin};
+
+        use super::*;
+        use crate::terrain_generator::{TerrainGenerator, TerrainMap};
+
+        /// `perlin2` of the shader, on the CPU.
+        fn perlin(table: &[u8; 256], [x, y]: [f64; 2]) -> f64 {
+            let hash = |cx: i64, cy: i64| {
+                let a = table[(cx & 255) as usize] as usize;
+                table[a ^ (cy & 255) as usize]
+            };
+            let gradient = |h: u8, dx: f64, dy: f64| match h & 3 {
+                0 => dx + dy,
+                1 => -dx + dy,
+                2 => dx - dy,
+                _ => -dx - dy,
+            };
+            let (cx, cy) = (x.floor(), y.floor());
+            let (dx, dy) = (x - cx, y - cy);
+            let (cx, cy) = (cx as i64, cy as i64);
+            let quintic = |t: f64| t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+            let (u, v) = (quintic(dx), quintic(dy));
+            let g00 = gradient(hash(cx, cy), dx, dy);
+            let g10 = gradient(hash(cx + 1, cy), dx - 1.0, dy);
+            let g01 = gradient(hash(cx, cy + 1), dx, dy - 1.0);
+            let g11 = gradient(hash(cx + 1, cy + 1), dx - 1.0, dy - 1.0);
+            let lerp = |a: f64, b: f64, t: f64| b * t + a * (1.0 - t);
+            let value = lerp(lerp(g00, g01, v), lerp(g10, g11, v), u);
+            (value * std::f64::consts::SQRT_2).clamp(-1.0, 1.0)
+        }
+
+        #[test]
+        fn shader_perlin_reproduces_the_noise_crate() {
+            for seed in [0, 1, 42, u32::MAX] {
+                let table = permutation_table(seed);
+                let reference = Perlin::new(seed);
+                for i in 0..500 {
+                    let point = [i as f64 * 0.37 - 60.0, i as f64 * 0.91 - 200.0];
+                    assert!((perlin(&table, point) - reference.get(point)).abs() < 1e-12);
+                }
+            }
+        }
+
+        #[test]
+        fn gpu_maps_match_cpu_maps_closely() {
+            let mut generator = TerrainGenerator::new(4);
+            if !generator.set_gpu(true) {
+                return; // no GPU here: nothing to compare
+            }
+            let gpu = generator.generate_terrain(200, 150);
+            let cpu = TerrainGenerator::new(4).generate_terrain(200, 150);
+            let elevations = |map: &TerrainMap| {
+                map.terrain
+                    .iter()
+                    .flatten()
+                    .map(|p| p.elevation)
+                    .collect::<Vec<_>>()
+            };
+            let close = elevations(&gpu)
+                .iter()
+                .zip(elevations(&cpu))
+                .filter(|&(a, b)| (a - b).abs() < 0.01)
+                .count();
+            assert!(close as f64 > 0.99 * (200.0 * 150.0));
+        }
+    }
+}
@@ -0,0 +1,144 @@
+// Noise fields for `gpu.rs`. `perlin2` ports `noise::Perlin`'s 2D noise and
+// `elevation` the per-tile formula of `raw_elevation` in `elevation.rs`;
+// keep them in step with those.
+
+struct Params {
+    width: u32,
+    height: u32,
+    first_row: u32,
+    rows: u32,
+    // 1 / min(width, height): isotropic noise coordinates
+    iso: f32,
+    blob_count: u32,
+    edge_continent: u32,
+    // `noise` only: multiplies the isotropic coordinates
+    frequency: f32,
+}
+
+// A `PlacedBlob` of the continent plan
+struct Blob {
+    x: f32,
+    y: f32,
+    cos: f32,
+    sin: f32,
+    aspect: f32,
+    radius2: f32,
+    weight: f32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+// 256-entry permutation tables, one after another
+@group(0) @binding(1) var<storage, read> tables: array<u32>;
+@group(0) @binding(2) var<storage, read> blobs: array<Blob>;
+@group(0) @binding(3) var<storage, read_write> field: array<f32>;
+
+// Tables of `elevation`: the detail (warp) noise, then the elevation noise
+const DETAIL: u32 = 0u;
+const ELEVATION: u32 = 1u;
+
+fn hash(table: u32, x: i32, y: i32) -> u32 {
+    let base = table * 256u;
+    let a = tables[base + u32(x & 255)];
+    return tables[base + (a ^ u32(y & 255))];
+}
+
+fn gradient(h: u32, dx: f32, dy: f32) -> f32 {
+    switch h & 3u {
+        case 0u: { return dx + dy; }
+        case 1u: { return -dx + dy; }
+        case 2u: { return dx - dy; }
+        default: { return -dx - dy; }
+    }
+}
+
+fn quintic(t: vec2<f32>) -> vec2<f32> {
+    return t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+}
+
+fn perlin2(table: u32, p: vec2<f32>) -> f32 {
+    let corner = floor(p);
+    let c = vec2<i32>(corner);
+    let d = p - corner;
+    let g00 = gradient(hash(table, c.x, c.y), d.x, d.y);
+    let g10 = gradient(hash(table, c.x + 1, c.y), d.x - 1.0, d.y);
+    let g01 = gradient(hash(table, c.x, c.y + 1), d.x, d.y - 1.0);
+    let g11 = gradient(hash(table, c.x + 1, c.y + 1), d.x - 1.0, d.y - 1.0);
+    let curve = quintic(d);
+    let value = mix(mix(g00, g01, curve.y), mix(g10, g11, curve.y), curve.x);
+    return clamp(value * 1.41421356, -1.0, 1.0);
+}
+
+fn continent_bias(nx: f32, ny: f32) -> f32 {
+    var sea_prob = 1.0;
+    for (var i = 0u; i < params.blob_count; i++) {
+        let b = blobs[i];
+        let dx = nx - b.x;
+        let dy = ny - b.y;
+        let along = (dx * b.cos + dy * b.sin) / b.aspect;
+        let across = -dx * b.sin + dy * b.cos;
+        let d2 = (along * along + across * across) / b.radius2;
+        if d2 <= 16.0 {
+            sea_prob *= 1.0 - clamp(b.weight * exp(-2.5 * d2), 0.0, 1.0);
+        }
+    }
+    return (1.0 - sea_prob) * 1.6 - 0.8;
+}
+
+// The tile of this invocation, or false past the end of the batch
+fn tile(id: u32) -> vec3<u32> {
+    let x = id % params.width;
+    let y = params.first_row + id / params.width;
+    return vec3<u32>(x, y, u32(id < params.width * params.rows));
+}
+
+@compute @workgroup_size(64)
+fn elevation(@builtin(global_invocation_id) id: vec3<u32>) {
+    let t = tile(id.x);
+    if t.z == 0u {
+        return;
+    }
+    let nx = f32(t.x) / f32(params.width);
+    let ny = f32(t.y) / f32(params.height);
+    let a = vec2<f32>(f32(t.x), f32(t.y)) * params.iso;
+
+    let warp = 0.35;
+    let wx = perlin2(DETAIL, a * 2.0 + vec2<f32>(31.4, 47.2));
+    let wy = perlin2(DETAIL, a * 2.0 + vec2<f32>(73.1, 11.9));
+    let q = a + vec2<f32>(wx, wy) * warp;
+
+    var amp = 1.0;
+    var freq = 2.0;
+    var sum = 0.0;
+    var norm = 0.0;
+    for (var octave = 0; octave < 5; octave++) {
+        sum += perlin2(ELEVATION, q * freq) * amp;
+        norm += amp;
+        amp *= 0.5;
+        freq *= 2.0;
+    }
+    let fbm = sum / norm;
+
+    let r = 1.0 - abs(perlin2(ELEVATION, q * 3.0 + vec2<f32>(113.5, 57.7)));
+    let ridge = r * r;
+
+    let bias = continent_bias(nx, ny);
+    let mask01 = (bias + 0.8) / 1.6;
+    var v = bias + fbm * 0.45 + ridge * 0.5 * mask01;
+
+    if params.edge_continent == 0u {
+        let edge = min(min(nx, 1.0 - nx), min(ny, 1.0 - ny));
+        let f = smoothstep(0.0, 1.0, clamp(edge / 0.08, 0.0, 1.0));
+        v = v * f - (1.0 - f);
+    }
+    field[id.x] = v;
+}
+
+@compute @workgroup_size(64)
+fn noise(@builtin(global_invocation_id) id: vec3<u32>) {
+    let t = tile(id.x);
+    if t.z == 0u {
+        return;
+    }
+    let p = vec2<f32>(f32(t.x), f32(t.y)) * params.iso * params.frequency;
+    field[id.x] = perlin2(0u, p);
+}
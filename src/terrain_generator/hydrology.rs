@@ -6,13 +6,15 @@
 //! depressions become lakes; rain is then accumulated down the flow
 //! directions, and tiles whose drainage area exceeds a density-controlled
 //! threshold become rivers. Rivers therefore always reach the sea, join at
-//! confluences, and widen downstream.
+//! confluences, and widen downstream. On a map that wraps east-west, water
+//! flows across the seam and only the north and south edges drain away.
 
 use std::cmp::Ordering;
 use std::collections::BinaryHeap;
 
 use super::biome::Biome;
-use super::types::TerrainPoint;
+use super::progress::GenerationPhase;
+use super::types::{offset_tile, TerrainPoint};
 use super::TerrainGenerator;
 
 /// Min-heap node for the priority flood.
@@ -39,7 +41,7 @@ impl PartialOrd for FloodNode {
     }
 }
 
-const NEIGHBORS: [(i32, i32); 8] = [
+const NEIGHBORS: [(isize, isize); 8] = [
     (-1, -1),
     (0, -1),
     (1, -1),
@@ -50,6 +52,51 @@ const NEIGHBORS: [(i32, i32); 8] = [
     (1, 1),
 ];
 
+/// The elevation field `elev` (row-major, `width` wide) with every
+/// depression filled to its spill level, plus a hair per tile so water on
+/// the filled surface always has somewhere lower to flow. Water tiles and
+/// the map border (only its north and south edges when `wrap_x`) drain
+/// away and are left as they are.
+pub(super) fn fill_depressions(elev: &[f64], width: usize, height: usize, wrap_x: bool) -> Vec<f64> {
+    let n = width * height;
+    let idx_of = |x: usize, y: usize| y * width + x;
+    let mut filled = elev.to_vec();
+    let mut visited = vec![false; n];
+    let mut heap = BinaryHeap::new();
+
+    // Seeds: every water tile, plus land tiles on the map border (they
+    // drain off-map).
+    for y in 0..height {
+        for x in 0..width {
+            let i = idx_of(x, y);
+            let east_west = !wrap_x && (x == 0 || x == width - 1);
+            if elev[i] < 0.0 || east_west || y == 0 || y == height - 1 {
+                visited[i] = true;
+                heap.push(FloodNode { elev: filled[i], idx: i });
+            }
+        }
+    }
+
+    while let Some(FloodNode { elev: cur_elev, idx }) = heap.pop() {
+        let (x, y) = (idx % width, idx / width);
+        for (dx, dy) in NEIGHBORS {
+            let Some((nx, ny)) = offset_tile(x, y, dx, dy, width, height, wrap_x) else {
+                continue;
+            };
+            let ni = idx_of(nx, ny);
+            if visited[ni] {
+                continue;
+            }
+            visited[ni] = true;
+            // A tile inside a depression is raised to just above the
+            // lowest spill point seen so far.
+            filled[ni] = elev[ni].max(cur_elev + 1e-6);
+            heap.push(FloodNode { elev: filled[ni], idx: ni });
+        }
+    }
+    filled
+}
+
 impl TerrainGenerator {
     /// Generate rivers and lakes. Marks lake tiles in `terrain` directly and
     /// returns the river polylines (each traced from source to mouth).
@@ -72,43 +119,9 @@ impl TerrainGenerator {
             .collect();
 
         // --- Priority flood: fill depressions to their spill level ---
-        let mut filled = elev.clone();
-        let mut visited = vec![false; n];
-        let mut heap = BinaryHeap::new();
-
-        // Seeds: every water tile, plus land tiles on the map border (they
-        // drain off-map).
-        for y in 0..height {
-            for x in 0..width {
-                let i = idx_of(x, y);
-                if elev[i] < 0.0 || x == 0 || y == 0 || x == width - 1 || y == height - 1 {
-                    visited[i] = true;
-                    heap.push(FloodNode { elev: filled[i], idx: i });
-                }
-            }
-        }
-
-        while let Some(FloodNode { elev: cur_elev, idx }) = heap.pop() {
-            let x = (idx % width) as i32;
-            let y = (idx / width) as i32;
-            for (dx, dy) in NEIGHBORS {
-                let nx = x + dx;
-                let ny = y + dy;
-                if nx < 0 || ny < 0 || nx >= width as i32 || ny >= height as i32 {
-                    continue;
-                }
-                let ni = idx_of(nx as usize, ny as usize);
-                if visited[ni] {
-                    continue;
-                }
-                visited[ni] = true;
-                // A tile inside a depression is raised to just above the
-                // lowest spill point seen so far.
-                filled[ni] = elev[ni].max(cur_elev + 1e-6);
-                heap.push(FloodNode { elev: filled[ni], idx: ni });
-            }
-        }
+        let filled = fill_depressions(&elev, width, height, self.wrap_x);
 
+        self.report_progress(GenerationPhase::Rivers, 0.3);
         // --- Lakes: tiles raised by the fill are under a lake surface ---
         let mut is_lake = vec![false; n];
         for i in 0..n {
@@ -127,15 +140,12 @@ impl TerrainGenerator {
                 let mut stack = vec![start];
                 seen[start] = true;
                 while let Some(i) = stack.pop() {
-                    let x = (i % width) as i32;
-                    let y = (i / width) as i32;
+                    let (x, y) = (i % width, i / width);
                     for (dx, dy) in NEIGHBORS {
-                        let nx = x + dx;
-                        let ny = y + dy;
-                        if nx < 0 || ny < 0 || nx >= width as i32 || ny >= height as i32 {
+                        let Some((nx, ny)) = offset_tile(x, y, dx, dy, width, height, self.wrap_x) else {
                             continue;
-                        }
-                        let ni = idx_of(nx as usize, ny as usize);
+                        };
+                        let ni = idx_of(nx, ny);
                         if is_lake[ni] && !seen[ni] {
                             seen[ni] = true;
                             stack.push(ni);
@@ -156,6 +166,7 @@ impl TerrainGenerator {
             }
         }
 
+        self.report_progress(GenerationPhase::Rivers, 0.45);
         // --- Flow directions: steepest descent on the filled surface ---
         let mut downstream = vec![usize::MAX; n];
         for y in 0..height {
@@ -163,12 +174,10 @@ impl TerrainGenerator {
                 let i = idx_of(x, y);
                 let mut best = filled[i];
                 for (dx, dy) in NEIGHBORS {
-                    let nx = x as i32 + dx;
-                    let ny = y as i32 + dy;
-                    if nx < 0 || ny < 0 || nx >= width as i32 || ny >= height as i32 {
+                    let Some((nx, ny)) = offset_tile(x, y, dx, dy, width, height, self.wrap_x) else {
                         continue;
-                    }
-                    let ni = idx_of(nx as usize, ny as usize);
+                    };
+                    let ni = idx_of(nx, ny);
                     if filled[ni] < best {
                         best = filled[ni];
                         downstream[i] = ni;
@@ -177,6 +186,7 @@ impl TerrainGenerator {
             }
         }
 
+        self.report_progress(GenerationPhase::Rivers, 0.6);
         // --- Flow accumulation: rain one unit on every tile, pour downhill ---
         let mut order: Vec<usize> = (0..n).collect();
         order.sort_by(|&a, &b| filled[b].partial_cmp(&filled[a]).unwrap_or(Ordering::Equal));
@@ -188,6 +198,7 @@ impl TerrainGenerator {
             }
         }
 
+        self.report_progress(GenerationPhase::Rivers, 0.7);
         // --- Threshold drainage area by river density ---
         let land_tiles = elev.iter().filter(|&&e| e >= 0.0).count().max(1);
         let density = self.settings.river_density as f64;
@@ -200,6 +211,7 @@ impl TerrainGenerator {
             }
         }
 
+        self.report_progress(GenerationPhase::Rivers, 0.8);
         // --- Trace polylines from each river head downstream ---
         // A head is a river tile with no river tile flowing into it.
         let mut has_river_upstream = vec![false; n];
@@ -226,7 +238,6 @@ impl TerrainGenerator {
                     // Joined an already-traced river: stop at the confluence
                     break;
                 }
-                claimed[i] = true;
                 if elev[i] < 0.0 {
                     // Reached the sea
                     break;
@@ -238,7 +249,12 @@ impl TerrainGenerator {
                 }
                 i = ds;
             }
+            // Only rivers that are kept claim their tiles, or a later river
+            // would stop where a dropped one ran, short of the sea
             if path.len() >= 6 {
+                for &(x, y) in &path {
+                    claimed[idx_of(x, y)] = true;
+                }
                 rivers.push(path);
             }
         }
@@ -1,5 +1,5 @@
 use super::biome::Biome;
-use super::types::{PlaceLabel, TerrainPoint};
+use super::types::{offset_tile, wrap_dx, NamedRegion, PlaceLabel, TerrainMap, TerrainPoint};
 use super::TerrainGenerator;
 
 /// Which name generator to use for a labeled region.
@@ -11,12 +11,15 @@ enum RegionKind {
     Swamp,
 }
 
+/// Whether a biome belongs to a kind of region.
+pub(super) type BiomeTest = fn(&Biome) -> bool;
+
 /// Configuration for one class of labeled region: biome predicate, how many
 /// labels at most, and the minimum region size worth naming.
 struct RegionLabelSpec {
     kind: RegionKind,
     feature_type: &'static str,
-    predicate: fn(&Biome) -> bool,
+    predicate: BiomeTest,
     max_labels: usize,
     min_size: usize,
 }
@@ -52,22 +55,46 @@ const REGION_SPECS: [RegionLabelSpec; 4] = [
     },
 ];
 
+/// The kind of labelled region a tile of `biome` can belong to, as the
+/// labels' `feature_type` and the test its tiles pass.
+pub(super) fn region_kind_of(biome: Biome) -> Option<(&'static str, BiomeTest)> {
+    REGION_SPECS
+        .iter()
+        .find(|spec| (spec.predicate)(&biome))
+        .map(|spec| (spec.feature_type, spec.predicate))
+}
+
+/// The test the tiles of a labelled region of `feature_type` pass, e.g.
+/// `"forest"`; `None` for rivers and unknown types.
+pub(super) fn region_test(feature_type: &str) -> Option<BiomeTest> {
+    REGION_SPECS
+        .iter()
+        .find(|spec| spec.feature_type == feature_type)
+        .map(|spec| spec.predicate)
+}
+
 impl TerrainGenerator {
+    /// Labels for the largest regions of each kind and the major rivers,
+    /// and the tiles of each region labelled.
     pub(super) fn generate_labels(
         &mut self,
         terrain: &[Vec<TerrainPoint>],
         rivers: &[Vec<(usize, usize)>],
-    ) -> Vec<PlaceLabel> {
+    ) -> (Vec<PlaceLabel>, Vec<NamedRegion>) {
         let mut labels = Vec::new();
+        let mut named_regions = Vec::new();
         let mut placed_labels: Vec<(f32, f32)> = Vec::new();
 
         // Scale minimum distance between labels based on map size
         let map_scale = (terrain[0].len() as f32 / 160.0).max(terrain.len() as f32 / 120.0);
         let min_distance = 80.0 * map_scale;
+        let width = terrain[0].len();
+        let wrap_x = self.wrap_x;
         let is_too_close = |x: f32, y: f32, placed: &Vec<(f32, f32)>| -> bool {
-            placed
-                .iter()
-                .any(|&(px, py)| ((x - px).powi(2) + (y - py).powi(2)).sqrt() < min_distance)
+            placed.iter().any(|&(px, py)| {
+                let dx = wrap_dx((x - px) as f64, width, wrap_x) as f32;
+                (dx.powi(2) + (y - py).powi(2)).sqrt() < min_distance
+            })
         };
 
         // Label the largest regions of each kind
@@ -79,7 +106,7 @@ impl TerrainGenerator {
                 if region.len() <= spec.min_size {
                     continue;
                 }
-                let (cx, cy) = self.region_center(region);
+                let (cx, cy) = region_center(region, width, self.wrap_x);
                 let fx = cx as f32;
                 let fy = cy as f32;
                 if is_too_close(fx, fy, &placed_labels) {
@@ -91,6 +118,13 @@ impl TerrainGenerator {
                     RegionKind::Forest => self.generate_forest_name(i),
                     RegionKind::Swamp => self.generate_swamp_name(i),
                 };
+                let mut tiles = region.clone();
+                tiles.sort_by_key(|&(x, y)| (y, x));
+                named_regions.push(NamedRegion {
+                    name: name.clone(),
+                    feature_type: spec.feature_type.to_string(),
+                    tiles,
+                });
                 labels.push(PlaceLabel {
                     x: fx,
                     y: fy,
@@ -127,7 +161,7 @@ impl TerrainGenerator {
             }
         }
 
-        labels
+        (labels, named_regions)
     }
 
     fn find_regions(
@@ -152,23 +186,14 @@ impl TerrainGenerator {
                         visited[cy][cx] = true;
                         region.push((cx, cy));
 
-                        for dy in -1i32..=1 {
-                            for dx in -1i32..=1 {
+                        for dy in -1isize..=1 {
+                            for dx in -1isize..=1 {
                                 if dx == 0 && dy == 0 {
                                     continue;
                                 }
 
-                                let nx = cx as i32 + dx;
-                                let ny = cy as i32 + dy;
-
-                                if nx >= 0
-                                    && nx < terrain[0].len() as i32
-                                    && ny >= 0
-                                    && ny < terrain.len() as i32
-                                {
-                                    let nx = nx as usize;
-                                    let ny = ny as usize;
-
+                                let (width, height) = (terrain[0].len(), terrain.len());
+                                if let Some((nx, ny)) = offset_tile(cx, cy, dx, dy, width, height, self.wrap_x) {
                                     if !visited[ny][nx] && predicate(&terrain[ny][nx].biome) {
                                         stack.push((nx, ny));
                                     }
@@ -186,57 +211,96 @@ impl TerrainGenerator {
 
         regions
     }
+}
 
-    /// The most interior point of a region (pole of inaccessibility): a
-    /// multi-source BFS from the region boundary inward, returning the tile
-    /// with the greatest distance from any edge. This keeps ocean labels in
-    /// open water and mountain labels on the range's core. Tiles outside the
-    /// map count as boundary, so labels also stay away from map edges.
-    fn region_center(&self, region: &[(usize, usize)]) -> (usize, usize) {
-        use std::collections::{HashMap, VecDeque};
-
-        let in_region: std::collections::HashSet<(usize, usize)> =
-            region.iter().copied().collect();
-        let neighbors = |x: usize, y: usize| {
-            [(0i32, -1i32), (-1, 0), (1, 0), (0, 1)]
-                .into_iter()
-                .map(move |(dx, dy)| (x as i32 + dx, y as i32 + dy))
-        };
-
-        // Seed the BFS with all boundary tiles (those with a 4-neighbor
-        // outside the region or outside the map)
-        let mut dist: HashMap<(usize, usize), u32> = HashMap::new();
-        let mut queue = VecDeque::new();
-        for &(x, y) in region {
-            let on_boundary = neighbors(x, y).any(|(nx, ny)| {
-                nx < 0 || ny < 0 || !in_region.contains(&(nx as usize, ny as usize))
-            });
-            if on_boundary {
-                dist.insert((x, y), 0);
-                queue.push_back((x, y));
-            }
-        }
-
-        let mut best = region[0];
-        let mut best_dist = 0;
-        while let Some((x, y)) = queue.pop_front() {
-            let d = dist[&(x, y)];
-            if d > best_dist {
-                best_dist = d;
-                best = (x, y);
+impl TerrainMap {
+    /// The region each region label stands in, traced again from the
+    /// terrain: all tiles joined to its anchor, diagonals included, that
+    /// pass the test of its kind. For maps whose labels were moved or
+    /// terrain changed after generation; a label whose tile no longer
+    /// passes names no region.
+    pub(super) fn traced_regions(&self) -> Vec<NamedRegion> {
+        let mut regions = Vec::new();
+        for label in &self.labels {
+            let Some(belongs) = region_test(&label.feature_type) else {
+                continue;
+            };
+            let anchor = (label.x as usize, label.y as usize);
+            if !self.biome_at(anchor.0, anchor.1).is_some_and(|b| belongs(&b)) {
+                continue;
             }
-            for (nx, ny) in neighbors(x, y) {
-                if nx < 0 || ny < 0 {
-                    continue;
-                }
-                let p = (nx as usize, ny as usize);
-                if in_region.contains(&p) && !dist.contains_key(&p) {
-                    dist.insert(p, d + 1);
-                    queue.push_back(p);
+            let mut seen = std::collections::HashSet::from([anchor]);
+            let mut tiles = vec![anchor];
+            let mut next = 0;
+            while let Some(&(x, y)) = tiles.get(next) {
+                next += 1;
+                for dy in -1..=1 {
+                    for dx in -1..=1 {
+                        let Some(tile) = self.offset(x, y, dx, dy) else {
+                            continue;
+                        };
+                        if self.biome_at(tile.0, tile.1).is_some_and(|b| belongs(&b)) && seen.insert(tile) {
+                            tiles.push(tile);
+                        }
+                    }
                 }
             }
+            tiles.sort_by_key(|&(x, y)| (y, x));
+            regions.push(NamedRegion {
+                name: label.name.clone(),
+                feature_type: label.feature_type.clone(),
+                tiles,
+            });
         }
+        regions
+    }
+}
+
+/// The most interior point of a region (pole of inaccessibility): a
+/// multi-source BFS from the region boundary inward, returning the tile
+/// with the greatest distance from any edge. This keeps ocean labels in
+/// open water and mountain labels on the range's core. Tiles outside the
+/// map count as boundary, so labels also stay away from map edges; a
+/// region across the seam of a map that wraps is measured around it.
+pub(super) fn region_center(region: &[(usize, usize)], width: usize, wrap_x: bool) -> (usize, usize) {
+    use std::collections::{HashMap, VecDeque};
 
-        best
+    let in_region: std::collections::HashSet<(usize, usize)> =
+        region.iter().copied().collect();
+    // No height needed: a row below the map is never in the region
+    let neighbors = |x: usize, y: usize| {
+        [(0isize, -1isize), (-1, 0), (1, 0), (0, 1)]
+            .into_iter()
+            .map(move |(dx, dy)| offset_tile(x, y, dx, dy, width, usize::MAX, wrap_x))
+    };
+
+    // Seed the BFS with all boundary tiles (those with a 4-neighbor
+    // outside the region or outside the map)
+    let mut dist: HashMap<(usize, usize), u32> = HashMap::new();
+    let mut queue = VecDeque::new();
+    for &(x, y) in region {
+        let on_boundary = neighbors(x, y).any(|p| !p.is_some_and(|p| in_region.contains(&p)));
+        if on_boundary {
+            dist.insert((x, y), 0);
+            queue.push_back((x, y));
+        }
+    }
+
+    let mut best = region[0];
+    let mut best_dist = 0;
+    while let Some((x, y)) = queue.pop_front() {
+        let d = dist[&(x, y)];
+        if d > best_dist {
+            best_dist = d;
+            best = (x, y);
+        }
+        for p in neighbors(x, y).flatten() {
+            if in_region.contains(&p) && !dist.contains_key(&p) {
+                dist.insert(p, d + 1);
+                queue.push_back(p);
+            }
+        }
     }
+
+    best
 }
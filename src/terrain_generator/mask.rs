@@ -0,0 +1,263 @@
+//! Shape masks: land only within a silhouette the user gives.
+//!
+//! A [`ShapeMask`] is made from an image (light or opaque pixels are land,
+//! dark or transparent ones sea) or from a polygon, and is stretched over
+//! the whole map. A generator given one with
+//! [`TerrainGenerator::set_shape_mask`] raises the continent from the
+//! shape instead of its continent plan: the elevation bias climbs from the
+//! shape's edge inward over `MASK_BLEND` of the map, looked up through the
+//! terrain's domain warp (`MASK_WARP`), so the fractal noise draws a natural
+//! coastline just inside the edge, and no tile outside the shape is ever
+//! land. If the land percentage asks for more land than the
+//! shape can hold, at most `MASK_FILL` of it is land, leaving the noise
+//! room to carve bays along the edge.
+
+use std::path::Path;
+
+use image::imageops::FilterType;
+use image::DynamicImage;
+
+use crate::error::MapperError;
+
+use super::TerrainGenerator;
+
+/// Distance from the shape's edge over which its elevation bias rises to
+/// full strength, in fractions of the mask's shorter side
+pub const MASK_BLEND: f64 = 0.1;
+
+/// How far the shape is warped where its elevation bias is looked up, in
+/// fractions of the map's shorter side
+pub const MASK_WARP: f64 = 0.04;
+
+/// Most of the shape that is land, however much land is asked for
+pub const MASK_FILL: f64 = 0.8;
+
+/// Longest side of the grid a mask is kept at
+const MASK_RESOLUTION: u32 = 512;
+
+/// Diagonal step of the chamfer distance to the shape's edge
+const DIAGONAL: f32 = std::f32::consts::SQRT_2;
+
+/// Where land may be: a grid of signed distances to the shape's edge,
+/// stretched over the map.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShapeMask {
+    width: usize,
+    height: usize,
+    /// Distance from each cell's centre to the edge, in fractions of the
+    /// shorter side: positive inside the shape, negative outside
+    distance: Vec<f32>,
+}
+
+impl ShapeMask {
+    /// A mask of `width` by `height` cells, row by row, `inside` where land
+    /// may be.
+    pub fn from_cells(width: usize, height: usize, inside: &[bool]) -> ShapeMask {
+        let (width, height) = (width.max(1), height.max(1));
+        let inside = |i: usize| inside.get(i).copied().unwrap_or(false);
+        let to_outside = chamfer(width, height, |i| !inside(i));
+        let to_inside = chamfer(width, height, inside);
+        let side = width.min(height) as f32;
+        // Cells either side of the edge are half a cell from it
+        let distance = (0..width * height)
+            .map(|i| if inside(i) { to_outside[i] - 0.5 } else { 0.5 - to_inside[i] } / side)
+            .collect();
+        ShapeMask { width, height, distance }
+    }
+
+    /// A mask from `img`: where it has transparency, its opaque pixels are
+    /// land; otherwise its light ones. Large images are scaled down first.
+    pub fn from_image(img: &DynamicImage) -> ShapeMask {
+        let img = if img.width().max(img.height()) > MASK_RESOLUTION {
+            img.resize(MASK_RESOLUTION, MASK_RESOLUTION, FilterType::Triangle)
+        } else {
+            img.clone()
+        };
+        let rgba = img.to_rgba8();
+        let transparent = rgba.pixels().any(|p| p[3] < 255);
+        let inside: Vec<bool> = rgba
+            .pixels()
+            .map(|p| {
+                if transparent {
+                    p[3] >= 128
+                } else {
+                    let luma = 0.299 * p[0] as f32 + 0.587 * p[1] as f32 + 0.114 * p[2] as f32;
+                    luma >= 128.0
+                }
+            })
+            .collect();
+        Self::from_cells(rgba.width() as usize, rgba.height() as usize, &inside)
+    }
+
+    /// A mask from the polygon through `corners`, each (x, y) in fractions
+    /// of the map's width and height. Self-crossing outlines are filled
+    /// even-odd.
+    pub fn from_polygon(corners: &[[f64; 2]]) -> ShapeMask {
+        let size = MASK_RESOLUTION as usize;
+        let mut inside = vec![false; size * size];
+        for (i, cell) in inside.iter_mut().enumerate() {
+            let x = ((i % size) as f64 + 0.5) / size as f64;
+            let y = ((i / size) as f64 + 0.5) / size as f64;
+            *cell = polygon_contains(corners, x, y);
+        }
+        Self::from_cells(size, size, &inside)
+    }
+
+    /// Load a mask: a `.json` file is a polygon, an array of `[x, y]`
+    /// corners as in `from_polygon`; anything else is read as an image.
+    /// A mask with no land is refused.
+    pub fn load(path: &Path) -> Result<ShapeMask, MapperError> {
+        let is_json = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+        let mask = if is_json {
+            let corners: Vec<[f64; 2]> = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+            if corners.len() < 3 {
+                return Err(MapperError::Mask(format!("{}: a polygon needs at least 3 corners", path.display())));
+            }
+            Self::from_polygon(&corners)
+        } else {
+            Self::from_image(&image::open(path)?)
+        };
+        if mask.share() == 0.0 {
+            return Err(MapperError::Mask(format!("{}: no land in the shape", path.display())));
+        }
+        Ok(mask)
+    }
+
+    /// Signed distance to the shape's edge at map-normalized (`nx`, `ny`),
+    /// interpolated between cells: positive inside.
+    pub fn distance(&self, nx: f64, ny: f64) -> f64 {
+        let fx = (nx * self.width as f64 - 0.5).clamp(0.0, (self.width - 1) as f64);
+        let fy = (ny * self.height as f64 - 0.5).clamp(0.0, (self.height - 1) as f64);
+        let (x0, y0) = (fx as usize, fy as usize);
+        let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+        let (tx, ty) = (fx - x0 as f64, fy - y0 as f64);
+        let at = |x: usize, y: usize| self.distance[y * self.width + x] as f64;
+        let top = at(x0, y0) * (1.0 - tx) + at(x1, y0) * tx;
+        let bottom = at(x0, y1) * (1.0 - tx) + at(x1, y1) * tx;
+        top * (1.0 - ty) + bottom * ty
+    }
+
+    /// Whether map-normalized (`nx`, `ny`) is inside the shape.
+    pub fn contains(&self, nx: f64, ny: f64) -> bool {
+        self.distance(nx, ny) > 0.0
+    }
+
+    /// Share of the mask's cells inside the shape.
+    pub fn share(&self) -> f64 {
+        self.distance.iter().filter(|&&d| d > 0.0).count() as f64 / self.distance.len() as f64
+    }
+}
+
+/// Continent bias in [-0.8, 0.8], on the scale of the continent plan's, at
+/// `distance` from the shape's edge.
+pub(super) fn shape_bias(distance: f64) -> f64 {
+    (distance / MASK_BLEND).clamp(-1.0, 1.0) * 0.8
+}
+
+impl TerrainGenerator {
+    /// Raise land only within `mask`, or from the continent plan again with
+    /// `None`.
+    pub fn set_shape_mask(&mut self, mask: Option<ShapeMask>) {
+        self.shape_mask = mask;
+    }
+}
+
+/// Whether (`x`, `y`) is inside the polygon through `corners`, by the
+/// even-odd rule.
+fn polygon_contains(corners: &[[f64; 2]], x: f64, y: f64) -> bool {
+    let mut inside = false;
+    for (i, &[ax, ay]) in corners.iter().enumerate() {
+        let [bx, by] = corners[(i + 1) % corners.len()];
+        if (ay > y) != (by > y) && x < ax + (y - ay) / (by - ay) * (bx - ax) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/// Chamfer distance, in cells, from each cell to the nearest cell where
+/// `target` holds; far beyond the grid if none does.
+fn chamfer(width: usize, height: usize, target: impl Fn(usize) -> bool) -> Vec<f32> {
+    let far = (width + height) as f32 * 2.0;
+    let mut dist: Vec<f32> = (0..width * height).map(|i| if target(i) { 0.0 } else { far }).collect();
+    let steps = [(-1isize, 0isize, 1.0), (0, -1, 1.0), (-1, -1, DIAGONAL), (1, -1, DIAGONAL)];
+    let mut relax = |x: usize, y: usize, sign: isize| {
+        for &(dx, dy, cost) in &steps {
+            let (nx, ny) = (x as isize + dx * sign, y as isize + dy * sign);
+            if nx >= 0 && ny >= 0 && (nx as usize) < width && (ny as usize) < height {
+                let through = dist[ny as usize * width + nx as usize] + cost;
+                let here = &mut dist[y * width + x];
+                *here = here.min(through);
+            }
+        }
+    };
+    for y in 0..height {
+        for x in 0..width {
+            relax(x, y, 1);
+        }
+    }
+    for y in (0..height).rev() {
+        for x in (0..width).rev() {
+            relax(x, y, -1);
+        }
+    }
+    dist
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+
+    use super::*;
+    use crate::terrain_generator::GenerationSettings;
+
+    /// A fat arrowhead pointing right
+    fn arrowhead() -> ShapeMask {
+        ShapeMask::from_polygon(&[[0.1, 0.15], [0.9, 0.5], [0.1, 0.85], [0.35, 0.5]])
+    }
+
+    #[test]
+    fn polygons_cover_their_inside_only() {
+        let mask = arrowhead();
+        assert!(mask.contains(0.5, 0.5) && !mask.contains(0.2, 0.5) && !mask.contains(0.95, 0.1));
+        assert!((mask.share() - 0.2).abs() < 0.02, "share {}", mask.share());
+    }
+
+    #[test]
+    fn land_stays_inside_the_shape_with_a_noisy_coast() {
+        // Asking for more land than the shape holds fills most of it
+        let mask = arrowhead();
+        let settings = GenerationSettings::default().with_land_percentage(0.5);
+        let mut generator = TerrainGenerator::new_with_settings(8, settings);
+        generator.set_shape_mask(Some(mask.clone()));
+        let map = generator.generate_terrain(120, 90);
+        let (mut inside, mut outside) = (Vec::new(), Vec::new());
+        for y in 0..map.height {
+            for x in 0..map.width {
+                let is_land = map.terrain[y][x].elevation > 0.0;
+                // Tiles sample the mask at their top-left corner
+                if mask.contains(x as f64 / 120.0, y as f64 / 90.0) {
+                    inside.push(is_land);
+                } else {
+                    outside.push(is_land);
+                }
+            }
+        }
+        assert_eq!(outside.iter().filter(|&&l| l).count(), 0, "land outside the shape");
+        let filled = inside.iter().filter(|&&l| l).count() as f64 / inside.len() as f64;
+        assert!((0.7..=0.85).contains(&filled), "filled {}", filled);
+    }
+
+    #[test]
+    fn an_images_transparent_background_is_sea() {
+        let mut img = RgbaImage::new(40, 20);
+        for (x, y, pixel) in img.enumerate_pixels_mut() {
+            if (10..30).contains(&x) && (5..15).contains(&y) {
+                *pixel = Rgba([0, 0, 0, 255]);
+            }
+        }
+        let mask = ShapeMask::from_image(&DynamicImage::ImageRgba8(img));
+        assert!(mask.contains(0.5, 0.5) && !mask.contains(0.1, 0.5));
+        assert!(shape_bias(mask.distance(0.5, 0.5)) > 0.0 && shape_bias(mask.distance(0.05, 0.05)) == -0.8);
+    }
+}
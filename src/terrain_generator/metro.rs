@@ -0,0 +1,257 @@
+//! Metro areas: suburb towns around the largest cities.
+//!
+//! After the cities are placed, each of the largest gets a ring of suburbs
+//! on spokes leading out from its center; the road phase then builds one
+//! road along every spoke. Suburbs are ordinary entries of
+//! `TerrainMap::cities`, and a [`MetroArea`] ties them to their center.
+//! Labels collapse a metro area into one name when zoomed out (see
+//! [`TerrainMap::city_labels`]).
+
+use rand::Rng;
+
+use super::biome::Biome;
+use super::settlements::land_regions;
+use super::types::{City, MetroArea, TerrainMap, TerrainPoint};
+use super::TerrainGenerator;
+
+/// Cities at least this large get a metro area
+const METRO_POPULATION: u32 = 250_000;
+
+/// Most metro areas on one map (the largest cities get them)
+const MAX_METROS: usize = 3;
+
+/// Distance in tiles from the center at which suburbs are tried first;
+/// a suburb moves further out along its spoke if the tile is unusable
+const SUBURB_RADIUS: std::ops::Range<f64> = 6.0..10.0;
+
+/// How far past its first distance a suburb may move along its spoke
+const SUBURB_REACH: usize = 5;
+
+/// Closest a suburb may be to any other city, in tiles
+const SUBURB_GAP: f64 = 4.0;
+
+/// Zoom, in pixels per tile, from which each suburb gets its own label
+pub const SUBURB_LABEL_ZOOM: f32 = 8.0;
+
+impl TerrainGenerator {
+    /// Add suburbs around the largest of `cities` and group them into
+    /// metro areas.
+    pub(super) fn generate_metro_areas(
+        &mut self,
+        terrain: &[Vec<TerrainPoint>],
+        cities: &mut Vec<City>,
+    ) -> Vec<MetroArea> {
+        let mut metros = Vec::new();
+        let Some(width) = terrain.first().map(Vec::len) else {
+            return metros;
+        };
+        let height = terrain.len();
+        let regions = land_regions(terrain, self.wrap_x);
+        // On a symmetric map each area grows on one side and is copied to
+        // the others
+        let symmetry = self.city_symmetry();
+
+        let mut centers: Vec<usize> = (0..cities.len())
+            .filter(|&i| cities[i].population >= METRO_POPULATION)
+            .filter(|&i| symmetry.is_source(cities[i].x, cities[i].y, width, height))
+            .collect();
+        centers.sort_by_key(|&i| std::cmp::Reverse(cities[i].population));
+        centers.truncate((MAX_METROS / symmetry.sides()).max(1));
+        let apart = |x: usize, y: usize, ox: usize, oy: usize| {
+            let dx = x as f64 - ox as f64;
+            let dy = y as f64 - oy as f64;
+            (dx * dx + dy * dy).sqrt() >= SUBURB_GAP
+        };
+
+        for center in centers {
+            let (cx, cy) = (cities[center].x, cities[center].y);
+            let region = regions[cy * width + cx];
+            let count = self.rngs.cities.gen_range(3..=5);
+            let first_angle = self.rngs.cities.gen_range(0.0..std::f64::consts::TAU);
+            let mut suburbs = Vec::new();
+
+            for spoke in 0..count {
+                // Spokes spread evenly around the center, give or take
+                let angle = first_angle
+                    + std::f64::consts::TAU * spoke as f64 / count as f64
+                    + self.rngs.cities.gen_range(-0.3..0.3);
+                let radius = self.rngs.cities.gen_range(SUBURB_RADIUS);
+                let site = (0..=SUBURB_REACH).find_map(|step| {
+                    let r = radius + step as f64;
+                    let x = cx as f64 + 0.5 + angle.cos() * r;
+                    let y = cy as f64 + 0.5 + angle.sin() * r;
+                    if x < 0.0 || y < 0.0 {
+                        return None;
+                    }
+                    let (x, y) = (x as usize, y as usize);
+                    let usable = x < width
+                        && y < height
+                        && regions[y * width + x] == region
+                        && matches!(
+                            terrain[y][x].biome,
+                            Biome::Plains | Biome::Hills | Biome::Forest | Biome::Desert | Biome::Beach
+                        )
+                        && cities.iter().all(|city| apart(x, y, city.x, city.y))
+                        // Nor near its own copies, on a symmetric map
+                        && symmetry.images(x, y, width, height).iter().skip(1).all(|&(ix, iy)| apart(x, y, ix, iy));
+                    usable.then_some((x, y))
+                });
+                let Some((x, y)) = site else {
+                    continue;
+                };
+                let population = cities[center].population / self.rngs.cities.gen_range(10..25);
+                for (x, y) in symmetry.images(x, y, width, height) {
+                    let name = self.generate_city_name(cities.len());
+                    cities.push(City {
+                        x,
+                        y,
+                        name,
+                        population,
+                    });
+                }
+                suburbs.push((x, y));
+            }
+
+            if suburbs.is_empty() {
+                continue;
+            }
+            // The area and its copies, each around its copy of the center
+            for k in 0..symmetry.sides() {
+                let center = symmetry.image(k, cx, cy, width, height);
+                let images = suburbs.iter().map(|&(x, y)| symmetry.image(k, x, y, width, height));
+                if let Some(metro) = metros.iter_mut().find(|metro| metro.center == center) {
+                    // A center on the mirror line gathers both copies
+                    for suburb in images {
+                        if !metro.suburbs.contains(&suburb) {
+                            metro.suburbs.push(suburb);
+                        }
+                    }
+                    continue;
+                }
+                let Some(city) = cities.iter().find(|city| (city.x, city.y) == center) else {
+                    continue;
+                };
+                metros.push(MetroArea {
+                    name: format!("Greater {}", city.name),
+                    center,
+                    suburbs: images.collect(),
+                });
+            }
+        }
+        metros
+    }
+}
+
+impl TerrainMap {
+    /// The metro area city `index` is the center or a suburb of.
+    pub fn metro_area_of(&self, index: usize) -> Option<&MetroArea> {
+        let city = self.cities.get(index)?;
+        let tile = (city.x, city.y);
+        self.metro_areas
+            .iter()
+            .find(|metro| metro.center == tile || metro.suburbs.contains(&tile))
+    }
+
+    /// The cities to label at `pixels_per_tile`, as (index, text). Zoomed
+    /// out below [`SUBURB_LABEL_ZOOM`], a metro area is one label: its
+    /// center shows the metro area's name and its suburbs go unlabelled.
+    pub fn city_labels(&self, pixels_per_tile: f32) -> Vec<(usize, String)> {
+        let collapse = pixels_per_tile < SUBURB_LABEL_ZOOM;
+        self.cities
+            .iter()
+            .enumerate()
+            .filter_map(|(index, city)| {
+                let tile = (city.x, city.y);
+                let metro = self.metro_area_of(index).filter(|_| collapse);
+                match metro {
+                    Some(metro) if metro.center == tile => Some((index, metro.name.clone())),
+                    Some(_) => None,
+                    None => Some((index, city.name.clone())),
+                }
+            })
+            .collect()
+    }
+
+    /// Keep the metro areas in step with the cities: suburbs whose city is
+    /// gone are dropped, and so are metro areas whose center is gone or
+    /// that have no suburbs left.
+    pub(super) fn prune_metro_areas(&mut self) {
+        let cities = &self.cities;
+        let exists = |tile: &(usize, usize)| cities.iter().any(|c| (c.x, c.y) == *tile);
+        self.metro_areas.retain_mut(|metro| {
+            metro.suburbs.retain(exists);
+            exists(&metro.center) && !metro.suburbs.is_empty()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::OnceLock;
+
+    use crate::terrain_generator::{GenerationSettings, TerrainGenerator};
+
+    use super::*;
+
+    /// A map crowded enough to grow metro areas, made once
+    fn map() -> &'static TerrainMap {
+        static MAP: OnceLock<TerrainMap> = OnceLock::new();
+        let map = MAP.get_or_init(|| {
+            let settings = GenerationSettings::default().with_city_density(0.9);
+            TerrainGenerator::new_with_settings(8, settings).generate(240, 180)
+        });
+        assert!(!map.metro_areas.is_empty());
+        map
+    }
+
+    fn city_at(map: &TerrainMap, tile: (usize, usize)) -> usize {
+        map.cities.iter().position(|c| (c.x, c.y) == tile).unwrap()
+    }
+
+    #[test]
+    fn metro_areas_grow_round_large_cities() {
+        let map = map();
+        for metro in &map.metro_areas {
+            assert!(map.cities[city_at(map, metro.center)].population >= METRO_POPULATION);
+            for &suburb in &metro.suburbs {
+                let dx = suburb.0 as f64 - metro.center.0 as f64;
+                let dy = suburb.1 as f64 - metro.center.1 as f64;
+                assert!((dx * dx + dy * dy).sqrt() <= SUBURB_RADIUS.end + SUBURB_REACH as f64 + 1.0);
+            }
+        }
+    }
+
+    #[test]
+    fn every_suburb_is_reached_by_a_road_from_its_center() {
+        let map = map();
+        for metro in &map.metro_areas {
+            for &suburb in &metro.suburbs {
+                assert!(map.roads.iter().any(|road| {
+                    road.path.first() == Some(&metro.center) && road.path.last() == Some(&suburb)
+                }));
+                assert_eq!(map.metro_area_of(city_at(map, suburb)).unwrap().name, metro.name);
+            }
+        }
+    }
+
+    #[test]
+    fn zoomed_out_labels_name_the_metro_area_and_not_its_suburbs() {
+        let map = map();
+        let far = map.city_labels(2.0);
+        for metro in &map.metro_areas {
+            assert!(far.contains(&(city_at(map, metro.center), metro.name.clone())));
+            assert!(far.iter().all(|&(i, _)| !metro.suburbs.contains(&(map.cities[i].x, map.cities[i].y))));
+        }
+    }
+
+    #[test]
+    fn zoomed_in_labels_name_every_city() {
+        let map = map();
+        let near = map.city_labels(SUBURB_LABEL_ZOOM);
+        assert_eq!(near.len(), map.cities.len());
+        for metro in &map.metro_areas {
+            let center = city_at(map, metro.center);
+            assert!(near.contains(&(center, map.cities[center].name.clone())));
+        }
+    }
+}
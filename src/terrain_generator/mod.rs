@@ -2,36 +2,166 @@
 //!
 //! The generator is split into focused modules:
 //! - [`elevation`]: continent shapes and the elevation field
+//! - [`mask`]: shape masks that land is kept within
+//! - [`symmetry`]: mirrored and rotated maps
 //! - [`climate`]: moisture and temperature fields
 //! - [`biome`]: biome classification and colors
 //! - [`hydrology`]: river tracing
-//! - [`settlements`]: city placement, road pathfinding, bridges
+//! - [`settlements`]: city placement, road pathfinding, bridges and fords
+//! - [`metro`]: suburbs around the largest cities
+//! - [`modern`]: motorways, airports, container terminals and power lines
 //! - [`labels`]: named-region detection and label placement
+//! - [`parks`]: national parks and wilderness areas far from the cities
+//! - [`coastline`]: coastlines traced as outlines of the land
+//! - [`smoothing`]: rivers and roads as smooth curves for drawing
 //! - [`names`]: procedural place-name generation
+//! - [`poi`]: points of interest, built-in and registered kinds
+//! - [`noise_source`]: the noise functions fields are sampled from
+//! - [`progress`]: generation phases and progress reporting
+//! - [`random`]: independent random streams for the phases
+//! - [`query`]: lookups and routing on a finished map
+//! - [`viewshed`]: line of sight from a point
+//! - [`fog`]: fog of war, the tiles the players have seen
+//! - [`features`]: finding the named feature under the pointer
+//! - [`edit`]: hand edits to a finished map and re-deriving what they affect
+//! - [`region`]: rerolling one rectangle of a finished map
+//! - [`crop`]: cutting a rectangle out of a finished map
+//! - [`stitch`]: assembling one world from maps generated piece by piece
+//! - [`refine`]: a finished map at a higher resolution with added detail
+//! - [`downsample`]: a coarse overview of a finished map
+//! - [`network`]: the roads of a finished map as a graph
+//! - [`stats`]: summary statistics of a finished map
+//! - [`weather`]: day-by-day weather from the climate fields
+//! - [`cache`]: reusing phase outputs when only later settings change
+//! - [`gpu`]: computing the noise fields in a compute shader (`gpu` feature)
 
 mod biome;
+mod cache;
 mod climate;
+mod coastline;
+mod crop;
+mod downsample;
+mod drainage;
+mod economy;
+mod edit;
 mod elevation;
+mod features;
+mod fog;
+mod gpu;
 mod hydrology;
 mod labels;
+mod mask;
+mod metro;
+mod modern;
 mod names;
+mod network;
+mod noise_source;
+mod parks;
+mod poi;
+mod progress;
+mod query;
+mod random;
+mod refine;
+mod region;
+mod score;
 mod settlements;
+mod smoothing;
+mod stats;
+mod stitch;
+mod symmetry;
+mod territories;
+mod travel;
 mod types;
+mod validate;
+mod viewshed;
+mod weather;
 
 pub use biome::Biome;
-pub use types::{Bridge, City, GenerationSettings, PlaceLabel, Road, TerrainMap, TerrainPoint};
+pub use cache::PhaseCache;
+pub use coastline::COAST_TOLERANCE;
+pub use drainage::{RiverFlow, RiverOutlet};
+pub use economy::{CityEconomy, Economy, Good, TradeFlow};
+pub use edit::{Brush, BrushEffect, Rederive};
+pub use features::{FeatureIndex, LabelBox, MapFeature};
+pub use fog::{FogOfWar, ROUTE_REVEAL};
+pub use mask::{ShapeMask, MASK_BLEND, MASK_FILL, MASK_WARP};
+pub use metro::SUBURB_LABEL_ZOOM;
+pub use modern::{AIRPORT_POPULATION, MOTORWAY, PORT_POPULATION, POWER_LINE_POPULATION};
+pub use network::{NetworkRoute, NodeKind, RoadEdge, RoadNetwork, RoadNode};
+pub use noise_source::{noise_source, NoiseSource};
+pub use parks::{MAX_PARKS, PARK_CLEARANCE, PARK_MIN_SIZE, PARK_SETTLED_SHARE};
+pub use poi::{
+    PoiIcon, PoiKind, PoiPlacement, PoiRule, PoiSite, PoiStyle, PointOfInterest, BRIDGE_KIND, CITY_KIND,
+};
+pub use progress::{
+    CancellationToken, GenerationError, GenerationObserver, GenerationPhase, PhaseTimings,
+    ProgressCallback,
+};
+pub use query::{Route, TileInfo};
+pub use refine::DETAIL;
+pub use region::{Region, RegionReroll};
+pub use score::MapScore;
+pub use settlements::FORD_REACH;
+pub use smoothing::SMOOTHING_PASSES;
+pub use stats::{MapStats, RoadTotal, ELEVATION_BINS};
+pub use stitch::{ROAD_JOIN, SEAM_BLEND};
+pub use symmetry::Symmetry;
+pub use territories::Territories;
+pub use travel::{TravelProfile, TravelSpeeds, TravelTimes};
+pub use types::{
+    Bridge, City, Coastline, Formation, GenerationSettings, MetroArea, NamedRegion, NoiseKind, PlaceLabel, PowerLine,
+    Road, SettlementWeights, SmoothPaths, TerrainMap, TerrainPoint, MAX_EDGE_ROADS,
+};
+pub use validate::{Problem, Severity, ValidationReport};
+pub use viewshed::{Viewpoint, ELEVATION_KM};
+pub use weather::{Weather, DAYS_PER_YEAR};
 
-use noise::Perlin;
-use rand::SeedableRng;
-use rand_chacha::ChaCha8Rng;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use rayon::prelude::*;
+
+use crate::name_lists::NameLists;
+use cache::RunKey;
+use names::UsedNames;
+use random::PhaseRngs;
+use types::offset_tile;
+
+/// Rows the per-tile field loops hand to rayon at a time, reporting
+/// progress in between
+const ROW_BATCH: usize = 32;
 
 pub struct TerrainGenerator {
-    elevation_noise: Perlin,
-    moisture_noise: Perlin,
-    temperature_noise: Perlin,
-    detail_noise: Perlin,
-    rng: ChaCha8Rng,
+    seed: u32,
+    elevation_noise: Box<dyn NoiseSource>,
+    moisture_noise: Box<dyn NoiseSource>,
+    temperature_noise: Box<dyn NoiseSource>,
+    detail_noise: Box<dyn NoiseSource>,
+    rngs: PhaseRngs,
     settings: GenerationSettings,
+    /// Behind a mutex only so the generator is `Sync` and the field loops
+    /// can share it between threads; progress is reported through
+    /// `&mut self`, which never has to lock
+    progress: Option<Mutex<Box<dyn GenerationObserver>>>,
+    /// The phase reporting progress and when it started
+    running_phase: Option<(GenerationPhase, Instant)>,
+    timings: PhaseTimings,
+    /// Only set while `generate_cancellable` runs
+    cancel: Option<CancellationToken>,
+    cache: Option<PhaseCache>,
+    /// Noise fields are computed on the GPU (see `set_gpu`)
+    gpu: bool,
+    /// Names given on the map being built, kept unique per kind
+    used_names: UsedNames,
+    /// The user's words, drawn from instead of the built-in names
+    name_lists: NameLists,
+    /// Kinds of point of interest placed after the labels
+    poi_kinds: Vec<PoiKind>,
+    /// The shape land is raised within, in place of the continent plan
+    shape_mask: Option<ShapeMask>,
+    /// Whether the map being built wraps east-west: the setting for new
+    /// terrain, the map's own when later phases rerun on it
+    wrap_x: bool,
 }
 
 impl TerrainGenerator {
@@ -39,58 +169,113 @@ impl TerrainGenerator {
         Self::new_with_settings(seed, GenerationSettings::default())
     }
 
+    /// A generator for `seed`. Settings out of their range are clamped
+    /// into it.
     pub fn new_with_settings(seed: u32, settings: GenerationSettings) -> Self {
+        let settings = settings.clamped();
         TerrainGenerator {
-            elevation_noise: Perlin::new(seed),
-            moisture_noise: Perlin::new(seed.wrapping_add(1)),
-            temperature_noise: Perlin::new(seed.wrapping_add(2)),
-            detail_noise: Perlin::new(seed.wrapping_add(3)),
-            rng: ChaCha8Rng::seed_from_u64(seed as u64),
+            seed,
+            elevation_noise: noise_source(settings.elevation_noise, seed),
+            moisture_noise: noise_source(settings.moisture_noise, seed.wrapping_add(1)),
+            temperature_noise: noise_source(NoiseKind::Perlin, seed.wrapping_add(2)),
+            detail_noise: noise_source(NoiseKind::Perlin, seed.wrapping_add(3)),
+            rngs: PhaseRngs::from_seed(seed),
             settings,
+            progress: None,
+            running_phase: None,
+            timings: PhaseTimings::new(),
+            cancel: None,
+            cache: None,
+            gpu: false,
+            used_names: UsedNames::default(),
+            name_lists: NameLists::default(),
+            poi_kinds: PoiKind::builtin(),
+            shape_mask: None,
+            wrap_x: settings.wrap_x,
         }
     }
 
     pub fn set_settings(&mut self, settings: GenerationSettings) {
+        let settings = settings.clamped();
+        self.elevation_noise = noise_source(settings.elevation_noise, self.seed);
+        self.moisture_noise = noise_source(settings.moisture_noise, self.seed.wrapping_add(1));
         self.settings = settings;
     }
 
     pub fn generate(&mut self, width: usize, height: usize) -> TerrainMap {
-        let mut terrain = vec![
-            vec![
-                TerrainPoint {
-                    elevation: 0.0,
-                    moisture: 0.0,
-                    temperature: 0.0,
-                    biome: Biome::Plains,
-                };
-                width
-            ];
-            height
-        ];
+        self.all_phases(width, height)
+            .expect("generation without a cancellation token runs to completion")
+    }
 
-        // Generate the elevation field first (sea level depends on the whole
-        // distribution), then moisture (depends on distance to the ocean),
-        // then temperature and biome for every tile
-        let elevations = self.generate_elevation_field(width, height);
-        let moistures = self.generate_moisture_field(&elevations);
-        for y in 0..height {
-            for x in 0..width {
-                let elevation = elevations[y][x];
-                let moisture = moistures[y][x];
-                let temperature = self.generate_temperature(x, y, width, height, elevation);
-                let biome = self.determine_biome(elevation, moisture, temperature);
-
-                terrain[y][x] = TerrainPoint {
-                    elevation,
-                    moisture,
-                    temperature,
-                    biome,
-                };
-            }
+    /// Like `generate`, but once `cancel` is cancelled stops at the next
+    /// phase boundary, or the next batch of rows in the elevation and
+    /// climate phases, with `GenerationError::Cancelled`. The phase that was
+    /// interrupted never reports progress 1.0.
+    pub fn generate_cancellable(
+        &mut self,
+        width: usize,
+        height: usize,
+        cancel: &CancellationToken,
+    ) -> Result<TerrainMap, GenerationError> {
+        self.cancel = Some(cancel.clone());
+        let result = self.all_phases(width, height);
+        self.cancel = None;
+        result
+    }
+
+    fn all_phases(&mut self, width: usize, height: usize) -> Result<TerrainMap, GenerationError> {
+        let key = self.run_key(width, height);
+        if let Some(map) = self.cached(key.as_ref(), GenerationPhase::Labels, |c| &c.map) {
+            return Ok(map);
+        }
+        let mut map = self.terrain_phases(width, height)?;
+        self.settlement_phases(&mut map, GenerationPhase::Cities)?;
+        self.keep(key.as_ref(), |c| &mut c.map, &map);
+        Ok(map)
+    }
+
+    /// Run only the physical phases (elevation, climate, rivers). The
+    /// returned map has no cities, roads or labels yet.
+    pub fn generate_terrain(&mut self, width: usize, height: usize) -> TerrainMap {
+        self.terrain_phases(width, height)
+            .expect("generation without a cancellation token runs to completion")
+    }
+
+    fn terrain_phases(&mut self, width: usize, height: usize) -> Result<TerrainMap, GenerationError> {
+        self.checkpoint()?;
+        self.wrap_x = self.settings.wrap_x;
+        if width == 0 || height == 0 {
+            return Ok(TerrainMap {
+                seed: self.seed,
+                wrap_x: self.wrap_x,
+                ..TerrainMap::empty(width, height)
+            });
+        }
+        let key = self.run_key(width, height);
+        let key = key.as_ref();
+        if let Some(map) = self.cached(key, GenerationPhase::Rivers, |c| &c.rivers) {
+            return Ok(map);
         }
 
+        let mut terrain = match self.cached(key, GenerationPhase::Climate, |c| &c.climate) {
+            Some(terrain) => terrain,
+            None => {
+                // Generate the elevation field first (sea level depends on the
+                // whole distribution), then moisture (depends on distance to
+                // the ocean), then temperature and biome for every tile
+                let elevations = self.elevation_phase(key, width, height)?;
+                self.checkpoint()?;
+                let terrain = self.climate_phase(&elevations, width, height)?;
+                self.keep(key, |c| &mut c.climate, &terrain);
+                terrain
+            }
+        };
+        self.checkpoint()?;
+
         // Generate rivers and lakes (lake tiles are marked in `terrain`)
-        let rivers = self.generate_hydrology(&mut terrain);
+        self.report_progress(GenerationPhase::Rivers, 0.0);
+        let symmetry = self.settings.symmetry;
+        let rivers = symmetry.apply_to_paths(self.generate_hydrology(&mut terrain), width, height);
 
         // Apply river erosion and widen rivers
         for river in &rivers {
@@ -103,15 +288,16 @@ impl TerrainGenerator {
                     terrain[y][x].elevation *= 0.9; // More erosion
 
                     // Widen rivers by affecting adjacent cells
-                    for dy in -1i32..=1 {
-                        for dx in -1i32..=1 {
+                    for dy in -1isize..=1 {
+                        for dx in -1isize..=1 {
                             // Direct neighbors get more effect
                             if dx != 0 && dy != 0 {
                                 continue;
                             }
-                            let nx = (x as i32 + dx) as usize;
-                            let ny = (y as i32 + dy) as usize;
-                            if nx < width && ny < height && terrain[ny][nx].elevation > -0.1 {
+                            let Some((nx, ny)) = offset_tile(x, y, dx, dy, width, height, self.wrap_x) else {
+                                continue;
+                            };
+                            if terrain[ny][nx].elevation > -0.1 {
                                 terrain[ny][nx].elevation *= 0.95;
                             }
                         }
@@ -119,33 +305,193 @@ impl TerrainGenerator {
                 }
             }
         }
+        // Lakes, and where rivers met their images, are made alike again
+        symmetry.apply(&mut terrain);
+        self.report_progress(GenerationPhase::Rivers, 1.0);
 
-        // Generate cities following Zipf's law
-        let cities = self.generate_cities(&terrain);
+        let mut map = TerrainMap {
+            terrain,
+            rivers,
+            seed: self.seed,
+            wrap_x: self.wrap_x,
+            ..TerrainMap::empty(width, height)
+        };
+        map.coastlines = map.traced_coastlines();
+        map.smoothed = map.smoothed_paths();
+        self.keep(key, |c| &mut c.rivers, &map);
+        Ok(map)
+    }
 
-        // Generate roads connecting cities
-        let (roads, bridges) = self.generate_roads(&terrain, &cities, &rivers);
+    /// The elevation field, taken from the cache if it holds the one of
+    /// `key`'s run.
+    fn elevation_phase(
+        &mut self,
+        key: Option<&RunKey>,
+        width: usize,
+        height: usize,
+    ) -> Result<Vec<Vec<f64>>, GenerationError> {
+        if let Some(elevations) = self.cached(key, GenerationPhase::Elevation, |c| &c.elevation) {
+            return Ok(elevations);
+        }
+        self.report_progress(GenerationPhase::Elevation, 0.0);
+        let elevations = self.generate_elevation_field(width, height)?;
+        self.report_progress(GenerationPhase::Elevation, 1.0);
+        self.keep(key, |c| &mut c.elevation, &elevations);
+        Ok(elevations)
+    }
 
-        // Generate place labels including forests and swamps
-        let labels = self.generate_labels(&terrain, &rivers);
+    /// Moisture, temperature and biome for every tile of an elevation field.
+    fn climate_phase(
+        &mut self,
+        elevations: &[Vec<f64>],
+        width: usize,
+        height: usize,
+    ) -> Result<Vec<Vec<TerrainPoint>>, GenerationError> {
+        let mut terrain = vec![
+            vec![
+                TerrainPoint {
+                    elevation: 0.0,
+                    moisture: 0.0,
+                    temperature: 0.0,
+                    biome: Biome::Plains,
+                };
+                width
+            ];
+            height
+        ];
 
-        TerrainMap {
-            width,
-            height,
-            terrain,
-            labels,
-            rivers,
-            cities,
-            roads,
-            bridges,
+        self.report_progress(GenerationPhase::Climate, 0.0);
+        let moistures = self.generate_moisture_field(elevations);
+        let temperature_noise = self.temperature_noise_field(width, height);
+        self.report_progress(GenerationPhase::Climate, 0.2);
+        // Every tile depends only on its own fields, so rows are filled in
+        // parallel with the same result as one after another
+        for (batch, rows) in terrain.chunks_mut(ROW_BATCH).enumerate() {
+            let first = batch * ROW_BATCH;
+            self.checkpoint()?;
+            self.report_progress(GenerationPhase::Climate, 0.2 + 0.8 * first as f32 / height as f32);
+            rows.par_iter_mut().enumerate().for_each(|(i, row)| {
+                let y = first + i;
+                let noise: Vec<f64> = match &temperature_noise {
+                    Some(field) => field[y * width..(y + 1) * width].iter().map(|&v| v as f64).collect(),
+                    None => self.temperature_noise_row(y, width, height),
+                };
+                for (x, point) in row.iter_mut().enumerate() {
+                    let elevation = elevations[y][x];
+                    let moisture = moistures[y][x];
+                    let temperature = self.temperature_from_noise(noise[x], y, height, elevation);
+                    let biome = self.determine_biome(elevation, moisture, temperature);
+
+                    *point = TerrainPoint {
+                        elevation,
+                        moisture,
+                        temperature,
+                        biome,
+                    };
+                }
+            });
         }
+        self.settings.symmetry.apply(&mut terrain);
+        self.report_progress(GenerationPhase::Climate, 1.0);
+        Ok(terrain)
     }
+
+    /// Re-run `from` and every later phase on an existing map, keeping the
+    /// results of the phases before it. Rivers erode the terrain they cross,
+    /// so the physical phases can't be rerun in place: asking for one of
+    /// them regenerates the whole map at the same size.
+    pub fn regenerate(&mut self, map: &mut TerrainMap, from: GenerationPhase) {
+        self.settlement_phases(map, from)
+            .expect("generation without a cancellation token runs to completion")
+    }
+
+    fn settlement_phases(
+        &mut self,
+        map: &mut TerrainMap,
+        from: GenerationPhase,
+    ) -> Result<(), GenerationError> {
+        if from < GenerationPhase::Cities {
+            *map = self.terrain_phases(map.width, map.height)?;
+        }
+        if map.width == 0 || map.height == 0 {
+            return Ok(());
+        }
+        self.wrap_x = map.wrap_x;
+
+        // Names of the phases that rerun are free to be given again
+        self.used_names = UsedNames::kept_by(map, from);
+
+        // Generate cities following Zipf's law
+        self.checkpoint()?;
+        if from <= GenerationPhase::Cities {
+            self.report_progress(GenerationPhase::Cities, 0.0);
+            map.cities = self.generate_cities(&map.terrain, &map.rivers);
+            map.metro_areas = self.generate_metro_areas(&map.terrain, &mut map.cities);
+            self.report_progress(GenerationPhase::Cities, 1.0);
+        }
+        self.checkpoint()?;
+
+        // Generate roads connecting cities
+        if from <= GenerationPhase::Roads {
+            self.report_progress(GenerationPhase::Roads, 0.0);
+            let (roads, bridges) =
+                self.generate_roads(&map.terrain, &map.cities, &map.metro_areas, &map.rivers);
+            map.roads = roads;
+            map.bridges = bridges;
+            map.power_lines = if self.settings.modern { map.strung_power_lines() } else { Vec::new() };
+            self.report_progress(GenerationPhase::Roads, 1.0);
+        }
+        self.checkpoint()?;
+
+        // Generate place labels including forests and swamps, then the
+        // points of interest
+        self.report_progress(GenerationPhase::Labels, 0.0);
+        (map.labels, map.regions) = self.generate_labels(&map.terrain, &map.rivers);
+        map.designate_parks();
+        self.report_progress(GenerationPhase::Labels, 0.5);
+        self.place_points_of_interest(map);
+        map.smoothed = map.smoothed_paths();
+        self.report_progress(GenerationPhase::Labels, 1.0);
+        Ok(())
+    }
+}
+
+/// The map `TerrainGenerator::new(seed).generate(width, height)` makes,
+/// generated once and shared by every test that only reads it. Tests that
+/// change it work on a clone.
+#[cfg(test)]
+pub(crate) fn test_map(seed: u32, width: usize, height: usize) -> &'static TerrainMap {
+    use std::collections::HashMap;
+    use std::sync::{Mutex, OnceLock};
+
+    type Maps = HashMap<(u32, usize, usize), &'static OnceLock<TerrainMap>>;
+    static MAPS: OnceLock<Mutex<Maps>> = OnceLock::new();
+    // Hold the lock only to find the map's cell, so tests wanting other
+    // maps generate theirs at the same time
+    let cell: &'static OnceLock<TerrainMap> = MAPS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap()
+        .entry((seed, width, height))
+        .or_insert_with(|| Box::leak(Box::default()));
+    cell.get_or_init(|| TerrainGenerator::new(seed).generate(width, height))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn ice_sheets_only_cover_maps_set_cold() {
+        let temperate = GenerationSettings::default();
+        let cold = GenerationSettings {
+            temperature: 0.15,
+            ..Default::default()
+        };
+        assert_ne!(Biome::classify(0.3, 0.4, 0.0, &temperate), Biome::SnowPeaks);
+        assert_eq!(Biome::classify(0.3, 0.4, 0.0, &cold), Biome::SnowPeaks);
+    }
+
     #[test]
     fn land_percentage_matches_settings() {
         for &land in &[0.2f32, 0.4, 0.7] {
@@ -171,6 +517,46 @@ mod tests {
         }
     }
 
+    /// Tiles of `biomes` on the terrain generated with `settings`
+    fn count_biomes(settings: GenerationSettings, biomes: &[Biome]) -> usize {
+        let map = TerrainGenerator::new_with_settings(7, settings).generate_terrain(160, 120);
+        map.terrain
+            .iter()
+            .flatten()
+            .filter(|p| biomes.contains(&p.biome))
+            .count()
+    }
+
+    fn densities(mountain_density: f32, forest_density: f32) -> GenerationSettings {
+        GenerationSettings {
+            mountain_density,
+            forest_density,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn highlands_follow_mountain_density() {
+        let highlands = [Biome::Hills, Biome::Mountains, Biome::SnowPeaks];
+        let usual = count_biomes(densities(0.5, 0.5), &highlands);
+        assert!(count_biomes(densities(0.9, 0.5), &highlands) > usual);
+        assert!(count_biomes(densities(0.1, 0.5), &highlands) < usual);
+    }
+
+    #[test]
+    fn forests_follow_forest_density() {
+        assert!(count_biomes(densities(0.5, 0.9), &[Biome::Forest]) > count_biomes(densities(0.5, 0.1), &[Biome::Forest]));
+    }
+
+    #[test]
+    fn forests_and_swamps_follow_moisture() {
+        let wet = [Biome::Forest, Biome::Swamp];
+        let moisture = |moisture: f32| GenerationSettings::default().with_moisture(moisture);
+        let usual = count_biomes(moisture(0.5), &wet);
+        assert!(count_biomes(moisture(0.9), &wet) > usual);
+        assert!(count_biomes(moisture(0.1), &wet) < usual);
+    }
+
     #[test]
     fn same_seed_generates_identical_maps() {
         let make = || TerrainGenerator::new(99).generate(120, 90);
@@ -183,6 +569,160 @@ mod tests {
         );
     }
 
+    /// The phases timed, then the progress reported, while generating a map
+    type Reports = (Vec<GenerationPhase>, Vec<(GenerationPhase, f32)>);
+
+    /// The reports of one map, recorded once for the tests that check them.
+    fn progress_reports() -> &'static Reports {
+        use std::sync::{Arc, Mutex, OnceLock};
+
+        static REPORTS: OnceLock<Reports> = OnceLock::new();
+        REPORTS.get_or_init(|| {
+            let reports = Arc::new(Mutex::new(Vec::new()));
+            let sink = Arc::clone(&reports);
+            let mut generator = TerrainGenerator::new(3);
+            generator.set_progress_callback(Box::new(move |phase, fraction| {
+                sink.lock().unwrap().push((phase, fraction));
+            }));
+            generator.generate(120, 90);
+            let timed = generator.phase_timings().iter().map(|t| t.0).collect();
+            let reports = reports.lock().unwrap().clone();
+            (timed, reports)
+        })
+    }
+
+    #[test]
+    fn every_phase_is_timed_in_order() {
+        assert_eq!(progress_reports().0, GenerationPhase::ALL);
+    }
+
+    #[test]
+    fn progress_runs_from_0_to_1_through_each_phase_in_order() {
+        let reports = &progress_reports().1;
+        for phase in GenerationPhase::ALL {
+            let fractions: Vec<f32> = reports
+                .iter()
+                .filter(|(p, _)| *p == phase)
+                .map(|&(_, f)| f)
+                .collect();
+            assert_eq!(fractions.first(), Some(&0.0), "{:?} must start at 0", phase);
+            assert_eq!(fractions.last(), Some(&1.0), "{:?} must end at 1", phase);
+            assert_eq!(fractions.iter().filter(|&&f| f >= 1.0).count(), 1);
+            assert!(fractions.windows(2).all(|w| w[0] <= w[1]));
+        }
+        let order: Vec<GenerationPhase> = reports.iter().map(|&(p, _)| p).collect();
+        assert!(order.windows(2).all(|w| w[0] <= w[1]));
+    }
+
+    #[test]
+    fn regenerating_times_only_the_phases_rerun() {
+        let mut map = test_map(3, 120, 90).clone();
+        let mut generator = TerrainGenerator::new(3);
+        generator.regenerate(&mut map, GenerationPhase::Roads);
+        let timed: Vec<GenerationPhase> = generator.phase_timings().iter().map(|t| t.0).collect();
+        assert_eq!(timed, [GenerationPhase::Roads, GenerationPhase::Labels]);
+    }
+
+    #[test]
+    fn cancelling_stops_at_the_next_phase() {
+        use std::sync::{Arc, Mutex};
+
+        let cancel = CancellationToken::new();
+        let phases = Arc::new(Mutex::new(Vec::new()));
+        let (trigger, sink) = (cancel.clone(), Arc::clone(&phases));
+        let mut generator = TerrainGenerator::new(5);
+        generator.set_progress_callback(Box::new(move |phase, _| {
+            sink.lock().unwrap().push(phase);
+            if phase == GenerationPhase::Rivers {
+                trigger.cancel();
+            }
+        }));
+        assert_eq!(
+            generator.generate_cancellable(80, 60, &cancel).err(),
+            Some(GenerationError::Cancelled)
+        );
+        assert_eq!(
+            phases.lock().unwrap().last(),
+            Some(&GenerationPhase::Rivers)
+        );
+    }
+
+    #[test]
+    fn cancelling_stops_long_phases_between_batches_of_rows() {
+        use std::sync::{Arc, Mutex};
+
+        let cancel = CancellationToken::new();
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let (trigger, sink) = (cancel.clone(), Arc::clone(&reports));
+        let mut generator = TerrainGenerator::new(5);
+        generator.set_progress_callback(Box::new(move |phase, fraction| {
+            sink.lock().unwrap().push((phase, fraction));
+            if fraction > 0.0 {
+                trigger.cancel();
+            }
+        }));
+        assert!(generator.generate_cancellable(80, 4 * ROW_BATCH, &cancel).is_err());
+        let reports = reports.lock().unwrap();
+        assert!(reports.iter().all(|&(phase, fraction)| {
+            phase == GenerationPhase::Elevation && fraction < 0.5
+        }));
+    }
+
+    #[test]
+    fn uncancelled_generation_makes_the_usual_map() {
+        let finished = TerrainGenerator::new(5)
+            .generate_cancellable(80, 60, &CancellationToken::new())
+            .unwrap();
+        assert_eq!(
+            serde_json::to_string(&finished).unwrap(),
+            serde_json::to_string(test_map(5, 80, 60)).unwrap()
+        );
+    }
+
+    #[test]
+    fn odd_sizes_and_settings_generate_without_panicking() {
+        let extremes = [
+            GenerationSettings::default(),
+            GenerationSettings {
+                river_density: f32::NAN,
+                city_density: 1.0,
+                land_percentage: 1.0,
+                temperature: f32::INFINITY,
+                mountain_density: -3.0,
+                ..Default::default()
+            },
+            GenerationSettings {
+                city_density: 1.0,
+                land_percentage: 0.0,
+                river_density: 1.0,
+                ..Default::default()
+            },
+        ];
+        for settings in extremes {
+            for (width, height) in [(0, 0), (0, 10), (1, 1), (2, 3), (5, 5), (3, 70), (70, 3)] {
+                let map = TerrainGenerator::new_with_settings(11, settings).generate(width, height);
+                assert_eq!((map.width, map.height), (width, height));
+                assert_eq!(map.terrain.len(), if width == 0 { 0 } else { height });
+            }
+        }
+    }
+
+    #[test]
+    fn rerolling_cities_keeps_the_terrain() {
+        let original = test_map(21, 120, 90);
+        let mut map = original.clone();
+        TerrainGenerator::new(22).regenerate(&mut map, GenerationPhase::Cities);
+
+        assert_eq!(
+            serde_json::to_string(&map.terrain).unwrap(),
+            serde_json::to_string(&original.terrain).unwrap()
+        );
+        assert_eq!(map.rivers, original.rivers);
+        assert_eq!(map.labels.len(), original.labels.len());
+        let positions = |m: &TerrainMap| m.cities.iter().map(|c| (c.x, c.y)).collect::<Vec<_>>();
+        assert_ne!(positions(&map), positions(original));
+    }
+
     #[test]
     fn rivers_reach_water_edge_lake_or_confluence() {
         use std::collections::HashMap;
@@ -214,4 +754,51 @@ mod tests {
             );
         }
     }
+
+    /// Seed 5 at 120x90, wrapping east to west
+    fn wrapped_map() -> &'static TerrainMap {
+        use std::sync::OnceLock;
+
+        static MAP: OnceLock<TerrainMap> = OnceLock::new();
+        MAP.get_or_init(|| {
+            let settings = GenerationSettings::default().with_wrap_x(true);
+            TerrainGenerator::new_with_settings(5, settings).generate(120, 90)
+        })
+    }
+
+    #[test]
+    fn wrapped_terrain_changes_across_the_seam_no_more_than_elsewhere() {
+        let map = wrapped_map();
+        assert!(map.wrap_x);
+        let step = |a: usize, b: usize| -> f64 {
+            map.terrain.iter().map(|row| (row[a].elevation - row[b].elevation).abs()).sum::<f64>() / map.height as f64
+        };
+        let steepest = (1..map.width).map(|x| step(x - 1, x)).fold(0.0, f64::max);
+        assert!(step(map.width - 1, 0) <= steepest, "seam step {} over {}", step(map.width - 1, 0), steepest);
+    }
+
+    #[test]
+    fn wrapped_rivers_and_roads_cross_the_seam_the_short_way_round() {
+        let map = wrapped_map();
+        for path in map.rivers.iter().chain(map.roads.iter().map(|r| &r.path)) {
+            for pair in path.windows(2) {
+                assert!(map.x_delta(pair[0].0 as f32, pair[1].0 as f32).abs() <= 1.0);
+            }
+        }
+    }
+
+    #[test]
+    fn wrapped_images_tile_at_their_edge_columns() {
+        let img = crate::terrain_renderer::TerrainRenderer::render_to_image(wrapped_map(), 2);
+        let column_step = |a: u32, b: u32| -> u32 {
+            (0..img.height())
+                .map(|y| {
+                    let (p, q) = (img.get_pixel(a, y), img.get_pixel(b, y));
+                    (0..3).map(|c| p[c].abs_diff(q[c]) as u32).sum::<u32>()
+                })
+                .sum()
+        };
+        let steepest = (1..img.width()).map(|x| column_step(x - 1, x)).max().unwrap();
+        assert!(column_step(img.width() - 1, 0) <= steepest);
+    }
 }
@@ -0,0 +1,266 @@
+//! The modern era: contemporary infrastructure on the same worlds, with
+//! `GenerationSettings::modern` set.
+//!
+//! - The highways joining the largest cities are built as motorways,
+//!   numbered "M1", "M2", ...; renderers draw them widest, and travel and
+//!   stitching rank them with the highways or above.
+//! - Airports go on open ground a few tiles out from the cities of at
+//!   least `AIRPORT_POPULATION`, and container terminals on the coast by
+//!   the ports of at least `PORT_POPULATION`: two more kinds of point of
+//!   interest ([`PoiKind::modern`]), placed after the registered ones.
+//! - Power lines run straight from city to city whatever the ground,
+//!   joining the cities of at least `POWER_LINE_POPULATION` on each land
+//!   mass by the shortest lines that never cross the sea, and are kept in
+//!   `TerrainMap::power_lines`.
+
+use std::sync::Arc;
+
+use super::biome::Biome;
+use super::poi::{word_pool, PoiIcon, PoiKind, PoiPlacement, PoiSite, PoiStyle};
+use super::types::{wrap_dx, City, PowerLine, TerrainMap};
+
+/// Fewest people in a city that gets an airport
+pub const AIRPORT_POPULATION: u32 = 100_000;
+
+/// Fewest people in a port that gets a container terminal
+pub const PORT_POPULATION: u32 = 50_000;
+
+/// Fewest people in a city the power lines reach
+pub const POWER_LINE_POPULATION: u32 = 50_000;
+
+/// Road type of the motorways
+pub const MOTORWAY: &str = "motorway";
+
+/// The largest population of a city of at least `least` people within
+/// `reach` tiles of the site, if there is one.
+fn city_within(site: &PoiSite, least: u32, reach: f32) -> Option<u32> {
+    site.map
+        .cities
+        .iter()
+        .filter(|c| c.population >= least)
+        .filter(|c| (c.x as f32 - site.x as f32).hypot(c.y as f32 - site.y as f32) <= reach)
+        .map(|c| c.population)
+        .max()
+}
+
+impl PoiKind {
+    /// The kinds a modern-era map adds: airports by the large cities and
+    /// container terminals by the large ports.
+    pub fn modern() -> Vec<PoiKind> {
+        vec![
+            PoiKind {
+                style: PoiStyle {
+                    kind: "airport".to_string(),
+                    icon: PoiIcon::Circle,
+                    icon_color: [70, 110, 220],
+                    icon_radius: 5.0,
+                    label_size: 13.0,
+                    label_color: [210, 225, 255],
+                },
+                names: word_pool(
+                    &["Kingsfield", "Northgate", "Riverside", "Westmoor", "Hillcrest", "Lakeside", "Ashford"],
+                    &[" Airport", " International Airport", " Airfield"],
+                ),
+                placement: PoiPlacement {
+                    // Flat, open ground out of town, by the larger city first
+                    rule: Arc::new(|site| match site.point.biome {
+                        Biome::Plains | Biome::Desert if site.city_distance >= 3.0 => {
+                            city_within(site, AIRPORT_POPULATION, 8.0)
+                                .map_or(0.0, |people| people as f32 / AIRPORT_POPULATION as f32)
+                        }
+                        _ => 0.0,
+                    }),
+                    density: 2.0,
+                    spacing: 18.0,
+                },
+            },
+            PoiKind {
+                style: PoiStyle {
+                    kind: "container terminal".to_string(),
+                    icon: PoiIcon::Square,
+                    icon_color: [60, 140, 150],
+                    icon_radius: 4.0,
+                    label_size: 12.0,
+                    label_color: [200, 235, 235],
+                },
+                names: word_pool(
+                    &["Deepwater", "North Quay", "Harbour", "Eastern", "Channel", "Anchor"],
+                    &[" Container Terminal", " Freight Terminal", " Docks"],
+                ),
+                placement: PoiPlacement {
+                    rule: Arc::new(|site| {
+                        let land = !site.point.biome.is_water() && site.point.biome != Biome::River;
+                        if land && site.sea_neighbours >= 2 && city_within(site, PORT_POPULATION, 4.0).is_some() {
+                            site.sea_neighbours as f32
+                        } else {
+                            0.0
+                        }
+                    }),
+                    density: 1.5,
+                    spacing: 15.0,
+                },
+            },
+        ]
+    }
+}
+
+impl TerrainMap {
+    /// The tiles under `line`, from one end to the other; across the seam
+    /// of a map that wraps where that is the shorter way.
+    pub fn power_line_tiles(&self, line: &PowerLine) -> Vec<(usize, usize)> {
+        let (x0, y0) = (line.from.0 as f64, line.from.1 as f64);
+        let dx = wrap_dx(line.to.0 as f64 - x0, self.width, self.wrap_x);
+        let dy = line.to.1 as f64 - y0;
+        let steps = dx.abs().max(dy.abs()).round().max(1.0) as usize;
+        (0..=steps)
+            .map(|step| {
+                let t = step as f64 / steps as f64;
+                let x = (x0 + dx * t).round() as isize;
+                (x.rem_euclid(self.width.max(1) as isize) as usize, (y0 + dy * t).round() as usize)
+            })
+            .collect()
+    }
+
+    /// Power lines joining the cities of at least `POWER_LINE_POPULATION`
+    /// on each land mass: a minimum spanning forest of straight lines,
+    /// leaving out any that would cross the sea.
+    pub(super) fn strung_power_lines(&self) -> Vec<PowerLine> {
+        let cities: Vec<&City> = self.cities.iter().filter(|c| c.population >= POWER_LINE_POPULATION).collect();
+        let mut pairs = Vec::new();
+        for i in 0..cities.len() {
+            for j in i + 1..cities.len() {
+                let dx = wrap_dx(cities[j].x as f64 - cities[i].x as f64, self.width, self.wrap_x);
+                let dy = cities[j].y as f64 - cities[i].y as f64;
+                pairs.push((dx.hypot(dy), i, j));
+            }
+        }
+        pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut group: Vec<usize> = (0..cities.len()).collect();
+        let root = |group: &[usize], mut i: usize| {
+            while group[i] != i {
+                i = group[i];
+            }
+            i
+        };
+        let mut lines = Vec::new();
+        for (_, i, j) in pairs {
+            let (a, b) = (root(&group, i), root(&group, j));
+            if a == b {
+                continue;
+            }
+            let line = PowerLine { from: (cities[i].x, cities[i].y), to: (cities[j].x, cities[j].y) };
+            let at_sea = self.power_line_tiles(&line).iter().any(|&(x, y)| {
+                self.biome_at(x, y)
+                    .is_some_and(|b| matches!(b, Biome::Ocean | Biome::DeepOcean | Biome::Shore))
+            });
+            if !at_sea {
+                group[a] = b;
+                lines.push(line);
+            }
+        }
+        lines
+    }
+
+    /// Keep the power lines in step with the cities: a line that lost the
+    /// city at either end comes down.
+    pub(super) fn prune_power_lines(&mut self) {
+        let cities = &self.cities;
+        let exists = |tile: (usize, usize)| cities.iter().any(|c| (c.x, c.y) == tile);
+        self.power_lines.retain(|line| exists(line.from) && exists(line.to));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::OnceLock;
+
+    use super::*;
+    use crate::terrain_generator::{test_map, GenerationSettings, TerrainGenerator};
+
+    const SEEDS: [u32; 2] = [2, 12];
+
+    /// Modern maps of each of `SEEDS` at 240x180
+    fn modern_maps() -> &'static [TerrainMap] {
+        static MAPS: OnceLock<Vec<TerrainMap>> = OnceLock::new();
+        MAPS.get_or_init(|| {
+            let settings = GenerationSettings::default().with_modern(true);
+            SEEDS.iter().map(|&seed| TerrainGenerator::new_with_settings(seed, settings).generate(240, 180)).collect()
+        })
+    }
+
+    #[test]
+    fn plain_maps_have_no_motorways_airports_or_power_lines() {
+        for seed in SEEDS {
+            let plain = test_map(seed, 240, 180);
+            assert!(plain.roads.iter().all(|r| r.road_type != MOTORWAY));
+            assert!(plain.power_lines.is_empty() && plain.pois.iter().all(|p| p.kind != "airport"));
+        }
+    }
+
+    #[test]
+    fn modern_maps_add_motorways() {
+        for map in modern_maps() {
+            let motorways: Vec<_> = map.roads.iter().filter(|r| r.road_type == MOTORWAY).collect();
+            assert!(!motorways.is_empty());
+            assert!(motorways.iter().all(|r| r.name.starts_with('M')), "{:?}", motorways[0].name);
+        }
+    }
+
+    #[test]
+    fn airports_and_terminals_serve_big_cities() {
+        let mut found = (0, 0);
+        for map in modern_maps() {
+            for poi in &map.pois {
+                let near = |least: u32, reach: f32| {
+                    map.cities.iter().any(|c| {
+                        c.population >= least
+                            && (c.x as f32 - poi.position.0 as f32).hypot(c.y as f32 - poi.position.1 as f32) <= reach
+                    })
+                };
+                match poi.kind.as_str() {
+                    "airport" => {
+                        found.0 += 1;
+                        assert!(near(AIRPORT_POPULATION, 8.0));
+                    }
+                    "container terminal" => {
+                        found.1 += 1;
+                        assert!(near(PORT_POPULATION, 4.0));
+                        assert!(map.sea_neighbours(poi.position.0, poi.position.1) >= 2);
+                    }
+                    _ => {}
+                }
+            }
+            assert!(map.poi_style("airport").icon_color == [70, 110, 220]);
+        }
+        assert!(found.0 > 0 && found.1 > 0, "{:?}", found);
+    }
+
+    #[test]
+    fn power_lines_join_big_cities_straight_and_never_over_the_sea() {
+        let mut found = 0;
+        for map in modern_maps() {
+            found += map.power_lines.len();
+            for line in &map.power_lines {
+                for end in [line.from, line.to] {
+                    assert!(map.cities.iter().any(|c| (c.x, c.y) == end && c.population >= POWER_LINE_POPULATION));
+                }
+                for (x, y) in map.power_line_tiles(line) {
+                    assert!(!matches!(map.terrain[y][x].biome, Biome::Ocean | Biome::DeepOcean | Biome::Shore));
+                }
+            }
+        }
+        assert!(found > 0);
+    }
+
+    #[test]
+    fn power_lines_to_a_removed_city_are_pruned() {
+        for map in modern_maps() {
+            let mut moved = map.clone();
+            let end = moved.power_lines.first().map(|l| l.from);
+            moved.cities.retain(|c| Some((c.x, c.y)) != end);
+            moved.prune_power_lines();
+            assert!(moved.power_lines.iter().all(|l| Some(l.from) != end && Some(l.to) != end));
+        }
+    }
+}
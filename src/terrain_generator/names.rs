@@ -1,19 +1,230 @@
+use std::collections::{HashMap, HashSet};
+
 use rand::Rng;
 
+use crate::name_lists::{NameLists, NamePool};
+
+use super::progress::GenerationPhase;
+use super::types::TerrainMap;
 use super::TerrainGenerator;
 
+/// Fresh names drawn for a taken one before it is disambiguated instead
+const NAME_ATTEMPTS: usize = 8;
+
+/// Prefixes that tell two places of the same name apart, tried in order
+const CARDINALS: [&str; 4] = ["North", "South", "East", "West"];
+
+/// What a name is for. Names are unique within a kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(super) enum NameKind {
+    City,
+    Road,
+    Bridge,
+    River,
+    /// Oceans, mountain ranges, forests and swamps
+    Region,
+    PointOfInterest,
+}
+
+/// The names given so far on the map being generated, so that no name is
+/// handed out twice.
+#[derive(Debug, Clone, Default)]
+pub(super) struct UsedNames {
+    taken: HashMap<NameKind, HashSet<String>>,
+}
+
+impl UsedNames {
+    /// Every name already on `map`, for adding to it by hand.
+    pub(super) fn of_map(map: &TerrainMap) -> Self {
+        let mut names = Self::kept_by(map, GenerationPhase::Labels);
+        for label in &map.labels {
+            let kind = if label.feature_type == "river" { NameKind::River } else { NameKind::Region };
+            names.insert(kind, &label.name);
+        }
+        for poi in &map.pois {
+            names.insert(NameKind::PointOfInterest, &poi.name);
+        }
+        names
+    }
+
+    /// The names on `map` that survive rerunning the phases from `phase`:
+    /// those of the cities, roads and bridges made before it.
+    pub(super) fn kept_by(map: &TerrainMap, phase: GenerationPhase) -> Self {
+        let mut names = Self::default();
+        if phase > GenerationPhase::Cities {
+            for city in &map.cities {
+                names.insert(NameKind::City, &city.name);
+            }
+        }
+        if phase > GenerationPhase::Roads {
+            for road in &map.roads {
+                names.insert(NameKind::Road, &road.name);
+            }
+            for bridge in &map.bridges {
+                names.insert(NameKind::Bridge, &bridge.name);
+            }
+        }
+        names
+    }
+
+    fn is_taken(&self, kind: NameKind, name: &str) -> bool {
+        self.taken.get(&kind).is_some_and(|taken| taken.contains(name))
+    }
+
+    /// Take `name`; false if it was already taken.
+    fn insert(&mut self, kind: NameKind, name: &str) -> bool {
+        self.taken.entry(kind).or_default().insert(name.to_string())
+    }
+
+    /// Take `name`, or if it is taken a variant of it: with a cardinal
+    /// prefix ("North Oakhaven", "The East Swift River"), else numbered
+    /// ("Oakhaven III").
+    pub(super) fn claim(&mut self, kind: NameKind, name: String) -> String {
+        if self.insert(kind, &name) {
+            return name;
+        }
+        let (article, rest) = match name.strip_prefix("The ") {
+            Some(rest) => ("The ", rest),
+            None => ("", name.as_str()),
+        };
+        let has_cardinal = CARDINALS
+            .iter()
+            .any(|cardinal| rest.split(' ').next() == Some(cardinal));
+        if !has_cardinal {
+            for cardinal in CARDINALS {
+                let variant = format!("{}{} {}", article, cardinal, rest);
+                if self.insert(kind, &variant) {
+                    return variant;
+                }
+            }
+        }
+        (2..)
+            .map(|n| format!("{} {}", name, roman(n)))
+            .find(|variant| self.insert(kind, variant))
+            .expect("some numbered variant is free")
+    }
+}
+
+/// `n` in Roman numerals, for n >= 1.
+fn roman(mut n: usize) -> String {
+    const NUMERALS: [(usize, &str); 13] = [
+        (1000, "M"),
+        (900, "CM"),
+        (500, "D"),
+        (400, "CD"),
+        (100, "C"),
+        (90, "XC"),
+        (50, "L"),
+        (40, "XL"),
+        (10, "X"),
+        (9, "IX"),
+        (5, "V"),
+        (4, "IV"),
+        (1, "I"),
+    ];
+    let mut text = String::new();
+    for (value, numeral) in NUMERALS {
+        while n >= value {
+            text.push_str(numeral);
+            n -= value;
+        }
+    }
+    text
+}
+
 impl TerrainGenerator {
-    pub(super) fn generate_ocean_name(&mut self, _index: usize) -> String {
+    /// Draw names from `lists` where they have words for a kind of place,
+    /// instead of the built-in names.
+    pub fn set_name_lists(&mut self, lists: NameLists) {
+        self.name_lists = lists;
+    }
+
+    /// A name from the user's `pool` of `name_lists`, if it has words.
+    fn custom_name(&mut self, pool: fn(&NameLists) -> &NamePool) -> Option<String> {
+        pool(&self.name_lists).draw(&mut self.rngs.names)
+    }
+
+    /// A name of `kind` no other place on the map has: `draw` is asked for
+    /// a few fresh names, and if they are all taken the last is
+    /// disambiguated.
+    pub(super) fn unique_name(&mut self, kind: NameKind, mut draw: impl FnMut(&mut Self) -> String) -> String {
+        let mut name = draw(self);
+        for _ in 1..NAME_ATTEMPTS {
+            if !self.used_names.is_taken(kind, &name) {
+                break;
+            }
+            name = draw(self);
+        }
+        self.used_names.claim(kind, name)
+    }
+
+    pub(super) fn generate_ocean_name(&mut self, index: usize) -> String {
+        self.unique_name(NameKind::Region, |g| {
+            g.custom_name(|l| &l.oceans).unwrap_or_else(|| g.ocean_name(index))
+        })
+    }
+
+    pub(super) fn generate_mountain_name(&mut self, index: usize) -> String {
+        self.unique_name(NameKind::Region, |g| {
+            g.custom_name(|l| &l.mountains).unwrap_or_else(|| g.mountain_name(index))
+        })
+    }
+
+    pub(super) fn generate_forest_name(&mut self, index: usize) -> String {
+        self.unique_name(NameKind::Region, |g| {
+            g.custom_name(|l| &l.forests).unwrap_or_else(|| g.forest_name(index))
+        })
+    }
+
+    pub(super) fn generate_swamp_name(&mut self, index: usize) -> String {
+        self.unique_name(NameKind::Region, |g| {
+            g.custom_name(|l| &l.swamps).unwrap_or_else(|| g.swamp_name(index))
+        })
+    }
+
+    pub(super) fn generate_city_name(&mut self, index: usize) -> String {
+        self.unique_name(NameKind::City, |g| {
+            g.custom_name(|l| &l.cities).unwrap_or_else(|| g.city_name(index))
+        })
+    }
+
+    /// A road name made by `format` from a descriptor such as "King's",
+    /// e.g. `|d| format!("{} Highway", d)`.
+    pub(super) fn generate_road_name(&mut self, index: usize, format: impl Fn(&str) -> String) -> String {
+        self.unique_name(NameKind::Road, |g| format(g.road_descriptor(index)))
+    }
+
+    /// The next motorway's name: "M" and the lowest number not taken.
+    pub(super) fn generate_motorway_name(&mut self) -> String {
+        let name = (1..)
+            .map(|n| format!("M{}", n))
+            .find(|name| !self.used_names.is_taken(NameKind::Road, name))
+            .expect("a number is free");
+        self.used_names.insert(NameKind::Road, &name);
+        name
+    }
+
+    pub(super) fn generate_river_name(&mut self, index: usize) -> String {
+        self.unique_name(NameKind::River, |g| {
+            g.custom_name(|l| &l.rivers).unwrap_or_else(|| g.river_name(index))
+        })
+    }
+
+    pub(super) fn generate_bridge_name(&mut self, index: usize) -> String {
+        self.unique_name(NameKind::Bridge, |g| g.bridge_name(index))
+    }
+
+    fn ocean_name(&mut self, _index: usize) -> String {
         let prefixes = [
             "Azure", "Cerulean", "Sapphire", "Mystic", "Crystal", "Eternal", "Whispering",
         ];
         let suffixes = ["Sea", "Ocean", "Deep", "Abyss", "Waters", "Expanse", "Bay"];
-        let prefix = prefixes[self.rng.gen_range(0..prefixes.len())];
-        let suffix = suffixes[self.rng.gen_range(0..suffixes.len())];
+        let prefix = prefixes[self.rngs.names.gen_range(0..prefixes.len())];
+        let suffix = suffixes[self.rngs.names.gen_range(0..suffixes.len())];
         format!("{} {}", prefix, suffix)
     }
 
-    pub(super) fn generate_mountain_name(&mut self, index: usize) -> String {
+    fn mountain_name(&mut self, index: usize) -> String {
         let prefixes = ["Mount", "Mt.", "Peak"];
         let first_parts = [
             "Storm", "Iron", "Snow", "Thunder", "Eagle", "Wolf", "Dragon", "Crystal", "Shadow",
@@ -26,13 +237,13 @@ impl TerrainGenerator {
         let suffixes = ["Mountains", "Range", "Peaks", "Heights", "Alps", "Highlands"];
 
         // Ensure variety by using index to influence selection
-        let prefix_idx = (index + self.rng.gen_range(0..3)) % prefixes.len();
-        let first_idx = (index * 7 + self.rng.gen_range(0..4)) % first_parts.len();
-        let second_idx = (index * 5 + self.rng.gen_range(0..3)) % second_parts.len();
+        let prefix_idx = (index + self.rngs.names.gen_range(0..3)) % prefixes.len();
+        let first_idx = (index * 7 + self.rngs.names.gen_range(0..4)) % first_parts.len();
+        let second_idx = (index * 5 + self.rngs.names.gen_range(0..3)) % second_parts.len();
 
-        if self.rng.gen_bool(0.4) {
+        if self.rngs.names.gen_bool(0.4) {
             // Sometimes just use a suffix for the range
-            let suffix = suffixes[self.rng.gen_range(0..suffixes.len())];
+            let suffix = suffixes[self.rngs.names.gen_range(0..suffixes.len())];
             format!(
                 "The {}{} {}",
                 first_parts[first_idx], second_parts[second_idx], suffix
@@ -45,29 +256,29 @@ impl TerrainGenerator {
         }
     }
 
-    pub(super) fn generate_forest_name(&mut self, _index: usize) -> String {
+    fn forest_name(&mut self, _index: usize) -> String {
         let adjectives = [
             "Whispering", "Ancient", "Enchanted", "Dark", "Silver", "Golden", "Misty",
         ];
         let nouns = [
             "Woods", "Forest", "Grove", "Thicket", "Woodland", "Glade", "Copse",
         ];
-        let adj = adjectives[self.rng.gen_range(0..adjectives.len())];
-        let noun = nouns[self.rng.gen_range(0..nouns.len())];
+        let adj = adjectives[self.rngs.names.gen_range(0..adjectives.len())];
+        let noun = nouns[self.rngs.names.gen_range(0..nouns.len())];
         format!("{} {}", adj, noun)
     }
 
-    pub(super) fn generate_swamp_name(&mut self, _index: usize) -> String {
+    fn swamp_name(&mut self, _index: usize) -> String {
         let adjectives = [
             "Murky", "Fetid", "Misty", "Black", "Forgotten", "Cursed", "Silent",
         ];
         let nouns = ["Marsh", "Swamp", "Bog", "Fen", "Mire", "Wetlands", "Quagmire"];
-        let adj = adjectives[self.rng.gen_range(0..adjectives.len())];
-        let noun = nouns[self.rng.gen_range(0..nouns.len())];
+        let adj = adjectives[self.rngs.names.gen_range(0..adjectives.len())];
+        let noun = nouns[self.rngs.names.gen_range(0..nouns.len())];
         format!("{} {}", adj, noun)
     }
 
-    pub(super) fn generate_city_name(&mut self, index: usize) -> String {
+    fn city_name(&mut self, index: usize) -> String {
         let prefixes = [
             "New", "Port", "Fort", "Saint", "North", "South", "East", "West", "Old", "",
         ];
@@ -85,13 +296,13 @@ impl TerrainGenerator {
         let city_types = [" City", " Town", "", "", ""]; // Sometimes add City/Town
 
         // Use index to ensure variety
-        let prefix_chance = self.rng.gen_bool(0.4);
-        let first_idx = (index * 3 + self.rng.gen_range(0..4)) % first_parts.len();
-        let second_idx = (index * 5 + self.rng.gen_range(0..3)) % second_parts.len();
+        let prefix_chance = self.rngs.names.gen_bool(0.4);
+        let first_idx = (index * 3 + self.rngs.names.gen_range(0..4)) % first_parts.len();
+        let second_idx = (index * 5 + self.rngs.names.gen_range(0..3)) % second_parts.len();
 
-        let base_name = if self.rng.gen_bool(0.6) {
+        let base_name = if self.rngs.names.gen_bool(0.6) {
             // Compound name with suffix
-            let suffix = city_suffixes[(index * 7 + self.rng.gen_range(0..2)) % city_suffixes.len()];
+            let suffix = city_suffixes[(index * 7 + self.rngs.names.gen_range(0..2)) % city_suffixes.len()];
             format!(
                 "{}{}{}",
                 first_parts[first_idx], second_parts[second_idx], suffix
@@ -102,7 +313,7 @@ impl TerrainGenerator {
         };
 
         let with_prefix = if prefix_chance {
-            let prefix = prefixes[self.rng.gen_range(0..prefixes.len())];
+            let prefix = prefixes[self.rngs.names.gen_range(0..prefixes.len())];
             if prefix.is_empty() {
                 base_name
             } else {
@@ -113,11 +324,11 @@ impl TerrainGenerator {
         };
 
         // Add City/Town suffix for clarity
-        let city_type = city_types[self.rng.gen_range(0..city_types.len())];
+        let city_type = city_types[self.rngs.names.gen_range(0..city_types.len())];
         format!("{}{}", with_prefix, city_type)
     }
 
-    pub(super) fn generate_road_name(&mut self, index: usize) -> String {
+    fn road_descriptor(&mut self, index: usize) -> &'static str {
         let descriptors = [
             "King's",
             "Queen's",
@@ -137,11 +348,11 @@ impl TerrainGenerator {
             "Pilgrim's",
         ];
         // Use index to ensure variety
-        let desc_idx = (index * 3 + self.rng.gen_range(0..4)) % descriptors.len();
-        descriptors[desc_idx].to_string()
+        let desc_idx = (index * 3 + self.rngs.names.gen_range(0..4)) % descriptors.len();
+        descriptors[desc_idx]
     }
 
-    pub(super) fn generate_river_name(&mut self, _index: usize) -> String {
+    fn river_name(&mut self, _index: usize) -> String {
         let prefixes = ["River", "The"];
         let names = [
             "Silverflow",
@@ -152,8 +363,8 @@ impl TerrainGenerator {
             "Moonwater",
             "Swift",
         ];
-        let prefix = prefixes[self.rng.gen_range(0..prefixes.len())];
-        let name = names[self.rng.gen_range(0..names.len())];
+        let prefix = prefixes[self.rngs.names.gen_range(0..prefixes.len())];
+        let name = names[self.rngs.names.gen_range(0..names.len())];
 
         if prefix == "The" {
             format!("{} {} River", prefix, name)
@@ -162,7 +373,7 @@ impl TerrainGenerator {
         }
     }
 
-    pub(super) fn generate_bridge_name(&mut self, index: usize) -> String {
+    fn bridge_name(&mut self, index: usize) -> String {
         let prefixes = [
             "Old", "New", "Great", "High", "Stone", "Iron", "Wooden", "Ancient",
         ];
@@ -171,9 +382,71 @@ impl TerrainGenerator {
         ];
 
         // Always make it clear it's a bridge
-        let prefix_idx = (index * 5 + self.rng.gen_range(0..3)) % prefixes.len();
-        let middle_idx = (index * 3 + self.rng.gen_range(0..2)) % middles.len();
+        let prefix_idx = (index * 5 + self.rngs.names.gen_range(0..3)) % prefixes.len();
+        let middle_idx = (index * 3 + self.rngs.names.gen_range(0..2)) % middles.len();
 
         format!("{} {} Bridge", prefixes[prefix_idx], middles[middle_idx])
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::OnceLock;
+
+    use crate::terrain_generator::{GenerationSettings, TerrainGenerator};
+
+    use super::*;
+
+    fn assert_unique<'a>(names: impl Iterator<Item = &'a String>) {
+        let mut seen = HashSet::new();
+        for name in names {
+            assert!(seen.insert(name), "{} appears twice", name);
+        }
+    }
+
+    fn crowded() -> GenerationSettings {
+        GenerationSettings::default().with_city_density(1.0)
+    }
+
+    /// A map with enough cities to run short of names, made once
+    fn large_map() -> &'static TerrainMap {
+        static MAP: OnceLock<TerrainMap> = OnceLock::new();
+        let map = MAP.get_or_init(|| TerrainGenerator::new_with_settings(4, crowded()).generate(320, 240));
+        assert!(map.cities.len() > 30);
+        map
+    }
+
+    #[test]
+    fn every_name_on_a_large_map_is_unique() {
+        let map = large_map();
+        assert_unique(map.cities.iter().map(|c| &c.name));
+        assert_unique(map.roads.iter().map(|r| &r.name));
+        assert_unique(map.bridges.iter().map(|b| &b.name));
+        assert_unique(map.labels.iter().map(|l| &l.name));
+    }
+
+    #[test]
+    fn rerun_phases_name_things_apart_from_the_kept_ones() {
+        let mut generator = TerrainGenerator::new_with_settings(4, crowded());
+        let mut map = large_map().clone();
+        generator.regenerate(&mut map, GenerationPhase::Roads);
+        assert_unique(map.roads.iter().map(|r| &r.name));
+        let added = generator.name_city(&map);
+        assert!(map.cities.iter().all(|c| c.name != added));
+    }
+
+    #[test]
+    fn taken_names_get_a_cardinal_then_a_number() {
+        let mut names = UsedNames::default();
+        let mut claim = |name: &str| names.claim(NameKind::River, name.to_string());
+        assert_eq!(claim("The Swift River"), "The Swift River");
+        assert_eq!(claim("The Swift River"), "The North Swift River");
+        for _ in 0..3 {
+            claim("The Swift River");
+        }
+        assert_eq!(claim("The Swift River"), "The Swift River II");
+        assert_eq!(claim("North Oakhaven"), "North Oakhaven");
+        assert_eq!(claim("North Oakhaven"), "North Oakhaven II");
+        assert_eq!(roman(14), "XIV");
+    }
+}
@@ -0,0 +1,429 @@
+//! The roads of a finished map as a graph, for travel between cities.
+//!
+//! A map stores each road as a raw tile path, and roads overlap and branch
+//! off one another. [`RoadNetwork`] splits those paths at cities, bridges,
+//! junctions and dead ends, so each stretch of road between two such places
+//! is one edge.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use super::settlements::terrain_move_cost;
+use super::stats::path_length;
+use super::types::TerrainMap;
+
+/// What is at a node of the network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    /// A city, by index into the map's cities
+    City(usize),
+    /// A bridge, by index into the map's bridges
+    Bridge(usize),
+    /// Roads meet, merge or part
+    Junction,
+    /// A road ends away from any city
+    DeadEnd,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoadNode {
+    pub x: usize,
+    pub y: usize,
+    pub kind: NodeKind,
+}
+
+/// One stretch of road between two nodes. Where several roads share the
+/// same tiles, the stretch appears once, under the first of them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoadEdge {
+    pub from: usize,
+    pub to: usize,
+    /// Index into the map's roads
+    pub road: usize,
+    /// "motorway", "highway", "road" or "trail"
+    pub road_type: String,
+    /// Tiles from `from` to `to`, inclusive
+    pub path: Vec<(usize, usize)>,
+    /// Length in tiles (diagonal steps count as sqrt 2)
+    pub length: f64,
+    /// Sum of the terrain costs roads are laid with, higher over hills,
+    /// forests and rivers
+    pub terrain_cost: usize,
+}
+
+/// A way between two cities along the network.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetworkRoute {
+    /// Nodes from start to end, inclusive
+    pub nodes: Vec<usize>,
+    /// Edges in travel order
+    pub edges: Vec<usize>,
+    /// Length in tiles
+    pub length: f64,
+}
+
+/// The road graph of one map. Every city is a node, even without a road.
+#[derive(Debug, Clone, Default)]
+pub struct RoadNetwork {
+    pub nodes: Vec<RoadNode>,
+    pub edges: Vec<RoadEdge>,
+    /// Edges touching each node
+    adjacent: Vec<Vec<usize>>,
+    city_nodes: Vec<usize>,
+}
+
+/// Dijkstra queue entry, ordered so the shortest pops first.
+struct Visit {
+    length: f64,
+    node: usize,
+}
+
+impl PartialEq for Visit {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Visit {}
+
+impl PartialOrd for Visit {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Visit {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.length.total_cmp(&self.length)
+    }
+}
+
+impl RoadNetwork {
+    pub fn new(map: &TerrainMap) -> Self {
+        // Which roads pass through each tile
+        let mut roads_on: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+        for (index, road) in map.roads.iter().enumerate() {
+            for &tile in &road.path {
+                let roads = roads_on.entry(tile).or_default();
+                if roads.last() != Some(&index) {
+                    roads.push(index);
+                }
+            }
+        }
+
+        let mut kinds: HashMap<(usize, usize), NodeKind> = HashMap::new();
+        for road in &map.roads {
+            let last = road.path.len().saturating_sub(1);
+            for (i, &tile) in road.path.iter().enumerate() {
+                let shared = roads_on[&tile].len() > 1;
+                // Where a road joins or leaves a shared stretch
+                let parting = shared
+                    && [i.checked_sub(1), Some(i + 1)]
+                        .into_iter()
+                        .flatten()
+                        .filter_map(|j| road.path.get(j))
+                        .any(|next| roads_on[next] != roads_on[&tile]);
+                if i == 0 || i == last {
+                    let kind = if shared {
+                        NodeKind::Junction
+                    } else {
+                        NodeKind::DeadEnd
+                    };
+                    kinds.entry(tile).or_insert(kind);
+                } else if parting {
+                    kinds.insert(tile, NodeKind::Junction);
+                }
+            }
+        }
+        for (index, bridge) in map.bridges.iter().enumerate() {
+            if roads_on.contains_key(&(bridge.x, bridge.y)) {
+                kinds.insert((bridge.x, bridge.y), NodeKind::Bridge(index));
+            }
+        }
+        for (index, city) in map.cities.iter().enumerate() {
+            kinds.insert((city.x, city.y), NodeKind::City(index));
+        }
+
+        let mut network = RoadNetwork::default();
+        let mut node_at: HashMap<(usize, usize), usize> = HashMap::new();
+        // Cities first, then the rest in map order
+        let mut tiles: Vec<((usize, usize), NodeKind)> = kinds.into_iter().collect();
+        tiles.sort_by_key(|&((x, y), kind)| match kind {
+            NodeKind::City(index) => (0, index, 0, 0),
+            _ => (1, 0, y, x),
+        });
+        for ((x, y), kind) in tiles {
+            node_at.insert((x, y), network.nodes.len());
+            network.nodes.push(RoadNode { x, y, kind });
+        }
+        network.city_nodes = map.cities.iter().map(|c| node_at[&(c.x, c.y)]).collect();
+        network.adjacent = vec![Vec::new(); network.nodes.len()];
+
+        let mut seen: HashSet<Vec<(usize, usize)>> = HashSet::new();
+        for (index, road) in map.roads.iter().enumerate() {
+            let mut start = 0;
+            for i in 1..road.path.len() {
+                if !node_at.contains_key(&road.path[i]) {
+                    continue;
+                }
+                let path = road.path[start..=i].to_vec();
+                start = i;
+                let mut reversed = path.clone();
+                reversed.reverse();
+                if seen.contains(&reversed) || !seen.insert(path.clone()) {
+                    continue;
+                }
+
+                let terrain_cost = path
+                    .windows(2)
+                    .filter_map(|step| {
+                        let ((x0, y0), (x1, y1)) = (step[0], step[1]);
+                        terrain_move_cost(
+                            &map.terrain[y0][x0],
+                            &map.terrain[y1][x1],
+                            x0 != x1 && y0 != y1,
+                        )
+                    })
+                    .sum();
+                let edge = RoadEdge {
+                    from: node_at[&path[0]],
+                    to: node_at[&path[path.len() - 1]],
+                    road: index,
+                    road_type: road.road_type.clone(),
+                    length: path_length(&path),
+                    terrain_cost,
+                    path,
+                };
+                network.adjacent[edge.from].push(network.edges.len());
+                network.adjacent[edge.to].push(network.edges.len());
+                network.edges.push(edge);
+            }
+        }
+        network
+    }
+
+    /// The node of city `city` (an index into the map's cities).
+    pub fn city_node(&self, city: usize) -> Option<usize> {
+        self.city_nodes.get(city).copied()
+    }
+
+    /// Edges touching `node`.
+    pub fn edges_at(&self, node: usize) -> &[usize] {
+        &self.adjacent[node]
+    }
+
+    /// The shortest way by road between two cities, or `None` if no road
+    /// joins them.
+    pub fn shortest_route(&self, city_a: usize, city_b: usize) -> Option<NetworkRoute> {
+        let (start, goal) = (self.city_node(city_a)?, self.city_node(city_b)?);
+        let mut best = vec![f64::INFINITY; self.nodes.len()];
+        let mut came_by: Vec<Option<usize>> = vec![None; self.nodes.len()];
+        let mut queue = BinaryHeap::new();
+        best[start] = 0.0;
+        queue.push(Visit {
+            length: 0.0,
+            node: start,
+        });
+
+        while let Some(Visit { length, node }) = queue.pop() {
+            if node == goal {
+                break;
+            }
+            if length > best[node] {
+                continue;
+            }
+            for &edge in &self.adjacent[node] {
+                let edge_ref = &self.edges[edge];
+                let next = if edge_ref.from == node {
+                    edge_ref.to
+                } else {
+                    edge_ref.from
+                };
+                let next_length = length + edge_ref.length;
+                if next_length < best[next] {
+                    best[next] = next_length;
+                    came_by[next] = Some(edge);
+                    queue.push(Visit {
+                        length: next_length,
+                        node: next,
+                    });
+                }
+            }
+        }
+        if best[goal].is_infinite() {
+            return None;
+        }
+
+        let (mut nodes, mut edges) = (vec![goal], Vec::new());
+        let mut node = goal;
+        while let Some(edge) = came_by[node] {
+            let edge_ref = &self.edges[edge];
+            node = if edge_ref.to == node {
+                edge_ref.from
+            } else {
+                edge_ref.to
+            };
+            edges.push(edge);
+            nodes.push(node);
+        }
+        nodes.reverse();
+        edges.reverse();
+        Some(NetworkRoute {
+            nodes,
+            edges,
+            length: best[goal],
+        })
+    }
+
+    /// Groups of nodes joined by road, largest first. A city without
+    /// roads is a group of its own.
+    pub fn connected_components(&self) -> Vec<Vec<usize>> {
+        let mut component = vec![usize::MAX; self.nodes.len()];
+        let mut components: Vec<Vec<usize>> = Vec::new();
+        for first in 0..self.nodes.len() {
+            if component[first] != usize::MAX {
+                continue;
+            }
+            let mut members = Vec::new();
+            let mut stack = vec![first];
+            component[first] = components.len();
+            while let Some(node) = stack.pop() {
+                members.push(node);
+                for &edge in &self.adjacent[node] {
+                    for next in [self.edges[edge].from, self.edges[edge].to] {
+                        if component[next] == usize::MAX {
+                            component[next] = components.len();
+                            stack.push(next);
+                        }
+                    }
+                }
+            }
+            members.sort_unstable();
+            components.push(members);
+        }
+        components.sort_by_key(|members| std::cmp::Reverse(members.len()));
+        components
+    }
+
+    /// Edges that run over a tile of `river`, e.g. one of the map's rivers.
+    pub fn edges_crossing(&self, river: &[(usize, usize)]) -> Vec<usize> {
+        let river: HashSet<&(usize, usize)> = river.iter().collect();
+        (0..self.edges.len())
+            .filter(|&edge| {
+                self.edges[edge]
+                    .path
+                    .iter()
+                    .any(|tile| river.contains(tile))
+            })
+            .collect()
+    }
+}
+
+impl TerrainMap {
+    /// The map's roads as a graph.
+    pub fn road_network(&self) -> RoadNetwork {
+        RoadNetwork::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain_generator::test_map;
+
+    fn map() -> &'static TerrainMap {
+        let map = test_map(42, 200, 150);
+        assert!(!map.roads.is_empty());
+        map
+    }
+
+    /// The cities at the ends of the map's first highway
+    fn highway_cities(map: &TerrainMap) -> (usize, usize) {
+        let road = map.roads.iter().find(|r| r.road_type == "highway").unwrap();
+        let city_at = |tile: (usize, usize)| map.cities.iter().position(|c| (c.x, c.y) == tile);
+        let a = city_at(road.path[0]).unwrap();
+        let b = city_at(road.path[road.path.len() - 1]).unwrap();
+        (a, b)
+    }
+
+    #[test]
+    fn every_city_is_a_node() {
+        let network = map().road_network();
+        for (city, node) in network.city_nodes.iter().enumerate() {
+            assert_eq!(network.nodes[*node].kind, NodeKind::City(city));
+        }
+    }
+
+    #[test]
+    fn edges_touch_nodes_at_their_ends_only_and_cover_every_road() {
+        let map = map();
+        let network = map.road_network();
+        let node_tiles: HashSet<(usize, usize)> =
+            network.nodes.iter().map(|node| (node.x, node.y)).collect();
+        let mut covered = HashSet::new();
+        for edge in &network.edges {
+            let from = &network.nodes[edge.from];
+            assert_eq!(edge.path[0], (from.x, from.y));
+            let to = &network.nodes[edge.to];
+            assert_eq!(edge.path[edge.path.len() - 1], (to.x, to.y));
+            assert!(edge.path[1..edge.path.len() - 1]
+                .iter()
+                .all(|tile| !node_tiles.contains(tile)));
+            assert!(edge.length >= 1.0 && edge.terrain_cost >= 10);
+            covered.extend(edge.path.iter().copied());
+        }
+        assert!(map
+            .roads
+            .iter()
+            .flat_map(|r| &r.path)
+            .all(|t| covered.contains(t)));
+    }
+
+    #[test]
+    fn a_roads_cities_are_joined_by_a_chain_of_edges() {
+        let map = map();
+        let network = map.road_network();
+        let road = map.roads.iter().find(|r| r.road_type == "highway").unwrap();
+        let (a, b) = highway_cities(map);
+        let route = network.shortest_route(a, b).unwrap();
+        assert!(route.length <= path_length(&road.path) + 1e-9);
+        assert_eq!(route.nodes.len(), route.edges.len() + 1);
+        assert_eq!(route.nodes[0], network.city_node(a).unwrap());
+        assert_eq!(network.shortest_route(a, a).unwrap().length, 0.0);
+    }
+
+    #[test]
+    fn components_hold_every_node_and_agree_with_routes() {
+        let map = map();
+        let network = map.road_network();
+        let components = network.connected_components();
+        assert_eq!(
+            components.iter().map(Vec::len).sum::<usize>(),
+            network.nodes.len()
+        );
+        let together =
+            |x: usize, y: usize| components.iter().any(|c| c.contains(&x) && c.contains(&y));
+        let (a, b) = highway_cities(map);
+        assert!(together(a, b));
+        for (x, y) in [(0, 1), (1, 2), (0, 2)] {
+            assert_eq!(
+                together(x, y),
+                network.shortest_route(x, y).is_some(),
+                "cities {} and {}",
+                x,
+                y
+            );
+        }
+    }
+
+    #[test]
+    fn every_bridge_is_crossed_by_an_edge() {
+        let map = map();
+        let network = map.road_network();
+        for bridge in &map.bridges {
+            let crossing = network.edges_crossing(&[(bridge.x, bridge.y)]);
+            assert!(!crossing.is_empty());
+        }
+        assert!(network.edges_crossing(&[]).is_empty());
+    }
+}
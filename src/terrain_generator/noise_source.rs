@@ -0,0 +1,314 @@
+//! The noise functions fields are sampled from, chosen per field by
+//! [`NoiseKind`].
+//!
+//! Fields are sampled a row at a time through [`NoiseSource::sample_row`].
+//! With the `simd` feature, Perlin noise evaluates four points per
+//! instruction there, giving exactly the values of `noise::Perlin`. Maps
+//! that wrap east-west sample the 3D noise on a cylinder instead, through
+//! [`NoiseSource::sample_row_wrapped`].
+
+use std::f64::consts::TAU;
+
+use noise::{NoiseFn, OpenSimplex, Perlin, RidgedMulti, Simplex};
+
+use super::types::NoiseKind;
+
+/// 2D and 3D coherent noise, roughly in -1.0..=1.0. Every `NoiseFn` of the
+/// `noise` crate that can be shared between threads is one.
+pub trait NoiseSource: Send + Sync {
+    fn sample(&self, point: [f64; 2]) -> f64;
+
+    fn sample_3d(&self, point: [f64; 3]) -> f64;
+
+    /// `sample` of every point, into `out` (the same length). Sources that
+    /// can evaluate several points at once override this.
+    fn sample_row(&self, points: &[[f64; 2]], out: &mut [f64]) {
+        for (value, &point) in out.iter_mut().zip(points) {
+            *value = self.sample(point);
+        }
+    }
+
+    /// `sample` with the x axis wound around a cylinder `period` round, so
+    /// x and x + `period` give the same value; the plane when `period` is
+    /// `None`. A unit along the cylinder's surface is a unit of the plane,
+    /// so features keep their size.
+    fn sample_wrapped(&self, [x, y]: [f64; 2], period: Option<f64>) -> f64 {
+        match period {
+            Some(period) => {
+                let radius = period / TAU;
+                let angle = x / radius;
+                self.sample_3d([radius * angle.cos(), radius * angle.sin(), y])
+            }
+            None => self.sample([x, y]),
+        }
+    }
+
+    /// `sample_row` with the x axis wound around a cylinder, as
+    /// `sample_wrapped` does.
+    fn sample_row_wrapped(&self, points: &[[f64; 2]], period: Option<f64>, out: &mut [f64]) {
+        if period.is_none() {
+            return self.sample_row(points, out);
+        }
+        for (value, &point) in out.iter_mut().zip(points) {
+            *value = self.sample_wrapped(point, period);
+        }
+    }
+}
+
+impl<T: NoiseFn<f64, 2> + NoiseFn<f64, 3> + Send + Sync> NoiseSource for T {
+    fn sample(&self, point: [f64; 2]) -> f64 {
+        self.get(point)
+    }
+
+    fn sample_3d(&self, point: [f64; 3]) -> f64 {
+        self.get(point)
+    }
+}
+
+/// The noise of `kind` for `seed`.
+pub fn noise_source(kind: NoiseKind, seed: u32) -> Box<dyn NoiseSource> {
+    match kind {
+        #[cfg(feature = "simd")]
+        NoiseKind::Perlin => Box::new(simd::SimdPerlin::new(seed)),
+        #[cfg(not(feature = "simd"))]
+        NoiseKind::Perlin => Box::new(Perlin::new(seed)),
+        NoiseKind::OpenSimplex => Box::new(OpenSimplex::new(seed)),
+        NoiseKind::Simplex => Box::new(Simplex::new(seed)),
+        NoiseKind::Ridged => Box::new(RidgedMulti::<Perlin>::new(seed)),
+        NoiseKind::Worley => Box::new(Worley { seed }),
+    }
+}
+
+/// Cellular noise: the distance to the nearest of one random feature point
+/// per unit cell. The `noise` crate's `Worley` holds an `Rc`, so it can't
+/// be sampled from the generator's worker threads.
+struct Worley {
+    seed: u32,
+}
+
+impl Worley {
+    /// Hash of the seed and cell (x, y, z).
+    fn hash(&self, x: i64, y: i64, z: i64) -> u64 {
+        // SplitMix64 finalizer over the seed and the cell
+        let mut h = (self.seed as u64)
+            ^ (x as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+            ^ (y as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F)
+            ^ (z as u64).wrapping_mul(0x1656_67B1_9E37_79F9);
+        h = (h ^ (h >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        h = (h ^ (h >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        h ^ (h >> 31)
+    }
+
+    /// The feature point of cell (x, y), somewhere inside it.
+    fn feature_point(&self, x: i64, y: i64) -> (f64, f64) {
+        let h = self.hash(x, y, 0);
+        (x as f64 + unit(h), y as f64 + unit(h >> 32))
+    }
+
+    /// The feature point of cell (x, y, z): the 2D one for (x, y), at a
+    /// depth drawn from a second hash.
+    fn feature_point_3d(&self, x: i64, y: i64, z: i64) -> (f64, f64, f64) {
+        let h = self.hash(x, y, z);
+        let depth = self.hash(x, y, z.wrapping_add(0x5EED));
+        (x as f64 + unit(h), y as f64 + unit(h >> 32), z as f64 + unit(depth))
+    }
+}
+
+/// The low 32 bits of `bits` as a fraction in 0.0..1.0.
+fn unit(bits: u64) -> f64 {
+    (bits & 0xFFFF_FFFF) as f64 / 4_294_967_296.0
+}
+
+impl NoiseSource for Worley {
+    fn sample(&self, [x, y]: [f64; 2]) -> f64 {
+        let (cx, cy) = (x.floor() as i64, y.floor() as i64);
+        let mut nearest = f64::MAX;
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let (px, py) = self.feature_point(cx + dx, cy + dy);
+                nearest = nearest.min((px - x).hypot(py - y));
+            }
+        }
+        // Nearly every point is within one cell width of a feature point
+        nearest.min(1.0) * 2.0 - 1.0
+    }
+
+    fn sample_3d(&self, [x, y, z]: [f64; 3]) -> f64 {
+        let (cx, cy, cz) = (x.floor() as i64, y.floor() as i64, z.floor() as i64);
+        let mut nearest = f64::MAX;
+        for dz in -1..=1 {
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    let (px, py, pz) = self.feature_point_3d(cx + dx, cy + dy, cz + dz);
+                    nearest = nearest.min(((px - x).powi(2) + (py - y).powi(2) + (pz - z).powi(2)).sqrt());
+                }
+            }
+        }
+        nearest.min(1.0) * 2.0 - 1.0
+    }
+}
+
+/// The permutation table `noise::Perlin::new(seed)` hashes lattice points
+/// with, rebuilt the way the `noise` crate builds it (the crate keeps it
+/// private).
+#[cfg(any(feature = "simd", feature = "gpu"))]
+pub(super) fn permutation_table(seed: u32) -> [u8; 256] {
+    use rand::seq::SliceRandom;
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+
+    let mut key = [0; 16];
+    key[0] = 1;
+    for i in 1..4 {
+        key[i * 4..i * 4 + 4].copy_from_slice(&seed.to_le_bytes());
+    }
+    let mut rng = XorShiftRng::from_seed(key);
+    let mut table = [0; 256];
+    for (i, entry) in table.iter_mut().enumerate() {
+        *entry = i as u8;
+    }
+    table.shuffle(&mut rng);
+    table
+}
+
+#[cfg(feature = "simd")]
+mod simd {
+    use noise::{NoiseFn, Perlin};
+    use wide::f64x4;
+
+    use super::{permutation_table, NoiseSource};
+
+    /// `noise::Perlin` with a four-wide `sample_row`. Every operation is
+    /// the one `noise` does, in the same order and without fused
+    /// multiply-adds, so the values are bit for bit the same.
+    pub(super) struct SimdPerlin {
+        scalar: Perlin,
+        table: [u8; 256],
+    }
+
+    impl SimdPerlin {
+        pub(super) fn new(seed: u32) -> Self {
+            SimdPerlin {
+                scalar: Perlin::new(seed),
+                table: permutation_table(seed),
+            }
+        }
+
+        fn hash(&self, x: f64, y: f64) -> u8 {
+            let a = self.table[(x as isize & 0xff) as usize];
+            self.table[a as usize ^ (y as isize & 0xff) as usize]
+        }
+
+        /// Signs of the corner's gradient (±1, ±1) for each lane.
+        fn gradient(&self, cx: [f64; 4], cy: [f64; 4]) -> (f64x4, f64x4) {
+            let mut gx = [1.0; 4];
+            let mut gy = [1.0; 4];
+            for lane in 0..4 {
+                let h = self.hash(cx[lane], cy[lane]);
+                if h & 1 != 0 {
+                    gx[lane] = -1.0;
+                }
+                if h & 2 != 0 {
+                    gy[lane] = -1.0;
+                }
+            }
+            (f64x4::from(gx), f64x4::from(gy))
+        }
+
+        fn sample4(&self, x: f64x4, y: f64x4) -> f64x4 {
+            const SCALE_FACTOR: f64 = 2.0 / std::f64::consts::SQRT_2;
+            let (zero, one) = (f64x4::splat(0.0), f64x4::splat(1.0));
+            // `noise` steps down from whole non-positive coordinates too
+            let corner = |v: f64x4| {
+                f64x4::from(v.to_array().map(|v| if v <= 0.0 { v.trunc() - 1.0 } else { v.trunc() }))
+            };
+            let (cx, cy) = (corner(x), corner(y));
+            let (dx, dy) = (x - cx, y - cy);
+            let (cx, cy) = (cx.to_array(), cy.to_array());
+            let shifted = |c: [f64; 4]| c.map(|v| v + 1.0);
+
+            let dot = |(gx, gy): (f64x4, f64x4), ox: f64x4, oy: f64x4| gx * ox + gy * oy;
+            let g00 = dot(self.gradient(cx, cy), dx, dy);
+            let g10 = dot(self.gradient(shifted(cx), cy), dx - one, dy);
+            let g01 = dot(self.gradient(cx, shifted(cy)), dx, dy - one);
+            let g11 = dot(self.gradient(shifted(cx), shifted(cy)), dx - one, dy - one);
+
+            let quintic = |t: f64x4| {
+                let t = t.max(zero).min(one);
+                t * t * t * (t * (t * f64x4::splat(6.0) - f64x4::splat(15.0)) + f64x4::splat(10.0))
+            };
+            let (u, v) = (quintic(dx), quintic(dy));
+            let linear = |a: f64x4, b: f64x4, t: f64x4| b * t + a * (one - t);
+            let result = linear(linear(g00, g01, v), linear(g10, g11, v), u) * f64x4::splat(SCALE_FACTOR);
+            result.max(f64x4::splat(-1.0)).min(one)
+        }
+    }
+
+    impl NoiseSource for SimdPerlin {
+        fn sample(&self, point: [f64; 2]) -> f64 {
+            self.scalar.get(point)
+        }
+
+        fn sample_3d(&self, point: [f64; 3]) -> f64 {
+            self.scalar.get(point)
+        }
+
+        fn sample_row(&self, points: &[[f64; 2]], out: &mut [f64]) {
+            let mut chunks = points.chunks_exact(4);
+            let mut values = out.chunks_exact_mut(4);
+            for (p, value) in (&mut chunks).zip(&mut values) {
+                let x = f64x4::from([p[0][0], p[1][0], p[2][0], p[3][0]]);
+                let y = f64x4::from([p[0][1], p[1][1], p[2][1], p[3][1]]);
+                value.copy_from_slice(&self.sample4(x, y).to_array());
+            }
+            for (value, &point) in values.into_remainder().iter_mut().zip(chunks.remainder()) {
+                *value = self.scalar.get(point);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_match_point_samples() {
+        let points: Vec<[f64; 2]> = (0..203)
+            .map(|i| [i as f64 * 0.37 - 60.0, (i * i % 89) as f64 * 0.91 - 20.0])
+            .chain([[0.0, 0.0], [-3.0, 2.0], [-0.5, -7.0], [4.0, -0.0]])
+            .collect();
+        for kind in NoiseKind::ALL {
+            let source = noise_source(kind, 99);
+            let mut row = vec![0.0; points.len()];
+            source.sample_row(&points, &mut row);
+            for (&value, &point) in row.iter().zip(&points) {
+                assert_eq!(value.to_bits(), source.sample(point).to_bits(), "{:?}", kind);
+            }
+            if kind == NoiseKind::Perlin {
+                let reference = Perlin::new(99);
+                assert!(row.iter().zip(&points).all(|(&v, &p)| v == reference.get(p)));
+            }
+        }
+    }
+
+    #[test]
+    fn every_kind_is_seeded_and_in_range() {
+        for kind in NoiseKind::ALL {
+            let a = noise_source(kind, 7);
+            let b = noise_source(kind, 7);
+            let other = noise_source(kind, 8);
+            let points: Vec<[f64; 2]> = (0..200)
+                .map(|i| [i as f64 * 0.173, (i * i % 97) as f64 * 0.211])
+                .collect();
+            let mut differs = false;
+            for &point in &points {
+                let value = a.sample(point);
+                assert_eq!(value, b.sample(point), "{:?}", kind);
+                assert!((-1.5..=1.5).contains(&value), "{:?} gave {}", kind, value);
+                differs |= value != other.sample(point);
+            }
+            assert!(differs, "{:?} ignores the seed", kind);
+        }
+    }
+}
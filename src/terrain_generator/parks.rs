@@ -0,0 +1,176 @@
+//! National parks and wilderness areas.
+//!
+//! The largest labelled forests, mountain ranges and swamps with no city
+//! on them and at most `PARK_SETTLED_SHARE` of their tiles within
+//! `PARK_CLEARANCE` of one, `MAX_PARKS` at most, are set aside as
+//! protected areas: a forest or range becomes "... National
+//! Park", a swamp "... Wilderness". The region and its label take the new
+//! name and `TerrainMap::parks` lists it, so a crop, an overview or a
+//! stitched world keeps a park for as long as its region is there.
+//!
+//! Renderers draw each park's boundary dotted, traced from its tiles as
+//! the coastlines are from the land; rivers through a park count as part
+//! of it, so the boundary runs round the outside rather than along their
+//! banks.
+
+use std::collections::HashSet;
+
+use super::biome::Biome;
+use super::coastline::{ring_points, simplify_ring, traced_rings, COAST_TOLERANCE};
+use super::types::{Coastline, NamedRegion, TerrainMap};
+
+/// Most parks on one map
+pub const MAX_PARKS: usize = 3;
+
+/// Fewest tiles a region needs to become a park
+pub const PARK_MIN_SIZE: usize = 150;
+
+/// Tiles from a city, on any side, that count as near it
+pub const PARK_CLEARANCE: usize = 6;
+
+/// Largest share of a park's tiles that may be near a city
+pub const PARK_SETTLED_SHARE: f64 = 0.15;
+
+/// Boundaries shorter than this, in crossings of half a tile, are small
+/// clearings inside a park and left undrawn
+const MIN_BOUNDARY: usize = 16;
+
+impl TerrainMap {
+    /// Whether `name` names one of the parks.
+    pub fn is_park(&self, name: &str) -> bool {
+        self.parks.iter().any(|park| park == name)
+    }
+
+    /// The boundaries of every park as open runs of points in tile
+    /// coordinates, as [`Coastline::runs`] gives them.
+    pub fn park_boundaries(&self) -> Vec<Vec<(f32, f32)>> {
+        self.regions
+            .iter()
+            .filter(|region| self.is_park(&region.name))
+            .flat_map(|region| self.outline(region))
+            .collect()
+    }
+
+    /// The rings round `region` and the rivers through it.
+    fn outline(&self, region: &NamedRegion) -> Vec<Vec<(f32, f32)>> {
+        let inside = |x: isize, y: isize| {
+            let (x, y) = (x as usize, y as usize);
+            let banks = || {
+                [(0, -1), (-1, 0), (1, 0), (0, 1)]
+                    .into_iter()
+                    .filter_map(|(dx, dy)| self.offset(x, y, dx, dy))
+                    .filter(|&(nx, ny)| region.contains(nx, ny))
+                    .count()
+            };
+            region.contains(x, y) || (self.biome_at(x, y) == Some(Biome::River) && banks() >= 2)
+        };
+        traced_rings(self.width, self.height, inside)
+            .into_iter()
+            .filter(|ring| ring.len() >= MIN_BOUNDARY)
+            .flat_map(|ring| {
+                let points = simplify_ring(&ring_points(&ring, self.width, self.height), COAST_TOLERANCE);
+                Coastline { points, lake: false }.runs(self.width, self.height)
+            })
+            .collect()
+    }
+
+    /// Set aside the largest wild regions far from the cities as parks,
+    /// up to `MAX_PARKS` with those there are; see the
+    /// [module docs](self).
+    pub(super) fn designate_parks(&mut self) {
+        let towns: HashSet<(usize, usize)> = self.cities.iter().map(|c| (c.x, c.y)).collect();
+        let reach = PARK_CLEARANCE as isize;
+        let mut settled: HashSet<(usize, usize)> = HashSet::new();
+        for city in &self.cities {
+            for dy in -reach..=reach {
+                for dx in -reach..=reach {
+                    settled.extend(self.offset(city.x, city.y, dx, dy));
+                }
+            }
+        }
+        let mut wild: Vec<usize> = (0..self.regions.len())
+            .filter(|&i| {
+                let region = &self.regions[i];
+                matches!(region.feature_type.as_str(), "forest" | "mountains" | "swamp")
+                    && region.tiles.len() >= PARK_MIN_SIZE
+                    && !self.is_park(&region.name)
+                    && !region.tiles.iter().any(|tile| towns.contains(tile))
+                    && region.tiles.iter().filter(|tile| settled.contains(tile)).count() as f64
+                        <= region.tiles.len() as f64 * PARK_SETTLED_SHARE
+            })
+            .collect();
+        wild.sort_by_key(|&i| std::cmp::Reverse(self.regions[i].tiles.len()));
+
+        for i in wild.into_iter().take(MAX_PARKS.saturating_sub(self.parks.len())) {
+            let region = &mut self.regions[i];
+            let base = region.name.strip_prefix("The ").unwrap_or(&region.name);
+            let name = match region.feature_type.as_str() {
+                "swamp" => format!("{} Wilderness", base),
+                _ => format!("{} National Park", base),
+            };
+            for label in self.labels.iter_mut().filter(|l| l.name == region.name && l.feature_type == region.feature_type) {
+                label.name = name.clone();
+            }
+            region.name = name.clone();
+            self.parks.push(name);
+        }
+    }
+
+    /// Keep the parks in step with the regions: a park whose region is
+    /// gone is no longer one.
+    pub(super) fn prune_parks(&mut self) {
+        let regions = &self.regions;
+        self.parks.retain(|park| regions.iter().any(|region| &region.name == park));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain_generator::test_map;
+
+    fn maps() -> impl Iterator<Item = &'static TerrainMap> {
+        [1, 3, 12].into_iter().map(|seed| test_map(seed, 200, 150))
+    }
+
+    #[test]
+    fn parks_are_large_wild_regions_far_from_cities() {
+        let mut found = 0;
+        for map in maps() {
+            assert!(map.parks.len() <= MAX_PARKS);
+            for park in &map.parks {
+                found += 1;
+                assert!(park.ends_with(" National Park") || park.ends_with(" Wilderness"), "{}", park);
+                let region = map.regions.iter().find(|r| &r.name == park).expect("a park is a region");
+                assert!(region.tiles.len() >= PARK_MIN_SIZE);
+                assert!(map.labels.iter().any(|l| &l.name == park));
+                let near = |&(x, y): &&(usize, usize)| {
+                    map.cities.iter().any(|c| x.abs_diff(c.x).max(y.abs_diff(c.y)) <= PARK_CLEARANCE)
+                };
+                assert!(map.cities.iter().all(|c| !region.contains(c.x, c.y)), "a city in {}", park);
+                assert!(region.tiles.iter().filter(near).count() as f64 <= region.tiles.len() as f64 * PARK_SETTLED_SHARE);
+            }
+            assert_eq!(map.parks.is_empty(), map.park_boundaries().is_empty());
+        }
+        assert!(found > 0);
+    }
+
+    #[test]
+    fn designating_again_adds_nothing_new() {
+        for map in maps() {
+            let mut again = map.clone();
+            again.designate_parks();
+            assert_eq!(again.parks, map.parks);
+        }
+    }
+
+    #[test]
+    fn a_crop_that_leaves_a_parks_region_out_drops_the_park() {
+        for map in maps() {
+            let mut cut = map.clone();
+            cut.regions.retain(|r| !map.is_park(&r.name));
+            cut.prune_parks();
+            assert!(cut.parks.is_empty());
+        }
+    }
+}
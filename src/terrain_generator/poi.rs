@@ -0,0 +1,563 @@
+//! Points of interest: ruins, lighthouses, volcanoes and any kind a user
+//! of the library registers.
+//!
+//! Every kind is a [`PoiKind`]: its [`PoiStyle`] (icon and label), the
+//! words its points are named from, and a [`PoiPlacement`] rule scoring
+//! how well each tile suits it. The generator places every registered
+//! kind after the labels, drawing from its own random stream, and keeps
+//! the points in `TerrainMap::pois` with the styles in
+//! `TerrainMap::poi_styles`, so a saved map draws the same without the
+//! rules. Renderers and exports go through [`TerrainMap::points_of_interest`],
+//! which also lists the cities and bridges, instead of needing a field and
+//! a drawing branch per kind.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+
+use rand::Rng;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::name_lists::NamePool;
+
+use super::biome::Biome;
+use super::features::capitalized;
+use super::names::NameKind;
+use super::types::{TerrainMap, TerrainPoint};
+use super::TerrainGenerator;
+
+/// Kind of the cities and bridges in [`TerrainMap::points_of_interest`]
+pub const CITY_KIND: &str = "city";
+pub const BRIDGE_KIND: &str = "bridge";
+
+/// Closest two points of interest of any kinds may be, in tiles
+const MIN_POI_GAP: f32 = 2.0;
+
+/// Diagonal step of the chamfer distance to the nearest city
+const DIAGONAL: f32 = std::f32::consts::SQRT_2;
+
+/// A named place on the map, by kind.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PointOfInterest {
+    /// A registered kind, or [`CITY_KIND`] or [`BRIDGE_KIND`]
+    pub kind: String,
+    /// Tile (x, y)
+    pub position: (usize, usize),
+    pub name: String,
+    /// Free-form facts for tooltips and exports, e.g. a city's population
+    #[serde(default)]
+    pub metadata: BTreeMap<String, String>,
+}
+
+/// Shape of a point of interest's icon.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PoiIcon {
+    Circle,
+    Square,
+    Diamond,
+    /// Pointing up
+    Triangle,
+}
+
+impl PoiIcon {
+    /// Whether the icon of radius 1 centred on the origin covers (dx, dy),
+    /// y pointing down.
+    pub fn covers(&self, dx: f32, dy: f32) -> bool {
+        match self {
+            PoiIcon::Circle => dx * dx + dy * dy <= 1.0,
+            PoiIcon::Square => dx.abs() <= 0.8 && dy.abs() <= 0.8,
+            PoiIcon::Diamond => dx.abs() + dy.abs() <= 1.0,
+            PoiIcon::Triangle => (-1.0..=0.8).contains(&dy) && dx.abs() <= (dy + 1.0) * 0.6,
+        }
+    }
+
+    /// Corners of the icon of `radius` centred on (`x`, `y`), for vector
+    /// output; empty for the circle.
+    pub fn outline(&self, x: f32, y: f32, radius: f32) -> Vec<(f32, f32)> {
+        let corners: &[(f32, f32)] = match self {
+            PoiIcon::Circle => &[],
+            PoiIcon::Square => &[(-0.8, -0.8), (0.8, -0.8), (0.8, 0.8), (-0.8, 0.8)],
+            PoiIcon::Diamond => &[(0.0, -1.0), (1.0, 0.0), (0.0, 1.0), (-1.0, 0.0)],
+            PoiIcon::Triangle => &[(0.0, -1.0), (1.08, 0.8), (-1.08, 0.8)],
+        };
+        corners.iter().map(|&(dx, dy)| (x + dx * radius, y + dy * radius)).collect()
+    }
+}
+
+/// How a kind of point of interest is drawn. Sizes are in pixels at 10
+/// pixels per tile, and grow with the render scale like the other labels.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PoiStyle {
+    pub kind: String,
+    pub icon: PoiIcon,
+    pub icon_color: [u8; 3],
+    pub icon_radius: f32,
+    /// 0 draws the icon without a name
+    pub label_size: f32,
+    pub label_color: [u8; 3],
+}
+
+impl PoiStyle {
+    /// The style of kinds no style was kept for: a grey dot with a name.
+    pub fn fallback(kind: &str) -> PoiStyle {
+        PoiStyle {
+            kind: kind.to_string(),
+            icon: PoiIcon::Circle,
+            icon_color: [90, 90, 90],
+            icon_radius: 4.0,
+            label_size: 14.0,
+            label_color: [230, 230, 230],
+        }
+    }
+}
+
+/// What a placement rule knows about a tile.
+pub struct PoiSite<'a> {
+    pub map: &'a TerrainMap,
+    pub x: usize,
+    pub y: usize,
+    pub point: &'a TerrainPoint,
+    /// Tiles to the nearest city, 0 on a city
+    pub city_distance: f32,
+    /// How many of the 8 neighbouring tiles are sea
+    pub sea_neighbours: usize,
+}
+
+/// How well a tile suits a kind; tiles scoring 0 or less never get one.
+pub type PoiRule = Arc<dyn Fn(&PoiSite) -> f32 + Send + Sync>;
+
+/// Where and how many points of a kind are placed. Sites are drawn at
+/// random, each as likely as its score, skipping those too close to
+/// points already placed.
+#[derive(Clone)]
+pub struct PoiPlacement {
+    pub rule: PoiRule,
+    /// Points per 10,000 land tiles
+    pub density: f32,
+    /// Closest two points of this kind may be, in tiles
+    pub spacing: f32,
+}
+
+impl fmt::Debug for PoiPlacement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PoiPlacement")
+            .field("density", &self.density)
+            .field("spacing", &self.spacing)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A kind of point of interest the generator places.
+#[derive(Debug, Clone)]
+pub struct PoiKind {
+    pub style: PoiStyle,
+    /// Words the points are named from; with none they are named after
+    /// the kind ("Shrine", "North Shrine", ...)
+    pub names: NamePool,
+    pub placement: PoiPlacement,
+}
+
+impl PoiKind {
+    pub fn id(&self) -> &str {
+        &self.style.kind
+    }
+
+    /// The kinds every generator starts with: ruins well away from the
+    /// cities, lighthouses on headlands near them and volcanoes on the
+    /// highest peaks. Every call shares the same rules, so the phase cache
+    /// can tell the kinds are unchanged.
+    pub fn builtin() -> Vec<PoiKind> {
+        static BUILTIN: OnceLock<Vec<PoiKind>> = OnceLock::new();
+        BUILTIN.get_or_init(Self::make_builtin).clone()
+    }
+
+    fn make_builtin() -> Vec<PoiKind> {
+        let words = word_pool;
+        vec![
+            PoiKind {
+                style: PoiStyle {
+                    kind: "ruins".to_string(),
+                    icon: PoiIcon::Square,
+                    icon_color: [110, 90, 70],
+                    icon_radius: 4.0,
+                    label_size: 14.0,
+                    label_color: [230, 215, 190],
+                },
+                names: words(
+                    &["Ruins of ", "Fallen ", "Tombs of "],
+                    &["Eldamar", "Tharn", "Vael", "Caldris", "Orthos", "Myrr", "Sulen", "Karathis"],
+                ),
+                placement: PoiPlacement {
+                    rule: Arc::new(|site| match site.point.biome {
+                        _ if site.city_distance < 10.0 => 0.0,
+                        Biome::Desert => 2.0,
+                        Biome::Plains | Biome::Forest | Biome::Hills | Biome::Swamp => 1.0,
+                        _ => 0.0,
+                    }),
+                    density: 2.0,
+                    spacing: 20.0,
+                },
+            },
+            PoiKind {
+                style: PoiStyle {
+                    kind: "lighthouse".to_string(),
+                    icon: PoiIcon::Diamond,
+                    icon_color: [250, 215, 70],
+                    icon_radius: 4.0,
+                    label_size: 13.0,
+                    label_color: [255, 240, 190],
+                },
+                names: words(
+                    &["Gull", "Storm", "Grey", "Beacon", "Seal", "Wrack"],
+                    &[" Point Light", " Head Light", " Lighthouse"],
+                ),
+                placement: PoiPlacement {
+                    // The more sea around a tile, the further its light shows
+                    rule: Arc::new(|site| {
+                        let land = !site.point.biome.is_water() && site.point.biome != Biome::River;
+                        if land && site.city_distance <= 8.0 && site.sea_neighbours >= 3 {
+                            site.sea_neighbours as f32
+                        } else {
+                            0.0
+                        }
+                    }),
+                    density: 1.0,
+                    spacing: 25.0,
+                },
+            },
+            PoiKind {
+                style: PoiStyle {
+                    kind: "volcano".to_string(),
+                    icon: PoiIcon::Triangle,
+                    icon_color: [190, 50, 20],
+                    icon_radius: 6.0,
+                    label_size: 16.0,
+                    label_color: [255, 175, 130],
+                },
+                names: words(
+                    &["Mount ", "Mt. "],
+                    &["Cinder", "Ashfall", "Ember", "Smoulder", "Brimstone", "Pyre", "Scoria"],
+                ),
+                placement: PoiPlacement {
+                    rule: Arc::new(|site| match site.point.biome {
+                        Biome::Mountains | Biome::SnowPeaks => (site.point.elevation as f32 - 0.5).max(0.0),
+                        _ => 0.0,
+                    }),
+                    density: 0.5,
+                    spacing: 40.0,
+                },
+            },
+        ]
+    }
+}
+
+/// Names joined from each of `prefixes` with each of `suffixes`.
+pub(super) fn word_pool(prefixes: &[&str], suffixes: &[&str]) -> NamePool {
+    NamePool {
+        names: Vec::new(),
+        prefixes: prefixes.iter().map(|s| s.to_string()).collect(),
+        suffixes: suffixes.iter().map(|s| s.to_string()).collect(),
+    }
+}
+
+impl TerrainMap {
+    /// Every point of interest on the map: the cities (with their
+    /// population), the bridges, then the placed points.
+    pub fn points_of_interest(&self) -> Vec<PointOfInterest> {
+        let cities = self.cities.iter().map(|city| PointOfInterest {
+            kind: CITY_KIND.to_string(),
+            position: (city.x, city.y),
+            name: city.name.clone(),
+            metadata: BTreeMap::from([("population".to_string(), city.population.to_string())]),
+        });
+        let bridges = self.bridges.iter().map(|bridge| PointOfInterest {
+            kind: BRIDGE_KIND.to_string(),
+            position: (bridge.x, bridge.y),
+            name: bridge.name.clone(),
+            metadata: BTreeMap::new(),
+        });
+        cities.chain(bridges).chain(self.pois.iter().cloned()).collect()
+    }
+
+    /// How points of `kind` are drawn: the style kept with the map, else
+    /// [`PoiStyle::fallback`].
+    pub fn poi_style(&self, kind: &str) -> PoiStyle {
+        self.poi_styles
+            .iter()
+            .find(|style| style.kind == kind)
+            .cloned()
+            .unwrap_or_else(|| PoiStyle::fallback(kind))
+    }
+
+    /// Distance in tiles from every tile to the nearest city, by a
+    /// two-pass chamfer transform; infinite on a map without cities.
+    fn city_distances(&self) -> Vec<Vec<f32>> {
+        let (width, height) = (self.width, self.height);
+        let mut distance = vec![vec![f32::INFINITY; width]; height];
+        for city in self.cities.iter().filter(|c| c.x < width && c.y < height) {
+            distance[city.y][city.x] = 0.0;
+        }
+        let steps = [(-1, -1, DIAGONAL), (0, -1, 1.0), (1, -1, DIAGONAL), (-1, 0, 1.0)];
+        let mut relax = |x: usize, y: usize, flip: isize| {
+            for &(dx, dy, cost) in &steps {
+                let (Some(nx), Some(ny)) = (x.checked_add_signed(dx * flip), y.checked_add_signed(dy * flip)) else {
+                    continue;
+                };
+                if nx < width && ny < height {
+                    distance[y][x] = distance[y][x].min(distance[ny][nx] + cost);
+                }
+            }
+        };
+        for y in 0..height {
+            for x in 0..width {
+                relax(x, y, 1);
+            }
+        }
+        for y in (0..height).rev() {
+            for x in (0..width).rev() {
+                relax(x, y, -1);
+            }
+        }
+        distance
+    }
+
+    /// How many of the 8 neighbours of (x, y) are sea (not lakes).
+    pub(super) fn sea_neighbours(&self, x: usize, y: usize) -> usize {
+        let mut count = 0;
+        for dy in -1isize..=1 {
+            for dx in -1isize..=1 {
+                let neighbour = x.checked_add_signed(dx).zip(y.checked_add_signed(dy));
+                let sea = neighbour
+                    .and_then(|(nx, ny)| self.biome_at(nx, ny))
+                    .is_some_and(|b| matches!(b, Biome::Ocean | Biome::DeepOcean | Biome::Shore));
+                if (dx, dy) != (0, 0) && sea {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+}
+
+impl TerrainGenerator {
+    /// Add `kind` to the kinds of point of interest the generator places,
+    /// replacing a registered kind with the same id.
+    pub fn register_poi_kind(&mut self, kind: PoiKind) {
+        match self.poi_kinds.iter_mut().find(|k| k.id() == kind.id()) {
+            Some(registered) => *registered = kind,
+            None => self.poi_kinds.push(kind),
+        }
+    }
+
+    /// Stop placing the kind `id`; false if it wasn't registered.
+    pub fn unregister_poi_kind(&mut self, id: &str) -> bool {
+        let before = self.poi_kinds.len();
+        self.poi_kinds.retain(|k| k.id() != id);
+        self.poi_kinds.len() < before
+    }
+
+    /// The kinds placed, in the order they are placed in.
+    pub fn poi_kinds(&self) -> &[PoiKind] {
+        &self.poi_kinds
+    }
+
+    /// The registered kinds, and on a modern-era map the modern kinds not
+    /// registered after them.
+    fn placed_poi_kinds(&self) -> Vec<PoiKind> {
+        let mut kinds = self.poi_kinds.clone();
+        if self.settings.modern {
+            for kind in PoiKind::modern() {
+                if !kinds.iter().any(|k| k.id() == kind.id()) {
+                    kinds.push(kind);
+                }
+            }
+        }
+        kinds
+    }
+
+    /// Place and name the points of every registered kind on a map with
+    /// its cities, roads and labels, and keep their styles with it.
+    pub(super) fn place_points_of_interest(&mut self, map: &mut TerrainMap) {
+        map.pois = self.points_of_interest_for(map);
+        map.poi_styles = self.placed_poi_kinds().into_iter().map(|k| k.style).collect();
+    }
+
+    /// The points of every registered kind for `map`, kinds in order,
+    /// clear of its cities and of each other.
+    pub(super) fn points_of_interest_for(&mut self, map: &TerrainMap) -> Vec<PointOfInterest> {
+        let land = map.terrain.iter().flatten().filter(|p| !p.biome.is_water()).count();
+        let city_distances = map.city_distances();
+        let mut placed: Vec<PointOfInterest> = Vec::new();
+        for kind in self.placed_poi_kinds() {
+            let wanted = (kind.placement.density * land as f32 / 10_000.0).round() as usize;
+            if wanted == 0 {
+                continue;
+            }
+            let rule = &kind.placement.rule;
+            let scores: Vec<(f32, usize, usize)> = map
+                .terrain
+                .par_iter()
+                .enumerate()
+                .flat_map_iter(|(y, row)| {
+                    let city_distances = &city_distances[y];
+                    row.iter().enumerate().filter_map(move |(x, point)| {
+                        let site = PoiSite {
+                            map,
+                            x,
+                            y,
+                            point,
+                            city_distance: city_distances[x],
+                            sea_neighbours: map.sea_neighbours(x, y),
+                        };
+                        let score = if site.city_distance > 0.0 { rule(&site) } else { 0.0 };
+                        (score > 0.0).then_some((score, x, y))
+                    })
+                })
+                .collect();
+            // Weighted sampling without replacement: each site's key is
+            // u^(1/score), highest first
+            let mut sites: Vec<(f32, usize, usize)> = scores
+                .into_iter()
+                .map(|(score, x, y)| (self.rngs.pois.gen::<f32>().powf(1.0 / score), x, y))
+                .collect();
+            sites.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+            let mut chosen: Vec<(usize, usize)> = Vec::new();
+            for &(_, x, y) in &sites {
+                if chosen.len() == wanted {
+                    break;
+                }
+                let apart = |(ox, oy): (usize, usize), gap: f32| {
+                    (ox as f32 - x as f32).hypot(oy as f32 - y as f32) >= gap
+                };
+                if chosen.iter().all(|&c| apart(c, kind.placement.spacing))
+                    && placed.iter().all(|p| apart(p.position, MIN_POI_GAP))
+                {
+                    chosen.push((x, y));
+                }
+            }
+            for position in chosen {
+                let name = self.unique_name(NameKind::PointOfInterest, |g| {
+                    kind.names.draw(&mut g.rngs.names).unwrap_or_else(|| capitalized(kind.id()))
+                });
+                placed.push(PointOfInterest {
+                    kind: kind.id().to_string(),
+                    position,
+                    name,
+                    metadata: BTreeMap::new(),
+                });
+            }
+        }
+        placed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::OnceLock;
+
+    use super::*;
+    use crate::terrain_generator::test_map;
+
+    fn map() -> &'static TerrainMap {
+        test_map(21, 200, 150)
+    }
+
+    /// Seed 21 with a shrine on every hilltop far enough apart, and no volcanoes
+    fn shrine_generator() -> TerrainGenerator {
+        let shrine = PoiKind {
+            style: PoiStyle { icon: PoiIcon::Circle, icon_color: [200, 200, 255], ..PoiStyle::fallback("shrine") },
+            names: NamePool::default(),
+            placement: PoiPlacement {
+                rule: Arc::new(|site| if site.point.biome == Biome::Hills { 1.0 } else { 0.0 }),
+                density: 3.0,
+                spacing: 10.0,
+            },
+        };
+        let mut generator = TerrainGenerator::new(21);
+        generator.register_poi_kind(shrine);
+        generator.unregister_poi_kind("volcano");
+        generator
+    }
+
+    fn shrine_map() -> &'static TerrainMap {
+        static MAP: OnceLock<TerrainMap> = OnceLock::new();
+        MAP.get_or_init(|| shrine_generator().generate(200, 150))
+    }
+
+    #[test]
+    fn registered_kinds_are_placed_by_their_rules() {
+        let map = map();
+        assert_eq!(map.poi_styles.len(), 3);
+        assert!(!map.pois.is_empty());
+        for poi in &map.pois {
+            let (x, y) = poi.position;
+            assert!(!map.cities.iter().any(|c| (c.x, c.y) == (x, y)));
+            let biome = map.terrain[y][x].biome;
+            match poi.kind.as_str() {
+                "volcano" => assert!(matches!(biome, Biome::Mountains | Biome::SnowPeaks)),
+                "lighthouse" => assert!(map.sea_neighbours(x, y) >= 3, "{} at {},{}", poi.name, x, y),
+                "ruins" => assert!(!biome.is_water()),
+                kind => panic!("unexpected kind {}", kind),
+            }
+        }
+    }
+
+    #[test]
+    fn points_of_interest_list_cities_bridges_and_placed_points() {
+        let map = map();
+        let all = map.points_of_interest();
+        assert_eq!(all.len(), map.cities.len() + map.bridges.len() + map.pois.len());
+        assert_eq!(all[0].metadata["population"], map.cities[0].population.to_string());
+    }
+
+    #[test]
+    fn kinds_are_registered_and_unregistered_by_name() {
+        let mut generator = TerrainGenerator::new(21);
+        generator.register_poi_kind(PoiKind {
+            style: PoiStyle::fallback("shrine"),
+            names: NamePool::default(),
+            placement: PoiPlacement { rule: Arc::new(|_| 1.0), density: 1.0, spacing: 1.0 },
+        });
+        assert!(generator.unregister_poi_kind("volcano"));
+        assert!(!generator.unregister_poi_kind("volcano"));
+        assert_eq!(generator.poi_kinds().len(), 3);
+    }
+
+    #[test]
+    fn a_custom_kind_is_placed_by_its_rule_and_named_after_its_kind() {
+        let map = shrine_map();
+        let shrines: Vec<_> = map.pois.iter().filter(|p| p.kind == "shrine").collect();
+        assert!(!shrines.is_empty());
+        assert!(map.pois.iter().all(|p| p.kind != "volcano"));
+        for shrine in &shrines {
+            assert_eq!(map.terrain[shrine.position.1][shrine.position.0].biome, Biome::Hills);
+            assert!(shrine.name.ends_with("Shrine") || shrine.name.starts_with("Shrine"), "{}", shrine.name);
+        }
+        assert_eq!(map.poi_style("shrine").icon_color, [200, 200, 255]);
+        assert_eq!(map.poi_style("unknown").icon, PoiIcon::Circle);
+    }
+
+    #[test]
+    fn the_same_seed_and_kinds_place_the_same_points() {
+        assert_eq!(shrine_generator().generate(200, 150).pois, shrine_map().pois);
+    }
+
+    #[test]
+    fn a_saved_map_keeps_its_points_and_styles() {
+        let map = shrine_map();
+        let json = serde_json::to_string(map).unwrap();
+        let loaded: TerrainMap = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.pois, map.pois);
+        assert_eq!(loaded.poi_style("shrine"), map.poi_style("shrine"));
+    }
+
+    #[test]
+    fn icons_cover_their_centre_and_not_their_corners() {
+        for icon in [PoiIcon::Circle, PoiIcon::Square, PoiIcon::Diamond, PoiIcon::Triangle] {
+            assert!(icon.covers(0.0, 0.0) && !icon.covers(1.1, 1.1));
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use super::TerrainGenerator;
+
+/// A stage of the generation pipeline, in the order they run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum GenerationPhase {
+    Elevation,
+    Climate,
+    Rivers,
+    Cities,
+    Roads,
+    Labels,
+}
+
+impl GenerationPhase {
+    pub const ALL: [GenerationPhase; 6] = [
+        GenerationPhase::Elevation,
+        GenerationPhase::Climate,
+        GenerationPhase::Rivers,
+        GenerationPhase::Cities,
+        GenerationPhase::Roads,
+        GenerationPhase::Labels,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GenerationPhase::Elevation => "elevation",
+            GenerationPhase::Climate => "climate",
+            GenerationPhase::Rivers => "rivers",
+            GenerationPhase::Cities => "cities",
+            GenerationPhase::Roads => "roads",
+            GenerationPhase::Labels => "labels",
+        }
+    }
+}
+
+/// Called with the running phase and its completion fraction in [0, 1].
+/// Every phase reports 0.0 when it starts and 1.0 exactly once, when it
+/// finishes.
+pub type ProgressCallback = Box<dyn FnMut(GenerationPhase, f32) + Send>;
+
+/// Receives the generator's progress, for front-ends that want more than
+/// a [`ProgressCallback`]. Every method does nothing by default; closures
+/// taking a phase and a fraction implement `on_progress`.
+pub trait GenerationObserver: Send {
+    /// A phase is starting, before its first progress report.
+    fn on_phase_start(&mut self, _phase: GenerationPhase) {}
+
+    /// Same contract as [`ProgressCallback`].
+    fn on_progress(&mut self, _phase: GenerationPhase, _fraction: f32) {}
+
+    /// A phase finished (right after reporting 1.0) after `elapsed`.
+    fn on_phase_end(&mut self, _phase: GenerationPhase, _elapsed: Duration) {}
+}
+
+impl<F: FnMut(GenerationPhase, f32) + Send> GenerationObserver for F {
+    fn on_progress(&mut self, phase: GenerationPhase, fraction: f32) {
+        self(phase, fraction)
+    }
+}
+
+/// Wall-clock time of each phase of the last run, in the order they ran.
+/// Phases a run skipped (e.g. the terrain when rerolling cities) or was
+/// cancelled in are missing.
+pub type PhaseTimings = Vec<(GenerationPhase, Duration)>;
+
+/// Stops a generation started with `generate_cancellable` from another
+/// thread. Clones share the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Why a generation stopped without a map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GenerationError {
+    /// Its [`CancellationToken`] was cancelled
+    Cancelled,
+}
+
+impl fmt::Display for GenerationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GenerationError::Cancelled => write!(f, "generation cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for GenerationError {}
+
+impl TerrainGenerator {
+    /// Install a callback that receives progress while `generate` runs.
+    pub fn set_progress_callback(&mut self, callback: ProgressCallback) {
+        self.set_observer(Box::new(callback));
+    }
+
+    /// Install an observer that is told when phases start and end as well
+    /// as their progress. Replaces any progress callback.
+    pub fn set_observer(&mut self, observer: Box<dyn GenerationObserver>) {
+        self.progress = Some(Mutex::new(observer));
+    }
+
+    /// How long each phase of the last `generate`, `regenerate` or region
+    /// reroll took, whether or not an observer is installed.
+    pub fn phase_timings(&self) -> &[(GenerationPhase, Duration)] {
+        &self.timings
+    }
+
+    pub(super) fn report_progress(&mut self, phase: GenerationPhase, fraction: f32) {
+        let fraction = fraction.clamp(0.0, 1.0);
+        let mut observer = self
+            .progress
+            .as_mut()
+            .map(|observer| observer.get_mut().unwrap_or_else(PoisonError::into_inner));
+
+        if !matches!(self.running_phase, Some((running, _)) if running == phase) {
+            // Phases run in order, so one that doesn't follow the last
+            // finished phase starts a new run
+            if self.timings.last().is_some_and(|&(last, _)| phase <= last) {
+                self.timings.clear();
+            }
+            self.running_phase = Some((phase, Instant::now()));
+            if let Some(observer) = observer.as_mut() {
+                observer.on_phase_start(phase);
+            }
+        }
+        if let Some(observer) = observer.as_mut() {
+            observer.on_progress(phase, fraction);
+        }
+        if fraction >= 1.0 {
+            if let Some((_, started)) = self.running_phase.take() {
+                let elapsed = started.elapsed();
+                self.timings.push((phase, elapsed));
+                if let Some(observer) = observer.as_mut() {
+                    observer.on_phase_end(phase, elapsed);
+                }
+            }
+        }
+    }
+
+    /// `Cancelled` once the running `generate_cancellable` has been
+    /// cancelled, for use with `?` between phases and batches of rows.
+    pub(super) fn checkpoint(&self) -> Result<(), GenerationError> {
+        match &self.cancel {
+            Some(token) if token.is_cancelled() => Err(GenerationError::Cancelled),
+            _ => Ok(()),
+        }
+    }
+}
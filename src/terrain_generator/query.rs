@@ -0,0 +1,405 @@
+//! Questions about a finished map: what is at a tile, which city is
+//! closest, and how to travel between two points.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::measure::DEFAULT_KM_PER_TILE;
+
+use super::biome::Biome;
+use super::features::{FeatureIndex, MapFeature};
+use super::labels::region_kind_of;
+use super::region::Region;
+use super::travel::{TravelGrid, TravelProfile};
+use super::types::{Bridge, City, NamedRegion, PlaceLabel, Road, TerrainMap, TerrainPoint};
+
+/// A travel route across the map.
+#[derive(Debug, Clone)]
+pub struct Route {
+    /// Tiles from start to end, inclusive
+    pub path: Vec<(usize, usize)>,
+    /// Length in tiles (diagonal steps count as sqrt 2)
+    pub distance: f64,
+    /// Part of `distance` travelled on existing roads
+    pub road_distance: f64,
+    /// Number of times the route enters a river tile
+    pub river_crossings: usize,
+    /// Estimated travel time for the route's profile
+    pub hours: f64,
+}
+
+/// Everything on one tile, as shown by the GUI's inspector.
+#[derive(Debug, Clone)]
+pub struct TileInfo<'a> {
+    pub x: usize,
+    pub y: usize,
+    pub biome: Biome,
+    pub elevation: f64,
+    pub moisture: f64,
+    pub temperature: f64,
+    /// The closest city and its distance in tiles (0 if it is on this tile)
+    pub nearest_city: Option<(&'a City, f64)>,
+    /// The named ocean, range, forest or swamp the tile is part of
+    pub region: Option<&'a PlaceLabel>,
+    /// A river flows through the tile
+    pub river: bool,
+    /// Roads passing through the tile
+    pub roads: Vec<&'a Road>,
+    pub bridge: Option<&'a Bridge>,
+}
+
+/// One fact per line, e.g. for a tooltip or the CLI.
+impl fmt::Display for TileInfo<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:?} at {},{}", self.biome, self.x, self.y)?;
+        write!(
+            f,
+            "Elevation {:.2}, moisture {:.2}, temperature {:.2}",
+            self.elevation, self.moisture, self.temperature
+        )?;
+        if let Some((city, distance)) = self.nearest_city {
+            write!(
+                f,
+                "\nNearest city: {} (population {}), {:.1} tiles away",
+                city.name, city.population, distance
+            )?;
+        }
+        if let Some(region) = self.region {
+            write!(f, "\nPart of {}", region.name)?;
+        }
+        if self.river {
+            write!(f, "\nRiver")?;
+        }
+        if !self.roads.is_empty() {
+            let names: Vec<&str> = self.roads.iter().map(|r| r.name.as_str()).collect();
+            write!(f, "\nRoads: {}", names.join(", "))?;
+        }
+        if let Some(bridge) = self.bridge {
+            write!(f, "\nBridge: {}", bridge.name)?;
+        }
+        Ok(())
+    }
+}
+
+impl TerrainMap {
+    pub fn point_at(&self, x: usize, y: usize) -> Option<&TerrainPoint> {
+        self.terrain.get(y).and_then(|row| row.get(x))
+    }
+
+    pub fn biome_at(&self, x: usize, y: usize) -> Option<Biome> {
+        self.point_at(x, y).map(|p| p.biome)
+    }
+
+    pub fn elevation_at(&self, x: usize, y: usize) -> Option<f64> {
+        self.point_at(x, y).map(|p| p.elevation)
+    }
+
+    /// The elevation of each tile of `path` in turn, e.g. a road, a river
+    /// or a [`Route`]'s path: its cross-section, for judging how hard the
+    /// way is. Tiles outside the map are left out.
+    pub fn elevation_profile(&self, path: &[(usize, usize)]) -> Vec<f64> {
+        path.iter().filter_map(|&(x, y)| self.elevation_at(x, y)).collect()
+    }
+
+    /// Whether (x, y) is sea, shore or lake. Rivers can be crossed, so they
+    /// count as land here as they do for roads. `false` outside the map.
+    pub fn is_water(&self, x: usize, y: usize) -> bool {
+        self.biome_at(x, y).is_some_and(|biome| biome.is_water())
+    }
+
+    /// The named ocean, mountain range, forest or swamp that (x, y) is
+    /// part of, from the map's `regions`. `None` on unnamed terrain,
+    /// outside the map and on maps saved before regions were kept.
+    pub fn named_region_at(&self, x: usize, y: usize) -> Option<&NamedRegion> {
+        self.regions.iter().find(|region| region.contains(x, y))
+    }
+
+    /// The label of the named ocean, mountain range, forest or swamp that
+    /// (x, y) is part of: the label standing in the same connected stretch
+    /// of that terrain. `None` on unnamed terrain and outside the map.
+    pub fn region_of(&self, x: usize, y: usize) -> Option<&PlaceLabel> {
+        if !self.regions.is_empty() {
+            let region = self.named_region_at(x, y)?;
+            return self
+                .labels
+                .iter()
+                .find(|label| label.name == region.name && label.feature_type == region.feature_type);
+        }
+        // Older maps: flood the stretch from (x, y) until it reaches a label
+        let (feature_type, belongs) = region_kind_of(self.biome_at(x, y)?)?;
+        let anchors: HashMap<(usize, usize), &PlaceLabel> = self
+            .labels
+            .iter()
+            .filter(|label| label.feature_type == feature_type)
+            .map(|label| ((label.x as usize, label.y as usize), label))
+            .collect();
+        if anchors.is_empty() {
+            return None;
+        }
+
+        let mut visited = HashSet::from([(x, y)]);
+        let mut stack = vec![(x, y)];
+        while let Some(tile) = stack.pop() {
+            if let Some(label) = anchors.get(&tile) {
+                return Some(label);
+            }
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    let Some(next) = self.offset(tile.0, tile.1, dx, dy) else {
+                        continue;
+                    };
+                    if self.biome_at(next.0, next.1).is_some_and(|b| belongs(&b))
+                        && visited.insert(next)
+                    {
+                        stack.push(next);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Cities, bridges and labels standing in `rect` and roads passing
+    /// through it. For many queries on one map, build a [`FeatureIndex`]
+    /// once and ask its `in_rect` instead.
+    pub fn features_in(&self, rect: Region) -> Vec<MapFeature> {
+        FeatureIndex::new(self).in_rect(rect)
+    }
+
+    /// The city closest to (x, y) and its straight-line distance in tiles.
+    pub fn nearest_city(&self, x: usize, y: usize) -> Option<(&City, f64)> {
+        self.cities
+            .iter()
+            .map(|city| {
+                let dx = city.x as f64 - x as f64;
+                let dy = city.y as f64 - y as f64;
+                (city, (dx * dx + dy * dy).sqrt())
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    /// Describe the tile at (x, y), or `None` outside the map.
+    pub fn inspect(&self, x: usize, y: usize) -> Option<TileInfo<'_>> {
+        let point = self.point_at(x, y)?;
+        let tile = (x, y);
+        Some(TileInfo {
+            x,
+            y,
+            biome: point.biome,
+            elevation: point.elevation,
+            moisture: point.moisture,
+            temperature: point.temperature,
+            nearest_city: self.nearest_city(x, y),
+            region: self.region_of(x, y),
+            river: point.biome == Biome::River
+                || self.rivers.iter().any(|river| river.contains(&tile)),
+            roads: self
+                .roads
+                .iter()
+                .filter(|road| road.path.contains(&tile))
+                .collect(),
+            bridge: self.bridges.iter().find(|b| (b.x, b.y) == tile),
+        })
+    }
+
+    /// Look up a city by name, ignoring case.
+    pub fn find_city(&self, name: &str) -> Option<&City> {
+        self.cities
+            .iter()
+            .find(|city| city.name.eq_ignore_ascii_case(name.trim()))
+    }
+
+    /// Quickest route between two tiles for `profile`, with tiles
+    /// [`DEFAULT_KM_PER_TILE`] across; see [`TerrainMap::route_at_scale`].
+    pub fn route(&self, from: (usize, usize), to: (usize, usize), profile: TravelProfile) -> Option<Route> {
+        self.route_at_scale(from, to, profile, DEFAULT_KM_PER_TILE)
+    }
+
+    /// Quickest route between two tiles for `profile`, with tiles
+    /// `km_per_tile` across. Walkers and riders go anywhere on land and
+    /// follow roads where they help, carts keep to roads, and boats to
+    /// water, putting out from and landing at the ends. Returns `None`
+    /// outside the map or if the profile cannot get from one end to the
+    /// other.
+    pub fn route_at_scale(
+        &self,
+        from: (usize, usize),
+        to: (usize, usize),
+        profile: TravelProfile,
+        km_per_tile: f64,
+    ) -> Option<Route> {
+        self.point_at(from.0, from.1)?;
+        self.point_at(to.0, to.1)?;
+        let grid = TravelGrid::new(self, &profile.speeds(), km_per_tile);
+        let (path, hours) = grid.fastest_path(from, to)?;
+
+        let road_tiles: HashSet<(usize, usize)> = self
+            .roads
+            .iter()
+            .flat_map(|road| road.path.iter().copied())
+            .collect();
+        let mut route = self.describe_route(path, &road_tiles);
+        route.hours = hours;
+        Some(route)
+    }
+
+    fn describe_route(
+        &self,
+        path: Vec<(usize, usize)>,
+        road_tiles: &HashSet<(usize, usize)>,
+    ) -> Route {
+        let mut distance = 0.0;
+        let mut road_distance = 0.0;
+        let mut river_crossings = 0;
+        for step in path.windows(2) {
+            let (a, b) = (step[0], step[1]);
+            let length = if a.0 != b.0 && a.1 != b.1 {
+                std::f64::consts::SQRT_2
+            } else {
+                1.0
+            };
+            distance += length;
+            if road_tiles.contains(&a) && road_tiles.contains(&b) {
+                road_distance += length;
+            }
+            if self.biome_at(b.0, b.1) == Some(Biome::River)
+                && self.biome_at(a.0, a.1) != Some(Biome::River)
+            {
+                river_crossings += 1;
+            }
+        }
+        Route {
+            path,
+            distance,
+            road_distance,
+            river_crossings,
+            hours: 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain_generator::test_map;
+
+    fn map() -> &'static TerrainMap {
+        let map = test_map(11, 160, 120);
+        assert!(map.cities.len() >= 2, "seed should place cities");
+        map
+    }
+
+    /// The ends of the map's first highway, and the highway
+    fn highway(map: &TerrainMap) -> ((usize, usize), (usize, usize), &Road) {
+        let road = map.roads.iter().find(|r| r.road_type == "highway").unwrap();
+        (road.path[0], *road.path.last().unwrap(), road)
+    }
+
+    #[test]
+    fn cities_are_found_by_place_and_by_name() {
+        let map = map();
+        let city = &map.cities[0];
+        let (nearest, distance) = map.nearest_city(city.x, city.y).unwrap();
+        assert_eq!(nearest.name, city.name);
+        assert_eq!(distance, 0.0);
+        assert_eq!(
+            map.find_city(&city.name.to_uppercase()).unwrap().name,
+            city.name
+        );
+    }
+
+    #[test]
+    fn tiles_answer_inside_the_map_only() {
+        let map = map();
+        let city = &map.cities[0];
+        assert!(map.biome_at(map.width, 0).is_none());
+        assert!(map.inspect(0, map.height).is_none());
+        assert_eq!(
+            map.elevation_at(city.x, city.y),
+            Some(map.terrain[city.y][city.x].elevation)
+        );
+        assert!(!map.is_water(city.x, city.y));
+        assert!(!map.is_water(map.width, 0));
+    }
+
+    #[test]
+    fn a_city_is_among_the_features_on_its_tile() {
+        let city = &map().cities[0];
+        let city_tile = Region {
+            x: city.x,
+            y: city.y,
+            width: 1,
+            height: 1,
+        };
+        assert!(map().features_in(city_tile).contains(&MapFeature::City(0)));
+    }
+
+    #[test]
+    fn region_labels_name_the_stretch_they_stand_in() {
+        let map = map();
+        let regions: Vec<&PlaceLabel> = map
+            .labels
+            .iter()
+            .filter(|label| label.feature_type != "river")
+            .collect();
+        assert!(!regions.is_empty());
+        for label in regions {
+            let (x, y) = (label.x as usize, label.y as usize);
+            assert_eq!(map.region_of(x, y).map(|l| &l.name), Some(&label.name));
+        }
+        let city = &map.cities[0];
+        let dry = map.biome_at(city.x, city.y).unwrap();
+        if region_kind_of(dry).is_none() {
+            assert!(map.region_of(city.x, city.y).is_none());
+        }
+    }
+
+    #[test]
+    fn stored_regions_match_tracing_them_and_older_maps_still_answer() {
+        let map = map();
+        assert_eq!(map.regions, map.traced_regions());
+        let forest = map.regions.iter().find(|r| r.feature_type == "forest").unwrap();
+        let (x, y) = forest.tiles[forest.tiles.len() / 3];
+        assert_eq!(map.named_region_at(x, y).map(|r| &r.name), Some(&forest.name));
+        let older = TerrainMap { regions: Vec::new(), ..map.clone() };
+        assert_eq!(older.region_of(x, y).map(|l| &l.name), Some(&forest.name));
+    }
+
+    #[test]
+    fn a_route_follows_a_road_between_its_ends() {
+        let map = map();
+        let (start, end, _) = highway(map);
+        let route = map.route(start, end, TravelProfile::OnFoot).unwrap();
+        assert_eq!(route.path.first(), Some(&start));
+        assert_eq!(route.path.last(), Some(&end));
+        assert!(route.road_distance > 0.0);
+        assert!(route.road_distance <= route.distance);
+        assert!(route.hours > 0.0);
+        let profile = map.elevation_profile(&route.path);
+        assert_eq!(profile.len(), route.path.len());
+        assert_eq!(profile[0], map.terrain[start.1][start.0].elevation);
+        assert_eq!(map.elevation_profile(&[start, (map.width, 0), end]).len(), 2);
+    }
+
+    #[test]
+    fn horses_ride_faster_and_carts_keep_to_the_road() {
+        let map = map();
+        let (start, end, _) = highway(map);
+        let walk = map.route(start, end, TravelProfile::OnFoot).unwrap();
+        let ride = map.route(start, end, TravelProfile::Horse).unwrap();
+        assert!(ride.hours < walk.hours);
+        let cart = map.route(start, end, TravelProfile::Cart).unwrap();
+        assert!((cart.road_distance - cart.distance).abs() < 1e-9);
+    }
+
+    #[test]
+    fn inspecting_a_tile_lists_its_roads_rivers_and_nearest_city() {
+        let map = map();
+        let (start, _, road) = highway(map);
+        let info = map.inspect(start.0, start.1).unwrap();
+        assert!(info.roads.iter().any(|r| r.name == road.name));
+        assert_eq!(info.nearest_city.unwrap().1, 0.0);
+        let (rx, ry) = map.rivers[0][0];
+        assert!(map.inspect(rx, ry).unwrap().river);
+    }
+}
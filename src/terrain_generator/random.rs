@@ -0,0 +1,120 @@
+//! Independent random streams for the generation phases.
+//!
+//! Each kind of decision draws from its own stream, so a setting that
+//! changes how many numbers one phase draws (e.g. more cities) doesn't
+//! shift the numbers every later phase sees. Rivers follow the terrain
+//! and draw nothing.
+
+use rand::{Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use super::TerrainGenerator;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct PhaseRngs {
+    /// Continent plans
+    pub(super) terrain: ChaCha8Rng,
+    /// City sites and populations
+    pub(super) cities: ChaCha8Rng,
+    /// Road routes, their costs and wiggles
+    pub(super) roads: ChaCha8Rng,
+    /// Every generated name
+    pub(super) names: ChaCha8Rng,
+    /// Points of interest sites
+    pub(super) pois: ChaCha8Rng,
+}
+
+impl PhaseRngs {
+    /// The streams of `seed`: one ChaCha key, a different stream each.
+    pub(super) fn from_seed(seed: u32) -> Self {
+        let stream = |n: u64| {
+            let mut rng = ChaCha8Rng::seed_from_u64(seed as u64);
+            rng.set_stream(n);
+            rng
+        };
+        PhaseRngs {
+            terrain: stream(0),
+            cities: stream(1),
+            roads: stream(2),
+            names: stream(3),
+            pois: stream(4),
+        }
+    }
+
+    /// Streams seeded one after another from `rng`.
+    pub(super) fn from_rng(rng: &mut dyn RngCore) -> Self {
+        let mut next = || ChaCha8Rng::from_seed(rng.gen());
+        PhaseRngs {
+            terrain: next(),
+            cities: next(),
+            roads: next(),
+            names: next(),
+            pois: next(),
+        }
+    }
+}
+
+impl TerrainGenerator {
+    /// Draw the random decisions of later runs from `rng` instead of the
+    /// seed. Only the noise fields still come from the seed; each phase
+    /// gets its own sub-seed from `rng`, so the result is reproducible
+    /// whenever `rng` is.
+    pub fn set_rng(&mut self, rng: &mut dyn RngCore) {
+        self.rngs = PhaseRngs::from_rng(rng);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain_generator::GenerationSettings;
+
+    #[test]
+    fn phases_draw_from_their_own_streams() {
+        let mut rngs = PhaseRngs::from_seed(4);
+        let firsts = [
+            rngs.terrain.next_u64(),
+            rngs.cities.next_u64(),
+            rngs.roads.next_u64(),
+            rngs.names.next_u64(),
+            rngs.pois.next_u64(),
+        ];
+        for (i, a) in firsts.iter().enumerate() {
+            assert!(firsts[i + 1..].iter().all(|b| a != b));
+        }
+    }
+
+    #[test]
+    fn more_cities_leave_the_land_and_rivers_alone() {
+        let with_cities = |city_density: f32| {
+            let settings = GenerationSettings {
+                city_density,
+                ..Default::default()
+            };
+            TerrainGenerator::new_with_settings(4, settings).generate(120, 90)
+        };
+        let (few, many) = (with_cities(0.1), with_cities(0.9));
+        assert!(few.cities.len() < many.cities.len());
+        assert_eq!(few.rivers, many.rivers);
+        assert!(few
+            .terrain
+            .iter()
+            .flatten()
+            .zip(many.terrain.iter().flatten())
+            .all(|(a, b)| a.elevation == b.elevation && a.biome == b.biome));
+    }
+
+    #[test]
+    fn an_injected_rng_reproduces_the_map_it_made() {
+        let injected = |seed: u64| {
+            let mut generator = TerrainGenerator::new(4);
+            generator.set_rng(&mut ChaCha8Rng::seed_from_u64(seed));
+            generator.generate(120, 90)
+        };
+        let (a, b) = (injected(9), injected(9));
+        assert_eq!(
+            serde_json::to_string(&a).unwrap(),
+            serde_json::to_string(&b).unwrap()
+        );
+    }
+}
@@ -0,0 +1,287 @@
+//! Detail amplification: a finished map at a higher resolution.
+//!
+//! Every tile becomes `factor` by `factor` tiles. Elevation, moisture and
+//! temperature are interpolated between the old tile centres and the
+//! elevation gets `DETAIL` worth of noise finer than the old tiles, drawn
+//! from the generator's seed, which breaks up the coastlines and slopes
+//! the coarse grid smoothed over; the biomes are classified again from the
+//! result. Nothing on the map moves: cities, bridges and points of interest
+//! stand at the centre of the tile they stood on, rivers and roads run
+//! straight from centre to centre, and the ground under them takes no
+//! noise, so roads stay on land and rivers keep running downhill. Lake
+//! shores are interpolated like the fields.
+
+use std::collections::{HashMap, HashSet};
+
+use rayon::prelude::*;
+
+use super::biome::Biome;
+use super::types::{wrap_dx, TerrainMap, TerrainPoint};
+use super::TerrainGenerator;
+
+/// Largest change the detail noise makes to an elevation
+pub const DETAIL: f64 = 0.08;
+
+/// Noise frequencies added, in cycles per old tile, and their weights
+const OCTAVES: [(f64, f64); 3] = [(0.7, 0.55), (1.6, 0.3), (3.5, 0.15)];
+
+impl TerrainGenerator {
+    /// `map` with each tile split into `factor` by `factor`, with detail
+    /// from this generator's seed; see the [module docs](self). A factor
+    /// of 0 or 1 gives the map back as it was.
+    pub fn refine(&self, map: &TerrainMap, factor: usize) -> TerrainMap {
+        if factor <= 1 || map.terrain.is_empty() {
+            return map.clone();
+        }
+        let (width, height) = (map.width * factor, map.height * factor);
+        let centre = |(x, y): (usize, usize)| (x * factor + factor / 2, y * factor + factor / 2);
+
+        // Tiles under the features, with the elevation they keep
+        let mut pinned: HashMap<(usize, usize), f64> = HashMap::new();
+        let elevation = |(x, y): (usize, usize)| map.terrain[y][x].elevation;
+        let mut rivers: Vec<Vec<(usize, usize)>> = Vec::new();
+        let mut river_heights: Vec<Vec<f64>> = Vec::new();
+        for river in &map.rivers {
+            let (path, heights) = fine_line(map, river, factor, elevation);
+            rivers.push(path);
+            river_heights.push(heights);
+        }
+        // Each river runs downhill, tributaries no lower than where they
+        // join, until nothing changes
+        loop {
+            let mut changed = false;
+            for (path, heights) in rivers.iter().zip(&mut river_heights) {
+                let mut lowest = f64::INFINITY;
+                for (tile, height) in path.iter().zip(heights.iter_mut()) {
+                    lowest = lowest.min(*height).min(pinned.get(tile).copied().unwrap_or(f64::INFINITY));
+                    *height = lowest;
+                    let entry = pinned.entry(*tile).or_insert(lowest);
+                    if *entry > lowest {
+                        *entry = lowest;
+                        changed = true;
+                    }
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+        let mut roads = Vec::new();
+        for road in &map.roads {
+            let (path, heights) = fine_line(map, &road.path, factor, elevation);
+            for (tile, height) in path.iter().zip(heights) {
+                pinned.entry(*tile).or_insert(height);
+            }
+            let mut road = road.clone();
+            road.path = path;
+            for bridge in &mut road.bridges {
+                (bridge.x, bridge.y) = centre((bridge.x, bridge.y));
+            }
+            road.fords = road.fords.iter().map(|&at| centre(at)).collect();
+            roads.push(road);
+        }
+        let spots = map
+            .cities
+            .iter()
+            .map(|c| (c.x, c.y))
+            .chain(map.bridges.iter().map(|b| (b.x, b.y)))
+            .chain(map.pois.iter().map(|p| p.position));
+        for spot in spots {
+            pinned.entry(centre(spot)).or_insert(elevation(spot));
+        }
+
+        let river_tiles: HashSet<(usize, usize)> = rivers.iter().flatten().copied().collect();
+        let terrain: Vec<Vec<TerrainPoint>> = (0..height)
+            .into_par_iter()
+            .map(|y| {
+                (0..width)
+                    .map(|x| {
+                        let mut point = interpolate(map, (x as f64 + 0.5) / factor as f64 - 0.5, (y as f64 + 0.5) / factor as f64 - 0.5);
+                        let lake = point.biome == Biome::Lake;
+                        if let Some(&height) = pinned.get(&(x, y)) {
+                            point.elevation = height;
+                        } else if !lake {
+                            point.elevation = (point.elevation + self.detail(map, x, y, factor)).clamp(-1.0, 1.0);
+                        }
+                        point.biome = if lake {
+                            Biome::Lake
+                        } else if river_tiles.contains(&(x, y)) && point.elevation >= 0.0 {
+                            Biome::River
+                        } else {
+                            self.determine_biome(point.elevation, point.moisture, point.temperature)
+                        };
+                        point
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let mut fine = TerrainMap {
+            width,
+            height,
+            terrain,
+            rivers,
+            roads,
+            ..map.clone()
+        };
+        for city in &mut fine.cities {
+            (city.x, city.y) = centre((city.x, city.y));
+        }
+        for bridge in &mut fine.bridges {
+            (bridge.x, bridge.y) = centre((bridge.x, bridge.y));
+        }
+        for line in &mut fine.power_lines {
+            (line.from, line.to) = (centre(line.from), centre(line.to));
+        }
+        for metro in &mut fine.metro_areas {
+            metro.center = centre(metro.center);
+            metro.suburbs = metro.suburbs.iter().map(|&s| centre(s)).collect();
+        }
+        for poi in &mut fine.pois {
+            poi.position = centre(poi.position);
+        }
+        let half = (factor / 2) as f32;
+        for label in &mut fine.labels {
+            label.x = label.x * factor as f32 + half;
+            label.y = label.y * factor as f32 + half;
+        }
+        fine.regions = fine.traced_regions();
+        fine.coastlines = fine.traced_coastlines();
+        fine.smoothed = fine.smoothed_paths();
+        fine
+    }
+
+    /// The noise added to the elevation of fine tile (`x`, `y`).
+    fn detail(&self, map: &TerrainMap, x: usize, y: usize, factor: usize) -> f64 {
+        let (u, v) = (x as f64 / factor as f64, y as f64 / factor as f64);
+        OCTAVES
+            .iter()
+            .map(|&(frequency, weight)| {
+                let period = map.wrap_x.then_some(map.width as f64 * frequency);
+                let noise = self.detail_noise.sample_wrapped([u * frequency + 91.7, v * frequency + 23.3], period);
+                noise * weight
+            })
+            .sum::<f64>()
+            * DETAIL
+    }
+}
+
+/// `path` on the map `factor` times finer, straight from centre to
+/// centre, with heights interpolated along each step from `elevation`
+/// of the old tiles.
+fn fine_line(
+    map: &TerrainMap,
+    path: &[(usize, usize)],
+    factor: usize,
+    elevation: impl Fn((usize, usize)) -> f64,
+) -> (Vec<(usize, usize)>, Vec<f64>) {
+    let fine_width = (map.width * factor) as isize;
+    let half = factor / 2;
+    let mut tiles = Vec::new();
+    let mut heights = Vec::new();
+    if let Some(&(x, y)) = path.first() {
+        tiles.push((x * factor + half, y * factor + half));
+        heights.push(elevation((x, y)));
+    }
+    for step in path.windows(2) {
+        let ((x0, y0), (x1, y1)) = (step[0], step[1]);
+        // Across the seam of a wrapping map the step is still one tile
+        let dx = wrap_dx(x1 as f64 - x0 as f64, map.width, map.wrap_x) as isize;
+        let dy = y1 as isize - y0 as isize;
+        let (start_x, start_y) = ((x0 * factor + half) as isize, (y0 * factor + half) as isize);
+        let steps = dx.abs().max(dy.abs()) * factor as isize;
+        let (from, to) = (elevation((x0, y0)), elevation((x1, y1)));
+        for k in 1..=steps {
+            let t = k as f64 / steps as f64;
+            let fx = start_x + (dx as f64 * factor as f64 * t).round() as isize;
+            let fy = start_y + (dy as f64 * factor as f64 * t).round() as isize;
+            tiles.push((fx.rem_euclid(fine_width) as usize, fy as usize));
+            heights.push(from + (to - from) * t);
+        }
+    }
+    (tiles, heights)
+}
+
+/// The fields of `map` at (`u`, `v`) in tiles, interpolated between the
+/// four nearest tile centres; across the seam of a map that wraps. The
+/// biome is `Lake` where lakes weigh at least half, and otherwise left for
+/// the caller to classify.
+fn interpolate(map: &TerrainMap, u: f64, v: f64) -> TerrainPoint {
+    let v = v.clamp(0.0, (map.height - 1) as f64);
+    let u = if map.wrap_x { u.rem_euclid(map.width as f64) } else { u.clamp(0.0, (map.width - 1) as f64) };
+    let (x0, y0) = (u.floor() as usize, v.floor() as usize);
+    let (tx, ty) = (u - x0 as f64, v - y0 as f64);
+    let x1 = if map.wrap_x { (x0 + 1) % map.width } else { (x0 + 1).min(map.width - 1) };
+    let y1 = (y0 + 1).min(map.height - 1);
+    let at = |x: usize, y: usize| &map.terrain[y][x];
+    let mix = |field: fn(&TerrainPoint) -> f64| {
+        let top = field(at(x0, y0)) * (1.0 - tx) + field(at(x1, y0)) * tx;
+        let bottom = field(at(x0, y1)) * (1.0 - tx) + field(at(x1, y1)) * tx;
+        top * (1.0 - ty) + bottom * ty
+    };
+    let lake = mix(|p| if p.biome == Biome::Lake { 1.0 } else { 0.0 }) >= 0.5;
+    TerrainPoint {
+        elevation: mix(|p| p.elevation),
+        moisture: mix(|p| p.moisture),
+        temperature: mix(|p| p.temperature),
+        biome: if lake { Biome::Lake } else { at(x0, y0).biome },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::OnceLock;
+
+    use super::*;
+    use crate::terrain_generator::{test_map, Severity};
+
+    fn map() -> &'static TerrainMap {
+        test_map(8, 80, 60)
+    }
+
+    /// `map()` refined four times over
+    fn fine() -> &'static TerrainMap {
+        static FINE: OnceLock<TerrainMap> = OnceLock::new();
+        FINE.get_or_init(|| TerrainGenerator::new(8).refine(map(), 4))
+    }
+
+    #[test]
+    fn refined_maps_keep_their_cities_on_finer_ground() {
+        let (map, fine) = (map(), fine());
+        assert_eq!((fine.width, fine.height, fine.terrain.len(), fine.terrain[0].len()), (320, 240, 240, 320));
+        assert_eq!(fine.cities.len(), map.cities.len());
+        for (city, coarse) in fine.cities.iter().zip(&map.cities) {
+            assert_eq!((city.x, city.y), (coarse.x * 4 + 2, coarse.y * 4 + 2));
+            assert_eq!(fine.terrain[city.y][city.x].elevation, map.terrain[coarse.y][coarse.x].elevation);
+        }
+        let report = fine.validate();
+        assert!(report.problems.iter().all(|p| p.severity() != Severity::Error), "{}", report.display(fine));
+    }
+
+    #[test]
+    fn refined_roads_and_rivers_step_from_tile_to_neighbouring_tile() {
+        let fine = fine();
+        for path in fine.rivers.iter().chain(fine.roads.iter().map(|r| &r.path)) {
+            assert!(path.windows(2).all(|s| s[0].0.abs_diff(s[1].0) <= 1 && s[0].1.abs_diff(s[1].1) <= 1));
+        }
+    }
+
+    #[test]
+    fn about_as_much_land_with_coastlines_the_coarse_grid_could_not_draw() {
+        let (map, fine) = (map(), fine());
+        let land = |m: &TerrainMap| m.terrain.iter().flatten().filter(|p| p.elevation >= 0.0).count() as f64 / (m.width * m.height) as f64;
+        assert!((land(fine) - land(map)).abs() < 0.03);
+        let reshaped = (0..fine.height).flat_map(|y| (0..fine.width).map(move |x| (x, y))).any(|(x, y)| {
+            let coarse = map.terrain[y / 4][x / 4].elevation >= 0.0;
+            (fine.terrain[y][x].elevation >= 0.0) != coarse
+        });
+        assert!(reshaped);
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_detail() {
+        let generator = TerrainGenerator::new(8);
+        assert_eq!(generator.refine(map(), 4).terrain[100][100].elevation, fine().terrain[100][100].elevation);
+        assert_eq!(generator.refine(map(), 1).width, 80);
+    }
+}
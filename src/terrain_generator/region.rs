@@ -0,0 +1,347 @@
+//! Rerolling one rectangle of a finished map while keeping the rest.
+
+use std::collections::HashSet;
+
+use super::biome::Biome;
+use super::names::UsedNames;
+use super::progress::GenerationPhase;
+use super::types::{City, TerrainMap};
+use super::TerrainGenerator;
+
+/// A rectangle of tiles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Region {
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        x >= self.x && y >= self.y && x < self.x + self.width && y < self.y + self.height
+    }
+
+    /// The part of the region inside a `width` x `height` map, if any.
+    pub(super) fn clipped(self, width: usize, height: usize) -> Option<Region> {
+        let x1 = (self.x + self.width).min(width);
+        let y1 = (self.y + self.height).min(height);
+        (self.x < x1 && self.y < y1).then(|| Region {
+            width: x1 - self.x,
+            height: y1 - self.y,
+            ..self
+        })
+    }
+
+    /// Tiles from (x, y) to the nearest edge of the region.
+    fn inset(&self, x: usize, y: usize) -> usize {
+        (x - self.x)
+            .min(self.x + self.width - 1 - x)
+            .min(y - self.y)
+            .min(self.y + self.height - 1 - y)
+    }
+}
+
+/// What [`TerrainGenerator::regenerate_region`] rerolls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionReroll {
+    /// New elevation and climate (blended into the surroundings at the
+    /// edges), then new settlements to match
+    Terrain,
+    /// New cities on the same terrain, and roads to connect them
+    Settlements,
+}
+
+/// New cities keep at least this many tiles from the ones kept outside the
+/// region (half the spacing between generated towns)
+const KEPT_CITY_GAP: f64 = 20.0;
+
+impl TerrainGenerator {
+    /// Reroll `region` of `map` with this generator's seed and settings,
+    /// leaving everything outside it as it was. Rivers are traced again
+    /// across the whole map after a terrain reroll, since the new terrain
+    /// can move them downstream; roads into the region are replaced and
+    /// cities left without a road are joined to their nearest neighbour.
+    /// Returns false if the region lies outside the map.
+    pub fn regenerate_region(
+        &mut self,
+        map: &mut TerrainMap,
+        region: Region,
+        what: RegionReroll,
+    ) -> bool {
+        let Some(region) = region.clipped(map.width, map.height) else {
+            return false;
+        };
+        self.used_names = UsedNames::of_map(map);
+        self.wrap_x = map.wrap_x;
+        if what == RegionReroll::Terrain {
+            self.reroll_terrain(map, region);
+        }
+        self.reroll_settlements(map, region);
+        map.smoothed = map.smoothed_paths();
+        true
+    }
+
+    fn reroll_terrain(&mut self, map: &mut TerrainMap, region: Region) {
+        let (width, height) = (map.width, map.height);
+        // Fields for the whole map, so sea level and continents have the
+        // same scale as the map around the region
+        self.report_progress(GenerationPhase::Elevation, 0.0);
+        let elevations = self
+            .generate_elevation_field(width, height)
+            .expect("rerolls run without a cancellation token");
+        self.report_progress(GenerationPhase::Elevation, 1.0);
+
+        self.report_progress(GenerationPhase::Climate, 0.0);
+        // Weight of the new terrain: 0 on the region's edge, 1 from
+        // `margin` tiles in. The blend band wanders with noise so it doesn't
+        // leave straight seams along the rectangle
+        let margin = (region.width.min(region.height) as f64 / 5.0).max(2.0);
+        let weight = |x: usize, y: usize| {
+            let (nx, ny) = (x as f64 / 24.0, y as f64 / 24.0);
+            let wander = (self.detail_noise.sample([nx + 5.3, ny + 17.1]) + 0.5) * margin * 0.6;
+            let t = ((region.inset(x, y) as f64 + 0.5 - wander.max(0.0)) / margin).clamp(0.0, 1.0);
+            t * t * (3.0 - 2.0 * t)
+        };
+        let weights: Vec<Vec<f64>> = (region.y..region.y + region.height)
+            .map(|y| {
+                (region.x..region.x + region.width)
+                    .map(|x| weight(x, y))
+                    .collect()
+            })
+            .collect();
+        let mut blended: Vec<Vec<f64>> = map
+            .terrain
+            .iter()
+            .map(|row| row.iter().map(|p| p.elevation).collect())
+            .collect();
+        for y in region.y..region.y + region.height {
+            for x in region.x..region.x + region.width {
+                let w = weights[y - region.y][x - region.x];
+                blended[y][x] = blended[y][x] * (1.0 - w) + elevations[y][x] * w;
+            }
+        }
+        let moistures = self.generate_moisture_field(&blended);
+        for y in region.y..region.y + region.height {
+            for x in region.x..region.x + region.width {
+                let w = weights[y - region.y][x - region.x];
+                let elevation = blended[y][x];
+                let temperature = self.generate_temperature(x, y, width, height, elevation);
+                let point = &mut map.terrain[y][x];
+                point.elevation = elevation;
+                point.moisture = point.moisture * (1.0 - w) + moistures[y][x] * w;
+                point.temperature = point.temperature * (1.0 - w) + temperature * w;
+                point.biome =
+                    self.determine_biome(point.elevation, point.moisture, point.temperature);
+            }
+        }
+        self.report_progress(GenerationPhase::Climate, 1.0);
+
+        // Same as `rederive`: retrace without eroding again
+        self.report_progress(GenerationPhase::Rivers, 0.0);
+        for point in map.terrain.iter_mut().flatten() {
+            if matches!(point.biome, Biome::River | Biome::Lake) {
+                point.biome =
+                    self.determine_biome(point.elevation, point.moisture, point.temperature);
+            }
+        }
+        map.rivers = self.generate_hydrology(&mut map.terrain);
+        for &(x, y) in map.rivers.iter().flatten() {
+            let point = &mut map.terrain[y][x];
+            if point.biome != Biome::Lake {
+                point.biome = Biome::River;
+            }
+        }
+        self.report_progress(GenerationPhase::Rivers, 1.0);
+
+        // Labels outside the region keep their names
+        self.report_progress(GenerationPhase::Labels, 0.0);
+        let inside = |x: f32, y: f32| region.contains(x.max(0.0) as usize, y.max(0.0) as usize);
+        let (labels, _) = self.generate_labels(&map.terrain, &map.rivers);
+        map.labels.retain(|label| !inside(label.x, label.y));
+        map.labels
+            .extend(labels.into_iter().filter(|label| inside(label.x, label.y)));
+        map.regions = map.traced_regions();
+        map.prune_parks();
+        map.coastlines = map.traced_coastlines();
+        // And so do points of interest, unless the sea took their tile
+        let pois = self.points_of_interest_for(map);
+        let terrain = &map.terrain;
+        map.pois.retain(|poi| {
+            let (x, y) = poi.position;
+            !region.contains(x, y) && !terrain[y][x].biome.is_water()
+        });
+        map.pois.extend(pois.into_iter().filter(|poi| region.contains(poi.position.0, poi.position.1)));
+        self.report_progress(GenerationPhase::Labels, 1.0);
+    }
+
+    fn reroll_settlements(&mut self, map: &mut TerrainMap, region: Region) {
+        self.report_progress(GenerationPhase::Cities, 0.0);
+        let terrain = &map.terrain;
+        map.cities.retain(|city| {
+            !region.contains(city.x, city.y) && !terrain[city.y][city.x].biome.is_water()
+        });
+        map.prune_metro_areas();
+        map.prune_power_lines();
+        let kept = map.cities.len();
+        let candidates = self.generate_cities(&map.terrain, &map.rivers);
+        for city in candidates {
+            let clear = map.cities[..kept].iter().all(|other| {
+                let dx = other.x as f64 - city.x as f64;
+                let dy = other.y as f64 - city.y as f64;
+                (dx * dx + dy * dy).sqrt() >= KEPT_CITY_GAP
+            });
+            if region.contains(city.x, city.y) && clear {
+                map.cities.push(city);
+            }
+        }
+        self.report_progress(GenerationPhase::Cities, 1.0);
+
+        self.report_progress(GenerationPhase::Roads, 0.0);
+        let mut index = 0;
+        while index < map.roads.len() {
+            if map.roads[index]
+                .path
+                .iter()
+                .any(|&(x, y)| region.contains(x, y))
+            {
+                map.remove_road(index);
+            } else {
+                index += 1;
+            }
+        }
+        // Join every city no road reaches to its nearest neighbour,
+        // largest first so small towns can join the new roads
+        let mut order: Vec<usize> = (0..map.cities.len()).collect();
+        order.sort_by_key(|&i| std::cmp::Reverse(map.cities[i].population));
+        for (done, &i) in order.iter().enumerate() {
+            let city = map.cities[i].clone();
+            let reached: HashSet<(usize, usize)> = map
+                .roads
+                .iter()
+                .flat_map(|r| r.path.iter().copied())
+                .collect();
+            if !reached.contains(&(city.x, city.y)) {
+                if let Some(target) = nearest_other_city(&map.cities, i) {
+                    let road_type = if city.population > 100000 {
+                        "road"
+                    } else {
+                        "trail"
+                    };
+                    self.build_road(map, (city.x, city.y), target, road_type);
+                }
+            }
+            self.report_progress(GenerationPhase::Roads, done as f32 / order.len() as f32);
+        }
+        if self.settings.modern {
+            map.power_lines = map.strung_power_lines();
+        }
+        self.report_progress(GenerationPhase::Roads, 1.0);
+    }
+}
+
+fn nearest_other_city(cities: &[City], index: usize) -> Option<(usize, usize)> {
+    let city = &cities[index];
+    cities
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| i != index)
+        .map(|(_, other)| {
+            let dx = other.x as f64 - city.x as f64;
+            let dy = other.y as f64 - city.y as f64;
+            (other, dx * dx + dy * dy)
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(other, _)| (other.x, other.y))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::OnceLock;
+
+    use super::*;
+    use crate::terrain_generator::test_map;
+
+    const REGION: Region = Region {
+        x: 40,
+        y: 30,
+        width: 60,
+        height: 50,
+    };
+
+    fn original() -> &'static TerrainMap {
+        test_map(31, 160, 120)
+    }
+
+    /// The original map with the terrain of `REGION` rerolled, made once
+    fn rerolled() -> &'static TerrainMap {
+        static MAP: OnceLock<TerrainMap> = OnceLock::new();
+        MAP.get_or_init(|| {
+            let mut map = original().clone();
+            assert!(TerrainGenerator::new(99).regenerate_region(
+                &mut map,
+                REGION,
+                RegionReroll::Terrain
+            ));
+            map
+        })
+    }
+
+    #[test]
+    fn only_the_region_changes() {
+        let (original, map) = (original(), rerolled());
+        let mut changed = 0;
+        for y in 0..map.height {
+            for x in 0..map.width {
+                let (a, b) = (&original.terrain[y][x], &map.terrain[y][x]);
+                if REGION.contains(x, y) {
+                    changed += (a.elevation != b.elevation) as usize;
+                } else {
+                    assert_eq!(a.elevation, b.elevation);
+                    assert_eq!(a.moisture, b.moisture);
+                }
+            }
+        }
+        assert!(changed > REGION.width * REGION.height / 2);
+    }
+
+    #[test]
+    fn cities_outside_the_region_stay_unless_flooded() {
+        let map = rerolled();
+        for city in original()
+            .cities
+            .iter()
+            .filter(|c| !REGION.contains(c.x, c.y))
+        {
+            if !map.terrain[city.y][city.x].biome.is_water() {
+                assert!(map.find_city(&city.name).is_some());
+            }
+        }
+    }
+
+    #[test]
+    fn roads_stay_on_the_rerolled_land() {
+        let map = rerolled();
+        for road in &map.roads {
+            for &(x, y) in &road.path {
+                assert!(!map.terrain[y][x].biome.is_water());
+            }
+        }
+    }
+
+    #[test]
+    fn a_region_off_the_map_is_not_rerolled() {
+        let outside = Region {
+            x: 500,
+            y: 0,
+            width: 10,
+            height: 10,
+        };
+        let mut map = original().clone();
+        assert!(!TerrainGenerator::new(1).regenerate_region(
+            &mut map,
+            outside,
+            RegionReroll::Settlements
+        ));
+    }
+}
@@ -0,0 +1,288 @@
+//! A rough measure of how interesting a map is to play on, for picking
+//! the best of several seeds.
+//!
+//! Each criterion is scored from 0.0 to 1.0: an intricate coastline, a
+//! varied mix of land biomes, sheltered harbor sites, rivers that reach the
+//! sea, and land gathered into a few large masses rather than scattered
+//! specks. The road criterion needs cities and is left out of terrain-only
+//! maps. None of this is more than a heuristic; it is tuned to rank the
+//! default settings' maps the way a person leafing through them might.
+
+use std::fmt;
+
+use super::biome::Biome;
+use super::drainage::RiverOutlet;
+use super::network::NodeKind;
+use super::types::TerrainMap;
+
+/// Harbor sites closer together than this, in tiles, count once
+const HARBOR_SPACING: usize = 8;
+
+/// Harbor sites that earn full marks, per tile of the side of a square
+/// as large as the land: coasts grow with the land's span, not its area
+const HARBORS_PER_SPAN: f32 = 1.2;
+
+/// Land tiles per river for full marks
+const LAND_PER_RIVER: f32 = 1500.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MapScore {
+    /// Coast length against the perimeter of a round island of the same
+    /// area
+    pub coastline: f32,
+    /// Evenness of the land biome mix (normalized Shannon entropy)
+    pub biome_mix: f32,
+    /// Sheltered coastal sites, against `HARBORS_PER_SPAN`
+    pub harbors: f32,
+    /// Rivers ending in the sea, a lake or another river, against
+    /// `LAND_PER_RIVER`
+    pub rivers: f32,
+    /// Share of the land in the three largest landmasses
+    pub landmass: f32,
+    /// Share of cities on the largest road network; `None` without cities
+    pub roads: Option<f32>,
+}
+
+impl MapScore {
+    /// The criteria combined, 0 to 100.
+    pub fn total(&self) -> f32 {
+        let mut weighted = [
+            (self.coastline, 1.0),
+            (self.biome_mix, 1.0),
+            (self.harbors, 0.75),
+            (self.rivers, 1.0),
+            (self.landmass, 0.75),
+        ]
+        .to_vec();
+        if let Some(roads) = self.roads {
+            weighted.push((roads, 0.5));
+        }
+        let weights: f32 = weighted.iter().map(|(_, w)| w).sum();
+        100.0 * weighted.iter().map(|(score, w)| score * w).sum::<f32>() / weights
+    }
+}
+
+impl fmt::Display for MapScore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.0}/100 (coastline {:.2}, biomes {:.2}, harbors {:.2}, rivers {:.2}, landmass {:.2}",
+            self.total(),
+            self.coastline,
+            self.biome_mix,
+            self.harbors,
+            self.rivers,
+            self.landmass
+        )?;
+        if let Some(roads) = self.roads {
+            write!(f, ", roads {:.2}", roads)?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl TerrainMap {
+    /// Score the map (see [`MapScore`]). Works on terrain-only maps from
+    /// `generate_terrain`, without the road criterion.
+    pub fn score(&self) -> MapScore {
+        let (width, height) = (self.width, self.height);
+        if width == 0 || height == 0 {
+            return MapScore {
+                coastline: 0.0,
+                biome_mix: 0.0,
+                harbors: 0.0,
+                rivers: 0.0,
+                landmass: 0.0,
+                roads: None,
+            };
+        }
+        let land = |x: usize, y: usize| !self.terrain[y][x].biome.is_water();
+        let sea = |x: usize, y: usize| {
+            matches!(self.terrain[y][x].biome, Biome::Shore | Biome::Ocean | Biome::DeepOcean)
+        };
+        let neighbours = |x: usize, y: usize| {
+            [(0, -1), (-1, 0), (1, 0), (0, 1)].into_iter().filter_map(move |(dx, dy)| self.offset(x, y, dx, dy))
+        };
+
+        // Coastline: land tiles touching the sea
+        let mut land_tiles = 0;
+        let mut coast = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                if land(x, y) {
+                    land_tiles += 1;
+                    if neighbours(x, y).any(|(nx, ny)| sea(nx, ny)) {
+                        coast.push((x, y));
+                    }
+                }
+            }
+        }
+        let round = 2.0 * (std::f32::consts::PI * land_tiles as f32).sqrt();
+        let wiggle = coast.len() as f32 / round.max(1.0);
+        let coastline = (1.0 - (-(wiggle - 1.0).max(0.0) / 1.5).exp()).clamp(0.0, 1.0);
+
+        // Biome mix over the land only
+        let mut counts = std::collections::HashMap::new();
+        for point in self.terrain.iter().flatten().filter(|p| !p.biome.is_water()) {
+            *counts.entry(point.biome).or_insert(0usize) += 1;
+        }
+        let biome_mix = if counts.len() > 1 {
+            let entropy: f32 = counts
+                .values()
+                .map(|&count| {
+                    let p = count as f32 / land_tiles as f32;
+                    -p * p.ln()
+                })
+                .sum();
+            // Fewer than six biomes can't be much of a mix however even
+            entropy / (counts.len() as f32).ln() * (counts.len() as f32 / 6.0).min(1.0)
+        } else {
+            0.0
+        };
+
+        // Harbors: coast tiles in a bay, with water on a third to two
+        // thirds of the tiles around them, kept apart
+        let mut harbors: Vec<(usize, usize)> = Vec::new();
+        for &(x, y) in &coast {
+            let (mut water, mut around) = (0, 0);
+            for ny in y.saturating_sub(2)..=(y + 2).min(height - 1) {
+                for nx in x.saturating_sub(2)..=(x + 2).min(width - 1) {
+                    around += 1;
+                    if sea(nx, ny) {
+                        water += 1;
+                    }
+                }
+            }
+            let share = water as f32 / around as f32;
+            let apart = harbors
+                .iter()
+                .all(|&(hx, hy)| hx.abs_diff(x).max(hy.abs_diff(y)) >= HARBOR_SPACING);
+            if (0.33..=0.67).contains(&share) && apart {
+                harbors.push((x, y));
+            }
+        }
+        let wanted = (land_tiles as f32).sqrt() * HARBORS_PER_SPAN;
+        let harbors = (harbors.len() as f32 / wanted.max(1.0)).min(1.0);
+
+        // Rivers that drain somewhere
+        let drained = self
+            .river_flows()
+            .iter()
+            .filter(|f| matches!(f.outlet, RiverOutlet::Sea | RiverOutlet::Lake | RiverOutlet::River(_)))
+            .count();
+        let wanted = land_tiles as f32 / LAND_PER_RIVER;
+        let rivers = (drained as f32 / wanted.max(1.0)).min(1.0);
+
+        // Landmasses, 4-connected
+        let mut seen = vec![false; width * height];
+        let mut sizes = Vec::new();
+        for start in 0..width * height {
+            if seen[start] || !land(start % width, start / width) {
+                continue;
+            }
+            seen[start] = true;
+            let mut stack = vec![start];
+            let mut size = 0;
+            while let Some(tile) = stack.pop() {
+                size += 1;
+                for (nx, ny) in neighbours(tile % width, tile / width) {
+                    let next = ny * width + nx;
+                    if !seen[next] && land(nx, ny) {
+                        seen[next] = true;
+                        stack.push(next);
+                    }
+                }
+            }
+            sizes.push(size);
+        }
+        sizes.sort_unstable_by(|a, b| b.cmp(a));
+        let landmass = if land_tiles > 0 {
+            sizes.iter().take(3).sum::<usize>() as f32 / land_tiles as f32
+        } else {
+            0.0
+        };
+
+        let roads = (!self.cities.is_empty()).then(|| {
+            let network = self.road_network();
+            let largest = network
+                .connected_components()
+                .iter()
+                .map(|group| {
+                    group
+                        .iter()
+                        .filter(|&&node| matches!(network.nodes[node].kind, NodeKind::City(_)))
+                        .count()
+                })
+                .max()
+                .unwrap_or(0);
+            largest as f32 / self.cities.len() as f32
+        });
+
+        MapScore {
+            coastline,
+            biome_mix,
+            harbors,
+            rivers,
+            landmass,
+            roads,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain_generator::{test_map, GenerationPhase, TerrainGenerator};
+
+    /// The terrain of the 120x90 map of seed 3, before cities and roads
+    fn terrain() -> TerrainMap {
+        TerrainGenerator::new(3).generate_terrain(120, 90)
+    }
+
+    fn full() -> &'static TerrainMap {
+        test_map(3, 120, 90)
+    }
+
+    #[test]
+    fn terrain_scores_are_in_range_without_roads() {
+        let score = terrain().score();
+        assert!(score.roads.is_none());
+        for value in [
+            score.coastline,
+            score.biome_mix,
+            score.harbors,
+            score.rivers,
+            score.landmass,
+        ] {
+            assert!((0.0..=1.0).contains(&value), "{}", score);
+        }
+        assert!(score.total() > 0.0 && score.total() <= 100.0);
+    }
+
+    #[test]
+    fn finishing_scored_terrain_makes_the_full_map() {
+        // Best-of-N scores terrain first and finishes only the winner
+        let mut map = terrain();
+        TerrainGenerator::new(3).regenerate(&mut map, GenerationPhase::Cities);
+        assert_eq!(map.cities.len(), full().cities.len());
+        assert_eq!(map.roads.len(), full().roads.len());
+    }
+
+    #[test]
+    fn finished_maps_score_their_roads_and_the_same_coastline() {
+        let score = full().score();
+        assert!(score.roads.is_some_and(|r| r > 0.0 && r <= 1.0));
+        assert_eq!(score.coastline, terrain().score().coastline);
+    }
+
+    #[test]
+    fn a_map_all_sea_scores_nothing_for_its_land() {
+        let mut sea = full().clone();
+        for point in sea.terrain.iter_mut().flatten() {
+            point.biome = Biome::Ocean;
+        }
+        sea.rivers.clear();
+        let sea = sea.score();
+        assert_eq!((sea.coastline, sea.biome_mix, sea.landmass), (0.0, 0.0, 0.0));
+    }
+}
@@ -1,19 +1,24 @@
 use std::cmp::Ordering;
-use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
 
 use rand::Rng;
 
 use super::biome::Biome;
-use super::types::{Bridge, City, Road, TerrainPoint};
+use super::modern::MOTORWAY;
+use super::names::NameKind;
+use super::progress::GenerationPhase;
+use super::types::{
+    offset_tile, wrap_dx, Bridge, City, MetroArea, Road, SettlementWeights, TerrainMap, TerrainPoint,
+};
 use super::TerrainGenerator;
 
 /// Node in the pathfinding priority queue. Ordered by `f` (estimated total
 /// cost) so the BinaryHeap acts as a min-heap.
 #[derive(Copy, Clone, Eq, PartialEq)]
-struct PathState {
-    f: usize,
-    g: usize,
-    position: (usize, usize),
+pub(super) struct PathState {
+    pub(super) f: usize,
+    pub(super) g: usize,
+    pub(super) position: (usize, usize),
 }
 
 impl Ord for PathState {
@@ -28,9 +33,200 @@ impl PartialOrd for PathState {
     }
 }
 
+/// Min-priority queue of grid cells (by index, y * width + x) with
+/// decrease-key: a cell is queued at most once, and lowering its priority
+/// moves it up in place instead of queueing a stale copy.
+struct OpenSet {
+    /// Binary min-heap of (priority, cell)
+    heap: Vec<(usize, usize)>,
+    /// Where each cell sits in `heap`, or `usize::MAX` when it isn't queued
+    slots: Vec<usize>,
+}
+
+impl OpenSet {
+    fn new(cells: usize) -> Self {
+        OpenSet {
+            heap: Vec::new(),
+            slots: vec![usize::MAX; cells],
+        }
+    }
+
+    /// Queue `cell` with `priority`, or lower its priority if it is queued.
+    fn push_or_decrease(&mut self, cell: usize, priority: usize) {
+        let slot = match self.slots[cell] {
+            usize::MAX => {
+                self.heap.push((priority, cell));
+                self.heap.len() - 1
+            }
+            slot if priority < self.heap[slot].0 => {
+                self.heap[slot].0 = priority;
+                slot
+            }
+            _ => return,
+        };
+        self.slots[cell] = slot;
+        self.sift_up(slot);
+    }
+
+    /// The queued cell with the lowest priority.
+    fn pop(&mut self) -> Option<usize> {
+        let last = self.heap.len().checked_sub(1)?;
+        self.heap.swap(0, last);
+        let (_, cell) = self.heap.pop()?;
+        self.slots[cell] = usize::MAX;
+        if !self.heap.is_empty() {
+            self.slots[self.heap[0].1] = 0;
+            self.sift_down(0);
+        }
+        Some(cell)
+    }
+
+    fn sift_up(&mut self, mut slot: usize) {
+        while slot > 0 {
+            let parent = (slot - 1) / 2;
+            if self.heap[parent].0 <= self.heap[slot].0 {
+                break;
+            }
+            self.swap(slot, parent);
+            slot = parent;
+        }
+    }
+
+    fn sift_down(&mut self, mut slot: usize) {
+        loop {
+            let mut smallest = slot;
+            for child in [2 * slot + 1, 2 * slot + 2] {
+                if child < self.heap.len() && self.heap[child].0 < self.heap[smallest].0 {
+                    smallest = child;
+                }
+            }
+            if smallest == slot {
+                break;
+            }
+            self.swap(slot, smallest);
+            slot = smallest;
+        }
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        self.heap.swap(a, b);
+        self.slots[self.heap[a].1] = a;
+        self.slots[self.heap[b].1] = b;
+    }
+}
+
+/// Which cities roads join: cities are in one group when roads that share
+/// tiles join them. Kept up to date as roads are added.
+struct RoadGroups {
+    /// Union-find over the cities, then the roads in the order added
+    parent: Vec<usize>,
+    /// The city or road first seen on each tile
+    owner: HashMap<(usize, usize), usize>,
+}
+
+impl RoadGroups {
+    fn new(cities: &[City], roads: &[Road]) -> Self {
+        let mut groups = RoadGroups {
+            parent: (0..cities.len()).collect(),
+            owner: HashMap::new(),
+        };
+        for (index, city) in cities.iter().enumerate() {
+            groups.join_tile((city.x, city.y), index);
+        }
+        for road in roads {
+            groups.add_road(&road.path);
+        }
+        groups
+    }
+
+    fn root(&mut self, mut node: usize) -> usize {
+        while self.parent[node] != node {
+            self.parent[node] = self.parent[self.parent[node]];
+            node = self.parent[node];
+        }
+        node
+    }
+
+    /// Put `node` in the group of whatever was first on `tile`.
+    fn join_tile(&mut self, tile: (usize, usize), node: usize) {
+        let first = *self.owner.entry(tile).or_insert(node);
+        let (a, b) = (self.root(first), self.root(node));
+        self.parent[a] = b;
+    }
+
+    fn add_road(&mut self, path: &[(usize, usize)]) {
+        let node = self.parent.len();
+        self.parent.push(node);
+        for &tile in path {
+            self.join_tile(tile, node);
+        }
+    }
+
+    /// The group of the city at `index`.
+    fn group(&mut self, index: usize) -> usize {
+        self.root(index)
+    }
+}
+
+/// Label every tile with the land mass it belongs to: tiles share a label
+/// when a road could join them (see `terrain_move_cost`). Water tiles get
+/// `usize::MAX`. Lets road building skip searches that can't succeed, which
+/// would otherwise explore a whole island before giving up. With `wrap_x`,
+/// land across the east-west seam is the same land.
+pub(super) fn land_regions(terrain: &[Vec<TerrainPoint>], wrap_x: bool) -> Vec<usize> {
+    let (width, height) = (terrain[0].len(), terrain.len());
+    let walkable = |x: usize, y: usize| {
+        !matches!(
+            terrain[y][x].biome,
+            Biome::Ocean | Biome::DeepOcean | Biome::Lake | Biome::Shore
+        )
+    };
+    let mut regions = vec![usize::MAX; width * height];
+    let mut next_region = 0;
+    let mut stack = Vec::new();
+    for start in 0..width * height {
+        if regions[start] != usize::MAX || !walkable(start % width, start / width) {
+            continue;
+        }
+        regions[start] = next_region;
+        stack.push(start);
+        while let Some(cell) = stack.pop() {
+            let (x, y) = (cell % width, cell / width);
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    let Some((nx, ny)) = offset_tile(x, y, dx, dy, width, height, wrap_x) else {
+                        continue;
+                    };
+                    let next = ny * width + nx;
+                    if regions[next] == usize::MAX && walkable(nx, ny) {
+                        regions[next] = next_region;
+                        stack.push(next);
+                    }
+                }
+            }
+        }
+        next_region += 1;
+    }
+    regions
+}
+
+/// The x of each tile of `path` unrolled across the east-west seam when
+/// `wrap_x`: a step over the seam is a step of one, not most of `width`.
+fn unrolled_x(path: &[(usize, usize)], width: usize, wrap_x: bool) -> Vec<f32> {
+    let mut xs = Vec::with_capacity(path.len());
+    let mut x = path.first().map_or(0.0, |p| p.0 as f32);
+    for (i, p) in path.iter().enumerate() {
+        if i > 0 {
+            x += wrap_dx(p.0 as f64 - path[i - 1].0 as f64, width, wrap_x) as f32;
+        }
+        xs.push(x);
+    }
+    xs
+}
+
 /// Walk the came_from chain back from `end` and return the path in
 /// start-to-end order.
-fn reconstruct_path(
+pub(super) fn reconstruct_path(
     came_from: &HashMap<(usize, usize), (usize, usize)>,
     end: (usize, usize),
 ) -> Vec<(usize, usize)> {
@@ -44,8 +240,236 @@ fn reconstruct_path(
     path
 }
 
+/// Travel cost of one step from `from` onto `to` for roads and routes, or
+/// `None` if `to` can't be walked on (open water and lakes; rivers can be
+/// crossed). Flat terrain is cheap, climbs and rough biomes are expensive.
+pub(super) fn terrain_move_cost(
+    from: &TerrainPoint,
+    to: &TerrainPoint,
+    is_diagonal: bool,
+) -> Option<usize> {
+    // Cannot cross oceans or lakes
+    if matches!(
+        to.biome,
+        Biome::Ocean | Biome::DeepOcean | Biome::Lake | Biome::Shore
+    ) {
+        return None;
+    }
+
+    let mut move_cost = if is_diagonal { 14 } else { 10 };
+
+    // Heavy penalty for elevation changes (roads prefer flat terrain)
+    let elevation_change = (to.elevation - from.elevation).abs();
+    move_cost += (elevation_change * 100.0) as usize;
+
+    // Additional terrain-based costs
+    match to.biome {
+        Biome::River => move_cost *= RIVER_COST, // Rivers are expensive to cross (bridges needed)
+        Biome::Mountains => move_cost *= 8, // Mountains are very hard to cross
+        Biome::SnowPeaks => move_cost *= 10, // Snow peaks are nearly impassable
+        Biome::Hills => move_cost *= 2, // Hills are moderately difficult
+        Biome::Swamp => move_cost *= 3, // Swamps are difficult
+        Biome::Forest => move_cost = (move_cost as f32 * 1.5) as usize, // Forests slow travel
+        _ => {}
+    }
+    Some(move_cost)
+}
+
+/// Times the cost of a step onto a river, for the bridge it needs
+const RIVER_COST: usize = 5;
+
+/// Tiles from a crossing already made within which roads cross by it
+/// rather than make another, so roads gather there and the towns beside
+/// it become bridge towns
+const CROSSING_REACH: usize = 5;
+
+/// Trails wade across a river with fewer tiles than this above the
+/// crossing instead of bridging it
+pub const FORD_REACH: usize = 12;
+
+/// The river crossings of a road network, for new roads to share.
+#[derive(Debug, Clone)]
+pub(super) struct Crossings {
+    /// Tiles of river above each river tile, the most of any river
+    /// through it
+    reach: HashMap<(usize, usize), usize>,
+    /// Tiles roads cross rivers at, with the bridge there, or `None` for
+    /// a ford
+    made: HashMap<(usize, usize), Option<Bridge>>,
+}
+
+impl Crossings {
+    /// `rivers`, with nothing crossing them yet.
+    pub(super) fn new(rivers: &[Vec<(usize, usize)>]) -> Self {
+        let mut reach = HashMap::new();
+        for river in rivers {
+            for (above, &tile) in river.iter().enumerate() {
+                let most = reach.entry(tile).or_insert(above);
+                *most = (*most).max(above);
+            }
+        }
+        Crossings { reach, made: HashMap::new() }
+    }
+
+    /// The rivers of `map` and the bridges and fords its roads cross by.
+    pub(super) fn of_map(map: &TerrainMap) -> Self {
+        let mut crossings = Crossings::new(&map.rivers);
+        for road in &map.roads {
+            crossings.made.extend(road.fords.iter().map(|&at| (at, None)));
+        }
+        crossings.made.extend(map.bridges.iter().map(|b| ((b.x, b.y), Some(b.clone()))));
+        crossings
+    }
+}
+
+/// Random sites each city chooses the best of
+const SITE_SAMPLES: usize = 60;
+
+/// Closest two cities may ever be, in tiles, whatever the weights
+const MIN_CITY_GAP: f64 = 5.0;
+
+/// What a tile offers a city, each feature from 0.0 (nothing) to 1.0.
+/// `score` weighs them with the map's `SettlementWeights`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(super) struct SiteFeatures {
+    /// Falls off over 4 tiles from the sea
+    pub coast: f64,
+    /// Falls off over 4 tiles from a tile two rivers share
+    pub river_confluence: f64,
+    /// Falls off over 5 tiles from a river or lake
+    pub fresh_water: f64,
+    /// 1.0 where the steepest step to a neighbour is level, 0.0 from
+    /// `STEEP` up
+    pub flat_land: f64,
+    /// Share of farmland (plains), timber (forest) and ore (hills or
+    /// mountains) within 4 tiles
+    pub resources: f64,
+}
+
+/// Elevation step between neighbours that counts as no flat land at all
+const STEEP: f64 = 0.04;
+
+impl SiteFeatures {
+    /// The site's score before crowding: the weighted sum of its features.
+    pub(super) fn score(&self, weights: &SettlementWeights) -> f64 {
+        self.coast * weights.coast as f64
+            + self.river_confluence * weights.river_confluence as f64
+            + self.fresh_water * weights.fresh_water as f64
+            + self.flat_land * weights.flat_land as f64
+            + self.resources * weights.resources as f64
+    }
+
+    /// Features of every tile, row by row, looking across the east-west
+    /// seam when `wrap_x`.
+    pub(super) fn grid(
+        terrain: &[Vec<TerrainPoint>],
+        rivers: &[Vec<(usize, usize)>],
+        wrap_x: bool,
+    ) -> Vec<SiteFeatures> {
+        let height = terrain.len();
+        let width = terrain.first().map_or(0, Vec::len);
+        let biome = |x: usize, y: usize| terrain[y][x].biome;
+
+        let sea = proximity(width, height, 4, wrap_x, |x, y| {
+            matches!(biome(x, y), Biome::Ocean | Biome::DeepOcean | Biome::Shore)
+        });
+        let fresh = proximity(width, height, 5, wrap_x, |x, y| matches!(biome(x, y), Biome::River | Biome::Lake));
+        let mut rivers_through = vec![0u8; width * height];
+        for river in rivers {
+            let tiles: HashSet<&(usize, usize)> = river.iter().collect();
+            for &(x, y) in tiles {
+                if x < width && y < height {
+                    rivers_through[y * width + x] = rivers_through[y * width + x].saturating_add(1);
+                }
+            }
+        }
+        let confluence = proximity(width, height, 4, wrap_x, |x, y| rivers_through[y * width + x] >= 2);
+
+        let mut features = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let elevation = terrain[y][x].elevation;
+                let mut steepest = 0.0f64;
+                for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+                    if let Some((nx, ny)) = offset_tile(x, y, dx, dy, width, height, wrap_x) {
+                        steepest = steepest.max((terrain[ny][nx].elevation - elevation).abs());
+                    }
+                }
+
+                let (mut farmland, mut timber, mut ore) = (false, false, false);
+                for dy in -4..=4 {
+                    for dx in -4..=4 {
+                        let Some((nx, ny)) = offset_tile(x, y, dx, dy, width, height, wrap_x) else {
+                            continue;
+                        };
+                        match biome(nx, ny) {
+                            Biome::Plains => farmland = true,
+                            Biome::Forest => timber = true,
+                            Biome::Hills | Biome::Mountains => ore = true,
+                            _ => {}
+                        }
+                    }
+                }
+
+                let i = y * width + x;
+                features.push(SiteFeatures {
+                    coast: sea[i],
+                    river_confluence: confluence[i],
+                    fresh_water: fresh[i],
+                    flat_land: 1.0 - (steepest / STEEP).min(1.0),
+                    resources: [farmland, timber, ore].iter().filter(|&&b| b).count() as f64 / 3.0,
+                });
+            }
+        }
+        features
+    }
+}
+
+/// For every tile, 1.0 on a tile where `source` holds, falling linearly to
+/// 0.0 `reach` steps (4-connected, across the seam when `wrap_x`) away.
+fn proximity(
+    width: usize,
+    height: usize,
+    reach: usize,
+    wrap_x: bool,
+    source: impl Fn(usize, usize) -> bool,
+) -> Vec<f64> {
+    let mut dist = vec![usize::MAX; width * height];
+    let mut queue = VecDeque::new();
+    for y in 0..height {
+        for x in 0..width {
+            if source(x, y) {
+                dist[y * width + x] = 0;
+                queue.push_back((x, y));
+            }
+        }
+    }
+    while let Some((x, y)) = queue.pop_front() {
+        let d = dist[y * width + x] + 1;
+        if d >= reach {
+            continue;
+        }
+        for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+            let Some((nx, ny)) = offset_tile(x, y, dx, dy, width, height, wrap_x) else {
+                continue;
+            };
+            if dist[ny * width + nx] == usize::MAX {
+                dist[ny * width + nx] = d;
+                queue.push_back((nx, ny));
+            }
+        }
+    }
+    dist.iter()
+        .map(|&d| (1.0 - d as f64 / reach as f64).max(0.0))
+        .collect()
+}
+
 impl TerrainGenerator {
-    pub(super) fn generate_cities(&mut self, terrain: &[Vec<TerrainPoint>]) -> Vec<City> {
+    pub(super) fn generate_cities(
+        &mut self,
+        terrain: &[Vec<TerrainPoint>],
+        rivers: &[Vec<(usize, usize)>],
+    ) -> Vec<City> {
         let mut cities = Vec::new();
 
         // Handle 0% case - no cities at all
@@ -53,16 +477,24 @@ impl TerrainGenerator {
             return cities;
         }
 
+        // A symmetric map only sites cities in the part the rest is copied
+        // from, and copies each to the other parts
+        let symmetry = self.city_symmetry();
+        let (width, height) = (terrain[0].len(), terrain.len());
+
         // First, find all valid land tiles for city placement. Cities can sit
         // on any stable land biome, including the coast (coastal cities are
         // common) - the biome match itself guarantees we're not in water.
         let mut valid_positions = Vec::new();
-        for y in 2..terrain.len() - 2 {
-            for x in 2..terrain[0].len() - 2 {
+        let inner_rows = terrain.len().saturating_sub(4);
+        for (y, row) in terrain.iter().enumerate().skip(2).take(inner_rows) {
+            let inner = row.len().saturating_sub(4);
+            for (x, point) in row.iter().enumerate().skip(2).take(inner) {
                 if matches!(
-                    terrain[y][x].biome,
+                    point.biome,
                     Biome::Plains | Biome::Hills | Biome::Forest | Biome::Desert | Biome::Beach
-                ) {
+                ) && symmetry.is_source(x, y, width, height)
+                {
                     valid_positions.push((x, y));
                 }
             }
@@ -73,7 +505,7 @@ impl TerrainGenerator {
         }
 
         // Scale city counts based on settings and available land
-        let land_factor = valid_positions.len() as f32 / (terrain.len() * terrain[0].len()) as f32;
+        let land_factor = (valid_positions.len() * symmetry.sides()) as f32 / (width * height) as f32;
 
         // Major cities: 0-10 based on density and available land
         let num_major_cities = if self.settings.city_density < 0.1 {
@@ -97,8 +529,6 @@ impl TerrainGenerator {
             base.min(70)
         };
 
-        let mut placed_positions = Vec::new();
-
         // Generate city populations following Zipf's law for major cities
         let base_population = 500000;
         let mut populations: Vec<u32> = Vec::new();
@@ -109,133 +539,120 @@ impl TerrainGenerator {
         }
         // Medium cities
         for _ in 0..num_medium_cities {
-            populations.push(self.rng.gen_range(50000..150000));
+            populations.push(self.rngs.cities.gen_range(50000..150000));
         }
         // Small towns
         for _ in 0..num_towns {
-            populations.push(self.rng.gen_range(5000..30000));
+            populations.push(self.rngs.cities.gen_range(5000..30000));
         }
 
-        // Place cities on suitable terrain with better spacing
-        for (idx, pop) in populations.iter().enumerate() {
-            let mut attempts = 0;
-            let is_major = idx < num_major_cities;
-            let is_medium = idx < (num_major_cities + num_medium_cities);
-
-            while attempts < 150 && !valid_positions.is_empty() {
-                // Pick from valid land positions
-                let pos_idx = self.rng.gen_range(0..valid_positions.len());
-                let (x, y) = valid_positions[pos_idx];
-
-                let point = &terrain[y][x];
-
-                // Cities prefer certain terrain types
-                let suitable = match point.biome {
-                    Biome::Plains => true,
-                    Biome::Beach => is_major || self.rng.gen_bool(0.7), // Major cities like coasts
-                    Biome::Hills => self.rng.gen_bool(0.5),
-                    Biome::Forest => self.rng.gen_bool(0.2),
-                    _ => false,
-                };
-
-                if !suitable {
-                    attempts += 1;
-                    continue;
-                }
-
-                // Much larger minimum distances for better distribution
-                let mut min_dist = if is_major {
-                    100.0 // Major cities need LOTS of space
-                } else if is_medium {
-                    60.0 // Medium cities need good spacing
-                } else {
-                    40.0 // Towns should also be well-spaced
-                };
+        let features = SiteFeatures::grid(terrain, rivers, self.wrap_x);
+        let weights = self.settings.settlement_weights;
+        let wrap_x = self.wrap_x;
+        let too_close = |(ax, ay): (usize, usize), (bx, by): (usize, usize)| {
+            let dx = wrap_dx(ax as f64 - bx as f64, width, wrap_x);
+            let dy = ay as f64 - by as f64;
+            (dx * dx + dy * dy).sqrt() < MIN_CITY_GAP
+        };
 
-                // Check for grid alignment and minimum distances
-                let mut too_close = false;
-                let mut grid_aligned = false;
+        // Each city takes the best scoring of a random sample of sites, so
+        // the best sites fill first without every map looking the same. On a
+        // symmetric map each site stands for as many cities as it has copies
+        for (idx, pop) in populations.iter().enumerate().step_by(symmetry.sides()) {
+            // Bigger cities keep others further away
+            let spacing = if idx < num_major_cities {
+                100.0
+            } else if idx < num_major_cities + num_medium_cities {
+                60.0
+            } else {
+                40.0
+            };
 
-                for (i, &(cx, cy)) in placed_positions.iter().enumerate() {
-                    let dx = x as f64 - cx as f64;
-                    let dy = y as f64 - cy as f64;
+            let mut best: Option<((usize, usize), f64)> = None;
+            for _ in 0..SITE_SAMPLES {
+                let (x, y) = valid_positions[self.rngs.cities.gen_range(0..valid_positions.len())];
+                let mut crowding = 0.0;
+                let mut blocked = false;
+                for city in &cities {
+                    let dx = wrap_dx(x as f64 - city.x as f64, width, self.wrap_x);
+                    let dy = y as f64 - city.y as f64;
                     let dist = (dx * dx + dy * dy).sqrt();
-
-                    // Prevent cities from lining up on same latitude/longitude
-                    if (dx.abs() < 3.0 || dy.abs() < 3.0) && dist < 40.0 {
-                        grid_aligned = true; // Too aligned with existing city
-                        break;
-                    }
-
-                    // Special case: allow 1-2 towns near major cities (suburbs)
-                    if !is_major && !is_medium && i < num_major_cities {
-                        // Towns can be closer to major cities (suburbs)
-                        if dist < 6.0 {
-                            too_close = true; // But not too close
-                            break;
-                        } else if dist < 12.0 && self.rng.gen_bool(0.3) {
-                            // 30% chance to allow suburb placement
-                            min_dist = 8.0;
-                        }
-                    }
-
-                    if dist < min_dist {
-                        too_close = true;
-                        break;
-                    }
+                    blocked |= dist < MIN_CITY_GAP;
+                    crowding += (1.0 - dist / spacing).max(0.0);
                 }
-
-                // Add some offset to prevent grid patterns
-                if grid_aligned && attempts < 100 {
-                    attempts += 1;
+                // Copies of a site near the middle mustn't crowd each other
+                let images = symmetry.images(x, y, width, height);
+                blocked |= images.iter().enumerate().any(|(i, &a)| images[i + 1..].iter().any(|&b| too_close(a, b)));
+                if blocked {
                     continue;
                 }
+                let score = features[y * width + x].score(&weights) - weights.distance_penalty as f64 * crowding;
+                if best.is_none_or(|(_, best)| score > best) {
+                    best = Some(((x, y), score));
+                }
+            }
 
-                if !too_close {
+            // Nowhere left that isn't crowded out
+            if let Some(((x, y), _)) = best.filter(|&(_, score)| score >= 0.0) {
+                for (x, y) in symmetry.images(x, y, width, height) {
                     cities.push(City {
                         x,
                         y,
                         name: self.generate_city_name(cities.len()),
                         population: *pop,
                     });
-                    placed_positions.push((x, y));
-                    break;
                 }
-                attempts += 1;
             }
+            self.report_progress(GenerationPhase::Cities, idx as f32 / populations.len() as f32);
         }
 
         cities
     }
 
-    /// Collect bridges where a road path crosses a river, appending them to
-    /// the map-wide bridge list and returning the ones for this road.
-    fn detect_bridges(
+    /// The crossings of a road of `road_type` along `path` over rivers:
+    /// the bridges it crosses by, with new ones appended to the map-wide
+    /// list, and the fords a trail wades across rivers within `FORD_REACH`
+    /// of their source by. Tiles already bridged keep their bridge, and a
+    /// larger road bridges a ford.
+    pub(super) fn detect_crossings(
         &mut self,
         path: &[(usize, usize)],
-        river_points: &HashSet<(usize, usize)>,
+        road_type: &str,
+        crossings: &mut Crossings,
         terrain: &[Vec<TerrainPoint>],
         all_bridges: &mut Vec<Bridge>,
-    ) -> Vec<Bridge> {
+    ) -> (Vec<Bridge>, Vec<(usize, usize)>) {
         let mut bridges = Vec::new();
+        let mut fords = Vec::new();
         for &(x, y) in path {
-            if river_points.contains(&(x, y)) || terrain[y][x].biome == Biome::River {
+            let reach = crossings.reach.get(&(x, y)).copied();
+            if reach.is_none() && terrain[y][x].biome != Biome::River {
+                continue;
+            }
+            if let Some(Some(bridge)) = crossings.made.get(&(x, y)) {
+                bridges.push(bridge.clone());
+            } else if road_type == "trail" && reach.is_some_and(|above| above < FORD_REACH) {
+                fords.push((x, y));
+                crossings.made.insert((x, y), None);
+            } else {
                 let bridge = Bridge {
                     x,
                     y,
                     name: self.generate_bridge_name(all_bridges.len()),
                 };
+                crossings.made.insert((x, y), Some(bridge.clone()));
                 bridges.push(bridge.clone());
                 all_bridges.push(bridge);
             }
         }
-        bridges
+        (bridges, fords)
     }
 
     pub(super) fn generate_roads(
         &mut self,
         terrain: &[Vec<TerrainPoint>],
         cities: &[City],
+        metro_areas: &[MetroArea],
         rivers: &[Vec<(usize, usize)>],
     ) -> (Vec<Road>, Vec<Bridge>) {
         let mut roads = Vec::new();
@@ -245,13 +662,15 @@ impl TerrainGenerator {
             return (roads, all_bridges);
         }
 
-        // Create a set of river points for quick lookup
-        let mut river_points = HashSet::new();
-        for river in rivers {
-            for &point in river {
-                river_points.insert(point);
-            }
-        }
+        // Where the rivers are, and where roads cross them so far
+        let mut crossings = Crossings::new(rivers);
+
+        // Cities on different land masses can't be joined by road
+        let regions = land_regions(terrain, self.wrap_x);
+        let width = terrain[0].len();
+        let same_land = |(x1, y1): (usize, usize), (x2, y2): (usize, usize)| {
+            regions[y1 * width + x1] == regions[y2 * width + x2]
+        };
 
         // Track which cities are connected and existing road points for reuse
         let mut connected_cities = vec![false; cities.len()];
@@ -266,13 +685,13 @@ impl TerrainGenerator {
             let mut edges = Vec::new();
             for i in 0..major_count {
                 for j in i + 1..major_count {
-                    let dx = cities[i].x as f64 - cities[j].x as f64;
+                    let dx = wrap_dx(cities[i].x as f64 - cities[j].x as f64, width, self.wrap_x);
                     let dy = cities[i].y as f64 - cities[j].y as f64;
                     let dist = (dx * dx + dy * dy).sqrt();
                     edges.push((dist, i, j));
                 }
             }
-            edges.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+            edges.sort_by(|a, b| a.0.total_cmp(&b.0));
 
             // Build MST using Kruskal's algorithm
             let mut union_find = (0..major_count).collect::<Vec<_>>();
@@ -298,9 +717,28 @@ impl TerrainGenerator {
             }
         }
 
+        // Suburbs are joined to their metro area's center by spokes
+        let city_at = |tile: (usize, usize)| cities.iter().position(|c| (c.x, c.y) == tile);
+        let spokes: Vec<(usize, usize)> = metro_areas
+            .iter()
+            .filter_map(|metro| Some((city_at(metro.center)?, &metro.suburbs)))
+            .flat_map(|(center, suburbs)| suburbs.iter().filter_map(move |&s| Some((center, city_at(s)?))))
+            .collect();
+
+        // Progress: one step per highway, per spoke, per remaining city and
+        // per wilderness trail attempt
+        let total_steps = (mst_edges.len() + spokes.len() + 2 * cities.len()) as f32;
+        let mut steps_done = 0;
+
         // Step 2: Build main highways along MST edges
         for (i, j) in mst_edges {
-            let path = self.find_path(terrain, cities[i].x, cities[i].y, cities[j].x, cities[j].y);
+            self.report_progress(GenerationPhase::Roads, steps_done as f32 / total_steps);
+            steps_done += 1;
+            if !same_land((cities[i].x, cities[i].y), (cities[j].x, cities[j].y)) {
+                continue;
+            }
+            let path =
+                self.find_path(terrain, &crossings, cities[i].x, cities[i].y, cities[j].x, cities[j].y);
             if !path.is_empty() {
                 connected_cities[i] = true;
                 connected_cities[j] = true;
@@ -310,19 +748,57 @@ impl TerrainGenerator {
                     road_network.entry(point).or_default().push(roads.len());
                 }
 
-                let bridges = self.detect_bridges(&path, &river_points, terrain, &mut all_bridges);
+                // In the modern era, as motorways
+                let road_type = if self.settings.modern { MOTORWAY } else { "highway" };
+                let (bridges, fords) =
+                    self.detect_crossings(&path, road_type, &mut crossings, terrain, &mut all_bridges);
+                let name = if self.settings.modern {
+                    self.generate_motorway_name()
+                } else {
+                    self.generate_road_name(roads.len(), |d| format!("{} Highway", d))
+                };
 
                 roads.push(Road {
                     path,
-                    name: format!("{} Highway", self.generate_road_name(roads.len())),
-                    road_type: "highway".to_string(),
+                    name,
+                    road_type: road_type.to_string(),
                     bridges,
+                    fords,
+                });
+            }
+        }
+
+        // Step 2b: Spokes from metro centers out to their suburbs
+        for (center, suburb) in spokes {
+            self.report_progress(GenerationPhase::Roads, steps_done as f32 / total_steps);
+            steps_done += 1;
+            let (from, to) = (&cities[center], &cities[suburb]);
+            if !same_land((from.x, from.y), (to.x, to.y)) {
+                continue;
+            }
+            let path = self.find_path(terrain, &crossings, from.x, from.y, to.x, to.y);
+            if !path.is_empty() {
+                connected_cities[center] = true;
+                connected_cities[suburb] = true;
+                for &point in &path {
+                    road_network.entry(point).or_default().push(roads.len());
+                }
+                let (bridges, fords) =
+                    self.detect_crossings(&path, "road", &mut crossings, terrain, &mut all_bridges);
+                roads.push(Road {
+                    path,
+                    name: self.used_names.claim(NameKind::Road, format!("{} Road", to.name)),
+                    road_type: "road".to_string(),
+                    bridges,
+                    fords,
                 });
             }
         }
 
         // Step 3: Connect remaining cities, trying to create Y-junctions by connecting to existing roads
         for i in 0..cities.len() {
+            self.report_progress(GenerationPhase::Roads, steps_done as f32 / total_steps);
+            steps_done += 1;
             if !connected_cities[i] {
                 // Try to find the nearest point on an existing road
                 let mut best_connection = None;
@@ -333,7 +809,7 @@ impl TerrainGenerator {
                 // distance ties by coordinate to keep generation
                 // deterministic for a given seed.
                 for &(rx, ry) in road_network.keys() {
-                    let dx = cities[i].x as f64 - rx as f64;
+                    let dx = wrap_dx(cities[i].x as f64 - rx as f64, width, self.wrap_x);
                     let dy = cities[i].y as f64 - ry as f64;
                     let dist = (dx * dx + dy * dy).sqrt();
 
@@ -353,7 +829,7 @@ impl TerrainGenerator {
                 if best_connection.is_none() {
                     for j in 0..cities.len() {
                         if i != j && connected_cities[j] {
-                            let dx = cities[i].x as f64 - cities[j].x as f64;
+                            let dx = wrap_dx(cities[i].x as f64 - cities[j].x as f64, width, self.wrap_x);
                             let dy = cities[i].y as f64 - cities[j].y as f64;
                             let dist = (dx * dx + dy * dy).sqrt();
 
@@ -371,7 +847,7 @@ impl TerrainGenerator {
                     let mut min_dist = f64::MAX;
                     for j in 0..cities.len() {
                         if i != j {
-                            let dx = cities[i].x as f64 - cities[j].x as f64;
+                            let dx = wrap_dx(cities[i].x as f64 - cities[j].x as f64, width, self.wrap_x);
                             let dy = cities[i].y as f64 - cities[j].y as f64;
                             let dist = (dx * dx + dy * dy).sqrt();
                             if dist < min_dist {
@@ -383,8 +859,11 @@ impl TerrainGenerator {
                     best_connection = Some((cities[nearest].x, cities[nearest].y, false));
                 }
 
-                if let Some((target_x, target_y, is_road_junction)) = best_connection {
-                    let path = self.find_path(terrain, cities[i].x, cities[i].y, target_x, target_y);
+                let reachable = best_connection
+                    .filter(|&(x, y, _)| same_land((cities[i].x, cities[i].y), (x, y)));
+                if let Some((target_x, target_y, is_road_junction)) = reachable {
+                    let path =
+                        self.find_path(terrain, &crossings, cities[i].x, cities[i].y, target_x, target_y);
                     if !path.is_empty() {
                         connected_cities[i] = true;
 
@@ -393,43 +872,104 @@ impl TerrainGenerator {
                             road_network.entry(point).or_default().push(roads.len());
                         }
 
-                        let bridges =
-                            self.detect_bridges(&path, &river_points, terrain, &mut all_bridges);
-
                         let road_type = if cities[i].population > 100000 {
                             "road"
                         } else {
                             "trail"
                         };
-
-                        let road_name = if is_road_junction {
-                            format!("{} Branch", self.generate_road_name(roads.len()))
+                        let (bridges, fords) = self.detect_crossings(
+                            &path,
+                            road_type,
+                            &mut crossings,
+                            terrain,
+                            &mut all_bridges,
+                        );
+
+                        let kind = if is_road_junction {
+                            "Branch"
+                        } else if road_type == "trail" {
+                            "Trail"
                         } else {
-                            format!(
-                                "{} {}",
-                                self.generate_road_name(roads.len()),
-                                if road_type == "trail" { "Trail" } else { "Road" }
-                            )
+                            "Road"
                         };
+                        let road_name =
+                            self.generate_road_name(roads.len(), |d| format!("{} {}", d, kind));
 
                         roads.push(Road {
                             path,
                             name: road_name,
                             road_type: road_type.to_string(),
                             bridges,
+                            fords,
                         });
                     }
                 }
             }
         }
 
+        // Step 4: Every city reachable by road from the others on its land.
+        // Join the nearest two cities of road groups still apart, until
+        // each land mass has one group or no path is left to try. Groups
+        // only merge, so the pairs are tried once each, nearest first
+        let mut groups = RoadGroups::new(cities, &roads);
+        let mut pairs = Vec::new();
+        for (i, a) in cities.iter().enumerate() {
+            for (j, b) in cities.iter().enumerate().skip(i + 1) {
+                if same_land((a.x, a.y), (b.x, b.y)) {
+                    let dx = wrap_dx(a.x as f64 - b.x as f64, width, self.wrap_x);
+                    pairs.push((dx.hypot(a.y as f64 - b.y as f64), i, j));
+                }
+            }
+        }
+        // Stable, so equally near pairs keep their order
+        pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+        for (_, i, j) in pairs {
+            if groups.group(i) == groups.group(j) {
+                continue;
+            }
+            let path = self.find_path(terrain, &crossings, cities[i].x, cities[i].y, cities[j].x, cities[j].y);
+            if path.is_empty() {
+                continue;
+            }
+            for &point in &path {
+                road_network.entry(point).or_default().push(roads.len());
+            }
+            groups.add_road(&path);
+            let (bridges, fords) = self.detect_crossings(&path, "road", &mut crossings, terrain, &mut all_bridges);
+            roads.push(Road {
+                path,
+                name: self.generate_road_name(roads.len(), |d| format!("{} Road", d)),
+                road_type: "road".to_string(),
+                bridges,
+                fords,
+            });
+        }
+
+        // Highways off the map toward lands beyond it
+        for path in self.edge_road_paths(terrain, &crossings, cities) {
+            let (bridges, fords) =
+                self.detect_crossings(&path, "highway", &mut crossings, terrain, &mut all_bridges);
+            let beyond = self.generate_city_name(cities.len() + roads.len());
+            roads.push(Road {
+                path,
+                name: self.generate_road_name(roads.len(), |d| {
+                    format!("{} Highway (to {})", d, beyond)
+                }),
+                road_type: "highway".to_string(),
+                bridges,
+                fords,
+            });
+        }
+
         // Add some partial roads from cities that just go into the wilderness
         for i in 0..cities.len() {
-            if self.rng.gen_bool(0.3) {
+            self.report_progress(GenerationPhase::Roads, steps_done as f32 / total_steps);
+            steps_done += 1;
+            if self.rngs.roads.gen_bool(0.3) {
                 // 30% chance for each city to have an extra road
                 // Pick a random direction and distance
-                let angle = self.rng.gen_range(0.0..std::f64::consts::TAU);
-                let distance = self.rng.gen_range(15.0..30.0);
+                let angle = self.rngs.roads.gen_range(0.0..std::f64::consts::TAU);
+                let distance = self.rngs.roads.gen_range(15.0..30.0);
 
                 let target_x = (cities[i].x as f64 + angle.cos() * distance) as usize;
                 let target_y = (cities[i].y as f64 + angle.sin() * distance) as usize;
@@ -440,14 +980,15 @@ impl TerrainGenerator {
                         self.find_partial_path(terrain, cities[i].x, cities[i].y, target_x, target_y);
                     if path.len() > 5 {
                         // Only add if it's a meaningful path
-                        let bridges =
-                            self.detect_bridges(&path, &river_points, terrain, &mut all_bridges);
+                        let (bridges, fords) =
+                            self.detect_crossings(&path, "trail", &mut crossings, terrain, &mut all_bridges);
 
                         roads.push(Road {
                             path,
-                            name: format!("Old {} Trail", self.generate_road_name(roads.len())),
+                            name: self.generate_road_name(roads.len(), |d| format!("Old {} Trail", d)),
                             road_type: "trail".to_string(),
                             bridges,
+                            fords,
                         });
                     }
                 }
@@ -457,39 +998,183 @@ impl TerrainGenerator {
         (roads, all_bridges)
     }
 
-    /// A* pathfinding that avoids water bodies but can cross rivers.
-    fn find_path(
+    /// Paths for the `edge_roads` setting's highways off the map: from the
+    /// cities closest to the border (within a quarter of the map), each to
+    /// the nearest land tile of the same land mass on its nearest edge.
+    /// Edges without a road yet are served first.
+    fn edge_road_paths(
+        &mut self,
+        terrain: &[Vec<TerrainPoint>],
+        crossings: &Crossings,
+        cities: &[City],
+    ) -> Vec<Vec<(usize, usize)>> {
+        let mut paths = Vec::new();
+        let wanted = self.settings.edge_roads;
+        if wanted == 0 || cities.is_empty() {
+            return paths;
+        }
+        let (width, height) = (terrain[0].len(), terrain.len());
+        let regions = land_regions(terrain, self.wrap_x);
+        let border = width.max(height) / 4;
+
+        // (distance to the edge, city, edge: left, right, top, bottom)
+        let wrap_x = self.wrap_x;
+        let mut candidates: Vec<(usize, usize, usize)> = cities
+            .iter()
+            .enumerate()
+            .filter(|(_, city)| city.x < width && city.y < height)
+            .filter_map(|(i, city)| {
+                let mut distances = [city.x, width - 1 - city.x, city.y, height - 1 - city.y];
+                if wrap_x {
+                    // A map that wraps has no east or west edge to leave by
+                    distances[..2].fill(usize::MAX);
+                }
+                let (edge, &distance) = distances.iter().enumerate().min_by_key(|&(_, d)| *d)?;
+                (distance <= border).then_some((distance, i, edge))
+            })
+            .collect();
+        candidates.sort_unstable();
+
+        let mut edges_used = [false; 4];
+        let mut cities_used = vec![false; cities.len()];
+        for pass in 0..2 {
+            for &(_, i, edge) in &candidates {
+                if paths.len() == wanted {
+                    return paths;
+                }
+                if cities_used[i] || (pass == 0 && edges_used[edge]) {
+                    continue;
+                }
+                let city = &cities[i];
+                let region = regions[city.y * width + city.x];
+                // Tiles of the edge, nearest to the city first
+                let along = if edge < 2 { (city.y, height) } else { (city.x, width) };
+                let target = (0..along.1)
+                    .flat_map(|offset| [along.0.checked_sub(offset), along.0.checked_add(offset)])
+                    .flatten()
+                    .filter(|&t| t < along.1)
+                    .map(|t| match edge {
+                        0 => (0, t),
+                        1 => (width - 1, t),
+                        2 => (t, 0),
+                        _ => (t, height - 1),
+                    })
+                    .take(2 * border)
+                    .find(|&(x, y)| regions[y * width + x] == region);
+                let Some((tx, ty)) = target else {
+                    continue;
+                };
+                let path = self.find_path(terrain, crossings, city.x, city.y, tx, ty);
+                if !path.is_empty() {
+                    edges_used[edge] = true;
+                    cities_used[i] = true;
+                    paths.push(path);
+                }
+            }
+        }
+        paths
+    }
+
+    /// A road's path from (`x1`, `y1`) to (`x2`, `y2`), or an empty one if
+    /// there is none. Where the cheapest path would cross a river within
+    /// `CROSSING_REACH` tiles of one of the `crossings` already made, the
+    /// road goes by that crossing instead, and on from there the same way.
+    pub(super) fn find_path(
         &mut self,
         terrain: &[Vec<TerrainPoint>],
+        crossings: &Crossings,
         x1: usize,
         y1: usize,
         x2: usize,
         y2: usize,
     ) -> Vec<(usize, usize)> {
-        let mut g_score: HashMap<(usize, usize), usize> = HashMap::new();
-        let mut heap = BinaryHeap::new();
-        let mut came_from: HashMap<(usize, usize), (usize, usize)> = HashMap::new();
-
-        g_score.insert((x1, y1), 0);
-        heap.push(PathState {
-            f: 0,
-            g: 0,
-            position: (x1, y1),
-        });
-
-        while let Some(PathState { g, position, .. }) = heap.pop() {
-            let (x, y) = position;
+        let width = terrain[0].len();
+        let mut route = vec![(x1, y1)];
+        let mut from = (x1, y1);
+        let mut path = self.search_path(terrain, crossings, x1, y1, x2, y2);
+        let mut used = Vec::new();
+        loop {
+            // The crossing nearest the first new one near any
+            let near = |&(x, y): &(usize, usize)| {
+                if terrain[y][x].biome != Biome::River || crossings.made.contains_key(&(x, y)) {
+                    return None;
+                }
+                crossings
+                    .made
+                    .keys()
+                    .filter(|&&c| c != from && c != (x2, y2) && !used.contains(&c))
+                    .map(|&(cx, cy)| {
+                        let dx = wrap_dx(cx as f64 - x as f64, width, self.wrap_x).abs() as usize;
+                        (dx.max(cy.abs_diff(y)), (cx, cy))
+                    })
+                    .filter(|&(distance, _)| distance <= CROSSING_REACH)
+                    .min()
+                    .map(|(_, c)| c)
+            };
+            let Some(via) = path.iter().find_map(near) else {
+                break;
+            };
+            let to_via = self.search_path(terrain, crossings, from.0, from.1, via.0, via.1);
+            let onward = self.search_path(terrain, crossings, via.0, via.1, x2, y2);
+            if to_via.is_empty() || onward.is_empty() {
+                break;
+            }
+            used.push(via);
+            route.extend_from_slice(&to_via[1..]);
+            (from, path) = (via, onward);
+        }
+        if path.is_empty() {
+            return path;
+        }
+        route.extend_from_slice(&path[1..]);
+        route
+    }
 
-            if position == (x2, y2) {
+    /// A* pathfinding that avoids water bodies but can cross rivers, by
+    /// the `crossings` already made where it can. Search state lives in
+    /// flat arrays over the grid, and every cell remembers the direction it
+    /// was entered from and how many steps in a row went that way, so the
+    /// shape penalties don't walk the path back.
+    fn search_path(
+        &mut self,
+        terrain: &[Vec<TerrainPoint>],
+        crossings: &Crossings,
+        x1: usize,
+        y1: usize,
+        x2: usize,
+        y2: usize,
+    ) -> Vec<(usize, usize)> {
+        const NONE: usize = usize::MAX;
+        let (width, height) = (terrain[0].len(), terrain.len());
+        let index = |x: usize, y: usize| y * width + x;
+        let mut g_score = vec![NONE; width * height];
+        let mut came_from = vec![NONE; width * height];
+        // Direction each cell was entered from, and the length of the
+        // straight run in that direction ending there
+        let mut entered = vec![(0i32, 0i32); width * height];
+        let mut run = vec![0usize; width * height];
+        let mut open = OpenSet::new(width * height);
+
+        let (start, goal) = (index(x1, y1), index(x2, y2));
+        g_score[start] = 0;
+        open.push_or_decrease(start, 0);
+
+        while let Some(current) = open.pop() {
+            let (x, y) = (current % width, current / width);
+            let g = g_score[current];
+
+            if current == goal {
+                let mut path = vec![(x2, y2)];
+                let mut cell = goal;
+                while came_from[cell] != NONE {
+                    cell = came_from[cell];
+                    path.push((cell % width, cell / width));
+                }
+                path.reverse();
                 // Smooth the path to make it more natural
-                let path = reconstruct_path(&came_from, (x2, y2));
                 return self.smooth_path(path, terrain);
             }
 
-            if g > *g_score.get(&position).unwrap_or(&usize::MAX) {
-                continue;
-            }
-
             // Check all 8 neighbors
             for dy in -1i32..=1 {
                 for dx in -1i32..=1 {
@@ -497,54 +1182,34 @@ impl TerrainGenerator {
                         continue;
                     }
 
-                    let nx = (x as i32 + dx) as usize;
-                    let ny = (y as i32 + dy) as usize;
-
-                    if nx >= terrain[0].len() || ny >= terrain.len() {
+                    let Some((nx, ny)) =
+                        offset_tile(x, y, dx as isize, dy as isize, width, height, self.wrap_x)
+                    else {
                         continue;
-                    }
-
-                    let next_terrain = &terrain[ny][nx];
-
-                    // Cannot cross oceans or lakes
-                    if matches!(
-                        next_terrain.biome,
-                        Biome::Ocean | Biome::DeepOcean | Biome::Lake | Biome::Shore
-                    ) {
-                        continue;
-                    }
+                    };
 
                     // Calculate cost - consider elevation changes and terrain type
                     let is_diagonal = dx.abs() + dy.abs() == 2;
-                    let mut move_cost = if is_diagonal { 14 } else { 10 };
-
-                    // Add cost for elevation changes (roads prefer flat terrain)
-                    let current_elevation = terrain[y][x].elevation;
-                    let next_elevation = next_terrain.elevation;
-                    let elevation_change = (next_elevation - current_elevation).abs();
-
-                    // Heavy penalty for elevation changes
-                    move_cost += (elevation_change * 100.0) as usize;
-
-                    // Additional terrain-based costs
-                    match next_terrain.biome {
-                        Biome::River => move_cost *= 5, // Rivers are expensive to cross (bridges needed)
-                        Biome::Mountains => move_cost *= 8, // Mountains are very hard to cross
-                        Biome::SnowPeaks => move_cost *= 10, // Snow peaks are nearly impassable
-                        Biome::Hills => move_cost *= 2, // Hills are moderately difficult
-                        Biome::Swamp => move_cost *= 3, // Swamps are difficult
-                        Biome::Forest => move_cost = (move_cost as f32 * 1.5) as usize, // Forests slow travel
-                        _ => {}
+                    let Some(mut move_cost) =
+                        terrain_move_cost(&terrain[y][x], &terrain[ny][nx], is_diagonal)
+                    else {
+                        continue;
+                    };
+                    // A river where a road already crosses costs only the
+                    // ground
+                    if crossings.made.contains_key(&(nx, ny)) && terrain[ny][nx].biome == Biome::River {
+                        move_cost /= RIVER_COST;
                     }
+                    let elevation_change =
+                        (terrain[ny][nx].elevation - terrain[y][x].elevation).abs();
 
                     // Add MORE random variation to prevent unnaturally straight lines
-                    move_cost += self.rng.gen_range(5..35);
+                    move_cost += self.rngs.roads.gen_range(5..35);
 
                     // Shape penalties: discourage right-angle turns and long
                     // straight runs in ANY direction so roads curve gently
-                    if let Some(&prev_pos) = came_from.get(&position) {
-                        let prev_dx = x as i32 - prev_pos.0 as i32;
-                        let prev_dy = y as i32 - prev_pos.1 as i32;
+                    if came_from[current] != NONE {
+                        let (prev_dx, prev_dy) = entered[current];
 
                         // Detect right angles (90-degree turns)
                         let is_right_angle = !is_diagonal
@@ -561,21 +1226,14 @@ impl TerrainGenerator {
                             // PROHIBITIVE penalty for creating right angles
                             move_cost += 1000;
                         } else {
-                            // Count consecutive moves in the exact same
-                            // direction (diagonals included - otherwise roads
-                            // become long 45-degree lines)
-                            let mut straight_count = 0;
-                            let mut check_pos = position;
-                            while let Some(&prev) = came_from.get(&check_pos) {
-                                let check_dx = check_pos.0 as i32 - prev.0 as i32;
-                                let check_dy = check_pos.1 as i32 - prev.1 as i32;
-                                if check_dx == dx && check_dy == dy {
-                                    straight_count += 1;
-                                    check_pos = prev;
-                                } else {
-                                    break;
-                                }
-                            }
+                            // Consecutive moves in the exact same direction
+                            // (diagonals included - otherwise roads become
+                            // long 45-degree lines)
+                            let straight_count = if (prev_dx, prev_dy) == (dx, dy) {
+                                run[current]
+                            } else {
+                                0
+                            };
 
                             // Quadratic penalty for straight lines
                             if straight_count > 1 {
@@ -584,7 +1242,7 @@ impl TerrainGenerator {
 
                             // Base penalty for horizontal/vertical movement
                             if dx == 0 || dy == 0 {
-                                move_cost += 100 + self.rng.gen_range(25..50);
+                                move_cost += 100 + self.rngs.roads.gen_range(25..50);
 
                                 // Extra penalty if continuing horizontal/vertical
                                 if (dx == 0 && prev_dx == 0) || (dy == 0 && prev_dy == 0) {
@@ -613,22 +1271,26 @@ impl TerrainGenerator {
                         move_cost = (move_cost as f32 * 0.85) as usize;
                     }
 
+                    let next = index(nx, ny);
                     let next_g = g + move_cost;
-                    if next_g < *g_score.get(&(nx, ny)).unwrap_or(&usize::MAX) {
-                        g_score.insert((nx, ny), next_g);
-                        came_from.insert((nx, ny), position);
+                    if next_g < g_score[next] {
+                        g_score[next] = next_g;
+                        came_from[next] = current;
+                        entered[next] = (dx, dy);
+                        run[next] = if came_from[current] != NONE && entered[current] == (dx, dy) {
+                            run[current] + 1
+                        } else {
+                            1
+                        };
 
-                        // Euclidean-distance heuristic, scaled below the base
-                        // move cost so it stays admissible. It is added to the
-                        // heap priority only - never to the stored g score.
-                        let dx_goal = nx as f32 - x2 as f32;
+                        // Euclidean-distance heuristic, scaled to the cheapest
+                        // step (a diagonal over flat land costs at least 10,
+                        // about 7 a tile) so it stays admissible. It is added
+                        // to the queue priority only - never to the g score.
+                        let dx_goal = wrap_dx(nx as f64 - x2 as f64, width, self.wrap_x) as f32;
                         let dy_goal = ny as f32 - y2 as f32;
-                        let h = ((dx_goal * dx_goal + dy_goal * dy_goal).sqrt() * 3.0) as usize;
-                        heap.push(PathState {
-                            f: next_g + h,
-                            g: next_g,
-                            position: (nx, ny),
-                        });
+                        let h = ((dx_goal * dx_goal + dy_goal * dy_goal).sqrt() * 7.0) as usize;
+                        open.push_or_decrease(next, next_g + h);
                     }
                 }
             }
@@ -687,12 +1349,12 @@ impl TerrainGenerator {
                         continue;
                     }
 
-                    let nx = (x as i32 + dx) as usize;
-                    let ny = (y as i32 + dy) as usize;
-
-                    if nx >= terrain[0].len() || ny >= terrain.len() {
+                    let (width, height) = (terrain[0].len(), terrain.len());
+                    let Some((nx, ny)) =
+                        offset_tile(x, y, dx as isize, dy as isize, width, height, self.wrap_x)
+                    else {
                         continue;
-                    }
+                    };
 
                     let next_terrain = &terrain[ny][nx];
 
@@ -726,7 +1388,26 @@ impl TerrainGenerator {
         Vec::new()
     }
 
-    fn smooth_path(
+    /// `path` with its bends rounded and its straight runs given a wiggle,
+    /// stretch by stretch between the river tiles it crosses, which stay
+    /// where the search put them to keep roads on the crossings they chose.
+    fn smooth_path(&mut self, path: Vec<(usize, usize)>, terrain: &[Vec<TerrainPoint>]) -> Vec<(usize, usize)> {
+        if path.len() < 3 {
+            return path;
+        }
+        let mut smoothed = vec![path[0]];
+        let mut start = 0;
+        for (i, &(x, y)) in path.iter().enumerate().skip(1) {
+            if i + 1 == path.len() || terrain[y][x].biome == Biome::River {
+                let stretch = self.smooth_stretch(path[start..=i].to_vec(), terrain);
+                smoothed.extend_from_slice(&stretch[1..]);
+                start = i;
+            }
+        }
+        smoothed
+    }
+
+    fn smooth_stretch(
         &mut self,
         path: Vec<(usize, usize)>,
         terrain: &[Vec<TerrainPoint>],
@@ -734,20 +1415,32 @@ impl TerrainGenerator {
         if path.len() < 3 {
             return path;
         }
+        // Curves are worked out on the path unrolled across the east-west
+        // seam of a map that wraps, and their tiles wrapped back onto it
+        let width = terrain[0].len();
+        let wrap_x = self.wrap_x;
+        let tile_x = |x: f32| {
+            if wrap_x {
+                (x.round() as isize).rem_euclid(width as isize) as usize
+            } else {
+                x.round() as usize
+            }
+        };
 
         // First pass: Smooth out sharp corners with larger radius curves
         let mut splined = Vec::new();
         splined.push(path[0]);
+        let xs = unrolled_x(&path, width, wrap_x);
 
         for i in 1..path.len() - 1 {
-            let prev = path[i - 1];
-            let curr = path[i];
-            let next = path[i + 1];
+            let prev = (xs[i - 1], path[i - 1].1);
+            let curr = (xs[i], path[i].1);
+            let next = (xs[i + 1], path[i + 1].1);
 
             // Calculate vectors
-            let v1x = curr.0 as f32 - prev.0 as f32;
+            let v1x = curr.0 - prev.0;
             let v1y = curr.1 as f32 - prev.1 as f32;
-            let v2x = next.0 as f32 - curr.0 as f32;
+            let v2x = next.0 - curr.0;
             let v2y = next.1 as f32 - curr.1 as f32;
 
             // Calculate the dot product to detect angle changes
@@ -775,9 +1468,9 @@ impl TerrainGenerator {
                 let t1 = curve_radius / v1_len;
                 let t2 = curve_radius / v2_len;
 
-                let p1x = curr.0 as f32 - v1x * t1;
+                let p1x = curr.0 - v1x * t1;
                 let p1y = curr.1 as f32 - v1y * t1;
-                let p2x = curr.0 as f32 + v2x * t2;
+                let p2x = curr.0 + v2x * t2;
                 let p2y = curr.1 as f32 + v2y * t2;
 
                 // Generate curve points using cubic Bezier
@@ -791,15 +1484,15 @@ impl TerrainGenerator {
 
                     // Cubic Bezier formula (both inner control points at the corner)
                     let px = mt3 * p1x
-                        + 3.0 * mt2 * t * curr.0 as f32
-                        + 3.0 * mt * t2_pow * curr.0 as f32
+                        + 3.0 * mt2 * t * curr.0
+                        + 3.0 * mt * t2_pow * curr.0
                         + t3 * p2x;
                     let py = mt3 * p1y
                         + 3.0 * mt2 * t * curr.1 as f32
                         + 3.0 * mt * t2_pow * curr.1 as f32
                         + t3 * p2y;
 
-                    let curved_x = px.round().max(0.0) as usize;
+                    let curved_x = tile_x(px);
                     let curved_y = py.round().max(0.0) as usize;
 
                     if curved_x < terrain[0].len() && curved_y < terrain.len() {
@@ -814,7 +1507,7 @@ impl TerrainGenerator {
                 }
             } else {
                 // Keep the original point for straight segments
-                splined.push(curr);
+                splined.push(path[i]);
             }
         }
 
@@ -823,12 +1516,13 @@ impl TerrainGenerator {
         // Second pass: Add natural wiggles to straight segments
         let mut smoothed = Vec::new();
         smoothed.push(splined[0]);
+        let xs = unrolled_x(&splined, width, wrap_x);
 
         for i in 0..splined.len() - 1 {
-            let current = splined[i];
+            let current = (xs[i], splined[i].1);
             let next = splined[i + 1];
 
-            let dx = next.0 as f32 - current.0 as f32;
+            let dx = xs[i + 1] - current.0;
             let dy = next.1 as f32 - current.1 as f32;
             let distance = (dx * dx + dy * dy).sqrt();
 
@@ -837,15 +1531,15 @@ impl TerrainGenerator {
                 let num_points = (distance * 0.8) as usize;
 
                 // Generate a smooth noise curve for this segment
-                let phase = self.rng.gen_range(0.0..std::f32::consts::TAU);
-                let frequency = self.rng.gen_range(0.3..0.7);
-                let amplitude = self.rng.gen_range(0.5..1.2);
+                let phase = self.rngs.roads.gen_range(0.0..std::f32::consts::TAU);
+                let frequency = self.rngs.roads.gen_range(0.3..0.7);
+                let amplitude = self.rngs.roads.gen_range(0.5..1.2);
 
                 for j in 1..=num_points {
                     let t = j as f32 / (num_points + 1) as f32;
 
                     // Base position along the line
-                    let base_x = current.0 as f32 + dx * t;
+                    let base_x = current.0 + dx * t;
                     let base_y = current.1 as f32 + dy * t;
 
                     // Calculate perpendicular direction
@@ -864,8 +1558,8 @@ impl TerrainGenerator {
                     let wiggle_y = base_y + perp_y * total_wiggle;
 
                     // Add very small random variation for natural imperfection
-                    let final_x = (wiggle_x + self.rng.gen_range(-0.1..0.1)).round() as usize;
-                    let final_y = (wiggle_y + self.rng.gen_range(-0.1..0.1)).round() as usize;
+                    let final_x = tile_x(wiggle_x + self.rngs.roads.gen_range(-0.1..0.1));
+                    let final_y = (wiggle_y + self.rngs.roads.gen_range(-0.1..0.1)).round() as usize;
 
                     // Ensure the point is valid and preferably not in water
                     if final_x < terrain[0].len() && final_y < terrain.len() {
@@ -876,7 +1570,7 @@ impl TerrainGenerator {
                             smoothed.push((final_x, final_y));
                         } else {
                             // Fall back to straight line if curve goes into water
-                            smoothed.push((base_x.round() as usize, base_y.round() as usize));
+                            smoothed.push((tile_x(base_x), base_y.round() as usize));
                         }
                     }
                 }
@@ -890,3 +1584,154 @@ impl TerrainGenerator {
         smoothed
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain_generator::{test_map, GenerationSettings, TravelProfile};
+
+    #[test]
+    fn open_set_pops_lowest_priority_after_decreases() {
+        let mut open = OpenSet::new(6);
+        for (cell, priority) in [(0, 50), (1, 40), (2, 30), (3, 20), (4, 10)] {
+            open.push_or_decrease(cell, priority);
+        }
+        open.push_or_decrease(0, 5);
+        // Raising a priority is ignored
+        open.push_or_decrease(4, 60);
+        open.push_or_decrease(5, 25);
+        let order: Vec<usize> = std::iter::from_fn(|| open.pop()).collect();
+        assert_eq!(order, [0, 4, 3, 5, 2, 1]);
+    }
+
+    #[test]
+    fn settlement_weights_steer_city_sites() {
+        // Cities within 3 tiles of the sea, over a few maps
+        let coastal_cities = |weights: SettlementWeights| {
+            let mut coastal = 0;
+            for seed in 1..4 {
+                let settings = GenerationSettings::default().with_settlement_weights(weights);
+                let map = TerrainGenerator::new_with_settings(seed, settings).generate(200, 150);
+                let features = SiteFeatures::grid(&map.terrain, &map.rivers, false);
+                coastal += map
+                    .cities
+                    .iter()
+                    .filter(|city| features[city.y * map.width + city.x].coast > 0.0)
+                    .count();
+            }
+            coastal
+        };
+        assert!(coastal_cities(SettlementWeights::COASTAL_TRADE) > coastal_cities(SettlementWeights::RIVER_VALLEY));
+    }
+
+    #[test]
+    fn edge_roads_leave_the_map_from_border_cities() {
+        let settings = GenerationSettings::default().with_edge_roads(3);
+        let map = TerrainGenerator::new_with_settings(5, settings).generate(200, 150);
+        let leaving: Vec<&Road> = map.roads.iter().filter(|road| road.name.contains("(to ")).collect();
+        assert!(!leaving.is_empty() && leaving.len() <= 3);
+        for road in leaving {
+            let start = road.path[0];
+            assert!(map.cities.iter().any(|c| (c.x, c.y) == start));
+            let (x, y) = *road.path.last().unwrap();
+            assert!(x == 0 || y == 0 || x == map.width - 1 || y == map.height - 1);
+        }
+    }
+
+    #[test]
+    fn no_roads_leave_the_map_by_default() {
+        let map = test_map(5, 200, 150);
+        assert!(map.roads.iter().all(|road| !road.name.contains("(to ")));
+    }
+
+    #[test]
+    fn roads_only_join_cities_on_the_same_land() {
+        let map = test_map(3, 160, 120);
+        let regions = land_regions(&map.terrain, false);
+        let region = |(x, y): (usize, usize)| regions[y * map.width + x];
+        for road in &map.roads {
+            let first = region(road.path[0]);
+            assert_ne!(first, usize::MAX);
+            assert!(road.path.iter().all(|&tile| region(tile) == first));
+        }
+    }
+
+    #[test]
+    fn a_cart_can_reach_every_city_on_the_same_land() {
+        for seed in [3, 11] {
+            let map = TerrainGenerator::new_with_settings(seed, GenerationSettings::default().with_city_density(0.8))
+                .generate(200, 150);
+            let regions = land_regions(&map.terrain, false);
+            let land = |c: &City| regions[c.y * map.width + c.x];
+            let mut groups = RoadGroups::new(&map.cities, &map.roads);
+            for (i, a) in map.cities.iter().enumerate() {
+                // The first city on the land, by road as a cart goes
+                let first = map.cities.iter().position(|b| land(b) == land(a)).unwrap();
+                assert_eq!(groups.group(i), groups.group(first), "{} on seed {}", a.name, seed);
+                let other = &map.cities[first];
+                if i != first && i % 4 == 0 {
+                    assert!(map.route((a.x, a.y), (other.x, other.y), TravelProfile::Cart).is_some());
+                }
+            }
+        }
+    }
+
+    /// Plains with a river running down the middle from the top edge, and
+    /// the crossings made over it so far
+    struct RiverPlain {
+        generator: TerrainGenerator,
+        terrain: Vec<Vec<TerrainPoint>>,
+        crossings: Crossings,
+        bridges: Vec<Bridge>,
+    }
+
+    impl RiverPlain {
+        fn new() -> Self {
+            let plain = TerrainPoint { elevation: 0.2, moisture: 0.4, temperature: 0.5, biome: Biome::Plains };
+            let mut terrain = vec![vec![plain; 40]; 30];
+            let river: Vec<(usize, usize)> = (0..30).map(|y| (20, y)).collect();
+            for &(x, y) in &river {
+                terrain[y][x].biome = Biome::River;
+            }
+            let crossings = Crossings::new(&[river]);
+            RiverPlain { generator: TerrainGenerator::new(4), terrain, crossings, bridges: Vec::new() }
+        }
+
+        /// Lays a road from the west side at row `from` to the east side at row `to`
+        fn cross(&mut self, from: usize, to: usize, kind: &str) -> (Vec<Bridge>, Vec<(usize, usize)>) {
+            let path = self.generator.find_path(&self.terrain, &self.crossings, 5, from, 35, to);
+            self.generator.detect_crossings(&path, kind, &mut self.crossings, &self.terrain, &mut self.bridges)
+        }
+    }
+
+    #[test]
+    fn a_second_road_goes_out_of_its_way_to_the_first_ones_bridge() {
+        let mut plain = RiverPlain::new();
+        let (first, _) = plain.cross(20, 20, "road");
+        assert_eq!(first.len(), 1);
+        let (second, _) = plain.cross(25, 25, "road");
+        assert_eq!((second[0].x, second[0].y), (first[0].x, first[0].y));
+        assert_eq!(plain.bridges.len(), 1);
+    }
+
+    #[test]
+    fn near_the_source_a_trail_wades_across_and_a_highway_bridges_the_ford() {
+        let mut plain = RiverPlain::new();
+        let (none, fords) = plain.cross(3, 3, "trail");
+        assert!(none.is_empty() && fords.len() == 1 && fords[0].1 < FORD_REACH);
+        let (bridged, _) = plain.cross(3, 3, "highway");
+        assert_eq!(bridged.len(), 1);
+        assert_eq!(plain.bridges.len(), 1);
+    }
+
+    #[test]
+    fn generated_maps_keep_one_bridge_to_a_tile_shared_by_the_roads_there() {
+        let map = test_map(6, 160, 120);
+        let tiles: HashSet<(usize, usize)> = map.bridges.iter().map(|b| (b.x, b.y)).collect();
+        assert_eq!(tiles.len(), map.bridges.len());
+        for road in &map.roads {
+            assert!(road.bridges.iter().all(|b| tiles.contains(&(b.x, b.y))));
+            assert!(road.road_type == "trail" || road.fords.is_empty());
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! Smooth curves along the rivers and roads, for drawing.
+//!
+//! Rivers and roads are paths of tiles, which drawn as they are show the
+//! grid in every bend. Chaikin's corner cutting, `SMOOTHING_PASSES` times
+//! over, turns each into a curve that never strays more than a third of a
+//! tile from the line through its tiles. The curves are worked out once
+//! and kept in `TerrainMap::smoothed` beside the tiles, which stay what
+//! everything but drawing goes by.
+//!
+//! Some points are pinned for the curve to pass through: the ends of each
+//! path, river tiles where the river meets a lake, the sea or the end of a
+//! tributary, and road tiles on or beside water or a river, at a city or a
+//! bridge. Roads therefore cross water only where their tiles do, and
+//! rivers still reach their mouths and each other.
+
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+use super::biome::Biome;
+use super::types::{SmoothPaths, TerrainMap};
+
+/// Rounds of corner cutting; each doubles the points along a path
+pub const SMOOTHING_PASSES: usize = 2;
+
+impl TerrainMap {
+    /// River `index` as a curve to draw: smoothed while `smoothed` is up to
+    /// date with the rivers, along its tiles otherwise.
+    pub fn river_curve(&self, index: usize) -> Cow<'_, [(f32, f32)]> {
+        match self.smoothed.rivers.get(index) {
+            Some(curve) if self.smoothed.rivers.len() == self.rivers.len() => Cow::Borrowed(curve),
+            _ => Cow::Owned(self.unwrapped_path(&self.rivers[index])),
+        }
+    }
+
+    /// Road `index` as a curve to draw, like [`TerrainMap::river_curve`].
+    pub fn road_curve(&self, index: usize) -> Cow<'_, [(f32, f32)]> {
+        match self.smoothed.roads.get(index) {
+            Some(curve) if self.smoothed.roads.len() == self.roads.len() => Cow::Borrowed(curve),
+            _ => Cow::Owned(self.unwrapped_path(&self.roads[index].path)),
+        }
+    }
+
+    /// Every river and road smoothed again; see the [module docs](self).
+    pub(super) fn smoothed_paths(&self) -> SmoothPaths {
+        let mouths: HashSet<(usize, usize)> = self.rivers.iter().filter_map(|river| river.last().copied()).collect();
+        let rivers = self
+            .rivers
+            .iter()
+            .map(|river| self.smoothed_path(river, |(x, y)| self.is_water(x, y) || mouths.contains(&(x, y))))
+            .collect();
+        let roads = (0..self.roads.len()).map(|index| self.smoothed_road(index)).collect();
+        SmoothPaths { rivers, roads }
+    }
+
+    /// Road `index` smoothed, for keeping `smoothed` up to date as roads
+    /// are added.
+    pub(super) fn smoothed_road(&self, index: usize) -> Vec<(f32, f32)> {
+        let road = &self.roads[index];
+        let bridges: HashSet<(usize, usize)> = road.bridges.iter().map(|b| (b.x, b.y)).collect();
+        let wet = |x: usize, y: usize| self.biome_at(x, y).is_some_and(|b| b.is_water() || b == Biome::River);
+        self.smoothed_path(&road.path, |(x, y)| {
+            let near_water = (-1..=1)
+                .flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+                .filter_map(|(dx, dy)| self.offset(x, y, dx, dy))
+                .any(|(nx, ny)| wet(nx, ny));
+            near_water || bridges.contains(&(x, y)) || self.cities.iter().any(|c| (c.x, c.y) == (x, y))
+        })
+    }
+
+    /// `path` after `SMOOTHING_PASSES` of corner cutting that passes
+    /// through its ends and the tiles `pinned` holds for.
+    fn smoothed_path(&self, path: &[(usize, usize)], pinned: impl Fn((usize, usize)) -> bool) -> Vec<(f32, f32)> {
+        let mut points = self.unwrapped_path(path);
+        let last = path.len().saturating_sub(1);
+        let mut fixed: Vec<bool> = path.iter().enumerate().map(|(i, &tile)| i == 0 || i == last || pinned(tile)).collect();
+        if points.len() < 3 {
+            return points;
+        }
+        for _ in 0..SMOOTHING_PASSES {
+            let mut next = vec![points[0]];
+            let mut next_fixed = vec![true];
+            for i in 0..points.len() - 1 {
+                let (a, b) = (points[i], points[i + 1]);
+                if i > 0 && fixed[i] {
+                    next.push(a);
+                    next_fixed.push(true);
+                }
+                let cut = |t: f32| (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t);
+                next.extend([cut(0.25), cut(0.75)]);
+                next_fixed.extend([false, false]);
+            }
+            next.push(points[points.len() - 1]);
+            next_fixed.push(true);
+            (points, fixed) = (next, next_fixed);
+        }
+
+        // Points along a straight stretch add nothing
+        let mut curve = vec![points[0]];
+        for i in 1..points.len() - 1 {
+            let (a, b, c) = (curve[curve.len() - 1], points[i], points[i + 1]);
+            let turn = (b.0 - a.0) * (c.1 - b.1) - (b.1 - a.1) * (c.0 - b.0);
+            if fixed[i] || turn.abs() > 1e-4 {
+                curve.push(b);
+            }
+        }
+        curve.push(points[points.len() - 1]);
+        curve
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::terrain_generator::{test_map, TerrainMap};
+
+    fn map() -> &'static TerrainMap {
+        test_map(9, 140, 100)
+    }
+
+    /// Whether every point of a curve is close to one of the tiles
+    fn near(curve: &[(f32, f32)], tiles: &[(f32, f32)]) -> bool {
+        curve.iter().all(|&(x, y)| tiles.iter().any(|&(tx, ty)| (x - tx).hypot(y - ty) < 0.75))
+    }
+
+    #[test]
+    fn every_river_and_road_is_smoothed() {
+        let map = map();
+        assert_eq!(map.smoothed.rivers.len(), map.rivers.len());
+        assert_eq!(map.smoothed.roads.len(), map.roads.len());
+    }
+
+    #[test]
+    fn smoothed_rivers_stay_by_their_tiles_and_end_where_they_do() {
+        let map = map();
+        let mut cut = 0;
+        for (index, river) in map.rivers.iter().enumerate() {
+            let curve = map.river_curve(index);
+            let tiles = map.unwrapped_path(river);
+            assert_eq!((curve[0], curve[curve.len() - 1]), (tiles[0], tiles[tiles.len() - 1]));
+            assert!(near(&curve, &tiles));
+            cut += (curve.len() != tiles.len()) as usize;
+        }
+        assert!(cut > 0);
+    }
+
+    #[test]
+    fn smoothed_roads_keep_off_the_water_their_tiles_keep_off() {
+        let map = map();
+        for (index, road) in map.roads.iter().enumerate() {
+            let curve = map.road_curve(index);
+            assert!(near(&curve, &map.unwrapped_path(&road.path)));
+            for &(x, y) in curve.iter() {
+                let tile = ((x.round() as isize).rem_euclid(map.width as isize) as usize, y.round() as usize);
+                assert!(!map.is_water(tile.0, tile.1) || road.path.contains(&tile), "{:?} on {}", tile, road.name);
+            }
+        }
+    }
+
+    #[test]
+    fn a_map_whose_roads_changed_since_is_drawn_along_the_tiles() {
+        let mut edited = map().clone();
+        edited.roads.pop();
+        assert_eq!(edited.road_curve(0).len(), edited.roads[0].path.len());
+    }
+}
@@ -0,0 +1,272 @@
+//! Summary statistics of a finished map, shared by the CLI's `stats`
+//! subcommand and the GUI's statistics panel.
+
+use std::fmt;
+
+use super::biome::Biome;
+use super::types::TerrainMap;
+
+/// Number of bars in the elevation histogram
+pub const ELEVATION_BINS: usize = 20;
+
+/// Every biome, in the order statistics list them when counts tie
+const BIOMES: [Biome; 13] = [
+    Biome::DeepOcean,
+    Biome::Ocean,
+    Biome::Shore,
+    Biome::Beach,
+    Biome::Plains,
+    Biome::Forest,
+    Biome::Hills,
+    Biome::Mountains,
+    Biome::SnowPeaks,
+    Biome::River,
+    Biome::Lake,
+    Biome::Swamp,
+    Biome::Desert,
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapStats {
+    pub tiles: usize,
+    /// Tiles of each biome present on the map, most common first
+    pub biomes: Vec<(Biome, usize)>,
+    /// Tile counts for [`ELEVATION_BINS`] equal slices of -1.0..=1.0
+    pub elevation_histogram: [usize; ELEVATION_BINS],
+    /// City populations, largest first, so index + 1 is the city's rank
+    pub populations: Vec<u32>,
+    /// Roads of each type, in the order the type first appears
+    pub roads: Vec<RoadTotal>,
+    pub rivers: usize,
+    pub bridges: usize,
+}
+
+/// Number and combined length of the roads of one type.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoadTotal {
+    pub road_type: String,
+    pub count: usize,
+    /// In tiles (diagonal steps count as sqrt 2)
+    pub length: f64,
+}
+
+impl TerrainMap {
+    pub fn stats(&self) -> MapStats {
+        let mut biome_counts = [0; BIOMES.len()];
+        let mut elevation_histogram = [0; ELEVATION_BINS];
+        for point in self.terrain.iter().flatten() {
+            biome_counts[BIOMES.iter().position(|&b| b == point.biome).unwrap()] += 1;
+            let bin = ((point.elevation + 1.0) / 2.0 * ELEVATION_BINS as f64).floor();
+            elevation_histogram[(bin.max(0.0) as usize).min(ELEVATION_BINS - 1)] += 1;
+        }
+        let mut biomes: Vec<(Biome, usize)> = BIOMES
+            .iter()
+            .copied()
+            .zip(biome_counts)
+            .filter(|&(_, count)| count > 0)
+            .collect();
+        biomes.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+
+        let mut populations: Vec<u32> = self.cities.iter().map(|c| c.population).collect();
+        populations.sort_unstable_by(|a, b| b.cmp(a));
+
+        let mut roads: Vec<RoadTotal> = Vec::new();
+        for road in &self.roads {
+            let length = path_length(&road.path);
+            match roads.iter_mut().find(|t| t.road_type == road.road_type) {
+                Some(total) => {
+                    total.count += 1;
+                    total.length += length;
+                }
+                None => roads.push(RoadTotal {
+                    road_type: road.road_type.clone(),
+                    count: 1,
+                    length,
+                }),
+            }
+        }
+
+        MapStats {
+            tiles: self.width * self.height,
+            biomes,
+            elevation_histogram,
+            populations,
+            roads,
+            rivers: self.rivers.len(),
+            bridges: self.bridges.len(),
+        }
+    }
+}
+
+/// Length of a path of neighbouring tiles, in tiles (diagonal steps count
+/// as sqrt 2).
+pub(super) fn path_length(path: &[(usize, usize)]) -> f64 {
+    path.windows(2)
+        .map(|step| {
+            let diagonal = step[0].0 != step[1].0 && step[0].1 != step[1].1;
+            if diagonal {
+                std::f64::consts::SQRT_2
+            } else {
+                1.0
+            }
+        })
+        .sum()
+}
+
+impl MapStats {
+    /// Share of the map that is not water, from 0.0 to 1.0.
+    pub fn land_share(&self) -> f64 {
+        let land: usize = self
+            .biomes
+            .iter()
+            .filter(|(biome, _)| !biome.is_water())
+            .map(|&(_, count)| count)
+            .sum();
+        land as f64 / self.tiles.max(1) as f64
+    }
+
+    /// Elevation range covered by histogram bar `bin`.
+    pub fn elevation_bin_range(bin: usize) -> (f64, f64) {
+        let width = 2.0 / ELEVATION_BINS as f64;
+        (-1.0 + bin as f64 * width, -1.0 + (bin + 1) as f64 * width)
+    }
+
+    /// Exponent `s` of the Zipf curve population = P / rank^s that best
+    /// fits the cities (least squares on log population against log rank).
+    /// Zipf's law predicts about 1. `None` with fewer than two cities.
+    pub fn zipf_exponent(&self) -> Option<f64> {
+        let points: Vec<(f64, f64)> = self
+            .populations
+            .iter()
+            .enumerate()
+            .filter(|&(_, &population)| population > 0)
+            .map(|(i, &population)| (((i + 1) as f64).ln(), (population as f64).ln()))
+            .collect();
+        if points.len() < 2 {
+            return None;
+        }
+        let n = points.len() as f64;
+        let mean_x = points.iter().map(|p| p.0).sum::<f64>() / n;
+        let mean_y = points.iter().map(|p| p.1).sum::<f64>() / n;
+        let covariance: f64 = points.iter().map(|p| (p.0 - mean_x) * (p.1 - mean_y)).sum();
+        let variance: f64 = points.iter().map(|p| (p.0 - mean_x).powi(2)).sum();
+        Some(-covariance / variance)
+    }
+
+    /// Combined length of all roads, in tiles.
+    pub fn road_length(&self) -> f64 {
+        self.roads.iter().map(|t| t.length).sum()
+    }
+}
+
+/// A plain-text report, e.g. for the CLI.
+impl fmt::Display for MapStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} tiles, {:.1}% land",
+            self.tiles,
+            self.land_share() * 100.0
+        )?;
+
+        writeln!(f, "\nBiomes:")?;
+        for &(biome, count) in &self.biomes {
+            let share = count as f64 / self.tiles as f64 * 100.0;
+            writeln!(f, "  {:<10} {:>5.1}%", format!("{:?}", biome), share)?;
+        }
+
+        writeln!(f, "\nElevation:")?;
+        let tallest = self.elevation_histogram.iter().copied().max().unwrap_or(0);
+        for (bin, &count) in self.elevation_histogram.iter().enumerate() {
+            let (low, high) = Self::elevation_bin_range(bin);
+            let bar = (count * 40).checked_div(tallest).unwrap_or(0);
+            writeln!(
+                f,
+                "  {:>5.2} to {:>5.2} {:>8} {}",
+                low,
+                high,
+                count,
+                "#".repeat(bar)
+            )?;
+        }
+
+        write!(f, "\nCities: {}", self.populations.len())?;
+        if let Some(exponent) = self.zipf_exponent() {
+            write!(f, ", Zipf exponent {:.2} (1.00 is Zipf's law)", exponent)?;
+        }
+        writeln!(f)?;
+        for (rank, population) in self.populations.iter().enumerate().take(10) {
+            writeln!(f, "  {:>3}. {}", rank + 1, population)?;
+        }
+        if self.populations.len() > 10 {
+            writeln!(f, "  ... {} more", self.populations.len() - 10)?;
+        }
+
+        writeln!(
+            f,
+            "\nRoads: {}, {:.0} tiles in total",
+            self.roads.iter().map(|t| t.count).sum::<usize>(),
+            self.road_length()
+        )?;
+        for total in &self.roads {
+            writeln!(
+                f,
+                "  {:<8} {:>4} roads, {:>7.0} tiles",
+                total.road_type, total.count, total.length
+            )?;
+        }
+
+        write!(f, "\nRivers: {}, bridges: {}", self.rivers, self.bridges)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain_generator::test_map;
+
+    fn map() -> &'static TerrainMap {
+        test_map(12, 160, 120)
+    }
+
+    #[test]
+    fn biomes_and_elevations_account_for_every_tile() {
+        let stats = map().stats();
+        assert_eq!(stats.tiles, 160 * 120);
+        assert_eq!(stats.biomes.iter().map(|b| b.1).sum::<usize>(), stats.tiles);
+        assert!(stats.biomes.windows(2).all(|w| w[0].1 >= w[1].1));
+        assert_eq!(stats.elevation_histogram.iter().sum::<usize>(), stats.tiles);
+    }
+
+    #[test]
+    fn every_city_has_a_population_largest_first() {
+        let stats = map().stats();
+        assert_eq!(stats.populations.len(), map().cities.len());
+        assert!(stats.populations.windows(2).all(|w| w[0] >= w[1]));
+    }
+
+    #[test]
+    fn every_road_is_counted_and_measured() {
+        let map = map();
+        let stats = map.stats();
+        assert_eq!(
+            stats.roads.iter().map(|t| t.count).sum::<usize>(),
+            map.roads.len()
+        );
+        let shortest_possible: usize = map
+            .roads
+            .iter()
+            .map(|r| r.path.len().saturating_sub(1))
+            .sum();
+        assert!(stats.road_length() >= shortest_possible as f64);
+    }
+
+    #[test]
+    fn ideal_zipf_populations_have_exponent_one() {
+        let zipf = MapStats {
+            populations: vec![600000, 300000, 200000, 150000],
+            ..map().stats()
+        };
+        assert!((zipf.zipf_exponent().unwrap() - 1.0).abs() < 1e-9);
+    }
+}
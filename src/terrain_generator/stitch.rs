@@ -0,0 +1,473 @@
+//! Assembling one large world from maps generated piece by piece.
+//!
+//! Each piece is placed at its offset, later pieces covering earlier ones
+//! where they overlap, and whatever no piece covers is deep ocean. Along
+//! the seams, where pieces meet each other or the open sea, elevation,
+//! moisture and temperature are blended over `SEAM_BLEND` tiles and the
+//! biomes there classified again, so coastlines run on across the join
+//! instead of stopping at a cliff. Rivers that ran off a piece's edge are
+//! carried on downhill until they reach water or another river, and
+//! roads that ran off it are joined to the nearest road leaving the piece
+//! next door within `ROAD_JOIN` tiles, along the path the generator would
+//! take, with bridges where the new stretch crosses a river. Cities,
+//! bridges, labels and points of interest keep their places on the pieces
+//! that are still showing.
+
+use std::collections::{HashSet, VecDeque};
+
+use super::biome::Biome;
+use super::names::UsedNames;
+use super::settlements::Crossings;
+use super::types::{Bridge, City, PlaceLabel, PowerLine, Road, TerrainMap, TerrainPoint};
+use super::TerrainGenerator;
+
+/// Tiles either side of a seam whose climate and elevation are blended
+pub const SEAM_BLEND: usize = 8;
+
+/// Farthest apart two roads leaving neighbouring pieces may be to be
+/// joined, in tiles
+pub const ROAD_JOIN: f64 = 24.0;
+
+/// Longest a river is carried on past the edge of its piece, in tiles
+const RIVER_REACH: usize = 200;
+
+/// What lies where no piece does
+const OPEN_SEA: TerrainPoint = TerrainPoint {
+    elevation: -1.0,
+    moisture: 1.0,
+    temperature: 0.5,
+    biome: Biome::DeepOcean,
+};
+
+impl TerrainMap {
+    /// One map from `pieces`, each placed with its top-left corner at its
+    /// (x, y) offset; see the [module docs](self) for how the seams are
+    /// joined. The world is just large enough to hold every piece and is
+    /// seeded like the first; it does not wrap.
+    pub fn stitch(pieces: &[((usize, usize), TerrainMap)]) -> TerrainMap {
+        let width = pieces.iter().map(|((x, _), map)| x + map.width).max().unwrap_or(0);
+        let height = pieces.iter().map(|((_, y), map)| y + map.height).max().unwrap_or(0);
+        let mut world = TerrainMap::empty(width, height);
+        world.seed = pieces.first().map_or(0, |(_, map)| map.seed);
+        if width == 0 || height == 0 {
+            return world;
+        }
+
+        // Which piece shows at each tile
+        let mut owner: Vec<Option<usize>> = vec![None; width * height];
+        world.terrain = vec![vec![OPEN_SEA; width]; height];
+        for (index, ((ox, oy), piece)) in pieces.iter().enumerate() {
+            for (y, row) in piece.terrain.iter().enumerate() {
+                for (x, point) in row.iter().enumerate() {
+                    world.terrain[oy + y][ox + x] = point.clone();
+                    owner[(oy + y) * width + ox + x] = Some(index);
+                }
+            }
+        }
+        let shows = |index: usize, (x, y): (usize, usize)| owner[y * width + x] == Some(index);
+
+        for (index, ((ox, oy), piece)) in pieces.iter().enumerate() {
+            let moved = |(x, y): (usize, usize)| (x + ox, y + oy);
+            // Rivers and roads where their piece still shows, a run each
+            for river in &piece.rivers {
+                let river: Vec<(usize, usize)> = river.iter().copied().map(moved).collect();
+                world.rivers.extend(split_where(&river, |&at| shows(index, at)));
+            }
+            for road in &piece.roads {
+                let path: Vec<(usize, usize)> = road.path.iter().copied().map(moved).collect();
+                for path in split_where(&path, |&at| shows(index, at)) {
+                    let bridges = road
+                        .bridges
+                        .iter()
+                        .map(|b| Bridge { x: b.x + ox, y: b.y + oy, name: b.name.clone() })
+                        .filter(|b| path.contains(&(b.x, b.y)))
+                        .collect();
+                    let fords = road.fords.iter().copied().map(moved).filter(|at| path.contains(at)).collect();
+                    world.roads.push(Road { path, bridges, fords, ..road.clone() });
+                }
+            }
+            for bridge in &piece.bridges {
+                let at = moved((bridge.x, bridge.y));
+                if shows(index, at) {
+                    world.bridges.push(Bridge { x: at.0, y: at.1, name: bridge.name.clone() });
+                }
+            }
+            for city in &piece.cities {
+                let (x, y) = moved((city.x, city.y));
+                if shows(index, (x, y)) {
+                    world.cities.push(City { x, y, ..city.clone() });
+                }
+            }
+            for line in &piece.power_lines {
+                let (from, to) = (moved(line.from), moved(line.to));
+                if shows(index, from) && shows(index, to) {
+                    world.power_lines.push(PowerLine { from, to });
+                }
+            }
+            for metro in &piece.metro_areas {
+                let mut metro = metro.clone();
+                metro.center = moved(metro.center);
+                if shows(index, metro.center) {
+                    metro.suburbs = metro.suburbs.iter().map(|&s| moved(s)).filter(|&s| shows(index, s)).collect();
+                    world.metro_areas.push(metro);
+                }
+            }
+            for label in &piece.labels {
+                let (x, y) = (label.x + *ox as f32, label.y + *oy as f32);
+                let tile = ((x as usize).min(width - 1), (y as usize).min(height - 1));
+                if shows(index, tile) {
+                    world.labels.push(PlaceLabel { x, y, ..label.clone() });
+                }
+            }
+            for poi in &piece.pois {
+                let mut poi = poi.clone();
+                poi.position = moved(poi.position);
+                if shows(index, poi.position) {
+                    world.pois.push(poi);
+                }
+            }
+            for park in &piece.parks {
+                if !world.parks.contains(park) {
+                    world.parks.push(park.clone());
+                }
+            }
+            for style in &piece.poi_styles {
+                if !world.poi_styles.iter().any(|s| s.kind == style.kind) {
+                    world.poi_styles.push(style.clone());
+                }
+            }
+        }
+
+        let mut generator = TerrainGenerator::new(world.seed);
+        let band = blend_seams(&mut world, &owner, &generator);
+        let seams = |(x, y): (usize, usize)| is_seam(&owner, width, height, x, y, false);
+        extend_rivers(&mut world, &seams);
+        // Blending may have raised a river's bed above the tile upstream
+        for river in &world.rivers {
+            for i in 1..river.len() {
+                let (a, b) = (river[i - 1], river[i]);
+                if band.contains(&b) {
+                    let upstream = world.terrain[a.1][a.0].elevation;
+                    let point = &mut world.terrain[b.1][b.0];
+                    point.elevation = point.elevation.min(upstream);
+                }
+            }
+        }
+        for &(x, y) in world.rivers.iter().flatten() {
+            let point = &mut world.terrain[y][x];
+            if !point.biome.is_water() {
+                point.biome = Biome::River;
+            }
+        }
+        join_roads(&mut world, &mut generator, &owner, &seams);
+        world.regions = world.traced_regions();
+        world.prune_parks();
+        world.coastlines = world.traced_coastlines();
+        world.smoothed = world.smoothed_paths();
+        world
+    }
+}
+
+/// The runs of `path` where `keep` holds that are at least a step long.
+fn split_where(path: &[(usize, usize)], keep: impl Fn(&(usize, usize)) -> bool) -> Vec<Vec<(usize, usize)>> {
+    let mut runs: Vec<Vec<(usize, usize)>> = vec![Vec::new()];
+    for tile in path {
+        if keep(tile) {
+            runs.last_mut().unwrap().push(*tile);
+        } else if !runs.last().unwrap().is_empty() {
+            runs.push(Vec::new());
+        }
+    }
+    runs.retain(|run| run.len() >= 2);
+    runs
+}
+
+/// Whether (`x`, `y`) borders a tile of another piece, or with `open_sea`
+/// also one no piece covers.
+fn is_seam(owner: &[Option<usize>], width: usize, height: usize, x: usize, y: usize, open_sea: bool) -> bool {
+    let here = owner[y * width + x];
+    [(0isize, -1isize), (-1, 0), (1, 0), (0, 1)].iter().any(|&(dx, dy)| {
+        let (Some(nx), Some(ny)) = (x.checked_add_signed(dx), y.checked_add_signed(dy)) else {
+            return false;
+        };
+        if nx >= width || ny >= height {
+            return false;
+        }
+        let there = owner[ny * width + nx];
+        there != here && (open_sea || (here.is_some() && there.is_some()))
+    })
+}
+
+/// Blend the fields over `SEAM_BLEND` tiles either side of every seam,
+/// fading from the mean of the surrounding square at the seam to the
+/// piece's own values, and classify the biomes there again. Returns the
+/// tiles changed.
+fn blend_seams(world: &mut TerrainMap, owner: &[Option<usize>], generator: &TerrainGenerator) -> HashSet<(usize, usize)> {
+    let (width, height) = (world.width, world.height);
+    let mut distance = vec![usize::MAX; width * height];
+    let mut queue = VecDeque::new();
+    for y in 0..height {
+        for x in 0..width {
+            if is_seam(owner, width, height, x, y, true) {
+                distance[y * width + x] = 0;
+                queue.push_back((x, y));
+            }
+        }
+    }
+    while let Some((x, y)) = queue.pop_front() {
+        let d = distance[y * width + x];
+        if d + 1 >= SEAM_BLEND {
+            continue;
+        }
+        for (dx, dy) in [(0isize, -1isize), (-1, 0), (1, 0), (0, 1)] {
+            let Some((nx, ny)) = world.offset(x, y, dx, dy) else {
+                continue;
+            };
+            if distance[ny * width + nx] == usize::MAX {
+                distance[ny * width + nx] = d + 1;
+                queue.push_back((nx, ny));
+            }
+        }
+    }
+
+    let fields: [fn(&TerrainPoint) -> f64; 3] = [|p| p.elevation, |p| p.moisture, |p| p.temperature];
+    let sums: Vec<Vec<f64>> = fields.iter().map(|field| summed_area(&world.terrain, *field)).collect();
+    let mut band = HashSet::new();
+    let reach = SEAM_BLEND as isize;
+    for y in 0..height {
+        for x in 0..width {
+            let d = distance[y * width + x];
+            if d >= SEAM_BLEND || owner[y * width + x].is_none() {
+                continue;
+            }
+            let x0 = (x as isize - reach).max(0) as usize;
+            let y0 = (y as isize - reach).max(0) as usize;
+            let (x1, y1) = ((x + SEAM_BLEND + 1).min(width), (y + SEAM_BLEND + 1).min(height));
+            let area = ((x1 - x0) * (y1 - y0)) as f64;
+            let mut mean = [0.0; 3];
+            for (value, sum) in mean.iter_mut().zip(&sums) {
+                let at = |x: usize, y: usize| sum[y * (width + 1) + x];
+                *value = (at(x1, y1) - at(x0, y1) - at(x1, y0) + at(x0, y0)) / area;
+            }
+            let own = 1.0 - (1.0 - d as f64 / SEAM_BLEND as f64).powi(2);
+            let point = &mut world.terrain[y][x];
+            point.elevation = point.elevation * own + mean[0] * (1.0 - own);
+            point.moisture = point.moisture * own + mean[1] * (1.0 - own);
+            point.temperature = point.temperature * own + mean[2] * (1.0 - own);
+            let keeps_water = point.biome == Biome::Lake || (point.biome == Biome::River && point.elevation >= 0.0);
+            if !keeps_water {
+                point.biome = generator.determine_biome(point.elevation, point.moisture, point.temperature);
+            }
+            band.insert((x, y));
+        }
+    }
+    band
+}
+
+/// Summed-area table of `field` over `terrain`, one row and column larger.
+fn summed_area(terrain: &[Vec<TerrainPoint>], field: fn(&TerrainPoint) -> f64) -> Vec<f64> {
+    let width = terrain.first().map_or(0, Vec::len);
+    let mut sums = vec![0.0; (width + 1) * (terrain.len() + 1)];
+    for (y, row) in terrain.iter().enumerate() {
+        for (x, point) in row.iter().enumerate() {
+            sums[(y + 1) * (width + 1) + x + 1] =
+                field(point) + sums[y * (width + 1) + x + 1] + sums[(y + 1) * (width + 1) + x] - sums[y * (width + 1) + x];
+        }
+    }
+    sums
+}
+
+/// Carry each river that ends on a seam on downhill, steepest first, until
+/// it reaches water or another river, or finds nothing lower.
+fn extend_rivers(world: &mut TerrainMap, on_seam: &impl Fn((usize, usize)) -> bool) {
+    let mut river_tiles: HashSet<(usize, usize)> = world.rivers.iter().flatten().copied().collect();
+    for index in 0..world.rivers.len() {
+        let Some(&end) = world.rivers[index].last() else {
+            continue;
+        };
+        if !on_seam(end) || world.is_water(end.0, end.1) {
+            continue;
+        }
+        let own: HashSet<(usize, usize)> = world.rivers[index].iter().copied().collect();
+        let mut at = end;
+        for _ in 0..RIVER_REACH {
+            let elevation = |(x, y): (usize, usize)| world.terrain[y][x].elevation;
+            let lowest = (-1..=1)
+                .flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+                .filter_map(|(dx, dy)| world.offset(at.0, at.1, dx, dy))
+                .filter(|tile| !own.contains(tile))
+                .min_by(|&a, &b| elevation(a).total_cmp(&elevation(b)));
+            let Some(next) = lowest.filter(|&next| elevation(next) < elevation(at)) else {
+                break;
+            };
+            world.rivers[index].push(next);
+            at = next;
+            if world.is_water(next.0, next.1) || river_tiles.contains(&next) {
+                break;
+            }
+        }
+        river_tiles.extend(world.rivers[index].iter().copied());
+    }
+}
+
+/// Join pairs of roads that end on a seam between different pieces, the
+/// nearest pairs first.
+fn join_roads(
+    world: &mut TerrainMap,
+    generator: &mut TerrainGenerator,
+    owner: &[Option<usize>],
+    on_seam: &impl Fn((usize, usize)) -> bool,
+) {
+    let piece_at = |(x, y): (usize, usize)| owner[y * world.width + x];
+    let mut tried: HashSet<((usize, usize), (usize, usize))> = HashSet::new();
+    loop {
+        // Road ends on a seam: (road, at its start, tile)
+        let ends: Vec<(usize, bool, (usize, usize))> = world
+            .roads
+            .iter()
+            .enumerate()
+            .flat_map(|(index, road)| [(index, true, road.path[0]), (index, false, *road.path.last().unwrap())])
+            .filter(|&(_, _, tile)| on_seam(tile))
+            .collect();
+        let mut best: Option<(f64, usize, usize)> = None;
+        for (i, a) in ends.iter().enumerate() {
+            for (j, b) in ends.iter().enumerate().skip(i + 1) {
+                let distance = (a.2 .0 as f64 - b.2 .0 as f64).hypot(a.2 .1 as f64 - b.2 .1 as f64);
+                let apart = a.0 != b.0 && piece_at(a.2) != piece_at(b.2) && !tried.contains(&(a.2, b.2));
+                if apart && distance <= ROAD_JOIN && best.is_none_or(|(d, _, _)| distance < d) {
+                    best = Some((distance, i, j));
+                }
+            }
+        }
+        let Some((_, i, j)) = best else {
+            return;
+        };
+        let ((road_a, start_a, from), (road_b, start_b, to)) = (ends[i], ends[j]);
+        let on_land = |(x, y): (usize, usize)| !world.is_water(x, y);
+        let mut crossings = Crossings::of_map(world);
+        let link = if on_land(from) && on_land(to) {
+            generator.find_path(&world.terrain, &crossings, from.0, from.1, to.0, to.1)
+        } else {
+            Vec::new()
+        };
+        if link.len() < 2 {
+            // Ends that cannot be joined are left as they are
+            tried.insert((from, to));
+            continue;
+        }
+        generator.used_names = UsedNames::of_map(world);
+        let road_type = world.roads[road_a].road_type.clone();
+        let (new_bridges, new_fords) =
+            generator.detect_crossings(&link, &road_type, &mut crossings, &world.terrain, &mut world.bridges);
+
+        // a's path ending at `from`, the link, then b's path from `to`
+        let mut b = world.roads.remove(road_b);
+        let a = &mut world.roads[if road_a > road_b { road_a - 1 } else { road_a }];
+        if start_a {
+            a.path.reverse();
+        }
+        if !start_b {
+            b.path.reverse();
+        }
+        a.path.extend_from_slice(&link[1..link.len() - 1]);
+        a.path.append(&mut b.path);
+        a.bridges.extend(new_bridges);
+        a.bridges.append(&mut b.bridges);
+        a.fords.extend(new_fords);
+        a.fords.append(&mut b.fords);
+        if road_rank(&b.road_type) > road_rank(&a.road_type) {
+            a.road_type = b.road_type;
+        }
+    }
+}
+
+fn road_rank(road_type: &str) -> u8 {
+    match road_type {
+        "motorway" => 3,
+        "highway" => 2,
+        "road" => 1,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::OnceLock;
+
+    use super::*;
+    use crate::terrain_generator::{test_map, Region, Severity};
+
+    fn whole() -> &'static TerrainMap {
+        test_map(11, 160, 100)
+    }
+
+    /// `whole()` cut in two down the middle and stitched back together
+    fn rejoined() -> &'static TerrainMap {
+        static WORLD: OnceLock<TerrainMap> = OnceLock::new();
+        WORLD.get_or_init(|| {
+            let half = |x: usize| whole().crop(Region { x, y: 0, width: 80, height: 100 });
+            TerrainMap::stitch(&[((0, 0), half(0)), ((80, 0), half(80))])
+        })
+    }
+
+    fn west() -> &'static TerrainMap {
+        test_map(3, 100, 80)
+    }
+
+    fn east() -> &'static TerrainMap {
+        test_map(4, 100, 90)
+    }
+
+    /// Two unrelated maps side by side, the western one shorter
+    fn unrelated() -> &'static TerrainMap {
+        static WORLD: OnceLock<TerrainMap> = OnceLock::new();
+        WORLD.get_or_init(|| TerrainMap::stitch(&[((0, 0), west().clone()), ((100, 0), east().clone())]))
+    }
+
+    fn assert_no_errors(world: &TerrainMap) {
+        let report = world.validate();
+        assert!(report.problems.iter().all(|p| p.severity() != Severity::Error), "{:?}", report.problems);
+    }
+
+    #[test]
+    fn a_map_cut_in_two_goes_back_together() {
+        let (map, world) = (whole(), rejoined());
+        assert_eq!((world.width, world.height), (160, 100));
+        assert_eq!(world.cities.len(), map.cities.len());
+        assert_eq!(world.terrain[50][20].elevation, map.terrain[50][20].elevation);
+        assert_no_errors(world);
+    }
+
+    #[test]
+    fn roads_are_joined_across_the_cut() {
+        let world = rejoined();
+        let crosses = |m: &TerrainMap| m.roads.iter().filter(|r| r.path.iter().any(|t| t.0 < 80) && r.path.iter().any(|t| t.0 >= 80)).count();
+        assert!(crosses(world) > 0);
+        // No two roads are left ending either side of the cut
+        let ends: Vec<usize> = world.roads.iter().flat_map(|r| [r.path[0].0, r.path[r.path.len() - 1].0]).collect();
+        assert!(!(ends.contains(&79) && ends.contains(&80)));
+    }
+
+    #[test]
+    fn unrelated_pieces_leave_open_sea_below_the_shorter_one() {
+        let world = unrelated();
+        assert_eq!((world.width, world.height), (200, 90));
+        assert_eq!(world.terrain[85][20].biome, Biome::DeepOcean);
+        assert_eq!(world.terrain[40][150].biome, east().terrain[40][50].biome);
+        assert_no_errors(world);
+    }
+
+    #[test]
+    fn unrelated_pieces_meet_without_a_cliff() {
+        let (world, west, east) = (unrelated(), west(), east());
+        let step = |x: usize, y: usize| (world.terrain[y][x + 1].elevation - world.terrain[y][x].elevation).abs();
+        let seam: f64 = (0..80).map(|y| step(99, y)).sum();
+        let cliff: f64 = (0..80).map(|y| (east.terrain[y][0].elevation - west.terrain[y][99].elevation).abs()).sum();
+        assert!(seam < cliff / 4.0, "seam {} cliff {}", seam, cliff);
+    }
+
+    #[test]
+    fn stitching_nothing_makes_an_empty_map() {
+        assert!(TerrainMap::stitch(&[]).terrain.is_empty());
+    }
+}
@@ -0,0 +1,224 @@
+//! Symmetric maps, for multiplayer games where every side should start
+//! equal.
+//!
+//! A [`Symmetry`] other than `None` makes the terrain look the same from
+//! each of its sides: the raw elevation field is copied from one part of
+//! the map (the tiles that are their own [`Symmetry::source`]) to the rest
+//! before the sea level is chosen, the climate grid likewise, and the
+//! rivers traced from that part are mirrored along with it. With
+//! `GenerationSettings::symmetric_cities` cities are only sited in that
+//! part too, each with a copy of the same size in every other, and the
+//! metro areas around them are copied as well. Roads are then found over
+//! the mirrored terrain as usual, so they are symmetric wherever the
+//! cheapest routes are.
+
+use serde::{Deserialize, Serialize};
+
+use super::TerrainGenerator;
+
+/// How a map repeats itself.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Symmetry {
+    #[default]
+    None,
+    /// The east half mirrors the west: two sides, facing across the middle
+    Mirror,
+    /// A half turn about the centre leaves the map as it was: two sides,
+    /// opposite each other
+    Rotate,
+    /// Mirrored both east-west and north-south: four quarters alike
+    Quarters,
+}
+
+impl Symmetry {
+    pub const ALL: [Symmetry; 4] = [Symmetry::None, Symmetry::Mirror, Symmetry::Rotate, Symmetry::Quarters];
+
+    pub const NAMES: [&'static str; 4] = ["none", "mirror", "rotate", "quarters"];
+
+    /// A symmetry by name, as in `NAMES`.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "none" => Some(Symmetry::None),
+            "mirror" | "2-mirror" => Some(Symmetry::Mirror),
+            "rotate" | "2-fold" => Some(Symmetry::Rotate),
+            "quarters" | "4-fold" => Some(Symmetry::Quarters),
+            _ => None,
+        }
+    }
+
+    /// The number of sides alike.
+    pub fn sides(self) -> usize {
+        self.flips().len()
+    }
+
+    /// The tiles that look the same as (`x`, `y`) on a map `width` by
+    /// `height`, (`x`, `y`) itself first, without repeats.
+    pub fn images(self, x: usize, y: usize, width: usize, height: usize) -> Vec<(usize, usize)> {
+        let mut images: Vec<(usize, usize)> = Vec::with_capacity(self.sides());
+        for &flip in self.flips() {
+            let image = flipped(flip, x, y, width, height);
+            if !images.contains(&image) {
+                images.push(image);
+            }
+        }
+        images
+    }
+
+    /// The tile (`x`, `y`) is copied from: the first of its images in
+    /// reading order.
+    pub fn source(self, x: usize, y: usize, width: usize, height: usize) -> (usize, usize) {
+        self.flips()
+            .iter()
+            .map(|&flip| flipped(flip, x, y, width, height))
+            .min_by_key(|&(x, y)| (y, x))
+            .unwrap_or((x, y))
+    }
+
+    /// Whether (`x`, `y`) is in the part of the map the rest is copied
+    /// from.
+    pub fn is_source(self, x: usize, y: usize, width: usize, height: usize) -> bool {
+        self.source(x, y, width, height) == (x, y)
+    }
+
+    /// Copy every tile of `grid` from its source.
+    pub(super) fn apply<T: Clone>(self, grid: &mut [Vec<T>]) {
+        if self == Symmetry::None {
+            return;
+        }
+        let height = grid.len();
+        let width = grid.first().map_or(0, Vec::len);
+        for y in 0..height {
+            for x in 0..width {
+                let (sx, sy) = self.source(x, y, width, height);
+                if (sx, sy) != (x, y) {
+                    grid[y][x] = grid[sy][sx].clone();
+                }
+            }
+        }
+    }
+
+    /// The paths of `paths` that start in the source part, each followed
+    /// by its images elsewhere. A path that is its own image is kept once.
+    pub(super) fn apply_to_paths(self, paths: Vec<Vec<(usize, usize)>>, width: usize, height: usize) -> Vec<Vec<(usize, usize)>> {
+        if self == Symmetry::None {
+            return paths;
+        }
+        let mut mirrored = Vec::with_capacity(paths.len());
+        for path in paths {
+            let Some(&(x, y)) = path.first() else {
+                continue;
+            };
+            if !self.is_source(x, y, width, height) {
+                continue;
+            }
+            let first = mirrored.len();
+            for &flip in self.flips() {
+                let image: Vec<(usize, usize)> = path.iter().map(|&(x, y)| flipped(flip, x, y, width, height)).collect();
+                if !mirrored[first..].contains(&image) {
+                    mirrored.push(image);
+                }
+            }
+        }
+        mirrored
+    }
+
+    /// Image `k` of (`x`, `y`), `k` below `sides`, counting repeats: the
+    /// tile the `k`th copy of the map puts there.
+    pub(super) fn image(self, k: usize, x: usize, y: usize, width: usize, height: usize) -> (usize, usize) {
+        flipped(self.flips()[k], x, y, width, height)
+    }
+
+    /// Whether each image is flipped east-west and north-south, the
+    /// unflipped original first.
+    fn flips(self) -> &'static [(bool, bool)] {
+        match self {
+            Symmetry::None => &[(false, false)],
+            Symmetry::Mirror => &[(false, false), (true, false)],
+            Symmetry::Rotate => &[(false, false), (true, true)],
+            Symmetry::Quarters => &[(false, false), (true, false), (false, true), (true, true)],
+        }
+    }
+}
+
+impl TerrainGenerator {
+    /// The symmetry cities are placed with: the map's, if they follow it.
+    pub(super) fn city_symmetry(&self) -> Symmetry {
+        if self.settings.symmetric_cities {
+            self.settings.symmetry
+        } else {
+            Symmetry::None
+        }
+    }
+}
+
+fn flipped(flip: (bool, bool), x: usize, y: usize, width: usize, height: usize) -> (usize, usize) {
+    (
+        if flip.0 { width - 1 - x } else { x },
+        if flip.1 { height - 1 - y } else { y },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::OnceLock;
+
+    use super::*;
+    use crate::terrain_generator::{GenerationSettings, TerrainMap};
+
+    /// Seed 21 at 120x90 with symmetric cities, under each symmetry
+    fn symmetric_maps() -> &'static [(Symmetry, TerrainMap)] {
+        static MAPS: OnceLock<Vec<(Symmetry, TerrainMap)>> = OnceLock::new();
+        MAPS.get_or_init(|| {
+            [Symmetry::Mirror, Symmetry::Rotate, Symmetry::Quarters]
+                .into_iter()
+                .map(|symmetry| {
+                    let settings = GenerationSettings {
+                        symmetry,
+                        symmetric_cities: true,
+                        city_density: 0.8,
+                        ..Default::default()
+                    };
+                    (symmetry, TerrainGenerator::new_with_settings(21, settings).generate(120, 90))
+                })
+                .collect()
+        })
+    }
+
+    #[test]
+    fn tiles_have_their_images_and_sources() {
+        assert_eq!(Symmetry::Rotate.images(1, 2, 10, 8), vec![(1, 2), (8, 5)]);
+        assert_eq!(Symmetry::Quarters.images(4, 0, 9, 8), vec![(4, 0), (4, 7)]);
+        assert_eq!(Symmetry::Mirror.source(7, 3, 10, 8), (2, 3));
+    }
+
+    #[test]
+    fn symmetric_maps_look_the_same_from_every_side() {
+        for (symmetry, map) in symmetric_maps() {
+            for y in 0..map.height {
+                for x in 0..map.width {
+                    for (ix, iy) in symmetry.images(x, y, map.width, map.height) {
+                        let (a, b) = (&map.terrain[y][x], &map.terrain[iy][ix]);
+                        assert_eq!((a.elevation, a.biome), (b.elevation, b.biome), "{:?} at {},{}", symmetry, x, y);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn every_river_and_city_has_its_counterparts() {
+        for (symmetry, map) in symmetric_maps() {
+            let rivers: Vec<_> = map.rivers.iter().map(|r| r[0]).collect();
+            let cities: Vec<_> = map.cities.iter().map(|c| (c.x, c.y, c.population)).collect();
+            assert!(!cities.is_empty());
+            for &(x, y) in &rivers {
+                assert!(symmetry.images(x, y, 120, 90).iter().all(|image| rivers.contains(image)));
+            }
+            for &(x, y, population) in &cities {
+                let images = symmetry.images(x, y, 120, 90);
+                assert!(images.iter().all(|&(ix, iy)| cities.contains(&(ix, iy, population))), "{:?}", symmetry);
+            }
+        }
+    }
+}
@@ -0,0 +1,108 @@
+//! Territories: every land tile assigned to the city it is quickest to
+//! reach from, a Voronoi diagram measured in travel time rather than
+//! straight-line distance. Mountains and sea push borders towards the
+//! harder side and roads pull them outwards.
+
+use super::travel::{TravelGrid, TravelSpeeds};
+use super::types::TerrainMap;
+
+/// The city each land tile belongs to, row-major.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Territories {
+    pub width: usize,
+    pub height: usize,
+    /// Index into `TerrainMap::cities`; `None` on water and on land no
+    /// city can reach
+    pub owner: Vec<Option<usize>>,
+}
+
+impl Territories {
+    /// The city that (x, y) belongs to; `None` outside the map.
+    pub fn owner_at(&self, x: usize, y: usize) -> Option<usize> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        self.owner[y * self.width + x]
+    }
+
+    /// Land tiles per city, indexed like `TerrainMap::cities`.
+    pub fn areas(&self, city_count: usize) -> Vec<usize> {
+        let mut areas = vec![0; city_count];
+        for &city in self.owner.iter().flatten() {
+            if let Some(area) = areas.get_mut(city) {
+                *area += 1;
+            }
+        }
+        areas
+    }
+}
+
+impl TerrainMap {
+    /// Assign every land tile to the city quickest to reach from it at the
+    /// default [`TravelSpeeds`], so land across a strait belongs to the
+    /// port that ferries reach it from.
+    pub fn territories(&self) -> Territories {
+        let tiles: Vec<usize> = self.cities.iter().map(|c| c.y * self.width + c.x).collect();
+        let grid = TravelGrid::new(self, &TravelSpeeds::default(), 1.0);
+        let mut owner = grid.nearest_source(&tiles);
+        for (tile, point) in owner.iter_mut().zip(self.terrain.iter().flatten()) {
+            if point.biome.is_water() {
+                *tile = None;
+            }
+        }
+        Territories {
+            width: self.width,
+            height: self.height,
+            owner,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::OnceLock;
+
+    use crate::terrain_generator::test_map;
+
+    use super::*;
+
+    fn map() -> &'static TerrainMap {
+        test_map(12, 160, 120)
+    }
+
+    /// The map's territories, worked out once
+    fn territories() -> &'static Territories {
+        static TERRITORIES: OnceLock<Territories> = OnceLock::new();
+        TERRITORIES.get_or_init(|| map().territories())
+    }
+
+    #[test]
+    fn every_city_owns_its_own_tile() {
+        for (index, city) in map().cities.iter().enumerate() {
+            assert_eq!(territories().owner_at(city.x, city.y), Some(index));
+        }
+    }
+
+    #[test]
+    fn all_the_land_is_owned_and_none_of_the_water() {
+        let map = map();
+        for y in 0..map.height {
+            for x in 0..map.width {
+                // Every tile is reachable by land or by boat
+                assert_eq!(territories().owner_at(x, y).is_some(), !map.is_water(x, y));
+            }
+        }
+        assert!(territories().owner_at(map.width, 0).is_none());
+    }
+
+    #[test]
+    fn areas_add_up_to_the_land() {
+        let map = map();
+        let areas = territories().areas(map.cities.len());
+        let land = (0..map.height)
+            .flat_map(|y| (0..map.width).map(move |x| (x, y)))
+            .filter(|&(x, y)| !map.is_water(x, y))
+            .count();
+        assert_eq!(areas.iter().sum::<usize>(), land);
+    }
+}
@@ -0,0 +1,538 @@
+//! Travel times: between every pair of cities, and along routes for a
+//! [`TravelProfile`].
+//!
+//! Journeys cross the tile grid at a speed set by what each tile holds:
+//! roads by their type, open country by its biome, and water by boat.
+//! Going aboard or ashore costs a fixed wait, so short ferry hops and long
+//! sea voyages are both possible but neither is free.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::fmt::Write;
+
+use rayon::prelude::*;
+use serde::Serialize;
+
+use super::biome::Biome;
+use super::types::TerrainMap;
+
+/// Speeds, in km/h, that travel times are worked out with. The defaults
+/// are a loaded caravan's.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TravelSpeeds {
+    pub highway: f64,
+    pub road: f64,
+    pub trail: f64,
+    /// Off-road on plains; other biomes are slower (see [`terrain_factor`])
+    pub open_country: f64,
+    /// Sea, shore and lake tiles, by ship or ferry
+    pub water: f64,
+    /// Hours lost each time a journey boards or leaves a boat
+    pub embark_hours: f64,
+}
+
+impl Default for TravelSpeeds {
+    fn default() -> Self {
+        Self {
+            highway: 6.0,
+            road: 5.0,
+            trail: 4.0,
+            open_country: 3.0,
+            water: 8.0,
+            embark_hours: 6.0,
+        }
+    }
+}
+
+impl TravelSpeeds {
+    /// Speed on a road of `road_type`; motorways count as highways and
+    /// unknown types as trails.
+    fn on_road(&self, road_type: &str) -> f64 {
+        match road_type {
+            "motorway" | "highway" => self.highway,
+            "road" => self.road,
+            _ => self.trail,
+        }
+    }
+}
+
+/// Share of the open-country speed kept off-road in `biome`. River tiles
+/// away from a bridge have to be forded.
+fn terrain_factor(biome: Biome) -> f64 {
+    match biome {
+        Biome::Plains => 1.0,
+        Biome::Beach => 0.9,
+        Biome::Desert => 0.7,
+        Biome::Forest | Biome::Hills => 0.6,
+        Biome::Swamp => 0.35,
+        Biome::Mountains => 0.3,
+        Biome::River => 0.25,
+        Biome::SnowPeaks => 0.15,
+        Biome::DeepOcean | Biome::Ocean | Biome::Shore | Biome::Lake => 1.0,
+    }
+}
+
+/// Hours between every pair of a map's cities.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TravelTimes {
+    pub km_per_tile: f64,
+    /// City names, in the map's city order
+    pub cities: Vec<String>,
+    /// `hours[i][j]` from city `i` to city `j`; `None` if no land or water
+    /// path joins them
+    pub hours: Vec<Vec<Option<f64>>>,
+}
+
+impl TravelTimes {
+    /// The matrix as CSV: a header row of city names, then one row per
+    /// city. Hours have one decimal; unreachable pairs are left empty.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("city");
+        for name in &self.cities {
+            csv.push(',');
+            csv.push_str(&csv_field(name));
+        }
+        csv.push('\n');
+        for (name, row) in self.cities.iter().zip(&self.hours) {
+            csv.push_str(&csv_field(name));
+            for hours in row {
+                csv.push(',');
+                if let Some(hours) = hours {
+                    let _ = write!(csv, "{:.1}", hours);
+                }
+            }
+            csv.push('\n');
+        }
+        csv
+    }
+}
+
+/// Quote a CSV field if it needs it.
+pub(super) fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+/// How a route travels: each profile has its own [`TravelSpeeds`] and
+/// keeps to the ground it can cross.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TravelProfile {
+    /// Walking, anywhere on land
+    OnFoot,
+    /// Riding, anywhere on land
+    Horse,
+    /// A wagon, on roads only
+    Cart,
+    /// Sailing, on water only; routes may start and end in a port on the
+    /// shore
+    Boat,
+}
+
+impl TravelProfile {
+    pub const ALL: [TravelProfile; 4] = [
+        TravelProfile::OnFoot,
+        TravelProfile::Horse,
+        TravelProfile::Cart,
+        TravelProfile::Boat,
+    ];
+
+    /// Lower-case name, as the CLI takes it.
+    pub fn name(self) -> &'static str {
+        match self {
+            TravelProfile::OnFoot => "foot",
+            TravelProfile::Horse => "horse",
+            TravelProfile::Cart => "cart",
+            TravelProfile::Boat => "boat",
+        }
+    }
+
+    /// The profile called `name`, ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|profile| profile.name().eq_ignore_ascii_case(name.trim()))
+    }
+
+    /// Speeds for this profile; a speed of zero is ground it cannot cross.
+    pub fn speeds(self) -> TravelSpeeds {
+        let none = TravelSpeeds {
+            highway: 0.0,
+            road: 0.0,
+            trail: 0.0,
+            open_country: 0.0,
+            water: 0.0,
+            embark_hours: 0.0,
+        };
+        match self {
+            TravelProfile::OnFoot => TravelSpeeds {
+                highway: 5.0,
+                road: 5.0,
+                trail: 4.5,
+                open_country: 4.0,
+                ..none
+            },
+            TravelProfile::Horse => TravelSpeeds {
+                highway: 10.0,
+                road: 9.0,
+                trail: 7.0,
+                open_country: 6.0,
+                ..none
+            },
+            TravelProfile::Cart => TravelSpeeds {
+                highway: 5.0,
+                road: 4.0,
+                trail: 3.0,
+                ..none
+            },
+            TravelProfile::Boat => TravelSpeeds {
+                water: 10.0,
+                embark_hours: 1.0,
+                ..none
+            },
+        }
+    }
+}
+
+/// Search queue entry, ordered so the lowest priority pops first.
+struct Visit {
+    priority: f64,
+    /// Hours to reach `tile` when it was queued
+    hours: f64,
+    tile: usize,
+}
+
+impl PartialEq for Visit {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Visit {}
+
+impl PartialOrd for Visit {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Visit {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.priority.total_cmp(&self.priority)
+    }
+}
+
+/// A map's tiles priced in hours for one set of speeds. Each step takes
+/// half its length at the speed of the tile it leaves and half at the tile
+/// it enters; a tile on a road goes at the fastest road through it.
+pub(super) struct TravelGrid<'a> {
+    map: &'a TerrainMap,
+    km_per_tile: f64,
+    embark_hours: f64,
+    /// km/h per tile, row-major; zero where the speeds forbid it
+    speed: Vec<f64>,
+    water: Vec<bool>,
+    /// The highest speed anywhere, for A*'s estimate
+    fastest: f64,
+}
+
+impl<'a> TravelGrid<'a> {
+    pub(super) fn new(map: &'a TerrainMap, speeds: &TravelSpeeds, km_per_tile: f64) -> Self {
+        let mut speed: Vec<f64> = map
+            .terrain
+            .iter()
+            .flatten()
+            .map(|point| {
+                if point.biome.is_water() {
+                    speeds.water
+                } else {
+                    speeds.open_country * terrain_factor(point.biome)
+                }
+            })
+            .collect();
+        for road in &map.roads {
+            let on_road = speeds.on_road(&road.road_type);
+            for &(x, y) in &road.path {
+                let tile = &mut speed[y * map.width + x];
+                *tile = tile.max(on_road);
+            }
+        }
+        let water = map
+            .terrain
+            .iter()
+            .flatten()
+            .map(|point| point.biome.is_water())
+            .collect();
+        let fastest = speed.iter().copied().fold(0.0, f64::max);
+        Self {
+            map,
+            km_per_tile,
+            embark_hours: speeds.embark_hours,
+            speed,
+            water,
+            fastest,
+        }
+    }
+
+    /// Hours from `tile` to the neighbouring `next`, or `None` if the
+    /// speeds forbid it. The tiles in `ends` can always be entered and
+    /// left, so a boat can put out from a city on the shore; their half of
+    /// the step goes at the other tile's speed.
+    fn step_hours(&self, tile: usize, next: usize, ends: &[usize]) -> Option<f64> {
+        let width = self.map.width;
+        let diagonal = tile % width != next % width && tile / width != next / width;
+        let km = if diagonal { self.km_per_tile * std::f64::consts::SQRT_2 } else { self.km_per_tile };
+        let (mut from, mut to) = (self.speed[tile], self.speed[next]);
+        if from <= 0.0 && ends.contains(&tile) {
+            from = to;
+        }
+        if to <= 0.0 && ends.contains(&next) {
+            to = from;
+        }
+        if from <= 0.0 || to <= 0.0 {
+            return None;
+        }
+        let mut hours = km / 2.0 / from + km / 2.0 / to;
+        if self.water[tile] != self.water[next] {
+            hours += self.embark_hours;
+        }
+        Some(hours)
+    }
+
+    /// The eight tiles around `tile` that lie on the map, across the seam
+    /// of one that wraps east-west.
+    fn neighbours(&self, tile: usize) -> impl Iterator<Item = usize> + '_ {
+        let width = self.map.width;
+        let (x, y) = (tile % width, tile / width);
+        (-1..=1)
+            .flat_map(move |dy| (-1..=1).map(move |dx| (dx, dy)))
+            .filter(|&step| step != (0, 0))
+            .filter_map(move |(dx, dy)| self.map.offset(x, y, dx, dy))
+            .map(move |(nx, ny)| ny * width + nx)
+    }
+
+    /// Hours from `start` to every tile; infinite where it cannot go.
+    fn hours_from(&self, start: usize, ends: &[usize]) -> Vec<f64> {
+        let mut best = vec![f64::INFINITY; self.speed.len()];
+        let mut heap = BinaryHeap::new();
+        best[start] = 0.0;
+        heap.push(Visit { priority: 0.0, hours: 0.0, tile: start });
+
+        while let Some(Visit { hours, tile, .. }) = heap.pop() {
+            if hours > best[tile] {
+                continue;
+            }
+            for next in self.neighbours(tile) {
+                let Some(step) = self.step_hours(tile, next, ends) else {
+                    continue;
+                };
+                if hours + step < best[next] {
+                    best[next] = hours + step;
+                    heap.push(Visit { priority: hours + step, hours: hours + step, tile: next });
+                }
+            }
+        }
+        best
+    }
+
+    /// For every tile, the index into `sources` of the source it is
+    /// quickest to reach from; `None` where no source can get to.
+    pub(super) fn nearest_source(&self, sources: &[usize]) -> Vec<Option<usize>> {
+        let mut best = vec![f64::INFINITY; self.speed.len()];
+        let mut owner = vec![None; self.speed.len()];
+        let mut heap = BinaryHeap::new();
+        for (index, &tile) in sources.iter().enumerate() {
+            // Two sources on one tile: the first keeps it
+            if owner[tile].is_none() {
+                best[tile] = 0.0;
+                owner[tile] = Some(index);
+                heap.push(Visit { priority: 0.0, hours: 0.0, tile });
+            }
+        }
+
+        while let Some(Visit { hours, tile, .. }) = heap.pop() {
+            if hours > best[tile] {
+                continue;
+            }
+            for next in self.neighbours(tile) {
+                let Some(step) = self.step_hours(tile, next, sources) else {
+                    continue;
+                };
+                if hours + step < best[next] {
+                    best[next] = hours + step;
+                    owner[next] = owner[tile];
+                    heap.push(Visit { priority: hours + step, hours: hours + step, tile: next });
+                }
+            }
+        }
+        owner
+    }
+
+    /// The quickest path from `from` to `to` and its hours, by A*.
+    pub(super) fn fastest_path(
+        &self,
+        from: (usize, usize),
+        to: (usize, usize),
+    ) -> Option<(Vec<(usize, usize)>, f64)> {
+        if self.fastest <= 0.0 {
+            return None;
+        }
+        let width = self.map.width;
+        let (start, goal) = (from.1 * width + from.0, to.1 * width + to.0);
+        let ends = [start, goal];
+        // Straight-line km at top speed never overestimates
+        let estimate = |tile: usize| {
+            let dx = (tile % width) as f64 - to.0 as f64;
+            let dy = (tile / width) as f64 - to.1 as f64;
+            dx.hypot(dy) * self.km_per_tile / self.fastest
+        };
+
+        let mut best = vec![f64::INFINITY; self.speed.len()];
+        let mut came_from: HashMap<usize, usize> = HashMap::new();
+        let mut heap = BinaryHeap::new();
+        best[start] = 0.0;
+        heap.push(Visit { priority: estimate(start), hours: 0.0, tile: start });
+
+        while let Some(Visit { hours, tile, .. }) = heap.pop() {
+            if tile == goal {
+                let mut path = vec![to];
+                let mut at = goal;
+                while let Some(&previous) = came_from.get(&at) {
+                    path.push((previous % width, previous / width));
+                    at = previous;
+                }
+                path.reverse();
+                return Some((path, best[goal]));
+            }
+            if hours > best[tile] {
+                continue;
+            }
+            for next in self.neighbours(tile) {
+                let Some(step) = self.step_hours(tile, next, &ends) else {
+                    continue;
+                };
+                let next_hours = hours + step;
+                if next_hours < best[next] {
+                    best[next] = next_hours;
+                    came_from.insert(next, tile);
+                    heap.push(Visit { priority: next_hours + estimate(next), hours: next_hours, tile: next });
+                }
+            }
+        }
+        None
+    }
+}
+
+impl TerrainMap {
+    /// Travel times between all cities at `speeds`, with tiles
+    /// `km_per_tile` across (see [`TravelGrid`] for how steps are timed).
+    pub fn travel_times(&self, speeds: &TravelSpeeds, km_per_tile: f64) -> TravelTimes {
+        let grid = TravelGrid::new(self, speeds, km_per_tile);
+        let city_tiles: Vec<usize> = self.cities.iter().map(|c| c.y * self.width + c.x).collect();
+        let hours = city_tiles
+            .par_iter()
+            .map(|&start| {
+                let best = grid.hours_from(start, &city_tiles);
+                city_tiles
+                    .iter()
+                    .map(|&end| Some(best[end]).filter(|h| h.is_finite()))
+                    .collect()
+            })
+            .collect();
+
+        TravelTimes {
+            km_per_tile,
+            cities: self.cities.iter().map(|c| c.name.clone()).collect(),
+            hours,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::OnceLock;
+
+    use crate::terrain_generator::test_map;
+
+    use super::*;
+
+    fn map() -> &'static TerrainMap {
+        let map = test_map(12, 160, 120);
+        assert!(map.cities.len() > 2);
+        map
+    }
+
+    /// The map's travel times at default speeds, worked out once
+    fn times() -> &'static TravelTimes {
+        static TIMES: OnceLock<TravelTimes> = OnceLock::new();
+        TIMES.get_or_init(|| map().travel_times(&TravelSpeeds::default(), 5.0))
+    }
+
+    #[test]
+    fn the_matrix_is_symmetric_and_staying_put_takes_no_time() {
+        let (n, times) = (map().cities.len(), times());
+        assert_eq!(times.hours.len(), n);
+        for i in 0..n {
+            assert_eq!(times.hours[i][i], Some(0.0));
+            for j in 0..n {
+                match (times.hours[i][j], times.hours[j][i]) {
+                    (Some(a), Some(b)) => assert!((a - b).abs() < 1e-6),
+                    (a, b) => assert_eq!(a, b),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn a_road_between_two_cities_is_at_worst_a_trail_all_the_way() {
+        let map = map();
+        let index_at = |tile: &(usize, usize)| map.cities.iter().position(|c| (c.x, c.y) == *tile);
+        for road in &map.roads {
+            let (Some(a), Some(b)) = (road.path.first().and_then(index_at), road.path.last().and_then(index_at)) else {
+                continue;
+            };
+            let km: f64 = road
+                .path
+                .windows(2)
+                .map(|w| if w[0].0 != w[1].0 && w[0].1 != w[1].1 { std::f64::consts::SQRT_2 } else { 1.0 })
+                .sum::<f64>()
+                * 5.0;
+            assert!(times().hours[a][b].unwrap() <= km / TravelSpeeds::default().trail + 1e-6);
+        }
+    }
+
+    #[test]
+    fn the_csv_has_a_row_and_a_column_for_every_city() {
+        let n = map().cities.len();
+        let csv = times().to_csv();
+        assert_eq!(csv.lines().count(), n + 1);
+        assert!(csv.lines().all(|line| line.matches(',').count() >= n));
+    }
+
+    #[test]
+    fn boats_keep_to_water_between_ports() {
+        let map = map();
+        let coastal = |c: &&crate::terrain_generator::City| {
+            (c.x.saturating_sub(1)..=c.x + 1)
+                .any(|x| (c.y.saturating_sub(1)..=c.y + 1).any(|y| map.is_water(x, y)))
+        };
+        let ports: Vec<_> = map.cities.iter().filter(coastal).collect();
+        assert!(ports.len() >= 2);
+        // Two ports on the same sea
+        let voyage = ports[1..]
+            .iter()
+            .find_map(|b| map.route((ports[0].x, ports[0].y), (b.x, b.y), TravelProfile::Boat))
+            .unwrap();
+        let inner = &voyage.path[1..voyage.path.len() - 1];
+        assert!(inner.iter().all(|&(x, y)| map.is_water(x, y)));
+        assert!(voyage.hours >= 2.0 * TravelProfile::Boat.speeds().embark_hours);
+    }
+
+    #[test]
+    fn profiles_are_found_by_name() {
+        assert_eq!(TravelProfile::from_name("Horse"), Some(TravelProfile::Horse));
+        assert!(TravelProfile::ALL.iter().all(|&p| TravelProfile::from_name(p.name()) == Some(p)));
+    }
+}
@@ -1,6 +1,15 @@
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
 use serde::{Deserialize, Serialize};
 
+use crate::error::MapperError;
+
 use super::biome::Biome;
+use super::drainage::RiverFlow;
+use super::poi::{PointOfInterest, PoiStyle};
+use super::symmetry::Symmetry;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TerrainPoint {
@@ -30,8 +39,20 @@ pub struct City {
 pub struct Road {
     pub path: Vec<(usize, usize)>,
     pub name: String,
-    pub road_type: String,    // "highway", "road", "trail"
+    pub road_type: String,    // "motorway", "highway", "road", "trail"
     pub bridges: Vec<Bridge>, // Bridges along this road
+    /// River tiles the road wades across, with no bridge
+    #[serde(default)]
+    pub fords: Vec<(usize, usize)>,
+}
+
+/// A power line strung straight between two cities, whatever the ground
+/// between; see the `modern` module.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PowerLine {
+    /// Tile (x, y) of the city at each end
+    pub from: (usize, usize),
+    pub to: (usize, usize),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -41,7 +62,54 @@ pub struct Bridge {
     pub name: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// The tiles of a region a label names: a sea, mountain range, forest or
+/// swamp.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NamedRegion {
+    pub name: String,
+    /// As its label's: "ocean", "mountains", "forest" or "swamp"
+    pub feature_type: String,
+    /// In reading order, row by row
+    pub tiles: Vec<(usize, usize)>,
+}
+
+impl NamedRegion {
+    /// Whether (`x`, `y`) is one of the region's tiles.
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        self.tiles.binary_search_by_key(&(y, x), |&(x, y)| (y, x)).is_ok()
+    }
+}
+
+/// A closed outline between land and water: points in tile coordinates,
+/// tile (x, y) at the point (x, y) as the renderer draws it, with land on
+/// the left walking from each point to the next and back to the first.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Coastline {
+    pub points: Vec<(f32, f32)>,
+    /// The water along it is mostly lake rather than sea
+    pub lake: bool,
+}
+
+/// The rivers and roads as smooth curves for drawing, one per river and
+/// per road in the same order, in the coordinates of
+/// [`TerrainMap::unwrapped_path`]; see the `smoothing` module.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SmoothPaths {
+    pub rivers: Vec<Vec<(f32, f32)>>,
+    pub roads: Vec<Vec<(f32, f32)>>,
+}
+
+/// A large city and the suburb towns around it, which are cities of the
+/// map in their own right. Cities are referred to by tile.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetroArea {
+    /// The name the whole area is labelled with when zoomed out
+    pub name: String,
+    pub center: (usize, usize),
+    pub suburbs: Vec<(usize, usize)>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TerrainMap {
     pub width: usize,
     pub height: usize,
@@ -51,21 +119,513 @@ pub struct TerrainMap {
     pub cities: Vec<City>,
     pub roads: Vec<Road>,
     pub bridges: Vec<Bridge>,
+    /// Missing from maps saved before metro areas existed
+    #[serde(default)]
+    pub metro_areas: Vec<MetroArea>,
+    /// The seed the map was generated from, which its weather also
+    /// follows; 0 on maps saved before it was recorded
+    #[serde(default)]
+    pub seed: u32,
+    /// Points of interest other than cities and bridges (see
+    /// [`TerrainMap::points_of_interest`]); missing from older maps
+    #[serde(default)]
+    pub pois: Vec<PointOfInterest>,
+    /// How each kind in `pois` is drawn
+    #[serde(default)]
+    pub poi_styles: Vec<PoiStyle>,
+    /// The tiles of each region labelled in `labels`; missing from maps
+    /// saved before they were kept
+    #[serde(default)]
+    pub regions: Vec<NamedRegion>,
+    /// Power lines between the larger cities of a modern-era map
+    #[serde(default)]
+    pub power_lines: Vec<PowerLine>,
+    /// The names of the regions set aside as national parks and
+    /// wilderness areas; see the `parks` module
+    #[serde(default)]
+    pub parks: Vec<String>,
+    /// Every coast and lake shore as a closed ring; traced again when
+    /// maps saved before they were kept are loaded
+    #[serde(default)]
+    pub coastlines: Vec<Coastline>,
+    /// The rivers and roads smoothed; worked out again when maps saved
+    /// before they were kept are loaded
+    #[serde(default)]
+    pub smoothed: SmoothPaths,
+    /// The map wraps east-west (see `GenerationSettings::wrap_x`): column
+    /// 0 is next to column `width - 1`, and rivers and roads may step
+    /// across between them
+    #[serde(default)]
+    pub wrap_x: bool,
+}
+
+impl TerrainMap {
+    /// A map of `width` by `height` with no terrain generated on it yet.
+    pub fn empty(width: usize, height: usize) -> Self {
+        TerrainMap {
+            width,
+            height,
+            terrain: Vec::new(),
+            labels: Vec::new(),
+            rivers: Vec::new(),
+            cities: Vec::new(),
+            roads: Vec::new(),
+            bridges: Vec::new(),
+            metro_areas: Vec::new(),
+            seed: 0,
+            pois: Vec::new(),
+            poi_styles: Vec::new(),
+            regions: Vec::new(),
+            power_lines: Vec::new(),
+            parks: Vec::new(),
+            coastlines: Vec::new(),
+            smoothed: SmoothPaths::default(),
+            wrap_x: false,
+        }
+    }
+
+    /// The tile `(dx, dy)` from (`x`, `y`): across the seam on a map that
+    /// wraps east-west, `None` off the edge otherwise.
+    pub fn offset(&self, x: usize, y: usize, dx: isize, dy: isize) -> Option<(usize, usize)> {
+        offset_tile(x, y, dx, dy, self.width, self.height, self.wrap_x)
+    }
+
+    /// The tiles of `path` as (x, y) coordinates, unrolled across the seam
+    /// of a map that wraps east-west so every step stays a step to a
+    /// neighbour: x runs below 0 or past `width` after the path crosses.
+    /// Drawn again at each of [`TerrainMap::wrap_shifts`], the part past
+    /// the edge shows on the other side.
+    pub fn unwrapped_path(&self, path: &[(usize, usize)]) -> Vec<(f32, f32)> {
+        let mut x = path.first().map_or(0.0, |&(x, _)| x as f32);
+        path.iter()
+            .enumerate()
+            .map(|(i, &(tx, ty))| {
+                if i > 0 {
+                    x += self.x_delta(path[i - 1].0 as f32, tx as f32);
+                }
+                (x, ty as f32)
+            })
+            .collect()
+    }
+
+    /// East-west shifts, in tiles, to draw each feature at so that what
+    /// spills past one edge of a map that wraps shows at the other and the
+    /// image tiles seamlessly; only 0 on a map that doesn't wrap.
+    pub fn wrap_shifts(&self) -> Vec<f32> {
+        if self.wrap_x {
+            vec![0.0, -(self.width as f32), self.width as f32]
+        } else {
+            vec![0.0]
+        }
+    }
+
+    /// How far east `to_x` is from `from_x`: the short way round the seam
+    /// on a map that wraps east-west, so the result is within half the
+    /// width either way.
+    pub fn x_delta(&self, from_x: f32, to_x: f32) -> f32 {
+        wrap_dx((to_x - from_x) as f64, self.width, self.wrap_x) as f32
+    }
+
+    /// Write the map as JSON so it can be reloaded later (e.g. to reroll
+    /// settlements on the same landmass). The river network is written
+    /// alongside as `river_flow` for other tools; loading ignores it and
+    /// works it out again from the rivers.
+    pub fn save_json(&self, path: &Path) -> Result<(), MapperError> {
+        #[derive(Serialize)]
+        struct SavedMap<'a> {
+            #[serde(flatten)]
+            map: &'a TerrainMap,
+            river_flow: Vec<RiverFlow>,
+        }
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let writer = BufWriter::new(File::create(path)?);
+        let saved = SavedMap {
+            map: self,
+            river_flow: self.river_flows(),
+        };
+        serde_json::to_writer(writer, &saved)?;
+        Ok(())
+    }
+
+    /// Read a map written by [`TerrainMap::save_json`]. Malformed files are
+    /// reported as `MapperError::Json`.
+    pub fn load_json(path: &Path) -> Result<TerrainMap, MapperError> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut map: TerrainMap = serde_json::from_reader(reader)?;
+        if map.coastlines.is_empty() {
+            map.coastlines = map.traced_coastlines();
+        }
+        if map.smoothed == SmoothPaths::default() {
+            map.smoothed = map.smoothed_paths();
+        }
+        Ok(map)
+    }
+}
+
+/// The tile `(dx, dy)` from (`x`, `y`) on a `width` by `height` grid: the
+/// column wraps around when `wrap_x`, and off the grid is `None`.
+pub(super) fn offset_tile(
+    x: usize,
+    y: usize,
+    dx: isize,
+    dy: isize,
+    width: usize,
+    height: usize,
+    wrap_x: bool,
+) -> Option<(usize, usize)> {
+    let ny = y.checked_add_signed(dy).filter(|&ny| ny < height)?;
+    let nx = if wrap_x {
+        (x as isize + dx).rem_euclid(width as isize) as usize
+    } else {
+        x.checked_add_signed(dx).filter(|&nx| nx < width)?
+    };
+    Some((nx, ny))
+}
+
+/// The east-west distance `dx` on a `width` wide map taken the short way
+/// round the seam when `wrap_x`.
+pub(super) fn wrap_dx(dx: f64, width: usize, wrap_x: bool) -> f64 {
+    let width = width as f64;
+    if wrap_x && dx.abs() > width / 2.0 {
+        dx - width * dx.signum()
+    } else {
+        dx
+    }
+}
+
+/// Large-scale layout of the landmasses. When `GenerationSettings` leaves
+/// this unset, each seed picks one of the first five at random.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Formation {
+    /// Volcanic island chain along a curved line (Hawaii, Aleutians)
+    IslandChain,
+    /// Elongated continent with a mountainous spine
+    Spine,
+    /// Crescent / island arc (Japan, Indonesia)
+    IslandArc,
+    /// A few large plates forming one complex landmass
+    Plates,
+    /// Clusters of islands with power-law sizes
+    Archipelago,
+    /// A ring of land around a central sea (Mediterranean, Black Sea)
+    InlandSea,
+}
+
+impl Formation {
+    pub const ALL: [Formation; 6] = [
+        Formation::IslandChain,
+        Formation::Spine,
+        Formation::IslandArc,
+        Formation::Plates,
+        Formation::Archipelago,
+        Formation::InlandSea,
+    ];
+}
+
+/// The noise function behind one field of the map. Perlin is the classic
+/// look; ridged multifractal noise folds into sharp mountain ranges and
+/// Worley noise into cells.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NoiseKind {
+    #[default]
+    Perlin,
+    OpenSimplex,
+    Simplex,
+    Ridged,
+    Worley,
+}
+
+impl NoiseKind {
+    pub const ALL: [NoiseKind; 5] = [
+        NoiseKind::Perlin,
+        NoiseKind::OpenSimplex,
+        NoiseKind::Simplex,
+        NoiseKind::Ridged,
+        NoiseKind::Worley,
+    ];
+}
+
+/// How city sites are scored. A site's score is the sum of its features
+/// (each 0.0 to 1.0) times these weights, minus `distance_penalty` times
+/// its crowding by cities already placed; see `SiteFeatures` in
+/// `settlements.rs`. A weight of 0.0 ignores its feature.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SettlementWeights {
+    /// Sea within a few tiles: harbours and trade
+    pub coast: f32,
+    /// Close to where two rivers meet
+    pub river_confluence: f32,
+    /// A river or lake within a few tiles
+    pub fresh_water: f32,
+    /// Gentle slopes around the site
+    pub flat_land: f32,
+    /// Farmland, timber and ore (plains, forest, hills) all close by
+    pub resources: f32,
+    /// How strongly cities keep their distance from each other
+    pub distance_penalty: f32,
 }
 
-#[derive(Debug, Clone, Copy)]
+impl Default for SettlementWeights {
+    fn default() -> Self {
+        SettlementWeights {
+            coast: 0.6,
+            river_confluence: 0.4,
+            fresh_water: 0.6,
+            flat_land: 0.5,
+            resources: 0.4,
+            distance_penalty: 1.0,
+        }
+    }
+}
+
+impl SettlementWeights {
+    /// Trading ports: cities line the coasts
+    pub const COASTAL_TRADE: SettlementWeights = SettlementWeights {
+        coast: 1.0,
+        river_confluence: 0.1,
+        fresh_water: 0.2,
+        flat_land: 0.3,
+        resources: 0.2,
+        distance_penalty: 1.0,
+    };
+
+    /// River-valley civilizations: cities follow the rivers inland
+    pub const RIVER_VALLEY: SettlementWeights = SettlementWeights {
+        coast: 0.0,
+        river_confluence: 1.0,
+        fresh_water: 1.0,
+        flat_land: 0.6,
+        resources: 0.4,
+        distance_penalty: 1.0,
+    };
+
+    /// Copy with every weight forced into 0.0..=1.0 (NaN takes the
+    /// default's value).
+    pub fn clamped(self) -> Self {
+        let default = SettlementWeights::default();
+        let unit = |value: f32, default: f32| {
+            if value.is_nan() {
+                default
+            } else {
+                value.clamp(0.0, 1.0)
+            }
+        };
+        SettlementWeights {
+            coast: unit(self.coast, default.coast),
+            river_confluence: unit(self.river_confluence, default.river_confluence),
+            fresh_water: unit(self.fresh_water, default.fresh_water),
+            flat_land: unit(self.flat_land, default.flat_land),
+            resources: unit(self.resources, default.resources),
+            distance_penalty: unit(self.distance_penalty, default.distance_penalty),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct GenerationSettings {
     pub river_density: f32,   // 0.0 (low) to 1.0 (high)
     pub city_density: f32,    // 0.0 (low) to 1.0 (high)
     pub land_percentage: f32, // 0.0 (mostly water) to 1.0 (mostly land)
+    /// Landmass layout; `None` lets each seed choose one
+    pub formation: Option<Formation>,
+    /// Global climate: 0.0 (frozen) to 1.0 (hot), 0.5 is temperate
+    pub temperature: f32,
+    /// How much of the land is hills and mountains: 0.0 (flat) to 1.0
+    /// (rugged), 0.5 is the natural mix
+    pub mountain_density: f32,
+    /// How readily lowlands grow forest instead of plains: 0.0 to 1.0
+    pub forest_density: f32,
+    /// Overall wetness: 0.0 (arid) to 1.0 (wet), 0.5 leaves the moisture
+    /// field as generated
+    pub moisture: f32,
+    /// Size in tiles of maps generated when the caller doesn't choose one
+    /// (the CLI; the GUI has its own size list)
+    pub map_width: usize,
+    pub map_height: usize,
+    /// Noise the elevation field is built from
+    pub elevation_noise: NoiseKind,
+    /// Noise the moisture field is built from
+    pub moisture_noise: NoiseKind,
+    /// What makes a good city site
+    pub settlement_weights: SettlementWeights,
+    /// Highways from border cities off the edge of the map, toward lands
+    /// beyond it (0 to `MAX_EDGE_ROADS`)
+    pub edge_roads: usize,
+    /// The map wraps east-west: its left and right edges join, as on a
+    /// globe, and only its north and south edges are the end of the world
+    pub wrap_x: bool,
+    /// How the terrain repeats itself, for maps fair to every side
+    pub symmetry: Symmetry,
+    /// With a `symmetry`, cities and metro areas repeat along with the
+    /// terrain
+    pub symmetric_cities: bool,
+    /// A contemporary map: motorways, airports, container terminals and
+    /// power lines (see the `modern` module)
+    pub modern: bool,
 }
 
+/// Most roads leaving the map that `GenerationSettings::edge_roads` can ask for
+pub const MAX_EDGE_ROADS: usize = 8;
+
 impl Default for GenerationSettings {
     fn default() -> Self {
         GenerationSettings {
             river_density: 0.5,   // medium
             city_density: 0.5,    // medium
             land_percentage: 0.4, // 40% land, 60% water
+            formation: None,
+            temperature: 0.5,
+            mountain_density: 0.5,
+            forest_density: 0.5,
+            moisture: 0.5,
+            map_width: 320,
+            map_height: 240,
+            elevation_noise: NoiseKind::Perlin,
+            moisture_noise: NoiseKind::Perlin,
+            settlement_weights: SettlementWeights::default(),
+            edge_roads: 0,
+            wrap_x: false,
+            symmetry: Symmetry::None,
+            symmetric_cities: false,
+            modern: false,
         }
     }
 }
+
+impl GenerationSettings {
+    /// Copy of these settings with every field forced into its valid range.
+    /// A NaN takes the default's value.
+    pub fn clamped(self) -> Self {
+        let default = GenerationSettings::default();
+        let unit = |value: f32, default: f32| {
+            if value.is_nan() {
+                default
+            } else {
+                value.clamp(0.0, 1.0)
+            }
+        };
+        GenerationSettings {
+            river_density: unit(self.river_density, default.river_density),
+            city_density: unit(self.city_density, default.city_density),
+            land_percentage: unit(self.land_percentage, default.land_percentage),
+            formation: self.formation,
+            temperature: unit(self.temperature, default.temperature),
+            mountain_density: unit(self.mountain_density, default.mountain_density),
+            forest_density: unit(self.forest_density, default.forest_density),
+            moisture: unit(self.moisture, default.moisture),
+            map_width: self.map_width.max(1),
+            map_height: self.map_height.max(1),
+            elevation_noise: self.elevation_noise,
+            moisture_noise: self.moisture_noise,
+            settlement_weights: self.settlement_weights.clamped(),
+            edge_roads: self.edge_roads.min(MAX_EDGE_ROADS),
+            wrap_x: self.wrap_x,
+            symmetry: self.symmetry,
+            symmetric_cities: self.symmetric_cities,
+            modern: self.modern,
+        }
+    }
+
+    /// Power a land tile's quantile is raised to for its elevation: above
+    /// 1 on flat maps, sinking land toward the lowlands, below 1 on rugged
+    /// ones. Elevation `e` then covers `e^(1/ruggedness)` of the land.
+    pub fn ruggedness(&self) -> f64 {
+        4f64.powf(0.5 - self.mountain_density as f64)
+    }
+
+    // Builder-style setters, e.g.
+    // `GenerationSettings::default().with_land_percentage(0.6).with_map_size(480, 360)`.
+    // Values are stored as given; `clamped` brings them into range.
+
+    pub fn with_river_density(mut self, river_density: f32) -> Self {
+        self.river_density = river_density;
+        self
+    }
+
+    pub fn with_city_density(mut self, city_density: f32) -> Self {
+        self.city_density = city_density;
+        self
+    }
+
+    pub fn with_land_percentage(mut self, land_percentage: f32) -> Self {
+        self.land_percentage = land_percentage;
+        self
+    }
+
+    pub fn with_formation(mut self, formation: Option<Formation>) -> Self {
+        self.formation = formation;
+        self
+    }
+
+    pub fn with_temperature(mut self, temperature: f32) -> Self {
+        self.temperature = temperature;
+        self
+    }
+
+    pub fn with_mountain_density(mut self, mountain_density: f32) -> Self {
+        self.mountain_density = mountain_density;
+        self
+    }
+
+    pub fn with_forest_density(mut self, forest_density: f32) -> Self {
+        self.forest_density = forest_density;
+        self
+    }
+
+    pub fn with_moisture(mut self, moisture: f32) -> Self {
+        self.moisture = moisture;
+        self
+    }
+
+    pub fn with_map_size(mut self, map_width: usize, map_height: usize) -> Self {
+        self.map_width = map_width;
+        self.map_height = map_height;
+        self
+    }
+
+    pub fn with_elevation_noise(mut self, elevation_noise: NoiseKind) -> Self {
+        self.elevation_noise = elevation_noise;
+        self
+    }
+
+    pub fn with_moisture_noise(mut self, moisture_noise: NoiseKind) -> Self {
+        self.moisture_noise = moisture_noise;
+        self
+    }
+
+    pub fn with_settlement_weights(mut self, settlement_weights: SettlementWeights) -> Self {
+        self.settlement_weights = settlement_weights;
+        self
+    }
+
+    pub fn with_edge_roads(mut self, edge_roads: usize) -> Self {
+        self.edge_roads = edge_roads;
+        self
+    }
+
+    pub fn with_wrap_x(mut self, wrap_x: bool) -> Self {
+        self.wrap_x = wrap_x;
+        self
+    }
+
+    pub fn with_symmetry(mut self, symmetry: Symmetry) -> Self {
+        self.symmetry = symmetry;
+        self
+    }
+
+    pub fn with_symmetric_cities(mut self, symmetric_cities: bool) -> Self {
+        self.symmetric_cities = symmetric_cities;
+        self
+    }
+
+    pub fn with_modern(mut self, modern: bool) -> Self {
+        self.modern = modern;
+        self
+    }
+}
@@ -0,0 +1,305 @@
+//! Consistency checks on a finished map: the things generation should
+//! never produce and hand edits or loaders might, such as roads across
+//! the open sea or rivers running uphill.
+
+use std::fmt;
+
+use serde::Serialize;
+
+use super::biome::Biome;
+use super::hydrology::fill_depressions;
+use super::network::NodeKind;
+use super::types::TerrainMap;
+
+/// Largest rise along a river, as a share of the elevation it rises
+/// from, that still counts as level. Rivers are traced downhill and then
+/// erode their own tiles and their banks by a tenth or so, unevenly where
+/// rivers run side by side
+const RIVER_RISE_TOLERANCE: f64 = 0.2;
+
+/// How serious a problem is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Odd but possible, like a city alone on an island
+    Warning,
+    /// Something generation should never produce
+    Error,
+}
+
+/// One problem found on a map. Indices are into the map's lists.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Problem {
+    /// A city no road leads to from any other city
+    UnreachableCity { city: usize },
+    /// A road tile in deep ocean
+    RoadInDeepOcean { road: usize, at: (usize, usize) },
+    /// A step of a river that climbs, with depressions filled: rivers
+    /// may cross a pond or lake bed, but not rise over its rim
+    RiverUphill { river: usize, at: (usize, usize), rise: f64 },
+    /// A bridge on a tile no river crosses
+    BridgeOffRiver { bridge: usize },
+    /// A label anchored outside the map
+    LabelOutOfBounds { label: usize },
+    /// Two cities on the same tile
+    OverlappingCities { first: usize, second: usize },
+    /// A path, city or bridge tile outside the map
+    OutOfBounds { what: String, at: (usize, usize) },
+}
+
+impl Problem {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Problem::UnreachableCity { .. } => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+
+    /// A one-line description, naming what the map names.
+    pub fn describe(&self, map: &TerrainMap) -> String {
+        let city = |i: usize| map.cities.get(i).map_or("?", |c| c.name.as_str());
+        match self {
+            Problem::UnreachableCity { city: i } => {
+                format!("no road leads to {} from another city", city(*i))
+            }
+            Problem::RoadInDeepOcean { road, at } => format!(
+                "{} crosses deep ocean at {},{}",
+                map.roads.get(*road).map_or("?", |r| r.name.as_str()),
+                at.0,
+                at.1
+            ),
+            Problem::RiverUphill { river, at, rise } => {
+                format!("river {} climbs {:.3} at {},{}", river, rise, at.0, at.1)
+            }
+            Problem::BridgeOffRiver { bridge } => {
+                let bridge = &map.bridges[*bridge];
+                format!("{} at {},{} is not on a river", bridge.name, bridge.x, bridge.y)
+            }
+            Problem::LabelOutOfBounds { label } => {
+                let label = &map.labels[*label];
+                format!("label {} at {:.1},{:.1} is off the map", label.name, label.x, label.y)
+            }
+            Problem::OverlappingCities { first, second } => {
+                format!("{} and {} share a tile", city(*first), city(*second))
+            }
+            Problem::OutOfBounds { what, at } => format!("{} at {},{} is off the map", what, at.0, at.1),
+        }
+    }
+}
+
+/// Everything [`TerrainMap::validate`] found, errors first.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ValidationReport {
+    pub problems: Vec<Problem>,
+}
+
+impl ValidationReport {
+    /// No errors; warnings are allowed.
+    pub fn is_valid(&self) -> bool {
+        self.errors().next().is_none()
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &Problem> {
+        self.problems.iter().filter(|p| p.severity() == Severity::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &Problem> {
+        self.problems.iter().filter(|p| p.severity() == Severity::Warning)
+    }
+
+    /// The report as text, one problem per line.
+    pub fn display<'a>(&'a self, map: &'a TerrainMap) -> impl fmt::Display + 'a {
+        ReportDisplay { report: self, map }
+    }
+}
+
+struct ReportDisplay<'a> {
+    report: &'a ValidationReport,
+    map: &'a TerrainMap,
+}
+
+impl fmt::Display for ReportDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (errors, warnings) = (self.report.errors().count(), self.report.warnings().count());
+        writeln!(f, "{} errors, {} warnings", errors, warnings)?;
+        for problem in &self.report.problems {
+            let label = match problem.severity() {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+            };
+            writeln!(f, "  {}: {}", label, problem.describe(self.map))?;
+        }
+        Ok(())
+    }
+}
+
+impl TerrainMap {
+    /// Check the map for problems (see [`Problem`]).
+    pub fn validate(&self) -> ValidationReport {
+        let mut problems = Vec::new();
+        let inside = |(x, y): (usize, usize)| x < self.width && y < self.height;
+        let biome = |(x, y): (usize, usize)| self.terrain.get(y).and_then(|row| row.get(x)).map(|p| p.biome);
+        let elevations: Vec<f64> = self.terrain.iter().flatten().map(|p| p.elevation).collect();
+        let filled = fill_depressions(&elevations, self.width, self.height, self.wrap_x);
+        let elevation = |(x, y): (usize, usize)| filled[y * self.width + x];
+
+        for (index, road) in self.roads.iter().enumerate() {
+            for &at in &road.path {
+                if !inside(at) {
+                    problems.push(Problem::OutOfBounds { what: road.name.clone(), at });
+                } else if biome(at) == Some(Biome::DeepOcean) {
+                    problems.push(Problem::RoadInDeepOcean { road: index, at });
+                }
+            }
+        }
+
+        for (index, river) in self.rivers.iter().enumerate() {
+            if let Some(&at) = river.iter().find(|&&at| !inside(at)) {
+                problems.push(Problem::OutOfBounds { what: format!("river {}", index), at });
+                continue;
+            }
+            for step in river.windows(2) {
+                let rise = elevation(step[1]) - elevation(step[0]);
+                if rise > RIVER_RISE_TOLERANCE * elevation(step[0]).abs() + 1e-6 {
+                    problems.push(Problem::RiverUphill { river: index, at: step[1], rise });
+                }
+            }
+        }
+
+        for (index, bridge) in self.bridges.iter().enumerate() {
+            let at = (bridge.x, bridge.y);
+            if !inside(at) {
+                problems.push(Problem::OutOfBounds { what: bridge.name.clone(), at });
+            } else if !self.rivers.iter().any(|river| river.contains(&at)) && biome(at) != Some(Biome::River) {
+                problems.push(Problem::BridgeOffRiver { bridge: index });
+            }
+        }
+
+        for (index, label) in self.labels.iter().enumerate() {
+            let on_map = (0.0..=self.width as f32).contains(&label.x) && (0.0..=self.height as f32).contains(&label.y);
+            if !on_map {
+                problems.push(Problem::LabelOutOfBounds { label: index });
+            }
+        }
+
+        for (first, city) in self.cities.iter().enumerate() {
+            if !inside((city.x, city.y)) {
+                problems.push(Problem::OutOfBounds { what: city.name.clone(), at: (city.x, city.y) });
+            }
+            for (second, other) in self.cities.iter().enumerate().skip(first + 1) {
+                if (city.x, city.y) == (other.x, other.y) {
+                    problems.push(Problem::OverlappingCities { first, second });
+                }
+            }
+        }
+
+        // The road network can only be built from tiles on the map
+        let off_map = problems.iter().any(|p| matches!(p, Problem::OutOfBounds { .. }));
+        if self.cities.len() > 1 && !off_map {
+            let network = self.road_network();
+            for group in network.connected_components() {
+                let cities: Vec<usize> = group
+                    .iter()
+                    .filter_map(|&node| match network.nodes[node].kind {
+                        NodeKind::City(city) => Some(city),
+                        _ => None,
+                    })
+                    .collect();
+                if let [city] = cities[..] {
+                    problems.push(Problem::UnreachableCity { city });
+                }
+            }
+        }
+
+        problems.sort_by_key(|p| std::cmp::Reverse(p.severity()));
+        ValidationReport { problems }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain_generator::{test_map, Bridge};
+
+    fn map() -> &'static TerrainMap {
+        test_map(21, 120, 90)
+    }
+
+    /// A tile of deep ocean on `map`
+    fn deep_ocean(map: &TerrainMap) -> (usize, usize) {
+        map.terrain
+            .iter()
+            .flatten()
+            .position(|p| p.biome == Biome::DeepOcean)
+            .map(|i| (i % map.width, i / map.width))
+            .unwrap()
+    }
+
+    /// Whether `map()` once broken by `break_it` reports a problem `check`
+    /// picks out
+    fn caught(break_it: impl FnOnce(&mut TerrainMap), check: fn(&Problem) -> bool) -> bool {
+        let mut map = map().clone();
+        break_it(&mut map);
+        let report = map.validate();
+        !report.is_valid() && report.problems.iter().any(check)
+    }
+
+    #[test]
+    fn generated_maps_are_valid() {
+        let report = map().validate();
+        assert!(report.is_valid(), "{}", report.display(map()));
+    }
+
+    #[test]
+    fn roads_into_deep_ocean_are_caught() {
+        let deep = deep_ocean(map());
+        assert!(caught(
+            |map| map.roads[0].path.push(deep),
+            |p| matches!(p, Problem::RoadInDeepOcean { road: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn overlapping_cities_are_caught() {
+        assert!(caught(
+            |map| map.cities.push(map.cities[0].clone()),
+            |p| matches!(p, Problem::OverlappingCities { first: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn bridges_off_rivers_are_caught() {
+        let deep = deep_ocean(map());
+        assert!(caught(
+            |map| map.bridges.push(Bridge { x: deep.0, y: deep.1, name: "Bridge to Nowhere".to_string() }),
+            |p| matches!(p, Problem::BridgeOffRiver { .. })
+        ));
+    }
+
+    #[test]
+    fn labels_off_the_map_are_caught() {
+        assert!(caught(
+            |map| map.labels[0].x = -5.0,
+            |p| matches!(p, Problem::LabelOutOfBounds { label: 0 })
+        ));
+    }
+
+    #[test]
+    fn rivers_running_uphill_are_caught() {
+        let river = map().rivers.iter().position(|r| r.len() > 3).unwrap();
+        assert!(caught(|map| map.rivers[river].reverse(), |p| matches!(p, Problem::RiverUphill { .. })));
+    }
+
+    #[test]
+    fn reports_name_the_problem_and_its_severity() {
+        let mut map = map().clone();
+        let deep = deep_ocean(&map);
+        map.bridges.push(Bridge { x: deep.0, y: deep.1, name: "Bridge to Nowhere".to_string() });
+        let report = map.validate();
+        assert_eq!(report.problems[0].severity(), Severity::Error);
+        assert!(report.display(&map).to_string().contains("Bridge to Nowhere"));
+        let json = serde_json::to_value(&report).unwrap();
+        assert!(json["problems"][0]["kind"].is_string());
+    }
+}
@@ -0,0 +1,181 @@
+//! Line of sight: which tiles can be seen from a point.
+//!
+//! Heights are taken as kilometres, an elevation of 1 standing
+//! `ELEVATION_KM` above the sea, over tiles [`DEFAULT_KM_PER_TILE`]
+//! across, and the ground falls away with the curve of an Earth-sized
+//! world, so even a peak's view ends at its horizon. Water is seen at its
+//! surface. A tile is visible when no ground between it and the observer
+//! rises above the line joining them; the ground is sampled once per tile
+//! crossed, between the two tiles either side of the line.
+
+use std::collections::HashSet;
+
+use rayon::prelude::*;
+
+use crate::measure::DEFAULT_KM_PER_TILE;
+
+use super::types::{wrap_dx, TerrainMap};
+
+/// Height above the sea of an elevation of 1, in kilometres
+pub const ELEVATION_KM: f64 = 6.0;
+
+/// Radius of the world the horizon is curved by, in kilometres
+const WORLD_RADIUS_KM: f64 = 6371.0;
+
+/// A point to look out from, e.g. for a viewshed overlay.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewpoint {
+    pub x: usize,
+    pub y: usize,
+    /// Eye height above the ground, in elevation units: 0.01 is a
+    /// 60 m tower
+    pub height: f64,
+}
+
+impl Viewpoint {
+    /// Height of a watchtower, the default eye height
+    pub const TOWER: f64 = 0.005;
+}
+
+impl TerrainMap {
+    /// The tiles that can be seen from (`x`, `y`) with eyes
+    /// `observer_height` above the ground, in elevation units, including
+    /// the observer's own. Empty outside the map. On a map that wraps
+    /// east-west the view carries on across the seam.
+    pub fn viewshed(&self, x: usize, y: usize, observer_height: f64) -> HashSet<(usize, usize)> {
+        let Some(eye) = self.surface_km(x as isize, y) else {
+            return HashSet::new();
+        };
+        let eye = eye + observer_height.max(0.0) * ELEVATION_KM;
+        (0..self.height)
+            .into_par_iter()
+            .flat_map_iter(|ty| {
+                (0..self.width)
+                    .filter(move |&tx| self.sees(x, y, eye, tx, ty))
+                    .map(move |tx| (tx, ty))
+            })
+            .collect()
+    }
+
+    /// Whether an eye `eye` km high over (`x`, `y`) sees (`tx`, `ty`).
+    fn sees(&self, x: usize, y: usize, eye: f64, tx: usize, ty: usize) -> bool {
+        let dx = wrap_dx(tx as f64 - x as f64, self.width, self.wrap_x);
+        let dy = ty as f64 - y as f64;
+        let steps = dx.abs().max(dy.abs()).round() as usize;
+        if steps == 0 {
+            return true;
+        }
+        // Slope of the sight line to each point, in km per tile, less the
+        // drop of the world's curve
+        let slope = |k: f64, height: f64| {
+            let tiles = k * dx.hypot(dy) / steps as f64;
+            let drop = (tiles * DEFAULT_KM_PER_TILE).powi(2) / (2.0 * WORLD_RADIUS_KM);
+            (height - drop - eye) / tiles
+        };
+        let mut highest = f64::NEG_INFINITY;
+        for k in 1..steps {
+            let t = k as f64 / steps as f64;
+            let (sx, sy) = (x as f64 + dx * t, y as f64 + dy * t);
+            let Some(ground) = self.ground_between(sx, sy, dx.abs() >= dy.abs()) else {
+                return false;
+            };
+            highest = highest.max(slope(k as f64, ground));
+        }
+        self.surface_km(tx as isize, ty)
+            .is_some_and(|target| slope(steps as f64, target) >= highest)
+    }
+
+    /// Height at (`sx`, `sy`), where the line crosses a row or column of
+    /// tiles: interpolated across it, along y if the line runs mostly
+    /// east-west and along x otherwise.
+    fn ground_between(&self, sx: f64, sy: f64, across_y: bool) -> Option<f64> {
+        let (along, across) = if across_y { (sx.round(), sy) } else { (sy.round(), sx) };
+        let (low, t) = (across.floor(), across - across.floor());
+        let at = |offset: f64| {
+            let across = low + offset;
+            if across_y {
+                self.surface_km(along as isize, across as usize)
+            } else {
+                self.surface_km(across as isize, along as usize)
+            }
+        };
+        let first = at(0.0)?;
+        if t < 1e-9 {
+            return Some(first);
+        }
+        Some(first * (1.0 - t) + at(1.0).unwrap_or(first) * t)
+    }
+
+    /// Height of the ground or water surface at (`x`, `y`) in km, `x`
+    /// wrapped round a wrapping map; `None` off the map.
+    fn surface_km(&self, x: isize, y: usize) -> Option<f64> {
+        let x = if self.wrap_x { x.rem_euclid(self.width as isize) } else { x };
+        let elevation = self.elevation_at(usize::try_from(x).ok()?, y)?;
+        Some(elevation.max(0.0) * ELEVATION_KM)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain_generator::test_map;
+
+    fn map() -> &'static TerrainMap {
+        test_map(5, 120, 90)
+    }
+
+    fn peak() -> (usize, usize) {
+        let map = map();
+        let mut peak = (0, 0);
+        for y in 0..map.height {
+            for x in 0..map.width {
+                if map.terrain[y][x].elevation > map.terrain[peak.1][peak.0].elevation {
+                    peak = (x, y);
+                }
+            }
+        }
+        peak
+    }
+
+    #[test]
+    fn a_peak_sees_itself_and_its_neighbours() {
+        let (map, peak) = (map(), peak());
+        let from_peak = map.viewshed(peak.0, peak.1, Viewpoint::TOWER);
+        assert!(from_peak.contains(&peak));
+        assert!(from_peak.contains(&(peak.0 + 1, peak.1)) || peak.0 + 1 == map.width);
+    }
+
+    #[test]
+    fn standing_taller_never_hides_anything() {
+        let (map, peak) = (map(), peak());
+        let from_peak = map.viewshed(peak.0, peak.1, Viewpoint::TOWER);
+        let higher = map.viewshed(peak.0, peak.1, 0.05);
+        assert!(from_peak.is_subset(&higher) && higher.len() > from_peak.len());
+    }
+
+    #[test]
+    fn views_end_at_the_horizon() {
+        let map = map();
+        let shore = map
+            .cities
+            .iter()
+            .map(|c| (c.x, c.y))
+            .min_by(|a, b| map.terrain[a.1][a.0].elevation.total_cmp(&map.terrain[b.1][b.0].elevation))
+            .unwrap();
+        let eye_km = (map.terrain[shore.1][shore.0].elevation + Viewpoint::TOWER) * ELEVATION_KM;
+        let horizon = (2.0 * WORLD_RADIUS_KM * eye_km).sqrt() / DEFAULT_KM_PER_TILE;
+        let from_shore = map.viewshed(shore.0, shore.1, Viewpoint::TOWER);
+        let sea: Vec<f64> = from_shore
+            .iter()
+            .filter(|&&(x, y)| map.is_water(x, y))
+            .map(|&(x, y)| (x as f64 - shore.0 as f64).hypot(y as f64 - shore.1 as f64))
+            .collect();
+        assert!(!sea.is_empty());
+        assert!(sea.iter().all(|&d| d <= horizon + 1.0), "horizon {}", horizon);
+    }
+
+    #[test]
+    fn nothing_is_seen_from_off_the_map() {
+        assert!(map().viewshed(map().width, 0, 1.0).is_empty());
+    }
+}
@@ -0,0 +1,233 @@
+//! Day-by-day weather from the climate fields.
+//!
+//! A tile's temperature and moisture give its yearly average; on top of
+//! that come the seasons, stronger away from the equator (the middle row)
+//! and opposite in the two halves of the map, and weather systems: smooth
+//! noise over space and time, so neighbouring tiles share a cold snap or a
+//! wet spell that passes after a few days. Everything is a pure function
+//! of the map's seed, tile and day, so a given day always has the same
+//! weather and can be asked for in any order.
+
+use std::f32::consts::TAU;
+
+use crate::climate_export::MAX_PRECIPITATION_MM;
+
+use super::types::TerrainMap;
+
+/// Length of the weather year, in days; day 0 is midwinter in the
+/// northern half of the map
+pub const DAYS_PER_YEAR: u32 = 365;
+
+/// Yearly mean temperature, in °C, of the coldest and hottest tiles
+const COLDEST_C: f32 = -20.0;
+const HOTTEST_C: f32 = 35.0;
+/// Half the summer-to-winter swing at the equator and at the poles, in °C
+const SEASON_EQUATOR_C: f32 = 2.0;
+const SEASON_POLE_C: f32 = 16.0;
+/// How far a weather system moves the temperature from the season's, in °C
+const SYSTEM_SWING_C: f32 = 7.0;
+/// Size of a weather system, in tiles
+const SYSTEM_TILES: f32 = 24.0;
+/// Days a weather system takes to give way to the next
+const SYSTEM_DAYS: f32 = 3.0;
+/// Share of days with rain or snow on the driest and wettest tiles
+const DRIEST_RAIN_DAYS: f32 = 0.05;
+const WETTEST_RAIN_DAYS: f32 = 0.5;
+/// Noise channels, so temperature and wetness vary independently
+const TEMPERATURE_CHANNEL: u64 = 1;
+const WETNESS_CHANNEL: u64 = 2;
+
+/// The weather on one tile on one day.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Weather {
+    /// Mean temperature of the day, in °C
+    pub temperature_c: f32,
+    /// Rain, or snow as water, in mm
+    pub precipitation_mm: f32,
+    /// 0 (clear sky) to 1 (overcast)
+    pub cloud_cover: f32,
+}
+
+impl Weather {
+    /// One or two words for the day: "clear", "cloudy", "overcast",
+    /// "rain", "heavy rain" or "snow".
+    pub fn conditions(&self) -> &'static str {
+        if self.precipitation_mm > 0.0 {
+            if self.temperature_c <= 0.0 {
+                "snow"
+            } else if self.precipitation_mm >= 10.0 {
+                "heavy rain"
+            } else {
+                "rain"
+            }
+        } else if self.cloud_cover >= 0.6 {
+            "overcast"
+        } else if self.cloud_cover >= 0.3 {
+            "cloudy"
+        } else {
+            "clear"
+        }
+    }
+}
+
+impl TerrainMap {
+    /// The weather at tile (`x`, `y`) on `day`, counted from the start of
+    /// the first year; `None` off the map.
+    pub fn weather_at(&self, x: usize, y: usize, day: u32) -> Option<Weather> {
+        let point = self.point_at(x, y)?;
+        let (temperature, moisture) = (point.temperature as f32, point.moisture as f32);
+
+        let latitude = (y as f32 / self.height as f32 - 0.5).abs() * 2.0;
+        let hemisphere = if 2 * y < self.height { 1.0 } else { -1.0 };
+        let year = (day % DAYS_PER_YEAR) as f32 / DAYS_PER_YEAR as f32;
+        let swing = SEASON_EQUATOR_C + (SEASON_POLE_C - SEASON_EQUATOR_C) * latitude;
+        let season = -hemisphere * swing * (TAU * year).cos();
+
+        let at = [x as f32 / SYSTEM_TILES, y as f32 / SYSTEM_TILES, day as f32 / SYSTEM_DAYS];
+        let system = system_noise(self.seed, TEMPERATURE_CHANNEL, at);
+        let temperature_c = COLDEST_C + (HOTTEST_C - COLDEST_C) * temperature + season + SYSTEM_SWING_C * system;
+
+        // Wet spells come where the system's wetness tops the tile's
+        // threshold, so wetter tiles see them more often
+        let wetness = system_noise(self.seed, WETNESS_CHANNEL, at) * 0.5 + 0.5;
+        let rain_days = DRIEST_RAIN_DAYS + (WETTEST_RAIN_DAYS - DRIEST_RAIN_DAYS) * moisture;
+        let threshold = 1.0 - rain_days;
+        let yearly_mm = MAX_PRECIPITATION_MM * moisture * (0.3 + 0.7 * temperature);
+        let precipitation_mm = if wetness > threshold {
+            // Blended noise tops the threshold on fewer days than
+            // `rain_days`, and by little; the factor brings the year's total
+            // to about the climate rasters' estimate
+            let intensity = (wetness - threshold) / rain_days;
+            4.5 * intensity * yearly_mm / (DAYS_PER_YEAR as f32 * rain_days)
+        } else {
+            0.0
+        };
+        let cloud_cover = ((wetness - threshold + 0.3) / 0.3).clamp(0.0, 1.0);
+
+        Some(Weather {
+            temperature_c,
+            precipitation_mm,
+            cloud_cover,
+        })
+    }
+}
+
+/// Smooth noise in -1..1 at `at` (tiles and days scaled to weather
+/// systems): random values on the whole-number lattice of `seed` and
+/// `channel`, blended between with smoothstep.
+fn system_noise(seed: u32, channel: u64, at: [f32; 3]) -> f32 {
+    let cell = at.map(|v| v.floor());
+    let [fx, fy, ft] = [0, 1, 2].map(|k| {
+        let f = at[k] - cell[k];
+        f * f * (3.0 - 2.0 * f)
+    });
+    let corner = |dx: i64, dy: i64, dt: i64| {
+        lattice_value(seed, channel, [cell[0] as i64 + dx, cell[1] as i64 + dy, cell[2] as i64 + dt])
+    };
+    let lerp = |a: f32, b: f32, f: f32| a + (b - a) * f;
+    let plane = |dt: i64| {
+        lerp(
+            lerp(corner(0, 0, dt), corner(1, 0, dt), fx),
+            lerp(corner(0, 1, dt), corner(1, 1, dt), fx),
+            fy,
+        )
+    };
+    lerp(plane(0), plane(1), ft)
+}
+
+/// A value in -1..1 for one lattice point, hashed with SplitMix64.
+fn lattice_value(seed: u32, channel: u64, [x, y, t]: [i64; 3]) -> f32 {
+    let mut h = (seed as u64) << 32 ^ channel;
+    for v in [x, y, t] {
+        h = (h ^ v as u64).wrapping_add(0x9e37_79b9_7f4a_7c15);
+        h = (h ^ (h >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        h = (h ^ (h >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        h ^= h >> 31;
+    }
+    (h >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain_generator::test_map;
+
+    fn map() -> &'static TerrainMap {
+        let map = test_map(41, 80, 60);
+        assert_eq!(map.seed, 41);
+        map
+    }
+
+    /// A tile in the north of `map()`
+    const NORTH: (usize, usize) = (40, 5);
+
+    /// Every sixth tile of `map()` each way
+    fn sample_tiles() -> Vec<(usize, usize)> {
+        let map = map();
+        (0..map.height).step_by(6).flat_map(|y| (0..map.width).step_by(6).map(move |x| (x, y))).collect()
+    }
+
+    fn yearly_precipitation(x: usize, y: usize) -> f32 {
+        (0..DAYS_PER_YEAR).map(|d| map().weather_at(x, y, d).unwrap().precipitation_mm).sum::<f32>()
+    }
+
+    #[test]
+    fn weather_repeats_for_a_place_and_day() {
+        let (x, y) = NORTH;
+        assert_eq!(map().weather_at(x, y, 100), map().weather_at(x, y, 100));
+        assert!(map().weather_at(80, 0, 0).is_none());
+    }
+
+    #[test]
+    fn neighbours_share_a_weather_system_and_other_seeds_bring_other_weather() {
+        let (map, (x, y)) = (map(), NORTH);
+        let today = map.weather_at(x, y, 100).unwrap();
+        let next_door = map.weather_at(x + 1, y, 100).unwrap();
+        assert!((today.temperature_c - next_door.temperature_c).abs() < 2.0);
+        let other_seed = TerrainMap { seed: 42, ..map.clone() };
+        assert_ne!(other_seed.weather_at(x, y, 100), Some(today));
+    }
+
+    #[test]
+    fn summer_in_the_north_is_winter_in_the_south() {
+        let mean = |y: usize, days: std::ops::Range<u32>| {
+            let n = days.len() as f32;
+            days.map(|d| map().weather_at(NORTH.0, y, d).unwrap().temperature_c).sum::<f32>() / n
+        };
+        let (north_summer, north_winter) = (mean(5, 150..215), mean(5, 0..30));
+        assert!(north_summer > north_winter + 10.0, "{} {}", north_summer, north_winter);
+        assert!(mean(55, 0..30) > mean(55, 150..215) + 10.0);
+    }
+
+    #[test]
+    fn the_moistest_ground_gets_the_most_rain() {
+        let tiles = sample_tiles();
+        let (dry, wet) = tiles.iter().fold((tiles[0], tiles[0]), |(dry, wet), &t| {
+            let m = |(x, y): (usize, usize)| map().terrain[y][x].moisture;
+            (if m(t) < m(dry) { t } else { dry }, if m(t) > m(wet) { t } else { wet })
+        });
+        assert!(yearly_precipitation(wet.0, wet.1) > yearly_precipitation(dry.0, dry.1));
+    }
+
+    #[test]
+    fn a_years_rain_is_near_the_climate_rasters_estimate() {
+        let tiles = sample_tiles();
+        let expected: f32 = tiles
+            .iter()
+            .map(|&(x, y)| {
+                let p = &map().terrain[y][x];
+                MAX_PRECIPITATION_MM * p.moisture as f32 * (0.3 + 0.7 * p.temperature as f32)
+            })
+            .sum();
+        let total: f32 = tiles.iter().map(|&(x, y)| yearly_precipitation(x, y)).sum();
+        assert!((total / expected - 1.0).abs() < 0.25, "{} vs {}", total, expected);
+    }
+
+    #[test]
+    fn conditions_follow_the_temperature_rain_and_cloud() {
+        let snow = Weather { temperature_c: -3.0, precipitation_mm: 2.0, cloud_cover: 1.0 };
+        assert_eq!(snow.conditions(), "snow");
+        assert_eq!(Weather { temperature_c: 12.0, ..snow }.conditions(), "rain");
+        assert_eq!(Weather { precipitation_mm: 0.0, cloud_cover: 0.1, ..snow }.conditions(), "clear");
+    }
+}
@@ -1,8 +1,474 @@
-use crate::terrain_generator::{Biome, TerrainMap};
-use image::{ImageBuffer, Rgb, RgbImage};
+use crate::terrain_generator::{
+    Biome, FogOfWar, NoiseSource, PlaceLabel, RiverOutlet, TerrainMap, TerrainPoint, Viewpoint,
+};
+use image::{ImageBuffer, Luma, Rgb, RgbImage, RgbaImage};
+use noise::Perlin;
+
+mod labels;
+
+pub use labels::{LabelRenderer, Projection};
 
 pub struct TerrainRenderer;
 
+/// Which map layers to draw. Pixels not covered by an enabled layer stay
+/// fully transparent, so e.g. rivers and roads alone export as an overlay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layers {
+    pub terrain: bool,
+    /// Hillshading on the terrain; without it the terrain is flat color
+    pub relief: bool,
+    pub rivers: bool,
+    pub roads: bool,
+    pub cities: bool,
+    /// Text: region names, plus city, road and bridge names when those
+    /// layers are drawn
+    pub labels: bool,
+    /// Each city's territory tinted, with a line along the borders. An
+    /// analysis overlay, so `all()` leaves it off
+    pub territories: bool,
+    /// City lights seen at night: a glow round each city by its size and
+    /// a faint line along the roads. An overlay for any style, so `all()`
+    /// leaves it off; `RenderStyle::Night` draws them with the terrain
+    pub lights: bool,
+    /// Clouds over everything else, shaped by `RenderOptions::clouds` or
+    /// else the map's seed. Off in `all()`
+    pub clouds: bool,
+}
+
+impl Layers {
+    pub const NAMES: [&'static str; 8] =
+        ["terrain", "rivers", "roads", "cities", "labels", "territories", "lights", "clouds"];
+
+    pub fn all() -> Self {
+        Layers {
+            terrain: true,
+            relief: true,
+            rivers: true,
+            roads: true,
+            cities: true,
+            labels: true,
+            territories: false,
+            lights: false,
+            clouds: false,
+        }
+    }
+
+    pub fn none() -> Self {
+        Layers {
+            terrain: false,
+            relief: false,
+            rivers: false,
+            roads: false,
+            cities: false,
+            labels: false,
+            territories: false,
+            lights: false,
+            clouds: false,
+        }
+    }
+
+    /// Parse a comma-separated layer list such as `terrain,rivers,roads`.
+    /// `terrain` includes relief shading.
+    pub fn parse(list: &str) -> Result<Self, String> {
+        let mut layers = Layers::none();
+        for name in list.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            match name {
+                "terrain" => {
+                    layers.terrain = true;
+                    layers.relief = true;
+                }
+                "rivers" => layers.rivers = true,
+                "roads" => layers.roads = true,
+                "cities" => layers.cities = true,
+                "labels" => layers.labels = true,
+                "territories" => layers.territories = true,
+                "lights" => layers.lights = true,
+                "clouds" => layers.clouds = true,
+                "all" => {
+                    layers = Layers {
+                        territories: layers.territories,
+                        lights: layers.lights,
+                        clouds: layers.clouds,
+                        ..Layers::all()
+                    }
+                }
+                _ => {
+                    return Err(format!(
+                        "unknown layer '{}' (available: {}, all)",
+                        name,
+                        Self::NAMES.join(", ")
+                    ))
+                }
+            }
+        }
+        Ok(layers)
+    }
+
+    /// Each enabled layer on its own, named as in `NAMES`, for rendering
+    /// separately and compositing (in this order) or exporting one by one.
+    /// Terrain keeps its relief setting.
+    pub fn split(self) -> Vec<(&'static str, Layers)> {
+        let none = Layers::none();
+        [
+            (self.terrain, "terrain", Layers { terrain: true, relief: self.relief, ..none }),
+            (self.territories, "territories", Layers { territories: true, ..none }),
+            (self.rivers, "rivers", Layers { rivers: true, ..none }),
+            (self.roads, "roads", Layers { roads: true, ..none }),
+            (self.lights, "lights", Layers { lights: true, ..none }),
+            (self.cities, "cities", Layers { cities: true, ..none }),
+            (self.clouds, "clouds", Layers { clouds: true, ..none }),
+            (self.labels, "labels", Layers { labels: true, ..none }),
+        ]
+        .into_iter()
+        .filter(|&(enabled, _, _)| enabled)
+        .map(|(_, name, layer)| (name, layer))
+        .collect()
+    }
+}
+
+impl Default for Layers {
+    fn default() -> Self {
+        Layers::all()
+    }
+}
+
+/// How the terrain is colored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RenderStyle {
+    /// Biome colors over an elevation tint, like a printed atlas
+    #[default]
+    Atlas,
+    /// As seen from orbit: deep blue seas with a sun glint, land from bare
+    /// tan to green by moisture with a fine texture, snow where it is cold
+    Satellite,
+    /// Retro game art: each tile a block of `PIXEL_PALETTE` color, with no
+    /// interpolation, stepped relief and optional dithering
+    PixelArt,
+    /// Black ink on white paper for printing and photocopying: hatched
+    /// water, stippled deserts, contour lines for relief, solid rivers,
+    /// roads and cities
+    LineArt,
+    /// A satellite picture at night: the land and sea dimmed to moonlight,
+    /// with the city lights (as the `lights` layer) drawn over the terrain
+    Night,
+}
+
+impl RenderStyle {
+    pub const NAMES: [&'static str; 5] = ["atlas", "satellite", "pixel-art", "line-art", "night"];
+
+    /// A style by name, as in `NAMES`.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "atlas" => Some(RenderStyle::Atlas),
+            "satellite" => Some(RenderStyle::Satellite),
+            "pixel-art" | "pixel" => Some(RenderStyle::PixelArt),
+            "line-art" | "print" => Some(RenderStyle::LineArt),
+            "night" => Some(RenderStyle::Night),
+            _ => None,
+        }
+    }
+}
+
+/// How the tiles the players haven't seen are drawn over a map by
+/// [`TerrainRenderer::draw_fog`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FogStyle {
+    /// Under a drifting grey fog that nothing shows through
+    #[default]
+    Hidden,
+    /// Drained of color and dimmed: the lie of the land shows, but
+    /// nothing on it stands out
+    Desaturated,
+}
+
+impl FogStyle {
+    pub const NAMES: [&'static str; 2] = ["hidden", "desaturated"];
+
+    /// A fog style by name, as in `NAMES`.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "hidden" => Some(FogStyle::Hidden),
+            "desaturated" | "grey" | "gray" => Some(FogStyle::Desaturated),
+            _ => None,
+        }
+    }
+}
+
+/// What is drawn behind each city name to keep it readable over busy
+/// ground. With a plate, the plate is the label's box when labels are kept
+/// from overlapping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NamePlate {
+    /// Just the outlined name
+    #[default]
+    None,
+    /// A translucent dark rounded rectangle
+    Rounded,
+    /// A parchment banner with swallow-tailed ends, the name inked on it in
+    /// brown, for fantasy maps
+    Banner,
+}
+
+impl NamePlate {
+    pub const NAMES: [&'static str; 3] = ["none", "rounded", "banner"];
+
+    /// A plate by name, as in `NAMES`.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "none" => Some(NamePlate::None),
+            "rounded" | "box" => Some(NamePlate::Rounded),
+            "banner" | "parchment" => Some(NamePlate::Banner),
+            _ => None,
+        }
+    }
+
+    /// Room the plate leaves either side of text `height` pixels tall, in
+    /// pixels (across, down); the banner's includes its tails.
+    pub fn padding(self, height: f32) -> (i32, i32) {
+        match self {
+            NamePlate::None => (0, 0),
+            NamePlate::Rounded => ((height * 0.3).ceil() as i32, (height * 0.12).ceil() as i32),
+            NamePlate::Banner => ((height * 0.75).ceil() as i32, (height * 0.18).ceil() as i32),
+        }
+    }
+
+    /// Color of the name written on the plate and of the lines under it,
+    /// unoutlined, or `None` for white names outlined in black.
+    pub fn ink(self) -> Option<([u8; 3], [u8; 3])> {
+        match self {
+            NamePlate::Banner => Some(([72, 46, 22], [120, 92, 60])),
+            _ => None,
+        }
+    }
+}
+
+/// How the terrain is shaded. The defaults suit the CLI's scale of 5;
+/// at small scales less detail reads better, at large ones more strength.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderOptions {
+    pub style: RenderStyle,
+    /// Multiplies the steepness of the hillshade: 0 is flat, 1 the default
+    pub relief_strength: f32,
+    /// How fine the relief's wrinkles are: the elevation gradient is taken
+    /// over 0.35 / `relief_detail` tiles, so below 1 smooths and above 1
+    /// sharpens
+    pub relief_detail: f32,
+    /// Color blended into steep slopes, like bare rock
+    pub slope_tint: [u8; 3],
+    /// Share of `slope_tint` on the steepest slopes, 0 to 1
+    pub slope_tint_strength: f32,
+    /// Bands of lighter water along the coasts, palest at the shore, like
+    /// an engraved atlas. 0 keeps the plain darkened edge
+    pub coast_rings: u32,
+    /// Width of each coast band, in tiles
+    pub coast_ring_width: f32,
+    /// A line of this color along every coast and lake shore, drawn from
+    /// the map's `coastlines`
+    pub coastline: Option<[u8; 3]>,
+    /// Clouds drifting over a `Satellite` render, shaped by this seed; it
+    /// shapes the `clouds` layer too
+    pub clouds: Option<u32>,
+    /// Snow and ice by temperature for a season, from 0 (high summer: only
+    /// the coldest ground) to 1 (deep winter: snow far down the mountains
+    /// and out from the poles); lakes and rivers under snow freeze over
+    pub snow: Option<f32>,
+    /// Dither `PixelArt` tiles between the two nearest palette colors
+    pub dither: bool,
+    /// Arrows along the rivers pointing downstream, a ring at each source
+    /// and a diamond where a river meets the sea, a lake or the map edge
+    pub flow_arrows: bool,
+    /// Samples across and down each pixel, averaged, for the smooth
+    /// coastlines, rivers and roads of a final export at that square the
+    /// cost; 1 to `MAX_SUPERSAMPLE`. `PixelArt` keeps its hard blocks
+    pub supersample: u32,
+    /// Which names are written
+    pub labels: LabelOptions,
+    /// Shade the terrain that cannot be seen from this point, which is
+    /// ringed
+    pub viewshed: Option<Viewpoint>,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            style: RenderStyle::Atlas,
+            relief_strength: 1.0,
+            relief_detail: 1.0,
+            slope_tint: [139, 90, 43],
+            slope_tint_strength: 0.1,
+            coast_rings: 0,
+            coast_ring_width: 0.6,
+            coastline: None,
+            clouds: None,
+            snow: None,
+            dither: false,
+            flow_arrows: false,
+            supersample: 1,
+            labels: LabelOptions::default(),
+            viewshed: None,
+        }
+    }
+}
+
+/// Which names are written on the map. The default writes every one, for
+/// a dense reference map; `POSTER` keeps a clean few. Only text is left
+/// out: the cities, roads and bridges themselves are still drawn.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LabelOptions {
+    /// Most cities named, the most populous first; `None` names them all
+    pub max_city_labels: Option<usize>,
+    /// Cities smaller than this go unnamed
+    pub min_city_population_for_label: u32,
+    pub show_bridges: bool,
+    /// Highway names along their roads
+    pub show_roads: bool,
+    /// Share of each kind of region name (oceans, mountains, forests,
+    /// swamps, rivers) written, 0 to 1, the biggest regions first
+    pub geographic_label_density: f32,
+    /// Cities at least this populous may be named a little way off, with a
+    /// leader line back to them, when there is no room beside them;
+    /// smaller ones go unnamed instead. `u32::MAX` draws no leader lines
+    pub leader_min_population: u32,
+    /// Width of the leader lines in pixels, over a paler halo
+    pub leader_width: f32,
+    /// How far the leader lines bow out, as a share of their length; 0
+    /// draws them straight
+    pub leader_curve: f32,
+    /// Drawn behind the city names
+    pub plates: NamePlate,
+}
+
+impl Default for LabelOptions {
+    fn default() -> Self {
+        LabelOptions {
+            max_city_labels: None,
+            min_city_population_for_label: 0,
+            show_bridges: true,
+            show_roads: true,
+            geographic_label_density: 1.0,
+            leader_min_population: 20_000,
+            leader_width: 1.2,
+            leader_curve: 0.15,
+            plates: NamePlate::None,
+        }
+    }
+}
+
+impl LabelOptions {
+    /// A wall map: the dozen largest cities, no road or bridge names and
+    /// half the regions
+    pub const POSTER: LabelOptions = LabelOptions {
+        max_city_labels: Some(12),
+        min_city_population_for_label: 50_000,
+        show_bridges: false,
+        show_roads: false,
+        geographic_label_density: 0.5,
+        leader_min_population: 50_000,
+        leader_width: 1.2,
+        leader_curve: 0.15,
+        plates: NamePlate::None,
+    };
+
+    pub const NAMES: [&'static str; 2] = ["reference", "poster"];
+
+    /// Options by name, as in `NAMES`: `reference` is the default.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "reference" | "all" => Some(LabelOptions::default()),
+            "poster" => Some(LabelOptions::POSTER),
+            _ => None,
+        }
+    }
+
+    /// The cities of `map` to name at `pixels_per_tile`, as (index, text)
+    /// in map order: those `TerrainMap::city_labels` names that are big
+    /// enough, at most `max_city_labels` of them.
+    pub fn city_labels(&self, map: &TerrainMap, pixels_per_tile: f32) -> Vec<(usize, String)> {
+        let mut labels: Vec<(usize, String)> = map
+            .city_labels(pixels_per_tile)
+            .into_iter()
+            .filter(|&(index, _)| map.cities[index].population >= self.min_city_population_for_label)
+            .collect();
+        if let Some(max) = self.max_city_labels.filter(|&max| max < labels.len()) {
+            labels.sort_by_key(|&(index, _)| std::cmp::Reverse(map.cities[index].population));
+            labels.truncate(max);
+            labels.sort_by_key(|&(index, _)| index);
+        }
+        labels
+    }
+
+    /// Whether a city of `population` may be named away from it, with a
+    /// leader line.
+    pub fn has_leader(&self, population: u32) -> bool {
+        population >= self.leader_min_population
+    }
+
+    /// Points along the leader line from a label's edge at `from` to its
+    /// city at `to`, in pixels about one apart: a quadratic curve bowed
+    /// `leader_curve` of its length to the left.
+    pub fn leader_path(&self, from: [f32; 2], to: [f32; 2]) -> Vec<[f32; 2]> {
+        let (dx, dy) = (to[0] - from[0], to[1] - from[1]);
+        let length = dx.hypot(dy);
+        if length < 1.0 {
+            return vec![from, to];
+        }
+        let bow = length * self.leader_curve;
+        let control = [(from[0] + to[0]) / 2.0 + dy / length * bow, (from[1] + to[1]) / 2.0 - dx / length * bow];
+        let steps = (length * (1.0 + self.leader_curve.abs())).ceil() as usize;
+        (0..=steps)
+            .map(|step| {
+                let t = step as f32 / steps as f32;
+                let u = 1.0 - t;
+                [0, 1].map(|c| u * u * from[c] + 2.0 * u * t * control[c] + t * t * to[c])
+            })
+            .collect()
+    }
+
+    /// The region names of `map` to write: of each kind, the first
+    /// `geographic_label_density` of them (the labeller names the biggest
+    /// regions first), rounded up.
+    pub fn region_labels<'a>(&self, map: &'a TerrainMap) -> Vec<&'a PlaceLabel> {
+        let density = self.geographic_label_density.clamp(0.0, 1.0) as f64;
+        let mut seen: Vec<(&str, usize)> = Vec::new();
+        map.labels
+            .iter()
+            .filter(|label| {
+                let kind = label.feature_type.as_str();
+                let total = map.labels.iter().filter(|l| l.feature_type == kind).count();
+                let rank = match seen.iter_mut().find(|(k, _)| *k == kind) {
+                    Some((_, count)) => {
+                        *count += 1;
+                        *count - 1
+                    }
+                    None => {
+                        seen.push((kind, 1));
+                        0
+                    }
+                };
+                rank < (total as f64 * density).ceil() as usize
+            })
+            .collect()
+    }
+}
+
+/// A rectangle of the rendered map image, in pixels at the render scale.
+/// Rendering a viewport gives exactly the pixels of that rectangle in a
+/// full render, without drawing the rest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Viewport {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// Most samples across and down a pixel `RenderOptions::supersample` takes
+pub const MAX_SUPERSAMPLE: u32 = 4;
+
+/// Rows of the final image rendered at a time when supersampling, which
+/// keeps the finer render's memory in bounds
+const SUPERSAMPLE_STRIP: usize = 256;
+
 /// Smooth ocean gradient from abyss (elevation -1) to sea level (0).
 /// Used instead of discrete biome colors so the water shows no banding.
 fn water_color(elevation: f64) -> [f32; 3] {
@@ -14,33 +480,504 @@ fn water_color(elevation: f64) -> [f32; 3] {
     ]
 }
 
+/// Color the coast bands are lightened towards
+const COAST_RING_COLOR: [f32; 3] = [205.0, 228.0, 240.0];
+
+/// Share of `COAST_RING_COLOR` in the band at the shore
+const COAST_RING_STRENGTH: f32 = 0.45;
+
+/// Color of the coastlines in SVG exports when `RenderOptions::coastline`
+/// gives none
+pub const COAST_INK: [u8; 3] = [25, 45, 80];
+
+/// Color of the dotted boundaries of the parks, with the labels
+pub const PARK_INK: [u8; 3] = [20, 75, 30];
+
+/// Color of the power lines and their pylons, with the roads
+pub const POWER_LINE_INK: [u8; 3] = [95, 95, 110];
+
+/// Temperature below which snow lies at the height of summer (`snow` 0)
+pub const SUMMER_SNOW_LINE: f32 = 0.08;
+
+/// Temperature below which snow lies in deep winter (`snow` 1)
+pub const WINTER_SNOW_LINE: f32 = 0.24;
+
+/// How much colder than the snow line the snow lies unbroken
+const SNOW_FADE: f32 = 0.03;
+
+/// Color of fresh snow
+const SNOW_COLOR: [f32; 3] = [240.0, 244.0, 248.0];
+
+/// Color of frozen lakes and rivers, and of the cracks across them
+pub const ICE_COLOR: [u8; 3] = [198, 221, 240];
+const ICE_CRACK: [u8; 3] = [122, 158, 200];
+
+/// How much of a tile at `temperature` snow covers in `season` (as
+/// `RenderOptions::snow`), 0 to 1: none above the snow line, all of it
+/// `SNOW_FADE` below.
+fn snow_cover(temperature: f64, season: f32) -> f32 {
+    let line = SUMMER_SNOW_LINE + (WINTER_SNOW_LINE - SUMMER_SNOW_LINE) * season.clamp(0.0, 1.0);
+    ((line - temperature as f32) / SNOW_FADE).clamp(0.0, 1.0)
+}
+
+/// Whether the water at `point` is frozen over in `season`: where snow
+/// would mostly cover the ground.
+pub(crate) fn is_frozen(point: &TerrainPoint, season: f32) -> bool {
+    snow_cover(point.temperature, season) >= 0.5
+}
+
+/// Whether a crack in the ice runs through tile coordinates (`x`, `y`);
+/// `period` as for [`fractal`] at a frequency of 0.6.
+fn ice_crack(ice: &Perlin, x: f32, y: f32, period: Option<f32>) -> bool {
+    fractal(ice, x * 0.6, y * 0.6, 2, period).abs() < 0.03
+}
+
+/// `color` by moonlight, for the `Night` style: dimmed and cooled, water
+/// darkest.
+fn moonlit(color: [f32; 3], water: bool) -> [f32; 3] {
+    let dim = if water { 0.1 } else { 0.18 };
+    let mut color = color;
+    for (channel, (cool, base)) in color.iter_mut().zip([(0.8, 4.0), (0.9, 6.0), (1.0, 14.0)]) {
+        *channel = *channel * dim * cool + base;
+    }
+    color
+}
+
+/// Color of dim city lights, sodium orange
+pub const LIGHT_COLOR: [u8; 3] = [255, 176, 72];
+
+/// Color of the brightest city lights, where they run together
+pub const LIGHT_CORE: [u8; 3] = [255, 244, 214];
+
+/// How bright the city lights are at each pixel of `view`, row-major: a
+/// glow round each city, wider and brighter the more people live there and
+/// broken up like streets and blocks, and a dotted line along each road. At
+/// 1 and over a pixel is fully lit.
+fn city_lights(map: &TerrainMap, scale: usize, view: Viewport, shifts: &[f32]) -> Option<Vec<f32>> {
+    let mut glow = try_filled(view.width * view.height, 0.0f32)?;
+    let s = scale as f32;
+    let at = |px: i64, py: i64| {
+        let (x, y) = (px - view.x as i64, py - view.y as i64);
+        (x >= 0 && y >= 0 && x < view.width as i64 && y < view.height as i64)
+            .then(|| y as usize * view.width + x as usize)
+    };
+
+    // The roads: lamps and traffic along them, brighter on the big roads
+    let lamp = (s * 0.15).max(0.5);
+    for (index, road) in map.roads.iter().enumerate() {
+        let strength = match road.road_type.as_str() {
+            "motorway" | "highway" => 0.3,
+            "road" => 0.18,
+            _ => 0.08,
+        };
+        let curve = map.road_curve(index);
+        for (&shift, step) in shifts.iter().flat_map(|shift| curve.windows(2).map(move |step| (shift, step))) {
+            let (a, b) = (((step[0].0 + shift + 0.5) * s, (step[0].1 + 0.5) * s), ((step[1].0 + shift + 0.5) * s, (step[1].1 + 0.5) * s));
+            let steps = (b.0 - a.0).abs().max((b.1 - a.1).abs()).ceil().max(1.0) as usize;
+            for i in 0..=steps {
+                let t = i as f32 / steps as f32;
+                let (cx, cy) = (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t);
+                let r = lamp.ceil() as i64;
+                for dy in -r..=r {
+                    for dx in -r..=r {
+                        let (px, py) = (cx.round() as i64 + dx, cy.round() as i64 + dy);
+                        if let Some(index) = at(px, py) {
+                            let light = strength * (0.2 + pixel_hash(px as usize, py as usize));
+                            glow[index] = glow[index].max(light);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // The cities: a bright core fading out through the suburbs, added
+    // together so neighbouring towns run into one another
+    let streets = Perlin::new(23);
+    for (&shift, city) in shifts.iter().flat_map(|shift| map.cities.iter().map(move |city| (shift, city))) {
+        let people = city.population as f32;
+        let radius = (1.0 + (people / 10_000.0).sqrt() * 0.7) * s;
+        let brightness = (0.6 + (people / 100_000.0).sqrt() * 0.45).min(1.8);
+        let (cx, cy) = ((city.x as f32 + shift + 0.5) * s, (city.y as f32 + 0.5) * s);
+        let reach = (radius * 1.6).ceil() as i64;
+        for py in cy as i64 - reach..=cy as i64 + reach {
+            for px in cx as i64 - reach..=cx as i64 + reach {
+                let Some(index) = at(px, py) else {
+                    continue;
+                };
+                let d = (px as f32 - cx).hypot(py as f32 - cy) / radius;
+                let (tx, ty) = (px as f32 / s, py as f32 / s);
+                let blocks = (0.75 + 0.6 * fractal(&streets, tx * 2.5, ty * 2.5, 2, None)).max(0.0);
+                glow[index] += brightness * (-2.0 * d * d).exp() * blocks;
+            }
+        }
+    }
+    Some(glow)
+}
+
+/// Which pixels of `view` lie within `radius` pixels of the map's
+/// coastlines, row-major; the stretches along the map's edge are left out.
+fn coastline_pixels(map: &TerrainMap, scale: usize, view: Viewport, radius: f32) -> Option<Vec<bool>> {
+    let mut mask = try_filled(view.width * view.height, false)?;
+    let s = scale as f32;
+    let runs = map.coastlines.iter().flat_map(|coast| coast.runs(map.width, map.height));
+    for run in runs {
+        for step in run.windows(2) {
+            let (a, b) = ((step[0].0 * s, step[0].1 * s), (step[1].0 * s, step[1].1 * s));
+            let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+            let length = dx * dx + dy * dy;
+            let columns = (a.0.min(b.0) - radius).floor().max(view.x as f32) as usize
+                ..((a.0.max(b.0) + radius).ceil().max(0.0) as usize + 1).min(view.x + view.width);
+            let rows = (a.1.min(b.1) - radius).floor().max(view.y as f32) as usize
+                ..((a.1.max(b.1) + radius).ceil().max(0.0) as usize + 1).min(view.y + view.height);
+            for y in rows {
+                for x in columns.clone() {
+                    let (px, py) = (x as f32, y as f32);
+                    let t = if length > 0.0 { (((px - a.0) * dx + (py - a.1) * dy) / length).clamp(0.0, 1.0) } else { 0.0 };
+                    if (px - a.0 - t * dx).hypot(py - a.1 - t * dy) <= radius {
+                        mask[(y - view.y) * view.width + x - view.x] = true;
+                    }
+                }
+            }
+        }
+    }
+    Some(mask)
+}
+
+/// Distance in tiles from each tile to the nearest land tile (0 on land),
+/// row-major, by a two-pass chamfer transform. Capped at `limit`, which is
+/// all the coast rings need. With `wrap`, distances are measured across the
+/// east-west seam, running the passes twice to carry them over it.
+fn coast_distance(map: &TerrainMap, width: usize, height: usize, limit: f32, wrap: bool) -> Vec<f32> {
+    let mut distance: Vec<f32> = (0..width * height)
+        .map(|i| if map.terrain[i / width][i % width].biome.is_water() { limit } else { 0.0 })
+        .collect();
+    let diagonal = std::f32::consts::SQRT_2;
+    let mut relax = |x: usize, y: usize, neighbours: &[(isize, isize, f32)]| {
+        for &(dx, dy, step) in neighbours {
+            let (nx, ny) = (x as isize + dx, y as isize + dy);
+            let nx = if wrap { nx.rem_euclid(width as isize) } else { nx };
+            if nx < 0 || ny < 0 || nx as usize >= width || ny as usize >= height {
+                continue;
+            }
+            let through = distance[ny as usize * width + nx as usize] + step;
+            let here = &mut distance[y * width + x];
+            *here = here.min(through);
+        }
+    };
+    let before = [(-1, 0, 1.0), (0, -1, 1.0), (-1, -1, diagonal), (1, -1, diagonal)];
+    let after = [(1, 0, 1.0), (0, 1, 1.0), (1, 1, diagonal), (-1, 1, diagonal)];
+    for _ in 0..if wrap { 2 } else { 1 } {
+        for y in 0..height {
+            for x in 0..width {
+                relax(x, y, &before);
+            }
+        }
+        for y in (0..height).rev() {
+            for x in (0..width).rev() {
+                relax(x, y, &after);
+            }
+        }
+    }
+    distance
+}
+
+/// The colors of the `PixelArt` style
+pub const PIXEL_PALETTE: [[u8; 3]; 16] = [
+    [16, 24, 64],    // Deep sea
+    [29, 60, 130],   // Sea
+    [41, 115, 182],  // Shallows
+    [95, 175, 215],  // Surf, rivers
+    [230, 214, 156], // Sand
+    [198, 166, 92],  // Dry grass
+    [120, 178, 70],  // Grass
+    [70, 136, 52],   // Meadow
+    [36, 88, 44],    // Forest
+    [84, 96, 52],    // Marsh
+    [140, 110, 76],  // Hills
+    [104, 90, 80],   // Rock
+    [150, 145, 140], // Pale rock
+    [240, 244, 248], // Snow
+    [60, 52, 48],    // Roads
+    [200, 40, 40],   // Cities
+];
+
+/// Ordered 4x4 Bayer thresholds, in 0..1
+const BAYER_4X4: [[f32; 4]; 4] = [
+    [0.0 / 16.0, 8.0 / 16.0, 2.0 / 16.0, 10.0 / 16.0],
+    [12.0 / 16.0, 4.0 / 16.0, 14.0 / 16.0, 6.0 / 16.0],
+    [3.0 / 16.0, 11.0 / 16.0, 1.0 / 16.0, 9.0 / 16.0],
+    [15.0 / 16.0, 7.0 / 16.0, 13.0 / 16.0, 5.0 / 16.0],
+];
+
+/// `color` reduced to `PIXEL_PALETTE`. Dithered, the two nearest entries
+/// are mixed in proportion by the Bayer threshold at tile `(x, y)`.
+fn palette_color(color: [f32; 3], dither: Option<(usize, usize)>) -> [u8; 3] {
+    let distance = |entry: &[u8; 3]| {
+        (0..3).map(|c| (color[c] - entry[c] as f32).powi(2)).sum::<f32>()
+    };
+    let mut ranked: Vec<&[u8; 3]> = PIXEL_PALETTE.iter().collect();
+    ranked.sort_by(|a, b| distance(a).total_cmp(&distance(b)));
+    let (nearest, second) = (ranked[0], ranked[1]);
+    let Some((x, y)) = dither else {
+        return *nearest;
+    };
+    // How far along from the nearest entry to the second the color lies
+    let span: [f32; 3] = [0, 1, 2].map(|c| second[c] as f32 - nearest[c] as f32);
+    let along: f32 = (0..3).map(|c| (color[c] - nearest[c] as f32) * span[c]).sum::<f32>()
+        / span.iter().map(|v| v * v).sum::<f32>().max(1.0);
+    if along.clamp(0.0, 0.5) > BAYER_4X4[y % 4][x % 4] {
+        *second
+    } else {
+        *nearest
+    }
+}
+
+/// Tiles between the flow arrows along a river
+const ARROW_SPACING: usize = 8;
+
+/// Flow arrows along `river` of `map`, traced source to mouth: each a
+/// point and a unit direction downstream, in tile coordinates (tile centres
+/// at +0.5), unrolled across the seam as `TerrainMap::unwrapped_path` is.
+pub(crate) fn flow_arrows(map: &TerrainMap, river: &[(usize, usize)]) -> Vec<([f32; 2], [f32; 2])> {
+    let path = map.unwrapped_path(river);
+    let centre = |i: usize| [path[i].0 + 0.5, path[i].1 + 0.5];
+    (ARROW_SPACING / 2..river.len().saturating_sub(1))
+        .step_by(ARROW_SPACING)
+        .filter_map(|i| {
+            let (from, to) = (centre(i - 1), centre(i + 1));
+            let (dx, dy) = (to[0] - from[0], to[1] - from[1]);
+            let length = (dx * dx + dy * dy).sqrt();
+            (length > 0.0).then(|| (centre(i), [dx / length, dy / length]))
+        })
+        .collect()
+}
+
+/// Elevation between the contour lines of the `LineArt` style
+const CONTOUR_INTERVAL: f64 = 0.08;
+
+/// Tiles out from the coast that `LineArt` hatches the water
+const HATCH_REACH: f32 = 2.5;
+
+/// A well-mixed hash of pixel `(x, y)` in 0..1, for stippling.
+fn pixel_hash(x: usize, y: usize) -> f32 {
+    let mut h = (x as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15) ^ (y as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F);
+    h ^= h >> 29;
+    h = h.wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    h ^= h >> 32;
+    (h % 10_000) as f32 / 10_000.0
+}
+
+/// A tile's color seen from orbit, before texture and relief.
+fn satellite_color(point: &TerrainPoint) -> [f32; 3] {
+    let mix = |a: [f32; 3], b: [f32; 3], t: f32| [0, 1, 2].map(|c| a[c] + (b[c] - a[c]) * t.clamp(0.0, 1.0));
+    let (moisture, temperature) = (point.moisture as f32, point.temperature as f32);
+    let land = match point.biome {
+        Biome::Lake => return [18.0, 55.0, 85.0],
+        // Pale shelf seas darkening to near black in the deeps
+        biome if biome.is_water() => {
+            let depth = (-point.elevation).clamp(0.0, 1.0) as f32;
+            return mix([35.0, 105.0, 135.0], [3.0, 14.0, 45.0], depth.sqrt());
+        }
+        Biome::SnowPeaks => return [236.0, 240.0, 245.0],
+        Biome::Beach => [205.0, 190.0, 150.0],
+        biome => {
+            // Vegetation follows moisture, from bare tan to deep green
+            let green = mix([185.0, 160.0, 115.0], [45.0, 85.0, 35.0], moisture);
+            match biome {
+                Biome::Forest => mix(green, [28.0, 60.0, 28.0], 0.5),
+                Biome::Desert => mix(green, [215.0, 185.0, 135.0], 0.6),
+                Biome::Swamp => mix(green, [55.0, 75.0, 50.0], 0.5),
+                Biome::Hills => mix(green, [125.0, 112.0, 88.0], 0.3),
+                Biome::Mountains => mix(green, [130.0, 120.0, 108.0], 0.7),
+                _ => green,
+            }
+        }
+    };
+    // Snow lies wherever it is cold enough
+    mix(land, [232.0, 236.0, 242.0], (0.2 - temperature) / 0.2)
+}
+
+/// Fractal noise in -1..1 at tile coordinates, `octaves` of `perlin`,
+/// repeating every `period` along x when there is one.
+fn fractal(perlin: &Perlin, x: f32, y: f32, octaves: u32, period: Option<f32>) -> f32 {
+    let (mut sum, mut amplitude, mut frequency, mut total) = (0.0, 1.0, 1.0, 0.0);
+    for _ in 0..octaves {
+        let point = [x as f64 * frequency, y as f64 * frequency];
+        sum += perlin.sample_wrapped(point, period.map(|p| p as f64 * frequency)) * amplitude;
+        total += amplitude;
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+    (sum / total) as f32
+}
+
+/// How thick the clouds shaped by `clouds` are over tile coordinates
+/// (`x`, `y`), 0 for clear sky to 0.85; `period` as for [`fractal`] at a
+/// frequency of 1/14.
+fn cloud_density(clouds: &Perlin, x: f32, y: f32, period: Option<f32>) -> f32 {
+    let cover = fractal(clouds, x / 14.0, y / 14.0, 5, period);
+    ((cover - 0.12) * 2.5).clamp(0.0, 0.85)
+}
+
+/// `len` copies of `value`, or `None` if the memory can't be had, so a
+/// render too large for it gives up instead of aborting.
+fn try_filled<T: Clone>(len: usize, value: T) -> Option<Vec<T>> {
+    let mut buffer = Vec::new();
+    buffer.try_reserve_exact(len).ok()?;
+    buffer.resize(len, value);
+    Some(buffer)
+}
+
+/// Alpha-composite the RGBA pixel `src` over `dst`.
+fn blend_pixel(dst: &mut [u8], src: &[u8]) {
+    match src[3] {
+        0 => {}
+        255 => dst.copy_from_slice(src),
+        _ => {
+            let src_alpha = src[3] as f32 / 255.0;
+            let dst_alpha = dst[3] as f32 / 255.0;
+            let out_alpha = src_alpha + dst_alpha * (1.0 - src_alpha);
+            for c in 0..3 {
+                dst[c] = ((src[c] as f32 * src_alpha
+                    + dst[c] as f32 * dst_alpha * (1.0 - src_alpha))
+                    / out_alpha)
+                    .round() as u8;
+            }
+            dst[3] = (out_alpha * 255.0).round() as u8;
+        }
+    }
+}
+
+/// Tint for city `index`'s territory: hues a golden angle apart, so
+/// neighbouring cities (usually close in index too) differ clearly.
+fn territory_color(index: usize) -> [u8; 3] {
+    let hue = (index as f32 * 137.508) % 360.0 / 60.0;
+    let x = 1.0 - (hue % 2.0 - 1.0).abs();
+    let (r, g, b) = match hue as u32 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    // Muted: mixed halfway to mid grey
+    let channel = |v: f32| (64.0 + v * 160.0) as u8;
+    [channel(r), channel(g), channel(b)]
+}
+
 impl TerrainRenderer {
-    /// Renders a terrain map to RGBA pixel data
+    /// Renders a terrain map to RGBA pixel data. Never panics: sizes the
+    /// map's grid doesn't cover leave the terrain transparent, and a buffer
+    /// too large to address or allocate comes back empty.
     pub fn render_to_pixels(
         map: &TerrainMap,
         width: usize,
         height: usize,
         scale: usize,
     ) -> Vec<u8> {
-        let img_width = width * scale;
-        let img_height = height * scale;
-        let mut pixels = vec![0u8; img_width * img_height * 4];
+        Self::render_layers_to_pixels(map, width, height, scale, Layers::all(), &RenderOptions::default())
+    }
+
+    /// Renders only the selected layers to RGBA pixel data, composited in
+    /// order terrain, rivers, roads, cities. Labels are drawn by the callers.
+    pub fn render_layers_to_pixels(
+        map: &TerrainMap,
+        width: usize,
+        height: usize,
+        scale: usize,
+        layers: Layers,
+        options: &RenderOptions,
+    ) -> Vec<u8> {
+        let whole = Viewport {
+            x: 0,
+            y: 0,
+            width: width.saturating_mul(scale),
+            height: height.saturating_mul(scale),
+        };
+        Self::render_region(map, width, height, scale, whole, layers, options)
+    }
+
+    /// Renders the selected layers inside `viewport` only, for showing part
+    /// of a map zoomed in. The result is `viewport.width` x
+    /// `viewport.height` RGBA pixels.
+    pub fn render_viewport_to_pixels(
+        map: &TerrainMap,
+        scale: usize,
+        viewport: Viewport,
+        layers: Layers,
+        options: &RenderOptions,
+    ) -> Vec<u8> {
+        Self::render_region(map, map.width, map.height, scale, viewport, layers, options)
+    }
+
+    fn render_region(
+        map: &TerrainMap,
+        width: usize,
+        height: usize,
+        scale: usize,
+        view: Viewport,
+        layers: Layers,
+        options: &RenderOptions,
+    ) -> Vec<u8> {
+        if options.supersample > 1 && options.style != RenderStyle::PixelArt {
+            return Self::render_supersampled(map, width, height, scale, view, layers, options);
+        }
+        // Everything below draws in full-image pixel coordinates; the
+        // output buffer only covers `view`
+        let img_width = view.width;
+        let img_height = view.height;
+        let Some(mut pixels) = img_width
+            .checked_mul(img_height)
+            .and_then(|n| n.checked_mul(4))
+            .and_then(|len| try_filled(len, 0u8))
+        else {
+            return Vec::new();
+        };
 
-        if width < 2 || height < 2 {
+        // A map whose grid is smaller than its stated size (e.g. a
+        // hand-edited file) has nothing safe to sample
+        let covered = map.terrain.len() >= height
+            && map.terrain[..height].iter().all(|row| row.len() >= width);
+        if width < 2 || height < 2 || !covered {
             return pixels;
         }
 
+        // A map that wraps east-west is drawn so the image tiles seamlessly:
+        // the last column blends into the first, and whatever spills past
+        // one edge is drawn again at the other (at each of `shifts`)
+        let wrap = map.wrap_x && width == map.width;
+        let shifts: Vec<f32> = if wrap { map.wrap_shifts() } else { vec![0.0] };
+        // The columns either side of tile coordinate `x` and how far along
+        // from the first: across the seam when wrapping, clamped otherwise
+        let columns = |x: f32| -> (usize, usize, f32) {
+            if wrap {
+                let x = x.rem_euclid(width as f32);
+                let x0 = (x.floor() as usize).min(width - 1);
+                (x0, (x0 + 1) % width, x - x0 as f32)
+            } else {
+                let x0 = (x.max(0.0).floor() as usize).min(width - 1);
+                (x0, (x0 + 1).min(width - 1), (x - x0 as f32).clamp(0.0, 1.0))
+            }
+        };
+
         // Bilinear elevation sampling at sub-tile precision (clamped at edges)
         let sample_elevation = |tx: f32, ty: f32| -> f64 {
-            let x0 = (tx.max(0.0).floor() as usize).min(width - 2);
             let y0 = (ty.max(0.0).floor() as usize).min(height - 2);
-            let fx = ((tx - x0 as f32).clamp(0.0, 1.0)) as f64;
             let fy = ((ty - y0 as f32).clamp(0.0, 1.0)) as f64;
+            let (x0, x1, fx) = if wrap {
+                let (x0, x1, fx) = columns(tx);
+                (x0, x1, fx as f64)
+            } else {
+                let x0 = (tx.max(0.0).floor() as usize).min(width - 2);
+                (x0, x0 + 1, ((tx - x0 as f32).clamp(0.0, 1.0)) as f64)
+            };
 
             let e00 = map.terrain[y0][x0].elevation;
-            let e10 = map.terrain[y0][x0 + 1].elevation;
+            let e10 = map.terrain[y0][x1].elevation;
             let e01 = map.terrain[y0 + 1][x0].elevation;
-            let e11 = map.terrain[y0 + 1][x0 + 1].elevation;
+            let e11 = map.terrain[y0 + 1][x1].elevation;
 
             let e0 = e00 * (1.0 - fx) + e10 * fx;
             let e1 = e01 * (1.0 - fx) + e11 * fx;
@@ -49,19 +986,19 @@ impl TerrainRenderer {
 
         // Helper function to get terrain color with smooth coastlines
         let get_terrain_color = |x: f32, y: f32| -> [f32; 3] {
-            let x0 = (x.max(0.0).floor() as usize).min(width - 1);
+            let (x0, x1, fx) = columns(x);
             let y0 = (y.max(0.0).floor() as usize).min(height - 1);
-            let x1 = (x0 + 1).min(width - 1);
             let y1 = (y0 + 1).min(height - 1);
 
-            let fx = (x - x0 as f32).clamp(0.0, 1.0);
             let fy = (y - y0 as f32).clamp(0.0, 1.0);
 
             // Get the four corner points
             let get_point_data = |px: usize, py: usize| -> ([f32; 3], bool) {
                 let terrain_point = &map.terrain[py][px];
                 let is_water = terrain_point.biome.is_water();
-                let color = if terrain_point.biome == Biome::Lake {
+                let color = if options.style == RenderStyle::Satellite {
+                    satellite_color(terrain_point)
+                } else if terrain_point.biome == Biome::Lake {
                     let c = terrain_point.biome.color();
                     [c[0] as f32, c[1] as f32, c[2] as f32]
                 } else if is_water {
@@ -80,6 +1017,17 @@ impl TerrainRenderer {
                             + biome_color[2] as f32 * blend_factor,
                     ]
                 };
+                // Snow over the cold ground and ice on the cold lakes
+                let color = match options.snow {
+                    Some(season) if terrain_point.biome == Biome::Lake && is_frozen(terrain_point, season) => {
+                        ICE_COLOR.map(|c| c as f32)
+                    }
+                    Some(season) if !is_water => {
+                        let cover = snow_cover(terrain_point.temperature, season);
+                        [0, 1, 2].map(|c| color[c] + (SNOW_COLOR[c] - color[c]) * cover)
+                    }
+                    _ => color,
+                };
                 (color, is_water)
             };
 
@@ -145,249 +1093,693 @@ impl TerrainRenderer {
             }
         };
 
-        // Render each pixel with smooth interpolation
-        for py in 0..img_height {
-            for px in 0..img_width {
-                // Calculate position in terrain space with sub-pixel precision
-                let tx = px as f32 / scale as f32;
-                let ty = py as f32 / scale as f32;
-
-                let mut color = get_terrain_color(tx, ty);
-
-                let terrain_x = (tx.floor() as usize).min(width - 1);
-                let terrain_y = (ty.floor() as usize).min(height - 1);
-                let current_terrain = &map.terrain[terrain_y][terrain_x];
-
-                let elev_center = sample_elevation(tx, ty);
-
-                // Hillshade relief on land, from the smoothly interpolated
-                // elevation gradient (no screen-space texture patterns)
-                if elev_center > 0.0 {
-                    let elevation_factor = elev_center.clamp(0.0, 1.0);
-
-                    // Stronger relief at higher elevations, subtle on plains
-                    let gradient_scale = if elev_center > 0.82 {
-                        25.0 + elevation_factor * 5.0 // Mountains
-                    } else if elev_center > 0.6 {
-                        15.0 + elevation_factor * 10.0 // Hills
-                    } else if elev_center > 0.18 {
-                        8.0 + elevation_factor * 7.0 // Uplands
-                    } else {
-                        3.0 + elevation_factor * 5.0 // Plains
-                    };
+        // Bilinear sample of a per-tile field, on the same corners as the
+        // terrain colors
+        let sample_field = |field: &[f32], x: f32, y: f32| -> f32 {
+            let (x0, x1, fx) = columns(x);
+            let y0 = (y.max(0.0).floor() as usize).min(height - 1);
+            let y1 = (y0 + 1).min(height - 1);
+            let fy = (y - y0 as f32).clamp(0.0, 1.0);
+            let top = field[y0 * width + x0] * (1.0 - fx) + field[y0 * width + x1] * fx;
+            let bottom = field[y1 * width + x0] * (1.0 - fx) + field[y1 * width + x1] * fx;
+            top * (1.0 - fy) + bottom * fy
+        };
+        let ring_width = options.coast_ring_width.max(0.05);
+        let coast_rings = (layers.terrain && options.coast_rings > 0).then(|| {
+            // Water within reach of the outermost ring, and which side of
+            // the coastline a pixel falls on (as the terrain colors decide)
+            let reach = options.coast_rings as f32 * ring_width + 1.0;
+            let distance = coast_distance(map, width, height, reach, wrap);
+            let wet: Vec<f32> = distance.iter().map(|d| d.min(1.0)).collect();
+            (distance, wet)
+        });
 
-                    let sample_dist = 0.35;
-                    let dx = (sample_elevation(tx + sample_dist, ty)
-                        - sample_elevation(tx - sample_dist, ty))
-                        * gradient_scale
-                        / (sample_dist as f64 * 2.0);
-                    let dy = (sample_elevation(tx, ty + sample_dist)
-                        - sample_elevation(tx, ty - sample_dist))
-                        * gradient_scale
-                        / (sample_dist as f64 * 2.0);
-
-                    // Light from the northwest
-                    let light = (-0.7071, -0.7071, 0.5);
-
-                    // Surface normal from the gradient
-                    let normal_len = (dx * dx + dy * dy + 1.0).sqrt();
-                    let lighting = ((-dx) * light.0 + (-dy) * light.1 + light.2).max(0.0)
-                        / normal_len;
-
-                    // Moderate contrast: brighter on lit slopes, darker in shade
-                    let contrast = 0.3 + elevation_factor as f32 * 0.4;
-                    let shade_factor = if lighting > 0.6 {
-                        1.0 + (lighting - 0.6) as f32 * contrast
-                    } else {
-                        0.7 + lighting as f32 * 0.5
-                    };
+        // Satellite texture, sun glint (centre and radius in tiles) and clouds
+        let satellite = options.style == RenderStyle::Satellite;
+        let texture = Perlin::new(7);
+        let glint = (width as f32 * 0.35, height as f32 * 0.3, width.max(height) as f32 * 0.3);
+        let clouds = options.clouds.filter(|_| satellite).map(Perlin::new);
+        let night = options.style == RenderStyle::Night;
+        let ice = Perlin::new(41);
+        // Where texture sampled at `frequency` times the tile coordinates
+        // repeats: once round a map that wraps
+        let period = |frequency: f32| wrap.then_some(width as f32 * frequency);
 
-                    color[0] = (color[0] * shade_factor).min(255.0);
-                    color[1] = (color[1] * shade_factor).min(255.0);
-                    color[2] = (color[2] * shade_factor).min(255.0);
-
-                    // Slight brown tint on steep slopes
-                    if dx.abs() > 0.1 || dy.abs() > 0.1 {
-                        let slope_intensity = ((dx.abs() + dy.abs()).min(1.0) * 0.1) as f32;
-                        color[0] = (color[0] * (1.0 - slope_intensity) + 139.0 * slope_intensity)
-                            .min(255.0);
-                        color[1] = (color[1] * (1.0 - slope_intensity) + 90.0 * slope_intensity)
-                            .min(255.0);
-                        color[2] = (color[2] * (1.0 - slope_intensity) + 43.0 * slope_intensity)
-                            .min(255.0);
-                    }
-                }
-
-                // Darken water immediately next to land for a coastline edge
-                if current_terrain.biome.is_water() {
-                    let mut near_land = false;
-                    for dy in -1i32..=1 {
-                        for dx in -1i32..=1 {
-                            if dx == 0 && dy == 0 {
-                                continue;
-                            }
-                            let nx = terrain_x as i32 + dx;
-                            let ny = terrain_y as i32 + dy;
-                            if nx >= 0
-                                && ny >= 0
-                                && (nx as usize) < width
-                                && (ny as usize) < height
-                                && !map.terrain[ny as usize][nx as usize].biome.is_water()
-                            {
-                                near_land = true;
-                            }
-                        }
-                    }
-                    if near_land {
-                        color[0] *= 0.85;
-                        color[1] *= 0.9;
-                        color[2] *= 0.95;
+        // Pixel art: one palette color per tile, lighter where the ground
+        // rises towards the light in the northwest and darker where it falls
+        let pixel_art = options.style == RenderStyle::PixelArt;
+        let tile_colors: Vec<[u8; 3]> = if pixel_art && layers.terrain {
+            (0..width * height)
+                .map(|i| {
+                    let (x, y) = (i % width, i / width);
+                    let point = &map.terrain[y][x];
+                    let mut color = get_terrain_color(x as f32, y as f32);
+                    if layers.relief && !point.biome.is_water() {
+                        let west = if wrap { (x + width - 1) % width } else { x.saturating_sub(1) };
+                        let rise = point.elevation - map.terrain[y.saturating_sub(1)][west].elevation;
+                        let shade = 1.0 + (rise as f32 * 6.0 * options.relief_strength).clamp(-0.25, 0.25);
+                        color = color.map(|c| (c * shade).min(255.0));
                     }
-                }
+                    palette_color(color, options.dither.then_some((x, y)))
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
 
-                let pixel_index = (py * img_width + px) * 4;
-                pixels[pixel_index] = color[0] as u8;
-                pixels[pixel_index + 1] = color[1] as u8;
-                pixels[pixel_index + 2] = color[2] as u8;
-                pixels[pixel_index + 3] = 255;
-            }
-        }
+        // Line art: which side of the coast a point is on, and how far out
+        let line_art = options.style == RenderStyle::LineArt;
+        let (line_wet, line_distance, line_coast) = if line_art && layers.terrain {
+            let distance = coast_distance(map, width, height, HATCH_REACH + 1.0, wrap);
+            let Some(coast) = coastline_pixels(map, scale, view, (scale as f32 * 0.1).max(0.6)) else {
+                return Vec::new();
+            };
+            (distance.iter().map(|d| d.min(1.0)).collect(), distance, coast)
+        } else {
+            (Vec::new(), Vec::new(), Vec::new())
+        };
 
-        // Draw rivers as tapered lines: narrow at the source, wider at the
-        // mouth (rivers are traced source-to-mouth by the generator)
-        let river_color = [30.0f32, 100.0, 220.0];
-        let scale_f = scale as f32;
-        for river in &map.rivers {
-            if river.len() < 2 {
-                continue;
-            }
-            for i in 0..river.len() - 1 {
-                let t = i as f32 / river.len() as f32;
-                let radius = (scale_f * (0.15 + 0.4 * t)).max(0.7);
-
-                let (x0, y0) = river[i];
-                let (x1, y1) = river[i + 1];
-                let px0 = x0 as f32 * scale_f + scale_f / 2.0;
-                let py0 = y0 as f32 * scale_f + scale_f / 2.0;
-                let px1 = x1 as f32 * scale_f + scale_f / 2.0;
-                let py1 = y1 as f32 * scale_f + scale_f / 2.0;
-
-                let seg_len = ((px1 - px0).powi(2) + (py1 - py0).powi(2)).sqrt();
-                let steps = (seg_len.ceil() as usize).max(1);
-                for s in 0..=steps {
-                    let st = s as f32 / steps as f32;
-                    let cx = px0 + (px1 - px0) * st;
-                    let cy = py0 + (py1 - py0) * st;
-
-                    let r = radius.ceil() as i32;
-                    for dy in -r..=r {
-                        for dx in -r..=r {
-                            if (dx * dx + dy * dy) as f32 > radius * radius {
-                                continue;
-                            }
-                            let ix = cx as i32 + dx;
-                            let iy = cy as i32 + dy;
-                            if ix < 0 || iy < 0 || ix >= img_width as i32 || iy >= img_height as i32
-                            {
-                                continue;
-                            }
-                            let idx = ((iy as usize) * img_width + ix as usize) * 4;
-                            pixels[idx] = river_color[0] as u8;
-                            pixels[idx + 1] = river_color[1] as u8;
-                            pixels[idx + 2] = river_color[2] as u8;
-                        }
+        // Render each pixel with smooth interpolation
+        if layers.terrain {
+            for py in 0..img_height {
+                for px in 0..img_width {
+                    // Calculate position in terrain space with sub-pixel precision
+                    let tx = (view.x + px) as f32 / scale as f32;
+                    let ty = (view.y + py) as f32 / scale as f32;
+
+                    if line_art {
+                        let (gx, gy) = (view.x + px, view.y + py);
+                        let step = 1.0 / scale as f32;
+                        let wet = |x: f32, y: f32| sample_field(&line_wet, x, y) >= 0.5;
+                        // A solid coastline, then horizontal hatching that
+                        // thins out away from the shore
+                        let ink = line_coast[py * img_width + px] || if wet(tx, ty) {
+                            let out = sample_field(&line_distance, tx, ty) - 0.5;
+                            let spacing = if out < HATCH_REACH * 0.4 { 3 } else { 5 };
+                            out < HATCH_REACH && gy % spacing == 0
+                        } else {
+                            // Contours where the elevation crosses a level
+                            // between this pixel and the next one along
+                            let level = |x: f32, y: f32| (sample_elevation(x, y) / CONTOUR_INTERVAL).floor();
+                            let here = level(tx, ty);
+                            let contour = layers.relief && (here != level(tx + step, ty) || here != level(tx, ty + step));
+                            let tile = &map.terrain[(ty as usize).min(height - 1)][(tx as usize).min(width - 1)];
+                            let stipple = match tile.biome {
+                                Biome::Desert => 0.06,
+                                Biome::Beach => 0.03,
+                                Biome::Swamp if gy % 4 == 0 => 0.35,
+                                _ => 0.0,
+                            };
+                            contour || pixel_hash(gx, gy) < stipple
+                        };
+                        let tone = if ink { 0 } else { 255 };
+                        let pixel_index = (py * img_width + px) * 4;
+                        pixels[pixel_index..pixel_index + 4].copy_from_slice(&[tone, tone, tone, 255]);
+                        continue;
                     }
-                }
-            }
-        }
 
-        // Draw roads with better visibility
-        for road in &map.roads {
-            // Darker, more visible colors
-            let (road_color, road_width) = match road.road_type.as_str() {
-                "highway" => ([40, 40, 45, 230], 2usize), // Dark gray, 2 pixels wide
-                "road" => ([60, 55, 50, 220], 1),         // Dark brown-gray, 1 pixel
-                _ => ([80, 70, 60, 200], 1),              // Brown trail, 1 pixel
-            };
-            let road_blend = road_color[3] as f32 / 255.0;
+                    if pixel_art {
+                        let tile = (ty as usize).min(height - 1) * width + (tx as usize).min(width - 1);
+                        let pixel_index = (py * img_width + px) * 4;
+                        pixels[pixel_index..pixel_index + 3].copy_from_slice(&tile_colors[tile]);
+                        pixels[pixel_index + 3] = 255;
+                        continue;
+                    }
 
-            let mut draw_road_pixel = |px: usize, py: usize| {
-                if px >= img_width || py >= img_height {
-                    return;
-                }
-                let idx = (py * img_width + px) * 4;
-                pixels[idx] = (pixels[idx] as f32 * (1.0 - road_blend)
-                    + road_color[0] as f32 * road_blend) as u8;
-                pixels[idx + 1] = (pixels[idx + 1] as f32 * (1.0 - road_blend)
-                    + road_color[1] as f32 * road_blend) as u8;
-                pixels[idx + 2] = (pixels[idx + 2] as f32 * (1.0 - road_blend)
-                    + road_color[2] as f32 * road_blend) as u8;
-            };
+                    let mut color = get_terrain_color(tx, ty);
 
-            // Draw road path, connecting consecutive points with lines
-            for i in 0..road.path.len() {
-                let (x, y) = road.path[i];
-                if x >= width || y >= height {
-                    continue;
-                }
-                let base_px = x * scale + scale / 2;
-                let base_py = y * scale + scale / 2;
+                    let terrain_x = (tx.floor() as usize).min(width - 1);
+                    let terrain_y = (ty.floor() as usize).min(height - 1);
+                    let current_terrain = &map.terrain[terrain_y][terrain_x];
 
-                let mut draw_stamp = |px: usize, py: usize| {
-                    for offset in 0..road_width {
-                        draw_road_pixel(px + offset, py);
-                        if road_width == 2 {
-                            draw_road_pixel(px + offset, py + 1);
+                    let elev_center = sample_elevation(tx, ty);
+
+                    // Cracks across the frozen lakes
+                    let frozen_lake = current_terrain.biome == Biome::Lake
+                        && options.snow.is_some_and(|season| is_frozen(current_terrain, season));
+                    if frozen_lake && ice_crack(&ice, tx, ty, period(0.6)) {
+                        color = ICE_CRACK.map(|c| c as f32);
+                    }
+
+                    if satellite {
+                        // Fields and scrub: a fine mottling of the land
+                        if !current_terrain.biome.is_water() {
+                            let grain = fractal(&texture, tx * 1.7, ty * 1.7, 3, period(1.7));
+                            for channel in &mut color {
+                                *channel = (*channel * (1.0 + grain * 0.14)).clamp(0.0, 255.0);
+                            }
                         }
                     }
-                };
 
-                draw_stamp(base_px, base_py);
+                    // Hillshade relief on land, from the smoothly interpolated
+                    // elevation gradient (no screen-space texture patterns)
+                    if elev_center > 0.0 && layers.relief {
+                        let elevation_factor = elev_center.clamp(0.0, 1.0);
 
-                // Connect to next point with interpolation for smooth curves
-                if i < road.path.len() - 1 {
-                    let (next_x, next_y) = road.path[i + 1];
-                    if next_x >= width || next_y >= height {
-                        continue;
-                    }
-                    let next_px = next_x * scale + scale / 2;
-                    let next_py = next_y * scale + scale / 2;
+                        // Stronger relief at higher elevations, subtle on plains
+                        let gradient_scale = if elev_center > 0.82 {
+                            25.0 + elevation_factor * 5.0 // Mountains
+                        } else if elev_center > 0.6 {
+                            15.0 + elevation_factor * 10.0 // Hills
+                        } else if elev_center > 0.18 {
+                            8.0 + elevation_factor * 7.0 // Uplands
+                        } else {
+                            3.0 + elevation_factor * 5.0 // Plains
+                        } * options.relief_strength.max(0.0) as f64;
 
-                    let dx = (next_px as i32 - base_px as i32).abs();
-                    let dy = (next_py as i32 - base_py as i32).abs();
-                    let steps = dx.max(dy) as usize;
+                        let sample_dist = 0.35 / options.relief_detail.max(0.05);
+                        let dx = (sample_elevation(tx + sample_dist, ty)
+                            - sample_elevation(tx - sample_dist, ty))
+                            * gradient_scale
+                            / (sample_dist as f64 * 2.0);
+                        let dy = (sample_elevation(tx, ty + sample_dist)
+                            - sample_elevation(tx, ty - sample_dist))
+                            * gradient_scale
+                            / (sample_dist as f64 * 2.0);
 
-                    for step in 1..steps {
-                        let t = step as f32 / steps as f32;
-                        let interp_x =
-                            (base_px as f32 * (1.0 - t) + next_px as f32 * t) as usize;
-                        let interp_y =
-                            (base_py as f32 * (1.0 - t) + next_py as f32 * t) as usize;
-                        draw_stamp(interp_x, interp_y);
-                    }
+                        // Light from the northwest
+                        let light = (-0.7071, -0.7071, 0.5);
+
+                        // Surface normal from the gradient
+                        let normal_len = (dx * dx + dy * dy + 1.0).sqrt();
+                        let lighting =
+                            ((-dx) * light.0 + (-dy) * light.1 + light.2).max(0.0) / normal_len;
+
+                        // Moderate contrast: brighter on lit slopes, darker in shade
+                        let contrast = 0.3 + elevation_factor as f32 * 0.4;
+                        let shade_factor = if lighting > 0.6 {
+                            1.0 + (lighting - 0.6) as f32 * contrast
+                        } else {
+                            0.7 + lighting as f32 * 0.5
+                        };
+
+                        color[0] = (color[0] * shade_factor).min(255.0);
+                        color[1] = (color[1] * shade_factor).min(255.0);
+                        color[2] = (color[2] * shade_factor).min(255.0);
+
+                        // Rock tint on steep slopes
+                        if dx.abs() > 0.1 || dy.abs() > 0.1 {
+                            let slope_intensity = (dx.abs() + dy.abs()).min(1.0) as f32
+                                * options.slope_tint_strength.clamp(0.0, 1.0);
+                            for (channel, tint) in color.iter_mut().zip(options.slope_tint) {
+                                *channel = (*channel * (1.0 - slope_intensity)
+                                    + tint as f32 * slope_intensity)
+                                    .min(255.0);
+                            }
+                        }
+                    }
+
+                    if let Some((distance, wet)) = &coast_rings {
+                        // The coastline lies halfway between land and water
+                        // tiles, where the distance field is about 0.5
+                        if sample_field(wet, tx, ty) >= 0.5 {
+                            let from_coast = (sample_field(distance, tx, ty) - 0.5).max(0.0);
+                            let ring = (from_coast / ring_width) as u32;
+                            if ring < options.coast_rings {
+                                let t = (options.coast_rings - ring) as f32 / options.coast_rings as f32
+                                    * COAST_RING_STRENGTH;
+                                for (channel, pale) in color.iter_mut().zip(COAST_RING_COLOR) {
+                                    *channel = *channel * (1.0 - t) + pale * t;
+                                }
+                            }
+                        }
+                    } else if current_terrain.biome.is_water() && !satellite {
+                        // Darken water immediately next to land for a coastline edge
+                        let mut near_land = false;
+                        for dy in -1i32..=1 {
+                            for dx in -1i32..=1 {
+                                if dx == 0 && dy == 0 {
+                                    continue;
+                                }
+                                let nx = terrain_x as i32 + dx;
+                                let ny = terrain_y as i32 + dy;
+                                let nx = if wrap { nx.rem_euclid(width as i32) } else { nx };
+                                if nx >= 0
+                                    && ny >= 0
+                                    && (nx as usize) < width
+                                    && (ny as usize) < height
+                                    && !map.terrain[ny as usize][nx as usize].biome.is_water()
+                                {
+                                    near_land = true;
+                                }
+                            }
+                        }
+                        if near_land {
+                            color[0] *= 0.85;
+                            color[1] *= 0.9;
+                            color[2] *= 0.95;
+                        }
+                    }
+
+                    if satellite {
+                        // The sun's reflection brightens one stretch of sea
+                        if current_terrain.biome.is_water() {
+                            let from_glint = if wrap { map.x_delta(glint.0, tx) } else { tx - glint.0 };
+                            let (gx, gy) = (from_glint / glint.2, (ty - glint.1) / glint.2);
+                            let shine = (-(gx * gx + gy * gy)).exp()
+                                * (0.85 + 0.15 * fractal(&texture, tx * 0.6, ty * 0.6, 2, period(0.6)))
+                                * 45.0;
+                            for (channel, tone) in color.iter_mut().zip([1.0, 1.0, 0.85]) {
+                                *channel = (*channel + shine * tone).min(255.0);
+                            }
+                        }
+                        if let Some(clouds) = &clouds {
+                            let density = cloud_density(clouds, tx, ty, period(1.0 / 14.0));
+                            for channel in &mut color {
+                                *channel += (248.0 - *channel) * density;
+                            }
+                        }
+                    }
+
+                    if night {
+                        // Moonlight: the day's colors dimmed and cooled,
+                        // the sea darkest
+                        color = moonlit(color, current_terrain.biome.is_water());
+                    }
+
+                    let pixel_index = (py * img_width + px) * 4;
+                    pixels[pixel_index] = color[0] as u8;
+                    pixels[pixel_index + 1] = color[1] as u8;
+                    pixels[pixel_index + 2] = color[2] as u8;
+                    pixels[pixel_index + 3] = 255;
                 }
             }
         }
 
-        // Draw cities as round dots with circles for large cities
-        for city in &map.cities {
-            let cx = (city.x * scale + scale / 2) as i32;
-            let cy = (city.y * scale + scale / 2) as i32;
-
-            // Determine if it's a large city that needs a circle
-            let is_large_city = city.population > 100000;
-
-            // City dot sizes - scaled based on tile size for visibility
-            let size_factor = (scale as f32 / 10.0).max(0.5); // Scale relative to 10px baseline
-            let dot_radius = if city.population > 250000 {
-                (12.0 * size_factor) as i32 // Major cities
-            } else if city.population > 100000 {
-                (9.0 * size_factor) as i32 // Large cities
+        // The coasts inked over the terrain
+        if let (true, Some(color), false) = (layers.terrain, options.coastline, line_art) {
+            let Some(coast) = coastline_pixels(map, scale, view, (scale as f32 * 0.1).max(0.6)) else {
+                return Vec::new();
+            };
+            for (index, _) in coast.iter().enumerate().filter(|(_, &on)| on) {
+                pixels[index * 4..index * 4 + 4].copy_from_slice(&[color[0], color[1], color[2], 255]);
+            }
+        }
+
+        // Tint each city's territory, with a darker line where it meets
+        // another (on the pixels at the edge of a tile whose right or lower
+        // neighbour belongs elsewhere)
+        if layers.territories && !map.cities.is_empty() {
+            let territories = map.territories();
+            for py in 0..img_height {
+                for px in 0..img_width {
+                    let tile_of = |x: usize, y: usize| ((x / scale).min(width - 1), (y / scale).min(height - 1));
+                    let (tx, ty) = tile_of(view.x + px, view.y + py);
+                    let Some(owner) = territories.owner_at(tx, ty) else {
+                        continue;
+                    };
+                    let (rx, _) = tile_of(view.x + px + 1, view.y + py);
+                    let (_, by) = tile_of(view.x + px, view.y + py + 1);
+                    let border = [(rx, ty), (tx, by)]
+                        .iter()
+                        .any(|&(x, y)| territories.owner_at(x, y).is_some_and(|other| other != owner));
+                    let [r, g, b] = territory_color(owner);
+                    let tint = if border {
+                        [r / 2, g / 2, b / 2, 170]
+                    } else {
+                        [r, g, b, 56]
+                    };
+                    let idx = (py * img_width + px) * 4;
+                    blend_pixel(&mut pixels[idx..idx + 4], &tint);
+                }
+            }
+        }
+
+        // Shade the tiles out of sight from the viewpoint, and ring it
+        if let (true, Some(viewpoint)) = (layers.terrain, options.viewshed) {
+            let visible = map.viewshed(viewpoint.x, viewpoint.y, viewpoint.height);
+            let center = ((viewpoint.x as f32 + 0.5) * scale as f32, (viewpoint.y as f32 + 0.5) * scale as f32);
+            let ring = (scale as f32 * 1.5).max(4.0);
+            for py in 0..img_height {
+                for px in 0..img_width {
+                    let (x, y) = (view.x + px, view.y + py);
+                    let idx = (py * img_width + px) * 4;
+                    let from_center = (x as f32 + 0.5 - center.0).hypot(y as f32 + 0.5 - center.1);
+                    if (from_center - ring).abs() < 1.0 {
+                        blend_pixel(&mut pixels[idx..idx + 4], &[200, 30, 30, 255]);
+                    } else if !visible.contains(&((x / scale).min(width - 1), (y / scale).min(height - 1))) {
+                        blend_pixel(&mut pixels[idx..idx + 4], &[20, 20, 40, 120]);
+                    }
+                }
+            }
+        }
+
+        // Dot the boundaries of the parks, a dot every `gap` pixels along
+        if layers.labels && !map.parks.is_empty() {
+            let ink = if line_art {
+                [0, 0, 0]
+            } else if pixel_art {
+                PIXEL_PALETTE[0]
+            } else {
+                PARK_INK
+            };
+            let s = scale as f32;
+            let (radius, gap) = ((s * 0.15).max(1.3), (s * 0.9).max(6.0));
+            for run in map.park_boundaries() {
+                let mut next = 0.0;
+                for step in run.windows(2) {
+                    let (a, b) = ((step[0].0 * s, step[0].1 * s), (step[1].0 * s, step[1].1 * s));
+                    let length = (b.0 - a.0).hypot(b.1 - a.1);
+                    while next <= length {
+                        let t = if length > 0.0 { next / length } else { 0.0 };
+                        let (cx, cy) = (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t);
+                        let r = radius.ceil() as i64;
+                        for dy in -r..=r {
+                            for dx in -r..=r {
+                                let (px, py) = (cx.round() as i64 + dx, cy.round() as i64 + dy);
+                                let (ix, iy) = (px - view.x as i64, py - view.y as i64);
+                                let outside = ix < 0 || iy < 0 || ix >= img_width as i64 || iy >= img_height as i64;
+                                if outside || (px as f32 - cx).hypot(py as f32 - cy) > radius {
+                                    continue;
+                                }
+                                let idx = (iy as usize * img_width + ix as usize) * 4;
+                                blend_pixel(&mut pixels[idx..idx + 4], &[ink[0], ink[1], ink[2], 255]);
+                            }
+                        }
+                        next += gap;
+                    }
+                    next -= length;
+                }
+            }
+        }
+
+        // Draw rivers as tapered lines: narrow at the source, wider at the
+        // mouth (rivers are traced source-to-mouth by the generator)
+        let river_color = if line_art {
+            [0.0f32; 3]
+        } else if night {
+            [14.0, 26.0, 60.0]
+        } else {
+            [30.0f32, 100.0, 220.0]
+        };
+        // Where the snow lies the rivers are frozen, ice with cracks
+        let frozen_at = |x: f32, y: f32| {
+            let x = (x.round() as isize).rem_euclid(width as isize) as usize;
+            let y = (y.round().max(0.0) as usize).min(height - 1);
+            !line_art && options.snow.is_some_and(|season| is_frozen(&map.terrain[y][x], season))
+        };
+        let ice_colors = if night {
+            let moonlit = |color: [u8; 3]| moonlit(color.map(|c| c as f32), true).map(|c| c as u8);
+            [moonlit(ICE_COLOR), moonlit(ICE_CRACK)]
+        } else {
+            [ICE_COLOR, ICE_CRACK]
+        };
+        let scale_f = scale as f32;
+        if layers.rivers && pixel_art {
+            // Whole tiles of river water
+            for &(x, y) in map.rivers.iter().filter(|r| r.len() >= 2).flatten() {
+                let water = if frozen_at(x as f32, y as f32) { palette_color(ICE_COLOR.map(|c| c as f32), None) } else { PIXEL_PALETTE[3] };
+                for py in (y * scale).max(view.y)..((y + 1) * scale).min(view.y + img_height) {
+                    for px in (x * scale).max(view.x)..((x + 1) * scale).min(view.x + img_width) {
+                        let idx = ((py - view.y) * img_width + px - view.x) * 4;
+                        pixels[idx..idx + 3].copy_from_slice(&water);
+                        pixels[idx + 3] = 255;
+                    }
+                }
+            }
+        } else if layers.rivers {
+            for (index, river) in map.rivers.iter().enumerate() {
+                if river.len() < 2 {
+                    continue;
+                }
+                let path = map.river_curve(index);
+                for (&shift, i) in shifts.iter().flat_map(|shift| (0..path.len() - 1).map(move |i| (shift, i))) {
+                    let t = i as f32 / path.len() as f32;
+                    let radius = (scale_f * (0.15 + 0.4 * t)).max(0.7);
+
+                    let (x0, y0) = path[i];
+                    let (x1, y1) = path[i + 1];
+                    let frozen = frozen_at(x0, y0);
+                    let px0 = (x0 + shift) * scale_f + scale_f / 2.0;
+                    let py0 = y0 * scale_f + scale_f / 2.0;
+                    let px1 = (x1 + shift) * scale_f + scale_f / 2.0;
+                    let py1 = y1 * scale_f + scale_f / 2.0;
+
+                    // Skip segments that can't touch the view
+                    let reach = radius + 1.0;
+                    if px0.max(px1) + reach < view.x as f32
+                        || py0.max(py1) + reach < view.y as f32
+                        || px0.min(px1) - reach > (view.x + img_width) as f32
+                        || py0.min(py1) - reach > (view.y + img_height) as f32
+                    {
+                        continue;
+                    }
+
+                    let seg_len = ((px1 - px0).powi(2) + (py1 - py0).powi(2)).sqrt();
+                    let steps = (seg_len.ceil() as usize).max(1);
+                    for s in 0..=steps {
+                        let st = s as f32 / steps as f32;
+                        let cx = px0 + (px1 - px0) * st;
+                        let cy = py0 + (py1 - py0) * st;
+
+                        let r = radius.ceil() as i32;
+                        for dy in -r..=r {
+                            for dx in -r..=r {
+                                if (dx * dx + dy * dy) as f32 > radius * radius {
+                                    continue;
+                                }
+                                let ix = cx as i32 + dx - view.x as i32;
+                                let iy = cy as i32 + dy - view.y as i32;
+                                if ix < 0
+                                    || iy < 0
+                                    || ix >= img_width as i32
+                                    || iy >= img_height as i32
+                                {
+                                    continue;
+                                }
+                                let idx = ((iy as usize) * img_width + ix as usize) * 4;
+                                let color = if !frozen {
+                                    river_color.map(|c| c as u8)
+                                } else if pixel_hash(ix as usize + view.x, iy as usize + view.y) < 0.12 {
+                                    ice_colors[1]
+                                } else {
+                                    ice_colors[0]
+                                };
+                                pixels[idx..idx + 3].copy_from_slice(&color);
+                                pixels[idx + 3] = 255;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        // Which way the rivers run: chevrons along them, and their ends
+        if layers.rivers && options.flow_arrows {
+            let ink = if line_art {
+                [0, 0, 0]
+            } else if pixel_art {
+                PIXEL_PALETTE[0]
             } else {
-                (6.0 * size_factor) as i32 // Towns
+                [10, 45, 130]
+            };
+            let mut put = |x: f32, y: f32| {
+                let (x, y) = (x.floor() as i64 - view.x as i64, y.floor() as i64 - view.y as i64);
+                if x >= 0 && y >= 0 && (x as usize) < img_width && (y as usize) < img_height {
+                    let idx = (y as usize * img_width + x as usize) * 4;
+                    pixels[idx..idx + 3].copy_from_slice(&ink);
+                    pixels[idx + 3] = 255;
+                }
+            };
+            let line = |from: [f32; 2], to: [f32; 2], put: &mut dyn FnMut(f32, f32)| {
+                let steps = (to[0] - from[0]).abs().max((to[1] - from[1]).abs()).ceil().max(1.0) as usize;
+                for step in 0..=steps {
+                    let t = step as f32 / steps as f32;
+                    put(from[0] + (to[0] - from[0]) * t, from[1] + (to[1] - from[1]) * t);
+                }
             };
+            let size = (scale_f * 1.2).max(3.0);
+            let flows = map.river_flows();
+            for (&shift, flow) in shifts.iter().flat_map(|shift| flows.iter().map(move |flow| (shift, flow))) {
+                let river = &map.rivers[flow.river];
+                for (at, [dx, dy]) in flow_arrows(map, river) {
+                    let tip = [(at[0] + shift + dx * 0.5) * scale_f, (at[1] + dy * 0.5) * scale_f];
+                    // Wings swept back 35 degrees either side
+                    let (cos, sin) = (35f32.to_radians().cos(), 35f32.to_radians().sin());
+                    for side in [-1.0, 1.0] {
+                        let (wx, wy) = (dx * cos - side * dy * sin, side * dx * sin + dy * cos);
+                        line(tip, [tip[0] - wx * size, tip[1] - wy * size], &mut put);
+                    }
+                }
+                let mark = (scale_f * 0.6).max(2.0);
+                let centre = |(x, y): (usize, usize)| [(x as f32 + shift + 0.5) * scale_f, (y as f32 + 0.5) * scale_f];
+                let source = centre(flow.source);
+                let steps = (mark * 8.0) as usize;
+                for step in 0..steps {
+                    let angle = step as f32 / steps as f32 * std::f32::consts::TAU;
+                    put(source[0] + angle.cos() * mark, source[1] + angle.sin() * mark);
+                }
+                if !matches!(flow.outlet, RiverOutlet::River(_)) {
+                    let mouth = centre(flow.mouth);
+                    let half = mark.ceil() as i32 + 1;
+                    for dy in -half..=half {
+                        for dx in -(half - dy.abs())..=(half - dy.abs()) {
+                            put(mouth[0] + dx as f32, mouth[1] + dy as f32);
+                        }
+                    }
+                }
+            }
+        }
+
+        // Draw roads with better visibility
+        if layers.roads {
+            for (index, road) in map.roads.iter().enumerate() {
+                // Darker, more visible colors
+                let (mut road_color, road_width) = match road.road_type.as_str() {
+                    "motorway" => ([200, 80, 40, 240], 3usize), // Orange-red, 3 pixels wide
+                    "highway" => ([40, 40, 45, 230], 2),      // Dark gray, 2 pixels wide
+                    "road" => ([60, 55, 50, 220], 1),         // Dark brown-gray, 1 pixel
+                    _ => ([80, 70, 60, 200], 1),              // Brown trail, 1 pixel
+                };
+                if pixel_art {
+                    // Solid, so no colors outside the palette
+                    let [r, g, b] = PIXEL_PALETTE[14];
+                    road_color = [r, g, b, 255];
+                } else if line_art {
+                    road_color = [0, 0, 0, 255];
+                } else if night {
+                    road_color = [36, 34, 40, road_color[3]];
+                }
+                let road_blend = road_color[3] as f32 / 255.0;
 
+                let mut draw_road_pixel = |px: i64, py: i64| {
+                    let (px, py) = (px - view.x as i64, py - view.y as i64);
+                    if px < 0 || py < 0 || px >= img_width as i64 || py >= img_height as i64 {
+                        return;
+                    }
+                    let idx = (py as usize * img_width + px as usize) * 4;
+                    if pixels[idx + 3] == 255 {
+                        pixels[idx] = (pixels[idx] as f32 * (1.0 - road_blend)
+                            + road_color[0] as f32 * road_blend)
+                            as u8;
+                        pixels[idx + 1] = (pixels[idx + 1] as f32 * (1.0 - road_blend)
+                            + road_color[1] as f32 * road_blend)
+                            as u8;
+                        pixels[idx + 2] = (pixels[idx + 2] as f32 * (1.0 - road_blend)
+                            + road_color[2] as f32 * road_blend)
+                            as u8;
+                    } else {
+                        // Translucent background (terrain layer off):
+                        // composite with the "over" operator
+                        let dst_alpha = pixels[idx + 3] as f32 / 255.0;
+                        let out_alpha = road_blend + dst_alpha * (1.0 - road_blend);
+                        for c in 0..3 {
+                            pixels[idx + c] =
+                                ((pixels[idx + c] as f32 * dst_alpha * (1.0 - road_blend)
+                                    + road_color[c] as f32 * road_blend)
+                                    / out_alpha) as u8;
+                        }
+                        pixels[idx + 3] = (out_alpha * 255.0).round() as u8;
+                    }
+                };
+                let mut draw_stamp = |px: i64, py: i64| {
+                    for dy in 0..road_width as i64 {
+                        for dx in 0..road_width as i64 {
+                            draw_road_pixel(px + dx, py + dy);
+                        }
+                    }
+                };
+                let centre = |tile: f32| (tile * scale_f) as i64 + scale as i64 / 2;
+
+                // Draw road path, connecting consecutive points with lines:
+                // along the smoothed curve, or the tiles themselves for pixel
+                // art and roads that leave the grid
+                if road.path.iter().any(|&(x, y)| x >= width || y >= height) {
+                    continue;
+                }
+                let path = if pixel_art { map.unwrapped_path(&road.path).into() } else { map.road_curve(index) };
+                for (&shift, i) in shifts.iter().flat_map(|shift| (0..path.len()).map(move |i| (shift, i))) {
+                    let base_px = centre(path[i].0 + shift);
+                    let base_py = centre(path[i].1);
+
+                    draw_stamp(base_px, base_py);
+
+                    // Connect to next point with interpolation for smooth curves
+                    if i < path.len() - 1 {
+                        let next_px = centre(path[i + 1].0 + shift);
+                        let next_py = centre(path[i + 1].1);
+
+                        let dx = (next_px - base_px).abs();
+                        let dy = (next_py - base_py).abs();
+                        let steps = dx.max(dy);
+
+                        for step in 1..steps {
+                            let t = step as f32 / steps as f32;
+                            let interp_x =
+                                (base_px as f32 * (1.0 - t) + next_px as f32 * t) as i64;
+                            let interp_y =
+                                (base_py as f32 * (1.0 - t) + next_py as f32 * t) as i64;
+                            draw_stamp(interp_x, interp_y);
+                        }
+                    }
+                }
+            }
+
+            // Power lines: a thin straight wire with a pylon every tile or
+            // so, over the roads they cross
+            let ink = if line_art {
+                [0, 0, 0]
+            } else if pixel_art {
+                PIXEL_PALETTE[12]
+            } else {
+                POWER_LINE_INK
+            };
+            let s = scale as f32;
+            let pylon_gap = (s * 1.2).max(8.0);
+            let mut put = |px: f32, py: f32, alpha: u8| {
+                let (ix, iy) = (px.round() as i64 - view.x as i64, py.round() as i64 - view.y as i64);
+                if ix >= 0 && iy >= 0 && ix < img_width as i64 && iy < img_height as i64 {
+                    let idx = (iy as usize * img_width + ix as usize) * 4;
+                    blend_pixel(&mut pixels[idx..idx + 4], &[ink[0], ink[1], ink[2], alpha]);
+                }
+            };
+            for (&shift, line) in shifts.iter().flat_map(|shift| map.power_lines.iter().map(move |line| (shift, line))) {
+                let ends = map.unwrapped_path(&[line.from, line.to]);
+                let (a, b) = (
+                    ((ends[0].0 + shift + 0.5) * s, (ends[0].1 + 0.5) * s),
+                    ((ends[1].0 + shift + 0.5) * s, (ends[1].1 + 0.5) * s),
+                );
+                let length = (b.0 - a.0).hypot(b.1 - a.1);
+                for step in 0..=length.ceil() as usize {
+                    let t = if length > 0.0 { step as f32 / length } else { 0.0 };
+                    put(a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t, 200);
+                }
+                let pylons = (length / pylon_gap).floor() as usize;
+                for pylon in 1..pylons {
+                    let t = pylon as f32 / pylons as f32;
+                    let (cx, cy) = (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t);
+                    for (dx, dy) in [(0.0, 0.0), (1.0, 0.0), (-1.0, 0.0), (0.0, 1.0), (0.0, -1.0)] {
+                        put(cx + dx, cy + dy, 255);
+                    }
+                }
+            }
+        }
+
+        // City lights, added to what is drawn so they glow; over nothing
+        // they are drawn translucent, for an overlay
+        if layers.lights || (night && layers.terrain) {
+            let Some(glow) = city_lights(map, scale, view, &shifts) else {
+                return Vec::new();
+            };
+            for (index, &light) in glow.iter().enumerate().filter(|(_, &light)| light > 0.01) {
+                let core = ((light - 0.7) / 0.8).clamp(0.0, 1.0);
+                let color = [0, 1, 2].map(|c| LIGHT_COLOR[c] as f32 + (LIGHT_CORE[c] as f32 - LIGHT_COLOR[c] as f32) * core);
+                let alpha = light.min(1.0);
+                let pixel = &mut pixels[index * 4..index * 4 + 4];
+                if pixel[3] == 255 {
+                    for c in 0..3 {
+                        pixel[c] = (pixel[c] as f32 + color[c] * alpha).min(255.0) as u8;
+                    }
+                } else {
+                    blend_pixel(pixel, &[color[0] as u8, color[1] as u8, color[2] as u8, (alpha * 255.0) as u8]);
+                }
+            }
+        }
+
+        // Draw cities as round dots with circles for large cities
+        if layers.cities {
             let mut put_pixel = |px: i32, py: i32, color: [u8; 3]| {
+                let (px, py) = (px - view.x as i32, py - view.y as i32);
                 if px < 0 || py < 0 || px >= img_width as i32 || py >= img_height as i32 {
                     return;
                 }
@@ -398,36 +1790,99 @@ impl TerrainRenderer {
                 pixels[idx + 3] = 255;
             };
 
-            // Draw circle around large cities first
-            if is_large_city {
-                let circle_radius = dot_radius + 3; // Circle 3 pixels larger than dot
+            for (&shift, city) in shifts.iter().flat_map(|shift| map.cities.iter().map(move |city| (shift, city))) {
+                if city.x >= width || city.y >= height {
+                    continue;
+                }
+                let cx = (city.x * scale + scale / 2) as i32 + (shift * scale_f) as i32;
+                let cy = (city.y * scale + scale / 2) as i32;
+
+                // Determine if it's a large city that needs a circle
+                let is_large_city = city.population > 100000;
+
+                // City dot sizes - scaled based on tile size for visibility
+                let size_factor = (scale as f32 / 10.0).max(0.5); // Scale relative to 10px baseline
+                let dot_radius = if city.population > 250000 {
+                    (12.0 * size_factor) as i32 // Major cities
+                } else if city.population > 100000 {
+                    (9.0 * size_factor) as i32 // Large cities
+                } else {
+                    (6.0 * size_factor) as i32 // Towns
+                };
+
+                // Draw circle around large cities first
+                if is_large_city {
+                    let circle_radius = dot_radius + 3; // Circle 3 pixels larger than dot
 
-                for dy in -(circle_radius + 1)..=(circle_radius + 1) {
-                    for dx in -(circle_radius + 1)..=(circle_radius + 1) {
-                        let dist_sq = dx * dx + dy * dy;
-                        let outer = (circle_radius + 1) * (circle_radius + 1);
-                        let inner = (circle_radius - 1) * (circle_radius - 1);
+                    for dy in -(circle_radius + 1)..=(circle_radius + 1) {
+                        for dx in -(circle_radius + 1)..=(circle_radius + 1) {
+                            let dist_sq = dx * dx + dy * dy;
+                            let outer = (circle_radius + 1) * (circle_radius + 1);
+                            let inner = (circle_radius - 1) * (circle_radius - 1);
+
+                            // Draw if we're in the circle ring (not inside, not outside)
+                            if dist_sq <= outer && dist_sq >= inner {
+                                put_pixel(cx + dx, cy + dy, if line_art { [0, 0, 0] } else { [20, 20, 20] });
+                            }
+                        }
+                    }
+                }
 
-                        // Draw if we're in the circle ring (not inside, not outside)
-                        if dist_sq <= outer && dist_sq >= inner {
+                // Draw solid round dot for city
+                let dot_color = if line_art {
+                    [0, 0, 0]
+                } else if city.population > 250000 {
+                    [220, 20, 20] // Major cities - red dot
+                } else if city.population > 100000 {
+                    [180, 40, 40] // Large cities - dark red dot
+                } else {
+                    [20, 20, 20] // Towns - black dot
+                };
+                for dy in -dot_radius..=dot_radius {
+                    for dx in -dot_radius..=dot_radius {
+                        if dx * dx + dy * dy <= dot_radius * dot_radius {
+                            put_pixel(cx + dx, cy + dy, dot_color);
+                        }
+                    }
+                }
+            }
+
+            // Points of interest as their kind's icon, outlined
+            for (&shift, poi) in shifts.iter().flat_map(|shift| map.pois.iter().map(move |poi| (shift, poi))) {
+                let (x, y) = poi.position;
+                if x >= width || y >= height {
+                    continue;
+                }
+                let style = map.poi_style(&poi.kind);
+                let cx = (x * scale + scale / 2) as i32 + (shift * scale_f) as i32;
+                let cy = (y * scale + scale / 2) as i32;
+                let radius = style.icon_radius * (scale as f32 / 10.0).max(0.5);
+                let fill = if line_art { [255, 255, 255] } else { style.icon_color };
+                let reach = radius.ceil() as i32 + 1;
+                for dy in -reach..=reach {
+                    for dx in -reach..=reach {
+                        let (fx, fy) = (dx as f32, dy as f32);
+                        if style.icon.covers(fx / radius, fy / radius) {
+                            put_pixel(cx + dx, cy + dy, fill);
+                        } else if style.icon.covers(fx / (radius + 1.0), fy / (radius + 1.0)) {
                             put_pixel(cx + dx, cy + dy, [20, 20, 20]);
                         }
                     }
                 }
             }
+        }
 
-            // Draw solid round dot for city
-            let dot_color = if city.population > 250000 {
-                [220, 20, 20] // Major cities - red dot
-            } else if city.population > 100000 {
-                [180, 40, 40] // Large cities - dark red dot
-            } else {
-                [20, 20, 20] // Towns - black dot
-            };
-            for dy in -dot_radius..=dot_radius {
-                for dx in -dot_radius..=dot_radius {
-                    if dx * dx + dy * dy <= dot_radius * dot_radius {
-                        put_pixel(cx + dx, cy + dy, dot_color);
+        // Clouds over everything: white, translucent at their edges
+        if layers.clouds {
+            let clouds = Perlin::new(options.clouds.unwrap_or(map.seed));
+            for py in 0..img_height {
+                for px in 0..img_width {
+                    let tx = (view.x + px) as f32 / scale as f32;
+                    let ty = (view.y + py) as f32 / scale as f32;
+                    let density = cloud_density(&clouds, tx, ty, period(1.0 / 14.0));
+                    if density > 0.0 {
+                        let idx = (py * img_width + px) * 4;
+                        blend_pixel(&mut pixels[idx..idx + 4], &[248, 248, 250, (density * 255.0) as u8]);
                     }
                 }
             }
@@ -436,10 +1891,187 @@ impl TerrainRenderer {
         pixels
     }
 
-    /// Renders terrain map to an image for PNG export
+    /// `render_region` at `options.supersample` times the scale, a strip of
+    /// `SUPERSAMPLE_STRIP` rows at a time, each block of samples box-filtered
+    /// into one pixel. Colors are averaged weighted by their alpha, so
+    /// transparent samples don't darken the edges of a layer.
+    fn render_supersampled(
+        map: &TerrainMap,
+        width: usize,
+        height: usize,
+        scale: usize,
+        view: Viewport,
+        layers: Layers,
+        options: &RenderOptions,
+    ) -> Vec<u8> {
+        let samples = options.supersample.clamp(1, MAX_SUPERSAMPLE) as usize;
+        let single = RenderOptions { supersample: 1, ..*options };
+        let Some(mut pixels) = view
+            .width
+            .checked_mul(view.height)
+            .and_then(|n| n.checked_mul(4))
+            .and_then(|len| try_filled(len, 0u8))
+        else {
+            return Vec::new();
+        };
+        let fine_width = view.width * samples;
+        for top in (0..view.height).step_by(SUPERSAMPLE_STRIP) {
+            let rows = SUPERSAMPLE_STRIP.min(view.height - top);
+            let strip = Viewport {
+                x: view.x * samples,
+                y: (view.y + top) * samples,
+                width: fine_width,
+                height: rows * samples,
+            };
+            let fine = Self::render_region(map, width, height, scale * samples, strip, layers, &single);
+            if fine.is_empty() {
+                return Vec::new();
+            }
+            for row in 0..rows {
+                for column in 0..view.width {
+                    let mut sum = [0.0f32; 4];
+                    for sy in row * samples..(row + 1) * samples {
+                        for sx in column * samples..(column + 1) * samples {
+                            let sample = &fine[(sy * fine_width + sx) * 4..][..4];
+                            let alpha = sample[3] as f32;
+                            for c in 0..3 {
+                                sum[c] += sample[c] as f32 * alpha;
+                            }
+                            sum[3] += alpha;
+                        }
+                    }
+                    let out = &mut pixels[((top + row) * view.width + column) * 4..][..4];
+                    if sum[3] > 0.0 {
+                        for c in 0..3 {
+                            out[c] = (sum[c] / sum[3]).round() as u8;
+                        }
+                        out[3] = (sum[3] / (samples * samples) as f32).round() as u8;
+                    }
+                }
+            }
+        }
+        pixels
+    }
+
+    /// Alpha-composites RGBA `layer` over `base`, both the same size, as
+    /// for stacking layers that were rendered separately.
+    pub fn composite_over(base: &mut [u8], layer: &[u8]) {
+        for (dst, src) in base.chunks_exact_mut(4).zip(layer.chunks_exact(4)) {
+            blend_pixel(dst, src);
+        }
+    }
+
+    /// Draw `plate` filling the box `width` by `height` pixels with its
+    /// top-left corner at (`x`, `y`), for a name `NamePlate::padding` in
+    /// from its edges. The rounded plate is anti-aliased; the banner is
+    /// outlined in a darker brown.
+    pub fn draw_plate(img: &mut RgbaImage, plate: NamePlate, x: i32, y: i32, width: i32, height: i32) {
+        let (w, h) = (width as f32, height as f32);
+        // How far inside the plate the centre of pixel (px, py) lies, in
+        // pixels; negative outside
+        let inside = |px: f32, py: f32| -> f32 {
+            match plate {
+                NamePlate::None => -1.0,
+                NamePlate::Rounded => {
+                    let radius = h.min(w) * 0.3;
+                    let qx = (px - w / 2.0).abs() - (w / 2.0 - radius);
+                    let qy = (py - h / 2.0).abs() - (h / 2.0 - radius);
+                    radius - qx.max(0.0).hypot(qy.max(0.0)) - qx.max(qy).min(0.0)
+                }
+                NamePlate::Banner => {
+                    // A V cut into each end, as deep as the tails are long
+                    let notch = h * 0.4;
+                    let from_end = px.min(w - px);
+                    let cut = notch * (1.0 - (py - h / 2.0).abs() / (h / 2.0));
+                    (from_end - cut).min(py).min(h - py)
+                }
+            }
+        };
+        let (fill, edge) = match plate {
+            NamePlate::Banner => ([236, 222, 186, 235], [120, 88, 48, 255]),
+            _ => ([18, 22, 30, 150], [18, 22, 30, 150]),
+        };
+        for py in y.max(0)..(y + height).min(img.height() as i32) {
+            for px in x.max(0)..(x + width).min(img.width() as i32) {
+                let depth = inside((px - x) as f32 + 0.5, (py - y) as f32 + 0.5);
+                let [r, g, b, a] = match plate {
+                    NamePlate::Banner if depth >= 1.5 => fill,
+                    NamePlate::Banner => edge,
+                    _ => fill,
+                };
+                let cover = (depth + 0.5).clamp(0.0, 1.0);
+                if cover > 0.0 {
+                    let pixel = img.get_pixel_mut(px as u32, py as u32);
+                    blend_pixel(&mut pixel.0, &[r, g, b, (a as f32 * cover).round() as u8]);
+                }
+            }
+        }
+    }
+
+    /// Draw `fog` over `img`, a map rendered at `scale` with its labels:
+    /// the tiles not yet revealed as `style` says, fading in over half a
+    /// tile at the edge of what has been seen. Over transparent pixels the
+    /// fog is drawn alone, for an overlay.
+    pub fn draw_fog(img: &mut RgbaImage, fog: &FogOfWar, scale: u32, style: FogStyle) {
+        if fog.width == 0 || fog.height == 0 {
+            return;
+        }
+        let s = scale.max(1) as f32;
+        let mist = Perlin::new(31);
+        let period = fog.wrap_x.then_some(fog.width as f32 / 6.0);
+        // Revealed tiles are 1 at their centres, blended between
+        let seen = |x: isize, y: isize| {
+            let x = if fog.wrap_x { x.rem_euclid(fog.width as isize) } else { x.clamp(0, fog.width as isize - 1) };
+            let y = y.clamp(0, fog.height as isize - 1);
+            if fog.is_revealed(x as usize, y as usize) { 1.0 } else { 0.0 }
+        };
+        for (px, py, pixel) in img.enumerate_pixels_mut() {
+            let (tx, ty) = (px as f32 / s - 0.5, py as f32 / s - 0.5);
+            let (x0, y0) = (tx.floor() as isize, ty.floor() as isize);
+            let (fx, fy) = (tx - x0 as f32, ty - y0 as f32);
+            let top = seen(x0, y0) * (1.0 - fx) + seen(x0 + 1, y0) * fx;
+            let bottom = seen(x0, y0 + 1) * (1.0 - fx) + seen(x0 + 1, y0 + 1) * fx;
+            let revealed = top * (1.0 - fy) + bottom * fy;
+            // Hidden unless well inside what was seen
+            let hidden = ((0.75 - revealed) * 2.0).clamp(0.0, 1.0);
+            if hidden <= 0.0 {
+                continue;
+            }
+            let color = match style {
+                FogStyle::Hidden => {
+                    let swirl = fractal(&mist, (tx + 0.5) / 6.0, (ty + 0.5) / 6.0, 4, period);
+                    let tone = 200.0 + swirl * 30.0;
+                    [tone, tone, tone + 6.0, 255.0]
+                }
+                FogStyle::Desaturated if pixel[3] == 255 => {
+                    let [r, g, b, _] = pixel.0.map(|c| c as f32);
+                    let grey = (0.3 * r + 0.59 * g + 0.11 * b) * 0.7 + 30.0;
+                    [grey, grey, grey, 255.0]
+                }
+                FogStyle::Desaturated => [110.0, 110.0, 115.0, 150.0],
+            };
+            let alpha = (color[3] * hidden).round() as u8;
+            blend_pixel(&mut pixel.0, &[color[0] as u8, color[1] as u8, color[2] as u8, alpha]);
+        }
+    }
+
+    /// Pixel size of `map` drawn at `scale`, or `None` if that is too large
+    /// for an image
+    fn image_size(map: &TerrainMap, scale: u32) -> Option<(u32, u32)> {
+        let width = u32::try_from(map.width).ok()?.checked_mul(scale)?;
+        let height = u32::try_from(map.height).ok()?.checked_mul(scale)?;
+        (width as usize)
+            .checked_mul(height as usize)?
+            .checked_mul(4)?;
+        Some((width, height))
+    }
+
+    /// Renders terrain map to an image for PNG export. A map too large for
+    /// an image at `scale` gives an empty one.
     pub fn render_to_image(map: &TerrainMap, scale: u32) -> RgbImage {
-        let width = map.width as u32 * scale;
-        let height = map.height as u32 * scale;
+        let Some((width, height)) = Self::image_size(map, scale) else {
+            return RgbImage::new(0, 0);
+        };
         let mut img = ImageBuffer::new(width, height);
 
         // Get the pixel data
@@ -448,7 +2080,7 @@ impl TerrainRenderer {
         // Convert to RGB image
         for y in 0..height {
             for x in 0..width {
-                let idx = ((y * width + x) * 4) as usize;
+                let idx = (y as usize * width as usize + x as usize) * 4;
                 if idx + 2 < pixels.len() {
                     img.put_pixel(x, y, Rgb([pixels[idx], pixels[idx + 1], pixels[idx + 2]]));
                 }
@@ -457,4 +2089,705 @@ impl TerrainRenderer {
 
         img
     }
+
+    /// Renders the selected layers to an RGBA image; areas no layer covers
+    /// are transparent
+    pub fn render_layers_to_image(
+        map: &TerrainMap,
+        scale: u32,
+        layers: Layers,
+        options: &RenderOptions,
+    ) -> RgbaImage {
+        let Some((width, height)) = Self::image_size(map, scale) else {
+            return RgbaImage::new(0, 0);
+        };
+        let pixels = Self::render_layers_to_pixels(
+            map,
+            map.width,
+            map.height,
+            scale as usize,
+            layers,
+            options,
+        );
+        RgbaImage::from_raw(width, height, pixels).unwrap_or_else(|| RgbaImage::new(width, height))
+    }
+
+    /// Elevation as a 16-bit grayscale heightmap, one pixel per tile, for
+    /// game engines and 3D tools. The full -1..1 range maps to 0..65535,
+    /// so sea level is always mid-gray (32768) whatever the map.
+    pub fn heightmap_image(map: &TerrainMap) -> ImageBuffer<Luma<u16>, Vec<u16>> {
+        let mut img = ImageBuffer::new(map.width as u32, map.height as u32);
+        for (pixel, point) in img.pixels_mut().zip(map.terrain.iter().flatten()) {
+            let level = (point.elevation.clamp(-1.0, 1.0) + 1.0) / 2.0 * 65535.0;
+            *pixel = Luma([level.round() as u16]);
+        }
+        img
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain_generator::{test_map, GenerationSettings, TerrainGenerator};
+
+    /// The small map most render tests draw
+    fn map() -> &'static TerrainMap {
+        test_map(8, 60, 45)
+    }
+
+    #[test]
+    fn viewport_matches_the_full_render() {
+        let map = map();
+        let scale = 3;
+        let full = TerrainRenderer::render_to_pixels(map, map.width, map.height, scale);
+        let view = Viewport {
+            x: 50,
+            y: 31,
+            width: 70,
+            height: 40,
+        };
+        let part = TerrainRenderer::render_viewport_to_pixels(map, scale, view, Layers::all(), &RenderOptions::default());
+        assert_eq!(part.len(), view.width * view.height * 4);
+
+        let full_width = map.width * scale;
+        for y in 0..view.height {
+            let start = ((view.y + y) * full_width + view.x) * 4;
+            assert_eq!(
+                &part[y * view.width * 4..(y + 1) * view.width * 4],
+                &full[start..start + view.width * 4],
+                "row {} differs",
+                y
+            );
+        }
+    }
+
+    #[test]
+    fn separately_rendered_layers_composite_to_the_full_render() {
+        let map = map();
+        let scale = 3;
+        let options = RenderOptions::default();
+        let render = |layers| TerrainRenderer::render_layers_to_pixels(map, 60, 45, scale, layers, &options);
+        let full = render(Layers::all());
+
+        let layers = Layers::all().split();
+        let names: Vec<_> = layers.iter().map(|&(name, _)| name).collect();
+        assert_eq!(names, ["terrain", "rivers", "roads", "cities", "labels"]);
+        let mut stacked = render(layers[0].1);
+        for &(_, layer) in &layers[1..] {
+            TerrainRenderer::composite_over(&mut stacked, &render(layer));
+        }
+        // Blending in two steps may round differently
+        assert!(full
+            .iter()
+            .zip(&stacked)
+            .all(|(&a, &b)| (a as i32 - b as i32).abs() <= 2));
+    }
+
+    #[test]
+    fn territories_tint_land_and_leave_water_clear() {
+        let map = map();
+        let scale = 2;
+        let overlay = TerrainRenderer::render_layers_to_pixels(
+            map,
+            60,
+            45,
+            scale,
+            Layers {
+                territories: true,
+                ..Layers::none()
+            },
+            &RenderOptions::default(),
+        );
+        let territories = map.territories();
+        for (i, pixel) in overlay.chunks_exact(4).enumerate() {
+            let (x, y) = (i % (60 * scale) / scale, i / (60 * scale) / scale);
+            assert_eq!(pixel[3] > 0, territories.owner_at(x, y).is_some());
+        }
+    }
+
+    #[test]
+    fn territories_are_drawn_only_when_asked_for() {
+        assert!(Layers::parse("all,territories").unwrap().territories);
+        assert_eq!(Layers::parse("territories,all").unwrap(), Layers { territories: true, ..Layers::all() });
+        assert!(!Layers::all().territories);
+    }
+
+    /// The offset of the middle pixel of a tile of `map()` rendered at scale 4
+    fn tile_centre((x, y): (usize, usize)) -> usize {
+        ((y * 4 + 2) * 60 * 4 + x * 4 + 2) * 4
+    }
+
+    /// Open country in `map()` far from any city or road
+    fn wild() -> (usize, usize) {
+        let map = map();
+        (0..45)
+            .flat_map(|y| (0..60).map(move |x| (x, y)))
+            .find(|&(x, y): &(usize, usize)| {
+                let far = |(fx, fy): (usize, usize), reach: usize| x.abs_diff(fx).max(y.abs_diff(fy)) > reach;
+                map.cities.iter().all(|c| far((c.x, c.y), 12)) && map.roads.iter().flat_map(|r| &r.path).all(|&t| far(t, 2))
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn night_dims_the_terrain_and_lights_the_cities() {
+        let map = map();
+        let brightness = |pixels: &[u8], tile| {
+            let idx = tile_centre(tile);
+            pixels[idx..idx + 3].iter().map(|&c| c as u32).sum::<u32>()
+        };
+        let terrain = Layers { terrain: true, relief: true, ..Layers::none() };
+        let night = RenderOptions { style: RenderStyle::Night, ..RenderOptions::default() };
+        let day = TerrainRenderer::render_layers_to_pixels(map, 60, 45, 4, terrain, &RenderOptions::default());
+        let dark = TerrainRenderer::render_layers_to_pixels(map, 60, 45, 4, terrain, &night);
+
+        // The largest city glows; open country far from any city stays dark
+        let city = map.cities.iter().max_by_key(|c| c.population).unwrap();
+        assert!(brightness(&dark, (city.x, city.y)) > 450);
+        let wild = wild();
+        assert!(brightness(&dark, wild) < 120 && brightness(&dark, wild) < brightness(&day, wild));
+    }
+
+    #[test]
+    fn the_lights_overlay_alone_is_transparent_away_from_cities_and_roads() {
+        let map = map();
+        let lights = Layers { lights: true, ..Layers::none() };
+        let overlay = TerrainRenderer::render_layers_to_pixels(map, 60, 45, 4, lights, &RenderOptions::default());
+        let alpha = |tile| overlay[tile_centre(tile) + 3];
+        let city = map.cities.iter().max_by_key(|c| c.population).unwrap();
+        assert!(alpha((city.x, city.y)) > 200);
+        assert_eq!(alpha(wild()), 0);
+    }
+
+    #[test]
+    fn lights_and_night_are_asked_for_by_name() {
+        assert!(!Layers::all().lights && Layers::parse("all,lights").unwrap().lights);
+        assert_eq!(RenderStyle::parse("night"), Some(RenderStyle::Night));
+    }
+
+    #[test]
+    fn fog_hides_what_was_not_revealed() {
+        let map = map();
+        let full = TerrainRenderer::render_layers_to_image(map, 4, Layers::all(), &RenderOptions::default());
+        let mut fog = FogOfWar::new(map);
+        fog.reveal_circle((20, 20), 6.0);
+        for style in [FogStyle::Hidden, FogStyle::Desaturated] {
+            let mut img = full.clone();
+            TerrainRenderer::draw_fog(&mut img, &fog, 4, style);
+            // Well inside the reveal nothing changes; far outside all does
+            assert_eq!(img.get_pixel(82, 82), full.get_pixel(82, 82));
+            let hidden = img.get_pixel(200, 150);
+            assert_ne!(hidden, full.get_pixel(200, 150));
+            if style == FogStyle::Desaturated {
+                assert!(hidden[0] == hidden[1] && hidden[1] == hidden[2]);
+            }
+        }
+        assert_eq!(FogStyle::parse("desaturated"), Some(FogStyle::Desaturated));
+    }
+
+    /// The cloud layer alone of `map()` at scale 2, for a cloud seed
+    fn clouds(seed: u32) -> Vec<u8> {
+        let clouds = Layers { clouds: true, ..Layers::none() };
+        let options = RenderOptions { clouds: Some(seed), ..RenderOptions::default() };
+        TerrainRenderer::render_layers_to_pixels(map(), 60, 45, 2, clouds, &options)
+    }
+
+    #[test]
+    fn clouds_cover_some_of_the_map() {
+        let once = clouds(3);
+        let covered = once.chunks_exact(4).filter(|p| p[3] > 0).count() as f32 / (once.len() / 4) as f32;
+        assert!(covered > 0.05 && covered < 0.95, "{}", covered);
+    }
+
+    #[test]
+    fn clouds_are_the_same_each_time_for_a_seed() {
+        assert_eq!(clouds(3), clouds(3));
+        assert_ne!(clouds(3), clouds(4));
+    }
+
+    /// The terrain of `map()` with relief at `relief_strength` and no
+    /// slope tint, at scale 2
+    fn shaded(relief_strength: f32) -> Vec<u8> {
+        let terrain = Layers { terrain: true, relief: true, ..Layers::none() };
+        let options = RenderOptions { relief_strength, slope_tint_strength: 0.0, ..RenderOptions::default() };
+        TerrainRenderer::render_layers_to_pixels(map(), 60, 45, 2, terrain, &options)
+    }
+
+    #[test]
+    fn default_options_render_the_plain_map() {
+        let options = RenderOptions::default();
+        let pixels = TerrainRenderer::render_layers_to_pixels(map(), 60, 45, 2, Layers::all(), &options);
+        assert_eq!(pixels, TerrainRenderer::render_to_pixels(map(), 60, 45, 2));
+    }
+
+    #[test]
+    fn stronger_relief_shades_the_land_more() {
+        // Spread of brightness over the land pixels
+        let spread = |pixels: &[u8]| {
+            let values: Vec<f32> = pixels
+                .chunks_exact(4)
+                .enumerate()
+                .filter(|(i, _)| !map().terrain[i / 120 / 2][i % 120 / 2].biome.is_water())
+                .map(|(_, p)| p[0] as f32 + p[1] as f32 + p[2] as f32)
+                .collect();
+            let mean = values.iter().sum::<f32>() / values.len() as f32;
+            values.iter().map(|v| (v - mean).abs()).sum::<f32>() / values.len() as f32
+        };
+        assert!(spread(&shaded(0.0)) < spread(&shaded(2.0)));
+    }
+
+    #[test]
+    fn the_open_sea_is_never_shaded() {
+        let (flat, strong) = (shaded(0.0), shaded(2.0));
+        for (i, (a, b)) in flat.chunks_exact(4).zip(strong.chunks_exact(4)).enumerate() {
+            if map().terrain[i / 120 / 2][i % 120 / 2].biome == Biome::DeepOcean {
+                assert_eq!(a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn slope_tint_colors_the_slopes() {
+        let render = |options: RenderOptions| {
+            TerrainRenderer::render_layers_to_pixels(map(), 60, 45, 2, Layers::all(), &options)
+        };
+        let red = RenderOptions { slope_tint: [255, 0, 0], slope_tint_strength: 1.0, ..RenderOptions::default() };
+        let redness = |pixels: &[u8]| pixels.chunks_exact(4).map(|p| p[0] as i64 - p[1] as i64).sum::<i64>();
+        assert!(redness(&render(red)) > redness(&render(RenderOptions::default())));
+    }
+
+    #[test]
+    fn coast_rings_lighten_the_water_by_the_shore() {
+        let map = map();
+        let render = |coast_rings| {
+            let options = RenderOptions { coast_rings, ..RenderOptions::default() };
+            TerrainRenderer::render_layers_to_pixels(map, 60, 45, 4, Layers::all(), &options)
+        };
+        let (plain, ringed) = (render(0), render(3));
+        let distance = coast_distance(map, 60, 45, 10.0, false);
+        let (mut lighter, mut shore) = (0, 0);
+        for (i, (a, b)) in plain.chunks_exact(4).zip(ringed.chunks_exact(4)).enumerate() {
+            let tile = (i / 240 / 4) * 60 + i % 240 / 4;
+            if distance[tile] == 0.0 {
+                continue;
+            }
+            let brightness = |p: &[u8]| p[0] as u32 + p[1] as u32 + p[2] as u32;
+            if distance[tile] <= 1.0 {
+                shore += 1;
+                lighter += (brightness(b) > brightness(a)) as usize;
+            } else if distance[tile] >= 4.0 {
+                // Beyond the last ring (3 x 0.6 tiles) the sea is untouched
+                assert_eq!(a, b);
+            }
+        }
+        assert!(shore > 0 && lighter * 10 >= shore * 9, "{} of {}", lighter, shore);
+    }
+
+    #[test]
+    fn water_beside_land_is_one_tile_out() {
+        let distance = coast_distance(map(), 60, 45, 10.0, false);
+        for y in 0..45 {
+            for x in 1..60 {
+                if distance[y * 60 + x - 1] == 0.0 && distance[y * 60 + x] > 0.0 {
+                    assert_eq!(distance[y * 60 + x], 1.0);
+                }
+            }
+        }
+    }
+
+    fn satellite() -> RenderOptions {
+        RenderOptions { style: RenderStyle::Satellite, ..RenderOptions::default() }
+    }
+
+    fn cloudy(seed: u32) -> RenderOptions {
+        RenderOptions { clouds: Some(seed), ..satellite() }
+    }
+
+    fn render_all(options: &RenderOptions) -> Vec<u8> {
+        TerrainRenderer::render_layers_to_pixels(map(), 60, 45, 2, Layers::all(), options)
+    }
+
+    #[test]
+    fn satellite_style_is_not_the_atlas() {
+        assert_ne!(render_all(&satellite()), render_all(&RenderOptions::default()));
+        assert_eq!(RenderStyle::parse("Satellite"), Some(RenderStyle::Satellite));
+    }
+
+    #[test]
+    fn satellite_ground_snows_when_cold() {
+        let cold = TerrainPoint { temperature: 0.0, moisture: 0.8, elevation: 0.3, biome: Biome::Plains };
+        let warm = TerrainPoint { temperature: 0.6, ..cold.clone() };
+        assert!(satellite_color(&cold).iter().all(|&c| c > 200.0));
+        assert!(satellite_color(&warm)[1] > satellite_color(&warm)[0]);
+    }
+
+    #[test]
+    fn clouds_follow_their_seed() {
+        let with_clouds = render_all(&cloudy(3));
+        assert_eq!(with_clouds, render_all(&cloudy(3)));
+        assert_ne!(with_clouds, render_all(&cloudy(4)));
+    }
+
+    #[test]
+    fn clouds_only_whiten() {
+        let (plain, with_clouds) = (render_all(&satellite()), render_all(&cloudy(3)));
+        assert!(plain.iter().zip(&with_clouds).all(|(a, b)| b >= a));
+        assert!(plain.iter().zip(&with_clouds).any(|(a, b)| b > a));
+    }
+
+    #[test]
+    fn viewports_show_the_clouds_of_the_full_render() {
+        let view = Viewport { x: 20, y: 10, width: 30, height: 20 };
+        let part = TerrainRenderer::render_viewport_to_pixels(map(), 2, view, Layers::all(), &cloudy(3));
+        let full = render_all(&cloudy(3));
+        assert_eq!(&part[..30 * 4], &full[(10 * 120 + 20) * 4..(10 * 120 + 50) * 4]);
+    }
+
+    /// The terrain alone of an 80x60 map at scale 2, under some snow
+    fn snowy(map: &TerrainMap, snow: Option<f32>) -> Vec<u8> {
+        let terrain = Layers { terrain: true, ..Layers::none() };
+        let options = RenderOptions { snow, ..RenderOptions::default() };
+        TerrainRenderer::render_layers_to_pixels(map, 80, 60, 2, terrain, &options)
+    }
+
+    #[test]
+    fn snow_spreads_with_the_season() {
+        let map = test_map(5, 80, 60);
+        let white = |pixels: &[u8]| pixels.chunks(4).filter(|p| p[..3].iter().all(|&c| c > 225)).count();
+        let (summer, winter) = (snowy(map, Some(0.0)), snowy(map, Some(1.0)));
+        assert!(white(&snowy(map, None)) < white(&summer) && white(&summer) < white(&winter));
+    }
+
+    #[test]
+    fn only_the_cold_ground_whitens() {
+        // Pixels blend the tiles right and below
+        let map = test_map(5, 80, 60);
+        let (plain, winter) = (snowy(map, None), snowy(map, Some(1.0)));
+        let warm = |x: usize, y: usize| map.terrain[y.min(59)][x.min(79)].temperature > WINTER_SNOW_LINE as f64;
+        for (i, (before, after)) in plain.chunks(4).zip(winter.chunks(4)).enumerate() {
+            let (x, y) = (i % 160 / 2, i / 160 / 2);
+            if warm(x, y) && warm(x + 1, y) && warm(x, y + 1) && warm(x + 1, y + 1) {
+                assert_eq!(before, after);
+            }
+        }
+    }
+
+    #[test]
+    fn only_water_under_snow_freezes() {
+        let point = TerrainPoint { temperature: 0.0, moisture: 0.5, elevation: 0.2, biome: Biome::Lake };
+        assert!(is_frozen(&point, 0.0) && !is_frozen(&TerrainPoint { temperature: 0.2, ..point.clone() }, 0.0));
+        assert!(is_frozen(&TerrainPoint { temperature: 0.2, ..point.clone() }, 1.0));
+        let mut map = test_map(5, 80, 60).clone();
+        for row in &mut map.terrain[20..30] {
+            for point in &mut row[30..40] {
+                *point = TerrainPoint { temperature: 0.0, ..point.clone() };
+                point.biome = Biome::Lake;
+            }
+        }
+        let frozen = snowy(&map, Some(0.0));
+        let ice = frozen[(50 * 160 + 70) * 4..(50 * 160 + 70) * 4 + 3].to_vec();
+        assert!(ice == ICE_COLOR || ice == ICE_CRACK, "{:?}", ice);
+        assert!(snowy(&map, None)[(50 * 160 + 70) * 4 + 2] < 200);
+    }
+
+    /// `map()` drawn as pixel art at scale 4, with or without dithering
+    fn pixel_art(dither: bool) -> Vec<u8> {
+        let layers = Layers { terrain: true, relief: true, rivers: true, roads: true, ..Layers::none() };
+        let options = RenderOptions { style: RenderStyle::PixelArt, dither, ..RenderOptions::default() };
+        TerrainRenderer::render_layers_to_pixels(map(), 60, 45, 4, layers, &options)
+    }
+
+    #[test]
+    fn pixel_art_keeps_to_the_palette() {
+        for pixels in [pixel_art(false), pixel_art(true)] {
+            assert!(pixels.chunks_exact(4).all(|p| PIXEL_PALETTE.contains(&[p[0], p[1], p[2]])));
+        }
+        assert_eq!(RenderStyle::parse("pixel-art"), Some(RenderStyle::PixelArt));
+    }
+
+    #[test]
+    fn dithering_changes_the_pixel_art() {
+        assert_ne!(pixel_art(false), pixel_art(true));
+    }
+
+    #[test]
+    fn pixel_art_fills_tiles_with_one_color() {
+        let (map, scale, solid) = (map(), 4, pixel_art(false));
+        // Away from roads (drawn between tile centres) each tile is one
+        // block of color
+        let near_road = |x: usize, y: usize| {
+            map.roads.iter().flat_map(|r| &r.path).any(|&(rx, ry)| rx.abs_diff(x) <= 1 && ry.abs_diff(y) <= 1)
+        };
+        let pixel = |x: usize, y: usize| &solid[(y * 60 * scale + x) * 4..(y * 60 * scale + x) * 4 + 4];
+        for y in 0..45 {
+            for x in (0..60).filter(|&x| !near_road(x, y)) {
+                let corner = pixel(x * scale, y * scale);
+                assert_eq!(corner, pixel(x * scale + scale - 1, y * scale + scale - 1));
+            }
+        }
+    }
+
+    /// `map()` drawn as line art at scale 4
+    fn line_art() -> Vec<u8> {
+        let options = RenderOptions { style: RenderStyle::LineArt, ..RenderOptions::default() };
+        TerrainRenderer::render_layers_to_pixels(map(), 60, 45, 4, Layers::all(), &options)
+    }
+
+    #[test]
+    fn line_art_is_black_and_white() {
+        assert!(line_art().chunks_exact(4).all(|p| p == [0, 0, 0, 255] || p == [255; 4]));
+        assert_eq!(RenderStyle::parse("print"), Some(RenderStyle::LineArt));
+    }
+
+    #[test]
+    fn line_art_hatches_the_shore_and_leaves_the_open_sea_blank() {
+        let (pixels, scale) = (line_art(), 4);
+        let distance = coast_distance(map(), 60, 45, 10.0, false);
+        let ink_in = |near: bool| {
+            let tiles: Vec<usize> = (0..60 * 45)
+                .filter(|&t| if near { (1.5..=2.0).contains(&distance[t]) } else { distance[t] >= 5.0 })
+                .collect();
+            let inked = tiles
+                .iter()
+                .flat_map(|&t| (0..scale * scale).map(move |i| ((t / 60) * scale + i / scale, (t % 60) * scale + i % scale)))
+                .filter(|&(y, x)| pixels[(y * 60 * scale + x) * 4] == 0)
+                .count();
+            (inked, tiles.len())
+        };
+        let (near_ink, near_tiles) = ink_in(true);
+        assert!(near_tiles > 0 && near_ink > 0);
+        assert_eq!(ink_in(false).0, 0);
+    }
+
+    #[test]
+    fn flow_arrows_point_downstream() {
+        let river: Vec<(usize, usize)> = (0..20).map(|i| (30 - i, 10)).collect();
+        let flat = TerrainMap::empty(40, 20);
+        let arrows = flow_arrows(&flat, &river);
+        assert_eq!(arrows.len(), 2);
+        for (at, direction) in arrows {
+            assert_eq!(direction, [-1.0, 0.0]);
+            assert!(river.contains(&(at[0] as usize, at[1] as usize)));
+        }
+        assert!(flow_arrows(&flat, &river[..3]).is_empty());
+    }
+
+    #[test]
+    fn flow_arrows_mark_the_rivers_layer_near_the_rivers() {
+        let map = map();
+        let rivers = Layers { rivers: true, ..Layers::none() };
+        let render = |flow_arrows| {
+            let options = RenderOptions { flow_arrows, ..RenderOptions::default() };
+            TerrainRenderer::render_layers_to_pixels(map, 60, 45, 4, rivers, &options)
+        };
+        let (plain, marked) = (render(false), render(true));
+        assert_ne!(plain, marked);
+        for (i, (a, b)) in plain.chunks_exact(4).zip(marked.chunks_exact(4)).enumerate() {
+            if a != b {
+                let (x, y) = (i % 240 / 4, i / 240 / 4);
+                assert!(map.rivers.iter().flatten().any(|&(rx, ry)| rx.abs_diff(x) <= 2 && ry.abs_diff(y) <= 2));
+            }
+        }
+    }
+
+    #[test]
+    fn flow_arrows_need_the_rivers_layer() {
+        let nothing = TerrainRenderer::render_layers_to_pixels(
+            map(),
+            60,
+            45,
+            4,
+            Layers::none(),
+            &RenderOptions { flow_arrows: true, ..RenderOptions::default() },
+        );
+        assert!(nothing.iter().all(|&v| v == 0));
+    }
+
+    #[test]
+    fn more_tiles_than_the_grid_has_leaves_the_terrain_out() {
+        let pixels = TerrainRenderer::render_to_pixels(test_map(8, 20, 15), 40, 30, 2);
+        assert_eq!(pixels.len(), 80 * 60 * 4);
+        assert!(pixels.chunks_exact(4).any(|p| p[3] == 0));
+    }
+
+    #[test]
+    fn empty_and_unaddressable_renders_are_empty() {
+        let empty = TerrainMap::empty(0, 0);
+        assert!(TerrainRenderer::render_to_pixels(&empty, 0, 0, 4).is_empty());
+        assert_eq!(TerrainRenderer::render_to_image(map(), 0).dimensions(), (0, 0));
+        let huge = TerrainMap::empty(usize::MAX, 2);
+        let image = TerrainRenderer::render_layers_to_image(&huge, 3, Layers::all(), &RenderOptions::default());
+        assert_eq!(image.dimensions(), (0, 0));
+    }
+
+    #[test]
+    fn heightmaps_put_sea_level_at_mid_gray() {
+        let map = test_map(8, 40, 30);
+        let heights = TerrainRenderer::heightmap_image(map);
+        assert_eq!(heights.dimensions(), (40, 30));
+        for (pixel, point) in heights.pixels().zip(map.terrain.iter().flatten()) {
+            if point.elevation.abs() > 1e-4 {
+                assert_eq!(pixel[0] >= 32768, point.elevation > 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn the_highest_ground_is_the_brightest_in_the_heightmap() {
+        let map = test_map(8, 40, 30);
+        let heights = TerrainRenderer::heightmap_image(map);
+        let highest = map.terrain.iter().flatten().map(|p| p.elevation).fold(f64::MIN, f64::max);
+        let peak = map.terrain.iter().flatten().position(|p| p.elevation == highest).unwrap();
+        assert_eq!(heights.pixels().map(|p| p[0]).max(), Some(heights.pixels().nth(peak).unwrap()[0]));
+    }
+
+    #[test]
+    fn name_plates_pad_by_their_style() {
+        assert_eq!(NamePlate::None.padding(20.0), (0, 0));
+        let (across, down) = NamePlate::Banner.padding(20.0);
+        assert!(across > NamePlate::Rounded.padding(20.0).0 && down > 0);
+        assert_eq!(NamePlate::parse("Parchment"), Some(NamePlate::Banner));
+    }
+
+    #[test]
+    fn name_plates_fill_their_box_and_nothing_else() {
+        for plate in [NamePlate::Rounded, NamePlate::Banner] {
+            let mut img = RgbaImage::new(60, 30);
+            TerrainRenderer::draw_plate(&mut img, plate, 10, 5, 40, 20);
+            for (x, y, pixel) in img.enumerate_pixels() {
+                let within = (10..50).contains(&x) && (5..25).contains(&y);
+                assert!(within || pixel[3] == 0, "{:?} at {},{}", plate, x, y);
+            }
+            // Solid in the middle, open at the corners of the rounded box
+            // and in the notches of the banner's tails
+            assert!(img.get_pixel(30, 15)[3] > 100);
+            let open = if plate == NamePlate::Rounded { (10, 5) } else { (11, 15) };
+            assert_eq!(img.get_pixel(open.0, open.1)[3], 0, "{:?}", plate);
+        }
+    }
+
+    #[test]
+    fn no_plate_draws_nothing() {
+        let blank = RgbaImage::new(60, 30);
+        let mut img = blank.clone();
+        TerrainRenderer::draw_plate(&mut img, NamePlate::None, 10, 5, 40, 20);
+        assert_eq!(img, blank);
+    }
+
+    /// Seed 8 at 240x180 with dense cities, for the label tests
+    fn crowded() -> &'static TerrainMap {
+        use std::sync::OnceLock;
+
+        static MAP: OnceLock<TerrainMap> = OnceLock::new();
+        MAP.get_or_init(|| {
+            let settings = GenerationSettings::default().with_city_density(0.9);
+            TerrainGenerator::new_with_settings(8, settings).generate(240, 180)
+        })
+    }
+
+    #[test]
+    fn default_label_options_name_everything() {
+        let (map, everything) = (crowded(), LabelOptions::default());
+        assert_eq!(everything.city_labels(map, 10.0), map.city_labels(10.0));
+        assert_eq!(everything.region_labels(map).len(), map.labels.len());
+    }
+
+    #[test]
+    fn the_biggest_cities_keep_their_names_in_map_order() {
+        let (map, everything) = (crowded(), LabelOptions::default());
+        let few = LabelOptions { max_city_labels: Some(3), ..everything };
+        let named = few.city_labels(map, 10.0);
+        assert_eq!(named.len(), 3);
+        assert!(named.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        let smallest_named = named.iter().map(|&(i, _)| map.cities[i].population).min().unwrap();
+        assert!(map.cities.iter().filter(|c| c.population > smallest_named).count() < 3);
+        let big = LabelOptions { min_city_population_for_label: 100_000, ..everything };
+        assert!(big.city_labels(map, 10.0).iter().all(|&(i, _)| map.cities[i].population >= 100_000));
+    }
+
+    #[test]
+    fn region_density_keeps_half_of_each_kind_rounding_up_and_none_at_0() {
+        let (map, everything) = (crowded(), LabelOptions::default());
+        let half = LabelOptions { geographic_label_density: 0.5, ..everything }.region_labels(map);
+        for kind in ["ocean", "mountains", "forest", "swamp", "river"] {
+            let total = map.labels.iter().filter(|l| l.feature_type == kind).count();
+            assert_eq!(half.iter().filter(|l| l.feature_type == kind).count(), total.div_ceil(2));
+        }
+        assert!(LabelOptions { geographic_label_density: 0.0, ..everything }.region_labels(map).is_empty());
+    }
+
+    #[test]
+    fn leader_lines_are_for_the_bigger_towns_only_and_bow_to_the_left() {
+        let everything = LabelOptions::default();
+        assert!(everything.has_leader(20_000) && !everything.has_leader(19_999));
+        assert!(!LabelOptions { leader_min_population: u32::MAX, ..everything }.has_leader(u32::MAX - 1));
+        let path = everything.leader_path([0.0, 50.0], [40.0, 50.0]);
+        assert_eq!((path[0], path[path.len() - 1]), ([0.0, 50.0], [40.0, 50.0]));
+        assert!(path.windows(2).all(|step| (step[1][0] - step[0][0]).hypot(step[1][1] - step[0][1]) <= 1.0));
+        let middle = path[path.len() / 2];
+        assert!((middle[1] - (50.0 - 40.0 * 0.15 / 2.0)).abs() < 0.2, "{:?}", middle);
+        let straight = LabelOptions { leader_curve: 0.0, ..everything }.leader_path([0.0, 50.0], [40.0, 50.0]);
+        assert!(straight.iter().all(|p| (p[1] - 50.0).abs() < 1e-3));
+    }
+
+    /// Tall enough at scale 3 to take two strips
+    fn tall() -> &'static TerrainMap {
+        test_map(4, 110, 100)
+    }
+
+    fn supersampled_layers() -> Layers {
+        Layers { terrain: true, relief: true, rivers: true, roads: true, ..Layers::none() }
+    }
+
+    fn supersampled(scale: usize, options: &RenderOptions) -> Vec<u8> {
+        TerrainRenderer::render_layers_to_pixels(tall(), 110, 100, scale, supersampled_layers(), options)
+    }
+
+    /// `tall()` at scale 3, sampled twice each way
+    fn sampled() -> &'static [u8] {
+        use std::sync::OnceLock;
+
+        static SAMPLED: OnceLock<Vec<u8>> = OnceLock::new();
+        SAMPLED.get_or_init(|| supersampled(3, &RenderOptions { supersample: 2, ..RenderOptions::default() }))
+    }
+
+    #[test]
+    fn supersampling_averages_a_finer_render() {
+        let (scale, samples) = (3, 2);
+        let sampled = sampled();
+        assert_eq!(sampled.len(), supersampled(scale, &RenderOptions::default()).len());
+        // Each pixel is the mean of its block of the render at twice the scale
+        let fine = supersampled(scale * samples, &RenderOptions::default());
+        let fine_width = 110 * scale * samples;
+        for (i, pixel) in sampled.chunks_exact(4).enumerate().step_by(37) {
+            let (x, y) = (i % (110 * scale) * samples, i / (110 * scale) * samples);
+            for c in 0..3 {
+                let block = [(0, 0), (1, 0), (0, 1), (1, 1)].map(|(dx, dy)| fine[((y + dy) * fine_width + x + dx) * 4 + c] as f32);
+                assert!((pixel[c] as f32 - block.iter().sum::<f32>() / 4.0).abs() <= 0.5, "pixel {}", i);
+            }
+        }
+    }
+
+    #[test]
+    fn supersampled_neighbouring_pixels_differ_less() {
+        let roughness = |pixels: &[u8]| pixels.chunks_exact(4).zip(pixels.chunks_exact(4).skip(1)).map(|(a, b)| a[0].abs_diff(b[0]) as u64).sum::<u64>();
+        assert!(roughness(sampled()) < roughness(&supersampled(3, &RenderOptions::default())));
+    }
+
+    #[test]
+    fn a_supersampled_viewport_is_still_exactly_that_part_of_the_whole_image() {
+        let view = Viewport { x: 30, y: 200, width: 40, height: 90 };
+        let smooth = RenderOptions { supersample: 2, ..RenderOptions::default() };
+        let part = TerrainRenderer::render_viewport_to_pixels(tall(), 3, view, supersampled_layers(), &smooth);
+        for row in 0..90 {
+            let whole = ((view.y + row) * 110 * 3 + view.x) * 4;
+            assert_eq!(&part[row * 40 * 4..(row + 1) * 40 * 4], &sampled()[whole..whole + 40 * 4]);
+        }
+    }
+
+    #[test]
+    fn supersampled_pixel_art_keeps_its_blocks() {
+        let pixel_art = RenderOptions { style: RenderStyle::PixelArt, ..RenderOptions::default() };
+        assert_eq!(supersampled(3, &RenderOptions { supersample: 4, ..pixel_art }), supersampled(3, &pixel_art));
+    }
 }
@@ -0,0 +1,655 @@
+//! Map names, laid out and drawn the same way for every front end.
+//!
+//! A [`LabelRenderer`] writes the names of a map onto its render. City
+//! names go beside their dots, the most populous first. They are plated
+//! and led off to one side as the [`LabelOptions`] say, and a small town
+//! with no room goes unnamed. Points of interest are named beside their
+//! icons where the cities left room, and highway, bridge and region names
+//! go on top. Layout happens in the pixels of the full render, so a
+//! viewport gets exactly the names a full render puts there and they stay
+//! put as the view pans.
+
+use std::collections::HashMap;
+
+use image::error::{ImageError, LimitError, LimitErrorKind};
+use image::{Rgba, RgbaImage};
+use rusttype::Scale;
+
+use super::{LabelOptions, Layers, NamePlate, TerrainRenderer, Viewport};
+use crate::error::MapperError;
+use crate::terrain_generator::{LabelBox, MapFeature, TerrainMap};
+use crate::text::{draw_text, FontChain};
+
+/// Room kept clear round a city or point of interest name, in pixels
+const LABEL_MARGIN: i32 = 5;
+/// Side of the grid cells placed names are bucketed into, in pixels
+const PLACED_CELL: i32 = 64;
+
+/// Where a pixel of the flat render lands in a reprojected image, such as
+/// a globe, or `None` if it is out of view.
+pub type Projection<'a> = &'a dyn Fn(f32, f32) -> Option<(f32, f32)>;
+
+/// Lays out and draws the names of one map; see the [module docs](self).
+#[derive(Clone, Copy)]
+pub struct LabelRenderer<'a> {
+    map: &'a TerrainMap,
+    scale: u32,
+    layers: Layers,
+    options: LabelOptions,
+    text_size: f32,
+    view: Option<Viewport>,
+    projection: Option<Projection<'a>>,
+    fonts: Option<&'a FontChain>,
+}
+
+/// What the names are drawn onto: one image, or the city and point of
+/// interest names on one and the highway, bridge and region names on
+/// another.
+enum Canvas<'c> {
+    One(&'c mut RgbaImage),
+    Split(&'c mut RgbaImage, &'c mut RgbaImage),
+}
+
+impl Canvas<'_> {
+    fn names(&mut self) -> &mut RgbaImage {
+        match self {
+            Canvas::One(img) | Canvas::Split(img, _) => img,
+        }
+    }
+
+    fn regions(&mut self) -> &mut RgbaImage {
+        match self {
+            Canvas::One(img) | Canvas::Split(_, img) => img,
+        }
+    }
+}
+
+/// The boxes (x, y, width, height) of the names placed so far, in pixels
+/// of the full render, bucketed by grid cell so a new name is checked only
+/// against the names near it.
+#[derive(Default)]
+struct PlacedBoxes {
+    boxes: Vec<(i32, i32, i32, i32)>,
+    cells: HashMap<(i32, i32), Vec<usize>>,
+}
+
+impl PlacedBoxes {
+    fn insert(&mut self, placed: (i32, i32, i32, i32)) {
+        let index = self.boxes.len();
+        self.boxes.push(placed);
+        for cell in Self::cells(placed, 0) {
+            self.cells.entry(cell).or_default().push(index);
+        }
+    }
+
+    /// The cells a box widened by `margin` on every side touches.
+    fn cells((x, y, width, height): (i32, i32, i32, i32), margin: i32) -> impl Iterator<Item = (i32, i32)> {
+        let first = ((x - margin).div_euclid(PLACED_CELL), (y - margin).div_euclid(PLACED_CELL));
+        let last = (
+            (x + width.max(1) + margin - 1).div_euclid(PLACED_CELL),
+            (y + height.max(1) + margin - 1).div_euclid(PLACED_CELL),
+        );
+        (first.1..=last.1).flat_map(move |row| (first.0..=last.0).map(move |column| (column, row)))
+    }
+
+    /// The placed boxes that overlap `(x, y, width, height)` widened by
+    /// `margin` on every side, each once.
+    fn overlapping(&self, (x, y, width, height): (i32, i32, i32, i32), margin: i32) -> Vec<usize> {
+        let mut found: Vec<usize> = Self::cells((x, y, width, height), margin)
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .copied()
+            .filter(|&i| {
+                let (ox, oy, ow, oh) = self.boxes[i];
+                x - margin < ox + ow && x + width + margin > ox && y - margin < oy + oh && y + height + margin > oy
+            })
+            .collect();
+        found.sort_unstable();
+        found.dedup();
+        found
+    }
+}
+
+impl<'a> LabelRenderer<'a> {
+    /// Names for `map` rendered at `scale` pixels per tile: those for the
+    /// `layers` drawn, as many as `options` asks for, sized as
+    /// [`export_text_size`](Self::export_text_size) says.
+    pub fn new(map: &'a TerrainMap, scale: u32, layers: Layers, options: LabelOptions) -> Self {
+        LabelRenderer {
+            map,
+            scale,
+            layers,
+            options,
+            text_size: Self::export_text_size(scale),
+            view: None,
+            projection: None,
+            fonts: None,
+        }
+    }
+
+    /// How much larger than at a scale of 10 an exported map's text is:
+    /// it grows with the map, but not below that.
+    pub fn export_text_size(scale: u32) -> f32 {
+        (scale as f32).max(10.0) / 10.0
+    }
+
+    /// Text `size` times as large as at a scale of 10, e.g. for a screen
+    /// where it stays the same size at every zoom.
+    pub fn with_text_size(self, size: f32) -> Self {
+        LabelRenderer { text_size: size, ..self }
+    }
+
+    /// Draw onto an image of just `view` of the render. Names are still
+    /// laid out across the whole map.
+    pub fn with_view(self, view: Viewport) -> Self {
+        LabelRenderer { view: Some(view), ..self }
+    }
+
+    /// Draw onto a reprojection of the render, each name at its place's
+    /// projected point. Names whose place is out of view are left out,
+    /// and none repeat across the seam.
+    pub fn with_projection(self, projection: Projection<'a>) -> Self {
+        LabelRenderer { projection: Some(projection), ..self }
+    }
+
+    /// Draw with `fonts`, e.g. kept from [`FontChain::for_map`] for
+    /// drawing the same map's names again, instead of building them for
+    /// each draw.
+    pub fn with_fonts(self, fonts: &'a FontChain) -> Self {
+        LabelRenderer { fonts: Some(fonts), ..self }
+    }
+
+    /// Draw every name onto `img`. Returns where the names of features
+    /// went, in tiles.
+    pub fn draw(&self, img: &mut RgbaImage) -> Result<Vec<LabelBox>, MapperError> {
+        self.draw_on(Canvas::One(img))
+    }
+
+    /// Draw as [`draw`](Self::draw) does, but the city and point of
+    /// interest names onto `names` and the rest onto `regions`, the same
+    /// size, to show and hide apart.
+    pub fn draw_layers(&self, names: &mut RgbaImage, regions: &mut RgbaImage) -> Result<Vec<LabelBox>, MapperError> {
+        self.draw_on(Canvas::Split(names, regions))
+    }
+
+    fn draw_on(&self, mut canvas: Canvas) -> Result<Vec<LabelBox>, MapperError> {
+        let (map, scale, layers, labels) = (self.map, self.scale as i32, self.layers, &self.options);
+        let built;
+        let fonts = match self.fonts {
+            Some(fonts) => fonts,
+            None => {
+                built = FontChain::for_map(map)?;
+                &built
+            }
+        };
+        let size = |points: f32| Scale::uniform(points * self.text_size);
+        let mut boxes = Vec::new();
+
+        // Laid out in the pixels of the full render, or of the projection;
+        // drawn less the corner of the view
+        let pixels = |tiles: usize| {
+            u32::try_from(tiles)
+                .ok()
+                .and_then(|tiles| tiles.checked_mul(self.scale))
+                .filter(|&pixels| i32::try_from(pixels).is_ok())
+        };
+        let flat = pixels(map.width).zip(pixels(map.height)).ok_or_else(|| {
+            MapperError::Image(ImageError::Limits(LimitError::from_kind(LimitErrorKind::DimensionError)))
+        })?;
+        let image = canvas.names().dimensions();
+        let (bounds, corner) = match (self.projection, self.view) {
+            (Some(_), _) => ((image.0 as i32, image.1 as i32), (0, 0)),
+            (None, view) => {
+                let corner = view.map_or((0, 0), |v| (v.x as i32, v.y as i32));
+                ((flat.0 as i32, flat.1 as i32), corner)
+            }
+        };
+        let place = |x: f32, y: f32| -> Option<(i32, i32)> {
+            match self.projection {
+                Some(project) => project(x, y).map(|(x, y)| (x as i32, y as i32)),
+                None => Some((x as i32, y as i32)),
+            }
+        };
+        let label_box = |feature, x: i32, y: i32, width: i32, height: i32| {
+            let s = self.scale.max(1) as f32;
+            LabelBox { feature, x: x as f32 / s, y: y as f32 / s, width: width as f32 / s, height: height as f32 / s }
+        };
+        // On a map that wraps east-west, text running off one edge carries
+        // on at the other: road, bridge and region names are drawn again a
+        // map's width to either side
+        let seam_shifts: Vec<i32> = match self.projection {
+            Some(_) => vec![0],
+            None => map.wrap_shifts().iter().map(|shift| (shift * self.scale as f32) as i32).collect(),
+        };
+
+        let mut occupied = PlacedBoxes::default();
+
+        // Text for a layer is only drawn when that layer is drawn too.
+        // Zoomed out, a metro area is labelled once
+        let mut cities = if layers.labels && layers.cities {
+            labels.city_labels(map, self.scale as f32)
+        } else {
+            Vec::new()
+        };
+        cities.sort_by_key(|&(index, _)| std::cmp::Reverse(map.cities[index].population));
+        for (index, name) in cities {
+            let city = &map.cities[index];
+            let Some((city_x, city_y)) = place((city.x as i32 * scale + scale / 2) as f32, (city.y as i32 * scale + scale / 2) as f32) else {
+                continue;
+            };
+            let text_scale = if city.population > 250000 {
+                size(28.0)
+            } else if city.population > 100000 {
+                size(24.0)
+            } else {
+                size(20.0)
+            };
+            let population = (city.population > 100000).then(|| (format!("({}k)", city.population / 1000), size(16.0)));
+
+            // The label's box: on a plate, the plate round the name and the
+            // population; otherwise the name with some room to spare
+            let plate = labels.plates;
+            let (pad_x, pad_y) = plate.padding(text_scale.y);
+            let (text_width, text_height) = if plate == NamePlate::None {
+                (fonts.text_width(&name, text_scale).ceil() as i32 + 10, text_scale.y as i32 + 10)
+            } else {
+                let (mut width, mut height) = (fonts.text_width(&name, text_scale), text_scale.y);
+                if let Some((pop_text, pop_scale)) = &population {
+                    width = width.max(fonts.text_width(pop_text, *pop_scale));
+                    height += 5.0 + pop_scale.y;
+                }
+                (width.ceil() as i32 + 2 * pad_x, height.ceil() as i32 + 2 * pad_y)
+            };
+
+            let close_offsets = [
+                (scale + 5, -5),                                   // Right
+                (-(text_width + scale + 5), -5),                   // Left
+                (-text_width / 2, -(scale + text_height + 5)),     // Above
+                (-text_width / 2, scale + 5),                      // Below
+                (scale + 5, -(scale + text_height)),               // Right-up
+                (-(text_width + scale + 5), -(scale + text_height)), // Left-up
+                (scale + 5, scale),                                // Right-down
+                (-(text_width + scale + 5), scale),                // Left-down
+            ];
+            let far_offsets = [
+                (scale * 3, -(scale * 2)),                     // Far right-up
+                (-(text_width + scale * 3), -(scale * 2)),     // Far left-up
+                (scale * 3, scale * 2),                        // Far right-down
+                (-(text_width + scale * 3), scale * 2),        // Far left-down
+                (scale * 4, -scale),                           // Very far right
+                (-(text_width + scale * 4), -scale),           // Very far left
+                (-text_width / 2, -(scale * 3 + text_height)), // Very far above
+                (-text_width / 2, scale * 3),                  // Very far below
+            ];
+            let inside = |x: i32, y: i32| x > 0 && y > 0 && x + text_width < bounds.0 && y + text_height < bounds.1;
+            let fits = |&(x, y): &(i32, i32)| inside(x, y) && occupied.overlapping((x, y, text_width, text_height), LABEL_MARGIN).is_empty();
+
+            // Beside the city if there is room; otherwise, for the cities
+            // that may have a leader line, a little way off
+            let close = close_offsets.iter().map(|&(dx, dy)| (city_x + dx, city_y + dy)).find(fits);
+            let far = || far_offsets.iter().map(|&(dx, dy)| (city_x + dx, city_y + dy)).find(fits);
+            let placed = match close {
+                Some(pos) => Some((pos, false)),
+                None if labels.has_leader(city.population) => far().map(|pos| (pos, true)).or_else(|| {
+                    // Important cities are always named, where the fewest
+                    // other labels are in the way
+                    (city.population > 100000).then(|| {
+                        let radius = (scale * 6) as f32;
+                        let mut best = None;
+                        let mut fewest = usize::MAX;
+                        for angle in (0..360).step_by(45) {
+                            let rad = (angle as f32).to_radians();
+                            let x = city_x + (radius * rad.cos()) as i32 - text_width / 2;
+                            let y = city_y + (radius * rad.sin()) as i32 - text_height / 2;
+                            if !inside(x, y) {
+                                continue;
+                            }
+                            let count = occupied.overlapping((x, y, text_width, text_height), 0).len();
+                            if count < fewest {
+                                fewest = count;
+                                best = Some((x, y));
+                            }
+                            if count == 0 {
+                                break;
+                            }
+                        }
+                        (best.unwrap_or((city_x + radius as i32, city_y)), true)
+                    })
+                }),
+                // A small town with no room beside it goes unnamed rather
+                // than named somewhere it can't be told apart from its
+                // neighbours
+                None => None,
+            };
+            let Some(((label_x, label_y), leader)) = placed else {
+                continue;
+            };
+            occupied.insert((label_x, label_y, text_width, text_height));
+            boxes.push(label_box(MapFeature::City(index), label_x, label_y, text_width, text_height));
+
+            // The plate, then the name on it: inked in the plate's colors,
+            // or white with an outline
+            let img = canvas.names();
+            let (x, y) = (label_x - corner.0, label_y - corner.1);
+            TerrainRenderer::draw_plate(img, plate, x, y, text_width, text_height);
+            let (name_x, name_y) = (x + pad_x, y + pad_y);
+            let (name_color, population_color, outline) = match plate.ink() {
+                Some((name, population)) => (name, population, 0),
+                None => ([255, 255, 255], [200, 200, 200], 2),
+            };
+            draw_outlined(img, fonts, &name, (name_x, name_y), text_scale, name_color, ([0, 0, 0], outline));
+
+            // A label set away from its city points back to it, drawn after
+            // the text so it stays visible, from the edge of the text box
+            // nearest the city
+            if leader {
+                let (center_x, center_y) = (label_x + text_width / 2, label_y + text_height / 2);
+                let (dx, dy) = (city_x - center_x, city_y - center_y);
+                let from_x = if dx.abs() > dy.abs() {
+                    if dx > 0 { label_x + text_width } else { label_x }
+                } else {
+                    center_x
+                };
+                let from_y = if dy.abs() > dx.abs() {
+                    if dy > 0 { label_y + text_height } else { label_y }
+                } else {
+                    center_y
+                };
+                let from = (from_x - corner.0, from_y - corner.1);
+                draw_leader(img, from, (city_x - corner.0, city_y - corner.1), labels);
+            }
+
+            // The population below the name, on the plate if there is one
+            if let Some((pop_text, pop_scale)) = population {
+                let pop_y = if plate == NamePlate::None {
+                    label_y + text_height + 5
+                } else {
+                    label_y + pad_y + text_scale.y.ceil() as i32 + 5
+                };
+                let color = Rgba([population_color[0], population_color[1], population_color[2], 255]);
+                draw_text(img, color, name_x, pop_y - corner.1, pop_scale, fonts, &pop_text);
+                if plate == NamePlate::None {
+                    let pop_width = fonts.text_width(&pop_text, pop_scale).ceil() as i32;
+                    occupied.insert((label_x, pop_y, pop_width, pop_scale.y as i32));
+                }
+            }
+        }
+
+        // Points of interest are named beside their icon, in their kind's
+        // style, where there is room left by the cities
+        let pois = if layers.labels && layers.cities { map.pois.as_slice() } else { &[] };
+        for (index, poi) in pois.iter().enumerate() {
+            let style = map.poi_style(&poi.kind);
+            if style.label_size <= 0.0 {
+                continue;
+            }
+            let poi_scale = size(style.label_size);
+            let icon_radius = (style.icon_radius * (self.scale as f32 / 10.0).max(0.5)) as i32;
+            let text_width = fonts.text_width(&poi.name, poi_scale).ceil() as i32;
+            let text_height = poi_scale.y as i32;
+            let Some((poi_x, poi_y)) = place((poi.position.0 as i32 * scale + scale / 2) as f32, (poi.position.1 as i32 * scale + scale / 2) as f32) else {
+                continue;
+            };
+            let positions = [
+                (poi_x + icon_radius + 4, poi_y - text_height / 2),              // Right
+                (poi_x - icon_radius - 4 - text_width, poi_y - text_height / 2), // Left
+                (poi_x - text_width / 2, poi_y - icon_radius - 2 - text_height), // Above
+                (poi_x - text_width / 2, poi_y + icon_radius + 2),               // Below
+            ];
+            let Some(&(label_x, label_y)) = positions.iter().find(|&&(x, y)| {
+                x >= 0 && y >= 0 && x + text_width < bounds.0 && y + text_height < bounds.1
+                    && occupied.overlapping((x, y, text_width, text_height), LABEL_MARGIN).is_empty()
+            }) else {
+                continue;
+            };
+            let at = (label_x - corner.0, label_y - corner.1);
+            draw_outlined(canvas.names(), fonts, &poi.name, at, poi_scale, style.label_color, ([0, 0, 0], 1));
+            occupied.insert((label_x, label_y, text_width, text_height));
+            boxes.push(label_box(MapFeature::Poi(index), label_x, label_y, text_width, text_height));
+        }
+
+        // Highway names at the middle of the longer highways, over a white
+        // outline
+        let roads = if layers.labels && layers.roads && labels.show_roads { map.roads.as_slice() } else { &[] };
+        for (index, road) in roads.iter().enumerate() {
+            if !matches!(road.road_type.as_str(), "highway" | "motorway") || road.path.len() <= 10 {
+                continue;
+            }
+            let (rx, ry) = road.path[road.path.len() / 2];
+            let Some((x, y)) = place((rx as i32 * scale) as f32, (ry as i32 * scale) as f32) else {
+                continue;
+            };
+            let road_scale = size(16.0);
+            for &shift in &seam_shifts {
+                let at = (x + shift - corner.0, y - corner.1);
+                draw_outlined(canvas.regions(), fonts, &road.name, at, road_scale, [60, 60, 60], ([255, 255, 255], 1));
+            }
+            let width = fonts.text_width(&road.name, road_scale).ceil() as i32;
+            boxes.push(label_box(MapFeature::Road(index), x, y, width, road_scale.y as i32));
+        }
+
+        // Bridge names just above their bridges
+        let bridges = if layers.labels && layers.roads && labels.show_bridges { map.bridges.as_slice() } else { &[] };
+        for (index, bridge) in bridges.iter().enumerate() {
+            let Some((x, y)) = place((bridge.x as i32 * scale) as f32, (bridge.y as i32 * scale - scale / 2) as f32) else {
+                continue;
+            };
+            let bridge_scale = size(14.0);
+            for &shift in &seam_shifts {
+                let at = (x + shift - corner.0, y - corner.1);
+                draw_outlined(canvas.regions(), fonts, &bridge.name, at, bridge_scale, [80, 60, 40], ([255, 255, 255], 1));
+            }
+            let width = fonts.text_width(&bridge.name, bridge_scale).ceil() as i32;
+            boxes.push(label_box(MapFeature::Bridge(index), x, y, width, bridge_scale.y as i32));
+        }
+
+        // Region names, the largest kinds of region in the largest text,
+        // colored by kind
+        let regions = if layers.labels { labels.region_labels(map) } else { Vec::new() };
+        for label in regions {
+            let Some((x, y)) = place(label.x * self.scale as f32, label.y * self.scale as f32) else {
+                continue;
+            };
+            let park = map.is_park(&label.name);
+            let (color, points) = match label.feature_type.as_str() {
+                _ if park => ([200, 235, 140], 24.0),
+                "ocean" => ([150, 200, 255], 32.0),
+                "mountains" => ([150, 150, 150], 26.0),
+                "forest" => ([100, 200, 100], 22.0),
+                "swamp" => ([150, 180, 150], 22.0),
+                "river" => ([100, 150, 255], 18.0),
+                _ => ([200, 200, 200], 20.0),
+            };
+            let label_scale = size(points);
+            for &shift in &seam_shifts {
+                let at = (x + shift - corner.0, y - corner.1);
+                draw_outlined(canvas.regions(), fonts, &label.name, at, label_scale, color, ([0, 0, 0], 1));
+            }
+            if let Some(index) = map.labels.iter().position(|l| std::ptr::eq(l, label)) {
+                let width = fonts.text_width(&label.name, label_scale).ceil() as i32;
+                boxes.push(label_box(MapFeature::Label(index), x, y, width, label_scale.y as i32));
+            }
+        }
+        Ok(boxes)
+    }
+}
+
+/// Draw `text` in `color` with its top-left at `at`, over an outline of
+/// (color, width in pixels); a width of 0 draws none.
+fn draw_outlined(img: &mut RgbaImage, fonts: &FontChain, text: &str, at: (i32, i32), scale: Scale, color: [u8; 3], outline: ([u8; 3], i32)) {
+    let ([r, g, b], width) = outline;
+    for dy in -width..=width {
+        for dx in -width..=width {
+            if dx != 0 || dy != 0 {
+                draw_text(img, Rgba([r, g, b, 255]), at.0 + dx, at.1 + dy, scale, fonts, text);
+            }
+        }
+    }
+    draw_text(img, Rgba([color[0], color[1], color[2], 255]), at.0, at.1, scale, fonts, text);
+}
+
+/// Draw a leader line from a label at `from` to its city at `to`, along
+/// `labels.leader_path`: a thin dark line, anti-aliased, over a paler halo
+/// that keeps it readable on any ground.
+fn draw_leader(img: &mut RgbaImage, from: (i32, i32), to: (i32, i32), labels: &LabelOptions) {
+    let path = labels.leader_path([from.0 as f32, from.1 as f32], [to.0 as f32, to.1 as f32]);
+    let radius = labels.leader_width.max(0.5) / 2.0;
+    for (ink, alpha, radius) in [([255, 255, 255], 0.55, radius + 1.0), ([25, 25, 30], 0.9, radius)] {
+        // How much of each pixel the line covers, so overlapping steps
+        // along it don't darken it
+        let mut coverage: HashMap<(i32, i32), f32> = HashMap::new();
+        let reach = radius.ceil() as i32 + 1;
+        for &[x, y] in &path {
+            for py in y as i32 - reach..=y as i32 + reach {
+                for px in x as i32 - reach..=x as i32 + reach {
+                    let distance = (px as f32 + 0.5 - x).hypot(py as f32 + 0.5 - y);
+                    let cover = (radius + 0.5 - distance).clamp(0.0, 1.0);
+                    if cover > 0.0 {
+                        let entry = coverage.entry((px, py)).or_insert(0.0);
+                        *entry = entry.max(cover);
+                    }
+                }
+            }
+        }
+        for ((x, y), cover) in coverage {
+            if x < 0 || y < 0 || x >= img.width() as i32 || y >= img.height() as i32 {
+                continue;
+            }
+            // Over the pixel, which is transparent off the terrain layer
+            let pixel = img.get_pixel_mut(x as u32, y as u32);
+            let (a, below) = (alpha * cover, pixel[3] as f32 / 255.0);
+            let out = a + below * (1.0 - a);
+            for c in 0..3 {
+                pixel[c] = ((ink[c] as f32 * a + pixel[c] as f32 * below * (1.0 - a)) / out).round() as u8;
+            }
+            pixel[3] = (out * 255.0).round() as u8;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::OnceLock;
+
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+    use crate::terrain_generator::test_map;
+
+    #[test]
+    fn placed_boxes_find_what_a_scan_of_every_box_finds() {
+        let mut rng = ChaCha8Rng::seed_from_u64(2);
+        let mut random_box = || (rng.gen_range(-100..600), rng.gen_range(-100..400), rng.gen_range(0..150), rng.gen_range(0..40));
+        let mut placed = PlacedBoxes::default();
+        let mut all = Vec::new();
+        for _ in 0..300 {
+            let new = random_box();
+            placed.insert(new);
+            all.push(new);
+        }
+        for _ in 0..500 {
+            let (x, y, width, height) = random_box();
+            for margin in [0, LABEL_MARGIN] {
+                let scanned: Vec<usize> = (0..all.len())
+                    .filter(|&i| {
+                        let (ox, oy, ow, oh) = all[i];
+                        x - margin < ox + ow && x + width + margin > ox && y - margin < oy + oh && y + height + margin > oy
+                    })
+                    .collect();
+                assert_eq!(placed.overlapping((x, y, width, height), margin), scanned);
+            }
+        }
+    }
+
+    #[test]
+    fn a_map_too_large_to_draw_is_an_error() {
+        let map = TerrainMap::empty(1 << 20, 4);
+        let names = LabelRenderer::new(&map, 4096, Layers::all(), LabelOptions::default());
+        assert!(names.draw(&mut RgbaImage::new(8, 8)).is_err());
+    }
+
+    #[test]
+    fn kept_fonts_draw_what_built_ones_do() {
+        let map = test_map(5, 80, 60);
+        let fonts = FontChain::for_map(map).unwrap();
+        let names = LabelRenderer::new(map, 4, Layers::all(), LabelOptions::default());
+        let (mut built, mut kept) = (RgbaImage::new(320, 240), RgbaImage::new(320, 240));
+        let boxes = names.draw(&mut built).unwrap();
+        assert_eq!(names.with_fonts(&fonts).draw(&mut kept).unwrap(), boxes);
+        assert_eq!(built, kept);
+    }
+
+    const SCALE: u32 = 6;
+
+    /// Without leader lines, no name is squeezed in where others are
+    fn no_leaders() -> LabelOptions {
+        LabelOptions { leader_min_population: u32::MAX, ..LabelOptions::default() }
+    }
+
+    fn names() -> LabelRenderer<'static> {
+        LabelRenderer::new(test_map(3, 160, 120), SCALE, Layers::all(), no_leaders())
+    }
+
+    /// The names of seed 3 at 160x120 drawn over the whole map, and their boxes
+    fn full() -> &'static (RgbaImage, Vec<LabelBox>) {
+        static FULL: OnceLock<(RgbaImage, Vec<LabelBox>)> = OnceLock::new();
+        FULL.get_or_init(|| {
+            let mut full = RgbaImage::new(160 * SCALE, 120 * SCALE);
+            let boxes = names().draw(&mut full).unwrap();
+            (full, boxes)
+        })
+    }
+
+    #[test]
+    fn cities_and_regions_are_named() {
+        let (_, boxes) = full();
+        assert!(boxes.iter().any(|b| matches!(b.feature, MapFeature::City(_))));
+        assert!(boxes.iter().any(|b| matches!(b.feature, MapFeature::Label(_))));
+    }
+
+    #[test]
+    fn city_and_point_of_interest_names_never_overlap() {
+        let (_, boxes) = full();
+        let placed: Vec<&LabelBox> =
+            boxes.iter().filter(|b| matches!(b.feature, MapFeature::City(_) | MapFeature::Poi(_))).collect();
+        for (i, a) in placed.iter().enumerate() {
+            for b in &placed[i + 1..] {
+                let apart = a.x + a.width <= b.x || b.x + b.width <= a.x || a.y + a.height <= b.y || b.y + b.height <= a.y;
+                assert!(apart, "{:?} overlaps {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn views_get_the_names_a_full_render_puts_there() {
+        // A view drawn on two layers matches that part of the full image
+        let (full, boxes) = full();
+        let view = Viewport { x: 200, y: 150, width: 300, height: 200 };
+        let mut cities = RgbaImage::new(300, 200);
+        let mut regions = cities.clone();
+        let split = names().with_view(view).draw_layers(&mut cities, &mut regions).unwrap();
+        assert_eq!(&split, boxes);
+        let mut stacked = regions.into_raw();
+        TerrainRenderer::composite_over(&mut stacked, cities.as_raw());
+        let ink = |p: &[u8]| p[3] > 0;
+        let crop = image::imageops::crop_imm(full, 200, 150, 300, 200).to_image();
+        let differ = crop.pixels().zip(stacked.chunks_exact(4)).filter(|(a, b)| ink(&a.0) != ink(b)).count();
+        assert!(differ < 300 * 200 / 100, "{} pixels differ", differ);
+    }
+
+    #[test]
+    fn smaller_text_makes_smaller_boxes() {
+        let (_, boxes) = full();
+        let small = names().with_text_size(0.45).draw(&mut RgbaImage::new(160 * SCALE, 120 * SCALE)).unwrap();
+        let area = |boxes: &[LabelBox]| boxes.iter().map(|b| b.width * b.height).sum::<f32>() / boxes.len() as f32;
+        assert!(area(&small) < area(boxes) / 2.0);
+    }
+
+    #[test]
+    fn only_the_names_of_the_layers_drawn_are_placed() {
+        let none = Layers { labels: false, ..Layers::all() };
+        let names = LabelRenderer::new(test_map(3, 160, 120), SCALE, none, no_leaders());
+        assert!(names.draw(&mut RgbaImage::new(160 * SCALE, 120 * SCALE)).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,346 @@
+//! Invariants every generated map should satisfy, and a driver that
+//! checks them over many seeds, sizes and settings.
+//!
+//! The generator is mostly one long pipeline of noise and thresholds, and
+//! a change deep in it (the elevation field above all) can break maps
+//! only for some seeds. Unit tests pin a handful of seeds; this checks
+//! properties that must hold for any of them. Outside the crate's own
+//! tests it needs the `testing` feature:
+//!
+//! ```ignore
+//! use mapper::testing::{check_cases, Case};
+//!
+//! let failures = check_cases(&Case::random(50, 1));
+//! assert!(failures.is_empty(), "{:#?}", failures);
+//! ```
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rayon::prelude::*;
+
+use crate::terrain_generator::{
+    Biome, GenerationSettings, RiverOutlet, Severity, TerrainGenerator, TerrainMap, TerrainPoint,
+};
+
+/// How far the share of land, lakes included, may stray from
+/// `land_percentage`. Sea level is set at that share of the elevations,
+/// so only the coast moved by erosion and beaches differs
+pub const LAND_SHARE_TOLERANCE: f32 = 0.02;
+
+/// How far each biome's share of the map may stray from the share its
+/// elevation band is given by the settings. Erosion wears some hills and
+/// mountains down into the lowlands; everything else keeps its band
+pub const BIOME_SHARE_TOLERANCE: f64 = 0.025;
+
+/// The elevation bands `Biome::classify` splits tiles into, with the
+/// biome each is named for; the lowland biomes share one band, split by
+/// moisture and temperature.
+const BIOME_BANDS: &[(&str, f64, f64)] = &[
+    ("DeepOcean", -1.0, -0.45),
+    ("Ocean", -0.45, -0.05),
+    ("Shore", -0.05, 0.0),
+    ("Beach", 0.0, 0.04),
+    ("lowlands", 0.04, 0.60),
+    ("Hills", 0.60, 0.82),
+    ("Mountains", 0.82, 0.95),
+    ("SnowPeaks", 0.95, 1.0),
+];
+
+/// A named property of a generated map.
+pub struct Invariant {
+    pub name: &'static str,
+    /// `Err` describes the first place the property fails
+    pub check: fn(&TerrainMap, &GenerationSettings) -> Result<(), String>,
+}
+
+/// Every invariant, in the order they are checked.
+pub const INVARIANTS: &[Invariant] = &[
+    Invariant {
+        name: "rivers end in water",
+        check: rivers_end_in_water,
+    },
+    Invariant {
+        name: "no city on water",
+        check: no_city_on_water,
+    },
+    Invariant {
+        name: "roads stay on land",
+        check: roads_stay_on_land,
+    },
+    Invariant {
+        name: "land share follows settings",
+        check: land_share_follows_settings,
+    },
+    Invariant {
+        name: "biome shares follow settings",
+        check: biome_shares_follow_settings,
+    },
+    Invariant {
+        name: "map validates",
+        check: map_validates,
+    },
+];
+
+fn rivers_end_in_water(map: &TerrainMap, _: &GenerationSettings) -> Result<(), String> {
+    match map.river_flows().iter().find(|f| f.outlet == RiverOutlet::Land) {
+        Some(flow) => Err(format!("river {} ends on land at {:?}", flow.river, flow.mouth)),
+        None => Ok(()),
+    }
+}
+
+fn no_city_on_water(map: &TerrainMap, _: &GenerationSettings) -> Result<(), String> {
+    match map.cities.iter().find(|c| map.terrain[c.y][c.x].biome.is_water()) {
+        Some(city) => Err(format!(
+            "{} at {},{} is on {:?}",
+            city.name, city.x, city.y, map.terrain[city.y][city.x].biome
+        )),
+        None => Ok(()),
+    }
+}
+
+fn roads_stay_on_land(map: &TerrainMap, _: &GenerationSettings) -> Result<(), String> {
+    for road in &map.roads {
+        if let Some(&(x, y)) = road.path.iter().find(|&&(x, y)| map.terrain[y][x].biome.is_water()) {
+            return Err(format!("{} crosses {:?} at {},{}", road.name, map.terrain[y][x].biome, x, y));
+        }
+    }
+    Ok(())
+}
+
+fn land_share_follows_settings(map: &TerrainMap, settings: &GenerationSettings) -> Result<(), String> {
+    let tiles = map.terrain.iter().flatten();
+    let sea = |p: &&TerrainPoint| matches!(p.biome, Biome::Shore | Biome::Ocean | Biome::DeepOcean);
+    let land = tiles.clone().filter(|p| !sea(p)).count() as f32 / tiles.count().max(1) as f32;
+    if (land - settings.land_percentage).abs() > LAND_SHARE_TOLERANCE {
+        Err(format!("{:.0}% land for land_percentage {:.2}", land * 100.0, settings.land_percentage))
+    } else {
+        Ok(())
+    }
+}
+
+/// Index into `BIOME_BANDS` of the band `point` was classified in. Rivers,
+/// lakes and ice sheets are laid over whatever band they are in, so they
+/// count toward the band of their elevation.
+fn biome_band(point: &TerrainPoint, settings: &GenerationSettings) -> usize {
+    let band = match point.biome {
+        Biome::DeepOcean => Some(0),
+        Biome::Ocean => Some(1),
+        Biome::Shore => Some(2),
+        Biome::Beach => Some(3),
+        Biome::Plains | Biome::Forest | Biome::Swamp | Biome::Desert => Some(4),
+        Biome::Hills => Some(5),
+        Biome::Mountains => Some(6),
+        Biome::SnowPeaks if !Biome::is_ice_sheet(point.temperature, settings) => Some(7),
+        Biome::SnowPeaks | Biome::River | Biome::Lake => None,
+    };
+    band.unwrap_or_else(|| {
+        BIOME_BANDS
+            .iter()
+            .rposition(|&(_, low, _)| point.elevation >= low)
+            .unwrap_or(0)
+    })
+}
+
+/// The share of the map the band from `low` to `high` should cover. Water
+/// elevations are quantiles of the water, less 1; land ones are quantiles
+/// of the land raised to the settings' ruggedness.
+fn expected_band_share(low: f64, high: f64, settings: &GenerationSettings) -> f64 {
+    let land = settings.land_percentage as f64;
+    if high <= 0.0 {
+        (1.0 - land) * (high - low)
+    } else {
+        let quantile = |elevation: f64| elevation.powf(1.0 / settings.ruggedness());
+        land * (quantile(high) - quantile(low))
+    }
+}
+
+fn biome_shares_follow_settings(map: &TerrainMap, settings: &GenerationSettings) -> Result<(), String> {
+    let mut counts = [0usize; BIOME_BANDS.len()];
+    for point in map.terrain.iter().flatten() {
+        counts[biome_band(point, settings)] += 1;
+    }
+    let tiles = counts.iter().sum::<usize>().max(1) as f64;
+    for (&(name, low, high), &count) in BIOME_BANDS.iter().zip(&counts) {
+        let share = count as f64 / tiles;
+        let expected = expected_band_share(low, high, settings);
+        if (share - expected).abs() > BIOME_SHARE_TOLERANCE {
+            return Err(format!(
+                "{} covers {:.1}% of the map, settings give it {:.1}%",
+                name,
+                share * 100.0,
+                expected * 100.0
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn map_validates(map: &TerrainMap, _: &GenerationSettings) -> Result<(), String> {
+    let report = map.validate();
+    match report.problems.iter().find(|p| p.severity() == Severity::Error) {
+        Some(problem) => Err(problem.describe(map)),
+        None => Ok(()),
+    }
+}
+
+/// One map to generate and check.
+#[derive(Debug, Clone, Copy)]
+pub struct Case {
+    pub seed: u32,
+    pub width: usize,
+    pub height: usize,
+    pub settings: GenerationSettings,
+}
+
+impl Case {
+    /// `count` cases with random seeds, sizes from 40x30 to 240x180 and
+    /// settings across their ranges, the same for the same `seed`.
+    pub fn random(count: usize, seed: u64) -> Vec<Case> {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        (0..count)
+            .map(|_| {
+                let width = rng.gen_range(40..=240);
+                let settings = GenerationSettings {
+                    river_density: rng.gen_range(0.0..=1.0),
+                    city_density: rng.gen_range(0.0..=1.0),
+                    land_percentage: rng.gen_range(0.2..=0.8),
+                    temperature: rng.gen_range(0.0..=1.0),
+                    mountain_density: rng.gen_range(0.0..=1.0),
+                    forest_density: rng.gen_range(0.0..=1.0),
+                    moisture: rng.gen_range(0.0..=1.0),
+                    ..GenerationSettings::default()
+                };
+                Case {
+                    seed: rng.gen(),
+                    width,
+                    height: width * 3 / 4,
+                    settings,
+                }
+            })
+            .collect()
+    }
+}
+
+/// An invariant that failed for a case.
+#[derive(Debug, Clone)]
+pub struct Failure {
+    pub case: Case,
+    pub invariant: &'static str,
+    pub message: String,
+}
+
+/// Check every invariant on `map`, generated from `settings`.
+pub fn check_map(map: &TerrainMap, settings: &GenerationSettings) -> Vec<(&'static str, String)> {
+    INVARIANTS
+        .iter()
+        .filter_map(|invariant| (invariant.check)(map, settings).err().map(|e| (invariant.name, e)))
+        .collect()
+}
+
+/// Generate every case (in parallel) and check its map. Returns the
+/// failures, in case order.
+pub fn check_cases(cases: &[Case]) -> Vec<Failure> {
+    cases
+        .par_iter()
+        .flat_map_iter(|case| {
+            let map = TerrainGenerator::new_with_settings(case.seed, case.settings)
+                .generate(case.width, case.height);
+            check_map(&map, &case.settings)
+                .into_iter()
+                .map(|(invariant, message)| Failure {
+                    case: *case,
+                    invariant,
+                    message,
+                })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::OnceLock;
+
+    use super::*;
+
+    /// The random cases the invariants are tested on, with their maps,
+    /// generated once for all the tests.
+    fn random_maps() -> &'static [(Case, TerrainMap)] {
+        static MAPS: OnceLock<Vec<(Case, TerrainMap)>> = OnceLock::new();
+        MAPS.get_or_init(|| {
+            Case::random(16, 1435)
+                .into_par_iter()
+                .map(|case| {
+                    let map = TerrainGenerator::new_with_settings(case.seed, case.settings)
+                        .generate(case.width, case.height);
+                    (case, map)
+                })
+                .collect()
+        })
+    }
+
+    fn assert_holds_on_random_maps(check: fn(&TerrainMap, &GenerationSettings) -> Result<(), String>) {
+        let failures: Vec<_> = random_maps()
+            .iter()
+            .filter_map(|(case, map)| check(map, &case.settings).err().map(|e| (case, e)))
+            .collect();
+        assert!(failures.is_empty(), "{:#?}", failures);
+    }
+
+    #[test]
+    fn random_cases_repeat_for_a_seed() {
+        let (first, again) = (Case::random(16, 1435), Case::random(16, 1435));
+        assert_eq!(first.len(), 16);
+        assert!(first.iter().zip(&again).all(|(a, b)| a.seed == b.seed && a.width == b.width));
+    }
+
+    #[test]
+    fn rivers_end_in_water_on_random_maps() {
+        assert_holds_on_random_maps(rivers_end_in_water);
+    }
+
+    #[test]
+    fn no_city_is_on_water_on_random_maps() {
+        assert_holds_on_random_maps(no_city_on_water);
+    }
+
+    #[test]
+    fn roads_stay_on_land_on_random_maps() {
+        assert_holds_on_random_maps(roads_stay_on_land);
+    }
+
+    #[test]
+    fn land_share_follows_settings_on_random_maps() {
+        assert_holds_on_random_maps(land_share_follows_settings);
+    }
+
+    #[test]
+    fn biome_shares_follow_settings_on_random_maps() {
+        assert_holds_on_random_maps(biome_shares_follow_settings);
+    }
+
+    #[test]
+    fn random_maps_validate() {
+        assert_holds_on_random_maps(map_validates);
+    }
+
+    #[test]
+    fn a_city_moved_onto_water_fails() {
+        let (case, map) = &random_maps()[0];
+        let mut map = map.clone();
+        let water = map.terrain.iter().flatten().position(|p| p.biome.is_water()).unwrap();
+        map.cities[0].x = water % map.width;
+        map.cities[0].y = water / map.width;
+        let failed: Vec<&str> = check_map(&map, &case.settings).into_iter().map(|(name, _)| name).collect();
+        assert_eq!(failed, ["no city on water"]);
+    }
+
+    #[test]
+    fn biome_shares_for_other_settings_fail() {
+        let (case, map) = &random_maps()[0];
+        let rugged = GenerationSettings {
+            mountain_density: if case.settings.mountain_density < 0.5 { 1.0 } else { 0.0 },
+            ..case.settings
+        };
+        assert!(biome_shares_follow_settings(map, &rugged).is_err());
+    }
+}
@@ -0,0 +1,302 @@
+//! Label text: a chain of fonts, shaping and drawing.
+//!
+//! Labels are drawn from a [`FontChain`]: the bundled DejaVu Sans (Latin,
+//! Greek, Cyrillic and more) first, then system fonts picked for whatever
+//! characters of the map's names it lacks, such as CJK. Each character goes
+//! to the first font in the chain that has it, and each run of text in one
+//! font is shaped with rustybuzz (kerning, accents from combining marks,
+//! the joined forms of Arabic and Indic scripts) before rusttype
+//! rasterizes the glyphs. A character no font has is drawn as the first
+//! font's missing-glyph box.
+
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::ops::Range;
+use std::sync::OnceLock;
+
+use image::{GenericImage, Pixel};
+use imageproc::pixelops::weighted_sum;
+use rusttype::{point, Font, GlyphId, Scale};
+use rustybuzz::{Face, UnicodeBuffer};
+
+use crate::error::MapperError;
+use crate::terrain_generator::TerrainMap;
+
+const BUNDLED_FONT: &[u8] = include_bytes!("../assets/fonts/DejaVuSans.ttf");
+
+/// One font of a chain: its data for shaping and parsed for drawing.
+struct ChainFont {
+    name: String,
+    data: Cow<'static, [u8]>,
+    index: u32,
+    raster: Font<'static>,
+}
+
+impl ChainFont {
+    fn new(name: String, data: Cow<'static, [u8]>, index: u32) -> Option<ChainFont> {
+        let raster = match &data {
+            Cow::Borrowed(bytes) => Font::try_from_bytes_and_index(bytes, index)?,
+            Cow::Owned(bytes) => Font::try_from_vec_and_index(bytes.clone(), index)?,
+        };
+        Face::from_slice(&data, index)?;
+        Some(ChainFont { name, data, index, raster })
+    }
+
+    fn face(&self) -> Face<'_> {
+        Face::from_slice(&self.data, self.index).expect("checked when the font was added")
+    }
+}
+
+/// A glyph of laid-out text, in pixels from the text's origin on the
+/// baseline.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShapedGlyph {
+    /// Index of the font in the chain
+    pub font: usize,
+    pub id: u16,
+    pub x: f32,
+    pub y: f32,
+}
+
+/// Text shaped at one size.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextLayout {
+    pub glyphs: Vec<ShapedGlyph>,
+    /// Advance of the whole text, in pixels
+    pub width: f32,
+    /// Baseline below the top of the text: the first font's ascent
+    pub ascent: f32,
+}
+
+/// Fonts to draw labels with, tried in order for each character.
+pub struct FontChain {
+    fonts: Vec<ChainFont>,
+}
+
+impl FontChain {
+    /// Only the bundled DejaVu Sans.
+    pub fn bundled() -> Result<FontChain, MapperError> {
+        let font = ChainFont::new("DejaVu Sans".to_string(), Cow::Borrowed(BUNDLED_FONT), 0)
+            .ok_or(MapperError::Font("DejaVuSans.ttf"))?;
+        Ok(FontChain { fonts: vec![font] })
+    }
+
+    /// The bundled font, with system fonts for what it can't draw of
+    /// `map`'s city, road, bridge, region and point of interest names.
+    pub fn for_map(map: &TerrainMap) -> Result<FontChain, MapperError> {
+        let names = map.cities.iter().map(|c| c.name.as_str())
+            .chain(map.roads.iter().map(|r| r.name.as_str()))
+            .chain(map.bridges.iter().map(|b| b.name.as_str()))
+            .chain(map.labels.iter().map(|l| l.name.as_str()))
+            .chain(map.pois.iter().map(|p| p.name.as_str()));
+        let mut chain = Self::bundled()?;
+        chain.add_system_fallbacks(names);
+        Ok(chain)
+    }
+
+    /// Names of the fonts, in the order they are tried.
+    pub fn names(&self) -> Vec<&str> {
+        self.fonts.iter().map(|f| f.name.as_str()).collect()
+    }
+
+    /// The characters of `texts` no font in the chain has. Spaces and
+    /// combining marks don't count; they are drawn with their neighbours.
+    pub fn missing<'a>(&self, texts: impl IntoIterator<Item = &'a str>) -> BTreeSet<char> {
+        let faces: Vec<Face> = self.fonts.iter().map(ChainFont::face).collect();
+        texts
+            .into_iter()
+            .flat_map(str::chars)
+            .filter(|&c| !joins_neighbour(c) && !faces.iter().any(|face| face.glyph_index(c).is_some()))
+            .collect()
+    }
+
+    /// Add system fonts to the end of the chain until it has every
+    /// character of `texts`, or no system font has those left: each time the
+    /// one with the most of them, preferring a regular weight and style.
+    /// The system fonts are only looked for when something is missing.
+    pub fn add_system_fallbacks<'a>(&mut self, texts: impl IntoIterator<Item = &'a str>) {
+        let mut missing = self.missing(texts);
+        if missing.is_empty() {
+            return;
+        }
+        let db = system_fonts();
+        while !missing.is_empty() {
+            let best = db
+                .faces()
+                .filter_map(|info| {
+                    let covered = db.with_face_data(info.id, |data, index| {
+                        let face = rustybuzz::ttf_parser::Face::parse(data, index).ok()?;
+                        Some(missing.iter().filter(|&&c| face.glyph_index(c).is_some()).count())
+                    })??;
+                    let regular = info.style == fontdb::Style::Normal && info.weight == fontdb::Weight::NORMAL;
+                    (covered > 0).then_some((covered, regular, info))
+                })
+                .max_by_key(|&(covered, regular, _)| (covered, regular));
+            let Some((_, _, info)) = best else {
+                break;
+            };
+            let name = info.families.first().map_or_else(|| info.post_script_name.clone(), |(family, _)| family.clone());
+            let font = db
+                .with_face_data(info.id, |data, index| ChainFont::new(name, Cow::Owned(data.to_vec()), index))
+                .flatten();
+            let Some(font) = font else {
+                break;
+            };
+            let face = font.face();
+            missing.retain(|&c| face.glyph_index(c).is_none());
+            drop(face);
+            self.fonts.push(font);
+        }
+    }
+
+    /// `text` shaped at `scale`, each run of characters in the first font
+    /// that has them.
+    pub fn layout(&self, text: &str, scale: Scale) -> TextLayout {
+        let mut glyphs = Vec::new();
+        let mut pen = 0.0;
+        for (font_index, range) in self.runs(text) {
+            let font = &self.fonts[font_index];
+            let mut buffer = UnicodeBuffer::new();
+            buffer.push_str(&text[range]);
+            buffer.guess_segment_properties();
+            let shaped = rustybuzz::shape(&font.face(), &[], buffer);
+            // rusttype sizes a font by its ascent to descent, not its em
+            let (sx, sy) = (font.raster.scale_for_pixel_height(scale.x), font.raster.scale_for_pixel_height(scale.y));
+            for (info, position) in shaped.glyph_infos().iter().zip(shaped.glyph_positions()) {
+                glyphs.push(ShapedGlyph {
+                    font: font_index,
+                    id: info.glyph_id as u16,
+                    x: pen + position.x_offset as f32 * sx,
+                    y: -position.y_offset as f32 * sy,
+                });
+                pen += position.x_advance as f32 * sx;
+            }
+        }
+        TextLayout {
+            glyphs,
+            width: pen,
+            ascent: self.fonts[0].raster.v_metrics(scale).ascent,
+        }
+    }
+
+    /// Width of `text` at `scale`, in pixels.
+    pub fn text_width(&self, text: &str, scale: Scale) -> f32 {
+        self.layout(text, scale).width
+    }
+
+    /// `text` split into runs of one font each, as byte ranges.
+    fn runs(&self, text: &str) -> Vec<(usize, Range<usize>)> {
+        let faces: Vec<Face> = self.fonts.iter().map(ChainFont::face).collect();
+        let mut runs: Vec<(usize, Range<usize>)> = Vec::new();
+        for (start, c) in text.char_indices() {
+            let end = start + c.len_utf8();
+            let font = match runs.last() {
+                Some(&(font, _)) if joins_neighbour(c) => font,
+                _ => faces.iter().position(|face| face.glyph_index(c).is_some()).unwrap_or(0),
+            };
+            match runs.last_mut() {
+                Some((last, range)) if *last == font => range.end = end,
+                _ => runs.push((font, start..end)),
+            }
+        }
+        runs
+    }
+}
+
+/// Draw `text` with its top-left corner at (`x`, `y`), blending `color`
+/// in by glyph coverage the way `imageproc`'s `draw_text_mut` does.
+pub fn draw_text<I>(image: &mut I, color: I::Pixel, x: i32, y: i32, scale: Scale, fonts: &FontChain, text: &str)
+where
+    I: GenericImage,
+    I::Pixel: Pixel<Subpixel = u8>,
+{
+    let layout = fonts.layout(text, scale);
+    let (width, height) = image.dimensions();
+    for glyph in &layout.glyphs {
+        let positioned = fonts.fonts[glyph.font]
+            .raster
+            .glyph(GlyphId(glyph.id))
+            .scaled(scale)
+            .positioned(point(glyph.x, layout.ascent + glyph.y));
+        let Some(bounds) = positioned.pixel_bounding_box() else {
+            continue;
+        };
+        positioned.draw(|gx, gy, coverage| {
+            let px = x + bounds.min.x + gx as i32;
+            let py = y + bounds.min.y + gy as i32;
+            if px >= 0 && py >= 0 && (px as u32) < width && (py as u32) < height {
+                let pixel = image.get_pixel(px as u32, py as u32);
+                image.put_pixel(px as u32, py as u32, weighted_sum(pixel, color, 1.0 - coverage, coverage));
+            }
+        });
+    }
+}
+
+/// Characters drawn in the font of the text around them: spaces,
+/// combining marks and joiners.
+fn joins_neighbour(c: char) -> bool {
+    c.is_whitespace()
+        || matches!(c,
+            '\u{0300}'..='\u{036F}' | '\u{1AB0}'..='\u{1AFF}' | '\u{1DC0}'..='\u{1DFF}'
+            | '\u{200C}' | '\u{200D}' | '\u{20D0}'..='\u{20FF}' | '\u{FE00}'..='\u{FE0F}'
+            | '\u{FE20}'..='\u{FE2F}')
+}
+
+/// The system's fonts, found once per run.
+fn system_fonts() -> &'static fontdb::Database {
+    static FONTS: OnceLock<fontdb::Database> = OnceLock::new();
+    FONTS.get_or_init(|| {
+        let mut db = fontdb::Database::new();
+        db.load_system_fonts();
+        db
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+
+    use super::*;
+
+    const SCALE: Scale = Scale { x: 20.0, y: 20.0 };
+
+    #[test]
+    fn accented_greek_and_cyrillic_names_are_all_in_the_bundled_font() {
+        let fonts = FontChain::bundled().unwrap();
+        let names = ["Zürich", "Ἀθῆναι", "Москва", "Łódź"];
+        assert!(fonts.missing(names).is_empty());
+        for name in names {
+            let layout = fonts.layout(name, SCALE);
+            assert!(layout.glyphs.iter().all(|g| g.id != 0), "{} has a missing glyph", name);
+        }
+        assert_eq!(fonts.missing(["東京 Tokyo"]), BTreeSet::from(['京', '東']));
+    }
+
+    #[test]
+    fn combining_accents_are_composed_and_pairs_are_kerned() {
+        let fonts = FontChain::bundled().unwrap();
+        let composed = fonts.layout("e\u{301}", SCALE);
+        assert_eq!(composed.glyphs.len(), 1);
+        assert_eq!(composed.glyphs[0].id, fonts.layout("é", SCALE).glyphs[0].id);
+        let apart = fonts.text_width("A", SCALE) + fonts.text_width("V", SCALE);
+        assert!(fonts.text_width("AV", SCALE) < apart);
+    }
+
+    #[test]
+    fn characters_nothing_has_stay_in_the_first_fonts_run() {
+        let fonts = FontChain::bundled().unwrap();
+        assert_eq!(fonts.runs("Edo 東京"), vec![(0, 0..10)]);
+    }
+
+    #[test]
+    fn text_is_drawn_within_its_measured_width_from_the_top() {
+        let fonts = FontChain::bundled().unwrap();
+        let mut img = RgbaImage::new(200, 40);
+        draw_text(&mut img, Rgba([255, 255, 255, 255]), 10, 5, SCALE, &fonts, "Zürich");
+        let inked: Vec<(u32, u32)> = img.enumerate_pixels().filter(|(_, _, p)| p[3] > 0).map(|(x, y, _)| (x, y)).collect();
+        assert!(!inked.is_empty());
+        let right = inked.iter().map(|&(x, _)| x).max().unwrap();
+        assert!(right as f32 <= 10.0 + fonts.text_width("Zürich", SCALE) + 1.0);
+        assert!(inked.iter().all(|&(x, y)| x >= 10 && (5..5 + 20).contains(&y)));
+    }
+}